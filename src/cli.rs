@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Dynamic configuration transfer through a running broker's debug endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigCommand {
+    /// Writes the document to `output`, or stdout when unset
+    Export {
+        include_secrets: bool,
+        output: Option<PathBuf>,
+    },
+    /// Applies the document in `input`, all or nothing
+    Import { input: PathBuf },
+}
+
+/// Command line options
+///
+/// Usage: `kafka config (export [--include-secrets] [FILE] | import FILE) --debug-addr ADDR`
+/// or `kafka [--format [--force]] [--log-dir DIR] [--node-id ID] [--debug-addr ADDR]
+/// [--replay CAPTURE [--original-timing]] [--decode-hex FRAME [--response-to KEY:VERSION]]
+/// [--strict-config] [--config server.properties | server.properties]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CliOptions {
+    /// Format the log directory and exit instead of serving
+    pub format: bool,
+    /// Allow formatting a non-empty log directory
+    pub force: bool,
+    /// Overrides `log.dirs`
+    pub log_dir: Option<PathBuf>,
+    /// Overrides `node.id`
+    pub node_id: Option<i32>,
+    /// Address to serve the debug endpoint on; disabled when unset
+    pub debug_addr: Option<SocketAddr>,
+    /// Replay a captured request log against an in-process broker and exit
+    pub replay: Option<PathBuf>,
+    /// Replay at the captured pace instead of as fast as possible
+    pub original_timing: bool,
+    /// Print an annotated hex view of a frame, without its length prefix,
+    /// and exit
+    pub decode_hex: Option<String>,
+    /// Decode `decode_hex` as the response to this api key and version
+    /// instead of as a request
+    pub response_to: Option<(i16, i16)>,
+    /// `config export`/`config import` against the broker at `debug_addr`
+    pub config_command: Option<ConfigCommand>,
+    /// Properties file path, positional or given with `--config`
+    pub config_path: Option<PathBuf>,
+    /// Fail on unknown keys in the properties file, as `config.strict=true`
+    pub strict_config: bool,
+}
+
+impl CliOptions {
+    /// Parses options from the arguments following the program name
+    pub fn parse<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Self::default();
+        let mut args = args.into_iter().peekable();
+
+        let mut include_secrets = false;
+        let mut config_flag = false;
+        let config_action = if args.peek().map(String::as_str) == Some("config") {
+            args.next();
+            match args.next().as_deref() {
+                Some(action @ ("export" | "import")) => Some(action.to_string()),
+                _ => return Err(anyhow!("config requires export or import")),
+            }
+        } else {
+            None
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => options.format = true,
+                "--force" => options.force = true,
+                "--log-dir" => {
+                    let dir = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--log-dir requires a directory"))?;
+                    options.log_dir = Some(PathBuf::from(dir));
+                }
+                "--node-id" => {
+                    let id = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--node-id requires a value"))?;
+                    options.node_id = Some(
+                        id.parse()
+                            .map_err(|_| anyhow!("--node-id must be an integer, got {}", id))?,
+                    );
+                }
+                "--debug-addr" => {
+                    let addr = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--debug-addr requires an address"))?;
+                    options.debug_addr =
+                        Some(addr.parse().map_err(|_| {
+                            anyhow!("--debug-addr must be host:port, got {}", addr)
+                        })?);
+                }
+                "--replay" => {
+                    let capture = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--replay requires a capture file"))?;
+                    options.replay = Some(PathBuf::from(capture));
+                }
+                "--config" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--config requires a properties file"))?;
+                    if options.config_path.is_some() {
+                        return Err(anyhow!("The properties file is given more than once"));
+                    }
+                    options.config_path = Some(PathBuf::from(path));
+                    config_flag = true;
+                }
+                "--strict-config" => options.strict_config = true,
+                "--original-timing" => options.original_timing = true,
+                "--include-secrets" => include_secrets = true,
+                "--decode-hex" => {
+                    let frame = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--decode-hex requires a hex frame"))?;
+                    options.decode_hex = Some(frame);
+                }
+                "--response-to" => {
+                    let request = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--response-to requires KEY:VERSION"))?;
+                    let parsed = request.split_once(':').and_then(|(key, version)| {
+                        Some((key.parse().ok()?, version.parse().ok()?))
+                    });
+                    options.response_to = Some(parsed.ok_or_else(|| {
+                        anyhow!("--response-to must be KEY:VERSION, got {}", request)
+                    })?);
+                }
+                flag if flag.starts_with("--") => return Err(anyhow!("Unknown option {}", flag)),
+                path => {
+                    if options.config_path.is_some() {
+                        return Err(anyhow!("The properties file is given more than once"));
+                    }
+                    options.config_path = Some(PathBuf::from(path));
+                }
+            }
+        }
+
+        if let Some(action) = config_action {
+            if config_flag {
+                return Err(anyhow!(
+                    "--config is not valid together with config {}",
+                    action
+                ));
+            }
+            if options.debug_addr.is_none() {
+                return Err(anyhow!("config {} requires --debug-addr", action));
+            }
+            let file = options.config_path.take();
+            options.config_command = Some(if action == "export" {
+                ConfigCommand::Export {
+                    include_secrets,
+                    output: file,
+                }
+            } else {
+                ConfigCommand::Import {
+                    input: file.ok_or_else(|| anyhow!("config import requires a file"))?,
+                }
+            });
+        }
+        if include_secrets && !matches!(options.config_command, Some(ConfigCommand::Export { .. }))
+        {
+            return Err(anyhow!(
+                "--include-secrets is only valid together with config export"
+            ));
+        }
+        if options.force && !options.format {
+            return Err(anyhow!("--force is only valid together with --format"));
+        }
+        if options.original_timing && options.replay.is_none() {
+            return Err(anyhow!(
+                "--original-timing is only valid together with --replay"
+            ));
+        }
+        if options.response_to.is_some() && options.decode_hex.is_none() {
+            return Err(anyhow!(
+                "--response-to is only valid together with --decode-hex"
+            ));
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliOptions> {
+        CliOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_positional_properties_path() {
+        let options = parse(&["/tmp/server.properties"]).unwrap();
+        assert_eq!(
+            options.config_path,
+            Some(PathBuf::from("/tmp/server.properties"))
+        );
+        assert!(!options.format);
+
+        let options = parse(&["--config", "/tmp/server.properties"]).unwrap();
+        assert_eq!(
+            options.config_path,
+            Some(PathBuf::from("/tmp/server.properties"))
+        );
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["--config", "a.properties", "b.properties"]).is_err());
+
+        let options = parse(&["--strict-config", "server.properties"]).unwrap();
+        assert!(options.strict_config);
+    }
+
+    #[test]
+    fn test_format_flags() {
+        let options = parse(&[
+            "--format",
+            "--force",
+            "--log-dir",
+            "/tmp/x",
+            "--node-id",
+            "2",
+        ])
+        .unwrap();
+        assert!(options.format && options.force);
+        assert_eq!(options.log_dir, Some(PathBuf::from("/tmp/x")));
+        assert_eq!(options.node_id, Some(2));
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        assert!(parse(&["--force"]).is_err());
+        assert!(parse(&["--log-dir"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["--debug-addr", "localhost"]).is_err());
+        assert!(parse(&["--original-timing"]).is_err());
+        assert!(parse(&["--response-to", "18:3"]).is_err());
+        assert!(parse(&["--decode-hex", "00", "--response-to", "18"]).is_err());
+    }
+
+    #[test]
+    fn test_replay_flags() {
+        let options = parse(&["--replay", "session.capture", "--original-timing"]).unwrap();
+        assert_eq!(options.replay, Some(PathBuf::from("session.capture")));
+        assert!(options.original_timing);
+    }
+
+    #[test]
+    fn test_decode_flags() {
+        let options = parse(&["--decode-hex", "0000002a", "--response-to", "18:3"]).unwrap();
+        assert_eq!(options.decode_hex.as_deref(), Some("0000002a"));
+        assert_eq!(options.response_to, Some((18, 3)));
+    }
+
+    #[test]
+    fn test_config_subcommands() {
+        let options = parse(&[
+            "config",
+            "export",
+            "--include-secrets",
+            "--debug-addr",
+            "127.0.0.1:9999",
+            "out.json",
+        ])
+        .unwrap();
+        assert_eq!(
+            options.config_command,
+            Some(ConfigCommand::Export {
+                include_secrets: true,
+                output: Some(PathBuf::from("out.json")),
+            })
+        );
+        assert_eq!(options.config_path, None);
+
+        let options = parse(&[
+            "config",
+            "import",
+            "in.json",
+            "--debug-addr",
+            "127.0.0.1:9999",
+        ])
+        .unwrap();
+        assert_eq!(
+            options.config_command,
+            Some(ConfigCommand::Import {
+                input: PathBuf::from("in.json")
+            })
+        );
+
+        assert!(parse(&["config", "import", "--debug-addr", "127.0.0.1:9999"]).is_err());
+        assert!(parse(&["config", "export"]).is_err());
+        assert!(parse(&["config", "list"]).is_err());
+        assert!(parse(&["--include-secrets"]).is_err());
+    }
+}
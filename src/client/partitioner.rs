@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a partition count learned from Metadata is trusted
+pub const DEFAULT_PARTITION_COUNT_TTL: Duration = Duration::from_secs(300);
+
+/// Murmur2 hash, bit-compatible with the Java client's `Utils.murmur2`
+///
+/// Keyed records must land on the same partition as they would from a
+/// real producer, so this follows the Java implementation exactly,
+/// including its little-endian word reads and seed.
+pub fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let length = data.len();
+    let mut h = SEED ^ length as u32;
+
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+
+    let tail = chunks.remainder();
+    if tail.len() >= 3 {
+        h ^= (tail[2] as u32) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= (tail[1] as u32) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= tail[0] as u32;
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
+
+/// Partition for a keyed record, as the Java client computes it
+///
+/// # Examples
+/// ```
+/// use codecrafters_kafka::client::partitioner::{murmur2, partition_for_key};
+///
+/// assert_eq!(murmur2(b"21"), -973932308);
+/// assert_eq!(partition_for_key(b"21", 6), (-973932308i32 & 0x7fff_ffff) % 6);
+/// ```
+pub fn partition_for_key(key: &[u8], partition_count: i32) -> i32 {
+    (murmur2(key) & 0x7fff_ffff) % partition_count
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CachedCount {
+    count: i32,
+    fetched_at: Instant,
+}
+
+/// Client-side default partitioner
+///
+/// Keyed records are hashed with [`murmur2`]. Unkeyed records stick to one
+/// partition until [`DefaultPartitioner::on_new_batch`] is called for the
+/// topic, then move on to the next partition in turn, so batches fill up
+/// instead of being spread one record per partition.
+///
+/// Partition counts come from Metadata responses via
+/// [`DefaultPartitioner::update_partition_count`]. Once a count is older
+/// than the TTL, [`DefaultPartitioner::partition`] returns `None` and the
+/// caller refreshes Metadata before producing.
+#[derive(Debug)]
+pub struct DefaultPartitioner {
+    ttl: Duration,
+    counts: HashMap<String, CachedCount>,
+    sticky: HashMap<String, i32>,
+    next_sticky: i32,
+}
+
+impl DefaultPartitioner {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            counts: HashMap::new(),
+            sticky: HashMap::new(),
+            next_sticky: 0,
+        }
+    }
+
+    /// Records the partition count Metadata reported for `topic`
+    pub fn update_partition_count(&mut self, topic: &str, count: i32, now: Instant) {
+        if count <= 0 {
+            self.counts.remove(topic);
+            return;
+        }
+        self.counts.insert(
+            topic.to_string(),
+            CachedCount {
+                count,
+                fetched_at: now,
+            },
+        );
+        // A sticky partition past a shrunken count is no longer valid
+        if self
+            .sticky
+            .get(topic)
+            .is_some_and(|&sticky| sticky >= count)
+        {
+            self.sticky.remove(topic);
+        }
+    }
+
+    /// Cached partition count for `topic`, if it has not expired
+    pub fn partition_count(&self, topic: &str, now: Instant) -> Option<i32> {
+        self.counts
+            .get(topic)
+            .filter(|cached| now.saturating_duration_since(cached.fetched_at) < self.ttl)
+            .map(|cached| cached.count)
+    }
+
+    /// Chooses the partition for a record, or `None` when the partition
+    /// count is unknown or stale and Metadata must be refreshed first
+    pub fn partition(&mut self, topic: &str, key: Option<&[u8]>, now: Instant) -> Option<i32> {
+        let count = self.partition_count(topic, now)?;
+        Some(match key {
+            Some(key) => partition_for_key(key, count),
+            None => match self.sticky.get(topic) {
+                Some(&sticky) => sticky,
+                None => {
+                    let sticky = self.next_sticky.rem_euclid(count);
+                    self.next_sticky = sticky + 1;
+                    self.sticky.insert(topic.to_string(), sticky);
+                    sticky
+                }
+            },
+        })
+    }
+
+    /// Moves unkeyed records for `topic` to the next partition; called when
+    /// the batch for the current sticky partition is sent
+    pub fn on_new_batch(&mut self, topic: &str) {
+        if let Some(sticky) = self.sticky.remove(topic) {
+            self.next_sticky = sticky + 1;
+        }
+    }
+}
+
+impl Default for DefaultPartitioner {
+    fn default() -> Self {
+        Self::new(DEFAULT_PARTITION_COUNT_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_murmur2_matches_java_client() {
+        // Vectors from the Java client's UtilsTest
+        let cases: [(&[u8], i32); 6] = [
+            (b"21", -973932308),
+            (b"foobar", -790332482),
+            (b"a-little-bit-long-string", -985981536),
+            (b"a-little-bit-longer-string", -1486304829),
+            (
+                b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8",
+                -58897971,
+            ),
+            (b"abc", 479470107),
+        ];
+        for (key, expected) in cases {
+            assert_eq!(murmur2(key), expected, "{}", String::from_utf8_lossy(key));
+        }
+    }
+
+    #[test]
+    fn test_keyed_records_hash_to_fixed_partition() {
+        let mut partitioner = DefaultPartitioner::default();
+        let now = Instant::now();
+        partitioner.update_partition_count("orders", 6, now);
+
+        let partition = partitioner.partition("orders", Some(b"foobar"), now);
+        assert_eq!(partition, Some((-790332482i32 & 0x7fff_ffff) % 6));
+        partitioner.on_new_batch("orders");
+        assert_eq!(
+            partitioner.partition("orders", Some(b"foobar"), now),
+            partition
+        );
+    }
+
+    #[test]
+    fn test_unkeyed_records_stick_until_new_batch() {
+        let mut partitioner = DefaultPartitioner::default();
+        let now = Instant::now();
+        partitioner.update_partition_count("events", 3, now);
+
+        let mut chosen = Vec::new();
+        for _ in 0..4 {
+            let first = partitioner.partition("events", None, now).unwrap();
+            assert_eq!(partitioner.partition("events", None, now), Some(first));
+            chosen.push(first);
+            partitioner.on_new_batch("events");
+        }
+        assert_eq!(chosen, vec![0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn test_stale_partition_count_requires_refresh() {
+        let mut partitioner = DefaultPartitioner::new(Duration::from_secs(10));
+        let now = Instant::now();
+        assert_eq!(partitioner.partition("events", None, now), None);
+
+        partitioner.update_partition_count("events", 2, now);
+        assert!(partitioner.partition("events", None, now).is_some());
+        let later = now + Duration::from_secs(10);
+        assert_eq!(partitioner.partition("events", None, later), None);
+
+        partitioner.update_partition_count("events", 2, later);
+        assert!(partitioner.partition("events", None, later).is_some());
+    }
+}
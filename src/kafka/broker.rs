@@ -1,12 +1,154 @@
+use crate::kafka::clock::{Clock, SystemClock};
+use crate::kafka::config::{
+    broker_config_key, check_fetch_allowed, check_produce_allowed, is_sensitive_config_key,
+    topic_config_key, validate, BrokerConfig, ConfigError, ConfigKind, TopicConfig,
+    TOPIC_CONFIG_KEYS,
+};
+use crate::kafka::connection::{ConnectionState, FrameReader, RequestContext};
+use crate::kafka::connection_registry::{ConnectionFilter, ConnectionRegistry};
+use crate::kafka::diagnostics::{DiagnosticFeature, DiagnosticsLevel};
+use crate::kafka::dynamic_config::{
+    DynamicConfigDocument, DynamicConfigError, DynamicConfigRegistry, ImportSummary,
+};
+use crate::kafka::error::{wire_error, wire_error_for, BrokerError};
+use crate::kafka::events::{BrokerEvent, EventBus, RecentEvents, DEFAULT_RECENT_EVENTS};
+use crate::kafka::fetch_session::{FetchSessionCache, DEFAULT_FETCH_SESSION_SLOTS};
+use crate::kafka::group_coordinator::{GroupCoordinator, GroupError};
+use crate::kafka::handler::{ApiHandler, HandlerRegistry};
+use crate::kafka::health::{HealthState, HealthStatus};
+use crate::kafka::latency::DeliveryLatencyTracker;
+use crate::kafka::limits::Limits;
+use crate::kafka::metadata::ClusterMetadata;
+use crate::kafka::metadata_epoch::MetadataEpoch;
+use crate::kafka::offset_store::{CommittedOffset, OffsetStore};
+use crate::kafka::producer_id_manager::{InitProducerIdError, ProducerIdManager};
+use crate::kafka::purgatory::Purgatory;
+use crate::kafka::quarantine::{is_decode_failure, Quarantine};
+use crate::kafka::request_queue::{RequestPriority, RequestQueue};
+use crate::kafka::response_cache::{CacheLookup, ResponseCache};
+use crate::kafka::sasl::SaslConfig;
+use crate::kafka::state_dump::StateSnapshot;
+use crate::kafka::storage::log_dir::partition_dir;
+use crate::kafka::storage::log_segment::LogConfig;
+use crate::kafka::storage::partition_log::TimestampOffset;
+use crate::kafka::storage::segment_handles::SegmentHandleCache;
+use crate::kafka::storage::{PartitionLog, StorageError, Topic, TopicStore};
+use crate::kafka::throughput::ThroughputTracker;
+use crate::kafka::topic_metrics::TopicMetrics;
+use crate::kafka::watermark::HighWatermarkSubscriber;
 use crate::logging::{debug, error, info, warn, LogUtils};
+use crate::protocol::api_key::api_key_name;
+use crate::protocol::create_topics::{
+    CreatableTopic, CreatableTopicConfigs, CreatableTopicResult, CreateTopicsRequest,
+    CreateTopicsResponse, TOPIC_CONFIG_SOURCE,
+};
+use crate::protocol::decode_limits::DecodeLimits;
+use crate::protocol::delete_topics::{
+    DeletableTopicResult, DeleteTopicState, DeleteTopicsRequest, DeleteTopicsResponse,
+};
+use crate::protocol::describe_cluster::{
+    DescribeClusterBroker, DescribeClusterRequest, DescribeClusterResponse, ENDPOINT_TYPE_BROKERS,
+    ENDPOINT_TYPE_CONTROLLERS,
+};
+use crate::protocol::describe_configs::{
+    DescribeConfigsRequest, DescribeConfigsResource, DescribeConfigsResourceResult,
+    DescribeConfigsResponse, DescribeConfigsResult, DescribeConfigsSynonym, CONFIG_SOURCE_DEFAULT,
+    CONFIG_SOURCE_DYNAMIC_TOPIC, CONFIG_SOURCE_STATIC_BROKER, CONFIG_TYPE_BOOLEAN,
+    CONFIG_TYPE_LONG, CONFIG_TYPE_STRING, CONFIG_TYPE_UNKNOWN, RESOURCE_TYPE_BROKER,
+    RESOURCE_TYPE_TOPIC,
+};
+use crate::protocol::describe_topic_partitions::{
+    DescribeTopicPartitionsCursor, DescribeTopicPartitionsRequest, DescribeTopicPartitionsResponse,
+    DescribeTopicPartitionsResponsePartition, DescribeTopicPartitionsResponseTopic,
+    AUTHORIZED_OPERATIONS_OMITTED,
+};
+use crate::protocol::fetch::{
+    FetchPartition, FetchRequest, FetchResponse, FetchResponsePartition, FetchResponseTopic,
+    FetchTopic,
+};
+use crate::protocol::find_coordinator::{
+    Coordinator, FindCoordinatorRequest, FindCoordinatorResponse, COORDINATOR_TYPE_GROUP,
+    FIND_COORDINATOR_BATCHED_VERSION,
+};
+use crate::protocol::headers::sanitize_client_id;
+use crate::protocol::heartbeat::{HeartbeatRequest, HeartbeatResponse};
+use crate::protocol::incremental_alter_configs::{
+    AlterConfigsResource, AlterConfigsResourceResponse, AlterableConfig, ConfigOperation,
+    IncrementalAlterConfigsRequest, IncrementalAlterConfigsResponse,
+};
+use crate::protocol::init_producer_id::{
+    InitProducerIdRequest, InitProducerIdResponse, NO_PRODUCER_EPOCH, NO_PRODUCER_ID,
+};
+use crate::protocol::join_group::{
+    JoinGroupRequest, JoinGroupResponse, JOIN_GROUP_MEMBER_ID_REQUIRED_VERSION,
+};
+use crate::protocol::leave_group::{
+    LeaveGroupRequest, LeaveGroupResponse, LeftMember, LEAVE_GROUP_BATCHED_VERSION,
+};
+use crate::protocol::list_offsets::{
+    ListOffsetsPartitionResponse, ListOffsetsRequest, ListOffsetsResponse,
+    ListOffsetsTopicResponse, EARLIEST_TIMESTAMP, LATEST_TIMESTAMP,
+};
+use crate::protocol::message_set::{decode_message_set, records_magic, LegacyMessage};
+use crate::protocol::metadata::{
+    MetadataRequest, MetadataResponse, MetadataResponseBroker, MetadataResponsePartition,
+    MetadataResponseTopic,
+};
+use crate::protocol::offset_commit::{
+    OffsetCommitPartition, OffsetCommitPartitionResponse, OffsetCommitRequest,
+    OffsetCommitResponse, OffsetCommitTopicResponse,
+};
+use crate::protocol::offset_fetch::{
+    OffsetFetchRequest, OffsetFetchRequestGroup, OffsetFetchResponse, OffsetFetchResponseGroup,
+    OffsetFetchResponsePartition, OffsetFetchResponseTopic, OFFSET_FETCH_BATCHED_VERSION,
+};
+use crate::protocol::produce::{
+    ProducePartitionResponse, ProduceRequest, ProduceResponse, ProduceTopicResponse,
+    FIRST_RECORD_BATCH_VERSION,
+};
+use crate::protocol::record_batch::{
+    split_record_batches, RecordBatch, RecordBatchBuilder, NO_TIMESTAMP,
+};
+use crate::protocol::sasl_authenticate::{SaslAuthenticateRequest, SaslAuthenticateResponse};
+use crate::protocol::sasl_handshake::{SaslHandshakeRequest, SaslHandshakeResponse};
+use crate::protocol::sync_group::{SyncGroupRequest, SyncGroupResponse};
+use crate::protocol::trace::{trace_request, DecodeTrace};
 use crate::protocol::{
-    ProtocolDecode, ProtocolEncode, RequestHeaderV2, ResponseHeaderV0, WireFormat,
+    ApiKey, ErrorCode, KafkaRequest, KafkaResponse, ProtocolDecode, ProtocolEncode,
+    ProtocolEncodeVersioned, ProtocolError, ProtocolResult, RequestHeader, RequestHeaderV2,
+    ResponseHeader, WireFormat,
 };
-use anyhow::Result;
-use bytes::{Buf, BufMut, BytesMut};
-use std::time::Instant;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Size of the length prefix in front of every response frame
+const RESPONSE_LENGTH_PREFIX: usize = 4;
+
+/// Most bytes of a frame shown in a hex dump
+const MAX_HEX_DUMP_BYTES: usize = 1024;
+
+/// Address the broker listens on unless configured otherwise
+pub const DEFAULT_LISTEN_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9092);
+
+/// Node id of a broker that was not given one
+const DEFAULT_NODE_ID: i32 = 1;
+
+/// Most partitions one DescribeTopicPartitions response lists, as the
+/// reference broker's `max.request.partition.size.limit` defaults to
+const MAX_DESCRIBED_PARTITIONS: i32 = 2000;
 
 /// Core Kafka broker that handles message processing
 ///
@@ -15,113 +157,726 @@ use tokio::net::TcpStream;
 /// broker-specific operations.
 #[derive(Debug)]
 pub struct KafkaBroker {
-    // Future: Add fields for topics, partitions, logs, etc.
+    cancelled_by_disconnect: AtomicU64,
+    connections: ConnectionRegistry,
+    /// Cluster id loaded from a formatted log directory
+    cluster_id: Option<String>,
+    node_id: i32,
+    /// Rack advertised in Metadata and DescribeCluster (`broker.rack`)
+    rack: Option<String>,
+    /// Properties the broker was started with, as DescribeConfigs reports
+    config: BrokerConfig,
+    /// Set once the server has bound its listener
+    listen_addr: OnceLock<SocketAddr>,
+    topics: TopicStore,
+    groups: GroupCoordinator,
+    offsets: OffsetStore,
+    producer_ids: ProducerIdManager,
+    /// Log directory holding the partition logs, when one was loaded
+    log_dir: Option<PathBuf>,
+    limits: Arc<Limits>,
+    events: EventBus,
+    response_cache: ResponseCache,
+    fetch_sessions: FetchSessionCache,
+    recent_events: RecentEvents,
+    health: HealthState,
+    throughput: ThroughputTracker,
+    quarantine: Quarantine,
+    topic_metrics: TopicMetrics,
+    delivery_latency: DeliveryLatencyTracker,
+    purgatory: Purgatory,
+    request_queue: RequestQueue,
+    sasl: SaslConfig,
+    dynamic_config: DynamicConfigRegistry,
+    metadata_epoch: MetadataEpoch,
+    /// Handlers served ahead of the built-in APIs, ApiVersions among them
+    handlers: Arc<RwLock<HandlerRegistry>>,
+    /// Cancelled when the server starts shutting down
+    shutdown: CancellationToken,
 }
 
 impl KafkaBroker {
     /// Creates a new Kafka broker instance
     pub fn new() -> Self {
-        Self {}
+        let limits = Arc::new(Limits::default());
+        let events = EventBus::default();
+        let response_cache = ResponseCache::new(
+            limits.response_cache_bytes,
+            events.subscribe("response-cache"),
+        );
+        let fetch_sessions = FetchSessionCache::new(
+            DEFAULT_FETCH_SESSION_SLOTS,
+            events.subscribe("fetch-sessions"),
+        );
+        let recent_events =
+            RecentEvents::new(DEFAULT_RECENT_EVENTS, events.subscribe("debug-events"));
+        let topic_metrics = TopicMetrics::default().with_events(events.subscribe("topic-metrics"));
+        Self {
+            cancelled_by_disconnect: AtomicU64::new(0),
+            connections: ConnectionRegistry::new(),
+            cluster_id: None,
+            node_id: DEFAULT_NODE_ID,
+            rack: None,
+            config: BrokerConfig::default(),
+            listen_addr: OnceLock::new(),
+            topics: TopicStore::new().with_events(events.clone()),
+            groups: GroupCoordinator::new().with_events(events.clone()),
+            offsets: OffsetStore::default(),
+            producer_ids: ProducerIdManager::in_memory(),
+            log_dir: None,
+            limits,
+            events,
+            response_cache,
+            fetch_sessions,
+            recent_events,
+            health: HealthState::default(),
+            throughput: ThroughputTracker::default(),
+            quarantine: Quarantine::disabled(),
+            topic_metrics,
+            delivery_latency: DeliveryLatencyTracker::default(),
+            purgatory: Purgatory::default(),
+            request_queue: RequestQueue::default(),
+            sasl: SaslConfig::default(),
+            dynamic_config: DynamicConfigRegistry::new(),
+            metadata_epoch: MetadataEpoch::in_memory(),
+            handlers: HandlerRegistry::new(),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Serves `handler.api_key()` with `handler`, returning the handler it
+    /// replaces
+    ///
+    /// The handler takes precedence over a built-in API with the same key,
+    /// and ApiVersions advertises its versions from then on.
+    pub fn register_handler(&self, handler: Arc<dyn ApiHandler>) -> Option<Arc<dyn ApiHandler>> {
+        let replaced = self.handlers.write().unwrap().register(handler);
+        self.response_cache.invalidate();
+        replaced
+    }
+
+    /// Starts the broker at the given diagnostics level
+    pub fn with_diagnostics_level(self, level: DiagnosticsLevel) -> Self {
+        self.dynamic_config.diagnostics().set_level(level);
+        self
+    }
+
+    /// Parks acks=-1 produces in the given purgatory
+    pub fn with_purgatory(mut self, purgatory: Purgatory) -> Self {
+        self.purgatory = purgatory;
+        self
+    }
+
+    /// Dispatches requests through the given worker queue, control-plane
+    /// requests ahead of data-plane ones
+    pub fn with_request_queue(mut self, request_queue: RequestQueue) -> Self {
+        self.request_queue = request_queue;
+        self
+    }
+
+    /// Offers the given SASL mechanisms instead of just PLAIN
+    pub fn with_sasl(mut self, sasl: SaslConfig) -> Self {
+        self.sasl = sasl;
+        self
+    }
+
+    /// Replaces the default per-topic metrics settings
+    pub fn with_topic_metrics(mut self, topic_metrics: TopicMetrics) -> Self {
+        self.topic_metrics = topic_metrics.with_events(self.events.subscribe("topic-metrics"));
+        self
+    }
+
+    /// Replaces the default end-to-end delivery latency settings
+    pub fn with_delivery_latency(mut self, delivery_latency: DeliveryLatencyTracker) -> Self {
+        self.delivery_latency = delivery_latency;
+        self
+    }
+
+    /// Saves requests that fail to parse to the given quarantine
+    pub fn with_quarantine(mut self, quarantine: Quarantine) -> Self {
+        self.quarantine = quarantine;
+        self
+    }
+
+    /// Replaces the default hot-partition detection settings
+    pub fn with_throughput(mut self, throughput: ThroughputTracker) -> Self {
+        self.throughput = throughput;
+        self
+    }
+
+    /// Replaces the default limits with ones built from configuration
+    pub fn with_limits(mut self, limits: Arc<Limits>) -> Self {
+        self.response_cache = ResponseCache::new(
+            limits.response_cache_bytes,
+            self.events.subscribe("response-cache"),
+        );
+        self.limits = limits;
+        self
+    }
+
+    /// Persists the metadata epoch with the given store
+    pub fn with_metadata_epoch(mut self, metadata_epoch: MetadataEpoch) -> Self {
+        self.metadata_epoch = metadata_epoch;
+        self
+    }
+
+    /// Hands out producer ids from the given manager
+    pub fn with_producer_ids(mut self, producer_ids: ProducerIdManager) -> Self {
+        self.producer_ids = producer_ids;
+        self
+    }
+
+    /// Sets the cluster id loaded from the log directory
+    pub fn with_cluster_id(mut self, cluster_id: impl Into<String>) -> Self {
+        self.cluster_id = Some(cluster_id.into());
+        self
+    }
+
+    /// Sets the log directory whose partition logs the broker manages
+    ///
+    /// Topics created from then on, those of the cluster metadata log
+    /// included, keep their partitions in segment files there.
+    pub fn with_log_dir(mut self, log_dir: impl Into<PathBuf>) -> Self {
+        let log_dir = log_dir.into();
+        self.topics = std::mem::take(&mut self.topics).with_log_dir(&log_dir);
+        self.log_dir = Some(log_dir);
+        self
+    }
+
+    /// Sizes and indexes partition segments by `log_config`
+    pub fn with_log_config(mut self, log_config: LogConfig) -> Self {
+        self.topics = std::mem::take(&mut self.topics).with_log_config(log_config);
+        self
+    }
+
+    /// Bounds the segment files partitions keep open by `segment_handles`
+    pub fn with_segment_handles(mut self, segment_handles: Arc<SegmentHandleCache>) -> Self {
+        self.topics = std::mem::take(&mut self.topics).with_segment_handles(segment_handles);
+        self
+    }
+
+    /// Hosts the topics of the cluster metadata log, under their ids
+    ///
+    /// Each topic gets as many partitions as its highest partition index
+    /// calls for. A topic that cannot be created, such as one whose name is
+    /// already taken, is skipped with a warning.
+    pub fn with_cluster_metadata(self, metadata: &ClusterMetadata) -> Self {
+        for topic in metadata.topics.values() {
+            let num_partitions = topic
+                .partitions
+                .keys()
+                .next_back()
+                .map_or(0, |&last| last + 1);
+            if let Err(e) = self.topics.create_topic_with_id(
+                &topic.name,
+                topic.topic_id,
+                num_partitions,
+                BTreeMap::new(),
+            ) {
+                warn!(topic = %topic.name, error = %e, "Skipping topic of the cluster metadata log");
+            }
+        }
+        self
+    }
+
+    /// Sets the node id this broker advertises
+    pub fn with_node_id(mut self, node_id: i32) -> Self {
+        self.node_id = node_id;
+        self
+    }
+
+    /// Sets the properties DescribeConfigs reports for this broker
+    pub fn with_config(mut self, config: BrokerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the rack this broker advertises
+    pub fn with_rack(mut self, rack: impl Into<String>) -> Self {
+        self.rack = Some(rack.into());
+        self
+    }
+
+    /// Records the address the server bound, which Metadata advertises
+    ///
+    /// Only the first call takes effect: a broker serves one listener.
+    pub fn set_listen_addr(&self, addr: SocketAddr) {
+        if self.listen_addr.set(addr).is_err() {
+            warn!(addr = %addr, "Listen address already set, ignoring");
+        }
+    }
+
+    /// Address advertised to clients, the default one until the server binds
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.listen_addr
+            .get()
+            .copied()
+            .unwrap_or(DEFAULT_LISTEN_ADDR)
+    }
+
+    /// Node id this broker advertises
+    pub fn node_id(&self) -> i32 {
+        self.node_id
+    }
+
+    /// Topics this broker hosts
+    pub fn topics(&self) -> &TopicStore {
+        &self.topics
+    }
+
+    /// Live connections, for listing and operator-initiated close
+    pub fn connections(&self) -> &ConnectionRegistry {
+        &self.connections
+    }
+
+    /// Cluster id of the formatted log directory, if one was loaded
+    pub fn cluster_id(&self) -> Option<&str> {
+        self.cluster_id.as_deref()
+    }
+
+    /// Limits enforced on connections and requests
+    pub fn limits(&self) -> &Arc<Limits> {
+        &self.limits
+    }
+
+    /// Liveness and readiness signals
+    pub fn health(&self) -> &HealthState {
+        &self.health
+    }
+
+    /// Whether the broker can serve data right now
+    pub fn readiness(&self) -> HealthStatus {
+        self.health.readiness(
+            self.connections.total_in_flight(),
+            self.limits.readiness_max_in_flight,
+        )
+    }
+
+    /// Per-partition produce and fetch throughput
+    pub fn throughput(&self) -> &ThroughputTracker {
+        &self.throughput
+    }
+
+    /// Per-topic traffic counters with bounded cardinality
+    pub fn topic_metrics(&self) -> &TopicMetrics {
+        &self.topic_metrics
+    }
+
+    /// Time batches spent in the broker before being fetched, per topic
+    pub fn delivery_latency(&self) -> &DeliveryLatencyTracker {
+        &self.delivery_latency
+    }
+
+    /// Consumer groups coordinated by this broker
+    pub fn groups(&self) -> &GroupCoordinator {
+        &self.groups
+    }
+
+    /// Properties the broker was started with
+    pub fn config(&self) -> &BrokerConfig {
+        &self.config
+    }
+
+    /// Deletes expired batches from every partition, returning how many
+    /// were deleted
+    ///
+    /// A topic's own `retention.ms` applies, or the broker's
+    /// `log.retention.ms` when it has none; -1 keeps everything. Batch
+    /// timestamps are trusted up to the topic's
+    /// `message.timestamp.after.max.ms` past their append time.
+    pub fn enforce_retention(&self) -> usize {
+        let log_retention_ms = match self.config.log_retention_ms() {
+            Ok(retention_ms) => retention_ms,
+            Err(e) => {
+                warn!(error = %e, "Invalid log.retention.ms, skipping retention");
+                return 0;
+            }
+        };
+        self.topics
+            .list_topics()
+            .iter()
+            .map(|topic| {
+                let config = self.topic_config(topic);
+                let retention_ms = config.retention_ms().unwrap_or(log_retention_ms);
+                let max_future_ms = config.message_timestamp_after_max_ms();
+                topic
+                    .partitions
+                    .iter()
+                    .map(|log| log.delete_expired_batches(retention_ms, max_future_ms))
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Drops idle producer state from every partition, returning how many
+    /// producers were dropped
+    pub fn expire_idle_producers(&self) -> usize {
+        self.topics
+            .list_topics()
+            .iter()
+            .flat_map(|topic| &topic.partitions)
+            .map(|log| log.producers().expire_idle_producers())
+            .sum()
+    }
+
+    /// Saved unparseable requests
+    pub fn quarantine(&self) -> &Quarantine {
+        &self.quarantine
+    }
+
+    /// Produce requests waiting for acks=-1
+    pub fn purgatory(&self) -> &Purgatory {
+        &self.purgatory
+    }
+
+    /// Worker queue requests wait in before being served
+    pub fn request_queue(&self) -> &RequestQueue {
+        &self.request_queue
+    }
+
+    /// Configuration changed at runtime, such as topic overrides
+    pub fn dynamic_config(&self) -> &DynamicConfigRegistry {
+        &self.dynamic_config
+    }
+
+    /// Counter bumped once per batch of metadata changes
+    pub fn metadata_epoch(&self) -> &MetadataEpoch {
+        &self.metadata_epoch
+    }
+
+    /// Imports a dynamic configuration document as one metadata change
+    ///
+    /// A document that fails validation leaves the epoch alone.
+    pub fn import_dynamic_config(
+        &self,
+        document: &DynamicConfigDocument,
+    ) -> Result<ImportSummary, DynamicConfigError> {
+        self.dynamic_config.import_under(document, |apply| {
+            self.metadata_epoch.advance(&self.events, apply).map(drop)
+        })
+    }
+
+    /// Starts a graceful shutdown
+    ///
+    /// Parked produces are completed first so their responses are written
+    /// while connections are still open. Each connection then stops reading
+    /// new requests, flushes the in-flight response and closes.
+    pub fn begin_shutdown(&self) {
+        self.purgatory.drain();
+        self.shutdown.cancel();
+    }
+
+    /// Bus carrying topic, config and listener change events
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Cache of encoded metadata-style responses
+    pub fn response_cache(&self) -> &ResponseCache {
+        &self.response_cache
+    }
+
+    /// Incremental fetch sessions of consumers
+    pub fn fetch_sessions(&self) -> &FetchSessionCache {
+        &self.fetch_sessions
+    }
+
+    /// Latest events published on the bus, for diagnostics
+    pub fn recent_events(&self) -> &RecentEvents {
+        &self.recent_events
+    }
+
+    /// Snapshot of in-memory state for debugging
+    ///
+    /// Each component is read under its own lock, one after the other.
+    /// Partition producer state is copied out before the log is read, as
+    /// appends take the log's lock before the producers'.
+    pub fn dump(&self) -> StateSnapshot {
+        let mut snapshot = StateSnapshot {
+            taken_at_ms: SystemClock.now_ms(),
+            cluster_id: self.cluster_id.clone(),
+            metadata_epoch: self.metadata_epoch.current(),
+            liveness: self.health.liveness(),
+            readiness: self.readiness(),
+            connections: self.connections.list(&ConnectionFilter::default()),
+            limits: self.limits.describe(),
+            response_cache: self.response_cache.metrics(),
+            event_subscribers: self.events.subscriber_count(),
+            cancelled_by_disconnect: self.cancelled_by_disconnect(),
+            quarantine: self.quarantine().metrics(),
+            fetch_sessions: self.fetch_sessions.len(),
+            partitions: Vec::new(),
+            groups: self.groups.snapshot(),
+            committed_offsets: self.offsets.snapshot(),
+            producer_ids: self.producer_ids.snapshot(),
+            topic_configs: BTreeMap::new(),
+        };
+        for topic in self.topics.list_topics() {
+            for log in &topic.partitions {
+                let producers = log.producers().clone();
+                snapshot.add_partition(log, Some(&producers));
+            }
+        }
+        for (topic, config) in self.dynamic_config.topic_configs() {
+            snapshot.add_topic_config(&topic, &config);
+        }
+        snapshot
     }
 
     /// Handles incoming client connections
     ///
     /// This method processes client requests and generates appropriate responses.
     /// It follows the Interface Segregation Principle by providing a clean
-    /// interface for connection handling.
-    pub async fn handle_connection(&self, stream: &mut TcpStream) -> Result<()> {
-        let peer_addr = stream.peer_addr()?;
+    /// interface for connection handling. The stream is generic so that tests
+    /// can drive the broker over in-memory pipes; `peer_addr` is passed in
+    /// because such streams have no address of their own.
+    ///
+    /// A request that fails to decode is answered under its correlation id
+    /// with an error code as the body, and the connection carries on.
+    ///
+    /// # Examples
+    /// ```
+    /// use codecrafters_kafka::kafka::broker::KafkaBroker;
+    /// use codecrafters_kafka::protocol::api_versions::ApiVersionsResponse;
+    /// use codecrafters_kafka::protocol::{ErrorCode, ProtocolEncode, RequestHeaderV2};
+    /// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let broker = KafkaBroker::new();
+    /// let (mut client, mut server) = tokio::io::duplex(4096);
+    /// let connection = async move {
+    ///     broker
+    ///         .handle_connection(&mut server, "127.0.0.1:50000".parse().unwrap())
+    ///         .await
+    /// };
+    /// let exchange = async move {
+    ///     // ApiVersions v0: header only, no body
+    ///     let request = RequestHeaderV2::with_client_id(18, 0, 7, "example").encode()?;
+    ///     client.write_i32(request.len() as i32).await?;
+    ///     client.write_all(&request).await?;
+    ///
+    ///     let mut response = vec![0; client.read_i32().await? as usize];
+    ///     client.read_exact(&mut response).await?;
+    ///     drop(client);
+    ///     anyhow::Ok(response)
+    /// };
+    /// let (_, response) = tokio::join!(connection, exchange);
+    ///
+    /// let mut response = bytes::Bytes::from(response?);
+    /// assert_eq!(&response.split_to(4)[..], &7i32.to_be_bytes());
+    /// let body = ApiVersionsResponse::decode(&mut response, 0)?;
+    /// assert_eq!(body.error_code, ErrorCode::NONE);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn handle_connection<S>(&self, stream: &mut S, peer_addr: SocketAddr) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         debug!(peer_addr = %peer_addr, "Starting connection handling");
 
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut frames = FrameReader::new(reader, self.limits.max_request_bytes);
+
+        // Cancelled when the client goes away so in-flight and parked
+        // operations for this connection are dropped promptly
+        let cancellation = CancellationToken::new();
+        let _cancel_on_exit = cancellation.clone().drop_guard();
+
+        let registration = self.connections.register(peer_addr);
+        let connected_at = Instant::now();
+        let mut state = ConnectionState::default();
+
         loop {
-            // Read message length (first 4 bytes)
-            let mut length_buffer = [0u8; 4];
-            match stream.read_exact(&mut length_buffer).await {
-                Ok(_) => {
-                    let message_length = u32::from_be_bytes(length_buffer) as usize;
-                    debug!(
+            // An operator close or a broker shutdown is only honoured
+            // between requests, so the in-flight request always completes
+            // and its response is flushed
+            let next_frame = tokio::select! {
+                biased;
+                _ = registration.close_requested() => Err("operator request"),
+                _ = self.shutdown.cancelled() => Err("broker shutdown"),
+                frame = frames.read_frame() => Ok(frame),
+            };
+            let next_frame = match next_frame {
+                Ok(frame) => frame,
+                Err(reason) => {
+                    info!(
                         peer_addr = %peer_addr,
-                        message_length = message_length,
-                        "Read message length prefix"
+                        connection_id = registration.id(),
+                        reason = reason,
+                        "Closing connection"
                     );
+                    writer.shutdown().await?;
+                    break;
+                }
+            };
 
-                    if message_length == 0 {
-                        warn!(peer_addr = %peer_addr, "Received message with zero length");
-                        continue;
-                    }
+            // Frozen once: every field decoded from here on is a view into
+            // this frame rather than a copy
+            let mut message_buffer = match next_frame {
+                Ok(Some(frame)) => frame.freeze(),
+                Ok(None) => {
+                    info!(peer_addr = %peer_addr, "Client disconnected");
+                    break;
+                }
+                Err(e) => {
+                    error!(
+                        peer_addr = %peer_addr,
+                        error = %e,
+                        "Failed to read request frame"
+                    );
+                    return Err(e);
+                }
+            };
 
-                    if message_length > 1024 * 1024 {
-                        error!(
-                            peer_addr = %peer_addr,
-                            message_length = message_length,
-                            max_allowed = 1024 * 1024,
-                            "Message too large, closing connection"
-                        );
-                        return Err(anyhow::anyhow!(
-                            "Message too large: {} bytes",
-                            message_length
-                        ));
-                    }
+            // One read per request; the request keeps this level throughout
+            let diagnostics = self.dynamic_config.diagnostics().level();
+            let context =
+                RequestContext::new(peer_addr, cancellation.clone()).with_diagnostics(diagnostics);
+            registration.set_client_id(Self::peek_client_id(&message_buffer).as_deref());
+            let _in_flight = registration.begin_request();
+            // Taken up front since decoding consumes the buffer; the clone
+            // shares the frame's memory, and with diagnostics off there is
+            // nothing to keep it for
+            let kept = (diagnostics > DiagnosticsLevel::Off).then(|| message_buffer.clone());
+            // Peeked before decoding consumes the frame, so a request that
+            // fails to decode can still be answered under its correlation id
+            let request_ids =
+                Self::peek_api(&message_buffer).zip(Self::peek_correlation_id(&message_buffer));
+            if diagnostics.allows(DiagnosticFeature::Capture) {
+                info!(
+                    peer_addr = %peer_addr,
+                    capture = %format_args!(
+                        "> {} {}",
+                        connected_at.elapsed().as_millis(),
+                        hex::encode(&message_buffer)
+                    ),
+                    "Captured request"
+                );
+            }
+
+            // Control-plane requests jump the queue for a worker; a frame
+            // too short to name its API waits with the data plane
+            let priority = request_ids.map_or(RequestPriority::Data, |((api_key, _), _)| {
+                RequestPriority::classify(api_key)
+            });
 
-                    // Read the message data
-                    let mut message_buffer = BytesMut::with_capacity(message_length);
-                    message_buffer.resize(message_length, 0);
+            // Process the request while watching for the client going away,
+            // which also abandons its place in the worker queue
+            let result = tokio::select! {
+                biased;
+                result = async {
+                    let _permit = self.request_queue.acquire(priority).await;
+                    self.serve_request(&mut message_buffer, &context, &mut state).await
+                } => result,
+                _ = frames.wait_for_disconnect() => {
+                    cancellation.cancel();
+                    self.cancelled_by_disconnect.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        peer_addr = %peer_addr,
+                        "Client disconnected with a request in flight, cancelling it"
+                    );
+                    break;
+                }
+            };
 
-                    match stream.read_exact(&mut message_buffer).await {
-                        Ok(_) => {
-                            debug!(
+            let response = match result {
+                Ok(response) => response,
+                Err(e) => {
+                    error!(
+                        peer_addr = %peer_addr,
+                        error = %format_args!("{:#}", e),
+                        "Failed to process request"
+                    );
+                    if let (Some(protocol_error), Some(frame)) =
+                        (e.downcast_ref::<ProtocolError>(), &kept)
+                    {
+                        if is_decode_failure(protocol_error) {
+                            self.diagnose_decode_failure(&context, frame, protocol_error);
+                        }
+                    }
+                    // Frames are length-prefixed, so a request that fails
+                    // leaves the next one readable. Only a frame too short
+                    // to name its correlation id goes unanswered.
+                    let Some(((api_key, api_version), correlation_id)) = request_ids else {
+                        warn!(
+                            peer_addr = %peer_addr,
+                            "Request is too short to carry a correlation id, skipping it"
+                        );
+                        continue;
+                    };
+                    match e.downcast::<ProtocolError>() {
+                        Ok(protocol_error) => {
+                            let error_code = wire_error(&BrokerError::Protocol(protocol_error));
+                            warn!(
                                 peer_addr = %peer_addr,
-                                bytes_read = message_length,
-                                "Successfully read message data"
+                                correlation_id,
+                                error_code = %error_code,
+                                "Request could not be decoded, returning an error response"
                             );
-
-                            // Process the request
-                            match self.process_request(&mut message_buffer, peer_addr).await {
-                                Ok(response) => {
-                                    // Send response length prefix
-                                    let response_length = response.len() as u32;
-                                    stream.write_all(&response_length.to_be_bytes()).await?;
-                                    stream.write_all(&response).await?;
-
-                                    debug!(
-                                        peer_addr = %peer_addr,
-                                        response_length = response_length,
-                                        "Sent response successfully"
-                                    );
-                                }
-                                Err(e) => {
-                                    error!(
-                                        peer_addr = %peer_addr,
-                                        error = %e,
-                                        "Failed to process request"
-                                    );
-                                    // Continue processing other requests instead of closing connection
-                                    continue;
-                                }
-                            }
+                            Some(Self::error_response(
+                                api_key,
+                                api_version,
+                                correlation_id,
+                                error_code,
+                            )?)
                         }
+                        // A handler failure is answered in the API's own
+                        // response, with the code its error maps to
                         Err(e) => {
-                            error!(
+                            let error_code = match (
+                                ApiKey::try_from(api_key),
+                                e.downcast_ref::<BrokerError>(),
+                            ) {
+                                (Ok(api), Some(error)) => wire_error_for(api, api_version, error),
+                                (Err(_), Some(error)) => wire_error(error),
+                                (_, None) => ErrorCode::UNKNOWN_SERVER_ERROR,
+                            };
+                            warn!(
                                 peer_addr = %peer_addr,
-                                error = %e,
-                                expected_bytes = message_length,
-                                "Failed to read message data"
+                                correlation_id,
+                                error_code = %error_code,
+                                "Request failed, returning an error response"
                             );
-                            return Err(e.into());
+                            Some(
+                                Self::api_error_response(
+                                    api_key,
+                                    api_version,
+                                    correlation_id,
+                                    error_code,
+                                )?
+                                .freeze(),
+                            )
                         }
                     }
                 }
-                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    info!(peer_addr = %peer_addr, "Client disconnected");
-                    break;
+            };
+
+            match response {
+                None => {
+                    debug!(peer_addr = %peer_addr, "Request expects no response");
                 }
-                Err(e) => {
-                    error!(
+                Some(response) => {
+                    // Already framed: length prefix, header and body
+                    writer.write_all(&response).await?;
+                    if diagnostics.allows(DiagnosticFeature::Capture) {
+                        info!(
+                            peer_addr = %peer_addr,
+                            capture = %format_args!(
+                                "< {} {}",
+                                connected_at.elapsed().as_millis(),
+                                hex::encode(&response[RESPONSE_LENGTH_PREFIX..])
+                            ),
+                            "Captured response"
+                        );
+                    }
+
+                    debug!(
                         peer_addr = %peer_addr,
-                        error = %e,
-                        "Failed to read message length"
+                        response_length = response.len() - RESPONSE_LENGTH_PREFIX,
+                        "Sent response successfully"
                     );
-                    return Err(e.into());
                 }
             }
         }
@@ -130,24 +885,106 @@ impl KafkaBroker {
         Ok(())
     }
 
-    /// Processes a single request and returns the response
-    async fn process_request(
+    /// Returns how many in-flight requests were cancelled because their
+    /// client disconnected before a response could be sent
+    pub fn cancelled_by_disconnect(&self) -> u64 {
+        self.cancelled_by_disconnect.load(Ordering::Relaxed)
+    }
+
+    /// Frames a response carrying only `error_code` as its body
+    ///
+    /// Used for requests that could not be decoded, where no layout of the
+    /// API's response can be filled in.
+    fn error_response(
+        api_key: i16,
+        api_version: i16,
+        correlation_id: i32,
+        error_code: ErrorCode,
+    ) -> ProtocolResult<Bytes> {
+        let response_header = ResponseHeader::for_api(api_key, api_version, correlation_id);
+        let mut response =
+            BytesMut::with_capacity(RESPONSE_LENGTH_PREFIX + response_header.encoded_size() + 2);
+        response.put_u32(0);
+        response_header.encode_to(&mut response)?;
+        response.put_i16(error_code.code());
+        let response_length = response.len() - RESPONSE_LENGTH_PREFIX;
+        response[..RESPONSE_LENGTH_PREFIX].copy_from_slice(&(response_length as u32).to_be_bytes());
+        Ok(response.freeze())
+    }
+
+    /// Frames the response of API `api_key` carrying only `error_code`
+    ///
+    /// A built-in API is answered with its own response, empty but for the
+    /// code, at `api_version` or the nearest version it is decoded at, and
+    /// the header follows that version. Other APIs have no layout to fill
+    /// in and get the bare code.
+    fn api_error_response(
+        api_key: i16,
+        api_version: i16,
+        correlation_id: i32,
+        error_code: ErrorCode,
+    ) -> ProtocolResult<BytesMut> {
+        let typed = ApiKey::try_from(api_key)
+            .ok()
+            .and_then(|api| KafkaResponse::error(api, error_code))
+            .zip(
+                KafkaRequest::VERSION_RANGES
+                    .iter()
+                    .find(|range| range.api_key == api_key),
+            );
+        let Some((body, range)) = typed else {
+            return Ok(BytesMut::from(
+                &Self::error_response(api_key, api_version, correlation_id, error_code)?[..],
+            ));
+        };
+        let version = api_version.clamp(range.min_version, range.max_version);
+        let response_header = ResponseHeader::for_api(api_key, version, correlation_id);
+        let mut response = BytesMut::with_capacity(
+            RESPONSE_LENGTH_PREFIX + response_header.encoded_size() + body.encoded_size(version),
+        );
+        response.put_u32(0);
+        response_header.encode_to(&mut response)?;
+        body.encode_versioned(version, &mut response)?;
+        let response_length = response.len() - RESPONSE_LENGTH_PREFIX;
+        response[..RESPONSE_LENGTH_PREFIX].copy_from_slice(&(response_length as u32).to_be_bytes());
+        Ok(response)
+    }
+
+    /// Decodes and serves a single request, returning the framed response
+    ///
+    /// The response is built in one buffer: a length placeholder, the
+    /// header, then the body, with the length backfilled once the body is
+    /// written.
+    async fn serve_request(
         &self,
-        buffer: &mut BytesMut,
-        peer_addr: std::net::SocketAddr,
-    ) -> Result<Vec<u8>> {
+        buffer: &mut Bytes,
+        context: &RequestContext,
+        state: &mut ConnectionState,
+    ) -> Result<Option<Bytes>> {
         let processing_start = Instant::now();
+        let peer_addr = context.peer_addr;
         let original_buffer_len = buffer.len();
+        WireFormat::trace_hex_dump("request", buffer, MAX_HEX_DUMP_BYTES);
 
-        // Parse request header
-        let header = match RequestHeaderV2::decode(buffer) {
+        // Parse request header; non-flexible requests carry no tag section.
+        // ApiVersions is parsed leniently: it has to be answered even when
+        // a newer client sends a header layout this broker does not know.
+        let decoded = match Self::peek_api(buffer) {
+            Some((api_key, _)) if api_key == ApiKey::ApiVersions.code() => {
+                RequestHeaderV2::decode_lenient(buffer).map(RequestHeader::V2)
+            }
+            Some((api_key, api_version)) => RequestHeader::decode_for(api_key, api_version, buffer),
+            None => RequestHeaderV2::decode(buffer).map(RequestHeader::V2),
+        };
+        let header = match decoded {
             Ok(h) => {
                 debug!(
                     peer_addr = %peer_addr,
-                    api_key = h.request_api_key,
-                    api_version = h.request_api_version,
-                    correlation_id = h.correlation_id,
-                    client_id = ?h.client_id,
+                    api_key = %api_key_name(h.api_key_code()),
+                    api_version = h.api_version(),
+                    correlation_id = h.correlation_id(),
+                    client_id = ?h.client_id(),
+                    header_version = h.version(),
                     "Successfully parsed request header"
                 );
                 h
@@ -156,126 +993,6025 @@ impl KafkaBroker {
                 error!(
                     peer_addr = %peer_addr,
                     error = %e,
+                    offset = ?e.offset(),
+                    field = ?e.path(),
                     buffer_length = original_buffer_len,
                     remaining_bytes = buffer.remaining(),
                     "Failed to parse request header"
                 );
 
-                // Log buffer contents for debugging
-                if buffer.len() <= 50 {
-                    debug!(
-                        peer_addr = %peer_addr,
-                        buffer_hex = hex::encode(&buffer[..]),
-                        "Request buffer contents (hex)"
-                    );
-                }
-
-                return Err(anyhow::anyhow!("Failed to parse request header: {}", e));
+                return Err(anyhow::Error::new(e).context("Failed to parse request header"));
             }
         };
 
         // Create request span for detailed tracking
         let request_span = LogUtils::request_span(
-            header.request_api_key as u16,
-            header.correlation_id,
-            header.client_id.as_deref(),
+            header.api_key_code(),
+            header.correlation_id(),
+            header.client_id(),
         );
         let _span_guard = request_span.enter();
 
-        // Create response header
-        let response_header = ResponseHeaderV0 {
-            correlation_id: header.correlation_id,
+        // The response header version depends on the API, not on the
+        // request header the client sent
+        let response_header = ResponseHeader::for_api(
+            header.api_key_code(),
+            header.api_version(),
+            header.correlation_id(),
+        );
+
+        // Handlers return the body before anything is written, so the whole
+        // frame is allocated once at its final size
+        let new_response = |body_size: usize| -> ProtocolResult<BytesMut> {
+            let mut response = BytesMut::with_capacity(
+                RESPONSE_LENGTH_PREFIX + response_header.encoded_size() + body_size,
+            );
+            response.put_u32(0);
+            response_header.encode_to(&mut response)?;
+            Ok(response)
         };
 
-        // Generate response based on API key
-        let response_data = match header.request_api_key {
-            18 => {
-                // ApiVersions request
-                debug!("Processing ApiVersions request");
-                self.handle_api_versions_request(&header).await?
-            }
-            _ => {
-                warn!(
-                    api_key = header.request_api_key,
-                    "Unsupported API key, returning error response"
+        let unsupported = || -> ProtocolResult<BytesMut> {
+            warn!(
+                api_key = %api_key_name(header.api_key_code()),
+                api_version = header.api_version(),
+                "Unsupported API key or version, returning error response"
+            );
+            let error_code = self.handle_unsupported_request(&header);
+            Self::api_error_response(
+                header.api_key_code(),
+                header.api_version(),
+                header.correlation_id(),
+                error_code,
+            )
+        };
+
+        // Versions are checked against the same table ApiVersions lists,
+        // except for ApiVersions itself, which is answered at any version
+        // so the client can downgrade
+        let (handler, supported) = {
+            let handlers = self.handlers.read().unwrap();
+            (
+                handlers.get(header.api_key_code()),
+                header.api_key() == Ok(ApiKey::ApiVersions)
+                    || handlers
+                        .supported_apis()
+                        .supports(header.api_key_code(), header.api_version()),
+            )
+        };
+        // Registered handlers come first, so they can replace a built-in API
+        let mut response = match handler {
+            _ if !supported => unsupported()?,
+            Some(handler) => {
+                debug!(
+                    api_key = %api_key_name(header.api_key_code()),
+                    "Processing request with registered handler"
                 );
-                self.handle_unsupported_request(&header).await?
+                let mut request = std::mem::take(buffer);
+                match self.response_cache.lookup(
+                    header.api_key_code(),
+                    header.api_version(),
+                    self.metadata_epoch.current(),
+                    request.clone(),
+                ) {
+                    CacheLookup::Hit(body) => {
+                        let mut response = new_response(body.len())?;
+                        response.extend_from_slice(&body);
+                        response
+                    }
+                    CacheLookup::Miss(ticket) => {
+                        let body = handler.handle(context, &header, &mut request).await?;
+                        let mut response = new_response(body.len())?;
+                        response.extend_from_slice(&body);
+                        self.response_cache.store(ticket, body.freeze());
+                        response
+                    }
+                    CacheLookup::Uncacheable => {
+                        let body = handler.handle(context, &header, &mut request).await?;
+                        let mut response = new_response(body.len())?;
+                        response.extend_from_slice(&body);
+                        response
+                    }
+                }
             }
+            None => match KafkaRequest::decode(&header, buffer)? {
+                KafkaRequest::Unknown { .. } => unsupported()?,
+                request => match self.dispatch(&header, request, context, state).await? {
+                    Some(body) => {
+                        let version = header.api_version();
+                        let mut response = new_response(body.encoded_size(version))?;
+                        body.encode_versioned(version, &mut response)?;
+                        response
+                    }
+                    None => return Ok(None),
+                },
+            },
         };
-
-        // Encode response
-        let mut response = BytesMut::new();
-        response.extend_from_slice(&response_header.encode()?);
-        response.extend_from_slice(&response_data);
+        let response_length = response.len() - RESPONSE_LENGTH_PREFIX;
+        response[..RESPONSE_LENGTH_PREFIX].copy_from_slice(&(response_length as u32).to_be_bytes());
 
         let processing_time = processing_start.elapsed();
 
         // Log request metrics
-        LogUtils::log_request_metrics(
-            header.request_api_key as u16,
-            header.correlation_id,
-            original_buffer_len,
-            response.len(),
-            processing_time.as_millis() as u64,
-            true, // success
-        );
+        if context
+            .diagnostics
+            .allows(DiagnosticFeature::RequestMetrics)
+        {
+            LogUtils::log_request_metrics(
+                header.api_key_code(),
+                header.correlation_id(),
+                original_buffer_len,
+                response_length,
+                processing_time.as_millis() as u64,
+                true, // success
+            );
+        }
 
-        Ok(response.to_vec())
+        Ok(Some(response.freeze()))
     }
 
-    /// Handles ApiVersions requests
-    async fn handle_api_versions_request(&self, _header: &RequestHeaderV2) -> Result<Vec<u8>> {
-        debug!("Generating ApiVersions response");
-
-        // Simple ApiVersions response structure:
-        // - error_code: i16 = 0 (no error)
-        // - api_versions: ARRAY
-        //   - api_key: i16
-        //   - min_version: i16
-        //   - max_version: i16
-        // - throttle_time_ms: i32 = 0
-
-        let mut response = BytesMut::new();
-
-        // Error code: 0 (no error)
-        response.put_i16(0);
-
-        // API versions array length: 1 (we support ApiVersions only)
-        response.put_i32(1);
-
-        // ApiVersions API (key 18)
-        response.put_i16(18); // api_key
-        response.put_i16(0); // min_version
-        response.put_i16(1); // max_version
-
-        // Throttle time: 0
-        response.put_i32(0);
-
+    /// Routes a decoded request to its handler
+    ///
+    /// Returns `None` when the client expects no response.
+    async fn dispatch(
+        &self,
+        header: &RequestHeader,
+        request: KafkaRequest,
+        context: &RequestContext,
+        state: &mut ConnectionState,
+    ) -> Result<Option<KafkaResponse>> {
+        let version = header.api_version();
         debug!(
-            response_length = response.len(),
-            "Generated ApiVersions response"
-        );
-        Ok(response.to_vec())
-    }
-
-    /// Handles unsupported requests
-    async fn handle_unsupported_request(&self, header: &RequestHeaderV2) -> Result<Vec<u8>> {
-        warn!(
-            api_key = header.request_api_key,
-            "Generating error response for unsupported API"
+            api_key = %api_key_name(request.api_key()),
+            api_version = version,
+            "Dispatching request"
         );
-
-        // Simple error response with error code 35 (UNSUPPORTED_VERSION)
-        let mut response = BytesMut::new();
-        response.put_i16(35); // UNSUPPORTED_VERSION error code
-
-        debug!(response_length = response.len(), "Generated error response");
-        Ok(response.to_vec())
-    }
-}
-
-impl Default for KafkaBroker {
-    fn default() -> Self {
-        Self::new()
+        let response = match request {
+            KafkaRequest::Produce(request) => {
+                match self
+                    .handle_produce_request(header, request, context)
+                    .await?
+                {
+                    Some(produce) => produce.into(),
+                    None => return Ok(None),
+                }
+            }
+            KafkaRequest::Fetch(request) => {
+                match self.handle_fetch_request(header, request, context).await? {
+                    Some(fetch) => fetch.into(),
+                    None => return Ok(None),
+                }
+            }
+            KafkaRequest::ListOffsets(request) => self.handle_list_offsets_request(request)?.into(),
+            KafkaRequest::Metadata(request) => self.handle_metadata_request(request)?.into(),
+            KafkaRequest::OffsetCommit(request) => {
+                self.handle_offset_commit_request(request)?.into()
+            }
+            KafkaRequest::OffsetFetch(request) => {
+                self.handle_offset_fetch_request(version, request)?.into()
+            }
+            KafkaRequest::FindCoordinator(request) => self
+                .handle_find_coordinator_request(version, request)?
+                .into(),
+            KafkaRequest::JoinGroup(request) => self
+                .handle_join_group_request(header, request)
+                .await?
+                .into(),
+            KafkaRequest::Heartbeat(request) => self.handle_heartbeat_request(request)?.into(),
+            KafkaRequest::LeaveGroup(request) => {
+                self.handle_leave_group_request(version, request)?.into()
+            }
+            KafkaRequest::SyncGroup(request) => {
+                self.handle_sync_group_request(request).await?.into()
+            }
+            KafkaRequest::SaslHandshake(request) => {
+                self.handle_sasl_handshake_request(request, state)?.into()
+            }
+            KafkaRequest::CreateTopics(request) => {
+                self.handle_create_topics_request(request)?.into()
+            }
+            KafkaRequest::DeleteTopics(request) => {
+                self.handle_delete_topics_request(request)?.into()
+            }
+            KafkaRequest::InitProducerId(request) => {
+                self.handle_init_producer_id_request(request)?.into()
+            }
+            KafkaRequest::DescribeConfigs(request) => {
+                self.handle_describe_configs_request(request)?.into()
+            }
+            KafkaRequest::SaslAuthenticate(request) => self
+                .handle_sasl_authenticate_request(request, context, state)?
+                .into(),
+            KafkaRequest::IncrementalAlterConfigs(request) => self
+                .handle_incremental_alter_configs_request(request)?
+                .into(),
+            KafkaRequest::DescribeCluster(request) => {
+                self.handle_describe_cluster_request(request)?.into()
+            }
+            KafkaRequest::DescribeTopicPartitions(request) => self
+                .handle_describe_topic_partitions_request(request)?
+                .into(),
+            // Answered before typed decoding
+            request @ (KafkaRequest::ApiVersions(_) | KafkaRequest::Unknown { .. }) => {
+                bail!(
+                    "{} request cannot be dispatched",
+                    api_key_name(request.api_key())
+                )
+            }
+        };
+        Ok(Some(response))
+    }
+
+    /// Runs the diagnostics the request's level asks for on a frame that
+    /// failed to parse
+    fn diagnose_decode_failure(
+        &self,
+        context: &RequestContext,
+        frame: &Bytes,
+        error: &ProtocolError,
+    ) {
+        let level = context.diagnostics;
+        if level.allows(DiagnosticFeature::Quarantine) {
+            if let Some((kept, frame_len)) = self.quarantine.keep(frame) {
+                self.quarantine
+                    .record(context.peer_addr, &kept, frame_len, error);
+            }
+        }
+        if level.allows(DiagnosticFeature::HexDump) {
+            info!(
+                peer_addr = %context.peer_addr,
+                frame_len = frame.len(),
+                "Unparseable request:\n{}",
+                WireFormat::hex_dump(frame, MAX_HEX_DUMP_BYTES)
+            );
+        }
+        if level.allows(DiagnosticFeature::DecodeTrace) {
+            let mut trace = DecodeTrace::new();
+            let _ = trace_request(frame, &mut trace);
+            info!(
+                peer_addr = %context.peer_addr,
+                "Decode trace of unparseable request:\n{}",
+                trace.render(frame)
+            );
+        }
+    }
+
+    /// Reads the client id from a request header without consuming it
+    ///
+    /// Held to the same rules as header decoding: an id over the length
+    /// limit reads as absent and control characters are escaped.
+    fn peek_client_id(buffer: &[u8]) -> Option<Cow<'_, str>> {
+        let length = i16::from_be_bytes(buffer.get(8..10)?.try_into().ok()?);
+        let length = usize::try_from(length).ok()?;
+        if length > DecodeLimits::current().max_client_id_length {
+            return None;
+        }
+        std::str::from_utf8(buffer.get(10..10 + length)?)
+            .ok()
+            .map(sanitize_client_id)
+    }
+
+    /// Returns the correlation id that follows the api key and version
+    fn peek_correlation_id(buffer: &[u8]) -> Option<i32> {
+        WireFormat::peek_i32(buffer.get(4..)?).ok()
+    }
+
+    /// Returns the api key and version the request in `buffer` starts with
+    fn peek_api(buffer: &[u8]) -> Option<(i16, i16)> {
+        let api_key = WireFormat::peek_i16(buffer).ok()?;
+        let api_version = buffer
+            .get(2..4)
+            .map(|bytes| i16::from_be_bytes([bytes[0], bytes[1]]))?;
+        Some((api_key, api_version))
+    }
+
+    /// Handles Fetch requests
+    ///
+    /// From v7 a full fetch opens a [`FetchSessionCache`] session, and the
+    /// session's incremental fetches only get the partitions that changed.
+    /// Topics are looked up by id when the request carries no name, as from
+    /// v13. When the records read fall short of `min_bytes`, the fetch waits
+    /// for a fetched partition's high watermark to move and reads again,
+    /// until there are enough or `max_wait_ms` has passed. An error in any
+    /// partition answers at once. Returns `None` if the connection goes away
+    /// while waiting, leaving the fetch session as if the fetch never came.
+    async fn handle_fetch_request(
+        &self,
+        header: &RequestHeader,
+        mut request: FetchRequest,
+        context: &RequestContext,
+    ) -> Result<Option<FetchResponse>> {
+        let version = header.api_version();
+        debug!(
+            topics = request.topics.len(),
+            session_id = request.session_id,
+            session_epoch = request.session_epoch,
+            "Decoded Fetch request"
+        );
+        let session = match self.fetch_sessions.begin(version, &mut request) {
+            Ok(session) => session,
+            Err(error_code) => {
+                debug!(
+                    session_id = request.session_id,
+                    error_code = ?error_code,
+                    "Fetch session refused"
+                );
+                return Ok(Some(FetchResponse {
+                    throttle_time_ms: 0,
+                    error_code,
+                    session_id: 0,
+                    responses: Vec::new(),
+                }));
+            }
+        };
+        let deadline = Instant::now() + Duration::from_millis(request.max_wait_ms.max(0) as u64);
+        // Subscribed before the first read, so an append landing in between
+        // still wakes the wait below
+        let mut watermarks = self.subscribe_fetch_watermarks(&request.topics);
+        let (mut responses, mut fetched) = self.read_fetch_topics(&request, version).await;
+        while !fetch_is_satisfied(&responses, request.min_bytes) && Instant::now() < deadline {
+            let seen = responses
+                .iter()
+                .flat_map(|topic| &topic.partitions)
+                .map(|partition| partition.high_watermark);
+            tokio::select! {
+                _ = wait_past_any(&mut watermarks, seen) => {}
+                _ = tokio::time::sleep_until(deadline.into()) => break,
+                _ = context.cancelled() => {
+                    debug!(
+                        peer_addr = %context.peer_addr,
+                        "Client disconnected during a Fetch wait"
+                    );
+                    return Ok(None);
+                }
+            }
+            (responses, fetched) = self.read_fetch_topics(&request, version).await;
+        }
+        // Only the read that is answered counts, however often we read
+        for batches in &fetched {
+            self.record_fetched(batches, header.client_id());
+        }
+        self.fetch_sessions.finish(session, &mut responses);
+        Ok(Some(FetchResponse {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            session_id: session.session_id,
+            responses,
+        }))
+    }
+
+    /// Resolves a fetched topic by name, or by id from v13
+    fn lookup_fetch_topic(&self, topic: &FetchTopic) -> Option<Arc<Topic>> {
+        if topic.topic.is_empty() {
+            self.topics.get_topic_by_id(topic.topic_id)
+        } else {
+            self.topics.get_topic(&topic.topic)
+        }
+    }
+
+    /// Subscribes to the high watermark of every fetched partition
+    ///
+    /// One entry per requested partition, in request order, so they line up
+    /// with the partitions of [`Self::read_fetch_topics`]. Unknown partitions
+    /// have no watermark to wait on.
+    fn subscribe_fetch_watermarks(
+        &self,
+        topics: &[FetchTopic],
+    ) -> Vec<Option<HighWatermarkSubscriber>> {
+        let mut watermarks = Vec::new();
+        for topic in topics {
+            let stored = self.lookup_fetch_topic(topic);
+            watermarks.extend(topic.partitions.iter().map(|partition| {
+                let stored = stored.as_ref()?;
+                let log = stored
+                    .partitions
+                    .get(usize::try_from(partition.partition).ok()?)?;
+                Some(log.subscribe_high_watermark())
+            }));
+        }
+        watermarks
+    }
+
+    /// Reads every partition of a Fetch request once
+    ///
+    /// Returns the responses along with what was read from each partition
+    /// that returned batches, for [`Self::record_fetched`].
+    async fn read_fetch_topics(
+        &self,
+        request: &FetchRequest,
+        version: i16,
+    ) -> (Vec<FetchResponseTopic>, Vec<FetchedBatches>) {
+        let mut responses = Vec::with_capacity(request.topics.len());
+        let mut fetched = Vec::new();
+        for topic in &request.topics {
+            let stored = self.lookup_fetch_topic(topic);
+            let mut partitions = Vec::with_capacity(topic.partitions.len());
+            for partition in &topic.partitions {
+                let fetched_partition = match &stored {
+                    Some(stored) => {
+                        self.fetch_partition(stored, partition, request.isolation_level)
+                            .await
+                    }
+                    None => Err(BrokerError::UnknownTopicOrPartition {
+                        topic: if topic.topic.is_empty() {
+                            topic.topic_id.to_string()
+                        } else {
+                            topic.topic.clone()
+                        },
+                        partition: partition.partition,
+                    }),
+                };
+                partitions.push(match fetched_partition {
+                    Ok((response, batches)) => {
+                        fetched.extend(batches);
+                        response
+                    }
+                    Err(error) => FetchResponsePartition::error(
+                        partition.partition,
+                        wire_error_for(ApiKey::Fetch, version, &error),
+                    ),
+                });
+            }
+            responses.push(FetchResponseTopic {
+                topic: topic.topic.clone(),
+                topic_id: topic.topic_id,
+                partitions,
+            });
+        }
+        (responses, fetched)
+    }
+
+    /// Records batches answered to a Fetch
+    ///
+    /// The time each batch spent in the log is its topic's delivery
+    /// latency, and the bytes and records count towards the partition's
+    /// fetch throughput and its topic's metrics.
+    fn record_fetched(&self, batches: &FetchedBatches, client_id: Option<&str>) {
+        for &append_time_ms in &batches.append_times_ms {
+            self.delivery_latency
+                .record_fetched_batch(&batches.topic, append_time_ms);
+        }
+        self.throughput.record_fetch(
+            &batches.topic,
+            batches.partition,
+            client_id,
+            batches.bytes,
+            batches.records,
+        );
+        self.topic_metrics
+            .record_fetch(&batches.topic, batches.bytes, batches.records);
+    }
+
+    /// Reads one partition of a Fetch request
+    ///
+    /// Returns the stored batches from the one holding the fetch offset, up
+    /// to `partition_max_bytes` but at least one batch, and never past the
+    /// high watermark. The batches are concatenated as they were stored,
+    /// without being re-encoded. A fetch offset past the log end reads
+    /// nothing; one before the log start is out of range. Disk-backed
+    /// partitions are read through the segments their log keeps open. A
+    /// topic with `fetch.enable=false` is refused with POLICY_VIOLATION,
+    /// unless it is internal. Nothing is recorded here, as a long-polling
+    /// fetch may read a partition several times; what was read is returned
+    /// alongside when there were batches.
+    async fn fetch_partition(
+        &self,
+        topic: &Topic,
+        partition: &FetchPartition,
+        isolation_level: i8,
+    ) -> std::result::Result<(FetchResponsePartition, Option<FetchedBatches>), BrokerError> {
+        let log = usize::try_from(partition.partition)
+            .ok()
+            .and_then(|index| topic.partitions.get(index))
+            .ok_or_else(|| BrokerError::UnknownTopicOrPartition {
+                topic: topic.name.clone(),
+                partition: partition.partition,
+            })?;
+        check_fetch_allowed(&topic.name, &self.topic_config(topic))?;
+        let high_watermark = log.high_watermark();
+        let batches = if partition.fetch_offset > log.log_end_offset() {
+            Vec::new()
+        } else {
+            log.read_from(
+                partition.fetch_offset,
+                partition.partition_max_bytes.max(0) as usize,
+            )?
+        };
+        let visible: Vec<_> = batches
+            .iter()
+            .take_while(|batch| batch.base_offset < high_watermark)
+            .collect();
+        let records = match visible[..] {
+            [batch] => batch.data.clone(),
+            _ => {
+                let size = visible.iter().map(|batch| batch.data.len()).sum();
+                let mut records = BytesMut::with_capacity(size);
+                for batch in &visible {
+                    records.extend_from_slice(&batch.data);
+                }
+                records.freeze()
+            }
+        };
+        let fetched = (!visible.is_empty()).then(|| FetchedBatches {
+            topic: topic.name.clone(),
+            partition: partition.partition,
+            bytes: records.len() as u64,
+            records: visible
+                .iter()
+                .map(|batch| (batch.last_offset - batch.base_offset + 1) as u64)
+                .sum(),
+            append_times_ms: visible.iter().map(|batch| batch.append_time_ms).collect(),
+        });
+        debug!(
+            topic = %topic.name,
+            partition = partition.partition,
+            fetch_offset = partition.fetch_offset,
+            batches = visible.len(),
+            bytes = records.len(),
+            "Read partition for Fetch"
+        );
+
+        let response = FetchResponsePartition {
+            partition_index: partition.partition,
+            error_code: ErrorCode::NONE,
+            high_watermark,
+            last_stable_offset: high_watermark,
+            log_start_offset: log.log_start_offset(),
+            // Only read_committed consumers look for aborted transactions
+            aborted_transactions: (isolation_level == 1).then(Vec::new),
+            preferred_read_replica: -1,
+            records: Some(records),
+        };
+        Ok((response, fetched))
+    }
+
+    /// Handles ListOffsets requests
+    ///
+    /// Resolves the earliest and latest special timestamps from the
+    /// partition log, and any other timestamp to the first record stamped
+    /// at or after it. The other special timestamps get
+    /// OFFSET_NOT_AVAILABLE.
+    fn handle_list_offsets_request(
+        &self,
+        request: ListOffsetsRequest,
+    ) -> Result<ListOffsetsResponse> {
+        debug!(
+            topics = request.topics.len(),
+            isolation_level = request.isolation_level,
+            "Decoded ListOffsets request"
+        );
+        Ok(ListOffsetsResponse {
+            throttle_time_ms: 0,
+            topics: request
+                .topics
+                .into_iter()
+                .map(|topic| {
+                    let partitions = topic
+                        .partitions
+                        .iter()
+                        .map(|partition| {
+                            match self.list_offset(
+                                &topic.name,
+                                partition.partition_index,
+                                partition.timestamp,
+                            ) {
+                                Ok(found) => ListOffsetsPartitionResponse::found(
+                                    partition.partition_index,
+                                    found.timestamp,
+                                    found.offset,
+                                ),
+                                Err(error) => ListOffsetsPartitionResponse::error(
+                                    partition.partition_index,
+                                    wire_error(&error),
+                                ),
+                            }
+                        })
+                        .collect();
+                    ListOffsetsTopicResponse {
+                        name: topic.name,
+                        partitions,
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Offset of one partition for a ListOffsets timestamp
+    ///
+    /// A timestamp newer than every record resolves to the log end offset.
+    /// Only a record found by its timestamp reports one; the others get -1.
+    fn list_offset(
+        &self,
+        topic: &str,
+        partition: i32,
+        timestamp: i64,
+    ) -> std::result::Result<TimestampOffset, BrokerError> {
+        let log = self
+            .topics
+            .get_topic(topic)
+            .and_then(|found| found.partitions.get(partition as usize).cloned())
+            .ok_or_else(|| BrokerError::UnknownTopicOrPartition {
+                topic: topic.to_string(),
+                partition,
+            })?;
+        let unstamped = |offset| TimestampOffset {
+            timestamp: -1,
+            offset,
+        };
+        match timestamp {
+            EARLIEST_TIMESTAMP => Ok(unstamped(log.log_start_offset())),
+            LATEST_TIMESTAMP => Ok(unstamped(log.log_end_offset())),
+            _ if timestamp < 0 => Err(BrokerError::OffsetNotAvailable {
+                topic: topic.to_string(),
+                partition,
+                timestamp,
+            }),
+            _ => Ok(log
+                .offset_for_timestamp(timestamp)?
+                .unwrap_or_else(|| unstamped(log.log_end_offset()))),
+        }
+    }
+
+    /// Handles Metadata requests
+    ///
+    /// This broker is the only node and the controller, and leads every
+    /// partition of the topic store. Topics that do not exist are reported
+    /// as unknown; they are not created automatically.
+    fn handle_metadata_request(&self, request: MetadataRequest) -> Result<MetadataResponse> {
+        debug!(
+            topics = ?request.topics.as_ref().map(Vec::len),
+            "Decoded Metadata request"
+        );
+        let addr = self.listen_addr();
+        Ok(MetadataResponse {
+            throttle_time_ms: 0,
+            brokers: vec![MetadataResponseBroker {
+                node_id: self.node_id,
+                host: addr.ip().to_string(),
+                port: i32::from(addr.port()),
+                rack: self.rack.clone(),
+            }],
+            cluster_id: self.cluster_id.clone(),
+            controller_id: self.node_id,
+            topics: match request.topics {
+                None => self
+                    .topics
+                    .list_topics()
+                    .iter()
+                    .map(|topic| self.metadata_topic(topic))
+                    .collect(),
+                Some(topics) => topics
+                    .into_iter()
+                    .map(|requested| {
+                        let topic = match &requested.name {
+                            Some(name) => self.topics.get_topic(name),
+                            None => self.topics.get_topic_by_id(requested.topic_id),
+                        };
+                        match topic {
+                            Some(topic) => self.metadata_topic(&topic),
+                            None => {
+                                MetadataResponseTopic::unknown(requested.name, requested.topic_id)
+                            }
+                        }
+                    })
+                    .collect(),
+            },
+            cluster_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        })
+    }
+
+    /// Metadata of a hosted topic, with this broker leading every partition
+    fn metadata_topic(&self, topic: &Topic) -> MetadataResponseTopic {
+        MetadataResponseTopic {
+            error_code: ErrorCode::NONE,
+            name: Some(topic.name.clone()),
+            topic_id: topic.topic_id,
+            is_internal: topic.is_internal(),
+            partitions: (0..topic.partition_count())
+                .map(|partition_index| MetadataResponsePartition {
+                    error_code: ErrorCode::NONE,
+                    partition_index,
+                    leader_id: self.node_id,
+                    leader_epoch: 0,
+                    replica_nodes: vec![self.node_id],
+                    isr_nodes: vec![self.node_id],
+                    offline_replicas: Vec::new(),
+                })
+                .collect(),
+            topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        }
+    }
+
+    /// Handles OffsetCommit requests
+    ///
+    /// A commit from a member that is not part of the group's current
+    /// generation fails for every partition. Simple commits, with a
+    /// negative generation, skip the membership check.
+    fn handle_offset_commit_request(
+        &self,
+        request: OffsetCommitRequest,
+    ) -> Result<OffsetCommitResponse> {
+        debug!(
+            group_id = %request.group_id,
+            member_id = %request.member_id,
+            generation_id = request.generation_id,
+            topics = request.topics.len(),
+            "Decoded OffsetCommit request"
+        );
+        let membership = self
+            .groups
+            .validate_commit(&request.group_id, request.generation_id, &request.member_id)
+            .map_err(|error| {
+                debug!(group_id = %request.group_id, error = %error, "Commit refused");
+                wire_error(&error.into())
+            });
+        Ok(OffsetCommitResponse {
+            throttle_time_ms: 0,
+            topics: request
+                .topics
+                .iter()
+                .map(|topic| {
+                    let partitions = topic
+                        .partitions
+                        .iter()
+                        .map(|partition| {
+                            let result = membership.and_then(|()| {
+                                self.commit_offset(&request.group_id, &topic.name, partition)
+                                    .map_err(|error| wire_error(&error))
+                            });
+                            OffsetCommitPartitionResponse {
+                                partition_index: partition.partition_index,
+                                error_code: result.err().unwrap_or(ErrorCode::NONE),
+                            }
+                        })
+                        .collect();
+                    OffsetCommitTopicResponse {
+                        name: topic.name.clone(),
+                        partitions,
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Stores one partition's committed offset
+    fn commit_offset(
+        &self,
+        group_id: &str,
+        topic: &str,
+        commit: &OffsetCommitPartition,
+    ) -> std::result::Result<(), BrokerError> {
+        let partition = commit.partition_index;
+        let exists = self
+            .topics
+            .get_topic(topic)
+            .is_some_and(|found| (0..found.partitions.len() as i32).contains(&partition));
+        if !exists {
+            return Err(BrokerError::UnknownTopicOrPartition {
+                topic: topic.to_string(),
+                partition,
+            });
+        }
+        self.offsets.commit(
+            group_id,
+            topic,
+            partition,
+            commit.committed_offset,
+            commit.committed_leader_epoch,
+            commit.committed_metadata.as_deref(),
+        )?;
+        Ok(())
+    }
+
+    /// Handles OffsetFetch requests
+    ///
+    /// Partitions without a committed offset answer -1. A null topic list
+    /// returns every offset the group committed.
+    fn handle_offset_fetch_request(
+        &self,
+        version: i16,
+        request: OffsetFetchRequest,
+    ) -> Result<OffsetFetchResponse> {
+        debug!(
+            groups = request.groups.len(),
+            require_stable = request.require_stable,
+            "Decoded OffsetFetch request"
+        );
+        Ok(OffsetFetchResponse {
+            throttle_time_ms: 0,
+            groups: request
+                .groups
+                .iter()
+                .map(|group| match self.fetch_offsets(version, group) {
+                    Ok(topics) => OffsetFetchResponseGroup {
+                        group_id: group.group_id.clone(),
+                        topics,
+                        error_code: ErrorCode::NONE,
+                    },
+                    Err(error) => {
+                        debug!(group_id = %group.group_id, error = %error, "Offsets not fetched");
+                        OffsetFetchResponseGroup::error(group.group_id.clone(), wire_error(&error))
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Committed offsets of one group
+    ///
+    /// From v8, asking for every offset of a group the broker has never
+    /// heard of fails with GROUP_ID_NOT_FOUND rather than returning none.
+    fn fetch_offsets(
+        &self,
+        version: i16,
+        group: &OffsetFetchRequestGroup,
+    ) -> std::result::Result<Vec<OffsetFetchResponseTopic>, BrokerError> {
+        let group_id = &group.group_id;
+        if group_id.is_empty() {
+            return Err(GroupError::InvalidGroupId {
+                group_id: group_id.clone(),
+            }
+            .into());
+        }
+        let partition = |partition_index, committed: Option<CommittedOffset>| {
+            let committed = committed.unwrap_or(CommittedOffset {
+                offset: -1,
+                leader_epoch: -1,
+                metadata: String::new(),
+                commit_timestamp_ms: -1,
+            });
+            OffsetFetchResponsePartition {
+                partition_index,
+                committed_offset: committed.offset,
+                committed_leader_epoch: committed.leader_epoch,
+                metadata: Some(committed.metadata),
+                error_code: ErrorCode::NONE,
+            }
+        };
+
+        let Some(topics) = &group.topics else {
+            let offsets = self.offsets.group_offsets(group_id);
+            if offsets.is_empty()
+                && version >= OFFSET_FETCH_BATCHED_VERSION
+                && self.groups.group_phase(group_id).is_none()
+            {
+                return Err(GroupError::GroupIdNotFound {
+                    group_id: group_id.clone(),
+                }
+                .into());
+            }
+            let mut topics: Vec<OffsetFetchResponseTopic> = Vec::new();
+            for (topic, partition_index, committed) in offsets {
+                let partition = partition(partition_index, Some(committed));
+                match topics.last_mut() {
+                    Some(last) if last.name == topic => last.partitions.push(partition),
+                    _ => topics.push(OffsetFetchResponseTopic {
+                        name: topic,
+                        partitions: vec![partition],
+                    }),
+                }
+            }
+            return Ok(topics);
+        };
+        Ok(topics
+            .iter()
+            .map(|topic| OffsetFetchResponseTopic {
+                name: topic.name.clone(),
+                partitions: topic
+                    .partition_indexes
+                    .iter()
+                    .map(|&partition_index| {
+                        let committed =
+                            self.offsets
+                                .committed(group_id, &topic.name, partition_index);
+                        partition(partition_index, committed)
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Handles FindCoordinator requests
+    ///
+    /// This broker is the only node, so it coordinates every group. Other
+    /// key types are not served and get INVALID_REQUEST.
+    fn handle_find_coordinator_request(
+        &self,
+        version: i16,
+        request: FindCoordinatorRequest,
+    ) -> Result<FindCoordinatorResponse> {
+        debug!(
+            key_type = request.key_type,
+            keys = ?request.keys(version),
+            "Decoded FindCoordinator request"
+        );
+        let addr = self.listen_addr();
+        let mut coordinators =
+            request
+                .keys(version)
+                .into_iter()
+                .map(|key| match request.key_type {
+                    COORDINATOR_TYPE_GROUP => Coordinator {
+                        key,
+                        node_id: self.node_id,
+                        host: addr.ip().to_string(),
+                        port: i32::from(addr.port()),
+                        error_code: ErrorCode::NONE,
+                        error_message: None,
+                    },
+                    key_type => {
+                        let error = BrokerError::UnknownCoordinatorType { key_type };
+                        debug!(key = %key, error = %error, "No coordinator");
+                        Coordinator::error(key, wire_error(&error), Some(error.to_string()))
+                    }
+                });
+        Ok(if version >= FIND_COORDINATOR_BATCHED_VERSION {
+            FindCoordinatorResponse::batched(coordinators.collect())
+        } else {
+            // Before v4 the request carries exactly one key
+            FindCoordinatorResponse::single(coordinators.next().unwrap())
+        })
+    }
+
+    /// Handles JoinGroup requests
+    ///
+    /// The answer waits until the group's rebalance has collected every
+    /// member, so it may take up to the rebalance timeout.
+    async fn handle_join_group_request(
+        &self,
+        header: &RequestHeader,
+        request: JoinGroupRequest,
+    ) -> Result<JoinGroupResponse> {
+        let version = header.api_version();
+        debug!(
+            group_id = %request.group_id,
+            member_id = %request.member_id,
+            protocols = request.protocols.len(),
+            "Decoded JoinGroup request"
+        );
+        let require_member_id = version >= JOIN_GROUP_MEMBER_ID_REQUIRED_VERSION;
+        let client_id = header.client_id().unwrap_or_default();
+        match self
+            .groups
+            .join_group(&request, client_id, require_member_id)
+            .await
+        {
+            Ok(joined) => Ok(joined),
+            Err(error) => {
+                debug!(group_id = %request.group_id, error = %error, "Member not joined");
+                let member_id = match &error {
+                    GroupError::MemberIdRequired { member_id, .. } => member_id.clone(),
+                    _ => request.member_id.clone(),
+                };
+                Ok(JoinGroupResponse::error(
+                    wire_error(&error.into()),
+                    member_id,
+                ))
+            }
+        }
+    }
+
+    /// Handles Heartbeat requests
+    fn handle_heartbeat_request(&self, request: HeartbeatRequest) -> Result<HeartbeatResponse> {
+        debug!(
+            group_id = %request.group_id,
+            member_id = %request.member_id,
+            generation_id = request.generation_id,
+            "Decoded Heartbeat request"
+        );
+        let error_code = match self.groups.heartbeat(&request) {
+            Ok(()) => ErrorCode::NONE,
+            Err(error) => {
+                debug!(group_id = %request.group_id, error = %error, "Heartbeat refused");
+                wire_error(&error.into())
+            }
+        };
+        Ok(HeartbeatResponse::new(error_code))
+    }
+
+    /// Handles LeaveGroup requests
+    ///
+    /// From v3 each member named gets its own error code. Before that the
+    /// one member's error is the response's.
+    fn handle_leave_group_request(
+        &self,
+        version: i16,
+        request: LeaveGroupRequest,
+    ) -> Result<LeaveGroupResponse> {
+        debug!(
+            group_id = %request.group_id,
+            members = request.members.len(),
+            "Decoded LeaveGroup request"
+        );
+        let members: Vec<LeftMember> = request
+            .members
+            .iter()
+            .map(|member| {
+                let error_code = match self.groups.leave_group(&request.group_id, member) {
+                    Ok(()) => ErrorCode::NONE,
+                    Err(error) => {
+                        debug!(group_id = %request.group_id, error = %error, "Member not removed");
+                        wire_error(&error.into())
+                    }
+                };
+                LeftMember {
+                    member_id: member.member_id.clone(),
+                    group_instance_id: member.group_instance_id.clone(),
+                    error_code,
+                }
+            })
+            .collect();
+        if version < LEAVE_GROUP_BATCHED_VERSION {
+            let error_code = members
+                .first()
+                .map(|member| member.error_code)
+                .unwrap_or(ErrorCode::NONE);
+            return Ok(LeaveGroupResponse::error(error_code));
+        }
+        Ok(LeaveGroupResponse {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            members,
+        })
+    }
+
+    /// Handles SyncGroup requests
+    ///
+    /// Members other than the leader wait for the leader's assignments.
+    async fn handle_sync_group_request(
+        &self,
+        request: SyncGroupRequest,
+    ) -> Result<SyncGroupResponse> {
+        debug!(
+            group_id = %request.group_id,
+            member_id = %request.member_id,
+            generation_id = request.generation_id,
+            assignments = request.assignments.len(),
+            "Decoded SyncGroup request"
+        );
+        match self.groups.sync_group(&request).await {
+            Ok(synced) => Ok(synced),
+            Err(error) => {
+                debug!(group_id = %request.group_id, error = %error, "Member not synced");
+                Ok(SyncGroupResponse::error(wire_error(&error.into())))
+            }
+        }
+    }
+
+    /// Handles SaslHandshake requests
+    ///
+    /// The enabled mechanisms are listed whatever the outcome, so a client
+    /// can report a mismatch. Tokens are only read from SaslAuthenticate,
+    /// not as the raw frames a v0 client follows up with.
+    fn handle_sasl_handshake_request(
+        &self,
+        request: SaslHandshakeRequest,
+        state: &mut ConnectionState,
+    ) -> Result<SaslHandshakeResponse> {
+        debug!(mechanism = %request.mechanism, "Decoded SaslHandshake request");
+        let error_code = match state.sasl.handshake(&self.sasl, &request.mechanism) {
+            Ok(()) => ErrorCode::NONE,
+            Err(error) => {
+                debug!(error = %error, "SaslHandshake refused");
+                wire_error(&error.into())
+            }
+        };
+        Ok(SaslHandshakeResponse {
+            error_code,
+            mechanisms: self.sasl.enabled_mechanisms.clone(),
+        })
+    }
+
+    /// Handles SaslAuthenticate requests, which must follow a successful
+    /// handshake on the same connection
+    fn handle_sasl_authenticate_request(
+        &self,
+        request: SaslAuthenticateRequest,
+        context: &RequestContext,
+        state: &mut ConnectionState,
+    ) -> Result<SaslAuthenticateResponse> {
+        match state.sasl.authenticate(&request.auth_bytes) {
+            Ok(principal) => {
+                info!(
+                    peer_addr = %context.peer_addr,
+                    principal = %principal,
+                    "Client authenticated"
+                );
+                Ok(SaslAuthenticateResponse {
+                    error_code: ErrorCode::NONE,
+                    error_message: None,
+                    auth_bytes: Bytes::new(),
+                    session_lifetime_ms: 0,
+                })
+            }
+            Err(error) => {
+                warn!(peer_addr = %context.peer_addr, error = %error, "SaslAuthenticate refused");
+                let message = error.to_string();
+                Ok(SaslAuthenticateResponse::error(
+                    wire_error(&error.into()),
+                    message,
+                ))
+            }
+        }
+    }
+
+    /// Handles CreateTopics requests
+    ///
+    /// Each topic is checked and created on its own, so one bad topic does
+    /// not fail the others. With `validate_only` the checks run but nothing
+    /// is created. The topics created by one request are one metadata
+    /// change.
+    fn handle_create_topics_request(
+        &self,
+        request: CreateTopicsRequest,
+    ) -> Result<CreateTopicsResponse> {
+        debug!(
+            topics = request.topics.len(),
+            validate_only = request.validate_only,
+            "Decoded CreateTopics request"
+        );
+        let create_all = || {
+            request
+                .topics
+                .iter()
+                .map(
+                    |topic| match self.create_topic(topic, request.validate_only) {
+                        Ok(result) => result,
+                        Err(error) => {
+                            debug!(topic = %topic.name, error = %error, "Topic not created");
+                            CreatableTopicResult::error(
+                                topic.name.clone(),
+                                wire_error(&error),
+                                Some(error.to_string()),
+                            )
+                        }
+                    },
+                )
+                .collect()
+        };
+        let topics = if request.validate_only {
+            create_all()
+        } else {
+            self.metadata_epoch.advance(&self.events, create_all)?.1
+        };
+        Ok(CreateTopicsResponse {
+            throttle_time_ms: 0,
+            topics,
+        })
+    }
+
+    /// Checks and, unless `validate_only`, creates one requested topic
+    ///
+    /// This broker is the only replica, so the replication factor must be
+    /// 1 or -1 for the default, and manual assignments may only name this
+    /// node. A partition count of -1 means the default of one partition.
+    fn create_topic(
+        &self,
+        topic: &CreatableTopic,
+        validate_only: bool,
+    ) -> std::result::Result<CreatableTopicResult, BrokerError> {
+        let num_partitions = if topic.assignments.is_empty() {
+            if !matches!(topic.replication_factor, -1 | 1) {
+                return Err(BrokerError::InvalidReplicationFactor {
+                    topic: topic.name.clone(),
+                    replication_factor: topic.replication_factor,
+                });
+            }
+            match topic.num_partitions {
+                -1 => self.config.num_partitions()?,
+                num_partitions => num_partitions,
+            }
+        } else {
+            let invalid = |reason: String| BrokerError::InvalidReplicaAssignment {
+                topic: topic.name.clone(),
+                reason,
+            };
+            for (index, assignment) in topic.assignments.iter().enumerate() {
+                if assignment.partition_index != index as i32 {
+                    return Err(invalid(format!(
+                        "partitions must be assigned in order from 0, found {} at {}",
+                        assignment.partition_index, index
+                    )));
+                }
+                if assignment.broker_ids != [self.node_id] {
+                    return Err(invalid(format!(
+                        "partition {} may only be assigned to broker {}",
+                        index, self.node_id
+                    )));
+                }
+            }
+            topic.assignments.len() as i32
+        };
+        let configs: BTreeMap<String, String> = topic
+            .configs
+            .iter()
+            .filter_map(|config| Some((config.name.clone(), config.value.clone()?)))
+            .collect();
+        let topic_id = if validate_only {
+            self.topics
+                .validate_new_topic(&topic.name, num_partitions)?;
+            Uuid::nil()
+        } else {
+            let created = self
+                .topics
+                .create_topic(&topic.name, num_partitions, configs.clone())?;
+            info!(
+                topic = %created.name,
+                topic_id = %created.topic_id,
+                partitions = num_partitions,
+                "Created topic"
+            );
+            created.topic_id
+        };
+        Ok(CreatableTopicResult {
+            name: topic.name.clone(),
+            topic_id,
+            error_code: ErrorCode::NONE,
+            error_message: None,
+            num_partitions,
+            replication_factor: 1,
+            configs: Some(
+                configs
+                    .into_iter()
+                    .map(|(name, value)| CreatableTopicConfigs {
+                        name,
+                        value: Some(value),
+                        read_only: false,
+                        config_source: TOPIC_CONFIG_SOURCE,
+                        is_sensitive: false,
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Handles DeleteTopics requests
+    ///
+    /// Each topic is deleted on its own, so an unknown or internal topic
+    /// does not fail the others. The topics deleted by one request are one
+    /// metadata change.
+    fn handle_delete_topics_request(
+        &self,
+        request: DeleteTopicsRequest,
+    ) -> Result<DeleteTopicsResponse> {
+        debug!(
+            topics = request.topics.len(),
+            "Decoded DeleteTopics request"
+        );
+        let (_, responses) = self.metadata_epoch.advance(&self.events, || {
+            request
+                .topics
+                .iter()
+                .map(|topic| match self.delete_topic(topic) {
+                    Ok(deleted) => DeletableTopicResult {
+                        name: Some(deleted.name.clone()),
+                        topic_id: deleted.topic_id,
+                        error_code: ErrorCode::NONE,
+                        error_message: None,
+                    },
+                    Err(error) => {
+                        debug!(error = %error, "Topic not deleted");
+                        DeletableTopicResult {
+                            name: topic.name.clone(),
+                            topic_id: topic.topic_id,
+                            error_code: wire_error(&error),
+                            error_message: Some(error.to_string()),
+                        }
+                    }
+                })
+                .collect()
+        })?;
+        Ok(DeleteTopicsResponse {
+            throttle_time_ms: 0,
+            responses,
+        })
+    }
+
+    /// Deletes one requested topic, given by name or by id, with its logs
+    fn delete_topic(
+        &self,
+        topic: &DeleteTopicState,
+    ) -> std::result::Result<Arc<Topic>, BrokerError> {
+        let name = match &topic.name {
+            Some(name) => name.clone(),
+            None => self
+                .topics
+                .get_topic_by_id(topic.topic_id)
+                .ok_or(StorageError::UnknownTopicId {
+                    topic_id: topic.topic_id,
+                })?
+                .name
+                .clone(),
+        };
+        let deleted = self.topics.delete_topic(&name)?;
+        info!(topic = %deleted.name, topic_id = %deleted.topic_id, "Deleted topic");
+        if let Some(log_dir) = &self.log_dir {
+            for partition in 0..deleted.partition_count() {
+                let dir = partition_dir(log_dir, &deleted.name, partition);
+                match std::fs::remove_dir_all(&dir) {
+                    Ok(()) => debug!(dir = %dir.display(), "Removed partition directory"),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => warn!(
+                        dir = %dir.display(),
+                        error = %e,
+                        "Failed to remove partition directory"
+                    ),
+                }
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Handles InitProducerId requests
+    ///
+    /// Idempotent producers get a fresh id at epoch 0. One that names the
+    /// id and epoch it had before (v3+) keeps its id with the epoch bumped,
+    /// which fences off anything it still sends under the old epoch.
+    /// Transactions are not implemented, so a transactional producer is
+    /// told there is no coordinator.
+    fn handle_init_producer_id_request(
+        &self,
+        request: InitProducerIdRequest,
+    ) -> Result<InitProducerIdResponse> {
+        debug!(
+            transactional_id = ?request.transactional_id,
+            producer_id = request.producer_id,
+            producer_epoch = request.producer_epoch,
+            "Decoded InitProducerId request"
+        );
+        let refused = if let Some(transactional_id) = &request.transactional_id {
+            Some(BrokerError::TransactionsUnsupported {
+                transactional_id: transactional_id.clone(),
+            })
+        } else if (request.producer_id == NO_PRODUCER_ID)
+            != (request.producer_epoch == NO_PRODUCER_EPOCH)
+        {
+            Some(BrokerError::InvalidProducerIdAndEpoch {
+                producer_id: request.producer_id,
+                producer_epoch: request.producer_epoch,
+            })
+        } else {
+            None
+        };
+        if let Some(error) = refused {
+            debug!(error = %error, "InitProducerId refused");
+            return Ok(InitProducerIdResponse::error(wire_error(&error)));
+        }
+        let (producer_id, producer_epoch) = match self
+            .producer_ids
+            .init_producer_id(request.producer_id, request.producer_epoch)
+        {
+            Ok(initialized) => initialized,
+            Err(InitProducerIdError::ProducerState(e)) => {
+                debug!(error = %e, "InitProducerId refused");
+                return Ok(InitProducerIdResponse::error(wire_error(&e.into())));
+            }
+            Err(e @ InitProducerIdError::Persist(_)) => return Err(e.into()),
+        };
+        info!(
+            producer_id,
+            producer_epoch, "Initialized idempotent producer"
+        );
+        Ok(InitProducerIdResponse {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            producer_id,
+            producer_epoch,
+        })
+    }
+
+    /// Handles DescribeConfigs requests
+    ///
+    /// Each resource is described on its own, so an unknown topic does not
+    /// fail the others.
+    fn handle_describe_configs_request(
+        &self,
+        request: DescribeConfigsRequest,
+    ) -> Result<DescribeConfigsResponse> {
+        debug!(
+            resources = request.resources.len(),
+            include_synonyms = request.include_synonyms,
+            "Decoded DescribeConfigs request"
+        );
+        let results = request
+            .resources
+            .iter()
+            .map(
+                |resource| match self.describe_configs(resource, request.include_synonyms) {
+                    Ok(configs) => DescribeConfigsResult {
+                        error_code: ErrorCode::NONE,
+                        error_message: None,
+                        resource_type: resource.resource_type,
+                        resource_name: resource.resource_name.clone(),
+                        configs,
+                    },
+                    Err(error) => {
+                        debug!(
+                            resource = %resource.resource_name,
+                            error = %error,
+                            "DescribeConfigs failed for resource"
+                        );
+                        DescribeConfigsResult::error(
+                            resource.resource_type,
+                            resource.resource_name.clone(),
+                            wire_error(&error),
+                            Some(error.to_string()),
+                        )
+                    }
+                },
+            )
+            .collect();
+        Ok(DescribeConfigsResponse {
+            throttle_time_ms: 0,
+            results,
+        })
+    }
+
+    /// Effective configs of one resource, restricted to the requested keys
+    ///
+    /// A topic reports every topic key, with the configs it was created
+    /// with taking precedence over the defaults. This broker reports its
+    /// properties, which cannot be changed at runtime. An empty broker name
+    /// asks for the cluster-wide dynamic defaults, of which there are none.
+    fn describe_configs(
+        &self,
+        resource: &DescribeConfigsResource,
+        include_synonyms: bool,
+    ) -> std::result::Result<Vec<DescribeConfigsResourceResult>, BrokerError> {
+        let mut configs = match resource.resource_type {
+            RESOURCE_TYPE_TOPIC => {
+                let topic = self
+                    .topics
+                    .get_topic(&resource.resource_name)
+                    .ok_or_else(|| StorageError::UnknownTopic {
+                        topic: resource.resource_name.clone(),
+                    })?;
+                let topic_configs = topic.configs();
+                let mut values: BTreeMap<&str, &str> = TOPIC_CONFIG_KEYS
+                    .iter()
+                    .map(|key| (key.name, key.default))
+                    .collect();
+                for (name, value) in &topic_configs {
+                    values.insert(name, value);
+                }
+                values
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let key = topic_config_key(name);
+                        let source = if topic_configs.contains_key(name) {
+                            CONFIG_SOURCE_DYNAMIC_TOPIC
+                        } else {
+                            CONFIG_SOURCE_DEFAULT
+                        };
+                        let entry = ConfigEntry {
+                            name,
+                            value,
+                            source,
+                            read_only: false,
+                            kind: key.map(|key| key.kind),
+                            default: key.map(|key| key.default),
+                        };
+                        entry.describe(include_synonyms)
+                    })
+                    .collect()
+            }
+            RESOURCE_TYPE_BROKER if resource.resource_name.is_empty() => Vec::new(),
+            RESOURCE_TYPE_BROKER => {
+                if resource.resource_name.parse() != Ok(self.node_id) {
+                    return Err(BrokerError::UnexpectedBrokerId {
+                        resource_name: resource.resource_name.clone(),
+                        node_id: self.node_id,
+                    });
+                }
+                self.config
+                    .entries()
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let key = broker_config_key(name);
+                        let source = if self.config.is_overridden(name) {
+                            CONFIG_SOURCE_STATIC_BROKER
+                        } else {
+                            CONFIG_SOURCE_DEFAULT
+                        };
+                        let entry = ConfigEntry {
+                            name,
+                            value,
+                            source,
+                            read_only: true,
+                            kind: key.map(|key| key.kind),
+                            default: key.map(|key| key.default),
+                        };
+                        entry.describe(include_synonyms)
+                    })
+                    .collect()
+            }
+            resource_type => return Err(BrokerError::UnsupportedResourceType { resource_type }),
+        };
+        if let Some(keys) = &resource.configuration_keys {
+            configs.retain(|config| keys.contains(&config.name));
+        }
+        Ok(configs)
+    }
+
+    /// Handles IncrementalAlterConfigs requests
+    ///
+    /// Each resource is altered on its own, so a refused change does not
+    /// fail the others. The resources altered by one request are one
+    /// metadata change; a `validate_only` request checks every change but
+    /// makes none.
+    fn handle_incremental_alter_configs_request(
+        &self,
+        request: IncrementalAlterConfigsRequest,
+    ) -> Result<IncrementalAlterConfigsResponse> {
+        debug!(
+            resources = request.resources.len(),
+            validate_only = request.validate_only,
+            "Decoded IncrementalAlterConfigs request"
+        );
+        let alter_all = || {
+            request
+                .resources
+                .iter()
+                .map(|resource| {
+                    let (error_code, error_message) =
+                        match self.alter_configs(resource, request.validate_only) {
+                            Ok(()) => (ErrorCode::NONE, None),
+                            Err(error) => {
+                                debug!(
+                                    resource = %resource.resource_name,
+                                    error = %error,
+                                    "IncrementalAlterConfigs failed for resource"
+                                );
+                                (wire_error(&error), Some(error.to_string()))
+                            }
+                        };
+                    AlterConfigsResourceResponse {
+                        error_code,
+                        error_message,
+                        resource_type: resource.resource_type,
+                        resource_name: resource.resource_name.clone(),
+                    }
+                })
+                .collect()
+        };
+        let responses = if request.validate_only {
+            alter_all()
+        } else {
+            self.metadata_epoch.advance(&self.events, alter_all)?.1
+        };
+        Ok(IncrementalAlterConfigsResponse {
+            throttle_time_ms: 0,
+            responses,
+        })
+    }
+
+    /// Checks and, unless `validate_only`, applies the changes to one resource
+    ///
+    /// Only topics can be altered: the broker's own configs are read-only.
+    /// The changes to a topic apply entirely or not at all.
+    fn alter_configs(
+        &self,
+        resource: &AlterConfigsResource,
+        validate_only: bool,
+    ) -> std::result::Result<(), BrokerError> {
+        if resource.resource_type != RESOURCE_TYPE_TOPIC {
+            return Err(BrokerError::UnsupportedResourceType {
+                resource_type: resource.resource_type,
+            });
+        }
+        let topic = self
+            .topics
+            .get_topic(&resource.resource_name)
+            .ok_or_else(|| StorageError::UnknownTopic {
+                topic: resource.resource_name.clone(),
+            })?;
+        let change = |configs: &mut BTreeMap<String, String>| {
+            resource
+                .configs
+                .iter()
+                .try_for_each(|config| alter_topic_config(configs, config))
+        };
+        if validate_only {
+            change(&mut topic.configs())?;
+        } else {
+            topic.alter_configs(change)?;
+            for config in &resource.configs {
+                self.events.publish(BrokerEvent::ConfigUpdated {
+                    resource: topic.name.clone(),
+                    key: config.name.clone(),
+                });
+                info!(
+                    audit = true,
+                    topic = %topic.name,
+                    key = %config.name,
+                    operation = ?config.config_operation,
+                    value = ?config.value.as_deref().filter(|_| !is_sensitive_config_key(&config.name)),
+                    "Topic configuration altered"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Effective configuration of a topic
+    ///
+    /// The configs the topic was created or altered with take precedence
+    /// over the imported dynamic overrides, which take precedence over the
+    /// defaults.
+    fn topic_config(&self, topic: &Topic) -> TopicConfig {
+        self.dynamic_config
+            .topic_config(&topic.name)
+            .with_overrides(topic.configs())
+    }
+
+    /// Handles DescribeCluster requests
+    ///
+    /// This broker is the only node and the controller. The cluster id is
+    /// the one of the formatted log directory, empty when none was loaded.
+    /// Controllers are not reachable through this listener, so asking for
+    /// their endpoints fails as it does on a Kafka broker.
+    fn handle_describe_cluster_request(
+        &self,
+        request: DescribeClusterRequest,
+    ) -> Result<DescribeClusterResponse> {
+        debug!(
+            include_cluster_authorized_operations = request.include_cluster_authorized_operations,
+            endpoint_type = request.endpoint_type,
+            "Decoded DescribeCluster request"
+        );
+        let refused = match request.endpoint_type {
+            ENDPOINT_TYPE_BROKERS => None,
+            ENDPOINT_TYPE_CONTROLLERS => Some(BrokerError::ControllerEndpointsUnsupported),
+            endpoint_type => Some(BrokerError::UnknownEndpointType { endpoint_type }),
+        };
+        let (error_code, error_message, brokers) = match refused {
+            Some(error) => {
+                debug!(error = %error, "DescribeCluster refused");
+                (wire_error(&error), Some(error.to_string()), Vec::new())
+            }
+            None => {
+                let addr = self.listen_addr();
+                let broker = DescribeClusterBroker {
+                    broker_id: self.node_id,
+                    host: addr.ip().to_string(),
+                    port: i32::from(addr.port()),
+                    rack: self.rack.clone(),
+                };
+                (ErrorCode::NONE, None, vec![broker])
+            }
+        };
+        Ok(DescribeClusterResponse {
+            throttle_time_ms: 0,
+            error_code,
+            error_message,
+            endpoint_type: request.endpoint_type,
+            cluster_id: self.cluster_id.clone().unwrap_or_default(),
+            controller_id: self.node_id,
+            brokers,
+            cluster_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        })
+    }
+
+    /// Handles DescribeTopicPartitions requests
+    ///
+    /// Topics are answered from the topic store sorted by name, as the
+    /// reference broker does, so a cursor names a fixed position. An empty
+    /// topic list asks for every topic. Topics the store does not hold are
+    /// reported as unknown.
+    ///
+    /// At most `response_partition_limit` partitions are listed, capped at
+    /// [`MAX_DESCRIBED_PARTITIONS`]. A listing cut short ends with a cursor
+    /// at the first partition left out, and a request carrying that cursor
+    /// resumes from it.
+    fn handle_describe_topic_partitions_request(
+        &self,
+        request: DescribeTopicPartitionsRequest,
+    ) -> Result<DescribeTopicPartitionsResponse> {
+        let mut names = if request.topics.is_empty() {
+            self.topics
+                .list_topics()
+                .iter()
+                .map(|topic| topic.name.clone())
+                .collect()
+        } else {
+            request.topics
+        };
+        names.sort();
+        names.dedup();
+        debug!(
+            topics = names.len(),
+            partition_limit = request.response_partition_limit,
+            cursor = ?request.cursor,
+            "Decoded DescribeTopicPartitions request"
+        );
+
+        if let Some(cursor) = &request.cursor {
+            names.retain(|name| *name >= cursor.topic_name);
+        }
+        let mut remaining = request
+            .response_partition_limit
+            .clamp(1, MAX_DESCRIBED_PARTITIONS);
+        let mut topics = Vec::new();
+        let mut next_cursor = None;
+        for name in names {
+            let Some(topic) = self.topics.get_topic(&name) else {
+                topics.push(DescribeTopicPartitionsResponseTopic::unknown(name));
+                continue;
+            };
+            let first = match &request.cursor {
+                Some(cursor) if cursor.topic_name == name => cursor.partition_index.max(0),
+                _ => 0,
+            };
+            if first >= topic.partition_count() {
+                continue;
+            }
+            if remaining == 0 {
+                next_cursor = Some(DescribeTopicPartitionsCursor {
+                    topic_name: name,
+                    partition_index: first,
+                });
+                break;
+            }
+            let end = topic.partition_count().min(first + remaining);
+            remaining -= end - first;
+            topics.push(self.describe_topic_partitions_topic(&topic, first..end));
+            if end < topic.partition_count() {
+                next_cursor = Some(DescribeTopicPartitionsCursor {
+                    topic_name: name,
+                    partition_index: end,
+                });
+                break;
+            }
+        }
+
+        Ok(DescribeTopicPartitionsResponse {
+            throttle_time_ms: 0,
+            topics,
+            next_cursor,
+        })
+    }
+
+    /// A hosted topic as DescribeTopicPartitions reports it, with the
+    /// partitions in `partitions`
+    fn describe_topic_partitions_topic(
+        &self,
+        topic: &Topic,
+        partitions: Range<i32>,
+    ) -> DescribeTopicPartitionsResponseTopic {
+        DescribeTopicPartitionsResponseTopic {
+            error_code: ErrorCode::NONE,
+            name: Some(topic.name.clone()),
+            topic_id: topic.topic_id,
+            is_internal: topic.is_internal(),
+            partitions: partitions
+                .map(|partition_index| DescribeTopicPartitionsResponsePartition {
+                    error_code: ErrorCode::NONE,
+                    partition_index,
+                    leader_id: self.node_id,
+                    leader_epoch: 0,
+                    replica_nodes: vec![self.node_id],
+                    isr_nodes: vec![self.node_id],
+                    eligible_leader_replicas: Some(Vec::new()),
+                    last_known_elr: Some(Vec::new()),
+                    offline_replicas: Vec::new(),
+                })
+                .collect(),
+            topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        }
+    }
+
+    /// Handles Produce requests
+    ///
+    /// Records are validated message by message for v0-v2 and batch by
+    /// batch from v3, where `acks` and `transactional_id` are carried as
+    /// sent. Valid batches are appended to the partition log at its end
+    /// offset, and the high watermark follows since there are no replicas
+    /// to wait for. Each partition succeeds or fails on its own. Legacy
+    /// message sets are converted to a record batch before they are
+    /// appended. Returns `None` for acks=0, where the client does not read
+    /// a response. With acks=-1 the request is parked in the purgatory
+    /// first; a timed-out wait fails the partitions that
+    /// would otherwise have succeeded. If the connection goes away while
+    /// parked, the operation leaves the purgatory and nothing is answered.
+    async fn handle_produce_request(
+        &self,
+        header: &RequestHeader,
+        request: ProduceRequest,
+        context: &RequestContext,
+    ) -> Result<Option<ProduceResponse>> {
+        let version = header.api_version();
+
+        let mut topics: Vec<ProduceTopicResponse> = request
+            .topics
+            .iter()
+            .map(|topic| ProduceTopicResponse {
+                name: topic.name.clone(),
+                partitions: topic
+                    .partitions
+                    .iter()
+                    .map(|partition| {
+                        let checked = Self::check_produce_records(
+                            version,
+                            &topic.name,
+                            partition.index,
+                            partition.records.as_ref(),
+                        );
+                        let bytes = partition
+                            .records
+                            .as_ref()
+                            .map_or(0, |records| records.len())
+                            as u64;
+                        let records =
+                            checked.as_ref().map_or(0, CheckedRecords::record_count) as u64;
+                        checked
+                            .and_then(|checked| {
+                                self.append_produce_records(&topic.name, partition.index, checked)
+                            })
+                            // Rejected records are not throughput
+                            .inspect(|_| {
+                                self.throughput.record_produce(
+                                    &topic.name,
+                                    partition.index,
+                                    header.client_id(),
+                                    bytes,
+                                    records,
+                                );
+                                self.topic_metrics
+                                    .record_produce(&topic.name, bytes, records);
+                            })
+                            .unwrap_or_else(|error| ProducePartitionResponse {
+                                error_message: matches!(error, BrokerError::AccessDenied(_))
+                                    .then(|| error.to_string()),
+                                ..ProducePartitionResponse::error(
+                                    partition.index,
+                                    wire_error_for(ApiKey::Produce, version, &error),
+                                )
+                            })
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        if request.acks == 0 {
+            return Ok(None);
+        }
+
+        let delay = self.purgatory.config().delay;
+        if request.acks == -1 && !delay.is_zero() {
+            let timeout = Duration::from_millis(request.timeout_ms.max(0) as u64);
+            let parked = self.purgatory.park(header.correlation_id());
+            let completion = tokio::select! {
+                completion = parked.wait(delay, timeout) => completion,
+                _ = context.cancelled() => {
+                    debug!(
+                        peer_addr = %context.peer_addr,
+                        correlation_id = header.correlation_id(),
+                        "Client disconnected during a Produce wait"
+                    );
+                    return Ok(None);
+                }
+            };
+            if completion != ErrorCode::NONE {
+                for partition in topics.iter_mut().flat_map(|topic| &mut topic.partitions) {
+                    if partition.error_code == ErrorCode::NONE {
+                        partition.error_code = completion;
+                    }
+                }
+            }
+        }
+
+        Ok(Some(ProduceResponse {
+            topics,
+            throttle_time_ms: 0,
+        }))
+    }
+
+    /// Appends the validated records of one produce partition
+    ///
+    /// A legacy message set is converted to a single v2 record batch first.
+    /// The batches' base offsets are rewritten to the log end offset, and
+    /// a topic with `message.timestamp.type=LogAppendTime` has them
+    /// stamped with the append time, which is returned. Batches of an
+    /// idempotent producer have their sequence checked by the partition: a
+    /// retried batch gets its original offset back, a gap fails with
+    /// OUT_OF_ORDER_SEQUENCE_NUMBER. A topic with `produce.enable=false`
+    /// refuses the records with POLICY_VIOLATION.
+    fn append_produce_records(
+        &self,
+        topic: &str,
+        partition: i32,
+        records: CheckedRecords,
+    ) -> Result<ProducePartitionResponse, BrokerError> {
+        let unknown = || BrokerError::UnknownTopicOrPartition {
+            topic: topic.to_string(),
+            partition,
+        };
+        let stored = self.topics.get_topic(topic).ok_or_else(unknown)?;
+        check_produce_allowed(topic, &self.topic_config(&stored))?;
+        let log: &PartitionLog = usize::try_from(partition)
+            .ok()
+            .and_then(|index| stored.partitions.get(index))
+            .ok_or_else(unknown)?;
+        let batches = match records {
+            CheckedRecords::Batches(batches) => batches,
+            CheckedRecords::MessageSet(messages) => convert_message_set(&messages)?,
+        };
+
+        let log_append_time = stored
+            .configs()
+            .get("message.timestamp.type")
+            .is_some_and(|timestamp_type| timestamp_type == "LogAppendTime");
+        let appended = log
+            .append_record_batches(&batches, log_append_time)?
+            .ok_or_else(|| BrokerError::CorruptRecords("no record batches".to_string()))?;
+        log.advance_high_watermark(log.log_end_offset());
+        debug!(
+            topic = topic,
+            partition = partition,
+            batches = batches.len(),
+            base_offset = appended.base_offset,
+            "Appended produced records"
+        );
+
+        let mut response = ProducePartitionResponse::appended(
+            partition,
+            appended.base_offset,
+            log_append_time.then_some(appended.max_timestamp),
+        );
+        response.log_start_offset = log.log_start_offset();
+        Ok(response)
+    }
+
+    /// Validates the records of one partition
+    ///
+    /// Produce v0-v2 carry legacy message sets and v3+ carry v2 record
+    /// batches; records in the other format are rejected, as are batches
+    /// compressed with a codec this build cannot decompress.
+    fn check_produce_records(
+        version: i16,
+        topic: &str,
+        partition: i32,
+        records: Option<&Bytes>,
+    ) -> Result<CheckedRecords, BrokerError> {
+        let Some(records) = records else {
+            return Err(BrokerError::CorruptRecords("null records".to_string()));
+        };
+        let record_batches = version >= FIRST_RECORD_BATCH_VERSION;
+        let magic = records_magic(records);
+        match magic {
+            Some(magic) if (magic >= 2) != record_batches => {
+                return Err(BrokerError::UnsupportedMessageFormat { magic })
+            }
+            None if !records.is_empty() => {
+                return Err(BrokerError::CorruptRecords("truncated records".to_string()))
+            }
+            _ => {}
+        }
+        let checked = if record_batches {
+            split_record_batches(records).and_then(|batches| {
+                for batch in &batches {
+                    let compression = batch.compression()?;
+                    if !compression.is_available() {
+                        return Err(ProtocolError::UnsupportedCompression {
+                            codec: compression.id(),
+                        });
+                    }
+                }
+                Ok(CheckedRecords::Batches(batches))
+            })
+        } else {
+            decode_message_set(records).map(CheckedRecords::MessageSet)
+        };
+        checked.map_err(|e| {
+            warn!(
+                topic = topic,
+                partition = partition,
+                error = %e,
+                "Rejecting records"
+            );
+            match e {
+                ProtocolError::UnsupportedCompression { .. } => BrokerError::Protocol(e),
+                e => BrokerError::CorruptRecords(e.to_string()),
+            }
+        })
+    }
+
+    /// Handles unsupported requests, returning the error code to answer
+    /// with
+    fn handle_unsupported_request(&self, header: &RequestHeader) -> ErrorCode {
+        warn!(
+            api_key = %api_key_name(header.api_key_code()),
+            "Generating error response for unsupported API"
+        );
+
+        // Served APIs reach here for versions outside their range
+        let api_key = header.api_key_code();
+        let gated = self
+            .handlers
+            .read()
+            .unwrap()
+            .supported_apis()
+            .get(api_key)
+            .is_some();
+        let error = if gated {
+            BrokerError::UnsupportedVersion {
+                api_key: header.api_key_code(),
+                version: header.api_version(),
+            }
+        } else {
+            BrokerError::UnsupportedApi {
+                api_key: header.api_key_code(),
+            }
+        };
+        wire_error(&error)
+    }
+}
+
+impl Default for KafkaBroker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The records of one produce partition, once validated
+enum CheckedRecords {
+    /// v2 record batches, as sent from Produce v3
+    Batches(Vec<RecordBatch>),
+    /// A legacy message set, as sent up to Produce v2
+    MessageSet(Vec<LegacyMessage>),
+}
+
+impl CheckedRecords {
+    fn record_count(&self) -> usize {
+        match self {
+            Self::Batches(batches) => batches
+                .iter()
+                .map(|batch| batch.record_count() as usize)
+                .sum(),
+            Self::MessageSet(messages) => messages.len(),
+        }
+    }
+}
+
+/// Batches one partition returned to a Fetch, kept for recording once the
+/// response is final
+#[derive(Debug)]
+struct FetchedBatches {
+    topic: String,
+    partition: i32,
+    bytes: u64,
+    records: u64,
+    /// Log append time of each batch
+    append_times_ms: Vec<i64>,
+}
+
+/// Whether a Fetch can be answered without waiting any longer
+///
+/// It can once the records read add up to `min_bytes`, or as soon as any
+/// partition failed, so errors are never held back.
+fn fetch_is_satisfied(responses: &[FetchResponseTopic], min_bytes: i32) -> bool {
+    let mut bytes = 0;
+    for partition in responses.iter().flat_map(|topic| &topic.partitions) {
+        if partition.error_code != ErrorCode::NONE {
+            return true;
+        }
+        bytes += partition.records.as_ref().map_or(0, Bytes::len);
+    }
+    bytes >= min_bytes.max(0) as usize
+}
+
+/// Waits until any watermark moves past the one last read for its partition
+///
+/// Returns at once if a partition was dropped, so the caller reads again
+/// and reports it. Without any watermark it never returns.
+async fn wait_past_any(
+    watermarks: &mut [Option<HighWatermarkSubscriber>],
+    seen: impl Iterator<Item = i64>,
+) {
+    let mut waits: Vec<_> = watermarks
+        .iter_mut()
+        .zip(seen)
+        .filter_map(|(watermark, seen)| Some(Box::pin(watermark.as_mut()?.wait_past(seen))))
+        .collect();
+    std::future::poll_fn(|cx| {
+        if waits
+            .iter_mut()
+            .any(|wait| wait.as_mut().poll(cx).is_ready())
+        {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Converts the messages of a legacy message set into one v2 record batch
+///
+/// Keys, values and magic 1 timestamps carry over; magic 0 messages have
+/// no timestamp. Offsets are reassigned from zero, as the log rewrites
+/// them on append anyway.
+fn convert_message_set(messages: &[LegacyMessage]) -> Result<Vec<RecordBatch>, BrokerError> {
+    let mut builder = RecordBatchBuilder::new(0);
+    for message in messages {
+        builder.append(
+            message.timestamp.unwrap_or(NO_TIMESTAMP),
+            message.key.clone(),
+            message.value.clone(),
+            Vec::new(),
+        );
+    }
+    builder
+        .build()
+        .and_then(|data| split_record_batches(&data))
+        .map_err(|e| BrokerError::CorruptRecords(e.to_string()))
+}
+
+/// Applies one IncrementalAlterConfigs change to a topic's configs
+///
+/// SET and DELETE work on the keys topics understand. The list operations
+/// are refused, as no topic key holds a list this broker interprets.
+fn alter_topic_config(
+    configs: &mut BTreeMap<String, String>,
+    config: &AlterableConfig,
+) -> std::result::Result<(), ConfigError> {
+    if topic_config_key(&config.name).is_none() {
+        return Err(ConfigError::UnknownKey(config.name.clone()));
+    }
+    match config.config_operation {
+        ConfigOperation::Set => {
+            let value = config
+                .value
+                .as_deref()
+                .ok_or_else(|| ConfigError::MissingValue(config.name.clone()))?;
+            validate(&config.name, value)?;
+            configs.insert(config.name.clone(), value.to_string());
+        }
+        ConfigOperation::Delete => {
+            configs.remove(&config.name);
+        }
+        operation => {
+            return Err(ConfigError::UnsupportedOperation {
+                key: config.name.clone(),
+                operation: operation.code(),
+            })
+        }
+    }
+    Ok(())
+}
+
+/// A config with its effective value, as DescribeConfigs reports it
+struct ConfigEntry<'a> {
+    name: &'a str,
+    value: &'a str,
+    source: i8,
+    read_only: bool,
+    /// `None` for a key the broker does not know
+    kind: Option<ConfigKind>,
+    default: Option<&'a str>,
+}
+
+impl ConfigEntry<'_> {
+    /// The config for a DescribeConfigs result, with its value withheld
+    /// when it is sensitive
+    ///
+    /// Synonyms list where the value comes from, then the default it
+    /// overrides.
+    fn describe(&self, include_synonyms: bool) -> DescribeConfigsResourceResult {
+        let is_sensitive = is_sensitive_config_key(self.name);
+        let shown = |value: &str| (!is_sensitive).then(|| value.to_string());
+        let mut synonyms = Vec::new();
+        if include_synonyms {
+            synonyms.push(DescribeConfigsSynonym {
+                name: self.name.to_string(),
+                value: shown(self.value),
+                source: self.source,
+            });
+            if let Some(default) = self
+                .default
+                .filter(|_| self.source != CONFIG_SOURCE_DEFAULT)
+            {
+                synonyms.push(DescribeConfigsSynonym {
+                    name: self.name.to_string(),
+                    value: shown(default),
+                    source: CONFIG_SOURCE_DEFAULT,
+                });
+            }
+        }
+        DescribeConfigsResourceResult {
+            name: self.name.to_string(),
+            value: shown(self.value),
+            read_only: self.read_only,
+            config_source: self.source,
+            is_sensitive,
+            synonyms,
+            config_type: match self.kind {
+                Some(ConfigKind::Boolean) => CONFIG_TYPE_BOOLEAN,
+                Some(ConfigKind::Long) => CONFIG_TYPE_LONG,
+                Some(ConfigKind::String) => CONFIG_TYPE_STRING,
+                None => CONFIG_TYPE_UNKNOWN,
+            },
+            documentation: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::events::BrokerEvent;
+    use crate::kafka::group_coordinator::GroupPhase;
+    use crate::kafka::offset_store::MAX_OFFSET_METADATA_BYTES;
+    use crate::kafka::test_util::{
+        frame, read_response, spawn_connection, spawn_connection_with, test_peer_addr,
+    };
+    use crate::protocol::api_versions::{ApiVersionRange, ApiVersionsResponse};
+    use crate::protocol::create_topics::{CreatableReplicaAssignment, CreatableTopicConfig};
+    use crate::protocol::fetch::{FETCH_MAX_VERSION, FETCH_MIN_VERSION};
+    use crate::protocol::find_coordinator::COORDINATOR_TYPE_TRANSACTION;
+    use crate::protocol::join_group::JoinGroupRequestProtocol;
+    use crate::protocol::leave_group::LeavingMember;
+    use crate::protocol::list_offsets::{ListOffsetsPartition, ListOffsetsTopic};
+    use crate::protocol::offset_commit::{OffsetCommitTopic, SIMPLE_COMMIT_GENERATION};
+    use crate::protocol::offset_fetch::OffsetFetchRequestTopic;
+    use crate::protocol::sync_group::SyncGroupRequestAssignment;
+    use crate::protocol::{RequestHeaderV0, RequestHeaderV1};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, DuplexStream};
+
+    /// Builds a length-prefixed ApiVersions request frame
+    fn api_versions_frame(correlation_id: i32) -> Vec<u8> {
+        let header = RequestHeaderV2::with_client_id(18, 0, correlation_id, "test-client");
+        frame(&header.encode().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_api_versions_over_duplex() {
+        let (mut client, handle) = spawn_connection();
+
+        client.write_all(&api_versions_frame(7)).await.unwrap();
+        let response = read_response(&mut client).await;
+
+        // correlation_id followed by error_code 0
+        assert_eq!(&response[0..4], &7i32.to_be_bytes());
+        assert_eq!(&response[4..6], &0i16.to_be_bytes());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    /// Sends ApiVersions at `version` and decodes the reply at `expected_version`
+    async fn negotiate_api_versions(version: i16, expected_version: i16) -> ApiVersionsResponse {
+        let (mut client, handle) = spawn_connection();
+        let header = RequestHeaderV2::with_client_id(18, version, 3, "test-client");
+        client
+            .write_all(&frame(&header.encode().unwrap()))
+            .await
+            .unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &3i32.to_be_bytes());
+        let mut body = Bytes::copy_from_slice(&response[4..]);
+        let decoded = ApiVersionsResponse::decode(&mut body, expected_version).unwrap();
+        assert!(body.is_empty());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        decoded
+    }
+
+    #[tokio::test]
+    async fn test_api_versions_supported_versions() {
+        for version in [0, 3, 4] {
+            let response = negotiate_api_versions(version, version).await;
+            assert_eq!(response.error_code, ErrorCode::NONE);
+            assert!(response.api_keys.contains(&ApiVersionRange {
+                api_key: ApiKey::ApiVersions.code(),
+                min_version: 0,
+                max_version: 4,
+            }));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_versions_unsupported_versions_answer_v0() {
+        for version in [-1, 5, 1234, 32000] {
+            let response = negotiate_api_versions(version, 0).await;
+            assert_eq!(response.error_code, ErrorCode::UNSUPPORTED_VERSION);
+            assert!(
+                response
+                    .api_keys
+                    .iter()
+                    .any(|range| range.api_key == ApiKey::ApiVersions.code()
+                        && range.max_version == 4)
+            );
+        }
+    }
+
+    /// Sends a v3 ApiVersions request with the given body after the
+    /// header's tag section
+    async fn api_versions_v3_with_body(body: &[u8]) -> ApiVersionsResponse {
+        let (mut client, handle) = spawn_connection();
+        let header = RequestHeaderV2::with_client_id(18, 3, 9, "test-client");
+        let mut request = header.encode().unwrap().to_vec();
+        request.extend_from_slice(body);
+        client.write_all(&frame(&request)).await.unwrap();
+        let response = read_response(&mut client).await;
+        let decoded =
+            ApiVersionsResponse::decode(&mut Bytes::copy_from_slice(&response[4..]), 3).unwrap();
+
+        // The connection stays usable
+        client.write_all(&api_versions_frame(10)).await.unwrap();
+        assert_eq!(
+            &read_response(&mut client).await[0..4],
+            &10i32.to_be_bytes()
+        );
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        decoded
+    }
+
+    #[tokio::test]
+    async fn test_api_versions_v3_tolerates_truncated_body() {
+        // A software name cut off after two bytes
+        let response = api_versions_v3_with_body(&[11, b'l', b'i']).await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        assert!(!response.api_keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_api_versions_v3_invalid_utf8_is_invalid_request() {
+        let response = api_versions_v3_with_body(&[3, 0xff, 0xfe, 1, 0]).await;
+        assert_eq!(response.error_code, ErrorCode::INVALID_REQUEST);
+        assert!(!response.api_keys.is_empty());
+    }
+
+    /// Reads an ApiVersions response body field by field, without the
+    /// protocol decoder, into its error code and (key, min, max) entries
+    fn read_api_versions_by_hand(mut body: &[u8], version: i16) -> (i16, Vec<(i16, i16, i16)>) {
+        let flexible = version >= 3;
+        let error_code = body.get_i16();
+        let count = if flexible {
+            body.get_u8() as usize - 1
+        } else {
+            body.get_i32() as usize
+        };
+        let entries = (0..count)
+            .map(|_| {
+                let entry = (body.get_i16(), body.get_i16(), body.get_i16());
+                if flexible {
+                    assert_eq!(body.get_u8(), 0, "entry tag section");
+                }
+                entry
+            })
+            .collect();
+        if version >= 1 {
+            assert_eq!(body.get_i32(), 0, "throttle_time_ms");
+        }
+        if flexible {
+            assert_eq!(body.get_u8(), 0, "top-level tag section");
+        }
+        assert!(body.is_empty(), "{} trailing bytes", body.len());
+        (error_code, entries)
+    }
+
+    #[tokio::test]
+    async fn test_api_versions_answers_client_requests_in_their_version() {
+        // v3 as librdkafka 2.3.0 sends it and v4 as the Java client does:
+        // header v2 with a tag section, then software name and version
+        let librdkafka = hex::decode(concat!(
+            "00120003",
+            "00000001",
+            "0007",
+            "72646b61666b61",
+            "00",
+            "0b",
+            "6c696272646b61666b61",
+            "06",
+            "322e332e30",
+            "00",
+        ))
+        .unwrap();
+        let java = hex::decode(concat!(
+            "00120004",
+            "00000002",
+            "000a",
+            "70726f64756365722d31",
+            "00",
+            "12",
+            "6170616368652d6b61666b612d6a617661",
+            "06",
+            "332e372e30",
+            "00",
+        ))
+        .unwrap();
+        let (mut client, handle) = spawn_connection();
+        let expected: Vec<_> = KafkaRequest::VERSION_RANGES
+            .iter()
+            .map(|range| (range.api_key, range.min_version, range.max_version))
+            .collect();
+        assert!(expected.contains(&(ApiKey::ApiVersions.code(), 0, 4)));
+
+        for (correlation_id, request) in [(1i32, librdkafka), (2, java)] {
+            let version = i16::from_be_bytes([request[2], request[3]]);
+            client.write_all(&frame(&request)).await.unwrap();
+            let response = read_response(&mut client).await;
+            assert_eq!(&response[0..4], &correlation_id.to_be_bytes());
+            assert_eq!(
+                read_api_versions_by_hand(&response[4..], version),
+                (0, expected.clone())
+            );
+        }
+        // The older layouts: INT32 array length, then throttle_time_ms from v1
+        for version in 0..=2 {
+            let header = RequestHeaderV2::with_client_id(18, version, 3, "test-client");
+            client
+                .write_all(&frame(&header.encode().unwrap()))
+                .await
+                .unwrap();
+            let response = read_response(&mut client).await;
+            assert_eq!(
+                read_api_versions_by_hand(&response[4..], version),
+                (0, expected.clone())
+            );
+        }
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_response_header_version_follows_the_api() {
+        let (mut client, handle) = spawn_connection();
+
+        // ApiVersions v4 is flexible but still answered with header v0
+        let header = RequestHeaderV2::with_client_id(ApiKey::ApiVersions.code(), 4, 1, "c");
+        client
+            .write_all(&frame(&header.encode().unwrap()))
+            .await
+            .unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &1i32.to_be_bytes());
+        let mut body = Bytes::copy_from_slice(&response[4..]);
+        assert_eq!(
+            ApiVersionsResponse::decode(&mut body, 4)
+                .unwrap()
+                .error_code,
+            ErrorCode::NONE
+        );
+
+        // DescribeTopicPartitions v0 gets header v1: an empty tag section
+        // between the correlation id and the body
+        client
+            .write_all(&describe_topic_partitions_frame(2, &["orders"]))
+            .await
+            .unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &2i32.to_be_bytes());
+        assert_eq!(response[4], 0);
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    /// Builds a length-prefixed DescribeTopicPartitions v0 request frame
+    fn describe_topic_partitions_frame(correlation_id: i32, topics: &[&str]) -> Vec<u8> {
+        let header = RequestHeaderV2::with_client_id(
+            ApiKey::DescribeTopicPartitions.code(),
+            0,
+            correlation_id,
+            "test-client",
+        );
+        let mut request = header.encode().unwrap();
+        DescribeTopicPartitionsRequest {
+            topics: topics.iter().map(|name| name.to_string()).collect(),
+            response_partition_limit: 100,
+            cursor: None,
+        }
+        .encode_versioned(0, &mut request)
+        .unwrap();
+        frame(&request)
+    }
+
+    async fn describe_topic_partitions(topics: &[&str]) -> DescribeTopicPartitionsResponse {
+        let (mut client, handle) = spawn_connection();
+        client
+            .write_all(&describe_topic_partitions_frame(4, topics))
+            .await
+            .unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..5], &[0, 0, 0, 4, 0]);
+        let mut body = Bytes::copy_from_slice(&response[5..]);
+        let decoded = DescribeTopicPartitionsResponse::decode(&mut body, 0).unwrap();
+        assert!(body.is_empty());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        decoded
+    }
+
+    #[tokio::test]
+    async fn test_describe_unknown_topic() {
+        let response = describe_topic_partitions(&["unknown-topic"]).await;
+        assert_eq!(
+            response.topics,
+            vec![DescribeTopicPartitionsResponseTopic::unknown(
+                "unknown-topic"
+            )]
+        );
+        let topic = &response.topics[0];
+        assert_eq!(topic.error_code, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION);
+        assert!(topic.topic_id.is_nil());
+        assert_eq!(response.next_cursor, None);
+    }
+
+    #[tokio::test]
+    async fn test_describe_stored_topic() {
+        let broker = Arc::new(KafkaBroker::new());
+        let orders = broker
+            .topics()
+            .create_topic("orders", 2, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(broker);
+        client
+            .write_all(&describe_topic_partitions_frame(
+                4,
+                &["orders", "unknown-topic"],
+            ))
+            .await
+            .unwrap();
+        let response = read_response(&mut client).await;
+        let mut body = Bytes::copy_from_slice(&response[5..]);
+        let response = DescribeTopicPartitionsResponse::decode(&mut body, 0).unwrap();
+
+        let topic = &response.topics[0];
+        assert_eq!(topic.error_code, ErrorCode::NONE);
+        assert_eq!(topic.name.as_deref(), Some("orders"));
+        assert_eq!(topic.topic_id, orders.topic_id);
+        let indexes: Vec<_> = topic
+            .partitions
+            .iter()
+            .map(|partition| (partition.partition_index, partition.leader_id))
+            .collect();
+        assert_eq!(indexes, [(0, DEFAULT_NODE_ID), (1, DEFAULT_NODE_ID)]);
+        assert_eq!(
+            response.topics[1],
+            DescribeTopicPartitionsResponseTopic::unknown("unknown-topic")
+        );
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_describe_follows_cursor_across_pages() {
+        let broker = KafkaBroker::new();
+        broker
+            .topics()
+            .create_topic("orders", 7, BTreeMap::new())
+            .unwrap();
+        broker
+            .topics()
+            .create_topic("alpha", 2, BTreeMap::new())
+            .unwrap();
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let response = broker
+                .handle_describe_topic_partitions_request(DescribeTopicPartitionsRequest {
+                    topics: vec!["orders".to_string()],
+                    response_partition_limit: 3,
+                    cursor: cursor.take(),
+                })
+                .unwrap();
+            pages += 1;
+            assert_eq!(response.topics.len(), 1);
+            let topic = &response.topics[0];
+            assert_eq!(topic.name.as_deref(), Some("orders"));
+            assert!(topic.partitions.len() <= 3);
+            seen.extend(topic.partitions.iter().map(|p| p.partition_index));
+            match response.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 3);
+        assert_eq!(seen, (0..7).collect::<Vec<_>>());
+
+        // A limit reached at a topic boundary continues with the next topic
+        let response = broker
+            .handle_describe_topic_partitions_request(DescribeTopicPartitionsRequest {
+                topics: Vec::new(),
+                response_partition_limit: 2,
+                cursor: None,
+            })
+            .unwrap();
+        assert_eq!(response.topics.len(), 1);
+        assert_eq!(response.topics[0].partitions.len(), 2);
+        assert_eq!(
+            response.next_cursor,
+            Some(DescribeTopicPartitionsCursor {
+                topic_name: "orders".to_string(),
+                partition_index: 0,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_describe_topics_sorted_by_name() {
+        let response = describe_topic_partitions(&["zeta", "alpha", "mid", "alpha"]).await;
+        let names: Vec<_> = response
+            .topics
+            .iter()
+            .map(|topic| topic.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["alpha", "mid", "zeta"]);
+    }
+
+    async fn fetch(version: i16, topics: Vec<FetchTopic>) -> FetchResponse {
+        fetch_from(&Arc::new(KafkaBroker::new()), version, topics).await
+    }
+
+    async fn fetch_from(
+        broker: &Arc<KafkaBroker>,
+        version: i16,
+        topics: Vec<FetchTopic>,
+    ) -> FetchResponse {
+        let header =
+            RequestHeaderV2::with_client_id(ApiKey::Fetch.code(), version, 6, "test-client");
+        let mut request = header.encode().unwrap();
+        FetchRequest {
+            cluster_id: None,
+            replica_id: -1,
+            replica_epoch: -1,
+            max_wait_ms: 500,
+            min_bytes: 1,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id: 0,
+            session_epoch: 0,
+            topics,
+            forgotten_topics_data: Vec::new(),
+            rack_id: String::new(),
+        }
+        .encode_versioned(version, &mut request)
+        .unwrap();
+
+        let (mut client, handle) = spawn_connection_with(Arc::clone(broker));
+        client.write_all(&frame(&request)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..5], &[0, 0, 0, 6, 0]);
+        let mut body = Bytes::copy_from_slice(&response[5..]);
+        let decoded = FetchResponse::decode(&mut body, version).unwrap();
+        assert!(body.is_empty());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        decoded
+    }
+
+    fn fetch_header(version: i16) -> RequestHeader {
+        RequestHeader::V2(RequestHeaderV2::with_client_id(
+            ApiKey::Fetch.code(),
+            version,
+            1,
+            "test-client",
+        ))
+    }
+
+    fn test_context(cancellation: CancellationToken) -> RequestContext {
+        RequestContext::new("127.0.0.1:50000".parse().unwrap(), cancellation)
+    }
+
+    fn fetch_topic(topic: &str, topic_id: Uuid, partitions: &[i32]) -> FetchTopic {
+        FetchTopic {
+            topic: topic.to_string(),
+            topic_id,
+            partitions: partitions
+                .iter()
+                .map(|&partition| FetchPartition {
+                    partition,
+                    current_leader_epoch: -1,
+                    fetch_offset: 0,
+                    last_fetched_epoch: -1,
+                    log_start_offset: -1,
+                    partition_max_bytes: 1024 * 1024,
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_with_no_topics() {
+        let response = fetch(16, Vec::new()).await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        // Even an empty full fetch opens a session
+        assert_ne!(response.session_id, 0);
+        assert!(response.responses.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_incremental_fetch_leaves_out_unchanged_partitions() {
+        let broker = KafkaBroker::new();
+        broker
+            .topics()
+            .create_topic("orders", 2, BTreeMap::new())
+            .unwrap();
+        let request = |session_id, session_epoch, topics| FetchRequest {
+            cluster_id: None,
+            replica_id: -1,
+            replica_epoch: -1,
+            max_wait_ms: 0,
+            min_bytes: 0,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id,
+            session_epoch,
+            topics,
+            forgotten_topics_data: Vec::new(),
+            rack_id: String::new(),
+        };
+        let context = test_context(CancellationToken::new());
+
+        let full = broker
+            .handle_fetch_request(
+                &fetch_header(12),
+                request(0, 0, vec![fetch_topic("orders", Uuid::nil(), &[0, 1])]),
+                &context,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(full.responses[0].partitions.len(), 2);
+        assert_eq!(broker.fetch_sessions().len(), 1);
+
+        let incremental = broker
+            .handle_fetch_request(
+                &fetch_header(12),
+                request(full.session_id, 1, Vec::new()),
+                &context,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(incremental.error_code, ErrorCode::NONE);
+        assert_eq!(incremental.session_id, full.session_id);
+        assert!(incremental.responses.is_empty());
+
+        let stale = broker
+            .handle_fetch_request(
+                &fetch_header(12),
+                request(full.session_id, 1, Vec::new()),
+                &context,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stale.error_code, ErrorCode::INVALID_FETCH_SESSION_EPOCH);
+        assert_eq!(stale.session_id, 0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_waits_for_min_bytes() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let request = |max_wait_ms| FetchRequest {
+            cluster_id: None,
+            replica_id: -1,
+            replica_epoch: -1,
+            max_wait_ms,
+            min_bytes: 1,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id: 0,
+            session_epoch: -1,
+            topics: vec![fetch_topic("orders", Uuid::nil(), &[0])],
+            forgotten_topics_data: Vec::new(),
+            rack_id: String::new(),
+        };
+        let context = test_context(CancellationToken::new());
+
+        let started = Instant::now();
+        let empty = broker
+            .handle_fetch_request(&fetch_header(12), request(50), &context)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(empty.responses[0].partitions[0].high_watermark, 0);
+        assert_eq!(
+            empty.responses[0].partitions[0].records.as_deref(),
+            Some(&b""[..])
+        );
+
+        let parked = tokio::spawn({
+            let broker = Arc::clone(&broker);
+            async move {
+                let context = test_context(CancellationToken::new());
+                broker
+                    .handle_fetch_request(&fetch_header(12), request(30_000), &context)
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!parked.is_finished());
+        let topic = broker.topics().get_topic("orders").unwrap();
+        let log = &topic.partitions[0];
+        log.append(Bytes::from_static(b"first"), 1);
+        log.advance_high_watermark(log.log_end_offset());
+
+        let response = tokio::time::timeout(Duration::from_secs(5), parked)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.responses[0].partitions[0].high_watermark, 1);
+        assert_eq!(
+            response.responses[0].partitions[0].records.as_deref(),
+            Some(&b"first"[..])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_ends_fetch_wait() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let request = FetchRequest {
+            cluster_id: None,
+            replica_id: -1,
+            replica_epoch: -1,
+            max_wait_ms: 30_000,
+            min_bytes: 1,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id: 0,
+            session_epoch: 0,
+            topics: vec![fetch_topic("orders", Uuid::nil(), &[0])],
+            forgotten_topics_data: Vec::new(),
+            rack_id: String::new(),
+        };
+        let cancellation = CancellationToken::new();
+        let parked = tokio::spawn({
+            let broker = Arc::clone(&broker);
+            let context = test_context(cancellation.clone());
+            async move {
+                broker
+                    .handle_fetch_request(&fetch_header(12), request, &context)
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!parked.is_finished());
+
+        cancellation.cancel();
+        let response = tokio::time::timeout(Duration::from_secs(5), parked)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(response.is_none());
+        // The session opened by the fetch is still there, never answered
+        assert_eq!(broker.fetch_sessions().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_unknown_topic_id() {
+        let topic_id = Uuid::from_u128(0xdead);
+        let response = fetch(16, vec![fetch_topic("", topic_id, &[0, 1])]).await;
+        assert_eq!(response.responses.len(), 1);
+        assert_eq!(response.responses[0].topic_id, topic_id);
+        assert_eq!(
+            response.responses[0].partitions,
+            vec![
+                FetchResponsePartition::error(0, ErrorCode::UNKNOWN_TOPIC_ID),
+                FetchResponsePartition::error(1, ErrorCode::UNKNOWN_TOPIC_ID),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_unknown_topic_name() {
+        let response = fetch(12, vec![fetch_topic("orders", Uuid::nil(), &[0])]).await;
+        assert_eq!(response.responses[0].topic, "orders");
+        assert_eq!(
+            response.responses[0].partitions,
+            vec![FetchResponsePartition::error(
+                0,
+                ErrorCode::UNKNOWN_TOPIC_OR_PARTITION
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_returns_produced_batches() {
+        use crate::protocol::record_batch::encode_test_batch;
+
+        let broker = Arc::new(KafkaBroker::new());
+        let orders = broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+        for correlation_id in [1, 2] {
+            client
+                .write_all(&flexible_produce_frame(correlation_id, &[("orders", &[0])]))
+                .await
+                .unwrap();
+            read_response(&mut client).await;
+        }
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+
+        let produced = encode_test_batch(3, 0);
+        let response = fetch_from(&broker, 16, vec![fetch_topic("", orders.topic_id, &[0])]).await;
+        assert_eq!(response.responses[0].topic_id, orders.topic_id);
+        let partition = &response.responses[0].partitions[0];
+        assert_eq!(partition.error_code, ErrorCode::NONE);
+        assert_eq!(partition.high_watermark, 6);
+        assert_eq!(partition.log_start_offset, 0);
+        assert_eq!(partition.aborted_transactions, None);
+
+        // The stored bytes come back as produced, bar the rewritten base offset
+        let records = partition.records.clone().unwrap();
+        let batches = split_record_batches(&records).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].data(), &produced);
+        assert_eq!(&batches[1].data()[8..], &produced[8..]);
+        assert_eq!(batches[1].base_offset(), 3);
+        let expected = split_record_batches(&produced).unwrap()[0]
+            .records()
+            .unwrap();
+        let fetched = batches[0].records().unwrap();
+        assert_eq!(fetched.len(), 3);
+        assert_eq!(fetched, expected);
+
+        // By name, from the second batch
+        let mut topic = fetch_topic("orders", Uuid::nil(), &[0]);
+        topic.partitions[0].fetch_offset = 4;
+        let response = fetch_from(&broker, 12, vec![topic]).await;
+        let records = response.responses[0].partitions[0].records.clone().unwrap();
+        assert_eq!(split_record_batches(&records).unwrap()[0].base_offset(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_records_delivery_latency() {
+        use crate::kafka::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new(0));
+        let broker = Arc::new(
+            KafkaBroker::new()
+                .with_delivery_latency(DeliveryLatencyTracker::new(clock.clone(), 60_000)),
+        );
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+        client
+            .write_all(&flexible_produce_frame(1, &[("orders", &[0])]))
+            .await
+            .unwrap();
+        read_response(&mut client).await;
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+
+        // Fetched 40 ms after the append, as far as the tracker can tell
+        let appended_at = broker.topics().get_topic("orders").unwrap().partitions[0]
+            .read_from(0, 1024)
+            .unwrap()[0]
+            .append_time_ms;
+        clock.set_ms(appended_at + 40);
+        fetch_from(&broker, 12, vec![fetch_topic("orders", Uuid::nil(), &[0])]).await;
+        let histogram = broker.delivery_latency().histogram("orders").unwrap();
+        assert_eq!((histogram.count(), histogram.max_ms()), (1, 40));
+
+        // Catching up on data past the horizon is not recorded
+        clock.set_ms(appended_at + 120_000);
+        fetch_from(&broker, 12, vec![fetch_topic("orders", Uuid::nil(), &[0])]).await;
+        assert_eq!(
+            broker
+                .delivery_latency()
+                .histogram("orders")
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_counts_towards_throughput() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+        client
+            .write_all(&flexible_produce_frame(1, &[("orders", &[0])]))
+            .await
+            .unwrap();
+        read_response(&mut client).await;
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+
+        let fetched = fetch_from(&broker, 12, vec![fetch_topic("orders", Uuid::nil(), &[0])]).await;
+        let records = fetched.responses[0].partitions[0].records.clone().unwrap();
+        broker.throughput().evaluate();
+        let partition = &broker.throughput().top_partitions(1)[0];
+        assert_eq!(partition.fetch_bytes, records.len() as u64);
+        assert_eq!(partition.fetch_records, partition.produce_records);
+        assert!(partition.fetch_records > 0);
+
+        let series: BTreeMap<_, _> = broker.topic_metrics().series().into_iter().collect();
+        assert_eq!(series["orders"].fetch_bytes, records.len() as u64);
+        assert_eq!(series["orders"].fetch_records, partition.fetch_records);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_produce_is_not_throughput() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+        client
+            .write_all(&flexible_produce_frame(1, &[("orders", &[0, 5])]))
+            .await
+            .unwrap();
+        read_response(&mut client).await;
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+
+        // Only the batch appended to partition 0 counts
+        let appended = broker.topics().get_topic("orders").unwrap().partitions[0]
+            .read_from(0, 1024)
+            .unwrap()[0]
+            .data
+            .len() as u64;
+        let series: BTreeMap<_, _> = broker.topic_metrics().series().into_iter().collect();
+        assert_eq!(series["orders"].produce_bytes, appended);
+        assert_eq!(series["orders"].produce_records, 3);
+        broker.throughput().evaluate();
+        let partitions = broker.throughput().top_partitions(2);
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(partitions[0].produce_records, 3);
+    }
+
+    #[tokio::test]
+    async fn test_long_poll_counts_answered_batches_once() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let topic = broker.topics().get_topic("orders").unwrap();
+        let log = &topic.partitions[0];
+        log.append(Bytes::from_static(b"first"), 1);
+        log.advance_high_watermark(log.log_end_offset());
+
+        // Short of min_bytes, so the first batch is read again on every wake
+        let request = FetchRequest {
+            cluster_id: None,
+            replica_id: -1,
+            replica_epoch: -1,
+            max_wait_ms: 200,
+            min_bytes: 1024,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id: 0,
+            session_epoch: -1,
+            topics: vec![fetch_topic("orders", Uuid::nil(), &[0])],
+            forgotten_topics_data: Vec::new(),
+            rack_id: String::new(),
+        };
+        let parked = tokio::spawn({
+            let broker = Arc::clone(&broker);
+            async move {
+                let context = test_context(CancellationToken::new());
+                broker
+                    .handle_fetch_request(&fetch_header(12), request, &context)
+                    .await
+            }
+        });
+        for record in [&b"second"[..], &b"third"[..]] {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            log.append(Bytes::from_static(record), 1);
+            log.advance_high_watermark(log.log_end_offset());
+        }
+        let response = tokio::time::timeout(Duration::from_secs(5), parked)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            response.responses[0].partitions[0].records.as_deref(),
+            Some(&b"firstsecondthird"[..])
+        );
+
+        let series: BTreeMap<_, _> = broker.topic_metrics().series().into_iter().collect();
+        assert_eq!(series["orders"].fetch_bytes, 16);
+        assert_eq!(series["orders"].fetch_records, 3);
+        broker.throughput().evaluate();
+        let partition = &broker.throughput().top_partitions(1)[0];
+        assert_eq!((partition.fetch_bytes, partition.fetch_records), (16, 3));
+        let histogram = broker.delivery_latency().histogram("orders").unwrap();
+        assert_eq!(histogram.count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_offsets_outside_the_log() {
+        use crate::protocol::record_batch::encode_test_batch;
+
+        let broker = Arc::new(KafkaBroker::new());
+        let orders = broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let log = &orders.partitions[0];
+        for _ in 0..2 {
+            let batches = split_record_batches(&encode_test_batch(3, 0)).unwrap();
+            log.append_record_batches(&batches, false).unwrap();
+        }
+        log.advance_high_watermark(log.log_end_offset());
+        // The batches are stamped at time 0, so retention drops them both
+        assert_eq!(log.delete_expired_batches(1, i64::MAX), 2);
+        assert_eq!(log.log_start_offset(), 6);
+
+        for (fetch_offset, expected) in [
+            (0, ErrorCode::OFFSET_OUT_OF_RANGE),
+            (6, ErrorCode::NONE),
+            (100, ErrorCode::NONE),
+        ] {
+            let mut topic = fetch_topic("", orders.topic_id, &[0]);
+            topic.partitions[0].fetch_offset = fetch_offset;
+            let response = fetch_from(&broker, 16, vec![topic]).await;
+            let partition = &response.responses[0].partitions[0];
+            assert_eq!(partition.error_code, expected, "offset {}", fetch_offset);
+            assert_eq!(
+                partition.records.as_deref(),
+                Some(&[][..]),
+                "offset {}",
+                fetch_offset
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_reads_segment_file() {
+        use crate::kafka::storage::metadata_log::{encode_batch, MetadataRecord};
+        use crate::protocol::record_batch::encode_test_batch;
+
+        let log_dir = tempfile::tempdir().unwrap();
+        let record = MetadataRecord::Topic {
+            name: "orders".to_string(),
+            topic_id: Uuid::nil(),
+        };
+        // Batches at offsets 10-11 and 12, written as a previous run left them
+        let first = encode_batch(10, 0, &[record.clone(), record.clone()]).unwrap();
+        let second = encode_batch(12, 0, &[record]).unwrap();
+        let segment = [&first[..], &second[..]].concat();
+        std::fs::create_dir_all(log_dir.path().join("orders-0")).unwrap();
+        std::fs::write(
+            log_dir.path().join("orders-0/00000000000000000000.log"),
+            &segment,
+        )
+        .unwrap();
+        let broker = Arc::new(KafkaBroker::new().with_log_dir(log_dir.path()));
+        let orders = broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        // Appended but not yet committed, so past the high watermark
+        let uncommitted = split_record_batches(&encode_test_batch(1, 0)).unwrap();
+        orders.partitions[0]
+            .append_record_batches(&uncommitted, false)
+            .unwrap();
+
+        for (fetch_offset, expected, records) in [
+            (9, ErrorCode::OFFSET_OUT_OF_RANGE, &[][..]),
+            (10, ErrorCode::NONE, &segment[..]),
+            (11, ErrorCode::NONE, &segment[..]),
+            (12, ErrorCode::NONE, &second[..]),
+            (13, ErrorCode::NONE, &[][..]),
+        ] {
+            let mut topic = fetch_topic("orders", orders.topic_id, &[0]);
+            topic.partitions[0].fetch_offset = fetch_offset;
+            let response = fetch_from(&broker, 12, vec![topic]).await;
+            let partition = &response.responses[0].partitions[0];
+            assert_eq!(partition.error_code, expected, "offset {}", fetch_offset);
+            assert_eq!(partition.records.as_deref(), Some(records));
+            if expected == ErrorCode::NONE {
+                assert_eq!(partition.high_watermark, 13);
+                assert_eq!(partition.last_stable_offset, 13);
+                assert_eq!(partition.log_start_offset, 10);
+            }
+        }
+    }
+
+    async fn create_topics(
+        broker: &Arc<KafkaBroker>,
+        version: i16,
+        topics: Vec<CreatableTopic>,
+        validate_only: bool,
+    ) -> CreateTopicsResponse {
+        let header =
+            RequestHeaderV2::with_client_id(ApiKey::CreateTopics.code(), version, 8, "test-client");
+        let mut request = header.encode().unwrap();
+        CreateTopicsRequest {
+            topics,
+            timeout_ms: 30_000,
+            validate_only,
+        }
+        .encode_versioned(version, &mut request)
+        .unwrap();
+
+        let (mut client, handle) = spawn_connection_with(Arc::clone(broker));
+        client.write_all(&frame(&request)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..5], &[0, 0, 0, 8, 0]);
+        let mut body = Bytes::copy_from_slice(&response[5..]);
+        let decoded = CreateTopicsResponse::decode(&mut body, version).unwrap();
+        assert!(body.is_empty());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        decoded
+    }
+
+    fn creatable_topic(name: &str, num_partitions: i32) -> CreatableTopic {
+        CreatableTopic {
+            name: name.to_string(),
+            num_partitions,
+            replication_factor: 1,
+            assignments: Vec::new(),
+            configs: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_topics_validate_only_creates_nothing() {
+        let broker = Arc::new(KafkaBroker::new());
+        let response = create_topics(&broker, 7, vec![creatable_topic("orders", 3)], true).await;
+        let result = &response.topics[0];
+        assert_eq!(result.error_code, ErrorCode::NONE);
+        assert_eq!(result.num_partitions, 3);
+        assert!(result.topic_id.is_nil());
+        assert!(broker.topics().get_topic("orders").is_none());
+        assert_eq!(broker.metadata_epoch().current(), 0);
+
+        // Validation still reports what creation would fail with
+        let response = create_topics(&broker, 7, vec![creatable_topic("orders", 0)], true).await;
+        assert_eq!(response.topics[0].error_code, ErrorCode::INVALID_PARTITIONS);
+    }
+
+    #[tokio::test]
+    async fn test_create_topics_rejects_duplicates() {
+        let broker = Arc::new(KafkaBroker::new());
+        let response = create_topics(&broker, 7, vec![creatable_topic("orders", 2)], false).await;
+        let created = &response.topics[0];
+        assert_eq!(created.error_code, ErrorCode::NONE);
+        let topic = broker.topics().get_topic("orders").unwrap();
+        assert_eq!(created.topic_id, topic.topic_id);
+        assert_eq!(topic.partition_count(), 2);
+
+        for validate_only in [true, false] {
+            let response = create_topics(
+                &broker,
+                7,
+                vec![creatable_topic("orders", 5)],
+                validate_only,
+            )
+            .await;
+            let result = &response.topics[0];
+            assert_eq!(result.error_code, ErrorCode::TOPIC_ALREADY_EXISTS);
+            assert_eq!(
+                result.error_message.as_deref(),
+                Some("Topic 'orders' already exists")
+            );
+            assert!(result.topic_id.is_nil());
+        }
+        assert_eq!(
+            broker.topics().get_topic("orders").unwrap().topic_id,
+            topic.topic_id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_topics_batch_with_one_failure() {
+        let broker = Arc::new(KafkaBroker::new());
+        let mut compacted = creatable_topic("orders", -1);
+        compacted.configs = vec![CreatableTopicConfig {
+            name: "cleanup.policy".to_string(),
+            value: Some("compact".to_string()),
+        }];
+        let response = create_topics(
+            &broker,
+            5,
+            vec![
+                compacted,
+                creatable_topic("payments", 0),
+                CreatableTopic {
+                    replication_factor: 3,
+                    ..creatable_topic("audit", 1)
+                },
+            ],
+            false,
+        )
+        .await;
+
+        let codes: Vec<_> = response
+            .topics
+            .iter()
+            .map(|topic| (topic.name.as_str(), topic.error_code))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                ("orders", ErrorCode::NONE),
+                ("payments", ErrorCode::INVALID_PARTITIONS),
+                ("audit", ErrorCode::INVALID_REPLICATION_FACTOR),
+            ]
+        );
+        let orders = &response.topics[0];
+        assert_eq!(orders.num_partitions, 1);
+        assert_eq!(orders.configs.as_ref().unwrap()[0].name, "cleanup.policy");
+
+        let names: Vec<_> = broker
+            .topics()
+            .list_topics()
+            .iter()
+            .map(|topic| topic.name.clone())
+            .collect();
+        assert_eq!(names, ["orders"]);
+        assert_eq!(broker.metadata_epoch().current(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_topics_defaults_to_num_partitions() {
+        let properties = [("num.partitions".to_string(), "3".to_string())];
+        let broker =
+            Arc::new(KafkaBroker::new().with_config(BrokerConfig::from_properties(&properties)));
+        let response = create_topics(&broker, 7, vec![creatable_topic("orders", -1)], false).await;
+        assert_eq!(response.topics[0].error_code, ErrorCode::NONE);
+        assert_eq!(response.topics[0].num_partitions, 3);
+        assert_eq!(
+            broker
+                .topics()
+                .get_topic("orders")
+                .unwrap()
+                .partition_count(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_topics_with_manual_assignment() {
+        let broker = Arc::new(KafkaBroker::new().with_node_id(3));
+        let assigned = |broker_ids: Vec<i32>| CreatableTopic {
+            num_partitions: -1,
+            replication_factor: -1,
+            assignments: vec![
+                CreatableReplicaAssignment {
+                    partition_index: 0,
+                    broker_ids: vec![3],
+                },
+                CreatableReplicaAssignment {
+                    partition_index: 1,
+                    broker_ids,
+                },
+            ],
+            ..creatable_topic("orders", -1)
+        };
+        let response = create_topics(&broker, 7, vec![assigned(vec![3, 4])], false).await;
+        assert_eq!(
+            response.topics[0].error_code,
+            ErrorCode::INVALID_REPLICA_ASSIGNMENT
+        );
+        let response = create_topics(&broker, 7, vec![assigned(vec![3])], false).await;
+        assert_eq!(response.topics[0].error_code, ErrorCode::NONE);
+        assert_eq!(response.topics[0].num_partitions, 2);
+    }
+
+    async fn delete_topics(
+        broker: &Arc<KafkaBroker>,
+        version: i16,
+        topics: Vec<DeleteTopicState>,
+    ) -> DeleteTopicsResponse {
+        let header =
+            RequestHeaderV2::with_client_id(ApiKey::DeleteTopics.code(), version, 9, "test-client");
+        let mut request = header.encode().unwrap();
+        DeleteTopicsRequest {
+            topics,
+            timeout_ms: 30_000,
+        }
+        .encode_versioned(version, &mut request)
+        .unwrap();
+
+        let (mut client, handle) = spawn_connection_with(Arc::clone(broker));
+        client.write_all(&frame(&request)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..5], &[0, 0, 0, 9, 0]);
+        let mut body = Bytes::copy_from_slice(&response[5..]);
+        let decoded = DeleteTopicsResponse::decode(&mut body, version).unwrap();
+        assert!(body.is_empty());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        decoded
+    }
+
+    fn by_name(name: &str) -> DeleteTopicState {
+        DeleteTopicState {
+            name: Some(name.to_string()),
+            topic_id: Uuid::nil(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_topics_mixed_batch() {
+        let broker = Arc::new(KafkaBroker::new());
+        for name in ["orders", "__consumer_offsets"] {
+            broker
+                .topics()
+                .create_topic(name, 1, BTreeMap::new())
+                .unwrap();
+        }
+        let response = delete_topics(
+            &broker,
+            5,
+            vec![
+                by_name("orders"),
+                by_name("missing"),
+                by_name("__consumer_offsets"),
+            ],
+        )
+        .await;
+
+        let codes: Vec<_> = response
+            .responses
+            .iter()
+            .map(|topic| (topic.name.as_deref().unwrap(), topic.error_code))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                ("orders", ErrorCode::NONE),
+                ("missing", ErrorCode::UNKNOWN_TOPIC_OR_PARTITION),
+                ("__consumer_offsets", ErrorCode::INVALID_TOPIC_EXCEPTION),
+            ]
+        );
+        assert!(response.responses[0].error_message.is_none());
+        assert!(response.responses[1].error_message.is_some());
+        assert!(broker.topics().get_topic("orders").is_none());
+        assert!(broker.topics().get_topic("__consumer_offsets").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_delete_topics_by_id() {
+        let broker = Arc::new(KafkaBroker::new());
+        let topic = broker
+            .topics()
+            .create_topic("orders", 2, BTreeMap::new())
+            .unwrap();
+        let unknown_id = Uuid::from_u128(0xdead);
+        let by_id = |topic_id| DeleteTopicState {
+            name: None,
+            topic_id,
+        };
+        let response =
+            delete_topics(&broker, 6, vec![by_id(topic.topic_id), by_id(unknown_id)]).await;
+
+        let deleted = &response.responses[0];
+        assert_eq!(deleted.error_code, ErrorCode::NONE);
+        assert_eq!(deleted.name.as_deref(), Some("orders"));
+        assert_eq!(deleted.topic_id, topic.topic_id);
+        let unknown = &response.responses[1];
+        assert_eq!(unknown.error_code, ErrorCode::UNKNOWN_TOPIC_ID);
+        assert_eq!(unknown.name, None);
+        assert_eq!(unknown.topic_id, unknown_id);
+        assert!(broker.topics().list_topics().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_topics_removes_partition_directories() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let broker = Arc::new(KafkaBroker::new().with_log_dir(log_dir.path()));
+        broker
+            .topics()
+            .create_topic("orders", 2, BTreeMap::new())
+            .unwrap();
+        // Only the first partition has anything on disk
+        let segment = log_dir.path().join("orders-0/00000000000000000000.log");
+        std::fs::create_dir_all(segment.parent().unwrap()).unwrap();
+        std::fs::write(&segment, b"").unwrap();
+        let unrelated = log_dir.path().join("orders-archive-0");
+        std::fs::create_dir_all(&unrelated).unwrap();
+
+        let response = delete_topics(&broker, 4, vec![by_name("orders")]).await;
+        assert_eq!(response.responses[0].error_code, ErrorCode::NONE);
+        assert!(!log_dir.path().join("orders-0").exists());
+        assert!(unrelated.exists());
+    }
+
+    async fn list_offsets(
+        broker: &Arc<KafkaBroker>,
+        version: i16,
+        partitions: &[(i32, i64)],
+    ) -> Vec<ListOffsetsPartitionResponse> {
+        let mut request = if version >= 6 {
+            RequestHeaderV2::with_client_id(ApiKey::ListOffsets.code(), version, 10, "test-client")
+                .encode()
+        } else {
+            RequestHeaderV1::new(
+                ApiKey::ListOffsets.code(),
+                version,
+                10,
+                Some("test-client".into()),
+            )
+            .encode()
+        }
+        .unwrap();
+        ListOffsetsRequest {
+            replica_id: -1,
+            isolation_level: 0,
+            topics: vec![ListOffsetsTopic {
+                name: "orders".to_string(),
+                partitions: partitions
+                    .iter()
+                    .map(|&(partition_index, timestamp)| ListOffsetsPartition {
+                        partition_index,
+                        current_leader_epoch: -1,
+                        timestamp,
+                    })
+                    .collect(),
+            }],
+        }
+        .encode_versioned(version, &mut request)
+        .unwrap();
+
+        let (mut client, handle) = spawn_connection_with(Arc::clone(broker));
+        client.write_all(&frame(&request)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &10i32.to_be_bytes());
+        // Flexible versions carry an empty tag section after the correlation id
+        let body_start = if version >= 6 { 5 } else { 4 };
+        let mut body = Bytes::copy_from_slice(&response[body_start..]);
+        let decoded = ListOffsetsResponse::decode(&mut body, version).unwrap();
+        assert!(body.is_empty());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        decoded.topics.into_iter().next().unwrap().partitions
+    }
+
+    #[tokio::test]
+    async fn test_list_offsets_of_empty_partition() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        for version in [1, 9] {
+            let partitions = list_offsets(
+                &broker,
+                version,
+                &[(0, EARLIEST_TIMESTAMP), (0, LATEST_TIMESTAMP)],
+            )
+            .await;
+            assert_eq!(
+                partitions,
+                [
+                    ListOffsetsPartitionResponse::found(0, -1, 0),
+                    ListOffsetsPartitionResponse::found(0, -1, 0),
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_offsets_after_appends() {
+        let broker = Arc::new(KafkaBroker::new());
+        let topic = broker
+            .topics()
+            .create_topic("orders", 2, BTreeMap::new())
+            .unwrap();
+        topic.partitions[1].append(Bytes::from_static(b"first"), 3);
+        topic.partitions[1].append(Bytes::from_static(b"second"), 2);
+
+        let partitions = list_offsets(
+            &broker,
+            6,
+            &[
+                (1, EARLIEST_TIMESTAMP),
+                (1, LATEST_TIMESTAMP),
+                (0, LATEST_TIMESTAMP),
+                (1, i64::MAX),
+                // MAX_TIMESTAMP
+                (1, -3),
+                (2, LATEST_TIMESTAMP),
+            ],
+        )
+        .await;
+        assert_eq!(
+            partitions,
+            [
+                ListOffsetsPartitionResponse::found(1, -1, 0),
+                ListOffsetsPartitionResponse::found(1, -1, 5),
+                ListOffsetsPartitionResponse::found(0, -1, 0),
+                ListOffsetsPartitionResponse::found(1, -1, 5),
+                ListOffsetsPartitionResponse::error(1, ErrorCode::OFFSET_NOT_AVAILABLE),
+                ListOffsetsPartitionResponse::error(2, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_offsets_by_timestamp() {
+        use crate::protocol::record_batch::encode_test_batch;
+
+        let log_dir = tempfile::tempdir().unwrap();
+        let brokers = [
+            KafkaBroker::new(),
+            KafkaBroker::new()
+                .with_log_dir(log_dir.path())
+                .with_log_config(LogConfig {
+                    index_interval_bytes: 0,
+                    ..LogConfig::default()
+                }),
+        ];
+        for broker in brokers {
+            let broker = Arc::new(broker);
+            broker
+                .topics()
+                .create_topic("orders", 1, BTreeMap::new())
+                .unwrap();
+            let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+            for (correlation_id, timestamp) in [(1, 1_000), (2, 2_000), (3, 3_000)] {
+                client
+                    .write_all(&flexible_produce_frame_with(
+                        correlation_id,
+                        &[("orders", &[0])],
+                        encode_test_batch(1, timestamp),
+                    ))
+                    .await
+                    .unwrap();
+                read_response(&mut client).await;
+            }
+            drop(client);
+            assert!(handle.await.unwrap().is_ok());
+
+            let partitions = list_offsets(
+                &broker,
+                9,
+                &[(0, 1_000), (0, 1_500), (0, 500), (0, 3_000), (0, 4_000)],
+            )
+            .await;
+            assert_eq!(
+                partitions,
+                [
+                    // At, between, before and after the stamped records
+                    ListOffsetsPartitionResponse::found(0, 1_000, 0),
+                    ListOffsetsPartitionResponse::found(0, 2_000, 1),
+                    ListOffsetsPartitionResponse::found(0, 1_000, 0),
+                    ListOffsetsPartitionResponse::found(0, 3_000, 2),
+                    ListOffsetsPartitionResponse::found(0, -1, 3),
+                ]
+            );
+        }
+    }
+
+    async fn find_coordinator(
+        broker: &Arc<KafkaBroker>,
+        version: i16,
+        request: FindCoordinatorRequest,
+    ) -> FindCoordinatorResponse {
+        let mut message = if version >= 3 {
+            RequestHeaderV2::with_client_id(
+                ApiKey::FindCoordinator.code(),
+                version,
+                12,
+                "test-client",
+            )
+            .encode()
+        } else {
+            RequestHeaderV1::new(
+                ApiKey::FindCoordinator.code(),
+                version,
+                12,
+                Some("test-client".into()),
+            )
+            .encode()
+        }
+        .unwrap();
+        request.encode_versioned(version, &mut message).unwrap();
+
+        let (mut client, handle) = spawn_connection_with(Arc::clone(broker));
+        client.write_all(&frame(&message)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &12i32.to_be_bytes());
+        let body_start = if version >= 3 { 5 } else { 4 };
+        let mut body = Bytes::copy_from_slice(&response[body_start..]);
+        let decoded = FindCoordinatorResponse::decode(&mut body, version).unwrap();
+        assert!(body.is_empty());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        decoded
+    }
+
+    fn coordinator_broker() -> Arc<KafkaBroker> {
+        let broker = KafkaBroker::new();
+        broker.set_listen_addr("10.0.0.5:19092".parse().unwrap());
+        Arc::new(broker)
+    }
+
+    #[tokio::test]
+    async fn test_find_coordinator_v1_names_this_broker() {
+        let request = FindCoordinatorRequest {
+            key: "billing".to_string(),
+            key_type: COORDINATOR_TYPE_GROUP,
+            coordinator_keys: Vec::new(),
+        };
+        let response = find_coordinator(&coordinator_broker(), 1, request).await;
+        assert_eq!(
+            response,
+            FindCoordinatorResponse::single(Coordinator {
+                key: "billing".to_string(),
+                node_id: DEFAULT_NODE_ID,
+                host: "10.0.0.5".to_string(),
+                port: 19092,
+                error_code: ErrorCode::NONE,
+                error_message: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_coordinator_v4_answers_every_key() {
+        let request = FindCoordinatorRequest {
+            key: String::new(),
+            key_type: COORDINATOR_TYPE_GROUP,
+            coordinator_keys: vec!["billing".to_string(), "audit".to_string()],
+        };
+        let response = find_coordinator(&coordinator_broker(), 4, request).await;
+        let coordinators: Vec<_> = response
+            .coordinators
+            .iter()
+            .map(|c| {
+                (
+                    c.key.as_str(),
+                    c.node_id,
+                    c.host.as_str(),
+                    c.port,
+                    c.error_code,
+                )
+            })
+            .collect();
+        assert_eq!(
+            coordinators,
+            [
+                ("billing", 1, "10.0.0.5", 19092, ErrorCode::NONE),
+                ("audit", 1, "10.0.0.5", 19092, ErrorCode::NONE),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_coordinator_rejects_unknown_key_types() {
+        let broker = coordinator_broker();
+        for key_type in [COORDINATOR_TYPE_TRANSACTION, 7] {
+            let request = FindCoordinatorRequest {
+                key: String::new(),
+                key_type,
+                coordinator_keys: vec!["billing".to_string()],
+            };
+            let response = find_coordinator(&broker, 4, request).await;
+            assert_eq!(response.coordinators.len(), 1);
+            let coordinator = &response.coordinators[0];
+            assert_eq!(coordinator.error_code, ErrorCode::INVALID_REQUEST);
+            assert_eq!(coordinator.node_id, -1);
+            assert!(coordinator.error_message.is_some());
+        }
+
+        let request = FindCoordinatorRequest {
+            key: "billing".to_string(),
+            key_type: 7,
+            coordinator_keys: Vec::new(),
+        };
+        let response = find_coordinator(&broker, 2, request).await;
+        assert_eq!(response.error_code, ErrorCode::INVALID_REQUEST);
+        assert_eq!(response.port, -1);
+    }
+
+    /// Writes a flexible-version request without waiting for the answer
+    async fn send_request(
+        client: &mut DuplexStream,
+        client_id: &str,
+        api_key: i16,
+        version: i16,
+        body: &impl ProtocolEncodeVersioned,
+    ) {
+        let mut request = RequestHeaderV2::with_client_id(api_key, version, 13, client_id)
+            .encode()
+            .unwrap();
+        body.encode_versioned(version, &mut request).unwrap();
+        client.write_all(&frame(&request)).await.unwrap();
+    }
+
+    /// Reads the answer to a request written by [`send_request`]
+    async fn read_body(client: &mut DuplexStream) -> Bytes {
+        let response = read_response(client).await;
+        assert_eq!(&response[0..5], &[0, 0, 0, 13, 0]);
+        Bytes::copy_from_slice(&response[5..])
+    }
+
+    fn join_request(member_id: &str) -> JoinGroupRequest {
+        JoinGroupRequest {
+            group_id: "billing".to_string(),
+            session_timeout_ms: 10_000,
+            rebalance_timeout_ms: 30_000,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+            protocol_type: "consumer".to_string(),
+            protocols: vec![JoinGroupRequestProtocol {
+                name: "range".to_string(),
+                metadata: Bytes::from(format!("subscription of {}", member_id)),
+            }],
+            reason: None,
+        }
+    }
+
+    fn sync_request(
+        member_id: &str,
+        generation_id: i32,
+        assignments: &[(&str, &'static str)],
+    ) -> SyncGroupRequest {
+        SyncGroupRequest {
+            group_id: "billing".to_string(),
+            generation_id,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+            protocol_type: Some("consumer".to_string()),
+            protocol_name: Some("range".to_string()),
+            assignments: assignments
+                .iter()
+                .map(|&(member_id, assignment)| SyncGroupRequestAssignment {
+                    member_id: member_id.to_string(),
+                    assignment: Bytes::from_static(assignment.as_bytes()),
+                })
+                .collect(),
+        }
+    }
+
+    async fn join_group(
+        client: &mut DuplexStream,
+        client_id: &str,
+        request: &JoinGroupRequest,
+    ) -> JoinGroupResponse {
+        send_request(client, client_id, ApiKey::JoinGroup.code(), 7, request).await;
+        JoinGroupResponse::decode(&mut read_body(client).await, 7).unwrap()
+    }
+
+    async fn sync_group(
+        client: &mut DuplexStream,
+        request: &SyncGroupRequest,
+    ) -> SyncGroupResponse {
+        send_request(client, "test-client", ApiKey::SyncGroup.code(), 5, request).await;
+        SyncGroupResponse::decode(&mut read_body(client).await, 5).unwrap()
+    }
+
+    /// Joins with the MEMBER_ID_REQUIRED round trip, returning the member id
+    async fn member_id_for(client: &mut DuplexStream, client_id: &str) -> String {
+        let response = join_group(client, client_id, &join_request("")).await;
+        assert_eq!(response.error_code, ErrorCode::MEMBER_ID_REQUIRED);
+        assert!(response.member_id.starts_with(client_id));
+        response.member_id
+    }
+
+    async fn wait_for_phase(broker: &KafkaBroker, phase: GroupPhase) {
+        while broker.groups.group_phase("billing").map(|(phase, _)| phase) != Some(phase) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_two_members_join_and_sync() {
+        let broker = Arc::new(KafkaBroker::new());
+        let (mut first, _) = spawn_connection_with(Arc::clone(&broker));
+        let (mut second, _) = spawn_connection_with(Arc::clone(&broker));
+
+        // The first member forms generation 1 alone and leads it
+        let a = member_id_for(&mut first, "consumer-a").await;
+        let joined = join_group(&mut first, "consumer-a", &join_request(&a)).await;
+        assert_eq!(joined.error_code, ErrorCode::NONE);
+        assert_eq!(joined.generation_id, 1);
+        assert_eq!(joined.leader, a);
+        assert_eq!(joined.protocol_name.as_deref(), Some("range"));
+        assert_eq!(joined.members.len(), 1);
+        let synced = sync_group(&mut first, &sync_request(&a, 1, &[(&a, "orders-0,1")])).await;
+        assert_eq!(synced.error_code, ErrorCode::NONE);
+        assert_eq!(synced.assignment, "orders-0,1");
+
+        // The second member's join waits for the first to rejoin
+        let b = member_id_for(&mut second, "consumer-b").await;
+        send_request(
+            &mut second,
+            "consumer-b",
+            ApiKey::JoinGroup.code(),
+            7,
+            &join_request(&b),
+        )
+        .await;
+        wait_for_phase(&broker, GroupPhase::PreparingRebalance).await;
+        let leader = join_group(&mut first, "consumer-a", &join_request(&a)).await;
+        let follower = JoinGroupResponse::decode(&mut read_body(&mut second).await, 7).unwrap();
+
+        assert_eq!(leader.generation_id, 2);
+        assert_eq!(follower.generation_id, 2);
+        assert_eq!(follower.leader, a);
+        assert_eq!(follower.member_id, b);
+        assert!(follower.members.is_empty());
+        let members: Vec<_> = leader
+            .members
+            .iter()
+            .map(|member| (member.member_id.as_str(), member.metadata.clone()))
+            .collect();
+        assert_eq!(
+            members,
+            [
+                (a.as_str(), Bytes::from(format!("subscription of {}", a))),
+                (b.as_str(), Bytes::from(format!("subscription of {}", b))),
+            ]
+        );
+
+        // The follower's sync waits for the leader's assignments
+        send_request(
+            &mut second,
+            "consumer-b",
+            ApiKey::SyncGroup.code(),
+            5,
+            &sync_request(&b, 2, &[]),
+        )
+        .await;
+        let synced = sync_group(
+            &mut first,
+            &sync_request(&a, 2, &[(&a, "orders-0"), (&b, "orders-1")]),
+        )
+        .await;
+        assert_eq!(synced.assignment, "orders-0");
+        let synced = SyncGroupResponse::decode(&mut read_body(&mut second).await, 5).unwrap();
+        assert_eq!(synced.error_code, ErrorCode::NONE);
+        assert_eq!(synced.assignment, "orders-1");
+        assert_eq!(
+            broker.groups.group_phase("billing"),
+            Some((GroupPhase::Stable, 2))
+        );
+
+        let stale = sync_group(&mut second, &sync_request(&b, 1, &[])).await;
+        assert_eq!(stale.error_code, ErrorCode::ILLEGAL_GENERATION);
+    }
+
+    #[tokio::test]
+    async fn test_join_group_rejects_unknown_members() {
+        let (mut client, _) = spawn_connection();
+        let response = join_group(&mut client, "consumer-a", &join_request("ghost")).await;
+        assert_eq!(response.error_code, ErrorCode::UNKNOWN_MEMBER_ID);
+
+        let response = sync_group(&mut client, &sync_request("ghost", 1, &[])).await;
+        assert_eq!(response.error_code, ErrorCode::UNKNOWN_MEMBER_ID);
+    }
+
+    #[tokio::test]
+    async fn test_join_group_v3_assigns_member_id_directly() {
+        let (mut client, _) = spawn_connection();
+        let mut request =
+            RequestHeaderV1::new(ApiKey::JoinGroup.code(), 3, 14, Some("consumer-a".into()))
+                .encode()
+                .unwrap();
+        join_request("").encode_versioned(3, &mut request).unwrap();
+        client.write_all(&frame(&request)).await.unwrap();
+
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &14i32.to_be_bytes());
+        let joined =
+            JoinGroupResponse::decode(&mut Bytes::copy_from_slice(&response[4..]), 3).unwrap();
+        assert_eq!(joined.error_code, ErrorCode::NONE);
+        assert_eq!(joined.generation_id, 1);
+        assert!(joined.member_id.starts_with("consumer-a-"));
+        assert_eq!(joined.leader, joined.member_id);
+    }
+
+    async fn heartbeat(
+        client: &mut DuplexStream,
+        member_id: &str,
+        generation_id: i32,
+    ) -> HeartbeatResponse {
+        let request = HeartbeatRequest {
+            group_id: "billing".to_string(),
+            generation_id,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+        };
+        send_request(client, "test-client", ApiKey::Heartbeat.code(), 4, &request).await;
+        HeartbeatResponse::decode(&mut read_body(client).await, 4).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_and_leave_group() {
+        let broker = Arc::new(KafkaBroker::new());
+        let (mut client, _) = spawn_connection_with(Arc::clone(&broker));
+        let a = member_id_for(&mut client, "consumer-a").await;
+        join_group(&mut client, "consumer-a", &join_request(&a)).await;
+        sync_group(&mut client, &sync_request(&a, 1, &[(&a, "orders-0")])).await;
+
+        assert_eq!(
+            heartbeat(&mut client, &a, 1).await.error_code,
+            ErrorCode::NONE
+        );
+        assert_eq!(
+            heartbeat(&mut client, &a, 0).await.error_code,
+            ErrorCode::ILLEGAL_GENERATION
+        );
+        assert_eq!(
+            heartbeat(&mut client, "ghost", 1).await.error_code,
+            ErrorCode::UNKNOWN_MEMBER_ID
+        );
+
+        let request = LeaveGroupRequest {
+            group_id: "billing".to_string(),
+            members: [a.as_str(), "ghost"]
+                .into_iter()
+                .map(|member_id| LeavingMember {
+                    member_id: member_id.to_string(),
+                    group_instance_id: None,
+                    reason: Some("shutdown".to_string()),
+                })
+                .collect(),
+        };
+        send_request(
+            &mut client,
+            "test-client",
+            ApiKey::LeaveGroup.code(),
+            5,
+            &request,
+        )
+        .await;
+        let left = LeaveGroupResponse::decode(&mut read_body(&mut client).await, 5).unwrap();
+        assert_eq!(left.error_code, ErrorCode::NONE);
+        let errors: Vec<_> = left
+            .members
+            .iter()
+            .map(|member| (member.member_id.as_str(), member.error_code))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (a.as_str(), ErrorCode::NONE),
+                ("ghost", ErrorCode::UNKNOWN_MEMBER_ID)
+            ]
+        );
+        assert_eq!(
+            broker.groups.group_phase("billing"),
+            Some((GroupPhase::Empty, 2))
+        );
+        assert_eq!(
+            heartbeat(&mut client, &a, 1).await.error_code,
+            ErrorCode::UNKNOWN_MEMBER_ID
+        );
+    }
+
+    fn offset_commit_request(
+        generation_id: i32,
+        member_id: &str,
+        partitions: &[(i32, i64, &str)],
+    ) -> OffsetCommitRequest {
+        OffsetCommitRequest {
+            group_id: "billing".to_string(),
+            generation_id,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+            retention_time_ms: -1,
+            topics: vec![OffsetCommitTopic {
+                name: "orders".to_string(),
+                partitions: partitions
+                    .iter()
+                    .map(
+                        |&(partition_index, committed_offset, metadata)| OffsetCommitPartition {
+                            partition_index,
+                            committed_offset,
+                            committed_leader_epoch: -1,
+                            committed_metadata: Some(metadata.to_string()),
+                        },
+                    )
+                    .collect(),
+            }],
+        }
+    }
+
+    /// Commits with OffsetCommit v8, returning each partition's error
+    async fn offset_commit(
+        client: &mut DuplexStream,
+        request: &OffsetCommitRequest,
+    ) -> Vec<(i32, ErrorCode)> {
+        send_request(
+            client,
+            "test-client",
+            ApiKey::OffsetCommit.code(),
+            8,
+            request,
+        )
+        .await;
+        let response = OffsetCommitResponse::decode(&mut read_body(client).await, 8).unwrap();
+        response.topics[0]
+            .partitions
+            .iter()
+            .map(|partition| (partition.partition_index, partition.error_code))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_offset_commit_stores_offsets() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 2, BTreeMap::new())
+            .unwrap();
+        let (mut client, _) = spawn_connection_with(Arc::clone(&broker));
+
+        let oversized = "x".repeat(MAX_OFFSET_METADATA_BYTES + 1);
+        let request = offset_commit_request(
+            SIMPLE_COMMIT_GENERATION,
+            "",
+            &[
+                (0, 12, "first"),
+                (1, 30, ""),
+                (2, 5, ""),
+                (0, 14, &oversized),
+            ],
+        );
+        assert_eq!(
+            offset_commit(&mut client, &request).await,
+            [
+                (0, ErrorCode::NONE),
+                (1, ErrorCode::NONE),
+                (2, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION),
+                (0, ErrorCode::OFFSET_METADATA_TOO_LARGE),
+            ]
+        );
+
+        let committed = |partition| {
+            broker
+                .offsets
+                .committed("billing", "orders", partition)
+                .map(|committed| (committed.offset, committed.metadata))
+        };
+        assert_eq!(committed(0), Some((12, "first".to_string())));
+        assert_eq!(committed(1), Some((30, String::new())));
+        assert_eq!(committed(2), None);
+    }
+
+    async fn offset_fetch(
+        client: &mut DuplexStream,
+        version: i16,
+        groups: Vec<OffsetFetchRequestGroup>,
+    ) -> Vec<OffsetFetchResponseGroup> {
+        let request = OffsetFetchRequest {
+            groups,
+            require_stable: false,
+        };
+        send_request(
+            client,
+            "test-client",
+            ApiKey::OffsetFetch.code(),
+            version,
+            &request,
+        )
+        .await;
+        OffsetFetchResponse::decode(&mut read_body(client).await, version)
+            .unwrap()
+            .groups
+    }
+
+    #[tokio::test]
+    async fn test_offset_fetch_reads_back_commits() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 3, BTreeMap::new())
+            .unwrap();
+        let (mut client, _) = spawn_connection_with(Arc::clone(&broker));
+        let request = offset_commit_request(
+            SIMPLE_COMMIT_GENERATION,
+            "",
+            &[(0, 12, "first"), (1, 30, "second")],
+        );
+        offset_commit(&mut client, &request).await;
+        let offsets = |group: &OffsetFetchResponseGroup| -> Vec<_> {
+            group
+                .topics
+                .iter()
+                .flat_map(|topic| {
+                    topic.partitions.iter().map(|partition| {
+                        (
+                            topic.name.clone(),
+                            partition.partition_index,
+                            partition.committed_offset,
+                            partition.metadata.clone().unwrap_or_default(),
+                            partition.error_code,
+                        )
+                    })
+                })
+                .collect()
+        };
+        let orders = |partition, offset, metadata: &str| {
+            (
+                "orders".to_string(),
+                partition,
+                offset,
+                metadata.to_string(),
+                ErrorCode::NONE,
+            )
+        };
+
+        // Explicit partitions, one of them never committed
+        let groups = offset_fetch(
+            &mut client,
+            7,
+            vec![OffsetFetchRequestGroup {
+                group_id: "billing".to_string(),
+                topics: Some(vec![OffsetFetchRequestTopic {
+                    name: "orders".to_string(),
+                    partition_indexes: vec![0, 1, 2],
+                }]),
+            }],
+        )
+        .await;
+        assert_eq!(groups[0].error_code, ErrorCode::NONE);
+        assert_eq!(
+            offsets(&groups[0]),
+            [
+                orders(0, 12, "first"),
+                orders(1, 30, "second"),
+                orders(2, -1, "")
+            ]
+        );
+
+        // Every committed offset, for a known and an unknown group
+        let all = |group_id: &str| OffsetFetchRequestGroup {
+            group_id: group_id.to_string(),
+            topics: None,
+        };
+        let groups = offset_fetch(&mut client, 8, vec![all("billing"), all("search")]).await;
+        assert_eq!(groups[0].group_id, "billing");
+        assert_eq!(groups[0].error_code, ErrorCode::NONE);
+        assert_eq!(
+            offsets(&groups[0]),
+            [orders(0, 12, "first"), orders(1, 30, "second")]
+        );
+        assert_eq!(groups[1].group_id, "search");
+        assert_eq!(groups[1].error_code, ErrorCode::GROUP_ID_NOT_FOUND);
+        assert!(groups[1].topics.is_empty());
+    }
+
+    async fn sasl_handshake(client: &mut DuplexStream, mechanism: &str) -> SaslHandshakeResponse {
+        // No SaslHandshake version is flexible, so it takes header v1
+        let mut request = RequestHeaderV1::new(ApiKey::SaslHandshake.code(), 1, 13, None)
+            .encode()
+            .unwrap();
+        SaslHandshakeRequest {
+            mechanism: mechanism.to_string(),
+        }
+        .encode_versioned(1, &mut request)
+        .unwrap();
+        client.write_all(&frame(&request)).await.unwrap();
+        let response = read_response(client).await;
+        assert_eq!(&response[0..4], &13i32.to_be_bytes());
+        SaslHandshakeResponse::decode(&mut Bytes::copy_from_slice(&response[4..]), 1).unwrap()
+    }
+
+    async fn sasl_authenticate(
+        client: &mut DuplexStream,
+        token: &[u8],
+    ) -> SaslAuthenticateResponse {
+        let request = SaslAuthenticateRequest {
+            auth_bytes: Bytes::copy_from_slice(token),
+        };
+        send_request(
+            client,
+            "test-client",
+            ApiKey::SaslAuthenticate.code(),
+            2,
+            &request,
+        )
+        .await;
+        SaslAuthenticateResponse::decode(&mut read_body(client).await, 2).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sasl_handshake_with_unknown_mechanism() {
+        let (mut client, _) = spawn_connection();
+        let response = sasl_handshake(&mut client, "SCRAM-SHA-512").await;
+        assert_eq!(response.error_code, ErrorCode::UNSUPPORTED_SASL_MECHANISM);
+        assert_eq!(response.mechanisms, ["PLAIN"]);
+
+        // A refused mechanism leaves the connection free to pick another
+        let response = sasl_handshake(&mut client, "PLAIN").await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+
+        let sasl = SaslConfig {
+            enabled_mechanisms: vec!["PLAIN".to_string(), "SCRAM-SHA-512".to_string()],
+        };
+        let (mut client, _) = spawn_connection_with(Arc::new(KafkaBroker::new().with_sasl(sasl)));
+        let response = sasl_handshake(&mut client, "SCRAM-SHA-512").await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        assert_eq!(response.mechanisms, ["PLAIN", "SCRAM-SHA-512"]);
+    }
+
+    #[tokio::test]
+    async fn test_sasl_authenticate_requires_handshake() {
+        let (mut client, _) = spawn_connection();
+        let response = sasl_authenticate(&mut client, b"\0alice\0secret").await;
+        assert_eq!(response.error_code, ErrorCode::ILLEGAL_SASL_STATE);
+
+        assert_eq!(
+            sasl_handshake(&mut client, "PLAIN").await.error_code,
+            ErrorCode::NONE
+        );
+        let response = sasl_authenticate(&mut client, b"alice:secret").await;
+        assert_eq!(response.error_code, ErrorCode::SASL_AUTHENTICATION_FAILED);
+        let response = sasl_authenticate(&mut client, b"\0alice\0secret").await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        assert_eq!(response.error_message, None);
+
+        // Once authenticated the connection cannot start over
+        assert_eq!(
+            sasl_handshake(&mut client, "PLAIN").await.error_code,
+            ErrorCode::ILLEGAL_SASL_STATE
+        );
+        let response = sasl_authenticate(&mut client, b"\0alice\0secret").await;
+        assert_eq!(response.error_code, ErrorCode::ILLEGAL_SASL_STATE);
+
+        // The handshake is per connection
+        let (mut other, _) = spawn_connection();
+        let response = sasl_authenticate(&mut other, b"\0alice\0secret").await;
+        assert_eq!(response.error_code, ErrorCode::ILLEGAL_SASL_STATE);
+    }
+
+    fn init_producer_id_request(transactional_id: Option<&str>) -> InitProducerIdRequest {
+        InitProducerIdRequest {
+            transactional_id: transactional_id.map(str::to_string),
+            transaction_timeout_ms: 60_000,
+            producer_id: NO_PRODUCER_ID,
+            producer_epoch: NO_PRODUCER_EPOCH,
+        }
+    }
+
+    async fn init_producer_id(
+        client: &mut DuplexStream,
+        request: &InitProducerIdRequest,
+    ) -> InitProducerIdResponse {
+        send_request(
+            client,
+            "producer",
+            ApiKey::InitProducerId.code(),
+            4,
+            request,
+        )
+        .await;
+        InitProducerIdResponse::decode(&mut read_body(client).await, 4).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_init_producer_id_is_unique_across_connections() {
+        let broker = Arc::new(KafkaBroker::new());
+        let producers = (0..10).map(|_| {
+            let broker = Arc::clone(&broker);
+            tokio::spawn(async move {
+                let (mut client, _) = spawn_connection_with(broker);
+                init_producer_id(&mut client, &init_producer_id_request(None)).await
+            })
+        });
+        let mut ids = Vec::new();
+        for producer in producers.collect::<Vec<_>>() {
+            let response = producer.await.unwrap();
+            assert_eq!(response.error_code, ErrorCode::NONE);
+            assert_eq!(response.producer_epoch, 0);
+            ids.push(response.producer_id);
+        }
+        ids.sort();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_init_producer_id_resumes_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let start = || {
+            let producer_ids = ProducerIdManager::load(dir.path()).unwrap();
+            spawn_connection_with(Arc::new(KafkaBroker::new().with_producer_ids(producer_ids)))
+        };
+
+        let (mut client, _) = start();
+        for expected in 0..3 {
+            let response = init_producer_id(&mut client, &init_producer_id_request(None)).await;
+            assert_eq!(response.producer_id, expected);
+        }
+        drop(client);
+
+        let (mut client, _) = start();
+        let response = init_producer_id(&mut client, &init_producer_id_request(None)).await;
+        assert_eq!(response.producer_id, 3);
+    }
+
+    #[tokio::test]
+    async fn test_init_producer_id_refuses_transactions() {
+        let (mut client, _) = spawn_connection();
+        let response = init_producer_id(&mut client, &init_producer_id_request(Some("tx-1"))).await;
+        assert_eq!(response.error_code, ErrorCode::COORDINATOR_NOT_AVAILABLE);
+        assert_eq!(response.producer_id, NO_PRODUCER_ID);
+
+        let request = InitProducerIdRequest {
+            producer_id: 7,
+            ..init_producer_id_request(None)
+        };
+        let response = init_producer_id(&mut client, &request).await;
+        assert_eq!(response.error_code, ErrorCode::INVALID_REQUEST);
+
+        // Re-initializing needs an id the broker handed out
+        let request = InitProducerIdRequest {
+            producer_id: 7,
+            producer_epoch: 3,
+            ..init_producer_id_request(None)
+        };
+        let response = init_producer_id(&mut client, &request).await;
+        assert_eq!(response.error_code, ErrorCode::INVALID_PRODUCER_ID_MAPPING);
+    }
+
+    #[tokio::test]
+    async fn test_init_producer_id_bumps_the_epoch_of_a_known_producer() {
+        let (mut client, _) = spawn_connection();
+        let fresh = init_producer_id(&mut client, &init_producer_id_request(None)).await;
+        assert_eq!((fresh.producer_id, fresh.producer_epoch), (0, 0));
+
+        let reinit = InitProducerIdRequest {
+            producer_id: fresh.producer_id,
+            producer_epoch: fresh.producer_epoch,
+            ..init_producer_id_request(None)
+        };
+        let bumped = init_producer_id(&mut client, &reinit).await;
+        assert_eq!(bumped.error_code, ErrorCode::NONE);
+        assert_eq!((bumped.producer_id, bumped.producer_epoch), (0, 1));
+        // A retry of the same bump gets the same epoch
+        let retried = init_producer_id(&mut client, &reinit).await;
+        assert_eq!((retried.producer_id, retried.producer_epoch), (0, 1));
+
+        // Once bumped past it, the old epoch is fenced
+        let bump_again = InitProducerIdRequest {
+            producer_epoch: 1,
+            ..reinit.clone()
+        };
+        init_producer_id(&mut client, &bump_again).await;
+        let stale = init_producer_id(&mut client, &reinit).await;
+        assert_eq!(stale.error_code, ErrorCode::INVALID_PRODUCER_EPOCH);
+    }
+
+    async fn describe_configs(
+        client: &mut DuplexStream,
+        resources: Vec<DescribeConfigsResource>,
+    ) -> DescribeConfigsResponse {
+        let request = DescribeConfigsRequest {
+            resources,
+            include_synonyms: true,
+            include_documentation: false,
+        };
+        send_request(client, "admin", ApiKey::DescribeConfigs.code(), 4, &request).await;
+        DescribeConfigsResponse::decode(&mut read_body(client).await, 4).unwrap()
+    }
+
+    fn config_resource(
+        resource_type: i8,
+        name: &str,
+        keys: Option<&[&str]>,
+    ) -> DescribeConfigsResource {
+        DescribeConfigsResource {
+            resource_type,
+            resource_name: name.to_string(),
+            configuration_keys: keys.map(|keys| keys.iter().map(|key| key.to_string()).collect()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_describe_configs_of_topics_and_broker() {
+        let properties = [("num.partitions".to_string(), "3".to_string())];
+        let broker = KafkaBroker::new().with_config(BrokerConfig::from_properties(&properties));
+        let configs = BTreeMap::from([
+            ("retention.ms".to_string(), "1000".to_string()),
+            ("sasl.jaas.config".to_string(), "secret".to_string()),
+        ]);
+        broker.topics().create_topic("orders", 1, configs).unwrap();
+        let (mut client, _) = spawn_connection_with(Arc::new(broker));
+
+        let keys = ["retention.ms", "sasl.jaas.config", "segment.bytes"];
+        let response = describe_configs(
+            &mut client,
+            vec![
+                config_resource(RESOURCE_TYPE_TOPIC, "orders", Some(&keys)),
+                config_resource(RESOURCE_TYPE_TOPIC, "missing", None),
+                config_resource(RESOURCE_TYPE_BROKER, "1", Some(&["num.partitions"])),
+            ],
+        )
+        .await;
+        let [orders, missing, broker] = response.results.as_slice() else {
+            panic!("unexpected results {:?}", response.results);
+        };
+
+        assert_eq!(orders.error_code, ErrorCode::NONE);
+        let described: Vec<_> = orders
+            .configs
+            .iter()
+            .map(|c| {
+                (
+                    c.name.as_str(),
+                    c.value.as_deref(),
+                    c.config_source,
+                    c.is_sensitive,
+                )
+            })
+            .collect();
+        assert_eq!(
+            described,
+            [
+                (
+                    "retention.ms",
+                    Some("1000"),
+                    CONFIG_SOURCE_DYNAMIC_TOPIC,
+                    false
+                ),
+                ("sasl.jaas.config", None, CONFIG_SOURCE_DYNAMIC_TOPIC, true),
+                (
+                    "segment.bytes",
+                    Some("1073741824"),
+                    CONFIG_SOURCE_DEFAULT,
+                    false
+                ),
+            ]
+        );
+        let synonyms: Vec<_> = orders.configs[0]
+            .synonyms
+            .iter()
+            .map(|s| (s.value.as_deref(), s.source))
+            .collect();
+        assert_eq!(
+            synonyms,
+            [
+                (Some("1000"), CONFIG_SOURCE_DYNAMIC_TOPIC),
+                (Some("604800000"), CONFIG_SOURCE_DEFAULT),
+            ]
+        );
+        assert_eq!(orders.configs[0].config_type, CONFIG_TYPE_LONG);
+
+        assert_eq!(missing.error_code, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION);
+        assert!(missing.configs.is_empty());
+
+        assert_eq!(broker.error_code, ErrorCode::NONE);
+        assert_eq!(broker.configs.len(), 1);
+        assert_eq!(broker.configs[0].value.as_deref(), Some("3"));
+        assert_eq!(broker.configs[0].config_source, CONFIG_SOURCE_STATIC_BROKER);
+        assert!(broker.configs[0].read_only);
+
+        let response = describe_configs(
+            &mut client,
+            vec![
+                config_resource(RESOURCE_TYPE_BROKER, "2", None),
+                config_resource(RESOURCE_TYPE_BROKER, "", None),
+            ],
+        )
+        .await;
+        assert_eq!(response.results[0].error_code, ErrorCode::INVALID_REQUEST);
+        assert_eq!(response.results[1].error_code, ErrorCode::NONE);
+        assert!(response.results[1].configs.is_empty());
+    }
+
+    async fn alter_configs(
+        client: &mut DuplexStream,
+        topic: &str,
+        changes: &[(&str, ConfigOperation, Option<&str>)],
+        validate_only: bool,
+    ) -> AlterConfigsResourceResponse {
+        let request = IncrementalAlterConfigsRequest {
+            resources: vec![AlterConfigsResource {
+                resource_type: RESOURCE_TYPE_TOPIC,
+                resource_name: topic.to_string(),
+                configs: changes
+                    .iter()
+                    .map(|&(name, config_operation, value)| AlterableConfig {
+                        name: name.to_string(),
+                        config_operation,
+                        value: value.map(str::to_string),
+                    })
+                    .collect(),
+            }],
+            validate_only,
+        };
+        send_request(
+            client,
+            "admin",
+            ApiKey::IncrementalAlterConfigs.code(),
+            1,
+            &request,
+        )
+        .await;
+        let mut response =
+            IncrementalAlterConfigsResponse::decode(&mut read_body(client).await, 1).unwrap();
+        assert_eq!(response.responses.len(), 1);
+        response.responses.remove(0)
+    }
+
+    /// Effective value and source of one topic config, as DescribeConfigs reports it
+    async fn topic_config(client: &mut DuplexStream, topic: &str, name: &str) -> (String, i8) {
+        let resource = config_resource(RESOURCE_TYPE_TOPIC, topic, Some(&[name]));
+        let response = describe_configs(client, vec![resource]).await;
+        let config = &response.results[0].configs[0];
+        (config.value.clone().unwrap(), config.config_source)
+    }
+
+    #[tokio::test]
+    async fn test_alter_configs_then_describe() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, _) = spawn_connection_with(Arc::clone(&broker));
+        let mut events = broker.events().subscribe("test");
+
+        let set = [
+            ("retention.ms", ConfigOperation::Set, Some("1000")),
+            ("cleanup.policy", ConfigOperation::Set, Some("compact")),
+        ];
+        let response = alter_configs(&mut client, "orders", &set, false).await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        let updated: Vec<_> = std::iter::from_fn(|| events.try_recv())
+            .filter_map(|event| match event {
+                BrokerEvent::ConfigUpdated { resource, key } => Some((resource, key)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            updated,
+            [
+                ("orders".to_string(), "retention.ms".to_string()),
+                ("orders".to_string(), "cleanup.policy".to_string()),
+            ]
+        );
+        assert_eq!(
+            topic_config(&mut client, "orders", "retention.ms").await,
+            ("1000".to_string(), CONFIG_SOURCE_DYNAMIC_TOPIC)
+        );
+        assert_eq!(
+            topic_config(&mut client, "orders", "cleanup.policy").await,
+            ("compact".to_string(), CONFIG_SOURCE_DYNAMIC_TOPIC)
+        );
+
+        let delete = [("retention.ms", ConfigOperation::Delete, None)];
+        let response = alter_configs(&mut client, "orders", &delete, false).await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        assert_eq!(
+            topic_config(&mut client, "orders", "retention.ms").await,
+            ("604800000".to_string(), CONFIG_SOURCE_DEFAULT)
+        );
+        assert_eq!(broker.metadata_epoch().current(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_produce_and_fetch_can_be_disabled_per_topic() {
+        let broker = Arc::new(KafkaBroker::new());
+        let orders = broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+        async fn produce(
+            client: &mut DuplexStream,
+            correlation_id: i32,
+        ) -> ProducePartitionResponse {
+            client
+                .write_all(&flexible_produce_frame(correlation_id, &[("orders", &[0])]))
+                .await
+                .unwrap();
+            let response = read_response(client).await;
+            let mut body = Bytes::copy_from_slice(&response[5..]);
+            let mut decoded = ProduceResponse::decode(&mut body, 9).unwrap();
+            decoded.topics.remove(0).partitions.remove(0)
+        }
+        assert_eq!(produce(&mut client, 1).await.error_code, ErrorCode::NONE);
+
+        let disable = [("produce.enable", ConfigOperation::Set, Some("false"))];
+        let response = alter_configs(&mut client, "orders", &disable, false).await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        let refused = produce(&mut client, 2).await;
+        assert_eq!(refused.error_code, ErrorCode::POLICY_VIOLATION);
+        assert_eq!(
+            refused.error_message.as_deref(),
+            Some("Produce is disabled for topic orders (produce.enable=false)")
+        );
+        assert_eq!(orders.partitions[0].log_end_offset(), 3);
+        // Consumers keep draining the topic
+        let fetched = fetch_from(&broker, 12, vec![fetch_topic("orders", Uuid::nil(), &[0])]).await;
+        assert_eq!(
+            fetched.responses[0].partitions[0].error_code,
+            ErrorCode::NONE
+        );
+
+        // Re-enabled on the same connection
+        let enable = [("produce.enable", ConfigOperation::Delete, None)];
+        alter_configs(&mut client, "orders", &enable, false).await;
+        assert_eq!(produce(&mut client, 3).await.error_code, ErrorCode::NONE);
+
+        let disable = [("fetch.enable", ConfigOperation::Set, Some("false"))];
+        alter_configs(&mut client, "orders", &disable, false).await;
+        let fetched = fetch_from(&broker, 12, vec![fetch_topic("orders", Uuid::nil(), &[0])]).await;
+        assert_eq!(
+            fetched.responses[0].partitions[0].error_code,
+            ErrorCode::POLICY_VIOLATION
+        );
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_alter_configs_refusals_change_nothing() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, _) = spawn_connection_with(Arc::clone(&broker));
+
+        let set = [("retention.ms", ConfigOperation::Set, Some("1000"))];
+        let response = alter_configs(&mut client, "orders", &set, true).await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        assert!(broker
+            .topics()
+            .get_topic("orders")
+            .unwrap()
+            .configs()
+            .is_empty());
+
+        // A refused change fails the whole resource, including valid changes
+        for refused in [
+            ("no.such.key", ConfigOperation::Set, Some("1")),
+            ("retention.ms", ConfigOperation::Set, Some("soon")),
+            ("cleanup.policy", ConfigOperation::Append, Some("compact")),
+            ("cleanup.policy", ConfigOperation::Unknown(7), None),
+        ] {
+            let changes = [set[0], refused];
+            let response = alter_configs(&mut client, "orders", &changes, false).await;
+            assert_eq!(
+                response.error_code,
+                ErrorCode::INVALID_CONFIG,
+                "{:?}",
+                refused
+            );
+        }
+        assert!(broker
+            .topics()
+            .get_topic("orders")
+            .unwrap()
+            .configs()
+            .is_empty());
+
+        let response = alter_configs(&mut client, "missing", &set, false).await;
+        assert_eq!(response.error_code, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION);
+        assert_eq!(broker.metadata_epoch().current(), 5);
+    }
+
+    async fn describe_cluster(
+        client: &mut DuplexStream,
+        endpoint_type: i8,
+    ) -> DescribeClusterResponse {
+        let request = DescribeClusterRequest {
+            include_cluster_authorized_operations: false,
+            endpoint_type,
+        };
+        send_request(client, "admin", ApiKey::DescribeCluster.code(), 1, &request).await;
+        DescribeClusterResponse::decode(&mut read_body(client).await, 1).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_describe_cluster_lists_this_broker() {
+        let broker = KafkaBroker::new()
+            .with_cluster_id("MkU3OEVBNTcwNTJENDM2Qk")
+            .with_node_id(3)
+            .with_rack("rack-a");
+        let (mut client, _) = spawn_connection_with(Arc::new(broker));
+        let response = describe_cluster(&mut client, ENDPOINT_TYPE_BROKERS).await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+        assert_eq!(response.cluster_id, "MkU3OEVBNTcwNTJENDM2Qk");
+        assert_eq!(response.controller_id, 3);
+        assert_eq!(
+            response.brokers,
+            [DescribeClusterBroker {
+                broker_id: 3,
+                host: DEFAULT_LISTEN_ADDR.ip().to_string(),
+                port: i32::from(DEFAULT_LISTEN_ADDR.port()),
+                rack: Some("rack-a".to_string()),
+            }]
+        );
+
+        let response = describe_cluster(&mut client, ENDPOINT_TYPE_CONTROLLERS).await;
+        assert_eq!(response.error_code, ErrorCode::MISMATCHED_ENDPOINT_TYPE);
+        assert!(response.brokers.is_empty());
+        let response = describe_cluster(&mut client, 9).await;
+        assert_eq!(response.error_code, ErrorCode::UNSUPPORTED_ENDPOINT_TYPE);
+        assert_eq!(response.endpoint_type, 9);
+    }
+
+    #[tokio::test]
+    async fn test_offset_commit_checks_membership() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, _) = spawn_connection_with(Arc::clone(&broker));
+        let a = member_id_for(&mut client, "consumer-a").await;
+        join_group(&mut client, "consumer-a", &join_request(&a)).await;
+        sync_group(&mut client, &sync_request(&a, 1, &[(&a, "orders-0")])).await;
+
+        let stale = offset_commit_request(0, &a, &[(0, 7, "")]);
+        assert_eq!(
+            offset_commit(&mut client, &stale).await,
+            [(0, ErrorCode::ILLEGAL_GENERATION)]
+        );
+        let stranger = offset_commit_request(1, "ghost", &[(0, 7, "")]);
+        assert_eq!(
+            offset_commit(&mut client, &stranger).await,
+            [(0, ErrorCode::UNKNOWN_MEMBER_ID)]
+        );
+        assert_eq!(broker.offsets.committed("billing", "orders", 0), None);
+
+        let current = offset_commit_request(1, &a, &[(0, 7, "")]);
+        assert_eq!(
+            offset_commit(&mut client, &current).await,
+            [(0, ErrorCode::NONE)]
+        );
+        assert_eq!(
+            broker
+                .offsets
+                .committed("billing", "orders", 0)
+                .map(|committed| committed.offset),
+            Some(7)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dump_covers_partitions_groups_and_producers() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, _) = spawn_connection_with(Arc::clone(&broker));
+        client
+            .write_all(&flexible_produce_frame(1, &[("orders", &[0])]))
+            .await
+            .unwrap();
+        read_response(&mut client).await;
+        let a = member_id_for(&mut client, "consumer-a").await;
+        join_group(&mut client, "consumer-a", &join_request(&a)).await;
+        sync_group(&mut client, &sync_request(&a, 1, &[(&a, "orders-0")])).await;
+        offset_commit(&mut client, &offset_commit_request(1, &a, &[(0, 3, "")])).await;
+        broker.producer_ids.init_producer_id(-1, -1).unwrap();
+
+        let snapshot = broker.dump();
+        let orders: Vec<_> = snapshot
+            .partitions
+            .iter()
+            .filter(|partition| partition.log.topic == "orders")
+            .collect();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].log.high_watermark, 3);
+        assert_eq!(snapshot.groups.len(), 1);
+        assert_eq!(snapshot.groups[0].phase, GroupPhase::Stable);
+        assert_eq!(snapshot.groups[0].members, vec![a]);
+        assert_eq!(snapshot.committed_offsets.len(), 1);
+        assert_eq!(snapshot.committed_offsets[0].committed.offset, 3);
+        assert_eq!(snapshot.producer_ids.next_id, 1);
+        assert!(snapshot.inconsistencies().is_empty());
+
+        // An offset committed past what the partition holds is caught
+        broker
+            .offsets
+            .commit("billing", "orders", 0, 5, -1, None)
+            .unwrap();
+        assert_eq!(
+            broker.dump().inconsistencies(),
+            vec!["orders-0: group 'billing' committed offset 5 past high watermark 3"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retention_uses_topic_then_broker_setting() {
+        use crate::kafka::storage::partition_log::BatchTimestamp;
+
+        let properties = [("log.retention.ms".to_string(), "0".to_string())];
+        let broker = KafkaBroker::new().with_config(BrokerConfig::from_properties(&properties));
+        let create = |name: &str, configs: &[(&str, &str)]| {
+            let configs = configs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let topic = broker.topics().create_topic(name, 1, configs).unwrap();
+            let log = &topic.partitions[0];
+            // Stamped ten minutes ahead of the broker's clock
+            let stamped = SystemClock.now_ms() + 600_000;
+            log.append_batch(
+                Bytes::from_static(b"x"),
+                1,
+                BatchTimestamp::CreateTime(stamped),
+            );
+            log.advance_high_watermark(log.log_end_offset());
+            topic
+        };
+        // The timestamp is trusted, so the batch is not old yet
+        let future = create("future", &[]);
+        // The timestamp is not trusted, and the append time is past retention
+        let untrusted = create("untrusted", &[("message.timestamp.after.max.ms", "0")]);
+        // The topic's own retention wins over the broker's
+        let kept = create(
+            "kept",
+            &[
+                ("retention.ms", "3600000"),
+                ("message.timestamp.after.max.ms", "0"),
+            ],
+        );
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        assert_eq!(broker.enforce_retention(), 1);
+        assert_eq!(future.partitions[0].log_start_offset(), 0);
+        assert_eq!(untrusted.partitions[0].log_start_offset(), 1);
+        assert_eq!(kept.partitions[0].log_start_offset(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_client_id_answers_invalid_request() {
+        let (mut client, handle) = spawn_connection();
+        let request = RequestHeaderV1::new(ApiKey::Produce.code(), 2, 31, Some("x".repeat(4096)))
+            .encode()
+            .unwrap();
+        client.write_all(&frame(&request)).await.unwrap();
+
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &31i32.to_be_bytes());
+        assert_eq!(
+            &response[4..],
+            &ErrorCode::INVALID_REQUEST.code().to_be_bytes()
+        );
+
+        client.write_all(&api_versions_frame(32)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &32i32.to_be_bytes());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_header_answers_invalid_request() {
+        let (mut client, handle) = spawn_connection();
+        // Metadata v1 whose client id claims 20 bytes but carries 3
+        let mut request = BytesMut::new();
+        request.put_i16(ApiKey::Metadata.code());
+        request.put_i16(1);
+        request.put_i32(51);
+        request.put_i16(20);
+        request.put_slice(b"cli");
+        client.write_all(&frame(&request)).await.unwrap();
+
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &51i32.to_be_bytes());
+        assert_eq!(
+            &response[4..],
+            &ErrorCode::INVALID_REQUEST.code().to_be_bytes()
+        );
+
+        // The connection stays usable
+        client.write_all(&api_versions_frame(52)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &52i32.to_be_bytes());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_versions_of_other_apis_are_framed() {
+        let (mut client, handle) = spawn_connection();
+        // Fetch has no top-level error code before v7
+        for (api_version, correlation_id, answered_at, error_code) in [
+            (999, 41, FETCH_MAX_VERSION, ErrorCode::UNSUPPORTED_VERSION),
+            (-1, 42, FETCH_MIN_VERSION, ErrorCode::NONE),
+        ] {
+            let header = RequestHeaderV2::with_client_id(
+                ApiKey::Fetch.code(),
+                api_version,
+                correlation_id,
+                "test-client",
+            );
+            client
+                .write_all(&frame(&header.encode().unwrap()))
+                .await
+                .unwrap();
+            // An empty Fetch response at the nearest version served, under
+            // the header that version calls for
+            let response = read_response(&mut client).await;
+            assert_eq!(&response[0..4], &correlation_id.to_be_bytes());
+            let header_length = if answered_at >= 12 { 5 } else { 4 };
+            let mut body = Bytes::copy_from_slice(&response[header_length..]);
+            let fetch = FetchResponse::decode(&mut body, answered_at).unwrap();
+            assert_eq!(fetch.error_code, error_code);
+            assert!(fetch.responses.is_empty());
+            assert!(body.is_empty());
+        }
+
+        // The connection stays usable
+        client.write_all(&api_versions_frame(43)).await.unwrap();
+        assert_eq!(
+            &read_response(&mut client).await[0..4],
+            &43i32.to_be_bytes()
+        );
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_control_request_skips_saturated_data_queue() {
+        use crate::kafka::request_queue::RequestQueueConfig;
+
+        let broker = Arc::new(KafkaBroker::new().with_request_queue(RequestQueue::new(
+            RequestQueueConfig {
+                workers: 2,
+                max_consecutive_control: 8,
+            },
+        )));
+        broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        // Each fetch holds a worker for its full second of long-polling
+        let slow_fetch = FetchRequest {
+            cluster_id: None,
+            replica_id: -1,
+            replica_epoch: -1,
+            max_wait_ms: 1_000,
+            min_bytes: 1,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id: 0,
+            session_epoch: -1,
+            topics: vec![fetch_topic("orders", Uuid::nil(), &[0])],
+            forgotten_topics_data: Vec::new(),
+            rack_id: String::new(),
+        };
+        let mut fetchers = Vec::new();
+        for _ in 0..6 {
+            let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+            send_request(
+                &mut client,
+                "fetcher",
+                ApiKey::Fetch.code(),
+                12,
+                &slow_fetch,
+            )
+            .await;
+            fetchers.push((client, handle));
+        }
+        while broker.request_queue().metrics().data.depth < 4 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // Behind the four queued fetches it would wait three seconds; ahead
+        // of them it takes the first worker freed
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+        let started = Instant::now();
+        let mut request = RequestHeaderV0::new(ApiKey::ControlledShutdown.code(), 0, 21)
+            .encode()
+            .unwrap();
+        request.put_i32(1);
+        client.write_all(&frame(&request)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &21i32.to_be_bytes());
+        assert!(started.elapsed() < Duration::from_millis(1_800));
+
+        let metrics = broker.request_queue().metrics();
+        assert_eq!(metrics.control.dispatched, 1);
+        assert!(metrics.control.max_wait_ms < 1_800);
+        assert!(metrics.data.depth > 0);
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        for (mut client, handle) in fetchers {
+            read_body(&mut client).await;
+            drop(client);
+            assert!(handle.await.unwrap().is_ok());
+        }
+        assert_eq!(broker.request_queue().metrics().data.dispatched, 6);
+    }
+
+    #[tokio::test]
+    async fn test_header_v0_request_is_answered() {
+        let (mut client, handle) = spawn_connection();
+        // ControlledShutdown v0 has no client id; the broker id follows
+        let mut request = RequestHeaderV0::new(ApiKey::ControlledShutdown.code(), 0, 21)
+            .encode()
+            .unwrap();
+        request.put_i32(1);
+        client.write_all(&frame(&request)).await.unwrap();
+
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &21i32.to_be_bytes());
+        assert_eq!(
+            &response[4..],
+            &ErrorCode::UNSUPPORTED_VERSION.code().to_be_bytes()
+        );
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_decode_limit_answers_invalid_request() {
+        let (mut client, handle) = spawn_connection();
+        let mut request = BytesMut::new();
+        RequestHeaderV2::with_client_id(0, 2, 11, "test-client")
+            .encode_versioned(1, &mut request)
+            .unwrap();
+        // acks, timeout, one topic with one partition claiming 2GB of records
+        request.put_i16(1);
+        request.put_i32(1000);
+        request.put_i32(1);
+        WireFormat::encode_string(&mut request, "orders").unwrap();
+        request.put_i32(1);
+        request.put_i32(0);
+        request.put_i32(i32::MAX);
+        client.write_all(&frame(&request)).await.unwrap();
+
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &11i32.to_be_bytes());
+        assert_eq!(
+            &response[4..],
+            &ErrorCode::INVALID_REQUEST.code().to_be_bytes()
+        );
+
+        // The connection stays usable
+        client.write_all(&api_versions_frame(12)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &12i32.to_be_bytes());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_api_versions_served_from_response_cache() {
+        let broker = Arc::new(KafkaBroker::new());
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+
+        let mut first_body = None;
+        for correlation_id in 0..100 {
+            client
+                .write_all(&api_versions_frame(correlation_id))
+                .await
+                .unwrap();
+            let response = read_response(&mut client).await;
+            assert_eq!(&response[0..4], &correlation_id.to_be_bytes());
+            let body = first_body.get_or_insert_with(|| response[4..].to_vec());
+            assert_eq!(&response[4..], &body[..]);
+        }
+        let metrics = broker.response_cache().metrics();
+        assert_eq!((metrics.misses, metrics.hits), (1, 99));
+
+        broker.events().publish(BrokerEvent::TopicCreated {
+            topic: "orders".to_string(),
+            partitions: 1,
+        });
+        client.write_all(&api_versions_frame(100)).await.unwrap();
+        read_response(&mut client).await;
+        assert_eq!(broker.response_cache().metrics().misses, 2);
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_request_frame_limit_comes_from_limits() {
+        let properties = vec![("socket.request.max.bytes".to_string(), "16".to_string())];
+        let limits = Arc::new(Limits::from_properties(&properties).unwrap());
+        let broker = Arc::new(KafkaBroker::new().with_limits(limits));
+        let (mut client, handle) = spawn_connection_with(broker);
+
+        // The ApiVersions frame is larger than 16 bytes
+        client.write_all(&api_versions_frame(1)).await.unwrap();
+        assert!(handle.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_frame_delivered_one_byte_at_a_time() {
+        let (mut client, handle) = spawn_connection();
+
+        for byte in api_versions_frame(42) {
+            client.write_all(&[byte]).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &42i32.to_be_bytes());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_after_length_prefix() {
+        let (mut client, handle) = spawn_connection();
+
+        client.write_all(&16u32.to_be_bytes()).await.unwrap();
+        drop(client);
+
+        assert!(handle.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_mid_body() {
+        let (mut client, handle) = spawn_connection();
+
+        let frame = api_versions_frame(1);
+        client.write_all(&frame[..frame.len() - 3]).await.unwrap();
+        drop(client);
+
+        assert!(handle.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_clean_disconnect_between_frames() {
+        let (client, handle) = spawn_connection();
+        drop(client);
+
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    fn produce_frame(version: i16, acks: i16, correlation_id: i32) -> Vec<u8> {
+        produce_frame_with_timeout(version, acks, correlation_id, 1000)
+    }
+
+    fn produce_frame_with_timeout(
+        version: i16,
+        acks: i16,
+        correlation_id: i32,
+        timeout_ms: i32,
+    ) -> Vec<u8> {
+        use crate::protocol::message_set::{encode_message_set, LegacyMessage};
+        use crate::protocol::produce::{ProducePartitionData, ProduceTopicData};
+
+        let records = encode_message_set(&[LegacyMessage {
+            offset: 0,
+            magic: 0,
+            attributes: 0,
+            timestamp: None,
+            key: None,
+            value: Some(bytes::Bytes::from_static(b"hello")),
+        }])
+        .unwrap();
+        let request = ProduceRequest {
+            transactional_id: None,
+            acks,
+            timeout_ms,
+            topics: vec![ProduceTopicData {
+                name: "test".to_string(),
+                partitions: vec![ProducePartitionData {
+                    index: 0,
+                    records: Some(records.freeze()),
+                }],
+            }],
+        };
+
+        let mut buffer = BytesMut::new();
+        buffer.put_i16(0);
+        buffer.put_i16(version);
+        buffer.put_i32(correlation_id);
+        WireFormat::encode_nullable_string(&mut buffer, Some("legacy-client")).unwrap();
+        buffer.extend_from_slice(&request.encode(version).unwrap());
+        frame(&buffer)
+    }
+
+    #[tokio::test]
+    async fn test_legacy_message_set_is_stored_as_a_record_batch() {
+        let broker = Arc::new(KafkaBroker::new());
+        let test = broker
+            .topics()
+            .create_topic("test", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+
+        // Produce v0 and v2 both carry legacy message sets
+        for (version, base_offset) in [(0, 0), (2, 1)] {
+            client
+                .write_all(&produce_frame(version, 1, 7))
+                .await
+                .unwrap();
+            let response = read_response(&mut client).await;
+            let mut body = Bytes::copy_from_slice(&response[4..]);
+            let decoded = ProduceResponse::decode(&mut body, version).unwrap();
+            let partition = &decoded.topics[0].partitions[0];
+            assert_eq!(partition.error_code, ErrorCode::NONE);
+            assert_eq!(partition.base_offset, base_offset);
+        }
+
+        let log = &test.partitions[0];
+        assert_eq!(log.high_watermark(), 2);
+        let stored = log.read_from(0, usize::MAX).unwrap();
+        let records: Vec<_> = stored
+            .iter()
+            .flat_map(|batch| {
+                split_record_batches(&batch.data).unwrap()[0]
+                    .records()
+                    .unwrap()
+            })
+            .map(|record| (record.offset, record.timestamp, record.value))
+            .collect();
+        let hello = Some(Bytes::from_static(b"hello"));
+        assert_eq!(
+            records,
+            [(0, NO_TIMESTAMP, hello.clone()), (1, NO_TIMESTAMP, hello)]
+        );
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_produce_v0_gets_old_shape_response() {
+        let (mut client, handle) = spawn_connection();
+
+        client.write_all(&produce_frame(0, 1, 3)).await.unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &3i32.to_be_bytes());
+
+        let mut body = Bytes::copy_from_slice(&response[4..]);
+        let decoded = ProduceResponse::decode(&mut body, 0).unwrap();
+        assert!(body.is_empty());
+        let partition = &decoded.topics[0].partitions[0];
+        assert_eq!(partition.error_code, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION);
+        assert_eq!(partition.base_offset, -1);
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    /// Produce v9 frame with one v2 record batch per partition
+    fn flexible_produce_frame(correlation_id: i32, topics: &[(&str, &[i32])]) -> Vec<u8> {
+        use crate::protocol::record_batch::encode_test_batch;
+
+        flexible_produce_frame_with(correlation_id, topics, encode_test_batch(3, 0))
+    }
+
+    /// Produce v9 frame with `records` for every partition
+    fn flexible_produce_frame_with(
+        correlation_id: i32,
+        topics: &[(&str, &[i32])],
+        records: Bytes,
+    ) -> Vec<u8> {
+        use crate::protocol::produce::{ProducePartitionData, ProduceTopicData};
+
+        let header = RequestHeaderV2::with_client_id(
+            ApiKey::Produce.code(),
+            9,
+            correlation_id,
+            "test-client",
+        );
+        let mut request = header.encode().unwrap();
+        ProduceRequest {
+            transactional_id: None,
+            acks: 1,
+            timeout_ms: 1000,
+            topics: topics
+                .iter()
+                .map(|(name, partitions)| ProduceTopicData {
+                    name: name.to_string(),
+                    partitions: partitions
+                        .iter()
+                        .map(|&index| ProducePartitionData {
+                            index,
+                            records: Some(records.clone()),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+        .encode_versioned(9, &mut request)
+        .unwrap();
+        frame(&request)
+    }
+
+    #[tokio::test]
+    async fn test_flexible_produce_two_topics_three_partitions() {
+        let (mut client, handle) = spawn_connection();
+
+        client
+            .write_all(&flexible_produce_frame(
+                8,
+                &[("orders", &[0, 1]), ("payments", &[2])],
+            ))
+            .await
+            .unwrap();
+        let response = read_response(&mut client).await;
+        // Response header v1: correlation id and an empty tag section
+        assert_eq!(&response[0..5], &[0, 0, 0, 8, 0]);
+
+        let mut body = Bytes::copy_from_slice(&response[5..]);
+        let decoded = ProduceResponse::decode(&mut body, 9).unwrap();
+        assert!(body.is_empty());
+        let partitions: Vec<_> = decoded
+            .topics
+            .iter()
+            .flat_map(|topic| {
+                topic
+                    .partitions
+                    .iter()
+                    .map(move |partition| (topic.name.as_str(), partition.index))
+            })
+            .collect();
+        assert_eq!(partitions, [("orders", 0), ("orders", 1), ("payments", 2)]);
+        for partition in decoded.topics.iter().flat_map(|topic| &topic.partitions) {
+            assert_eq!(partition.error_code, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION);
+        }
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_produce_appends_to_partition_log() {
+        let broker = Arc::new(KafkaBroker::new());
+        let orders = broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+
+        let mut produced = Vec::new();
+        for correlation_id in [1, 2] {
+            // Partition 5 does not exist and fails on its own
+            client
+                .write_all(&flexible_produce_frame(
+                    correlation_id,
+                    &[("orders", &[0, 5])],
+                ))
+                .await
+                .unwrap();
+            let response = read_response(&mut client).await;
+            let mut body = Bytes::copy_from_slice(&response[5..]);
+            let decoded = ProduceResponse::decode(&mut body, 9).unwrap();
+            let partitions = &decoded.topics[0].partitions;
+            assert_eq!(
+                partitions[1].error_code,
+                ErrorCode::UNKNOWN_TOPIC_OR_PARTITION
+            );
+            assert_eq!(partitions[0].error_code, ErrorCode::NONE);
+            assert_eq!(partitions[0].log_append_time_ms, -1);
+            assert_eq!(partitions[0].log_start_offset, 0);
+            produced.push(partitions[0].base_offset);
+        }
+        // Each batch holds three records
+        assert_eq!(produced, [0, 3]);
+
+        let log = &orders.partitions[0];
+        assert_eq!(log.log_end_offset(), 6);
+        assert_eq!(log.high_watermark(), 6);
+        let stored = log.read_from(0, usize::MAX).unwrap();
+        let base_offsets: Vec<_> = stored
+            .iter()
+            .map(|batch| split_record_batches(&batch.data).unwrap()[0].base_offset())
+            .collect();
+        assert_eq!(base_offsets, [0, 3]);
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_produced_records_survive_a_restart() {
+        use crate::kafka::metadata::{ClusterPartition, ClusterTopic};
+
+        let log_dir = tempfile::tempdir().unwrap();
+        let broker = Arc::new(
+            KafkaBroker::new()
+                .with_log_dir(log_dir.path())
+                .with_log_config(LogConfig {
+                    segment_bytes: 1,
+                    ..LogConfig::default()
+                }),
+        );
+        let orders = broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+        for correlation_id in [1, 2] {
+            client
+                .write_all(&flexible_produce_frame(correlation_id, &[("orders", &[0])]))
+                .await
+                .unwrap();
+            read_response(&mut client).await;
+        }
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+        // Every batch overflows a one-byte segment
+        assert!(log_dir
+            .path()
+            .join("orders-0/00000000000000000003.log")
+            .exists());
+
+        let mut metadata = ClusterMetadata::default();
+        metadata.topics.insert(
+            "orders".to_string(),
+            ClusterTopic {
+                name: "orders".to_string(),
+                topic_id: orders.topic_id,
+                partitions: BTreeMap::from([(
+                    0,
+                    ClusterPartition {
+                        partition_index: 0,
+                        replicas: vec![1],
+                        isr: vec![1],
+                        leader: 1,
+                        leader_epoch: 0,
+                    },
+                )]),
+            },
+        );
+        let restarted = Arc::new(
+            KafkaBroker::new()
+                .with_log_dir(log_dir.path())
+                .with_cluster_metadata(&metadata),
+        );
+        let log = &restarted.topics().get_topic("orders").unwrap().partitions[0];
+        assert_eq!(log.log_end_offset(), 6);
+
+        let mut fetched = Vec::new();
+        for fetch_offset in [0, 3] {
+            let mut topic = fetch_topic("orders", orders.topic_id, &[0]);
+            topic.partitions[0].fetch_offset = fetch_offset;
+            let response = fetch_from(&restarted, 12, vec![topic]).await;
+            let partition = &response.responses[0].partitions[0];
+            assert_eq!(partition.error_code, ErrorCode::NONE);
+            assert_eq!(partition.high_watermark, 6);
+            let records = partition.records.clone().unwrap();
+            fetched.extend(
+                split_record_batches(&records)
+                    .unwrap()
+                    .iter()
+                    .map(|batch| batch.base_offset()),
+            );
+        }
+        assert_eq!(fetched, [0, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_produce_to_log_append_time_topic() {
+        let broker = Arc::new(KafkaBroker::new());
+        let configs = BTreeMap::from([(
+            "message.timestamp.type".to_string(),
+            "LogAppendTime".to_string(),
+        )]);
+        broker.topics().create_topic("orders", 1, configs).unwrap();
+        let (mut client, handle) = spawn_connection_with(broker);
+
+        client
+            .write_all(&flexible_produce_frame(1, &[("orders", &[0])]))
+            .await
+            .unwrap();
+        let response = read_response(&mut client).await;
+        let mut body = Bytes::copy_from_slice(&response[5..]);
+        let decoded = ProduceResponse::decode(&mut body, 9).unwrap();
+        let partition = &decoded.topics[0].partitions[0];
+        assert_eq!(partition.error_code, ErrorCode::NONE);
+        assert!(partition.log_append_time_ms > 0);
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_produce_rejects_unsupported_compression() {
+        use crate::protocol::compression::CompressionType;
+        use crate::protocol::record_batch::RecordBatchBuilder;
+
+        let mut builder = RecordBatchBuilder::new(0).with_compression(CompressionType::Gzip);
+        builder.append(0, None, Some(Bytes::from_static(b"value")), Vec::new());
+        let gzip = builder.build().unwrap();
+        // Codec 7 does not exist; the CRC is recomputed so only the codec is wrong
+        let mut unknown = gzip.to_vec();
+        unknown[22] |= 0x07;
+        let crc = crc32c::crc32c(&unknown[21..]);
+        unknown[17..21].copy_from_slice(&crc.to_be_bytes());
+
+        let (mut client, handle) = spawn_connection();
+        for (correlation_id, records, expected) in [
+            (1, gzip, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION),
+            (
+                2,
+                Bytes::from(unknown),
+                ErrorCode::UNSUPPORTED_COMPRESSION_TYPE,
+            ),
+        ] {
+            client
+                .write_all(&flexible_produce_frame_with(
+                    correlation_id,
+                    &[("orders", &[0])],
+                    records,
+                ))
+                .await
+                .unwrap();
+            let response = read_response(&mut client).await;
+            let mut body = Bytes::copy_from_slice(&response[5..]);
+            let decoded = ProduceResponse::decode(&mut body, 9).unwrap();
+            assert_eq!(
+                decoded.topics[0].partitions[0].error_code, expected,
+                "request {}",
+                correlation_id
+            );
+        }
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_produce_checks_sequences() {
+        use crate::protocol::record_batch::RecordBatchBuilder;
+
+        let idempotent_batch = |epoch: i16, base_sequence: i32| {
+            let mut builder = RecordBatchBuilder::new(0).with_producer(4, epoch, base_sequence);
+            builder.append(0, None, Some(Bytes::from_static(b"a")), Vec::new());
+            builder.append(0, None, Some(Bytes::from_static(b"b")), Vec::new());
+            builder.build().unwrap()
+        };
+        let broker = Arc::new(KafkaBroker::new());
+        let orders = broker
+            .topics()
+            .create_topic("orders", 1, BTreeMap::new())
+            .unwrap();
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+
+        for (correlation_id, records, expected) in [
+            (1, idempotent_batch(0, 0), (ErrorCode::NONE, 0)),
+            (2, idempotent_batch(0, 2), (ErrorCode::NONE, 2)),
+            // A retry of the first batch gets its original offset back
+            (3, idempotent_batch(0, 0), (ErrorCode::NONE, 0)),
+            (
+                4,
+                idempotent_batch(0, 7),
+                (ErrorCode::OUT_OF_ORDER_SEQUENCE_NUMBER, -1),
+            ),
+            // A bumped epoch restarts the sequence and fences the old one
+            (5, idempotent_batch(1, 0), (ErrorCode::NONE, 4)),
+            (
+                6,
+                idempotent_batch(0, 4),
+                (ErrorCode::INVALID_PRODUCER_EPOCH, -1),
+            ),
+        ] {
+            client
+                .write_all(&flexible_produce_frame_with(
+                    correlation_id,
+                    &[("orders", &[0])],
+                    records,
+                ))
+                .await
+                .unwrap();
+            let response = read_response(&mut client).await;
+            let mut body = Bytes::copy_from_slice(&response[5..]);
+            let decoded = ProduceResponse::decode(&mut body, 9).unwrap();
+            let partition = &decoded.topics[0].partitions[0];
+            assert_eq!(
+                (partition.error_code, partition.base_offset),
+                expected,
+                "request {}",
+                correlation_id
+            );
+        }
+        assert_eq!(orders.partitions[0].log_end_offset(), 6);
+        assert_eq!(orders.partitions[0].producers().tracked_producer_count(), 1);
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_produce_v3_rejects_legacy_message_set() {
+        let (mut client, handle) = spawn_connection();
+
+        client.write_all(&produce_frame(3, 1, 9)).await.unwrap();
+        let response = read_response(&mut client).await;
+        let mut body = Bytes::copy_from_slice(&response[4..]);
+        let decoded = ProduceResponse::decode(&mut body, 3).unwrap();
+        assert_eq!(
+            decoded.topics[0].partitions[0].error_code,
+            ErrorCode::UNSUPPORTED_FOR_MESSAGE_FORMAT
+        );
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_produce_acks_zero_sends_no_response() {
+        let (mut client, handle) = spawn_connection();
+
+        client.write_all(&produce_frame(2, 0, 5)).await.unwrap();
+        client.write_all(&api_versions_frame(6)).await.unwrap();
+
+        // The first response on the wire belongs to the ApiVersions request
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &6i32.to_be_bytes());
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_parked_produce_answered_before_shutdown_close() {
+        use crate::kafka::purgatory::{PurgatoryConfig, ShutdownCompletion};
+
+        let broker = Arc::new(
+            KafkaBroker::new().with_purgatory(Purgatory::new(PurgatoryConfig {
+                delay: Duration::from_secs(60),
+                shutdown_completion: ShutdownCompletion::TimedOut,
+            })),
+        );
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+
+        client
+            .write_all(&produce_frame_with_timeout(2, -1, 11, 60_000))
+            .await
+            .unwrap();
+        while broker.purgatory().pending().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(broker.purgatory().pending(), vec![11]);
+
+        broker.begin_shutdown();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..4], &11i32.to_be_bytes());
+
+        // The connection closes once the parked response is flushed
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+        assert!(handle.await.unwrap().is_ok());
+        assert!(broker.purgatory().pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_unparks_produce() {
+        use crate::kafka::purgatory::PurgatoryConfig;
+
+        let broker = Arc::new(
+            KafkaBroker::new().with_purgatory(Purgatory::new(PurgatoryConfig {
+                delay: Duration::from_secs(60),
+                ..PurgatoryConfig::default()
+            })),
+        );
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+
+        client
+            .write_all(&produce_frame_with_timeout(2, -1, 11, 60_000))
+            .await
+            .unwrap();
+        while broker.purgatory().pending().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("parked produce outlived its connection")
+            .unwrap()
+            .unwrap();
+        assert!(broker.purgatory().pending().is_empty());
+        assert_eq!(broker.cancelled_by_disconnect(), 1);
+    }
+
+    #[test]
+    fn test_handler_takes_constructed_request() {
+        let broker = KafkaBroker::new();
+        let response = broker
+            .handle_describe_topic_partitions_request(DescribeTopicPartitionsRequest {
+                topics: vec!["b".to_string(), "a".to_string(), "b".to_string()],
+                response_partition_limit: 100,
+                cursor: None,
+            })
+            .unwrap();
+        let names: Vec<_> = response
+            .topics
+            .iter()
+            .map(|topic| topic.name.as_deref())
+            .collect();
+        assert_eq!(names, vec![Some("a"), Some("b")]);
+    }
+
+    /// Answers API key 1000, v0-v2, by echoing the body, and records each
+    /// call
+    #[derive(Default)]
+    struct EchoHandler {
+        calls: std::sync::Mutex<Vec<(SocketAddr, i16, i32, Bytes)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ApiHandler for EchoHandler {
+        fn api_key(&self) -> i16 {
+            1000
+        }
+
+        fn supported_versions(&self) -> (i16, i16) {
+            (0, 2)
+        }
+
+        async fn handle(
+            &self,
+            ctx: &RequestContext,
+            header: &RequestHeader,
+            body: &mut Bytes,
+        ) -> Result<BytesMut> {
+            self.calls.lock().unwrap().push((
+                ctx.peer_addr,
+                header.api_version(),
+                header.correlation_id(),
+                body.clone(),
+            ));
+            Ok(BytesMut::from(&std::mem::take(body)[..]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registered_handler_is_invoked() {
+        let handler = Arc::new(EchoHandler::default());
+        let broker = Arc::new(KafkaBroker::new());
+        assert!(broker.register_handler(handler.clone()).is_none());
+        let (mut client, handle) = spawn_connection_with(broker);
+
+        for (version, correlation_id) in [(1, 21), (3, 22)] {
+            let mut request =
+                RequestHeaderV2::with_client_id(1000, version, correlation_id, "test-client")
+                    .encode()
+                    .unwrap();
+            request.extend_from_slice(b"ping");
+            client.write_all(&frame(&request)).await.unwrap();
+        }
+        // Unknown APIs are taken to be flexible: a v1 response header
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[..5], &[0, 0, 0, 21, 0]);
+        assert_eq!(&response[5..], b"ping");
+        // v3 is outside the handler's range
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[..5], &[0, 0, 0, 22, 0]);
+        assert_eq!(
+            &response[5..],
+            &ErrorCode::UNSUPPORTED_VERSION.code().to_be_bytes()
+        );
+        assert_eq!(
+            *handler.calls.lock().unwrap(),
+            vec![(test_peer_addr(), 1, 21, Bytes::from_static(b"ping"))]
+        );
+
+        client.write_all(&api_versions_frame(23)).await.unwrap();
+        let response = read_response(&mut client).await;
+        let mut body = Bytes::copy_from_slice(&response[4..]);
+        let api_versions = ApiVersionsResponse::decode(&mut body, 0).unwrap();
+        assert!(api_versions.api_keys.contains(&ApiVersionRange {
+            api_key: 1000,
+            min_version: 0,
+            max_version: 2,
+        }));
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    /// Serves Heartbeat by failing, with a [`BrokerError`] or without one
+    struct FailingHeartbeat {
+        broker_error: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl ApiHandler for FailingHeartbeat {
+        fn api_key(&self) -> i16 {
+            ApiKey::Heartbeat.code()
+        }
+
+        fn supported_versions(&self) -> (i16, i16) {
+            (0, 4)
+        }
+
+        async fn handle(
+            &self,
+            _ctx: &RequestContext,
+            _header: &RequestHeader,
+            _body: &mut Bytes,
+        ) -> Result<BytesMut> {
+            if self.broker_error {
+                Err(BrokerError::TransactionsUnsupported {
+                    transactional_id: "tx".to_string(),
+                }
+                .into())
+            } else {
+                bail!("handler fell over")
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_failure_is_answered() {
+        let broker = Arc::new(KafkaBroker::new());
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+        for (broker_error, correlation_id, error_code) in [
+            (true, 61, ErrorCode::COORDINATOR_NOT_AVAILABLE),
+            (false, 62, ErrorCode::UNKNOWN_SERVER_ERROR),
+        ] {
+            broker.register_handler(Arc::new(FailingHeartbeat { broker_error }));
+            let header = RequestHeaderV2::with_client_id(
+                ApiKey::Heartbeat.code(),
+                4,
+                correlation_id,
+                "test-client",
+            );
+            client
+                .write_all(&frame(&header.encode().unwrap()))
+                .await
+                .unwrap();
+
+            let response = read_response(&mut client).await;
+            assert_eq!(&response[0..4], &correlation_id.to_be_bytes());
+            let mut body = Bytes::copy_from_slice(&response[5..]);
+            let heartbeat = HeartbeatResponse::decode(&mut body, 4).unwrap();
+            assert_eq!(heartbeat.error_code, error_code);
+            assert!(body.is_empty());
+        }
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_supported_apis_gate_versions() {
+        let broker = Arc::new(KafkaBroker::new());
+        let (mut client, handle) = spawn_connection_with(Arc::clone(&broker));
+
+        // Out of the table's range: answered before reaching the handler
+        let header = RequestHeaderV2::with_client_id(ApiKey::Fetch.code(), 999, 31, "test-client");
+        client
+            .write_all(&frame(&header.encode().unwrap()))
+            .await
+            .unwrap();
+        let response = read_response(&mut client).await;
+        assert_eq!(&response[0..5], &[0, 0, 0, 31, 0]);
+        let mut body = Bytes::copy_from_slice(&response[5..]);
+        let unsupported = FetchResponse::decode(&mut body, FETCH_MAX_VERSION).unwrap();
+        assert_eq!(unsupported.error_code, ErrorCode::UNSUPPORTED_VERSION);
+
+        // Within range: the Fetch handler answers
+        let response = fetch(FETCH_MAX_VERSION, Vec::new()).await;
+        assert_eq!(response.error_code, ErrorCode::NONE);
+
+        // A handler registered later is advertised from the same table
+        broker.register_handler(Arc::new(EchoHandler::default()));
+        client.write_all(&api_versions_frame(32)).await.unwrap();
+        let response = read_response(&mut client).await;
+        let mut body = Bytes::copy_from_slice(&response[4..]);
+        let api_versions = ApiVersionsResponse::decode(&mut body, 0).unwrap();
+        assert_eq!(
+            api_versions.api_keys,
+            broker.handlers.read().unwrap().supported_apis().entries()
+        );
+        assert!(api_versions.api_keys.contains(&ApiVersionRange {
+            api_key: 1000,
+            min_version: 0,
+            max_version: 2,
+        }));
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
     }
 }
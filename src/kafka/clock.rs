@@ -0,0 +1,118 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of time for the broker
+///
+/// Components that stamp or compare timestamps take a `Clock` rather than
+/// calling `SystemTime::now()` directly so tests can control time.
+///
+/// Wall-clock time can jump backwards when NTP steps the system clock, so
+/// it is only for values that leave the broker (record timestamps) or are
+/// compared against them (retention). Deadlines, idle timeouts and rate
+/// windows use [`Clock::now_instant`], which never goes backwards.
+pub trait Clock: Send + Sync + Debug {
+    /// Milliseconds since the Unix epoch
+    fn now_ms(&self) -> i64;
+
+    /// Monotonic time for measuring elapsed durations
+    fn now_instant(&self) -> Instant;
+}
+
+/// Clock backed by the operating system
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as i64)
+            .unwrap_or(0)
+    }
+
+    fn now_instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Manually driven clock for tests
+///
+/// Wall-clock and monotonic time move together with [`MockClock::advance_ms`];
+/// [`MockClock::set_ms`] steps only the wall clock, like an NTP correction.
+#[derive(Debug)]
+pub struct MockClock {
+    now_ms: AtomicI64,
+    origin: Instant,
+    elapsed_ms: AtomicI64,
+}
+
+impl MockClock {
+    /// Creates a mock clock reading `now_ms`
+    pub fn new(now_ms: i64) -> Self {
+        Self {
+            now_ms: AtomicI64::new(now_ms),
+            origin: Instant::now(),
+            elapsed_ms: AtomicI64::new(0),
+        }
+    }
+
+    /// Sets the wall-clock time, which may move backwards
+    pub fn set_ms(&self, now_ms: i64) {
+        self.now_ms.store(now_ms, Ordering::SeqCst);
+    }
+
+    /// Moves the clock forward by `delta_ms`
+    pub fn advance_ms(&self, delta_ms: i64) {
+        self.now_ms.fetch_add(delta_ms, Ordering::SeqCst);
+        self.elapsed_ms.fetch_add(delta_ms.max(0), Ordering::SeqCst);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ms(&self) -> i64 {
+        self.now_ms.load(Ordering::SeqCst)
+    }
+
+    fn now_instant(&self) -> Instant {
+        self.origin + Duration::from_millis(self.elapsed_ms.load(Ordering::SeqCst) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new(1_000);
+        assert_eq!(clock.now_ms(), 1_000);
+
+        clock.advance_ms(500);
+        assert_eq!(clock.now_ms(), 1_500);
+
+        clock.set_ms(200);
+        assert_eq!(clock.now_ms(), 200);
+    }
+
+    #[test]
+    fn test_wall_clock_step_does_not_move_instant() {
+        let clock = MockClock::new(10_000);
+        let start = clock.now_instant();
+
+        clock.advance_ms(100);
+        clock.set_ms(5_000);
+        assert_eq!(clock.now_instant() - start, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_system_clock_is_after_2020() {
+        assert!(SystemClock.now_ms() > 1_577_836_800_000);
+    }
+}
@@ -0,0 +1,887 @@
+//! Protocol compatibility sweep
+//!
+//! Asks an in-process broker which versions it advertises through
+//! ApiVersions, then sends a canonical request for every advertised
+//! (api key, version) pair and checks the response decodes as that version.
+//! Advertising an API or version that has no case here, or that the broker
+//! cannot actually serve, fails the test. Versions that cannot be exercised
+//! must be listed in `skipped_versions` with a reason.
+
+use crate::kafka::test_util::{frame, read_response, spawn_connection};
+use crate::protocol::api_versions::ApiVersionsResponse;
+use crate::protocol::create_topics::{CreatableTopic, CreateTopicsRequest, CreateTopicsResponse};
+use crate::protocol::delete_topics::{DeleteTopicState, DeleteTopicsRequest, DeleteTopicsResponse};
+use crate::protocol::describe_cluster::{DescribeClusterRequest, DescribeClusterResponse};
+use crate::protocol::describe_configs::{
+    DescribeConfigsRequest, DescribeConfigsResource, DescribeConfigsResponse, RESOURCE_TYPE_BROKER,
+    RESOURCE_TYPE_TOPIC,
+};
+use crate::protocol::describe_topic_partitions::{
+    DescribeTopicPartitionsRequest, DescribeTopicPartitionsResponse,
+};
+use crate::protocol::fetch::{FetchPartition, FetchRequest, FetchResponse, FetchTopic};
+use crate::protocol::find_coordinator::{
+    FindCoordinatorRequest, FindCoordinatorResponse, COORDINATOR_TYPE_GROUP,
+    FIND_COORDINATOR_BATCHED_VERSION,
+};
+use crate::protocol::heartbeat::{HeartbeatRequest, HeartbeatResponse};
+use crate::protocol::incremental_alter_configs::{
+    AlterConfigsResource, AlterableConfig, ConfigOperation, IncrementalAlterConfigsRequest,
+    IncrementalAlterConfigsResponse,
+};
+use crate::protocol::init_producer_id::{InitProducerIdRequest, InitProducerIdResponse};
+use crate::protocol::join_group::{
+    JoinGroupRequest, JoinGroupRequestProtocol, JoinGroupResponse,
+    JOIN_GROUP_MEMBER_ID_REQUIRED_VERSION,
+};
+use crate::protocol::leave_group::{LeaveGroupRequest, LeaveGroupResponse, LeavingMember};
+use crate::protocol::list_offsets::{
+    ListOffsetsPartition, ListOffsetsRequest, ListOffsetsResponse, ListOffsetsTopic,
+    LATEST_TIMESTAMP,
+};
+use crate::protocol::message_set::{encode_message_set, LegacyMessage};
+use crate::protocol::metadata::{MetadataRequest, MetadataRequestTopic, MetadataResponse};
+use crate::protocol::offset_commit::{
+    OffsetCommitPartition, OffsetCommitRequest, OffsetCommitResponse, OffsetCommitTopic,
+    SIMPLE_COMMIT_GENERATION,
+};
+use crate::protocol::offset_fetch::{
+    OffsetFetchRequest, OffsetFetchRequestGroup, OffsetFetchRequestTopic, OffsetFetchResponse,
+};
+use crate::protocol::produce::{
+    ProducePartitionData, ProduceRequest, ProduceResponse, ProduceTopicData,
+    FIRST_RECORD_BATCH_VERSION,
+};
+use crate::protocol::record_batch::encode_test_batch;
+use crate::protocol::sasl_authenticate::{SaslAuthenticateRequest, SaslAuthenticateResponse};
+use crate::protocol::sasl_handshake::{SaslHandshakeRequest, SaslHandshakeResponse};
+use crate::protocol::sync_group::{SyncGroupRequest, SyncGroupResponse};
+use crate::protocol::{ErrorCode, ProtocolEncodeVersioned, WireFormat};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::collections::HashMap;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+const CLIENT_ID: &str = "compat-sweep";
+
+/// Per-API description of how to exercise and validate every version
+struct CompatCase {
+    api_key: i16,
+    name: &'static str,
+    /// First version using the flexible (compact, tagged) encoding
+    flexible_from: Option<i16>,
+    /// Builds the canonical request body for a version
+    build_request: fn(i16) -> BytesMut,
+    /// Decodes the response body for a version, consuming all of it
+    validate_response: fn(i16, &mut Bytes) -> Result<(), String>,
+    /// Versions the sweep deliberately does not exercise, with a reason
+    skipped_versions: &'static [(i16, &'static str)],
+}
+
+impl CompatCase {
+    fn is_flexible(&self, version: i16) -> bool {
+        self.flexible_from.is_some_and(|first| version >= first)
+    }
+
+    fn skip_reason(&self, version: i16) -> Option<&'static str> {
+        self.skipped_versions
+            .iter()
+            .find(|(skipped, _)| *skipped == version)
+            .map(|(_, reason)| *reason)
+    }
+}
+
+fn cases() -> Vec<CompatCase> {
+    vec![
+        CompatCase {
+            api_key: 0,
+            name: "Produce",
+            flexible_from: Some(9),
+            build_request: build_produce,
+            validate_response: validate_produce,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 1,
+            name: "Fetch",
+            flexible_from: Some(12),
+            build_request: build_fetch,
+            validate_response: validate_fetch,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 2,
+            name: "ListOffsets",
+            flexible_from: Some(6),
+            build_request: build_list_offsets,
+            validate_response: validate_list_offsets,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 3,
+            name: "Metadata",
+            flexible_from: Some(9),
+            build_request: build_metadata,
+            validate_response: validate_metadata,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 8,
+            name: "OffsetCommit",
+            flexible_from: Some(8),
+            build_request: build_offset_commit,
+            validate_response: validate_offset_commit,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 9,
+            name: "OffsetFetch",
+            flexible_from: Some(6),
+            build_request: build_offset_fetch,
+            validate_response: validate_offset_fetch,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 10,
+            name: "FindCoordinator",
+            flexible_from: Some(3),
+            build_request: build_find_coordinator,
+            validate_response: validate_find_coordinator,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 11,
+            name: "JoinGroup",
+            flexible_from: Some(6),
+            build_request: build_join_group,
+            validate_response: validate_join_group,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 12,
+            name: "Heartbeat",
+            flexible_from: Some(4),
+            build_request: build_heartbeat,
+            validate_response: validate_heartbeat,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 13,
+            name: "LeaveGroup",
+            flexible_from: Some(4),
+            build_request: build_leave_group,
+            validate_response: validate_leave_group,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 14,
+            name: "SyncGroup",
+            flexible_from: Some(4),
+            build_request: build_sync_group,
+            validate_response: validate_sync_group,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 17,
+            name: "SaslHandshake",
+            flexible_from: None,
+            build_request: build_sasl_handshake,
+            validate_response: validate_sasl_handshake,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 18,
+            name: "ApiVersions",
+            flexible_from: Some(3),
+            build_request: |_version| BytesMut::new(),
+            validate_response: validate_api_versions,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 19,
+            name: "CreateTopics",
+            flexible_from: Some(5),
+            build_request: build_create_topics,
+            validate_response: validate_create_topics,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 20,
+            name: "DeleteTopics",
+            flexible_from: Some(4),
+            build_request: build_delete_topics,
+            validate_response: validate_delete_topics,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 22,
+            name: "InitProducerId",
+            flexible_from: Some(2),
+            build_request: build_init_producer_id,
+            validate_response: validate_init_producer_id,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 32,
+            name: "DescribeConfigs",
+            flexible_from: Some(4),
+            build_request: build_describe_configs,
+            validate_response: validate_describe_configs,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 36,
+            name: "SaslAuthenticate",
+            flexible_from: Some(2),
+            build_request: build_sasl_authenticate,
+            validate_response: validate_sasl_authenticate,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 44,
+            name: "IncrementalAlterConfigs",
+            flexible_from: Some(1),
+            build_request: build_incremental_alter_configs,
+            validate_response: validate_incremental_alter_configs,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 60,
+            name: "DescribeCluster",
+            flexible_from: Some(0),
+            build_request: build_describe_cluster,
+            validate_response: validate_describe_cluster,
+            skipped_versions: &[],
+        },
+        CompatCase {
+            api_key: 75,
+            name: "DescribeTopicPartitions",
+            flexible_from: Some(0),
+            build_request: build_describe_topic_partitions,
+            validate_response: validate_describe_topic_partitions,
+            skipped_versions: &[],
+        },
+    ]
+}
+
+fn build_produce(version: i16) -> BytesMut {
+    let records = if version >= FIRST_RECORD_BATCH_VERSION {
+        encode_test_batch(1, 0)
+    } else {
+        // v0 clients only speak magic 0; v1 and v2 send magic 1
+        let magic = if version == 0 { 0 } else { 1 };
+        encode_message_set(&[LegacyMessage {
+            offset: 0,
+            magic,
+            attributes: 0,
+            timestamp: (magic == 1).then_some(0),
+            key: None,
+            value: Some(Bytes::from_static(b"compat")),
+        }])
+        .unwrap()
+        .freeze()
+    };
+
+    ProduceRequest {
+        transactional_id: None,
+        acks: 1,
+        timeout_ms: 1000,
+        topics: vec![ProduceTopicData {
+            name: "compat".to_string(),
+            partitions: vec![ProducePartitionData {
+                index: 0,
+                records: Some(records),
+            }],
+        }],
+    }
+    .encode(version)
+    .unwrap()
+}
+
+fn validate_produce(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = ProduceResponse::decode(body, version).map_err(|e| e.to_string())?;
+    if response.topics.len() != 1 || response.topics[0].partitions.len() != 1 {
+        return Err("expected one topic with one partition".to_string());
+    }
+    Ok(())
+}
+
+fn validate_api_versions(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = ApiVersionsResponse::decode(body, version).map_err(|e| e.to_string())?;
+    if response.error_code.is_error() {
+        return Err(format!("unexpected error code {}", response.error_code));
+    }
+    Ok(())
+}
+
+fn build_fetch(version: i16) -> BytesMut {
+    let request = FetchRequest {
+        cluster_id: None,
+        replica_id: -1,
+        replica_epoch: -1,
+        max_wait_ms: 0,
+        min_bytes: 1,
+        max_bytes: i32::MAX,
+        isolation_level: 0,
+        session_id: 0,
+        session_epoch: -1,
+        topics: vec![FetchTopic {
+            topic: "compat".to_string(),
+            topic_id: Uuid::from_u128(1),
+            partitions: vec![FetchPartition {
+                partition: 0,
+                current_leader_epoch: -1,
+                fetch_offset: 0,
+                last_fetched_epoch: -1,
+                log_start_offset: -1,
+                partition_max_bytes: 1024 * 1024,
+            }],
+        }],
+        forgotten_topics_data: Vec::new(),
+        rack_id: String::new(),
+    };
+    let mut body = BytesMut::new();
+    request.encode_versioned(version, &mut body).unwrap();
+    body
+}
+
+fn validate_fetch(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = FetchResponse::decode(body, version).map_err(|e| e.to_string())?;
+    if response.error_code.is_error() {
+        return Err(format!("unexpected error code {}", response.error_code));
+    }
+    match response.responses.as_slice() {
+        [topic] if topic.partitions.len() == 1 => Ok(()),
+        topics => Err(format!("unexpected topics {:?}", topics)),
+    }
+}
+
+fn build_list_offsets(version: i16) -> BytesMut {
+    let request = ListOffsetsRequest {
+        replica_id: -1,
+        isolation_level: 0,
+        topics: vec![ListOffsetsTopic {
+            name: "compat".to_string(),
+            partitions: vec![ListOffsetsPartition {
+                partition_index: 0,
+                current_leader_epoch: -1,
+                timestamp: LATEST_TIMESTAMP,
+            }],
+        }],
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_list_offsets(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = ListOffsetsResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match response.topics.as_slice() {
+        [topic] if topic.partitions.len() == 1 => Ok(()),
+        topics => Err(format!("unexpected topics {:?}", topics)),
+    }
+}
+
+fn build_metadata(version: i16) -> BytesMut {
+    let request = MetadataRequest {
+        topics: Some(vec![MetadataRequestTopic {
+            topic_id: Uuid::nil(),
+            name: Some("compat".to_string()),
+        }]),
+        allow_auto_topic_creation: false,
+        include_cluster_authorized_operations: false,
+        include_topic_authorized_operations: false,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_metadata(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = MetadataResponse::decode(body, version).map_err(|e| e.to_string())?;
+    if response.brokers.len() != 1 {
+        return Err(format!("unexpected brokers {:?}", response.brokers));
+    }
+    match response.topics.as_slice() {
+        [topic] if topic.name.as_deref() == Some("compat") => Ok(()),
+        topics => Err(format!("unexpected topics {:?}", topics)),
+    }
+}
+
+fn build_find_coordinator(version: i16) -> BytesMut {
+    let request = FindCoordinatorRequest {
+        key: "compat".to_string(),
+        key_type: COORDINATOR_TYPE_GROUP,
+        coordinator_keys: vec!["compat".to_string()],
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_find_coordinator(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = FindCoordinatorResponse::decode(body, version).map_err(|e| e.to_string())?;
+    let error_code = if version >= FIND_COORDINATOR_BATCHED_VERSION {
+        match response.coordinators.as_slice() {
+            [coordinator] if coordinator.key == "compat" => coordinator.error_code,
+            coordinators => return Err(format!("unexpected coordinators {:?}", coordinators)),
+        }
+    } else {
+        response.error_code
+    };
+    if error_code.is_error() {
+        return Err(format!("error code {}", error_code));
+    }
+    Ok(())
+}
+
+fn build_offset_commit(version: i16) -> BytesMut {
+    let request = OffsetCommitRequest {
+        group_id: compat_group(version),
+        generation_id: SIMPLE_COMMIT_GENERATION,
+        member_id: String::new(),
+        group_instance_id: None,
+        retention_time_ms: -1,
+        topics: vec![OffsetCommitTopic {
+            name: "compat-missing".to_string(),
+            partitions: vec![OffsetCommitPartition {
+                partition_index: 0,
+                committed_offset: 0,
+                committed_leader_epoch: -1,
+                committed_metadata: None,
+            }],
+        }],
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_offset_commit(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = OffsetCommitResponse::decode(body, version).map_err(|e| e.to_string())?;
+    let error_codes: Vec<_> = response
+        .topics
+        .iter()
+        .flat_map(|topic| &topic.partitions)
+        .map(|partition| partition.error_code)
+        .collect();
+    // The topic does not exist, so the commit is refused
+    if error_codes != [ErrorCode::UNKNOWN_TOPIC_OR_PARTITION] {
+        return Err(format!("unexpected response {:?}", response));
+    }
+    Ok(())
+}
+
+fn build_offset_fetch(version: i16) -> BytesMut {
+    let request = OffsetFetchRequest {
+        groups: vec![OffsetFetchRequestGroup {
+            group_id: compat_group(version),
+            topics: Some(vec![OffsetFetchRequestTopic {
+                name: "compat-missing".to_string(),
+                partition_indexes: vec![0],
+            }]),
+        }],
+        require_stable: false,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_offset_fetch(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = OffsetFetchResponse::decode(body, version).map_err(|e| e.to_string())?;
+    let offsets: Vec<_> = response
+        .groups
+        .iter()
+        .flat_map(|group| &group.topics)
+        .flat_map(|topic| &topic.partitions)
+        .map(|partition| (partition.committed_offset, partition.error_code))
+        .collect();
+    // Nothing was committed, so the partition has no offset
+    if offsets != [(-1, ErrorCode::NONE)] {
+        return Err(format!("unexpected response {:?}", response));
+    }
+    Ok(())
+}
+
+/// Names a group per version, so no join waits on an earlier version's member
+fn compat_group(version: i16) -> String {
+    format!("compat-{}", version)
+}
+
+fn build_join_group(version: i16) -> BytesMut {
+    let request = JoinGroupRequest {
+        group_id: compat_group(version),
+        session_timeout_ms: 10_000,
+        rebalance_timeout_ms: 10_000,
+        member_id: String::new(),
+        group_instance_id: None,
+        protocol_type: "consumer".to_string(),
+        protocols: vec![JoinGroupRequestProtocol {
+            name: "range".to_string(),
+            metadata: Bytes::new(),
+        }],
+        reason: None,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_join_group(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = JoinGroupResponse::decode(body, version).map_err(|e| e.to_string())?;
+    // Newer clients first learn their member id, older ones join at once
+    let expected = if version >= JOIN_GROUP_MEMBER_ID_REQUIRED_VERSION {
+        ErrorCode::MEMBER_ID_REQUIRED
+    } else {
+        ErrorCode::NONE
+    };
+    if response.error_code != expected || response.member_id.is_empty() {
+        return Err(format!("unexpected response {:?}", response));
+    }
+    Ok(())
+}
+
+fn build_heartbeat(version: i16) -> BytesMut {
+    let request = HeartbeatRequest {
+        group_id: compat_group(version),
+        generation_id: 1,
+        member_id: "compat".to_string(),
+        group_instance_id: None,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_heartbeat(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = HeartbeatResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match response.error_code {
+        ErrorCode::UNKNOWN_MEMBER_ID => Ok(()),
+        error_code => Err(format!("error code {}", error_code)),
+    }
+}
+
+fn build_leave_group(version: i16) -> BytesMut {
+    let request = LeaveGroupRequest {
+        group_id: compat_group(version),
+        members: vec![LeavingMember {
+            member_id: "compat".to_string(),
+            group_instance_id: None,
+            reason: None,
+        }],
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_leave_group(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = LeaveGroupResponse::decode(body, version).map_err(|e| e.to_string())?;
+    // Batched versions report the unknown member on its own entry
+    let error_code = match response.members.as_slice() {
+        [] => response.error_code,
+        [member] if response.error_code == ErrorCode::NONE => member.error_code,
+        _ => return Err(format!("unexpected response {:?}", response)),
+    };
+    match error_code {
+        ErrorCode::UNKNOWN_MEMBER_ID => Ok(()),
+        error_code => Err(format!("error code {}", error_code)),
+    }
+}
+
+fn build_sync_group(version: i16) -> BytesMut {
+    let request = SyncGroupRequest {
+        group_id: compat_group(version),
+        generation_id: 1,
+        member_id: "compat".to_string(),
+        group_instance_id: None,
+        protocol_type: None,
+        protocol_name: None,
+        assignments: Vec::new(),
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_sync_group(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = SyncGroupResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match response.error_code {
+        ErrorCode::UNKNOWN_MEMBER_ID => Ok(()),
+        error_code => Err(format!("error code {}", error_code)),
+    }
+}
+
+/// Names a topic per version, so every version creates a fresh one
+fn compat_topic(version: i16) -> String {
+    format!("compat-{}", version)
+}
+
+fn build_sasl_handshake(version: i16) -> BytesMut {
+    let request = SaslHandshakeRequest {
+        mechanism: "PLAIN".to_string(),
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_sasl_handshake(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = SaslHandshakeResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match response.error_code {
+        ErrorCode::NONE => Ok(()),
+        error_code => Err(format!("error code {}", error_code)),
+    }
+}
+
+fn build_create_topics(version: i16) -> BytesMut {
+    let request = CreateTopicsRequest {
+        topics: vec![CreatableTopic {
+            name: compat_topic(version),
+            num_partitions: 1,
+            replication_factor: 1,
+            assignments: Vec::new(),
+            configs: Vec::new(),
+        }],
+        timeout_ms: 30_000,
+        validate_only: false,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_create_topics(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = CreateTopicsResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match response.topics.as_slice() {
+        [topic] if topic.name == compat_topic(version) && !topic.error_code.is_error() => Ok(()),
+        topics => Err(format!("unexpected topics {:?}", topics)),
+    }
+}
+
+fn build_delete_topics(version: i16) -> BytesMut {
+    let request = DeleteTopicsRequest {
+        topics: vec![DeleteTopicState {
+            name: Some("compat".to_string()),
+            topic_id: Uuid::nil(),
+        }],
+        timeout_ms: 30_000,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_delete_topics(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = DeleteTopicsResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match response.responses.as_slice() {
+        [topic] if topic.name.as_deref() == Some("compat") => Ok(()),
+        topics => Err(format!("unexpected topics {:?}", topics)),
+    }
+}
+
+fn build_init_producer_id(version: i16) -> BytesMut {
+    let request = InitProducerIdRequest {
+        transactional_id: None,
+        transaction_timeout_ms: 60_000,
+        producer_id: -1,
+        producer_epoch: -1,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_init_producer_id(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = InitProducerIdResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match (response.error_code, response.producer_epoch) {
+        (ErrorCode::NONE, 0) if response.producer_id >= 0 => Ok(()),
+        _ => Err(format!("unexpected response {:?}", response)),
+    }
+}
+
+fn build_describe_configs(version: i16) -> BytesMut {
+    let request = DescribeConfigsRequest {
+        resources: vec![DescribeConfigsResource {
+            resource_type: RESOURCE_TYPE_BROKER,
+            resource_name: "1".to_string(),
+            configuration_keys: Some(vec!["node.id".to_string()]),
+        }],
+        include_synonyms: false,
+        include_documentation: false,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_describe_configs(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = DescribeConfigsResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match response.results.as_slice() {
+        [result] if result.error_code == ErrorCode::NONE && result.configs.len() == 1 => Ok(()),
+        results => Err(format!("unexpected results {:?}", results)),
+    }
+}
+
+fn build_sasl_authenticate(version: i16) -> BytesMut {
+    let request = SaslAuthenticateRequest {
+        auth_bytes: Bytes::from_static(b"\0compat\0compat"),
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_sasl_authenticate(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = SaslAuthenticateResponse::decode(body, version).map_err(|e| e.to_string())?;
+    // Every exchange is a fresh connection, so no handshake came first
+    match response.error_code {
+        ErrorCode::ILLEGAL_SASL_STATE => Ok(()),
+        error_code => Err(format!("error code {}", error_code)),
+    }
+}
+
+fn build_incremental_alter_configs(version: i16) -> BytesMut {
+    let request = IncrementalAlterConfigsRequest {
+        resources: vec![AlterConfigsResource {
+            resource_type: RESOURCE_TYPE_TOPIC,
+            resource_name: "compat".to_string(),
+            configs: vec![AlterableConfig {
+                name: "retention.ms".to_string(),
+                config_operation: ConfigOperation::Set,
+                value: Some("1000".to_string()),
+            }],
+        }],
+        validate_only: true,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_incremental_alter_configs(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response =
+        IncrementalAlterConfigsResponse::decode(body, version).map_err(|e| e.to_string())?;
+    // Every exchange is a fresh broker, so the topic does not exist
+    match response.responses.as_slice() {
+        [resource] if resource.error_code == ErrorCode::UNKNOWN_TOPIC_OR_PARTITION => Ok(()),
+        responses => Err(format!("unexpected responses {:?}", responses)),
+    }
+}
+
+fn build_describe_cluster(version: i16) -> BytesMut {
+    let request = DescribeClusterRequest {
+        include_cluster_authorized_operations: false,
+        endpoint_type: 1,
+    };
+    request.encode(version).unwrap()
+}
+
+fn validate_describe_cluster(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response = DescribeClusterResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match (response.error_code, response.brokers.as_slice()) {
+        (ErrorCode::NONE, [broker]) if broker.broker_id == response.controller_id => Ok(()),
+        _ => Err(format!("unexpected response {:?}", response)),
+    }
+}
+
+fn build_describe_topic_partitions(version: i16) -> BytesMut {
+    let request = DescribeTopicPartitionsRequest {
+        topics: vec!["compat".to_string()],
+        response_partition_limit: 100,
+        cursor: None,
+    };
+    let mut body = BytesMut::new();
+    request.encode_versioned(version, &mut body).unwrap();
+    body
+}
+
+fn validate_describe_topic_partitions(version: i16, body: &mut Bytes) -> Result<(), String> {
+    let response =
+        DescribeTopicPartitionsResponse::decode(body, version).map_err(|e| e.to_string())?;
+    match response.topics.as_slice() {
+        [topic] if topic.name.as_deref() == Some("compat") => Ok(()),
+        topics => Err(format!("unexpected topics {:?}", topics)),
+    }
+}
+
+/// Encodes request header v1, or v2 when the version is flexible
+fn encode_request_header(
+    buffer: &mut BytesMut,
+    api_key: i16,
+    version: i16,
+    correlation_id: i32,
+    flexible: bool,
+) {
+    buffer.put_i16(api_key);
+    buffer.put_i16(version);
+    buffer.put_i32(correlation_id);
+    WireFormat::encode_nullable_string(buffer, Some(CLIENT_ID)).unwrap();
+    if flexible {
+        // Empty tagged field section
+        WireFormat::encode_unsigned_varint(buffer, 0);
+    }
+}
+
+/// Sends one request over a fresh in-memory connection and returns the
+/// response with its length prefix stripped
+async fn exchange(request: &[u8]) -> Bytes {
+    let (mut client, _handle) = spawn_connection();
+    client.write_all(&frame(request)).await.unwrap();
+    Bytes::copy_from_slice(&read_response(&mut client).await[..])
+}
+
+/// Fetches the advertised version table from the broker itself
+async fn advertised_versions() -> HashMap<i16, (i16, i16)> {
+    let mut request = BytesMut::new();
+    encode_request_header(&mut request, 18, 0, 0, false);
+
+    let mut response = exchange(&request).await;
+    let _correlation_id = response.get_i32();
+    let response = ApiVersionsResponse::decode(&mut response, 0).unwrap();
+    assert_eq!(response.error_code, ErrorCode::NONE);
+
+    response
+        .api_keys
+        .into_iter()
+        .map(|range| (range.api_key, (range.min_version, range.max_version)))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_every_advertised_version_is_served() {
+    let cases: HashMap<i16, CompatCase> = cases()
+        .into_iter()
+        .map(|case| (case.api_key, case))
+        .collect();
+    let advertised = advertised_versions().await;
+    assert!(!advertised.is_empty(), "broker advertises no APIs");
+
+    let mut failures = Vec::new();
+    let mut correlation_id = 1000;
+
+    for (api_key, (min_version, max_version)) in advertised {
+        let Some(case) = cases.get(&api_key) else {
+            failures.push(format!(
+                "api key {} is advertised but has no compat case",
+                api_key
+            ));
+            continue;
+        };
+
+        for version in min_version..=max_version {
+            if let Some(reason) = case.skip_reason(version) {
+                eprintln!("skipping {} v{}: {}", case.name, version, reason);
+                continue;
+            }
+
+            correlation_id += 1;
+            let flexible = case.is_flexible(version);
+            let mut request = BytesMut::new();
+            encode_request_header(&mut request, api_key, version, correlation_id, flexible);
+            request.extend_from_slice(&(case.build_request)(version));
+
+            let mut response = exchange(&request).await;
+            if WireFormat::decode_i32(&mut response).ok() != Some(correlation_id) {
+                failures.push(format!(
+                    "{} v{}: correlation id mismatch",
+                    case.name, version
+                ));
+                continue;
+            }
+            // ApiVersions always answers with response header v0
+            if flexible && api_key != 18 {
+                if let Err(e) = WireFormat::decode_unsigned_varint(&mut response) {
+                    failures.push(format!("{} v{}: header tags: {}", case.name, version, e));
+                    continue;
+                }
+            }
+
+            match (case.validate_response)(version, &mut response) {
+                Ok(()) if response.has_remaining() => failures.push(format!(
+                    "{} v{}: {} trailing bytes in response",
+                    case.name,
+                    version,
+                    response.remaining()
+                )),
+                Ok(()) => {}
+                Err(e) => failures.push(format!("{} v{}: {}", case.name, version, e)),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "compatibility failures:\n{}",
+        failures.join("\n")
+    );
+}
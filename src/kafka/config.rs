@@ -0,0 +1,1030 @@
+use crate::kafka::storage::log_segment::LogConfig;
+use crate::logging::{debug, info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Definition of a configuration key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfigKey {
+    pub name: &'static str,
+    pub default: &'static str,
+    pub kind: ConfigKind,
+}
+
+/// Value type of a configuration key, used for validation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigKind {
+    Boolean,
+    Long,
+    String,
+}
+
+/// Every per-topic configuration key the broker understands
+///
+/// This table is the single source of truth for defaults and validation;
+/// anything not listed here is rejected as an unknown key.
+pub const TOPIC_CONFIG_KEYS: &[ConfigKey] = &[
+    ConfigKey {
+        name: "cleanup.policy",
+        default: "delete",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "retention.ms",
+        default: "604800000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "retention.bytes",
+        default: "-1",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "segment.bytes",
+        default: "1073741824",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "max.message.bytes",
+        default: "1048588",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "message.timestamp.after.max.ms",
+        default: "3600000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "message.timestamp.type",
+        default: "CreateTime",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "produce.enable",
+        default: "true",
+        kind: ConfigKind::Boolean,
+    },
+    ConfigKey {
+        name: "fetch.enable",
+        default: "true",
+        kind: ConfigKind::Boolean,
+    },
+];
+
+/// Looks up a per-topic configuration key by name
+pub fn topic_config_key(name: &str) -> Option<&'static ConfigKey> {
+    TOPIC_CONFIG_KEYS.iter().find(|key| key.name == name)
+}
+
+/// Broker-level keys accepted in `server.properties`, with Apache Kafka defaults
+pub const BROKER_CONFIG_KEYS: &[ConfigKey] = &[
+    ConfigKey {
+        name: "listeners",
+        default: "PLAINTEXT://:9092",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "advertised.listeners",
+        default: "",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "listener.security.protocol.map",
+        default: "PLAINTEXT:PLAINTEXT,CONTROLLER:PLAINTEXT",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "inter.broker.listener.name",
+        default: "PLAINTEXT",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "controller.listener.names",
+        default: "",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "controller.quorum.voters",
+        default: "",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "process.roles",
+        default: "",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "node.id",
+        default: "1",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "broker.rack",
+        default: "",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "log.dirs",
+        default: "/tmp/kafka-logs",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "socket.request.max.bytes",
+        default: "1048576",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "message.max.bytes",
+        default: "1048588",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "log.retention.ms",
+        default: "604800000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "log.retention.check.interval.ms",
+        default: "300000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "log.segment.bytes",
+        default: "1073741824",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "log.index.interval.bytes",
+        default: "4096",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "num.partitions",
+        default: "1",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "producer.id.expiration.ms",
+        default: "86400000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "auto.create.topics.enable",
+        default: "true",
+        kind: ConfigKind::Boolean,
+    },
+    ConfigKey {
+        name: "auto.format.empty.dirs",
+        default: "false",
+        kind: ConfigKind::Boolean,
+    },
+    ConfigKey {
+        name: "config.strict",
+        default: "false",
+        kind: ConfigKind::Boolean,
+    },
+    ConfigKey {
+        name: "debug.state.dump.enable",
+        default: "false",
+        kind: ConfigKind::Boolean,
+    },
+    ConfigKey {
+        name: "diagnostics.level",
+        default: "basic",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "log.segment.open.files.max",
+        default: "1000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "group.state.durability.enable",
+        default: "false",
+        kind: ConfigKind::Boolean,
+    },
+    ConfigKey {
+        name: "partition.throughput.window.ms",
+        default: "60000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "partition.skew.threshold.percent",
+        default: "50",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "topic.metrics.max.series",
+        default: "100",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "topic.metrics.rerank.interval.ms",
+        default: "60000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "topic.metrics.hash.names",
+        default: "false",
+        kind: ConfigKind::Boolean,
+    },
+    ConfigKey {
+        name: "delivery.latency.horizon.ms",
+        default: "300000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "quarantine.dir",
+        default: "",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "quarantine.max.frames",
+        default: "10",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "quarantine.window.ms",
+        default: "86400000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "quarantine.max.bytes",
+        default: "10485760",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "protocol.varint.strict",
+        default: "true",
+        kind: ConfigKind::Boolean,
+    },
+    ConfigKey {
+        name: "protocol.array.max.elements",
+        default: "100000",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "protocol.nesting.max.depth",
+        default: "16",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "client.id.max.length",
+        default: "256",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "produce.purgatory.delay.ms",
+        default: "0",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "produce.purgatory.shutdown.completion",
+        default: "timeout",
+        kind: ConfigKind::String,
+    },
+    ConfigKey {
+        name: "request.queue.workers",
+        default: "0",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "request.queue.control.max.consecutive",
+        default: "8",
+        kind: ConfigKind::Long,
+    },
+    ConfigKey {
+        name: "sasl.enabled.mechanisms",
+        default: "PLAIN",
+        kind: ConfigKind::String,
+    },
+];
+
+/// Looks up a broker configuration key by name
+pub fn broker_config_key(name: &str) -> Option<&'static ConfigKey> {
+    BROKER_CONFIG_KEYS.iter().find(|key| key.name == name)
+}
+
+/// Iterates every key the broker recognizes, broker-level and per-topic
+pub fn known_config_keys() -> impl Iterator<Item = &'static str> {
+    BROKER_CONFIG_KEYS
+        .iter()
+        .chain(TOPIC_CONFIG_KEYS)
+        .map(|key| key.name)
+}
+
+/// Placeholder written in place of sensitive configuration values
+pub const REDACTED_VALUE: &str = "[redacted]";
+
+/// Returns true for keys whose values must never be exported, such as
+/// passwords and JAAS login configuration
+pub fn is_sensitive_config_key(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["password", "secret", "jaas.config", "credentials"]
+        .iter()
+        .any(|marker| name.contains(marker))
+}
+
+/// Looks up a broker property, falling back to the key's default
+///
+/// Later occurrences of a key win, as when a properties file repeats it.
+pub fn broker_property<'a>(properties: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    properties
+        .iter()
+        .rev()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+        .or_else(|| broker_config_key(name).map(|key| key.default))
+}
+
+/// Configuration the broker was started with
+///
+/// Holds the properties read from `server.properties`; lookups of known
+/// keys fall back to the defaults from [`BROKER_CONFIG_KEYS`], which match
+/// Apache Kafka's. Keys the broker does not know are kept, so DescribeConfigs
+/// still reports them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BrokerConfig {
+    properties: Vec<(String, String)>,
+}
+
+impl BrokerConfig {
+    pub fn from_properties(properties: &[(String, String)]) -> Self {
+        let config = Self {
+            properties: properties.to_vec(),
+        };
+        for (key, value) in config.unknown_keys() {
+            debug!(key = %key, value = %value, "Keeping unknown broker property");
+        }
+        config
+    }
+
+    /// Parses the contents of a `server.properties` file
+    pub fn parse(contents: &str) -> Self {
+        Self::from_properties(&parse_properties(contents))
+    }
+
+    /// Properties set that are not broker configuration keys, by key
+    pub fn unknown_keys(&self) -> BTreeMap<&str, &str> {
+        self.properties
+            .iter()
+            .filter(|(key, _)| broker_config_key(key).is_none())
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+
+    /// The `listeners`, as `NAME://host:port` entries
+    pub fn listeners(&self) -> Vec<&str> {
+        self.get("listeners")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|listener| !listener.is_empty())
+            .collect()
+    }
+
+    /// Address of the first listener that is not a controller listener
+    ///
+    /// An empty host, as in the default `PLAINTEXT://:9092`, is taken as
+    /// 127.0.0.1, since the bound address is also the one advertised.
+    pub fn listen_addr(&self) -> Result<SocketAddr, ConfigError> {
+        let controllers: Vec<&str> = self
+            .get("controller.listener.names")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .collect();
+        let listener = self
+            .listeners()
+            .into_iter()
+            .find(|listener| {
+                let name = listener.split_once("://").map_or("", |(name, _)| name);
+                !controllers.contains(&name)
+            })
+            .ok_or_else(|| ConfigError::MissingValue("listeners".to_string()))?;
+        let invalid = || ConfigError::InvalidValue {
+            key: "listeners".to_string(),
+            value: listener.to_string(),
+        };
+        let (_, host_port) = listener.split_once("://").ok_or_else(invalid)?;
+        let (host, port) = host_port.rsplit_once(':').ok_or_else(invalid)?;
+        let host = match host.trim_start_matches('[').trim_end_matches(']') {
+            "" => "127.0.0.1",
+            host => host,
+        };
+        let port: u16 = port.parse().map_err(|_| invalid())?;
+        (host, port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(invalid)
+    }
+
+    /// The `log.dirs`, in the order given
+    pub fn log_dirs(&self) -> Vec<PathBuf> {
+        self.get("log.dirs")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// The first of the `log.dirs`, the one this broker keeps its logs in
+    pub fn log_dir(&self) -> Option<PathBuf> {
+        self.log_dirs().into_iter().next()
+    }
+
+    pub fn node_id(&self) -> Result<i32, ConfigError> {
+        self.parse_value("node.id")
+    }
+
+    /// Largest record batch a producer may send (`message.max.bytes`)
+    pub fn message_max_bytes(&self) -> Result<usize, ConfigError> {
+        self.parse_value("message.max.bytes")
+    }
+
+    pub fn log_retention_ms(&self) -> Result<i64, ConfigError> {
+        self.parse_value("log.retention.ms")
+    }
+
+    /// How often expired batches are looked for
+    /// (`log.retention.check.interval.ms`)
+    pub fn log_retention_check_interval(&self) -> Result<Duration, ConfigError> {
+        self.parse_value("log.retention.check.interval.ms")
+            .map(Duration::from_millis)
+    }
+
+    /// How partition segments are sized and indexed
+    pub fn log_config(&self) -> Result<LogConfig, ConfigError> {
+        Ok(LogConfig {
+            segment_bytes: self.parse_value("log.segment.bytes")?,
+            index_interval_bytes: self.parse_value("log.index.interval.bytes")?,
+        })
+    }
+
+    /// Partitions of a topic created without a partition count
+    pub fn num_partitions(&self) -> Result<i32, ConfigError> {
+        self.parse_value("num.partitions")
+    }
+
+    pub fn auto_create_topics_enable(&self) -> Result<bool, ConfigError> {
+        self.parse_value("auto.create.topics.enable")
+    }
+
+    /// Parses the effective value of a key
+    fn parse_value<T: FromStr>(&self, name: &str) -> Result<T, ConfigError> {
+        let value = self
+            .get(name)
+            .ok_or_else(|| ConfigError::MissingValue(name.to_string()))?;
+        value.parse().map_err(|_| ConfigError::InvalidValue {
+            key: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Resolves the effective value of a key
+    pub fn get(&self, name: &str) -> Option<&str> {
+        broker_property(&self.properties, name)
+    }
+
+    /// Returns true when the properties file sets the key
+    pub fn is_overridden(&self, name: &str) -> bool {
+        self.properties.iter().any(|(key, _)| key == name)
+    }
+
+    /// Effective values ordered by key: every known key, and any other key
+    /// the properties file sets
+    pub fn entries(&self) -> BTreeMap<&str, &str> {
+        let mut entries: BTreeMap<&str, &str> = BROKER_CONFIG_KEYS
+            .iter()
+            .map(|key| (key.name, key.default))
+            .collect();
+        for (name, value) in &self.properties {
+            entries.insert(name, value);
+        }
+        entries
+    }
+}
+
+/// Returns true for broker-internal topics such as `__consumer_offsets`
+pub fn is_internal_topic(name: &str) -> bool {
+    name.starts_with("__")
+}
+
+/// Errors raised when changing topic configuration
+#[derive(Error, Debug, PartialEq)]
+pub enum ConfigError {
+    #[error("Unknown configuration key: {0}")]
+    UnknownKey(String),
+
+    #[error("Invalid value {value:?} for configuration key {key}")]
+    InvalidValue { key: String, value: String },
+
+    #[error("Unknown configuration keys (strict mode): {}", .0.join(", "))]
+    StrictUnknownKeys(Vec<String>),
+
+    #[error("Missing value for configuration key {0}")]
+    MissingValue(String),
+
+    #[error("Operation {operation} is not supported on configuration key {key}")]
+    UnsupportedOperation { key: String, operation: i8 },
+}
+
+/// Effective configuration of a single topic
+///
+/// Holds only the overrides; every lookup falls back to the default from
+/// [`TOPIC_CONFIG_KEYS`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TopicConfig {
+    overrides: HashMap<String, String>,
+}
+
+impl TopicConfig {
+    /// Resolves the effective value of a key
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.overrides
+            .get(name)
+            .map(String::as_str)
+            .or_else(|| topic_config_key(name).map(|key| key.default))
+    }
+
+    /// Layers already validated overrides on top of these ones
+    pub fn with_overrides(mut self, overrides: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.overrides.extend(overrides);
+        self
+    }
+
+    /// Returns true when the key has an explicit override
+    pub fn is_overridden(&self, name: &str) -> bool {
+        self.overrides.contains_key(name)
+    }
+
+    /// Validates and applies an override, recording it in the audit log
+    pub fn set(&mut self, topic: &str, name: &str, value: &str) -> Result<(), ConfigError> {
+        validate(name, value)?;
+        let previous = self.overrides.insert(name.to_string(), value.to_string());
+        info!(
+            audit = true,
+            topic = topic,
+            key = name,
+            old_value = ?previous,
+            new_value = value,
+            "Topic configuration changed"
+        );
+        Ok(())
+    }
+
+    /// Removes an override so the key reverts to its default
+    pub fn delete(&mut self, topic: &str, name: &str) -> Result<(), ConfigError> {
+        if topic_config_key(name).is_none() {
+            return Err(ConfigError::UnknownKey(name.to_string()));
+        }
+        if let Some(previous) = self.overrides.remove(name) {
+            info!(
+                audit = true,
+                topic = topic,
+                key = name,
+                old_value = %previous,
+                "Topic configuration override removed"
+            );
+        }
+        Ok(())
+    }
+
+    /// Overrides in no particular order, with their actual values
+    pub fn overrides(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.overrides
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Overrides ordered by key, with sensitive values redacted
+    pub fn redacted_overrides(&self) -> BTreeMap<String, String> {
+        self.overrides
+            .iter()
+            .map(|(name, value)| {
+                let value = if is_sensitive_config_key(name) {
+                    REDACTED_VALUE.to_string()
+                } else {
+                    value.clone()
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    fn get_bool(&self, name: &str) -> bool {
+        self.get(name) == Some("true")
+    }
+
+    /// The topic's own `retention.ms`, if it has one; otherwise the
+    /// broker's `log.retention.ms` applies
+    pub fn retention_ms(&self) -> Option<i64> {
+        self.overrides
+            .get("retention.ms")
+            .and_then(|value| value.parse().ok())
+    }
+
+    /// How far ahead of its append time a batch's timestamp is trusted
+    /// (`message.timestamp.after.max.ms`)
+    pub fn message_timestamp_after_max_ms(&self) -> i64 {
+        self.get("message.timestamp.after.max.ms")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(i64::MAX)
+    }
+
+    /// Whether producers may write to the topic (`produce.enable`)
+    pub fn produce_enabled(&self) -> bool {
+        self.get_bool("produce.enable")
+    }
+
+    /// Whether consumers may read from the topic (`fetch.enable`)
+    pub fn fetch_enabled(&self) -> bool {
+        self.get_bool("fetch.enable")
+    }
+}
+
+/// Checks a value against the type of its configuration key
+pub fn validate(name: &str, value: &str) -> Result<(), ConfigError> {
+    let key = topic_config_key(name).ok_or_else(|| ConfigError::UnknownKey(name.to_string()))?;
+    let valid = match key.kind {
+        ConfigKind::Boolean => value == "true" || value == "false",
+        ConfigKind::Long => value.parse::<i64>().is_ok(),
+        ConfigKind::String => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(ConfigError::InvalidValue {
+            key: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Parses Java-style properties text into ordered key/value pairs
+///
+/// Blank lines and lines starting with `#` or `!` are skipped; keys and
+/// values are separated by the first `=` or `:`.
+pub fn parse_properties(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .map(|line| match line.find(['=', ':']) {
+            Some(split) => (
+                line[..split].trim().to_string(),
+                line[split + 1..].trim().to_string(),
+            ),
+            None => (line.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// Unrecognized key found in a configuration file
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownConfigKey {
+    pub key: String,
+    /// Closest known key, when one is similar enough to be a likely typo
+    pub suggestion: Option<&'static str>,
+}
+
+/// Result of checking a configuration file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigFileReport {
+    /// CRC32 of the file contents, logged so operators can tell which
+    /// revision of a file a broker started with
+    pub checksum: u32,
+    pub unknown_keys: Vec<UnknownConfigKey>,
+}
+
+/// Checks a properties file for unknown keys
+///
+/// Every unknown key is logged as a warning with a did-you-mean suggestion.
+/// In strict mode (the `strict` argument or `config.strict=true` in the file)
+/// unknown keys fail the check instead.
+pub fn check_config_file(contents: &str, strict: bool) -> Result<ConfigFileReport, ConfigError> {
+    let checksum = crc32fast::hash(contents.as_bytes());
+    let properties = parse_properties(contents);
+    let strict = strict
+        || properties
+            .iter()
+            .any(|(key, value)| key == "config.strict" && value == "true");
+
+    let unknown_keys: Vec<UnknownConfigKey> = properties
+        .iter()
+        .filter(|(key, _)| !known_config_keys().any(|known| known == key))
+        .map(|(key, _)| UnknownConfigKey {
+            key: key.clone(),
+            suggestion: suggest_config_key(key),
+        })
+        .collect();
+
+    for unknown in &unknown_keys {
+        warn!(
+            key = %unknown.key,
+            suggestion = unknown.suggestion,
+            "Unknown configuration key will be ignored"
+        );
+    }
+
+    if strict && !unknown_keys.is_empty() {
+        return Err(ConfigError::StrictUnknownKeys(
+            unknown_keys
+                .into_iter()
+                .map(|unknown| unknown.key)
+                .collect(),
+        ));
+    }
+
+    info!(
+        checksum = format!("{:08x}", checksum),
+        keys = properties.len(),
+        unknown_keys = unknown_keys.len(),
+        "Configuration file checked"
+    );
+
+    Ok(ConfigFileReport {
+        checksum,
+        unknown_keys,
+    })
+}
+
+/// Finds the known key closest to `key` by edit distance
+///
+/// Only suggests keys within a distance of 3, or a quarter of the key's
+/// length for long keys, so unrelated keys don't get nonsense suggestions.
+pub fn suggest_config_key(key: &str) -> Option<&'static str> {
+    let max_distance = (key.len() / 4).max(3);
+    known_config_keys()
+        .map(|known| (edit_distance(key, known), known))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Per-partition rejection produced by a topic access policy
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{error_message}")]
+pub struct AccessDenied {
+    pub error_message: String,
+}
+
+/// Checks whether a produce to `topic` is currently allowed
+pub fn check_produce_allowed(topic: &str, config: &TopicConfig) -> Result<(), AccessDenied> {
+    if config.produce_enabled() {
+        return Ok(());
+    }
+    Err(AccessDenied {
+        error_message: format!(
+            "Produce is disabled for topic {} (produce.enable=false)",
+            topic
+        ),
+    })
+}
+
+/// Checks whether a fetch from `topic` is currently allowed
+///
+/// Internal topics are always readable so coordinators keep working.
+pub fn check_fetch_allowed(topic: &str, config: &TopicConfig) -> Result<(), AccessDenied> {
+    if config.fetch_enabled() || is_internal_topic(topic) {
+        return Ok(());
+    }
+    Err(AccessDenied {
+        error_message: format!("Fetch is disabled for topic {} (fetch.enable=false)", topic),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sensitive_overrides_are_redacted() {
+        let mut config = TopicConfig::default();
+        config.set("orders", "retention.ms", "1000").unwrap();
+        config.overrides.insert(
+            "sasl.jaas.config".to_string(),
+            "PlainLoginModule password=\"hunter2\"".to_string(),
+        );
+
+        let overrides = config.redacted_overrides();
+        assert_eq!(overrides["retention.ms"], "1000");
+        assert_eq!(overrides["sasl.jaas.config"], REDACTED_VALUE);
+        assert!(is_sensitive_config_key("ssl.keystore.password"));
+        assert!(!is_sensitive_config_key("retention.ms"));
+    }
+
+    #[test]
+    fn test_defaults_resolve_without_overrides() {
+        let config = TopicConfig::default();
+        assert_eq!(config.get("retention.ms"), Some("604800000"));
+        assert!(config.produce_enabled());
+        assert!(config.fetch_enabled());
+        assert_eq!(config.get("no.such.key"), None);
+    }
+
+    #[test]
+    fn test_broker_config_entries() {
+        let config = BrokerConfig::from_properties(&[
+            ("num.partitions".to_string(), "3".to_string()),
+            ("ssl.key.password".to_string(), "hunter2".to_string()),
+        ]);
+        assert_eq!(config.get("num.partitions"), Some("3"));
+        assert_eq!(config.get("log.dirs"), Some("/tmp/kafka-logs"));
+        assert!(config.is_overridden("num.partitions"));
+        assert!(!config.is_overridden("log.dirs"));
+
+        let entries = config.entries();
+        assert_eq!(entries.len(), BROKER_CONFIG_KEYS.len() + 1);
+        assert_eq!(entries["num.partitions"], "3");
+        assert_eq!(entries["ssl.key.password"], "hunter2");
+    }
+
+    const SERVER_PROPERTIES: &str = include_str!("testdata/server.properties");
+
+    #[test]
+    fn test_server_properties_overrides() {
+        let config = BrokerConfig::parse(SERVER_PROPERTIES);
+        assert_eq!(
+            config.listeners(),
+            vec!["PLAINTEXT://127.0.0.1:19092", "CONTROLLER://:19093"]
+        );
+        assert_eq!(
+            config.listen_addr().unwrap(),
+            "127.0.0.1:19092".parse().unwrap()
+        );
+        assert_eq!(
+            config.log_dirs(),
+            vec![
+                PathBuf::from("/tmp/kraft-combined-logs"),
+                PathBuf::from("/tmp/spare-logs")
+            ]
+        );
+        assert_eq!(config.node_id(), Ok(2));
+        // The later of the two settings wins
+        assert_eq!(config.num_partitions(), Ok(3));
+        assert_eq!(config.message_max_bytes(), Ok(2097152));
+        assert_eq!(config.log_retention_ms(), Ok(3600000));
+        assert_eq!(config.auto_create_topics_enable(), Ok(false));
+        assert_eq!(
+            config.unknown_keys(),
+            BTreeMap::from([("group.initial.rebalance.delay.ms", "0")])
+        );
+    }
+
+    #[test]
+    fn test_server_properties_defaults() {
+        let config = BrokerConfig::parse("# nothing set\n\n");
+        assert_eq!(config.listeners(), vec!["PLAINTEXT://:9092"]);
+        assert_eq!(config.listen_addr().unwrap().port(), 9092);
+        assert!(config.listen_addr().unwrap().ip().is_loopback());
+        assert_eq!(config.log_dir(), Some(PathBuf::from("/tmp/kafka-logs")));
+        assert_eq!(config.node_id(), Ok(1));
+        assert_eq!(config.message_max_bytes(), Ok(1048588));
+        assert_eq!(config.log_retention_ms(), Ok(604800000));
+        assert_eq!(config.num_partitions(), Ok(1));
+        assert_eq!(config.auto_create_topics_enable(), Ok(true));
+        assert!(config.unknown_keys().is_empty());
+
+        let invalid = BrokerConfig::parse("num.partitions=many\nlisteners=PLAINTEXT://:port");
+        assert_eq!(
+            invalid.num_partitions(),
+            Err(ConfigError::InvalidValue {
+                key: "num.partitions".to_string(),
+                value: "many".to_string(),
+            })
+        );
+        assert!(invalid.listen_addr().is_err());
+    }
+
+    #[test]
+    fn test_disable_produce_then_recover() {
+        let mut config = TopicConfig::default();
+
+        config.set("orders", "produce.enable", "false").unwrap();
+        let denied = check_produce_allowed("orders", &config).unwrap_err();
+        assert!(denied.error_message.contains("produce.enable"));
+        assert!(check_fetch_allowed("orders", &config).is_ok());
+
+        config.set("orders", "produce.enable", "true").unwrap();
+        assert!(check_produce_allowed("orders", &config).is_ok());
+    }
+
+    #[test]
+    fn test_delete_restores_default() {
+        let mut config = TopicConfig::default();
+        config.set("orders", "fetch.enable", "false").unwrap();
+        assert!(check_fetch_allowed("orders", &config).is_err());
+
+        config.delete("orders", "fetch.enable").unwrap();
+        assert!(!config.is_overridden("fetch.enable"));
+        assert!(check_fetch_allowed("orders", &config).is_ok());
+    }
+
+    #[test]
+    fn test_internal_topics_exempt_from_fetch_disable() {
+        let mut config = TopicConfig::default();
+        config
+            .set("__consumer_offsets", "fetch.enable", "false")
+            .unwrap();
+        assert!(check_fetch_allowed("__consumer_offsets", &config).is_ok());
+    }
+
+    const TYPO_CONFIG: &str = "\
+# Broker settings
+node.id=1
+log.dirs=/tmp/kraft-combined-logs
+
+rentention.ms=1000
+num.partitons=3
+";
+
+    #[test]
+    fn test_parse_properties() {
+        let properties = parse_properties("# comment\n\n a = b \nc:d\n!bang\nflag\n");
+        assert_eq!(
+            properties,
+            vec![
+                ("a".to_string(), "b".to_string()),
+                ("c".to_string(), "d".to_string()),
+                ("flag".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_get_suggestions() {
+        let report = check_config_file(TYPO_CONFIG, false).unwrap();
+        assert_eq!(
+            report.unknown_keys,
+            vec![
+                UnknownConfigKey {
+                    key: "rentention.ms".to_string(),
+                    suggestion: Some("retention.ms"),
+                },
+                UnknownConfigKey {
+                    key: "num.partitons".to_string(),
+                    suggestion: Some("num.partitions"),
+                },
+            ]
+        );
+        assert_eq!(report.checksum, crc32fast::hash(TYPO_CONFIG.as_bytes()));
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_unknown_keys() {
+        let expected = Err(ConfigError::StrictUnknownKeys(vec![
+            "rentention.ms".to_string(),
+            "num.partitons".to_string(),
+        ]));
+        assert_eq!(check_config_file(TYPO_CONFIG, true), expected);
+
+        let in_file = format!("config.strict=true\n{}", TYPO_CONFIG);
+        assert_eq!(check_config_file(&in_file, false), expected);
+    }
+
+    #[test]
+    fn test_no_suggestion_for_unrelated_key() {
+        assert_eq!(suggest_config_key("completely.unrelated.setting"), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut config = TopicConfig::default();
+        assert_eq!(
+            config.set("orders", "produce.enable", "nope"),
+            Err(ConfigError::InvalidValue {
+                key: "produce.enable".to_string(),
+                value: "nope".to_string()
+            })
+        );
+        assert_eq!(
+            config.set("orders", "produce.enabled", "true"),
+            Err(ConfigError::UnknownKey("produce.enabled".to_string()))
+        );
+        assert!(config.set("orders", "retention.ms", "abc").is_err());
+    }
+}
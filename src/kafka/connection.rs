@@ -0,0 +1,238 @@
+use crate::kafka::diagnostics::DiagnosticsLevel;
+use crate::kafka::sasl::SaslState;
+use crate::logging::{debug, error, warn};
+use anyhow::Result;
+use bytes::{Buf, BytesMut};
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::sync::CancellationToken;
+
+/// Maximum number of bytes buffered ahead of the request being processed
+///
+/// Read-ahead only exists to notice disconnects while a request is in
+/// flight, so once this much pipelined data is waiting we stop reading
+/// until the current request completes.
+const READ_AHEAD_LIMIT: usize = 64 * 1024;
+
+/// Per-request context handed to request processing
+///
+/// Carries the connection's cancellation token, which fires when the client
+/// disconnects. Long-running or parked operations should select on
+/// [`RequestContext::cancelled`] so they are dropped as soon as nobody is
+/// left to receive the response.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub peer_addr: SocketAddr,
+    /// Diagnostics level read when the request arrived
+    pub diagnostics: DiagnosticsLevel,
+    cancellation: CancellationToken,
+}
+
+impl RequestContext {
+    /// Creates a context for a request received from `peer_addr`
+    pub fn new(peer_addr: SocketAddr, cancellation: CancellationToken) -> Self {
+        Self {
+            peer_addr,
+            diagnostics: DiagnosticsLevel::Off,
+            cancellation,
+        }
+    }
+
+    /// Runs the request with the given diagnostics level
+    pub fn with_diagnostics(mut self, level: DiagnosticsLevel) -> Self {
+        self.diagnostics = level;
+        self
+    }
+
+    /// Returns true once the owning connection has gone away
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Completes when the owning connection goes away
+    pub async fn cancelled(&self) {
+        self.cancellation.cancelled().await
+    }
+}
+
+/// State kept for the lifetime of one connection
+///
+/// Owned by the connection loop and lent to each request in turn, so
+/// requests on one connection see each other's changes in order.
+#[derive(Debug, Default)]
+pub struct ConnectionState {
+    pub sasl: SaslState,
+}
+
+/// Reads length-prefixed request frames from a stream
+///
+/// Unlike a plain `read_exact` loop, the reader can keep polling the socket
+/// while a request is being processed (see [`FrameReader::wait_for_disconnect`])
+/// without losing pipelined frames: anything read ahead is buffered and
+/// returned by later calls to [`FrameReader::read_frame`].
+pub struct FrameReader<R> {
+    reader: R,
+    buffer: BytesMut,
+    max_frame_size: usize,
+    eof: bool,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    /// Creates a frame reader rejecting frames larger than `max_frame_size`
+    pub fn new(reader: R, max_frame_size: usize) -> Self {
+        Self {
+            reader,
+            buffer: BytesMut::new(),
+            max_frame_size,
+            eof: false,
+        }
+    }
+
+    /// Reads the next frame, without its length prefix
+    ///
+    /// Returns `Ok(None)` when the peer closed the connection cleanly between
+    /// frames, and an error when it closed mid-frame or sent an oversized
+    /// frame.
+    pub async fn read_frame(&mut self) -> Result<Option<BytesMut>> {
+        loop {
+            if self.buffer.len() >= 4 {
+                let message_length =
+                    u32::from_be_bytes(self.buffer[..4].try_into().unwrap()) as usize;
+
+                if message_length == 0 {
+                    warn!("Received message with zero length");
+                    self.buffer.advance(4);
+                    continue;
+                }
+
+                if message_length > self.max_frame_size {
+                    error!(
+                        message_length = message_length,
+                        max_allowed = self.max_frame_size,
+                        "Message too large, closing connection"
+                    );
+                    return Err(anyhow::anyhow!(
+                        "Message too large: {} bytes",
+                        message_length
+                    ));
+                }
+
+                if self.buffer.len() >= 4 + message_length {
+                    self.buffer.advance(4);
+                    debug!(bytes_read = message_length, "Read request frame");
+                    return Ok(Some(self.buffer.split_to(message_length)));
+                }
+            }
+
+            if self.eof {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                return Err(anyhow::anyhow!(
+                    "Connection closed mid-frame with {} bytes buffered",
+                    self.buffer.len()
+                ));
+            }
+
+            if self.reader.read_buf(&mut self.buffer).await? == 0 {
+                self.eof = true;
+            }
+        }
+    }
+
+    /// Completes when the peer disconnects
+    ///
+    /// Data arriving in the meantime is buffered for later frames. This is
+    /// cancel-safe and meant to be raced against request processing.
+    pub async fn wait_for_disconnect(&mut self) {
+        loop {
+            if self.eof {
+                return;
+            }
+            if self.buffer.len() >= READ_AHEAD_LIMIT {
+                std::future::pending::<()>().await;
+            }
+            match self.reader.read_buf(&mut self.buffer).await {
+                Ok(0) | Err(_) => self.eof = true,
+                Ok(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[tokio::test]
+    async fn test_read_pipelined_frames() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut frames = FrameReader::new(server, 1024);
+
+        client.write_all(&frame(b"first")).await.unwrap();
+        client.write_all(&frame(b"second")).await.unwrap();
+        drop(client);
+
+        assert_eq!(&frames.read_frame().await.unwrap().unwrap()[..], b"first");
+        assert_eq!(&frames.read_frame().await.unwrap().unwrap()[..], b"second");
+        assert!(frames.read_frame().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_read_ahead_keeps_pipelined_frames() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut frames = FrameReader::new(server, 1024);
+
+        client.write_all(&frame(b"next")).await.unwrap();
+        drop(client);
+
+        // Detecting the disconnect buffers the pipelined frame rather than dropping it
+        frames.wait_for_disconnect().await;
+        assert_eq!(&frames.read_frame().await.unwrap().unwrap()[..], b"next");
+        assert!(frames.read_frame().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_is_rejected() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut frames = FrameReader::new(server, 8);
+
+        client.write_all(&frame(b"far too large")).await.unwrap();
+
+        assert!(frames.read_frame().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parked_operation_cancelled_on_disconnect() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut frames = FrameReader::new(server, 1024);
+        let cancellation = CancellationToken::new();
+        let context = RequestContext::new("127.0.0.1:50000".parse().unwrap(), cancellation.clone());
+
+        // A parked operation waiting far longer than the test allows
+        let parked = tokio::spawn(async move {
+            tokio::select! {
+                _ = context.cancelled() => true,
+                _ = tokio::time::sleep(Duration::from_secs(30)) => false,
+            }
+        });
+
+        drop(client);
+        frames.wait_for_disconnect().await;
+        cancellation.cancel();
+
+        let cancelled = tokio::time::timeout(Duration::from_secs(1), parked)
+            .await
+            .expect("parked operation outlived its connection")
+            .unwrap();
+        assert!(cancelled);
+    }
+}
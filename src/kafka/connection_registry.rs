@@ -0,0 +1,288 @@
+use crate::logging::info;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+/// Identifier assigned to each accepted connection
+pub type ConnectionId = u64;
+
+/// Errors raised when acting on a registered connection
+#[derive(Error, Debug, PartialEq)]
+pub enum ConnectionError {
+    #[error("Connection {0} not found")]
+    NotFound(ConnectionId),
+}
+
+/// Snapshot of one live connection
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConnectionInfo {
+    pub id: ConnectionId,
+    pub peer_addr: String,
+    pub client_id: Option<String>,
+    pub age_ms: u64,
+    pub in_flight: u64,
+    pub closing: bool,
+}
+
+/// Criteria for listing connections; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionFilter {
+    pub client_id: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+#[derive(Debug)]
+struct ConnectionEntry {
+    peer_addr: SocketAddr,
+    client_id: Mutex<Option<String>>,
+    connected_at: Instant,
+    in_flight: AtomicU64,
+    close: CancellationToken,
+}
+
+/// Live connections of the broker
+///
+/// Connections register themselves when accepted and are removed when their
+/// [`RegisteredConnection`] guard drops, however the connection ended.
+#[derive(Debug, Default)]
+pub struct ConnectionRegistry {
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<ConnectionId, Arc<ConnectionEntry>>>,
+}
+
+impl ConnectionRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a newly accepted connection
+    pub fn register(&self, peer_addr: SocketAddr) -> RegisteredConnection<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let entry = Arc::new(ConnectionEntry {
+            peer_addr,
+            client_id: Mutex::new(None),
+            connected_at: Instant::now(),
+            in_flight: AtomicU64::new(0),
+            close: CancellationToken::new(),
+        });
+        self.connections
+            .lock()
+            .unwrap()
+            .insert(id, Arc::clone(&entry));
+        RegisteredConnection {
+            registry: self,
+            id,
+            entry,
+        }
+    }
+
+    /// Lists live connections matching `filter`, ordered by id
+    pub fn list(&self, filter: &ConnectionFilter) -> Vec<ConnectionInfo> {
+        let connections = self.connections.lock().unwrap();
+        let mut listed: Vec<ConnectionInfo> = connections
+            .iter()
+            .filter(|(_, entry)| filter.ip.map_or(true, |ip| entry.peer_addr.ip() == ip))
+            .map(|(&id, entry)| ConnectionInfo {
+                id,
+                peer_addr: entry.peer_addr.to_string(),
+                client_id: entry.client_id.lock().unwrap().clone(),
+                age_ms: entry.connected_at.elapsed().as_millis() as u64,
+                in_flight: entry.in_flight.load(Ordering::Relaxed),
+                closing: entry.close.is_cancelled(),
+            })
+            .filter(|info| {
+                filter
+                    .client_id
+                    .as_ref()
+                    .map_or(true, |client_id| info.client_id.as_ref() == Some(client_id))
+            })
+            .collect();
+        listed.sort_by_key(|info| info.id);
+        listed
+    }
+
+    /// Number of live connections
+    pub fn len(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    /// Requests in flight across every connection
+    pub fn total_in_flight(&self) -> u64 {
+        self.connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.in_flight.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Returns true when no connections are registered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Asks a connection to close once its in-flight request completes
+    ///
+    /// The request is recorded in the audit log with the operator's reason.
+    /// Closing a connection that already ended, or asking twice, is harmless:
+    /// the former reports `NotFound`, the latter is a no-op.
+    pub fn request_close(&self, id: ConnectionId, reason: &str) -> Result<(), ConnectionError> {
+        let entry = self
+            .connections
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(ConnectionError::NotFound(id))?;
+
+        info!(
+            audit = true,
+            connection_id = id,
+            peer_addr = %entry.peer_addr,
+            client_id = ?entry.client_id.lock().unwrap(),
+            reason = reason,
+            "Operator requested connection close"
+        );
+        entry.close.cancel();
+        Ok(())
+    }
+}
+
+/// Registration of one connection, removed from the registry on drop
+#[derive(Debug)]
+pub struct RegisteredConnection<'a> {
+    registry: &'a ConnectionRegistry,
+    id: ConnectionId,
+    entry: Arc<ConnectionEntry>,
+}
+
+impl RegisteredConnection<'_> {
+    /// Identifier of this connection
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /// Records the client id reported in request headers
+    pub fn set_client_id(&self, client_id: Option<&str>) {
+        let mut current = self.entry.client_id.lock().unwrap();
+        if current.as_deref() != client_id {
+            *current = client_id.map(str::to_string);
+        }
+    }
+
+    /// Marks a request as in flight until the returned guard drops
+    pub fn begin_request(&self) -> InFlightGuard {
+        self.entry.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard {
+            entry: Arc::clone(&self.entry),
+        }
+    }
+
+    /// Returns true once an operator asked for this connection to close
+    pub fn is_close_requested(&self) -> bool {
+        self.entry.close.is_cancelled()
+    }
+
+    /// Completes when an operator asks for this connection to close
+    pub async fn close_requested(&self) {
+        self.entry.close.cancelled().await
+    }
+}
+
+impl Drop for RegisteredConnection<'_> {
+    fn drop(&mut self) {
+        self.registry.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Keeps a request counted as in flight while alive
+#[derive(Debug)]
+pub struct InFlightGuard {
+    entry: Arc<ConnectionEntry>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.entry.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_register_and_drop() {
+        let registry = ConnectionRegistry::new();
+        let first = registry.register(addr("10.0.0.1:1000"));
+        let second = registry.register(addr("10.0.0.2:1000"));
+        assert_ne!(first.id(), second.id());
+        assert_eq!(registry.len(), 2);
+
+        drop(first);
+        let listed = registry.list(&ConnectionFilter::default());
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, second.id());
+    }
+
+    #[test]
+    fn test_filters_by_client_id_and_ip() {
+        let registry = ConnectionRegistry::new();
+        let a = registry.register(addr("10.0.0.1:1000"));
+        let b = registry.register(addr("10.0.0.2:1000"));
+        a.set_client_id(Some("orders-consumer"));
+        b.set_client_id(Some("billing"));
+
+        let by_client = registry.list(&ConnectionFilter {
+            client_id: Some("billing".to_string()),
+            ip: None,
+        });
+        assert_eq!(by_client.len(), 1);
+        assert_eq!(by_client[0].id, b.id());
+
+        let by_ip = registry.list(&ConnectionFilter {
+            client_id: None,
+            ip: Some("10.0.0.1".parse().unwrap()),
+        });
+        assert_eq!(by_ip[0].id, a.id());
+    }
+
+    #[test]
+    fn test_in_flight_count() {
+        let registry = ConnectionRegistry::new();
+        let connection = registry.register(addr("10.0.0.1:1000"));
+
+        let guard = connection.begin_request();
+        assert_eq!(registry.list(&ConnectionFilter::default())[0].in_flight, 1);
+        drop(guard);
+        assert_eq!(registry.list(&ConnectionFilter::default())[0].in_flight, 0);
+    }
+
+    #[test]
+    fn test_close_after_connection_ended_is_not_found() {
+        let registry = ConnectionRegistry::new();
+        let connection = registry.register(addr("10.0.0.1:1000"));
+        let id = connection.id();
+
+        assert_eq!(registry.request_close(id, "test"), Ok(()));
+        assert!(connection.is_close_requested());
+        // Asking again while it drains is fine
+        assert_eq!(registry.request_close(id, "test"), Ok(()));
+
+        drop(connection);
+        assert_eq!(
+            registry.request_close(id, "test"),
+            Err(ConnectionError::NotFound(id))
+        );
+    }
+}
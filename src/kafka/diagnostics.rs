@@ -0,0 +1,238 @@
+use crate::kafka::config::{broker_property, ConfigError};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much diagnostic work the broker does per request
+///
+/// One knob for every diagnostic feature, so an incident needs a single
+/// change rather than a hunt for the right toggles. Each level includes
+/// everything below it; see [`DiagnosticFeature::min_level`] for what
+/// each one turns on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum DiagnosticsLevel {
+    /// Nothing beyond regular logging; the request path does no
+    /// formatting or copying for diagnostics
+    Off = 0,
+    Basic = 1,
+    Verbose = 2,
+    Capture = 3,
+}
+
+impl DiagnosticsLevel {
+    pub const ALL: [Self; 4] = [Self::Off, Self::Basic, Self::Verbose, Self::Capture];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Basic => "basic",
+            Self::Verbose => "verbose",
+            Self::Capture => "capture",
+        }
+    }
+
+    /// The next level, wrapping from Capture back to Off
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    /// Whether `feature` is active at this level
+    pub fn allows(self, feature: DiagnosticFeature) -> bool {
+        self >= feature.min_level()
+    }
+
+    /// Reads `diagnostics.level` from broker properties
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let value = broker_property(properties, "diagnostics.level")
+            .ok_or_else(|| ConfigError::UnknownKey("diagnostics.level".to_string()))?;
+        value.parse().map_err(|_| ConfigError::InvalidValue {
+            key: "diagnostics.level".to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    fn from_u8(value: u8) -> Self {
+        Self::ALL[value as usize]
+    }
+}
+
+impl fmt::Display for DiagnosticsLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DiagnosticsLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(value))
+            .ok_or_else(|| format!("unknown diagnostics level {:?}", value))
+    }
+}
+
+/// A diagnostic the request path performs only at some levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticFeature {
+    /// One log line per request with its sizes and processing time
+    RequestMetrics,
+    /// Saving unparseable requests to the quarantine directory
+    Quarantine,
+    /// Hex dump of every request that fails to parse
+    HexDump,
+    /// Field-by-field decode trace of every request that fails to parse
+    DecodeTrace,
+    /// Every request and response logged in the replay capture format
+    Capture,
+}
+
+impl DiagnosticFeature {
+    pub const ALL: [Self; 5] = [
+        Self::RequestMetrics,
+        Self::Quarantine,
+        Self::HexDump,
+        Self::DecodeTrace,
+        Self::Capture,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::RequestMetrics => "request_metrics",
+            Self::Quarantine => "quarantine",
+            Self::HexDump => "hex_dump",
+            Self::DecodeTrace => "decode_trace",
+            Self::Capture => "capture",
+        }
+    }
+
+    /// Lowest level at which the feature is active
+    ///
+    /// | feature          | level   |
+    /// |------------------|---------|
+    /// | `RequestMetrics` | basic   |
+    /// | `Quarantine`     | basic   |
+    /// | `HexDump`        | verbose |
+    /// | `DecodeTrace`    | verbose |
+    /// | `Capture`        | capture |
+    pub fn min_level(self) -> DiagnosticsLevel {
+        match self {
+            Self::RequestMetrics | Self::Quarantine => DiagnosticsLevel::Basic,
+            Self::HexDump | Self::DecodeTrace => DiagnosticsLevel::Verbose,
+            Self::Capture => DiagnosticsLevel::Capture,
+        }
+    }
+}
+
+/// The broker's current diagnostics level, changeable at runtime
+///
+/// Read once per request with a relaxed atomic load; the request then
+/// carries the level it started with, so a change never applies halfway
+/// through a request.
+#[derive(Debug)]
+pub struct Diagnostics {
+    level: AtomicU8,
+}
+
+impl Diagnostics {
+    pub fn new(level: DiagnosticsLevel) -> Self {
+        Self {
+            level: AtomicU8::new(level as u8),
+        }
+    }
+
+    pub fn level(&self) -> DiagnosticsLevel {
+        DiagnosticsLevel::from_u8(self.level.load(Ordering::Relaxed))
+    }
+
+    /// Sets the level, returning the previous one
+    pub fn set_level(&self, level: DiagnosticsLevel) -> DiagnosticsLevel {
+        DiagnosticsLevel::from_u8(self.level.swap(level as u8, Ordering::Relaxed))
+    }
+
+    /// Moves to the next level, wrapping from Capture back to Off, and
+    /// returns the new level
+    pub fn cycle(&self) -> DiagnosticsLevel {
+        let previous = self
+            .level
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |level| {
+                Some(DiagnosticsLevel::from_u8(level).next() as u8)
+            })
+            .unwrap();
+        DiagnosticsLevel::from_u8(previous).next()
+    }
+
+    /// Whether `feature` is active at the current level
+    pub fn enabled(&self, feature: DiagnosticFeature) -> bool {
+        self.level().allows(feature)
+    }
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self::new(DiagnosticsLevel::Basic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features_follow_the_documented_mapping() {
+        let active = |level: DiagnosticsLevel| -> Vec<DiagnosticFeature> {
+            DiagnosticFeature::ALL
+                .into_iter()
+                .filter(|&feature| level.allows(feature))
+                .collect()
+        };
+        use DiagnosticFeature::*;
+        assert_eq!(active(DiagnosticsLevel::Off), vec![]);
+        assert_eq!(
+            active(DiagnosticsLevel::Basic),
+            vec![RequestMetrics, Quarantine]
+        );
+        assert_eq!(
+            active(DiagnosticsLevel::Verbose),
+            vec![RequestMetrics, Quarantine, HexDump, DecodeTrace]
+        );
+        assert_eq!(active(DiagnosticsLevel::Capture), DiagnosticFeature::ALL);
+    }
+
+    #[test]
+    fn test_level_changes_at_runtime() {
+        let diagnostics = Diagnostics::default();
+        assert_eq!(diagnostics.level(), DiagnosticsLevel::Basic);
+        assert!(!diagnostics.enabled(DiagnosticFeature::HexDump));
+
+        assert_eq!(
+            diagnostics.set_level(DiagnosticsLevel::Verbose),
+            DiagnosticsLevel::Basic
+        );
+        assert!(diagnostics.enabled(DiagnosticFeature::HexDump));
+
+        assert_eq!(diagnostics.cycle(), DiagnosticsLevel::Capture);
+        assert_eq!(diagnostics.cycle(), DiagnosticsLevel::Off);
+        assert!(!diagnostics.enabled(DiagnosticFeature::Quarantine));
+        assert_eq!(diagnostics.cycle(), DiagnosticsLevel::Basic);
+    }
+
+    #[test]
+    fn test_level_from_properties() {
+        assert_eq!(
+            DiagnosticsLevel::from_properties(&[]).unwrap(),
+            DiagnosticsLevel::Basic
+        );
+        let verbose = vec![("diagnostics.level".to_string(), "Verbose".to_string())];
+        assert_eq!(
+            DiagnosticsLevel::from_properties(&verbose).unwrap(),
+            DiagnosticsLevel::Verbose
+        );
+        let invalid = vec![("diagnostics.level".to_string(), "loud".to_string())];
+        assert!(DiagnosticsLevel::from_properties(&invalid).is_err());
+    }
+}
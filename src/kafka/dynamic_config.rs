@@ -0,0 +1,367 @@
+use crate::kafka::config::{is_sensitive_config_key, validate, ConfigError, TopicConfig};
+use crate::kafka::diagnostics::Diagnostics;
+use crate::logging::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::RwLock;
+use thiserror::Error;
+
+/// Format version written to, and required of, dynamic configuration
+/// documents
+pub const DYNAMIC_CONFIG_FORMAT_VERSION: u32 = 1;
+
+/// A configuration value as it appears in an exported document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ExportedValue {
+    Value(String),
+    /// Stands in for a sensitive value left out of the export; on import it
+    /// only resolves against a value the target already holds
+    SecretRef {
+        secret_ref: String,
+    },
+}
+
+/// Snapshot of the configuration changed at runtime, for moving it from
+/// one broker to another
+///
+/// Only topic configuration overrides exist as dynamic state so far; new
+/// sections are added under a new `format_version`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DynamicConfigDocument {
+    pub format_version: u32,
+    /// Overrides by topic, then by key
+    #[serde(default)]
+    pub topic_configs: BTreeMap<String, BTreeMap<String, ExportedValue>>,
+}
+
+/// What an import changed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportSummary {
+    pub topics: usize,
+    pub overrides: usize,
+}
+
+/// Errors that reject a whole document before anything is applied
+#[derive(Error, Debug, PartialEq)]
+pub enum DynamicConfigError {
+    #[error(
+        "Unsupported dynamic config format version {0} (expected {DYNAMIC_CONFIG_FORMAT_VERSION})"
+    )]
+    UnsupportedFormatVersion(u32),
+
+    #[error("Invalid configuration for topic {topic}: {source}")]
+    InvalidTopicConfig {
+        topic: String,
+        #[source]
+        source: ConfigError,
+    },
+
+    #[error(
+        "Secret reference {0} has no matching value on this broker; export with secrets included"
+    )]
+    UnresolvedSecret(String),
+
+    #[error("Failed to record the configuration change: {0}")]
+    Persist(String),
+}
+
+fn secret_ref(topic: &str, name: &str) -> String {
+    format!("topic/{}/{}", topic, name)
+}
+
+/// Runtime configuration changes, keyed by resource
+#[derive(Debug, Default)]
+pub struct DynamicConfigRegistry {
+    topics: RwLock<BTreeMap<String, TopicConfig>>,
+    /// Broker-local incident tooling, so never part of an exported document
+    diagnostics: Diagnostics,
+}
+
+impl DynamicConfigRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The diagnostics level consulted on every request
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Effective configuration of a topic; defaults when nothing is overridden
+    pub fn topic_config(&self, topic: &str) -> TopicConfig {
+        self.topics
+            .read()
+            .unwrap()
+            .get(topic)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Topics that have at least one override
+    pub fn topic_configs(&self) -> BTreeMap<String, TopicConfig> {
+        self.topics.read().unwrap().clone()
+    }
+
+    /// Validates and applies one topic override
+    pub fn set_topic_config(
+        &self,
+        topic: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<(), ConfigError> {
+        self.topics
+            .write()
+            .unwrap()
+            .entry(topic.to_string())
+            .or_default()
+            .set(topic, name, value)
+    }
+
+    /// Exports every override
+    ///
+    /// Sensitive values are replaced by opaque references unless
+    /// `include_secrets` is set.
+    pub fn export(&self, include_secrets: bool) -> DynamicConfigDocument {
+        let topics = self.topics.read().unwrap();
+        let topic_configs = topics
+            .iter()
+            .map(|(topic, config)| {
+                let overrides = config
+                    .overrides()
+                    .map(|(name, value)| {
+                        let value = if is_sensitive_config_key(name) && !include_secrets {
+                            ExportedValue::SecretRef {
+                                secret_ref: secret_ref(topic, name),
+                            }
+                        } else {
+                            ExportedValue::Value(value.to_string())
+                        };
+                        (name.to_string(), value)
+                    })
+                    .collect();
+                (topic.clone(), overrides)
+            })
+            .collect();
+        DynamicConfigDocument {
+            format_version: DYNAMIC_CONFIG_FORMAT_VERSION,
+            topic_configs,
+        }
+    }
+
+    /// Replaces the dynamic configuration with the document's
+    ///
+    /// The whole document is validated first: on error nothing changes.
+    /// Overrides missing from the document are removed.
+    pub fn import(
+        &self,
+        document: &DynamicConfigDocument,
+    ) -> Result<ImportSummary, DynamicConfigError> {
+        self.import_under(document, |apply| {
+            apply();
+            Ok(())
+        })
+    }
+
+    /// Like [`DynamicConfigRegistry::import`], but the validated change is
+    /// handed to `commit` to run
+    ///
+    /// `commit` does whatever must be durable before the change applies,
+    /// such as persisting a new metadata epoch, then calls the change. If it
+    /// fails without calling the change, nothing is applied.
+    pub fn import_under(
+        &self,
+        document: &DynamicConfigDocument,
+        commit: impl FnOnce(Box<dyn FnOnce() + '_>) -> io::Result<()>,
+    ) -> Result<ImportSummary, DynamicConfigError> {
+        if document.format_version != DYNAMIC_CONFIG_FORMAT_VERSION {
+            return Err(DynamicConfigError::UnsupportedFormatVersion(
+                document.format_version,
+            ));
+        }
+
+        let mut topics = self.topics.write().unwrap();
+        let mut resolved = BTreeMap::new();
+        for (topic, overrides) in &document.topic_configs {
+            let mut values = BTreeMap::new();
+            for (name, value) in overrides {
+                let value = match value {
+                    ExportedValue::Value(value) => value.clone(),
+                    ExportedValue::SecretRef { secret_ref } => topics
+                        .get(topic)
+                        .filter(|config| config.is_overridden(name))
+                        .and_then(|config| config.get(name))
+                        .map(str::to_string)
+                        .ok_or_else(|| DynamicConfigError::UnresolvedSecret(secret_ref.clone()))?,
+                };
+                validate(name, &value).map_err(|source| {
+                    DynamicConfigError::InvalidTopicConfig {
+                        topic: topic.clone(),
+                        source,
+                    }
+                })?;
+                values.insert(name.clone(), value);
+            }
+            if !values.is_empty() {
+                resolved.insert(topic.clone(), values);
+            }
+        }
+
+        let summary = ImportSummary {
+            topics: resolved.len(),
+            overrides: resolved.values().map(BTreeMap::len).sum(),
+        };
+
+        // Everything validated: apply, so each change is audited
+        let apply = move || {
+            topics.retain(|topic, _| resolved.contains_key(topic));
+            for (topic, values) in resolved {
+                let config = topics.entry(topic.clone()).or_default();
+                let stale: Vec<String> = config
+                    .overrides()
+                    .map(|(name, _)| name.to_string())
+                    .filter(|name| !values.contains_key(name))
+                    .collect();
+                for name in stale {
+                    config
+                        .delete(&topic, &name)
+                        .expect("existing overrides have known keys");
+                }
+                for (name, value) in &values {
+                    if config.is_overridden(name) && config.get(name) == Some(value.as_str()) {
+                        continue;
+                    }
+                    config
+                        .set(&topic, name, value)
+                        .expect("validated before applying");
+                }
+            }
+        };
+        commit(Box::new(apply)).map_err(|e| DynamicConfigError::Persist(e.to_string()))?;
+        info!(
+            audit = true,
+            topics = summary.topics,
+            overrides = summary.overrides,
+            "Imported dynamic configuration"
+        );
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::broker::KafkaBroker;
+    use crate::kafka::config::TOPIC_CONFIG_KEYS;
+
+    fn describe(broker: &KafkaBroker, topic: &str) -> Vec<(String, Option<String>, bool)> {
+        let config = broker.dynamic_config().topic_config(topic);
+        TOPIC_CONFIG_KEYS
+            .iter()
+            .map(|key| {
+                (
+                    key.name.to_string(),
+                    config.get(key.name).map(str::to_string),
+                    config.is_overridden(key.name),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_export_import_parity_between_brokers() {
+        let source = KafkaBroker::new();
+        let registry = source.dynamic_config();
+        registry
+            .set_topic_config("orders", "retention.ms", "3600000")
+            .unwrap();
+        registry
+            .set_topic_config("orders", "cleanup.policy", "compact")
+            .unwrap();
+        registry
+            .set_topic_config("audit", "produce.enable", "false")
+            .unwrap();
+
+        let json = serde_json::to_string(&registry.export(false)).unwrap();
+        let document: DynamicConfigDocument = serde_json::from_str(&json).unwrap();
+        assert_eq!(document.format_version, DYNAMIC_CONFIG_FORMAT_VERSION);
+
+        let target = KafkaBroker::new();
+        target
+            .dynamic_config()
+            .set_topic_config("stale", "retention.ms", "1")
+            .unwrap();
+        let summary = target.dynamic_config().import(&document).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                topics: 2,
+                overrides: 3
+            }
+        );
+
+        for topic in ["orders", "audit", "stale"] {
+            assert_eq!(
+                describe(&source, topic),
+                describe(&target, topic),
+                "{}",
+                topic
+            );
+        }
+        assert_eq!(target.dynamic_config().export(false), document);
+        assert_eq!(source.dump().topic_configs, target.dump().topic_configs);
+    }
+
+    #[test]
+    fn test_invalid_document_changes_nothing() {
+        let registry = DynamicConfigRegistry::new();
+        registry
+            .set_topic_config("orders", "retention.ms", "3600000")
+            .unwrap();
+        let before = registry.export(true);
+
+        let mut document = before.clone();
+        document.topic_configs.insert(
+            "payments".to_string(),
+            BTreeMap::from([(
+                "retention.ms".to_string(),
+                ExportedValue::Value("forever".to_string()),
+            )]),
+        );
+        document.topic_configs.get_mut("orders").unwrap().insert(
+            "cleanup.policy".to_string(),
+            ExportedValue::Value("compact".to_string()),
+        );
+        assert!(matches!(
+            registry.import(&document),
+            Err(DynamicConfigError::InvalidTopicConfig { ref topic, .. }) if topic == "payments"
+        ));
+        assert_eq!(registry.export(true), before);
+
+        document.format_version = 2;
+        assert_eq!(
+            registry.import(&document),
+            Err(DynamicConfigError::UnsupportedFormatVersion(2))
+        );
+    }
+
+    #[test]
+    fn test_secret_references_only_resolve_locally() {
+        let value: ExportedValue =
+            serde_json::from_str(r#"{"secret_ref":"topic/orders/sasl.password"}"#).unwrap();
+        let document = DynamicConfigDocument {
+            format_version: DYNAMIC_CONFIG_FORMAT_VERSION,
+            topic_configs: BTreeMap::from([(
+                "orders".to_string(),
+                BTreeMap::from([("sasl.password".to_string(), value)]),
+            )]),
+        };
+        assert_eq!(
+            DynamicConfigRegistry::new().import(&document),
+            Err(DynamicConfigError::UnresolvedSecret(
+                "topic/orders/sasl.password".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,605 @@
+use crate::kafka::config::{AccessDenied, ConfigError};
+use crate::kafka::group_coordinator::GroupError;
+use crate::kafka::offset_store::OffsetStoreError;
+use crate::kafka::producer_state::ProducerStateError;
+use crate::kafka::sasl::SaslError;
+use crate::kafka::storage::StorageError;
+use crate::protocol::{ApiKey, ErrorCode, ProtocolError};
+use thiserror::Error;
+
+/// First Fetch version that identifies topics by id
+const FETCH_TOPIC_ID_VERSION: i16 = 13;
+
+/// Failures a request handler can report to the client
+///
+/// Every variant is turned into a wire code by [`wire_error`]; handlers
+/// return these instead of picking codes themselves.
+#[derive(Error, Debug)]
+pub enum BrokerError {
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    ProducerState(#[from] ProducerStateError),
+
+    #[error(transparent)]
+    Group(#[from] GroupError),
+
+    #[error(transparent)]
+    OffsetStore(#[from] OffsetStoreError),
+
+    #[error(transparent)]
+    Sasl(#[from] SaslError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    AccessDenied(#[from] AccessDenied),
+
+    #[error("Unsupported version {version} for API {api_key}")]
+    UnsupportedVersion { api_key: i16, version: i16 },
+
+    #[error("Unsupported API {api_key}")]
+    UnsupportedApi { api_key: i16 },
+
+    #[error("Unknown topic or partition {topic}-{partition}")]
+    UnknownTopicOrPartition { topic: String, partition: i32 },
+
+    #[error("Unsupported record format magic {magic}")]
+    UnsupportedMessageFormat { magic: i8 },
+
+    #[error("Corrupt records: {0}")]
+    CorruptRecords(String),
+
+    #[error("Invalid replication factor {replication_factor} for topic '{topic}'")]
+    InvalidReplicationFactor {
+        topic: String,
+        replication_factor: i16,
+    },
+
+    #[error("Invalid replica assignment for topic '{topic}': {reason}")]
+    InvalidReplicaAssignment { topic: String, reason: String },
+
+    #[error("No offset for timestamp {timestamp} in {topic}-{partition}")]
+    OffsetNotAvailable {
+        topic: String,
+        partition: i32,
+        timestamp: i64,
+    },
+
+    #[error("Unknown coordinator key type {key_type}")]
+    UnknownCoordinatorType { key_type: i8 },
+
+    #[error("No transaction coordinator for transactional id '{transactional_id}'")]
+    TransactionsUnsupported { transactional_id: String },
+
+    #[error("Producer id {producer_id} and epoch {producer_epoch} must both be set or both be -1")]
+    InvalidProducerIdAndEpoch {
+        producer_id: i64,
+        producer_epoch: i16,
+    },
+
+    #[error("This broker does not serve the controller endpoints")]
+    ControllerEndpointsUnsupported,
+
+    #[error("Unknown endpoint type {endpoint_type}")]
+    UnknownEndpointType { endpoint_type: i8 },
+
+    #[error("Unsupported resource type {resource_type}")]
+    UnsupportedResourceType { resource_type: i8 },
+
+    #[error("Unexpected broker id '{resource_name}', expected {node_id} or an empty name")]
+    UnexpectedBrokerId { resource_name: String, node_id: i32 },
+}
+
+/// Wire error code for a broker failure
+///
+/// The match is exhaustive, nested enums included, so a new variant does
+/// not compile until it has been given a code.
+pub fn wire_error(error: &BrokerError) -> ErrorCode {
+    match error {
+        // A located error is answered for what went wrong, not where
+        BrokerError::Protocol(error) => match error.root() {
+            ProtocolError::InvalidFormat(_)
+            | ProtocolError::SerializationError(_)
+            | ProtocolError::InsufficientBytes { .. }
+            | ProtocolError::InvalidUtf8(_)
+            | ProtocolError::StringTooLong { .. }
+            | ProtocolError::InvalidLength { .. }
+            | ProtocolError::NonCanonicalVarint { .. }
+            | ProtocolError::LimitExceeded { .. }
+            | ProtocolError::InvalidClientId { .. }
+            | ProtocolError::At { .. }
+            | ProtocolError::BufferOverflow { .. }
+            | ProtocolError::FlexibilityMismatch { .. } => ErrorCode::INVALID_REQUEST,
+            ProtocolError::UnsupportedCompression { .. } => ErrorCode::UNSUPPORTED_COMPRESSION_TYPE,
+        },
+        BrokerError::Storage(error) => match error {
+            StorageError::OffsetOutOfRange { .. } => ErrorCode::OFFSET_OUT_OF_RANGE,
+            StorageError::TopicAlreadyExists { .. } => ErrorCode::TOPIC_ALREADY_EXISTS,
+            StorageError::InvalidPartitions { .. } => ErrorCode::INVALID_PARTITIONS,
+            StorageError::InvalidTopic { .. } => ErrorCode::INVALID_TOPIC_EXCEPTION,
+            StorageError::UnknownTopic { .. } => ErrorCode::UNKNOWN_TOPIC_OR_PARTITION,
+            StorageError::UnknownTopicId { .. } => ErrorCode::UNKNOWN_TOPIC_ID,
+            StorageError::Io { .. } => ErrorCode::KAFKA_STORAGE_ERROR,
+            StorageError::ProducerState(error) => producer_state_code(error),
+        },
+        BrokerError::ProducerState(error) => producer_state_code(error),
+        BrokerError::Group(error) => match error {
+            GroupError::InvalidGroupId { .. } => ErrorCode::INVALID_GROUP_ID,
+            GroupError::InvalidSessionTimeout { .. } => ErrorCode::INVALID_SESSION_TIMEOUT,
+            GroupError::InconsistentGroupProtocol { .. } => ErrorCode::INCONSISTENT_GROUP_PROTOCOL,
+            GroupError::MemberIdRequired { .. } => ErrorCode::MEMBER_ID_REQUIRED,
+            GroupError::UnknownMemberId { .. } => ErrorCode::UNKNOWN_MEMBER_ID,
+            GroupError::IllegalGeneration { .. } => ErrorCode::ILLEGAL_GENERATION,
+            GroupError::RebalanceInProgress { .. } => ErrorCode::REBALANCE_IN_PROGRESS,
+            GroupError::GroupIdNotFound { .. } => ErrorCode::GROUP_ID_NOT_FOUND,
+        },
+        BrokerError::OffsetStore(error) => match error {
+            OffsetStoreError::MetadataTooLarge { .. } => ErrorCode::OFFSET_METADATA_TOO_LARGE,
+        },
+        BrokerError::Sasl(error) => match error {
+            SaslError::UnsupportedMechanism { .. } => ErrorCode::UNSUPPORTED_SASL_MECHANISM,
+            SaslError::IllegalState { .. } => ErrorCode::ILLEGAL_SASL_STATE,
+            SaslError::AuthenticationFailed { .. } => ErrorCode::SASL_AUTHENTICATION_FAILED,
+        },
+        BrokerError::Config(error) => match error {
+            ConfigError::UnknownKey(_)
+            | ConfigError::InvalidValue { .. }
+            | ConfigError::StrictUnknownKeys(_)
+            | ConfigError::MissingValue(_)
+            | ConfigError::UnsupportedOperation { .. } => ErrorCode::INVALID_CONFIG,
+        },
+        BrokerError::AccessDenied(_) => ErrorCode::POLICY_VIOLATION,
+        BrokerError::UnsupportedVersion { .. } | BrokerError::UnsupportedApi { .. } => {
+            ErrorCode::UNSUPPORTED_VERSION
+        }
+        BrokerError::UnknownTopicOrPartition { .. } => ErrorCode::UNKNOWN_TOPIC_OR_PARTITION,
+        BrokerError::UnsupportedMessageFormat { .. } => ErrorCode::UNSUPPORTED_FOR_MESSAGE_FORMAT,
+        BrokerError::CorruptRecords(_) => ErrorCode::CORRUPT_MESSAGE,
+        BrokerError::InvalidReplicationFactor { .. } => ErrorCode::INVALID_REPLICATION_FACTOR,
+        BrokerError::InvalidReplicaAssignment { .. } => ErrorCode::INVALID_REPLICA_ASSIGNMENT,
+        BrokerError::OffsetNotAvailable { .. } => ErrorCode::OFFSET_NOT_AVAILABLE,
+        BrokerError::UnknownCoordinatorType { .. } => ErrorCode::INVALID_REQUEST,
+        BrokerError::TransactionsUnsupported { .. } => ErrorCode::COORDINATOR_NOT_AVAILABLE,
+        BrokerError::InvalidProducerIdAndEpoch { .. } => ErrorCode::INVALID_REQUEST,
+        BrokerError::ControllerEndpointsUnsupported => ErrorCode::MISMATCHED_ENDPOINT_TYPE,
+        BrokerError::UnknownEndpointType { .. } => ErrorCode::UNSUPPORTED_ENDPOINT_TYPE,
+        BrokerError::UnsupportedResourceType { .. } | BrokerError::UnexpectedBrokerId { .. } => {
+            ErrorCode::INVALID_REQUEST
+        }
+    }
+}
+
+/// Wire error code for a rejected idempotent producer batch or init
+fn producer_state_code(error: &ProducerStateError) -> ErrorCode {
+    match error {
+        ProducerStateError::UnknownProducerId { .. } => ErrorCode::UNKNOWN_PRODUCER_ID,
+        ProducerStateError::InvalidProducerEpoch { .. } => ErrorCode::INVALID_PRODUCER_EPOCH,
+        ProducerStateError::OutOfOrderSequence { .. } => ErrorCode::OUT_OF_ORDER_SEQUENCE_NUMBER,
+        ProducerStateError::InvalidProducerIdMapping { .. } => {
+            ErrorCode::INVALID_PRODUCER_ID_MAPPING
+        }
+    }
+}
+
+/// Wire error code for a failure while serving `api_key` at `version`
+///
+/// Applies the cases where the same failure is reported differently
+/// depending on the request, and falls back to [`wire_error`].
+pub fn wire_error_for(api_key: ApiKey, version: i16, error: &BrokerError) -> ErrorCode {
+    match (api_key, error) {
+        // Fetch v13+ names topics by id, so a missing topic is an unknown id
+        (ApiKey::Fetch, BrokerError::UnknownTopicOrPartition { .. })
+            if version >= FETCH_TOPIC_ID_VERSION =>
+        {
+            ErrorCode::UNKNOWN_TOPIC_ID
+        }
+        _ => wire_error(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unknown_topic() -> BrokerError {
+        BrokerError::UnknownTopicOrPartition {
+            topic: "orders".to_string(),
+            partition: 0,
+        }
+    }
+
+    #[test]
+    fn test_every_mapping() {
+        let table: Vec<(BrokerError, ErrorCode)> = vec![
+            (
+                ProtocolError::InvalidFormat("bad".to_string()).into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::SerializationError("bad".to_string()).into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::insufficient_bytes(4, 2).into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::InvalidUtf8("bad".to_string()).into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::string_too_long(40_000, 32_767).into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::invalid_length(-2).into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::buffer_overflow(8, 4).into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::InvalidClientId {
+                    length: 4096,
+                    limit: 256,
+                }
+                .into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::LimitExceeded {
+                    field: "string length",
+                    limit: 32_767,
+                    requested: 1 << 31,
+                }
+                .into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::FlexibilityMismatch {
+                    api_key: 3,
+                    api_version: 4,
+                    header_flexible: false,
+                }
+                .into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                StorageError::OffsetOutOfRange {
+                    offset: 10,
+                    log_start_offset: 0,
+                    log_end_offset: 5,
+                }
+                .into(),
+                ErrorCode::OFFSET_OUT_OF_RANGE,
+            ),
+            (
+                StorageError::TopicAlreadyExists {
+                    topic: "orders".to_string(),
+                }
+                .into(),
+                ErrorCode::TOPIC_ALREADY_EXISTS,
+            ),
+            (
+                StorageError::InvalidPartitions {
+                    topic: "orders".to_string(),
+                    num_partitions: 0,
+                }
+                .into(),
+                ErrorCode::INVALID_PARTITIONS,
+            ),
+            (
+                StorageError::InvalidTopic {
+                    topic: "..".to_string(),
+                    reason: "'.' and '..' are not allowed".to_string(),
+                }
+                .into(),
+                ErrorCode::INVALID_TOPIC_EXCEPTION,
+            ),
+            (
+                StorageError::UnknownTopic {
+                    topic: "orders".to_string(),
+                }
+                .into(),
+                ErrorCode::UNKNOWN_TOPIC_OR_PARTITION,
+            ),
+            (
+                StorageError::UnknownTopicId {
+                    topic_id: uuid::Uuid::from_u128(1),
+                }
+                .into(),
+                ErrorCode::UNKNOWN_TOPIC_ID,
+            ),
+            (
+                StorageError::Io {
+                    path: "orders-0/00000000000000000000.log".into(),
+                    message: "permission denied".to_string(),
+                }
+                .into(),
+                ErrorCode::KAFKA_STORAGE_ERROR,
+            ),
+            (
+                ProducerStateError::UnknownProducerId { producer_id: 1 }.into(),
+                ErrorCode::UNKNOWN_PRODUCER_ID,
+            ),
+            (
+                ProducerStateError::InvalidProducerEpoch {
+                    producer_id: 1,
+                    epoch: 0,
+                    current_epoch: 1,
+                }
+                .into(),
+                ErrorCode::INVALID_PRODUCER_EPOCH,
+            ),
+            (
+                ProducerStateError::OutOfOrderSequence {
+                    producer_id: 1,
+                    expected: 1,
+                    received: 3,
+                }
+                .into(),
+                ErrorCode::OUT_OF_ORDER_SEQUENCE_NUMBER,
+            ),
+            (
+                ProducerStateError::InvalidProducerIdMapping {
+                    producer_id: 1,
+                    epoch: 0,
+                }
+                .into(),
+                ErrorCode::INVALID_PRODUCER_ID_MAPPING,
+            ),
+            (
+                SaslError::UnsupportedMechanism {
+                    mechanism: "GSSAPI".to_string(),
+                }
+                .into(),
+                ErrorCode::UNSUPPORTED_SASL_MECHANISM,
+            ),
+            (
+                SaslError::IllegalState {
+                    request: "SaslAuthenticate",
+                    state: "before a successful SaslHandshake",
+                }
+                .into(),
+                ErrorCode::ILLEGAL_SASL_STATE,
+            ),
+            (
+                SaslError::AuthenticationFailed {
+                    reason: "malformed PLAIN token".to_string(),
+                }
+                .into(),
+                ErrorCode::SASL_AUTHENTICATION_FAILED,
+            ),
+            (
+                ConfigError::UnknownKey("no.such.key".to_string()).into(),
+                ErrorCode::INVALID_CONFIG,
+            ),
+            (
+                ConfigError::InvalidValue {
+                    key: "retention.ms".to_string(),
+                    value: "soon".to_string(),
+                }
+                .into(),
+                ErrorCode::INVALID_CONFIG,
+            ),
+            (
+                ConfigError::StrictUnknownKeys(vec!["no.such.key".to_string()]).into(),
+                ErrorCode::INVALID_CONFIG,
+            ),
+            (
+                ConfigError::MissingValue("retention.ms".to_string()).into(),
+                ErrorCode::INVALID_CONFIG,
+            ),
+            (
+                ConfigError::UnsupportedOperation {
+                    key: "cleanup.policy".to_string(),
+                    operation: 2,
+                }
+                .into(),
+                ErrorCode::INVALID_CONFIG,
+            ),
+            (
+                AccessDenied {
+                    error_message: "Produce is disabled".to_string(),
+                }
+                .into(),
+                ErrorCode::POLICY_VIOLATION,
+            ),
+            (
+                BrokerError::UnsupportedVersion {
+                    api_key: 0,
+                    version: 99,
+                },
+                ErrorCode::UNSUPPORTED_VERSION,
+            ),
+            (
+                BrokerError::UnsupportedApi { api_key: 1000 },
+                ErrorCode::UNSUPPORTED_VERSION,
+            ),
+            (unknown_topic(), ErrorCode::UNKNOWN_TOPIC_OR_PARTITION),
+            (
+                ProtocolError::UnsupportedCompression { codec: 4 }.into(),
+                ErrorCode::UNSUPPORTED_COMPRESSION_TYPE,
+            ),
+            (
+                ProtocolError::InvalidFormat("bad".to_string())
+                    .at(12, "name")
+                    .within("topics[0]")
+                    .into(),
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                ProtocolError::UnsupportedCompression { codec: 4 }
+                    .at(40, "records")
+                    .into(),
+                ErrorCode::UNSUPPORTED_COMPRESSION_TYPE,
+            ),
+            (
+                BrokerError::UnsupportedMessageFormat { magic: 2 },
+                ErrorCode::UNSUPPORTED_FOR_MESSAGE_FORMAT,
+            ),
+            (
+                BrokerError::CorruptRecords("bad crc".to_string()),
+                ErrorCode::CORRUPT_MESSAGE,
+            ),
+            (
+                BrokerError::InvalidReplicationFactor {
+                    topic: "orders".to_string(),
+                    replication_factor: 3,
+                },
+                ErrorCode::INVALID_REPLICATION_FACTOR,
+            ),
+            (
+                BrokerError::InvalidReplicaAssignment {
+                    topic: "orders".to_string(),
+                    reason: "broker 2 does not exist".to_string(),
+                },
+                ErrorCode::INVALID_REPLICA_ASSIGNMENT,
+            ),
+            (
+                BrokerError::OffsetNotAvailable {
+                    topic: "orders".to_string(),
+                    partition: 0,
+                    timestamp: 1_700_000_000_000,
+                },
+                ErrorCode::OFFSET_NOT_AVAILABLE,
+            ),
+            (
+                BrokerError::UnknownCoordinatorType { key_type: 7 },
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                BrokerError::TransactionsUnsupported {
+                    transactional_id: "tx-1".to_string(),
+                },
+                ErrorCode::COORDINATOR_NOT_AVAILABLE,
+            ),
+            (
+                BrokerError::InvalidProducerIdAndEpoch {
+                    producer_id: 7,
+                    producer_epoch: -1,
+                },
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                BrokerError::ControllerEndpointsUnsupported,
+                ErrorCode::MISMATCHED_ENDPOINT_TYPE,
+            ),
+            (
+                BrokerError::UnknownEndpointType { endpoint_type: 9 },
+                ErrorCode::UNSUPPORTED_ENDPOINT_TYPE,
+            ),
+            (
+                BrokerError::UnsupportedResourceType { resource_type: 8 },
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                BrokerError::UnexpectedBrokerId {
+                    resource_name: "2".to_string(),
+                    node_id: 1,
+                },
+                ErrorCode::INVALID_REQUEST,
+            ),
+            (
+                GroupError::InvalidGroupId {
+                    group_id: String::new(),
+                }
+                .into(),
+                ErrorCode::INVALID_GROUP_ID,
+            ),
+            (
+                GroupError::InvalidSessionTimeout {
+                    session_timeout_ms: 10,
+                }
+                .into(),
+                ErrorCode::INVALID_SESSION_TIMEOUT,
+            ),
+            (
+                GroupError::InconsistentGroupProtocol {
+                    group_id: "billing".to_string(),
+                }
+                .into(),
+                ErrorCode::INCONSISTENT_GROUP_PROTOCOL,
+            ),
+            (
+                GroupError::MemberIdRequired {
+                    group_id: "billing".to_string(),
+                    member_id: "consumer-1".to_string(),
+                }
+                .into(),
+                ErrorCode::MEMBER_ID_REQUIRED,
+            ),
+            (
+                GroupError::UnknownMemberId {
+                    group_id: "billing".to_string(),
+                    member_id: "consumer-1".to_string(),
+                }
+                .into(),
+                ErrorCode::UNKNOWN_MEMBER_ID,
+            ),
+            (
+                GroupError::IllegalGeneration {
+                    group_id: "billing".to_string(),
+                    generation_id: 1,
+                    current: 2,
+                }
+                .into(),
+                ErrorCode::ILLEGAL_GENERATION,
+            ),
+            (
+                GroupError::RebalanceInProgress {
+                    group_id: "billing".to_string(),
+                }
+                .into(),
+                ErrorCode::REBALANCE_IN_PROGRESS,
+            ),
+            (
+                GroupError::GroupIdNotFound {
+                    group_id: "billing".to_string(),
+                }
+                .into(),
+                ErrorCode::GROUP_ID_NOT_FOUND,
+            ),
+            (
+                OffsetStoreError::MetadataTooLarge {
+                    group_id: "billing".to_string(),
+                    topic: "orders".to_string(),
+                    partition: 0,
+                    length: 5000,
+                    limit: 4096,
+                }
+                .into(),
+                ErrorCode::OFFSET_METADATA_TOO_LARGE,
+            ),
+        ];
+
+        for (error, expected) in &table {
+            assert_eq!(wire_error(error), *expected, "{:?}", error);
+            assert!(expected.is_error());
+        }
+    }
+
+    #[test]
+    fn test_unknown_topic_in_fetch_depends_on_version() {
+        let error = unknown_topic();
+        assert_eq!(
+            wire_error_for(ApiKey::Fetch, 4, &error),
+            ErrorCode::UNKNOWN_TOPIC_OR_PARTITION
+        );
+        assert_eq!(
+            wire_error_for(ApiKey::Fetch, 13, &error),
+            ErrorCode::UNKNOWN_TOPIC_ID
+        );
+        assert_eq!(
+            wire_error_for(ApiKey::Produce, 13, &error),
+            ErrorCode::UNKNOWN_TOPIC_OR_PARTITION
+        );
+    }
+}
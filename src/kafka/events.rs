@@ -0,0 +1,269 @@
+use crate::logging::warn;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+
+/// Default number of events buffered for each subscriber
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+/// Default number of events [`RecentEvents`] keeps
+pub const DEFAULT_RECENT_EVENTS: usize = 100;
+
+/// Broker lifecycle and metadata change events
+///
+/// Published by the components that own the underlying state so that other
+/// components (metrics, fetch sessions, response caches, diagnostics) can
+/// react without being wired to each other directly.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum BrokerEvent {
+    TopicCreated {
+        topic: String,
+        partitions: i32,
+    },
+    TopicDeleted {
+        topic: String,
+    },
+    HighWatermarkAdvanced {
+        topic: String,
+        partition: i32,
+        high_watermark: i64,
+    },
+    LogTruncated {
+        topic: String,
+        partition: i32,
+        log_end_offset: i64,
+    },
+    GroupStateChanged {
+        group_id: String,
+        state: String,
+    },
+    ConfigUpdated {
+        resource: String,
+        key: String,
+    },
+    ListenersChanged {
+        broker_id: i32,
+    },
+    /// A batch of metadata changes was applied under a new epoch
+    MetadataEpochAdvanced {
+        epoch: u64,
+    },
+}
+
+/// Typed broadcast bus for [`BrokerEvent`]s
+///
+/// Publishing never blocks: each subscriber has a bounded buffer and a
+/// subscriber that falls behind loses its oldest events, which it reports
+/// through [`EventSubscriber::dropped_events`] and a warning.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<BrokerEvent>,
+}
+
+impl EventBus {
+    /// Creates a bus buffering up to `capacity` events per subscriber
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Publishes an event, returning how many subscribers will see it
+    pub fn publish(&self, event: BrokerEvent) -> usize {
+        self.sender.send(event).unwrap_or(0)
+    }
+
+    /// Subscribes to events published from now on
+    ///
+    /// `name` identifies the subscriber in lag warnings.
+    pub fn subscribe(&self, name: &'static str) -> EventSubscriber {
+        EventSubscriber {
+            name,
+            receiver: self.sender.subscribe(),
+            dropped: 0,
+        }
+    }
+
+    /// Number of live subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+/// Receiving side of an [`EventBus`]
+#[derive(Debug)]
+pub struct EventSubscriber {
+    name: &'static str,
+    receiver: broadcast::Receiver<BrokerEvent>,
+    dropped: u64,
+}
+
+impl EventSubscriber {
+    /// Waits for the next event
+    ///
+    /// Returns `None` once the bus has been dropped. Events lost because this
+    /// subscriber lagged are skipped and counted.
+    pub async fn recv(&mut self) -> Option<BrokerEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(missed)) => self.record_lag(missed),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns the next event if one is already buffered
+    pub fn try_recv(&mut self) -> Option<BrokerEvent> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => return Some(event),
+                Err(TryRecvError::Lagged(missed)) => self.record_lag(missed),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Number of events this subscriber missed by lagging
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped
+    }
+
+    fn record_lag(&mut self, missed: u64) {
+        self.dropped += missed;
+        warn!(
+            subscriber = self.name,
+            missed_events = missed,
+            total_dropped = self.dropped,
+            "Event subscriber lagged, oldest events dropped"
+        );
+    }
+}
+
+/// The latest events seen on a bus, oldest first
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecentEventsSnapshot {
+    pub events: Vec<BrokerEvent>,
+    /// Events missed because they were not drained in time
+    pub dropped: u64,
+}
+
+#[derive(Debug)]
+struct RecentState {
+    subscriber: EventSubscriber,
+    events: VecDeque<BrokerEvent>,
+}
+
+/// Keeps the last `capacity` events of a bus for the debug endpoint
+///
+/// Events are drained from the subscriber when a snapshot is taken.
+#[derive(Debug)]
+pub struct RecentEvents {
+    capacity: usize,
+    state: Mutex<RecentState>,
+}
+
+impl RecentEvents {
+    pub fn new(capacity: usize, subscriber: EventSubscriber) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(RecentState {
+                subscriber,
+                events: VecDeque::with_capacity(capacity),
+            }),
+        }
+    }
+
+    /// The events kept so far
+    pub fn snapshot(&self) -> RecentEventsSnapshot {
+        let mut state = self.state.lock().unwrap();
+        while let Some(event) = state.subscriber.try_recv() {
+            if state.events.len() == self.capacity {
+                state.events.pop_front();
+            }
+            if self.capacity > 0 {
+                state.events.push_back(event);
+            }
+        }
+        RecentEventsSnapshot {
+            events: state.events.iter().cloned().collect(),
+            dropped: state.subscriber.dropped_events(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic_created(topic: &str) -> BrokerEvent {
+        BrokerEvent::TopicCreated {
+            topic: topic.to_string(),
+            partitions: 3,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_independent_subscribers_observe_event() {
+        let bus = EventBus::default();
+        let mut metrics = bus.subscribe("metrics");
+        let mut sessions = bus.subscribe("fetch-sessions");
+
+        assert_eq!(bus.publish(topic_created("orders")), 2);
+
+        assert_eq!(metrics.recv().await, Some(topic_created("orders")));
+        assert_eq!(sessions.recv().await, Some(topic_created("orders")));
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_drops_oldest_events() {
+        let bus = EventBus::new(4);
+        let mut slow = bus.subscribe("slow");
+
+        for i in 0..10 {
+            bus.publish(topic_created(&format!("topic-{}", i)));
+        }
+
+        // The six oldest events were overwritten; the newest four remain
+        assert_eq!(slow.recv().await, Some(topic_created("topic-6")));
+        assert_eq!(slow.dropped_events(), 6);
+        assert_eq!(slow.try_recv(), Some(topic_created("topic-7")));
+    }
+
+    #[test]
+    fn test_publish_without_subscribers() {
+        let bus = EventBus::default();
+        assert_eq!(bus.publish(topic_created("orders")), 0);
+    }
+
+    #[test]
+    fn test_recent_events_keep_the_latest() {
+        let bus = EventBus::default();
+        let recent = RecentEvents::new(2, bus.subscribe("debug-events"));
+        for topic in ["a", "b", "c"] {
+            bus.publish(topic_created(topic));
+        }
+        let snapshot = recent.snapshot();
+        assert_eq!(snapshot.events, [topic_created("b"), topic_created("c")]);
+        assert_eq!(snapshot.dropped, 0);
+        assert_eq!(
+            serde_json::to_value(&snapshot.events[0]).unwrap(),
+            serde_json::json!({"type": "TopicCreated", "topic": "b", "partitions": 3})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recv_returns_none_after_bus_dropped() {
+        let bus = EventBus::default();
+        let mut subscriber = bus.subscribe("test");
+        drop(bus);
+        assert_eq!(subscriber.recv().await, None);
+    }
+}
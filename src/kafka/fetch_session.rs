@@ -0,0 +1,499 @@
+use crate::kafka::events::{BrokerEvent, EventSubscriber};
+use crate::logging::debug;
+use crate::protocol::fetch::{
+    FetchPartition, FetchRequest, FetchResponsePartition, FetchResponseTopic, FetchTopic,
+};
+use crate::protocol::ErrorCode;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Default number of fetch sessions kept at once
+/// (`max.incremental.fetch.session.cache.slots`)
+pub const DEFAULT_FETCH_SESSION_SLOTS: usize = 1000;
+
+/// Epoch of a request opening a new session
+const INITIAL_EPOCH: i32 = 0;
+
+/// Epoch of a request closing its session, or fetching without one
+const FINAL_EPOCH: i32 = -1;
+
+/// First Fetch version with sessions
+const MIN_SESSION_VERSION: i16 = 7;
+
+/// A partition of a session, named as the client names it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SessionPartition {
+    topic: String,
+    topic_id: Uuid,
+    partition: i32,
+}
+
+#[derive(Debug)]
+struct CachedPartition {
+    fetch: FetchPartition,
+    /// High watermark and log start offset last sent, `None` until the
+    /// partition was sent once
+    sent: Option<(i64, i64)>,
+}
+
+#[derive(Debug)]
+struct FetchSession {
+    /// Epoch the next request of the session must carry
+    next_epoch: i32,
+    partitions: BTreeMap<SessionPartition, CachedPartition>,
+}
+
+impl FetchSession {
+    fn topics(&self) -> Vec<FetchTopic> {
+        let mut topics: Vec<FetchTopic> = Vec::new();
+        for (key, cached) in &self.partitions {
+            match topics.last_mut() {
+                Some(topic) if topic.topic == key.topic && topic.topic_id == key.topic_id => {
+                    topic.partitions.push(cached.fetch.clone());
+                }
+                _ => topics.push(FetchTopic {
+                    topic: key.topic.clone(),
+                    topic_id: key.topic_id,
+                    partitions: vec![cached.fetch.clone()],
+                }),
+            }
+        }
+        topics
+    }
+}
+
+#[derive(Debug)]
+struct SessionState {
+    sessions: HashMap<i32, FetchSession>,
+    last_session_id: i32,
+    events: EventSubscriber,
+}
+
+/// Session a Fetch request is served in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchSessionContext {
+    /// Id to answer with, 0 when the fetch is not part of a session
+    pub session_id: i32,
+    /// Whether the response only carries partitions that changed
+    pub incremental: bool,
+}
+
+impl FetchSessionContext {
+    const SESSIONLESS: Self = Self {
+        session_id: 0,
+        incremental: false,
+    };
+}
+
+/// Incremental fetch sessions (KIP-227)
+///
+/// A client opens a session with a full fetch; later requests of the
+/// session only name the partitions whose fetch position changed and the
+/// ones it stops fetching, and their responses only carry the partitions
+/// with records, errors or new offsets. Each request must carry the epoch
+/// following the previous one.
+///
+/// Sessions forget the partitions of deleted topics, and send truncated
+/// partitions again. A subscriber that lagged behind the event bus drops
+/// every session, so their clients start over with a full fetch.
+#[derive(Debug)]
+pub struct FetchSessionCache {
+    slots: usize,
+    state: Mutex<SessionState>,
+}
+
+impl FetchSessionCache {
+    /// Creates a cache of at most `slots` sessions
+    pub fn new(slots: usize, events: EventSubscriber) -> Self {
+        Self {
+            slots,
+            state: Mutex::new(SessionState {
+                sessions: HashMap::new(),
+                last_session_id: 0,
+                events,
+            }),
+        }
+    }
+
+    /// Resolves the session of a request
+    ///
+    /// The topics of an incremental request are replaced with every
+    /// partition of its session. Fails with FETCH_SESSION_ID_NOT_FOUND or
+    /// INVALID_FETCH_SESSION_EPOCH when the request does not continue a
+    /// known session.
+    pub fn begin(
+        &self,
+        version: i16,
+        request: &mut FetchRequest,
+    ) -> Result<FetchSessionContext, ErrorCode> {
+        if version < MIN_SESSION_VERSION {
+            return Ok(FetchSessionContext::SESSIONLESS);
+        }
+        let mut state = self.state.lock().unwrap();
+        Self::apply_events(&mut state);
+        match (request.session_id, request.session_epoch) {
+            (session_id, FINAL_EPOCH) => {
+                if state.sessions.remove(&session_id).is_some() {
+                    debug!(session_id, "Closed fetch session");
+                }
+                Ok(FetchSessionContext::SESSIONLESS)
+            }
+            (session_id, INITIAL_EPOCH) => {
+                state.sessions.remove(&session_id);
+                if state.sessions.len() >= self.slots {
+                    return Ok(FetchSessionContext::SESSIONLESS);
+                }
+                let session_id = Self::next_session_id(&mut state);
+                let mut session = FetchSession {
+                    next_epoch: 1,
+                    partitions: BTreeMap::new(),
+                };
+                Self::update(&mut session, request);
+                debug!(
+                    session_id,
+                    partitions = session.partitions.len(),
+                    "Opened fetch session"
+                );
+                state.sessions.insert(session_id, session);
+                Ok(FetchSessionContext {
+                    session_id,
+                    incremental: false,
+                })
+            }
+            (0, _) => Err(ErrorCode::INVALID_FETCH_SESSION_EPOCH),
+            (session_id, epoch) => {
+                let session = state
+                    .sessions
+                    .get_mut(&session_id)
+                    .ok_or(ErrorCode::FETCH_SESSION_ID_NOT_FOUND)?;
+                if epoch != session.next_epoch {
+                    return Err(ErrorCode::INVALID_FETCH_SESSION_EPOCH);
+                }
+                session.next_epoch = epoch.checked_add(1).unwrap_or(1);
+                Self::update(session, request);
+                request.topics = session.topics();
+                Ok(FetchSessionContext {
+                    session_id,
+                    incremental: true,
+                })
+            }
+        }
+    }
+
+    /// Records what a response sends, leaving out of an incremental
+    /// response the partitions that did not change
+    pub fn finish(&self, context: FetchSessionContext, responses: &mut Vec<FetchResponseTopic>) {
+        if context.session_id == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let Some(session) = state.sessions.get_mut(&context.session_id) else {
+            return;
+        };
+        for topic in responses.iter_mut() {
+            topic.partitions.retain(|partition| {
+                let key = SessionPartition {
+                    topic: topic.topic.clone(),
+                    topic_id: topic.topic_id,
+                    partition: partition.partition_index,
+                };
+                let Some(cached) = session.partitions.get_mut(&key) else {
+                    return true;
+                };
+                let offsets = (partition.high_watermark, partition.log_start_offset);
+                let changed = cached.sent != Some(offsets) || has_news(partition);
+                cached.sent = Some(offsets);
+                changed || !context.incremental
+            });
+        }
+        responses.retain(|topic| !topic.partitions.is_empty());
+    }
+
+    /// Number of open sessions
+    pub fn len(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        Self::apply_events(&mut state);
+        state.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds and updates the partitions a request names, and drops the ones
+    /// it forgets
+    fn update(session: &mut FetchSession, request: &FetchRequest) {
+        for topic in &request.topics {
+            for fetch in &topic.partitions {
+                let key = SessionPartition {
+                    topic: topic.topic.clone(),
+                    topic_id: topic.topic_id,
+                    partition: fetch.partition,
+                };
+                session
+                    .partitions
+                    .entry(key)
+                    .and_modify(|cached| cached.fetch = fetch.clone())
+                    .or_insert_with(|| CachedPartition {
+                        fetch: fetch.clone(),
+                        sent: None,
+                    });
+            }
+        }
+        for forgotten in &request.forgotten_topics_data {
+            for &partition in &forgotten.partitions {
+                session.partitions.remove(&SessionPartition {
+                    topic: forgotten.topic.clone(),
+                    topic_id: forgotten.topic_id,
+                    partition,
+                });
+            }
+        }
+    }
+
+    fn next_session_id(state: &mut SessionState) -> i32 {
+        loop {
+            state.last_session_id = state.last_session_id.checked_add(1).unwrap_or(1);
+            if !state.sessions.contains_key(&state.last_session_id) {
+                return state.last_session_id;
+            }
+        }
+    }
+
+    /// Drains pending events into the sessions
+    fn apply_events(state: &mut SessionState) {
+        let dropped_before = state.events.dropped_events();
+        while let Some(event) = state.events.try_recv() {
+            match event {
+                BrokerEvent::TopicDeleted { topic } => {
+                    for session in state.sessions.values_mut() {
+                        session.partitions.retain(|key, _| key.topic != topic);
+                    }
+                }
+                BrokerEvent::LogTruncated {
+                    topic, partition, ..
+                } => {
+                    for session in state.sessions.values_mut() {
+                        for (key, cached) in &mut session.partitions {
+                            if key.topic == topic && key.partition == partition {
+                                cached.sent = None;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if state.events.dropped_events() != dropped_before && !state.sessions.is_empty() {
+            debug!(
+                sessions = state.sessions.len(),
+                "Dropping fetch sessions after missing events"
+            );
+            state.sessions.clear();
+        }
+    }
+}
+
+/// Whether a partition result carries anything besides its offsets
+fn has_news(partition: &FetchResponsePartition) -> bool {
+    partition.error_code != ErrorCode::NONE
+        || partition
+            .records
+            .as_ref()
+            .is_some_and(|records| !records.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::events::EventBus;
+    use crate::protocol::fetch::FetchForgottenTopic;
+    use bytes::Bytes;
+
+    fn fetch_partition(partition: i32, fetch_offset: i64) -> FetchPartition {
+        FetchPartition {
+            partition,
+            current_leader_epoch: -1,
+            fetch_offset,
+            last_fetched_epoch: -1,
+            log_start_offset: -1,
+            partition_max_bytes: 1024,
+        }
+    }
+
+    fn request(session_id: i32, session_epoch: i32, partitions: &[(i32, i64)]) -> FetchRequest {
+        FetchRequest {
+            cluster_id: None,
+            replica_id: -1,
+            replica_epoch: -1,
+            max_wait_ms: 0,
+            min_bytes: 1,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id,
+            session_epoch,
+            topics: vec![FetchTopic {
+                topic: "orders".to_string(),
+                topic_id: Uuid::nil(),
+                partitions: partitions
+                    .iter()
+                    .map(|&(partition, offset)| fetch_partition(partition, offset))
+                    .collect(),
+            }],
+            forgotten_topics_data: Vec::new(),
+            rack_id: String::new(),
+        }
+    }
+
+    fn response(partitions: &[(i32, i64, &'static [u8])]) -> Vec<FetchResponseTopic> {
+        vec![FetchResponseTopic {
+            topic: "orders".to_string(),
+            topic_id: Uuid::nil(),
+            partitions: partitions
+                .iter()
+                .map(
+                    |&(partition_index, high_watermark, records)| FetchResponsePartition {
+                        partition_index,
+                        error_code: ErrorCode::NONE,
+                        high_watermark,
+                        last_stable_offset: high_watermark,
+                        log_start_offset: 0,
+                        aborted_transactions: None,
+                        preferred_read_replica: -1,
+                        records: Some(Bytes::from_static(records)),
+                    },
+                )
+                .collect(),
+        }]
+    }
+
+    fn sent_partitions(responses: &[FetchResponseTopic]) -> Vec<i32> {
+        responses
+            .iter()
+            .flat_map(|topic| &topic.partitions)
+            .map(|partition| partition.partition_index)
+            .collect()
+    }
+
+    #[test]
+    fn test_incremental_fetch_sends_only_changed_partitions() {
+        let bus = EventBus::default();
+        let cache = FetchSessionCache::new(10, bus.subscribe("fetch-sessions"));
+
+        let mut full = request(0, 0, &[(0, 0), (1, 0)]);
+        let context = cache.begin(12, &mut full).unwrap();
+        assert!(!context.incremental);
+        assert_ne!(context.session_id, 0);
+        let mut responses = response(&[(0, 3, b"batch"), (1, 0, b"")]);
+        cache.finish(context, &mut responses);
+        assert_eq!(sent_partitions(&responses), [0, 1]);
+
+        // Only partition 0 moved; the session still fetches both
+        let mut incremental = request(context.session_id, 1, &[(0, 3)]);
+        let next = cache.begin(12, &mut incremental).unwrap();
+        assert!(next.incremental);
+        assert_eq!(incremental.topics[0].partitions.len(), 2);
+        assert_eq!(incremental.topics[0].partitions[0].fetch_offset, 3);
+        let mut responses = response(&[(0, 3, b""), (1, 2, b"batch")]);
+        cache.finish(next, &mut responses);
+        assert_eq!(sent_partitions(&responses), [1]);
+
+        // Nothing changed at all
+        let mut incremental = request(context.session_id, 2, &[]);
+        let next = cache.begin(12, &mut incremental).unwrap();
+        let mut responses = response(&[(0, 3, b""), (1, 2, b"")]);
+        cache.finish(next, &mut responses);
+        assert!(responses.is_empty());
+
+        // Forgotten partitions leave the session
+        let mut forgetting = request(context.session_id, 3, &[]);
+        forgetting.forgotten_topics_data = vec![FetchForgottenTopic {
+            topic: "orders".to_string(),
+            topic_id: Uuid::nil(),
+            partitions: vec![0],
+        }];
+        cache.begin(12, &mut forgetting).unwrap();
+        assert_eq!(forgetting.topics[0].partitions.len(), 1);
+        assert_eq!(forgetting.topics[0].partitions[0].partition, 1);
+    }
+
+    #[test]
+    fn test_session_errors_and_closing() {
+        let bus = EventBus::default();
+        let cache = FetchSessionCache::new(1, bus.subscribe("fetch-sessions"));
+
+        assert_eq!(
+            cache.begin(12, &mut request(42, 1, &[])),
+            Err(ErrorCode::FETCH_SESSION_ID_NOT_FOUND)
+        );
+        assert_eq!(
+            cache.begin(12, &mut request(0, 1, &[])),
+            Err(ErrorCode::INVALID_FETCH_SESSION_EPOCH)
+        );
+        // Before v7 there are no sessions
+        assert_eq!(
+            cache.begin(6, &mut request(0, 0, &[(0, 0)])),
+            Ok(FetchSessionContext::SESSIONLESS)
+        );
+
+        let session_id = cache
+            .begin(12, &mut request(0, 0, &[(0, 0)]))
+            .unwrap()
+            .session_id;
+        assert_eq!(
+            cache.begin(12, &mut request(session_id, 2, &[])),
+            Err(ErrorCode::INVALID_FETCH_SESSION_EPOCH)
+        );
+        // The only slot is taken
+        assert_eq!(
+            cache.begin(12, &mut request(0, 0, &[(1, 0)])),
+            Ok(FetchSessionContext::SESSIONLESS)
+        );
+
+        assert_eq!(
+            cache.begin(12, &mut request(session_id, FINAL_EPOCH, &[(0, 0)])),
+            Ok(FetchSessionContext::SESSIONLESS)
+        );
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_events_update_sessions() {
+        let bus = EventBus::new(2);
+        let cache = FetchSessionCache::new(10, bus.subscribe("fetch-sessions"));
+        let context = cache
+            .begin(12, &mut request(0, 0, &[(0, 0), (1, 0)]))
+            .unwrap();
+        let mut responses = response(&[(0, 2, b""), (1, 2, b"")]);
+        cache.finish(context, &mut responses);
+
+        // A truncated partition is sent again even if its offsets look the same
+        bus.publish(BrokerEvent::LogTruncated {
+            topic: "orders".to_string(),
+            partition: 1,
+            log_end_offset: 2,
+        });
+        let next = cache
+            .begin(12, &mut request(context.session_id, 1, &[]))
+            .unwrap();
+        let mut responses = response(&[(0, 2, b""), (1, 2, b"")]);
+        cache.finish(next, &mut responses);
+        assert_eq!(sent_partitions(&responses), [1]);
+
+        bus.publish(BrokerEvent::TopicDeleted {
+            topic: "orders".to_string(),
+        });
+        let mut incremental = request(context.session_id, 2, &[]);
+        cache.begin(12, &mut incremental).unwrap();
+        assert!(incremental.topics.is_empty());
+
+        // Missing events drops every session
+        for _ in 0..3 {
+            bus.publish(BrokerEvent::TopicDeleted {
+                topic: "other".to_string(),
+            });
+        }
+        assert!(cache.is_empty());
+    }
+}
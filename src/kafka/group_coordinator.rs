@@ -0,0 +1,1177 @@
+use crate::kafka::events::{BrokerEvent, EventBus};
+use crate::kafka::group_state::{GroupMember, GroupState, GroupStateStore};
+use crate::logging::{debug, info, warn};
+use crate::protocol::heartbeat::HeartbeatRequest;
+use crate::protocol::join_group::{
+    JoinGroupRequest, JoinGroupRequestProtocol, JoinGroupResponse, JoinGroupResponseMember,
+};
+use crate::protocol::leave_group::LeavingMember;
+use crate::protocol::sync_group::{SyncGroupRequest, SyncGroupResponse};
+use crate::protocol::ErrorCode;
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+/// Shortest session timeout a member may ask for (`group.min.session.timeout.ms`)
+pub const MIN_SESSION_TIMEOUT_MS: i32 = 6_000;
+
+/// Longest session timeout a member may ask for (`group.max.session.timeout.ms`)
+pub const MAX_SESSION_TIMEOUT_MS: i32 = 1_800_000;
+
+/// How often [`GroupCoordinator::expire_sessions_every`] looks for members
+/// whose session has lapsed
+pub const SESSION_EXPIRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Errors raised by the group coordinator
+#[derive(Error, Debug, PartialEq)]
+pub enum GroupError {
+    #[error("Invalid group id '{group_id}'")]
+    InvalidGroupId { group_id: String },
+
+    #[error("Session timeout {session_timeout_ms} ms is outside the allowed range")]
+    InvalidSessionTimeout { session_timeout_ms: i32 },
+
+    #[error("Group '{group_id}' has no protocol in common with the member")]
+    InconsistentGroupProtocol { group_id: String },
+
+    #[error("Member '{member_id}' must rejoin group '{group_id}' with its member id")]
+    MemberIdRequired { group_id: String, member_id: String },
+
+    #[error("Unknown member '{member_id}' of group '{group_id}'")]
+    UnknownMemberId { group_id: String, member_id: String },
+
+    #[error("Generation {generation_id} of group '{group_id}' is not the current {current}")]
+    IllegalGeneration {
+        group_id: String,
+        generation_id: i32,
+        current: i32,
+    },
+
+    #[error("Group '{group_id}' is rebalancing")]
+    RebalanceInProgress { group_id: String },
+
+    #[error("Group '{group_id}' not found")]
+    GroupIdNotFound { group_id: String },
+}
+
+/// Where a group is in its rebalance cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum GroupPhase {
+    /// No members
+    Empty,
+    /// Waiting for every member to send JoinGroup
+    PreparingRebalance,
+    /// Joined; waiting for the leader's SyncGroup
+    CompletingRebalance,
+    /// Every member has its assignment
+    Stable,
+}
+
+/// State of one group, as dumped
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupSnapshot {
+    pub group_id: String,
+    pub phase: GroupPhase,
+    pub generation_id: i32,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub leader_id: Option<String>,
+    /// Member ids in the order they joined
+    pub members: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Member {
+    member_id: String,
+    group_instance_id: Option<String>,
+    rebalance_timeout: Duration,
+    session_timeout: Duration,
+    /// Last heartbeat, join or sync from the member
+    last_heartbeat: Instant,
+    protocols: Vec<JoinGroupRequestProtocol>,
+    assignment: Bytes,
+    /// JoinGroup parked until the rebalance completes
+    awaiting_join: Option<oneshot::Sender<JoinGroupResponse>>,
+    /// SyncGroup parked until the leader sends the assignments
+    awaiting_sync: Option<oneshot::Sender<SyncGroupResponse>>,
+}
+
+impl Member {
+    fn metadata(&self, protocol_name: &str) -> Bytes {
+        self.protocols
+            .iter()
+            .find(|protocol| protocol.name == protocol_name)
+            .map(|protocol| protocol.metadata.clone())
+            .unwrap_or_default()
+    }
+
+    fn supports(&self, protocol_name: &str) -> bool {
+        self.protocols
+            .iter()
+            .any(|protocol| protocol.name == protocol_name)
+    }
+
+    /// Whether the session lapsed, as of `now`
+    ///
+    /// A member with a request parked is alive however long it waits.
+    fn session_expired(&self, now: Instant) -> bool {
+        self.awaiting_join.is_none()
+            && self.awaiting_sync.is_none()
+            && self.last_heartbeat + self.session_timeout <= now
+    }
+}
+
+#[derive(Debug)]
+struct Group {
+    group_id: String,
+    phase: GroupPhase,
+    generation_id: i32,
+    protocol_type: Option<String>,
+    protocol_name: Option<String>,
+    leader_id: Option<String>,
+    /// In the order they joined
+    members: Vec<Member>,
+    /// Ids handed out with MEMBER_ID_REQUIRED that have not joined yet
+    pending_member_ids: HashSet<String>,
+    /// When a rebalance stops waiting for members that have not rejoined
+    rebalance_deadline: Option<Instant>,
+    /// Where phase changes are published, if anywhere
+    events: Option<EventBus>,
+}
+
+impl Group {
+    fn new(group_id: &str, events: Option<EventBus>) -> Self {
+        Self {
+            group_id: group_id.to_string(),
+            phase: GroupPhase::Empty,
+            generation_id: 0,
+            protocol_type: None,
+            protocol_name: None,
+            leader_id: None,
+            members: Vec::new(),
+            pending_member_ids: HashSet::new(),
+            rebalance_deadline: None,
+            events,
+        }
+    }
+
+    fn set_phase(&mut self, phase: GroupPhase) {
+        if self.phase == phase {
+            return;
+        }
+        self.phase = phase;
+        if let Some(events) = &self.events {
+            events.publish(BrokerEvent::GroupStateChanged {
+                group_id: self.group_id.clone(),
+                state: format!("{:?}", phase),
+            });
+        }
+    }
+
+    fn member_mut(&mut self, member_id: &str) -> Option<&mut Member> {
+        self.members
+            .iter_mut()
+            .find(|member| member.member_id == member_id)
+    }
+
+    fn is_member(&self, member_id: &str) -> bool {
+        self.members
+            .iter()
+            .any(|member| member.member_id == member_id)
+    }
+
+    /// Whether the protocols a joining member offers fit the group
+    fn accepts(&self, request: &JoinGroupRequest) -> bool {
+        if request.protocol_type.is_empty() || request.protocols.is_empty() {
+            return false;
+        }
+        if self.members.is_empty() {
+            return true;
+        }
+        self.protocol_type.as_deref() == Some(request.protocol_type.as_str())
+            && request.protocols.iter().any(|protocol| {
+                self.members
+                    .iter()
+                    .filter(|member| member.member_id != request.member_id)
+                    .all(|member| member.supports(&protocol.name))
+            })
+    }
+
+    /// Starts a rebalance, or extends the one under way
+    fn prepare_rebalance(&mut self) {
+        if self.phase != GroupPhase::PreparingRebalance {
+            debug!(
+                group_id = %self.group_id,
+                generation_id = self.generation_id,
+                "Preparing rebalance"
+            );
+            // Members waiting for an assignment of the old generation rejoin
+            for member in &mut self.members {
+                if let Some(waiter) = member.awaiting_sync.take() {
+                    let _ = waiter.send(SyncGroupResponse::error(ErrorCode::REBALANCE_IN_PROGRESS));
+                }
+            }
+            self.set_phase(GroupPhase::PreparingRebalance);
+        }
+        let timeout = self
+            .members
+            .iter()
+            .map(|member| member.rebalance_timeout)
+            .max()
+            .unwrap_or_default();
+        self.rebalance_deadline = Some(Instant::now() + timeout);
+    }
+
+    /// Removes members and rebalances the ones left
+    ///
+    /// The rebalance completes at once when every remaining member is
+    /// already waiting to rejoin, which includes none being left.
+    fn remove_members(&mut self, member_ids: &[String]) {
+        self.members
+            .retain(|member| !member_ids.contains(&member.member_id));
+        self.prepare_rebalance();
+        if self.all_members_joined() {
+            self.complete_join();
+        }
+    }
+
+    fn all_members_joined(&self) -> bool {
+        self.members
+            .iter()
+            .all(|member| member.awaiting_join.is_some())
+    }
+
+    /// Protocol most members prefer among those every member supports
+    ///
+    /// Ties go to the protocol the first member ranks higher.
+    fn select_protocol(&self) -> Option<String> {
+        let first = self.members.first()?;
+        let candidates: Vec<&str> = first
+            .protocols
+            .iter()
+            .map(|protocol| protocol.name.as_str())
+            .filter(|name| self.members.iter().all(|member| member.supports(name)))
+            .collect();
+        let mut votes: HashMap<&str, usize> = HashMap::new();
+        for member in &self.members {
+            if let Some(preferred) = member
+                .protocols
+                .iter()
+                .find(|protocol| candidates.contains(&protocol.name.as_str()))
+            {
+                *votes.entry(preferred.name.as_str()).or_default() += 1;
+            }
+        }
+        candidates
+            .iter()
+            .rev()
+            .max_by_key(|name| votes.get(*name).copied().unwrap_or(0))
+            .map(|name| name.to_string())
+    }
+
+    /// Ends the join phase with the members that rejoined
+    ///
+    /// Members that did not rejoin in time are removed. Every parked
+    /// JoinGroup is answered; the leader's answer lists the members.
+    fn complete_join(&mut self) {
+        let (joined, missing): (Vec<_>, Vec<_>) = std::mem::take(&mut self.members)
+            .into_iter()
+            .partition(|member| member.awaiting_join.is_some());
+        for member in &missing {
+            info!(
+                group_id = %self.group_id,
+                member_id = %member.member_id,
+                "Removing member that did not rejoin in time"
+            );
+        }
+        self.members = joined;
+        self.generation_id += 1;
+        self.rebalance_deadline = None;
+
+        if self.members.is_empty() {
+            self.set_phase(GroupPhase::Empty);
+            self.protocol_type = None;
+            self.protocol_name = None;
+            self.leader_id = None;
+            return;
+        }
+
+        self.protocol_name = self.select_protocol();
+        if !self
+            .leader_id
+            .as_deref()
+            .is_some_and(|leader| self.is_member(leader))
+        {
+            self.leader_id = Some(self.members[0].member_id.clone());
+        }
+        self.set_phase(GroupPhase::CompletingRebalance);
+        info!(
+            group_id = %self.group_id,
+            generation_id = self.generation_id,
+            members = self.members.len(),
+            protocol = ?self.protocol_name,
+            "Rebalance joined"
+        );
+
+        let protocol_name = self.protocol_name.clone().unwrap_or_default();
+        let leader = self.leader_id.clone().unwrap_or_default();
+        let now = Instant::now();
+        let member_list: Vec<_> = self
+            .members
+            .iter()
+            .map(|member| JoinGroupResponseMember {
+                member_id: member.member_id.clone(),
+                group_instance_id: member.group_instance_id.clone(),
+                metadata: member.metadata(&protocol_name),
+            })
+            .collect();
+        for member in &mut self.members {
+            let Some(waiter) = member.awaiting_join.take() else {
+                continue;
+            };
+            member.last_heartbeat = now;
+            let members = if member.member_id == leader {
+                member_list.clone()
+            } else {
+                Vec::new()
+            };
+            let _ = waiter.send(JoinGroupResponse {
+                throttle_time_ms: 0,
+                error_code: ErrorCode::NONE,
+                generation_id: self.generation_id,
+                protocol_type: self.protocol_type.clone(),
+                protocol_name: Some(protocol_name.clone()),
+                leader: leader.clone(),
+                skip_assignment: false,
+                member_id: member.member_id.clone(),
+                members,
+            });
+        }
+    }
+
+    fn sync_response(&self, assignment: Bytes) -> SyncGroupResponse {
+        SyncGroupResponse {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            protocol_type: self.protocol_type.clone(),
+            protocol_name: self.protocol_name.clone(),
+            assignment,
+        }
+    }
+
+    fn state(&self) -> GroupState {
+        GroupState {
+            group_id: self.group_id.clone(),
+            generation_id: self.generation_id,
+            protocol_name: self.protocol_name.clone(),
+            leader_id: self.leader_id.clone(),
+            members: self
+                .members
+                .iter()
+                .map(|member| GroupMember {
+                    member_id: member.member_id.clone(),
+                    group_instance_id: member.group_instance_id.clone(),
+                    assignment: member.assignment.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Groups {
+    groups: BTreeMap<String, Group>,
+    store: GroupStateStore,
+    events: Option<EventBus>,
+}
+
+/// Membership of consumer groups, all coordinated by this broker
+///
+/// A rebalance runs in two rounds. Every member sends JoinGroup, which is
+/// parked until all known members have rejoined or the largest rebalance
+/// timeout passes; the first member to join leads, and only its answer
+/// lists the members. The leader then sends the assignments with
+/// SyncGroup, which releases the other members' parked SyncGroups.
+///
+/// Members keep their session alive with heartbeats. One that leaves, or
+/// whose session timeout passes without a heartbeat, is removed and the
+/// rest are sent through a new rebalance; their heartbeats answer
+/// REBALANCE_IN_PROGRESS until they rejoin.
+///
+/// Stable groups are recorded in a [`GroupStateStore`].
+#[derive(Debug, Default)]
+pub struct GroupCoordinator {
+    inner: Mutex<Groups>,
+}
+
+impl GroupCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records stable groups in `store` instead of an in-memory one
+    pub fn with_store(self, store: GroupStateStore) -> Self {
+        self.inner.lock().unwrap().store = store;
+        self
+    }
+
+    /// Publishes group phase changes on `events`
+    pub fn with_events(self, events: EventBus) -> Self {
+        self.inner.lock().unwrap().events = Some(events);
+        self
+    }
+
+    /// Joins a member to a group, waiting for the rebalance to complete
+    ///
+    /// A member joining without an id gets one generated from its client
+    /// id. When `require_member_id` is set, as for JoinGroup v4+, that id
+    /// is returned in [`GroupError::MemberIdRequired`] and the member must
+    /// join again with it.
+    pub async fn join_group(
+        &self,
+        request: &JoinGroupRequest,
+        client_id: &str,
+        require_member_id: bool,
+    ) -> Result<JoinGroupResponse, GroupError> {
+        let (mut joined, mut deadline) = self.start_join(request, client_id, require_member_id)?;
+        loop {
+            tokio::select! {
+                response = &mut joined => {
+                    // The waiter is only dropped when the member was removed
+                    return response.map_err(|_| GroupError::UnknownMemberId {
+                        group_id: request.group_id.clone(),
+                        member_id: request.member_id.clone(),
+                    });
+                }
+                _ = tokio::time::sleep_until(deadline) => {
+                    match self.expire_rebalance(&request.group_id) {
+                        Some(extended) => deadline = extended,
+                        None => return joined.await.map_err(|_| GroupError::UnknownMemberId {
+                            group_id: request.group_id.clone(),
+                            member_id: request.member_id.clone(),
+                        }),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Registers a join and returns where its answer will arrive, with the
+    /// deadline of the rebalance it waits on
+    fn start_join(
+        &self,
+        request: &JoinGroupRequest,
+        client_id: &str,
+        require_member_id: bool,
+    ) -> Result<(oneshot::Receiver<JoinGroupResponse>, Instant), GroupError> {
+        let group_id = &request.group_id;
+        if group_id.is_empty() {
+            return Err(GroupError::InvalidGroupId {
+                group_id: group_id.clone(),
+            });
+        }
+        if !(MIN_SESSION_TIMEOUT_MS..=MAX_SESSION_TIMEOUT_MS).contains(&request.session_timeout_ms)
+        {
+            return Err(GroupError::InvalidSessionTimeout {
+                session_timeout_ms: request.session_timeout_ms,
+            });
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let Groups { groups, events, .. } = &mut *inner;
+        let group = groups
+            .entry(group_id.clone())
+            .or_insert_with(|| Group::new(group_id, events.clone()));
+        if !group.accepts(request) {
+            return Err(GroupError::InconsistentGroupProtocol {
+                group_id: group_id.clone(),
+            });
+        }
+
+        let member_id = if request.member_id.is_empty() {
+            let member_id = format!("{}-{}", client_id, Uuid::new_v4());
+            if require_member_id {
+                group.pending_member_ids.insert(member_id.clone());
+                return Err(GroupError::MemberIdRequired {
+                    group_id: group_id.clone(),
+                    member_id,
+                });
+            }
+            member_id
+        } else if group.pending_member_ids.remove(&request.member_id)
+            || group.is_member(&request.member_id)
+        {
+            request.member_id.clone()
+        } else {
+            return Err(GroupError::UnknownMemberId {
+                group_id: group_id.clone(),
+                member_id: request.member_id.clone(),
+            });
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        let rebalance_timeout = Duration::from_millis(request.rebalance_timeout_ms.max(0) as u64);
+        let session_timeout = Duration::from_millis(request.session_timeout_ms as u64);
+        match group.member_mut(&member_id) {
+            Some(member) => {
+                member.rebalance_timeout = rebalance_timeout;
+                member.session_timeout = session_timeout;
+                member.last_heartbeat = Instant::now();
+                member.protocols = request.protocols.clone();
+                member.awaiting_join = Some(sender);
+            }
+            None => {
+                debug!(group_id = %group_id, member_id = %member_id, "Member joining");
+                group.members.push(Member {
+                    member_id,
+                    group_instance_id: request.group_instance_id.clone(),
+                    rebalance_timeout,
+                    session_timeout,
+                    last_heartbeat: Instant::now(),
+                    protocols: request.protocols.clone(),
+                    assignment: Bytes::new(),
+                    awaiting_join: Some(sender),
+                    awaiting_sync: None,
+                });
+            }
+        }
+        group.protocol_type = Some(request.protocol_type.clone());
+        group.prepare_rebalance();
+        let deadline = group.rebalance_deadline.unwrap_or_else(Instant::now);
+        if group.all_members_joined() {
+            group.complete_join();
+        }
+        Ok((receiver, deadline))
+    }
+
+    /// Completes a rebalance whose deadline has passed
+    ///
+    /// Returns the new deadline if the rebalance was extended by a later
+    /// join, or `None` once it is no longer waiting.
+    fn expire_rebalance(&self, group_id: &str) -> Option<Instant> {
+        let mut inner = self.inner.lock().unwrap();
+        let group = inner.groups.get_mut(group_id)?;
+        if group.phase != GroupPhase::PreparingRebalance {
+            return None;
+        }
+        match group.rebalance_deadline {
+            Some(deadline) if deadline > Instant::now() => Some(deadline),
+            _ => {
+                warn!(group_id = %group_id, "Rebalance timed out waiting for members");
+                group.complete_join();
+                None
+            }
+        }
+    }
+
+    /// Gets a member's assignment for the current generation
+    ///
+    /// The leader's request carries every member's assignment and
+    /// completes the rebalance. Other members wait for it.
+    pub async fn sync_group(
+        &self,
+        request: &SyncGroupRequest,
+    ) -> Result<SyncGroupResponse, GroupError> {
+        let assigned = self.start_sync(request)?;
+        assigned.await.map_err(|_| GroupError::RebalanceInProgress {
+            group_id: request.group_id.clone(),
+        })
+    }
+
+    fn start_sync(
+        &self,
+        request: &SyncGroupRequest,
+    ) -> Result<oneshot::Receiver<SyncGroupResponse>, GroupError> {
+        let mut inner = self.inner.lock().unwrap();
+        let Groups { groups, store, .. } = &mut *inner;
+        let unknown_member = || GroupError::UnknownMemberId {
+            group_id: request.group_id.clone(),
+            member_id: request.member_id.clone(),
+        };
+        let group = groups
+            .get_mut(&request.group_id)
+            .ok_or_else(unknown_member)?;
+        if !group.is_member(&request.member_id) {
+            return Err(unknown_member());
+        }
+        if request.generation_id != group.generation_id {
+            return Err(GroupError::IllegalGeneration {
+                group_id: request.group_id.clone(),
+                generation_id: request.generation_id,
+                current: group.generation_id,
+            });
+        }
+        let mismatched = |requested: &Option<String>, current: &Option<String>| {
+            requested.is_some() && requested != current
+        };
+        if mismatched(&request.protocol_type, &group.protocol_type)
+            || mismatched(&request.protocol_name, &group.protocol_name)
+        {
+            return Err(GroupError::InconsistentGroupProtocol {
+                group_id: request.group_id.clone(),
+            });
+        }
+
+        if let Some(member) = group.member_mut(&request.member_id) {
+            member.last_heartbeat = Instant::now();
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        match group.phase {
+            GroupPhase::Empty | GroupPhase::PreparingRebalance => {
+                return Err(GroupError::RebalanceInProgress {
+                    group_id: request.group_id.clone(),
+                });
+            }
+            GroupPhase::Stable => {
+                let member = group
+                    .member_mut(&request.member_id)
+                    .ok_or_else(unknown_member)?;
+                let assignment = member.assignment.clone();
+                let _ = sender.send(group.sync_response(assignment));
+            }
+            GroupPhase::CompletingRebalance
+                if group.leader_id.as_deref() == Some(request.member_id.as_str()) =>
+            {
+                let mut assignments: HashMap<&str, &Bytes> = request
+                    .assignments
+                    .iter()
+                    .map(|assignment| (assignment.member_id.as_str(), &assignment.assignment))
+                    .collect();
+                for member in &mut group.members {
+                    member.assignment = assignments
+                        .remove(member.member_id.as_str())
+                        .cloned()
+                        .unwrap_or_default();
+                }
+                group.set_phase(GroupPhase::Stable);
+                info!(
+                    group_id = %group.group_id,
+                    generation_id = group.generation_id,
+                    "Group stable"
+                );
+                if let Err(error) = store.update(group.state()) {
+                    warn!(group_id = %group.group_id, error = %error, "Group state not recorded");
+                }
+                let now = Instant::now();
+                let waiters: Vec<_> = group
+                    .members
+                    .iter_mut()
+                    .filter_map(|member| {
+                        let waiter = member.awaiting_sync.take()?;
+                        member.last_heartbeat = now;
+                        Some((waiter, member.assignment.clone()))
+                    })
+                    .collect();
+                for (waiter, assignment) in waiters {
+                    let _ = waiter.send(group.sync_response(assignment));
+                }
+                let leader = group
+                    .member_mut(&request.member_id)
+                    .ok_or_else(unknown_member)?;
+                let assignment = leader.assignment.clone();
+                let _ = sender.send(group.sync_response(assignment));
+            }
+            GroupPhase::CompletingRebalance => {
+                let member = group
+                    .member_mut(&request.member_id)
+                    .ok_or_else(unknown_member)?;
+                member.awaiting_sync = Some(sender);
+            }
+        }
+        Ok(receiver)
+    }
+
+    /// Keeps a member's session alive
+    ///
+    /// During a rebalance the member is told to rejoin with
+    /// [`GroupError::RebalanceInProgress`]; its session is still kept
+    /// alive so it has time to do so.
+    pub fn heartbeat(&self, request: &HeartbeatRequest) -> Result<(), GroupError> {
+        let mut inner = self.inner.lock().unwrap();
+        let unknown_member = || GroupError::UnknownMemberId {
+            group_id: request.group_id.clone(),
+            member_id: request.member_id.clone(),
+        };
+        let group = inner
+            .groups
+            .get_mut(&request.group_id)
+            .ok_or_else(unknown_member)?;
+        let phase = group.phase;
+        let generation_id = group.generation_id;
+        let member = group
+            .member_mut(&request.member_id)
+            .ok_or_else(unknown_member)?;
+        member.last_heartbeat = Instant::now();
+        if phase == GroupPhase::PreparingRebalance {
+            return Err(GroupError::RebalanceInProgress {
+                group_id: request.group_id.clone(),
+            });
+        }
+        if request.generation_id != generation_id {
+            return Err(GroupError::IllegalGeneration {
+                group_id: request.group_id.clone(),
+                generation_id: request.generation_id,
+                current: generation_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Removes a member from a group and rebalances the rest
+    ///
+    /// The member is named by its id or, when that is empty, by its
+    /// group instance id.
+    pub fn leave_group(&self, group_id: &str, leaving: &LeavingMember) -> Result<(), GroupError> {
+        let mut inner = self.inner.lock().unwrap();
+        let unknown_member = || GroupError::UnknownMemberId {
+            group_id: group_id.to_string(),
+            member_id: leaving.member_id.clone(),
+        };
+        let group = inner.groups.get_mut(group_id).ok_or_else(unknown_member)?;
+        let member_id = group
+            .members
+            .iter()
+            .find(|member| {
+                if leaving.member_id.is_empty() {
+                    leaving.group_instance_id.is_some()
+                        && member.group_instance_id == leaving.group_instance_id
+                } else {
+                    member.member_id == leaving.member_id
+                }
+            })
+            .map(|member| member.member_id.clone())
+            .ok_or_else(unknown_member)?;
+        info!(
+            group_id = %group_id,
+            member_id = %member_id,
+            reason = ?leaving.reason,
+            "Member left"
+        );
+        group.remove_members(&[member_id]);
+        Ok(())
+    }
+
+    /// Removes members whose session lapsed and rebalances their groups
+    ///
+    /// Rebalances whose deadline passed are completed too, in case no
+    /// member is left waiting on them.
+    pub fn expire_sessions(&self) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        for group in inner.groups.values_mut() {
+            let expired: Vec<String> = group
+                .members
+                .iter()
+                .filter(|member| member.session_expired(now))
+                .map(|member| member.member_id.clone())
+                .collect();
+            for member_id in &expired {
+                info!(
+                    group_id = %group.group_id,
+                    member_id = %member_id,
+                    "Member session expired"
+                );
+            }
+            if !expired.is_empty() {
+                group.remove_members(&expired);
+            }
+            if group.phase == GroupPhase::PreparingRebalance
+                && group
+                    .rebalance_deadline
+                    .is_some_and(|deadline| deadline <= now)
+            {
+                warn!(group_id = %group.group_id, "Rebalance timed out waiting for members");
+                group.complete_join();
+            }
+        }
+    }
+
+    /// Runs [`GroupCoordinator::expire_sessions`] every `period`, forever
+    ///
+    /// Meant to be spawned as a background task next to the broker.
+    pub async fn expire_sessions_every(&self, period: Duration) {
+        let mut ticks = tokio::time::interval_at(Instant::now() + period, period);
+        loop {
+            ticks.tick().await;
+            self.expire_sessions();
+        }
+    }
+
+    /// Checks that a member may commit offsets for its group
+    ///
+    /// A negative generation marks a simple commit from outside the group
+    /// protocol, which is always allowed. Otherwise the member must belong
+    /// to the group's current generation, and not be waiting for its
+    /// assignment.
+    pub fn validate_commit(
+        &self,
+        group_id: &str,
+        generation_id: i32,
+        member_id: &str,
+    ) -> Result<(), GroupError> {
+        if generation_id < 0 {
+            return Ok(());
+        }
+        let inner = self.inner.lock().unwrap();
+        let unknown_member = || GroupError::UnknownMemberId {
+            group_id: group_id.to_string(),
+            member_id: member_id.to_string(),
+        };
+        let group = inner.groups.get(group_id).ok_or_else(unknown_member)?;
+        if !group.is_member(member_id) {
+            return Err(unknown_member());
+        }
+        if generation_id != group.generation_id {
+            return Err(GroupError::IllegalGeneration {
+                group_id: group_id.to_string(),
+                generation_id,
+                current: group.generation_id,
+            });
+        }
+        if group.phase == GroupPhase::CompletingRebalance {
+            return Err(GroupError::RebalanceInProgress {
+                group_id: group_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Phase and generation of a group, if it exists
+    pub fn group_phase(&self, group_id: &str) -> Option<(GroupPhase, i32)> {
+        let inner = self.inner.lock().unwrap();
+        let group = inner.groups.get(group_id)?;
+        Some((group.phase, group.generation_id))
+    }
+
+    /// State of every group, ordered by group id
+    pub fn snapshot(&self) -> Vec<GroupSnapshot> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .groups
+            .values()
+            .map(|group| GroupSnapshot {
+                group_id: group.group_id.clone(),
+                phase: group.phase,
+                generation_id: group.generation_id,
+                protocol_type: group.protocol_type.clone(),
+                protocol_name: group.protocol_name.clone(),
+                leader_id: group.leader_id.clone(),
+                members: group
+                    .members
+                    .iter()
+                    .map(|member| member.member_id.clone())
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join_request(member_id: &str, protocols: &[&str]) -> JoinGroupRequest {
+        JoinGroupRequest {
+            group_id: "billing".to_string(),
+            session_timeout_ms: 10_000,
+            rebalance_timeout_ms: 50,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+            protocol_type: "consumer".to_string(),
+            protocols: protocols
+                .iter()
+                .map(|name| JoinGroupRequestProtocol {
+                    name: name.to_string(),
+                    metadata: Bytes::new(),
+                })
+                .collect(),
+            reason: None,
+        }
+    }
+
+    fn heartbeat_request(member_id: &str, generation_id: i32) -> HeartbeatRequest {
+        HeartbeatRequest {
+            group_id: "billing".to_string(),
+            generation_id,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+        }
+    }
+
+    /// Joins `a` and then `b`, and syncs both into generation 2
+    ///
+    /// Both get a 5 s rebalance timeout.
+    async fn stable_pair(coordinator: &GroupCoordinator) -> (String, String) {
+        let join_request = |member_id: &str| JoinGroupRequest {
+            rebalance_timeout_ms: 5_000,
+            ..join_request(member_id, &["range"])
+        };
+        let first = coordinator
+            .join_group(&join_request(""), "consumer-a", false)
+            .await
+            .unwrap();
+        let a = first.member_id;
+        let (joining, rejoining) = (join_request(""), join_request(&a));
+        let (b, rejoined) = tokio::join!(
+            coordinator.join_group(&joining, "consumer-b", false),
+            coordinator.join_group(&rejoining, "consumer-a", false),
+        );
+        let b = b.unwrap().member_id;
+        assert_eq!(rejoined.unwrap().generation_id, 2);
+        let (leading, following) = (sync_request(&a, 2), sync_request(&b, 2));
+        let (leader, follower) = tokio::join!(
+            coordinator.sync_group(&leading),
+            coordinator.sync_group(&following),
+        );
+        leader.unwrap();
+        follower.unwrap();
+        (a, b)
+    }
+
+    fn sync_request(member_id: &str, generation_id: i32) -> SyncGroupRequest {
+        SyncGroupRequest {
+            group_id: "billing".to_string(),
+            generation_id,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+            protocol_type: None,
+            protocol_name: None,
+            assignments: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_member_id_required() {
+        let coordinator = GroupCoordinator::new();
+        let error = coordinator
+            .join_group(&join_request("", &["range"]), "consumer", true)
+            .await
+            .unwrap_err();
+        let GroupError::MemberIdRequired { member_id, .. } = error else {
+            panic!("unexpected {:?}", error);
+        };
+        assert!(member_id.starts_with("consumer-"));
+
+        let joined = coordinator
+            .join_group(&join_request(&member_id, &["range"]), "consumer", true)
+            .await
+            .unwrap();
+        assert_eq!(joined.member_id, member_id);
+        assert_eq!(joined.generation_id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_join_validation() {
+        let coordinator = GroupCoordinator::new();
+        let mut request = join_request("", &["range"]);
+        request.session_timeout_ms = 100;
+        assert_eq!(
+            coordinator.join_group(&request, "consumer", false).await,
+            Err(GroupError::InvalidSessionTimeout {
+                session_timeout_ms: 100
+            })
+        );
+
+        coordinator
+            .join_group(&join_request("", &["range"]), "consumer", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            coordinator
+                .join_group(&join_request("", &["sticky"]), "consumer", false)
+                .await,
+            Err(GroupError::InconsistentGroupProtocol {
+                group_id: "billing".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_protocol_most_members_prefer_wins() {
+        let mut group = Group::new("billing", None);
+        for (member_id, protocols) in [
+            ("a", &["range", "roundrobin"]),
+            ("b", &["roundrobin", "range"]),
+            ("c", &["roundrobin", "range"]),
+        ] {
+            group.members.push(Member {
+                member_id: member_id.to_string(),
+                group_instance_id: None,
+                rebalance_timeout: Duration::ZERO,
+                session_timeout: Duration::ZERO,
+                last_heartbeat: Instant::now(),
+                protocols: join_request(member_id, protocols).protocols,
+                assignment: Bytes::new(),
+                awaiting_join: None,
+                awaiting_sync: None,
+            });
+        }
+        assert_eq!(group.select_protocol().as_deref(), Some("roundrobin"));
+
+        // A tie goes to the first member's preference
+        group.members.pop();
+        assert_eq!(group.select_protocol().as_deref(), Some("range"));
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_drops_members_that_do_not_rejoin() {
+        let coordinator = GroupCoordinator::new();
+        let first = coordinator
+            .join_group(&join_request("", &["range"]), "consumer-a", false)
+            .await
+            .unwrap();
+        coordinator
+            .sync_group(&sync_request(&first.member_id, 1))
+            .await
+            .unwrap();
+
+        // The first member never rejoins, so the rebalance times out
+        let second = coordinator
+            .join_group(&join_request("", &["range"]), "consumer-b", false)
+            .await
+            .unwrap();
+        assert_eq!(second.generation_id, 2);
+        assert_eq!(second.leader, second.member_id);
+        assert_eq!(second.members.len(), 1);
+        assert_eq!(
+            coordinator
+                .sync_group(&sync_request(&first.member_id, 2))
+                .await,
+            Err(GroupError::UnknownMemberId {
+                group_id: "billing".to_string(),
+                member_id: first.member_id.clone(),
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_errors() {
+        let coordinator = GroupCoordinator::new();
+        let (a, _) = stable_pair(&coordinator).await;
+        assert_eq!(coordinator.heartbeat(&heartbeat_request(&a, 2)), Ok(()));
+        assert_eq!(
+            coordinator.heartbeat(&heartbeat_request("stranger", 2)),
+            Err(GroupError::UnknownMemberId {
+                group_id: "billing".to_string(),
+                member_id: "stranger".to_string(),
+            })
+        );
+        assert_eq!(
+            coordinator.heartbeat(&heartbeat_request(&a, 1)),
+            Err(GroupError::IllegalGeneration {
+                group_id: "billing".to_string(),
+                generation_id: 1,
+                current: 2,
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_member_expires() {
+        let coordinator = std::sync::Arc::new(GroupCoordinator::new());
+        let (a, b) = stable_pair(&coordinator).await;
+        let expiry = tokio::spawn({
+            let coordinator = std::sync::Arc::clone(&coordinator);
+            async move {
+                coordinator
+                    .expire_sessions_every(SESSION_EXPIRY_INTERVAL)
+                    .await
+            }
+        });
+
+        // `a` heartbeats well within its 10 s session, `b` stays silent
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_secs(3)).await;
+            assert_eq!(coordinator.heartbeat(&heartbeat_request(&a, 2)), Ok(()));
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(
+            coordinator.group_phase("billing"),
+            Some((GroupPhase::PreparingRebalance, 2))
+        );
+        assert_eq!(
+            coordinator.heartbeat(&heartbeat_request(&a, 2)),
+            Err(GroupError::RebalanceInProgress {
+                group_id: "billing".to_string()
+            })
+        );
+        assert!(matches!(
+            coordinator.heartbeat(&heartbeat_request(&b, 2)),
+            Err(GroupError::UnknownMemberId { .. })
+        ));
+
+        // `a` is the only member left, so its rejoin completes at once
+        let rejoined = coordinator
+            .join_group(&join_request(&a, &["range"]), "consumer-a", false)
+            .await
+            .unwrap();
+        assert_eq!(rejoined.generation_id, 3);
+        assert_eq!(rejoined.members.len(), 1);
+        expiry.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_leave_group_rebalances() {
+        let coordinator = GroupCoordinator::new();
+        let (a, b) = stable_pair(&coordinator).await;
+        let leaving = |member_id: &str| LeavingMember {
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+            reason: None,
+        };
+        coordinator.leave_group("billing", &leaving(&b)).unwrap();
+        assert_eq!(
+            coordinator.group_phase("billing"),
+            Some((GroupPhase::PreparingRebalance, 2))
+        );
+        assert_eq!(
+            coordinator.leave_group("billing", &leaving(&b)),
+            Err(GroupError::UnknownMemberId {
+                group_id: "billing".to_string(),
+                member_id: b.clone(),
+            })
+        );
+
+        // The last member leaving empties the group
+        coordinator.leave_group("billing", &leaving(&a)).unwrap();
+        assert_eq!(
+            coordinator.group_phase("billing"),
+            Some((GroupPhase::Empty, 3))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_phase_changes_are_published() {
+        let events = EventBus::default();
+        let mut subscriber = events.subscribe("test");
+        let coordinator = GroupCoordinator::new().with_events(events);
+        stable_pair(&coordinator).await;
+
+        let states: Vec<String> = std::iter::from_fn(|| subscriber.try_recv())
+            .map(|event| match event {
+                BrokerEvent::GroupStateChanged { group_id, state } => {
+                    assert_eq!(group_id, "billing");
+                    state
+                }
+                event => panic!("unexpected event {:?}", event),
+            })
+            .collect();
+        assert_eq!(
+            states,
+            [
+                "PreparingRebalance",
+                "CompletingRebalance",
+                "PreparingRebalance",
+                "CompletingRebalance",
+                "Stable",
+            ]
+        );
+    }
+}
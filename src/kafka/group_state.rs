@@ -0,0 +1,276 @@
+use crate::kafka::config::broker_property;
+use crate::kafka::storage::PartitionLog;
+use crate::logging::info;
+use crate::protocol::{ProtocolError, ProtocolResult, WireFormat};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Version written with every group state record
+///
+/// Newer versions may only append fields at the end of a record, so older
+/// brokers can replay them by ignoring what they do not understand.
+pub const GROUP_STATE_RECORD_VERSION: i16 = 0;
+
+/// A member of a consumer group and its assignment
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupMember {
+    pub member_id: String,
+    /// Set for static members (`group.instance.id`)
+    pub group_instance_id: Option<String>,
+    pub assignment: Bytes,
+}
+
+/// Coordinator state of one consumer group
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupState {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub protocol_name: Option<String>,
+    pub leader_id: Option<String>,
+    pub members: Vec<GroupMember>,
+}
+
+/// A change to a group, as appended to the group state log
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupStateRecord {
+    /// The group's complete state after a transition
+    Group(GroupState),
+    /// The group was removed
+    Tombstone { group_id: String },
+}
+
+impl GroupStateRecord {
+    /// Group the record applies to
+    pub fn group_id(&self) -> &str {
+        match self {
+            Self::Group(state) => &state.group_id,
+            Self::Tombstone { group_id } => group_id,
+        }
+    }
+
+    /// Serializes the record at [`GROUP_STATE_RECORD_VERSION`]
+    pub fn encode(&self) -> ProtocolResult<Bytes> {
+        let mut buffer = BytesMut::new();
+        buffer.put_i16(GROUP_STATE_RECORD_VERSION);
+        WireFormat::encode_string(&mut buffer, self.group_id())?;
+        match self {
+            Self::Tombstone { .. } => buffer.put_u8(0),
+            Self::Group(state) => {
+                buffer.put_u8(1);
+                buffer.put_i32(state.generation_id);
+                WireFormat::encode_nullable_string(&mut buffer, state.protocol_name.as_deref())?;
+                WireFormat::encode_nullable_string(&mut buffer, state.leader_id.as_deref())?;
+                WireFormat::encode_array(&mut buffer, &state.members, |buffer, member| {
+                    WireFormat::encode_string(buffer, &member.member_id)?;
+                    WireFormat::encode_nullable_string(
+                        buffer,
+                        member.group_instance_id.as_deref(),
+                    )?;
+                    WireFormat::encode_bytes(buffer, &member.assignment)
+                })?;
+            }
+        }
+        Ok(buffer.freeze())
+    }
+
+    /// Parses a record of any version
+    ///
+    /// Fields added after version 0 are skipped.
+    pub fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        let version = WireFormat::decode_i16(buffer)?;
+        if version < 0 {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "invalid group state record version {}",
+                version
+            )));
+        }
+        let group_id = WireFormat::decode_string(buffer)?;
+        let record = match WireFormat::decode_u8(buffer)? {
+            0 => Self::Tombstone { group_id },
+            _ => Self::Group(GroupState {
+                group_id,
+                generation_id: WireFormat::decode_i32(buffer)?,
+                protocol_name: WireFormat::decode_nullable_string(buffer)?,
+                leader_id: WireFormat::decode_nullable_string(buffer)?,
+                members: WireFormat::decode_array(buffer, |buffer| {
+                    Ok(GroupMember {
+                        member_id: WireFormat::decode_string(buffer)?,
+                        group_instance_id: WireFormat::decode_nullable_string(buffer)?,
+                        assignment: WireFormat::decode_bytes(buffer)?,
+                    })
+                })?,
+            }),
+        };
+        buffer.clear();
+        Ok(record)
+    }
+}
+
+/// Group coordinator state, optionally backed by a log
+///
+/// Without a log, groups live only in memory and a restart forces every
+/// group to rebalance. With one, each transition is appended before it is
+/// applied, and [`durable`](Self::durable) replays the log so groups come
+/// back with their generation, members and assignments intact.
+#[derive(Debug, Default)]
+pub struct GroupStateStore {
+    groups: HashMap<String, GroupState>,
+    log: Option<Arc<PartitionLog>>,
+}
+
+impl GroupStateStore {
+    /// Creates a store that keeps groups in memory only
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Creates a store backed by `log`, restoring the groups it holds
+    pub fn durable(log: Arc<PartitionLog>) -> ProtocolResult<Self> {
+        let mut groups = HashMap::new();
+        let batches = log
+            .read_from(log.log_start_offset(), usize::MAX)
+            .map_err(|e| ProtocolError::InvalidFormat(e.to_string()))?;
+        for batch in &batches {
+            match GroupStateRecord::decode(&mut batch.data.clone())? {
+                GroupStateRecord::Group(state) => {
+                    groups.insert(state.group_id.clone(), state);
+                }
+                GroupStateRecord::Tombstone { group_id } => {
+                    groups.remove(&group_id);
+                }
+            }
+        }
+        info!(
+            topic = log.topic(),
+            partition = log.partition(),
+            records = batches.len(),
+            groups = groups.len(),
+            "Restored group coordinator state"
+        );
+        Ok(Self {
+            groups,
+            log: Some(log),
+        })
+    }
+
+    /// Creates a store according to `group.state.durability.enable`
+    pub fn from_properties(
+        properties: &[(String, String)],
+        log: Arc<PartitionLog>,
+    ) -> ProtocolResult<Self> {
+        if broker_property(properties, "group.state.durability.enable") == Some("true") {
+            Self::durable(log)
+        } else {
+            Ok(Self::in_memory())
+        }
+    }
+
+    /// Whether transitions are written to a log
+    pub fn is_durable(&self) -> bool {
+        self.log.is_some()
+    }
+
+    /// Current state of a group
+    pub fn group(&self, group_id: &str) -> Option<&GroupState> {
+        self.groups.get(group_id)
+    }
+
+    /// Records a group's state after a transition
+    pub fn update(&mut self, state: GroupState) -> ProtocolResult<()> {
+        self.append(&GroupStateRecord::Group(state.clone()))?;
+        self.groups.insert(state.group_id.clone(), state);
+        Ok(())
+    }
+
+    /// Removes a group
+    pub fn remove(&mut self, group_id: &str) -> ProtocolResult<()> {
+        if self.groups.contains_key(group_id) {
+            self.append(&GroupStateRecord::Tombstone {
+                group_id: group_id.to_string(),
+            })?;
+            self.groups.remove(group_id);
+        }
+        Ok(())
+    }
+
+    fn append(&self, record: &GroupStateRecord) -> ProtocolResult<()> {
+        if let Some(log) = &self.log {
+            let offset = log.append(record.encode()?, 1);
+            log.advance_high_watermark(offset + 1);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stable_group(generation_id: i32) -> GroupState {
+        let member = |id: &str, instance: &str, assignment: &'static [u8]| GroupMember {
+            member_id: id.to_string(),
+            group_instance_id: Some(instance.to_string()),
+            assignment: Bytes::from_static(assignment),
+        };
+        GroupState {
+            group_id: "billing".to_string(),
+            generation_id,
+            protocol_name: Some("range".to_string()),
+            leader_id: Some("consumer-1".to_string()),
+            members: vec![
+                member("consumer-1", "host-a", b"orders-0"),
+                member("consumer-2", "host-b", b"orders-1"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_restart_restores_stable_group() {
+        let log = Arc::new(PartitionLog::new("__consumer_offsets", 0));
+        let mut store = GroupStateStore::durable(Arc::clone(&log)).unwrap();
+        store.update(stable_group(4)).unwrap();
+        store.update(stable_group(5)).unwrap();
+        drop(store);
+
+        let restarted = GroupStateStore::durable(log).unwrap();
+        assert_eq!(restarted.group("billing"), Some(&stable_group(5)));
+    }
+
+    #[test]
+    fn test_removed_group_stays_removed() {
+        let log = Arc::new(PartitionLog::new("__consumer_offsets", 0));
+        let mut store = GroupStateStore::durable(Arc::clone(&log)).unwrap();
+        store.update(stable_group(1)).unwrap();
+        store.remove("billing").unwrap();
+
+        assert_eq!(
+            GroupStateStore::durable(log).unwrap().group("billing"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_newer_record_versions_are_readable() {
+        let mut encoded =
+            BytesMut::from(&GroupStateRecord::Group(stable_group(2)).encode().unwrap()[..]);
+        encoded[..2].copy_from_slice(&1i16.to_be_bytes());
+        // A field a future version appended
+        encoded.put_i64(7);
+
+        let mut encoded = encoded.freeze();
+        assert_eq!(
+            GroupStateRecord::decode(&mut encoded).unwrap(),
+            GroupStateRecord::Group(stable_group(2))
+        );
+    }
+
+    #[test]
+    fn test_durability_is_off_by_default() {
+        let log = Arc::new(PartitionLog::new("__consumer_offsets", 0));
+        let mut store = GroupStateStore::from_properties(&[], Arc::clone(&log)).unwrap();
+        store.update(stable_group(1)).unwrap();
+        assert!(!store.is_durable());
+        assert_eq!(log.log_end_offset(), 0);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::kafka::connection::RequestContext;
+use crate::kafka::error::{wire_error, BrokerError};
+use crate::kafka::supported_apis::SupportedApis;
+use crate::logging::{debug, warn};
+use crate::protocol::api_versions::{
+    ApiVersionRange, ApiVersionsRequest, ApiVersionsResponse, API_VERSIONS_MAX_VERSION,
+    API_VERSIONS_MIN_VERSION,
+};
+use crate::protocol::{ApiKey, ProtocolEncodeVersioned, RequestHeader};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock, Weak};
+
+/// Serves one API from its raw request body
+///
+/// Registered handlers take precedence over the broker's built-in typed
+/// dispatch, and their version ranges are advertised in ApiVersions.
+#[async_trait]
+pub trait ApiHandler: Send + Sync {
+    /// The api key this handler serves
+    fn api_key(&self) -> i16;
+
+    /// Lowest and highest version served, inclusive
+    fn supported_versions(&self) -> (i16, i16);
+
+    /// Returns the response body for the request body in `body`
+    ///
+    /// Only called for versions within [`supported_versions`](Self::supported_versions),
+    /// except for ApiVersions, which has to answer any version.
+    async fn handle(
+        &self,
+        ctx: &RequestContext,
+        header: &RequestHeader,
+        body: &mut Bytes,
+    ) -> Result<BytesMut>;
+}
+
+/// The handlers registered with a broker, by api key
+///
+/// Keeps the broker's [`SupportedApis`] table, so registering a handler
+/// is all it takes for its API to be accepted and advertised.
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: HashMap<i16, Arc<dyn ApiHandler>>,
+    supported_apis: SupportedApis,
+}
+
+impl HandlerRegistry {
+    /// Creates a registry with only the ApiVersions handler, which lists
+    /// whatever the registry holds when it is asked
+    pub fn new() -> Arc<RwLock<Self>> {
+        let registry = Arc::new(RwLock::new(Self::default()));
+        let api_versions = ApiVersionsHandler::new(Arc::downgrade(&registry));
+        registry.write().unwrap().register(Arc::new(api_versions));
+        registry
+    }
+
+    /// Registers a handler, returning the one it replaces
+    pub fn register(&mut self, handler: Arc<dyn ApiHandler>) -> Option<Arc<dyn ApiHandler>> {
+        let (min_version, max_version) = handler.supported_versions();
+        self.supported_apis.insert(ApiVersionRange {
+            api_key: handler.api_key(),
+            min_version,
+            max_version,
+        });
+        self.handlers.insert(handler.api_key(), handler)
+    }
+
+    /// The handler registered for `api_key`
+    pub fn get(&self, api_key: i16) -> Option<Arc<dyn ApiHandler>> {
+        self.handlers.get(&api_key).cloned()
+    }
+
+    /// The APIs served, with their versions
+    ///
+    /// Built-in APIs keep the versions they decode, unless a registered
+    /// handler replaces them.
+    pub fn supported_apis(&self) -> &SupportedApis {
+        &self.supported_apis
+    }
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut api_keys: Vec<_> = self.handlers.keys().collect();
+        api_keys.sort();
+        f.debug_struct("HandlerRegistry")
+            .field("api_keys", &api_keys)
+            .finish()
+    }
+}
+
+/// Answers ApiVersions with the ranges of the registry it belongs to
+///
+/// Holds the registry weakly, as the registry holds the handler.
+pub struct ApiVersionsHandler {
+    registry: Weak<RwLock<HandlerRegistry>>,
+}
+
+impl ApiVersionsHandler {
+    pub fn new(registry: Weak<RwLock<HandlerRegistry>>) -> Self {
+        Self { registry }
+    }
+
+    /// Builds the response to a request at `requested_version`
+    ///
+    /// Receives the raw requested version, which may be anything the client
+    /// sent. Unsupported versions get UNSUPPORTED_VERSION in a v0 body. A
+    /// missing or truncated body is tolerated; a body that does not decode
+    /// is answered with INVALID_REQUEST, still listing the version ranges.
+    /// Returns the response with the version it must be encoded at.
+    pub fn respond(&self, requested_version: i16, body: &Bytes) -> (i16, ApiVersionsResponse) {
+        debug!("Generating ApiVersions response");
+
+        let api_versions = self
+            .registry
+            .upgrade()
+            .map(|registry| registry.read().unwrap().supported_apis().entries())
+            .unwrap_or_default();
+        let (version, mut api_versions) =
+            ApiVersionsResponse::negotiate(requested_version, api_versions);
+        if api_versions.error_code.is_error() {
+            warn!(
+                requested_version = requested_version,
+                max_version = API_VERSIONS_MAX_VERSION,
+                "Unsupported ApiVersions version, answering with v0"
+            );
+        } else {
+            match ApiVersionsRequest::decode_lenient(&mut body.clone(), version) {
+                Ok(request) if request.truncated => debug!(
+                    api_version = version,
+                    "ApiVersions body is missing or truncated, treating software name and version as empty"
+                ),
+                Ok(request) => debug!(
+                    client_software_name = %request.client_software_name,
+                    client_software_version = %request.client_software_version,
+                    "Decoded ApiVersions request"
+                ),
+                Err(e) => {
+                    warn!(error = %e, "Malformed ApiVersions request body");
+                    api_versions.error_code = wire_error(&BrokerError::Protocol(e));
+                }
+            }
+        }
+
+        debug!(
+            response_length = api_versions.encoded_size(version),
+            "Generated ApiVersions response"
+        );
+        (version, api_versions)
+    }
+}
+
+#[async_trait]
+impl ApiHandler for ApiVersionsHandler {
+    fn api_key(&self) -> i16 {
+        ApiKey::ApiVersions.code()
+    }
+
+    fn supported_versions(&self) -> (i16, i16) {
+        (API_VERSIONS_MIN_VERSION, API_VERSIONS_MAX_VERSION)
+    }
+
+    async fn handle(
+        &self,
+        _ctx: &RequestContext,
+        header: &RequestHeader,
+        body: &mut Bytes,
+    ) -> Result<BytesMut> {
+        let (version, response) = self.respond(header.api_version(), body);
+        body.clear();
+        Ok(response.encode(version)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::KafkaRequest;
+
+    struct Stub(i16, i16, i16);
+
+    #[async_trait]
+    impl ApiHandler for Stub {
+        fn api_key(&self) -> i16 {
+            self.0
+        }
+
+        fn supported_versions(&self) -> (i16, i16) {
+            (self.1, self.2)
+        }
+
+        async fn handle(
+            &self,
+            _ctx: &RequestContext,
+            _header: &RequestHeader,
+            _body: &mut Bytes,
+        ) -> Result<BytesMut> {
+            Ok(BytesMut::new())
+        }
+    }
+
+    fn range(registry: &RwLock<HandlerRegistry>, api_key: i16) -> Option<(i16, i16)> {
+        registry
+            .read()
+            .unwrap()
+            .supported_apis()
+            .get(api_key)
+            .map(|range| (range.min_version, range.max_version))
+    }
+
+    #[test]
+    fn test_registered_handlers_are_advertised() {
+        let registry = HandlerRegistry::new();
+        let builtin = registry.read().unwrap().supported_apis().entries();
+        assert_eq!(builtin, KafkaRequest::VERSION_RANGES);
+
+        registry
+            .write()
+            .unwrap()
+            .register(Arc::new(Stub(1000, 0, 2)));
+        registry
+            .write()
+            .unwrap()
+            .register(Arc::new(Stub(ApiKey::Fetch.code(), 4, 4)));
+        assert_eq!(range(&registry, 1000), Some((0, 2)));
+        assert_eq!(range(&registry, ApiKey::Fetch.code()), Some((4, 4)));
+        assert_eq!(
+            registry.read().unwrap().supported_apis().entries().len(),
+            builtin.len() + 1
+        );
+    }
+
+    #[test]
+    fn test_api_versions_lists_the_registry() {
+        let registry = HandlerRegistry::new();
+        registry
+            .write()
+            .unwrap()
+            .register(Arc::new(Stub(1000, 1, 3)));
+        let handler = registry
+            .read()
+            .unwrap()
+            .get(ApiKey::ApiVersions.code())
+            .unwrap();
+        assert_eq!(handler.supported_versions(), (0, 4));
+
+        let api_versions = ApiVersionsHandler::new(Arc::downgrade(&registry));
+        let (version, response) = api_versions.respond(3, &Bytes::new());
+        assert_eq!(version, 3);
+        assert!(response.api_keys.contains(&ApiVersionRange {
+            api_key: 1000,
+            min_version: 1,
+            max_version: 3,
+        }));
+    }
+}
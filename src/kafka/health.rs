@@ -0,0 +1,198 @@
+use crate::kafka::clock::{Clock, SystemClock};
+use crate::logging::{info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the accept loop reports that it is alive
+pub const ACCEPT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Heartbeat age beyond which the accept loop is considered dead
+const ACCEPT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Marker for "no heartbeat yet"
+const NO_HEARTBEAT: i64 = -1;
+
+/// Result of a liveness or readiness check
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthStatus {
+    pub healthy: bool,
+    /// The failing condition, when unhealthy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl HealthStatus {
+    fn ok() -> Self {
+        Self {
+            healthy: true,
+            reason: None,
+        }
+    }
+
+    fn failing(reason: impl Into<String>) -> Self {
+        Self {
+            healthy: false,
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+/// Liveness and readiness signals for orchestration probes
+///
+/// Liveness only asks whether the accept loop is still turning, which it
+/// proves by heartbeating. Readiness additionally requires startup recovery
+/// to have finished, no log directory to have failed and the number of
+/// in-flight requests to stay under the saturation threshold.
+#[derive(Debug)]
+pub struct HealthState {
+    clock: Arc<dyn Clock>,
+    origin: Instant,
+    /// Monotonic milliseconds since `origin` of the last heartbeat
+    last_heartbeat_ms: AtomicI64,
+    recovery_complete: AtomicBool,
+    failed_log_dirs: Mutex<BTreeMap<String, String>>,
+}
+
+impl HealthState {
+    /// Creates a state reading time from `clock`
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let origin = clock.now_instant();
+        Self {
+            clock,
+            origin,
+            last_heartbeat_ms: AtomicI64::new(NO_HEARTBEAT),
+            recovery_complete: AtomicBool::new(false),
+            failed_log_dirs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records that the accept loop is alive
+    pub fn heartbeat(&self) {
+        let elapsed = self.clock.now_instant().duration_since(self.origin);
+        self.last_heartbeat_ms
+            .store(elapsed.as_millis() as i64, Ordering::Relaxed);
+    }
+
+    /// Marks startup recovery as finished
+    pub fn mark_recovery_complete(&self) {
+        if !self.recovery_complete.swap(true, Ordering::Relaxed) {
+            info!("Recovery complete, broker ready to serve");
+        }
+    }
+
+    /// Records a log directory that can no longer be used
+    pub fn mark_log_dir_failed(&self, log_dir: impl Into<String>, reason: impl Into<String>) {
+        let (log_dir, reason) = (log_dir.into(), reason.into());
+        warn!(log_dir = %log_dir, reason = %reason, "Log directory marked as failed");
+        self.failed_log_dirs.lock().unwrap().insert(log_dir, reason);
+    }
+
+    /// Liveness: the accept loop heartbeated recently
+    pub fn liveness(&self) -> HealthStatus {
+        let last = self.last_heartbeat_ms.load(Ordering::Relaxed);
+        if last == NO_HEARTBEAT {
+            return HealthStatus::failing("accept loop not started");
+        }
+        let now = self.clock.now_instant().duration_since(self.origin);
+        let age = now.saturating_sub(Duration::from_millis(last as u64));
+        if age > ACCEPT_HEARTBEAT_TIMEOUT {
+            return HealthStatus::failing(format!(
+                "accept loop stalled: no heartbeat for {}ms",
+                age.as_millis()
+            ));
+        }
+        HealthStatus::ok()
+    }
+
+    /// Readiness: alive, recovered, no failed log dir and not saturated
+    pub fn readiness(&self, in_flight_requests: u64, saturation_threshold: u64) -> HealthStatus {
+        let liveness = self.liveness();
+        if !liveness.healthy {
+            return liveness;
+        }
+        if !self.recovery_complete.load(Ordering::Relaxed) {
+            return HealthStatus::failing("recovery in progress");
+        }
+        if let Some((log_dir, reason)) = self.failed_log_dirs.lock().unwrap().iter().next() {
+            return HealthStatus::failing(format!("log directory {} failed: {}", log_dir, reason));
+        }
+        if in_flight_requests > saturation_threshold {
+            return HealthStatus::failing(format!(
+                "request queue saturated: {} in flight, threshold {}",
+                in_flight_requests, saturation_threshold
+            ));
+        }
+        HealthStatus::ok()
+    }
+}
+
+impl Default for HealthState {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::clock::MockClock;
+
+    fn ready_state(clock: &Arc<MockClock>) -> HealthState {
+        let health = HealthState::new(clock.clone());
+        health.heartbeat();
+        health.mark_recovery_complete();
+        health
+    }
+
+    #[test]
+    fn test_liveness_follows_heartbeat() {
+        let clock = Arc::new(MockClock::new(0));
+        let health = HealthState::new(clock.clone());
+        assert_eq!(
+            health.liveness().reason.as_deref(),
+            Some("accept loop not started")
+        );
+
+        health.heartbeat();
+        assert!(health.liveness().healthy);
+
+        clock.advance_ms(11_000);
+        assert!(!health.liveness().healthy);
+        health.heartbeat();
+        assert!(health.liveness().healthy);
+    }
+
+    #[test]
+    fn test_failed_log_dir_fails_readiness_only() {
+        let clock = Arc::new(MockClock::new(0));
+        let health = ready_state(&clock);
+        assert!(health.readiness(0, 10).healthy);
+
+        health.mark_log_dir_failed("/data/kafka", "I/O error");
+        let readiness = health.readiness(0, 10);
+        assert!(!readiness.healthy);
+        assert_eq!(
+            readiness.reason.as_deref(),
+            Some("log directory /data/kafka failed: I/O error")
+        );
+        assert!(health.liveness().healthy);
+    }
+
+    #[test]
+    fn test_readiness_waits_for_recovery_and_capacity() {
+        let clock = Arc::new(MockClock::new(0));
+        let health = HealthState::new(clock.clone());
+        health.heartbeat();
+        assert_eq!(
+            health.readiness(0, 10).reason.as_deref(),
+            Some("recovery in progress")
+        );
+
+        health.mark_recovery_complete();
+        assert!(health.readiness(10, 10).healthy);
+        assert!(!health.readiness(11, 10).healthy);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::kafka::clock::{Clock, SystemClock};
+use crate::kafka::config::{broker_property, ConfigError};
+use crate::kafka::topic_metrics::escape_label_value;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// Upper bounds in milliseconds of the histogram buckets
+///
+/// Observations above the last bound land in a final overflow bucket.
+const BUCKET_BOUNDS_MS: [i64; 14] = [
+    1, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000,
+];
+
+/// Fixed-bucket latency histogram
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKET_BOUNDS_MS.len() + 1],
+    count: u64,
+    sum_ms: i64,
+    max_ms: i64,
+}
+
+impl LatencyHistogram {
+    /// Records one observation
+    pub fn record(&mut self, latency_ms: i64) {
+        let latency_ms = latency_ms.max(0);
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| latency_ms <= bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += latency_ms;
+        self.max_ms = self.max_ms.max(latency_ms);
+    }
+
+    /// Adds another histogram's observations to this one
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (bucket, observations) in self.buckets.iter_mut().zip(other.buckets) {
+            *bucket += observations;
+        }
+        self.count += other.count;
+        self.sum_ms += other.sum_ms;
+        self.max_ms = self.max_ms.max(other.max_ms);
+    }
+
+    /// Number of observations
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Mean latency, or 0 when empty
+    pub fn mean_ms(&self) -> i64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.sum_ms / self.count as i64
+        }
+    }
+
+    /// Largest observed latency
+    pub fn max_ms(&self) -> i64 {
+        self.max_ms
+    }
+
+    /// Upper bound of the bucket containing the given percentile (0-100)
+    ///
+    /// Observations in the overflow bucket report the observed maximum.
+    pub fn percentile_ms(&self, percentile: f64) -> i64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, &observations) in self.buckets.iter().enumerate() {
+            seen += observations;
+            if seen >= rank {
+                return BUCKET_BOUNDS_MS
+                    .get(bucket)
+                    .copied()
+                    .unwrap_or(self.max_ms)
+                    .min(self.max_ms);
+            }
+        }
+        self.max_ms
+    }
+}
+
+/// Per-topic summary of end-to-end delivery latency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopicLatencySummary {
+    pub topic: String,
+    pub count: u64,
+    pub mean_ms: i64,
+    pub p50_ms: i64,
+    pub p99_ms: i64,
+    pub max_ms: i64,
+}
+
+/// Tracks end-to-end delivery latency per topic
+///
+/// When a fetch returns a batch, the delta between the batch's max
+/// timestamp (stamped at append time) and now is the time the data spent
+/// in the broker before a consumer read it, something no client can measure
+/// alone. Batches older than the horizon are skipped so that consumers
+/// catching up on old data don't swamp the histogram.
+#[derive(Debug)]
+pub struct DeliveryLatencyTracker {
+    clock: Arc<dyn Clock>,
+    horizon_ms: i64,
+    histograms: Mutex<HashMap<String, LatencyHistogram>>,
+}
+
+impl DeliveryLatencyTracker {
+    /// Creates a tracker ignoring batches older than `horizon_ms`
+    pub fn new(clock: Arc<dyn Clock>, horizon_ms: i64) -> Self {
+        Self {
+            clock,
+            horizon_ms,
+            histograms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Builds a tracker on the system clock from broker properties, using
+    /// the default horizon when `delivery.latency.horizon.ms` is missing
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let name = "delivery.latency.horizon.ms";
+        let value = broker_property(properties, name)
+            .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))?;
+        let horizon_ms = value
+            .parse::<i64>()
+            .ok()
+            .filter(|&parsed| parsed >= 0)
+            .ok_or_else(|| ConfigError::InvalidValue {
+                key: name.to_string(),
+                value: value.to_string(),
+            })?;
+        Ok(Self::new(Arc::new(SystemClock), horizon_ms))
+    }
+
+    /// Records the delivery of a fetched batch
+    ///
+    /// Returns `false` when the batch was skipped for being past the horizon.
+    pub fn record_fetched_batch(&self, topic: &str, batch_max_timestamp_ms: i64) -> bool {
+        let latency_ms = self.clock.now_ms() - batch_max_timestamp_ms;
+        if latency_ms > self.horizon_ms {
+            return false;
+        }
+
+        let mut histograms = self.histograms.lock().unwrap();
+        match histograms.get_mut(topic) {
+            Some(histogram) => histogram.record(latency_ms),
+            None => {
+                let mut histogram = LatencyHistogram::default();
+                histogram.record(latency_ms);
+                histograms.insert(topic.to_string(), histogram);
+            }
+        }
+        true
+    }
+
+    /// Returns a copy of the histogram for a topic
+    pub fn histogram(&self, topic: &str) -> Option<LatencyHistogram> {
+        self.histograms.lock().unwrap().get(topic).cloned()
+    }
+
+    /// Summarizes every topic, sorted by name
+    pub fn summary(&self) -> Vec<TopicLatencySummary> {
+        let histograms = self.histograms.lock().unwrap();
+        let mut summary: Vec<_> = histograms
+            .iter()
+            .map(|(topic, histogram)| TopicLatencySummary {
+                topic: topic.clone(),
+                count: histogram.count(),
+                mean_ms: histogram.mean_ms(),
+                p50_ms: histogram.percentile_ms(50.0),
+                p99_ms: histogram.percentile_ms(99.0),
+                max_ms: histogram.max_ms(),
+            })
+            .collect();
+        summary.sort_by(|a, b| a.topic.cmp(&b.topic));
+        summary
+    }
+
+    /// Prometheus text exposition of the histograms
+    ///
+    /// Series are labelled with `label(topic)`; topics sharing a label
+    /// share a series.
+    pub fn render_prometheus(&self, label: impl Fn(&str) -> String) -> String {
+        let mut series: BTreeMap<String, LatencyHistogram> = BTreeMap::new();
+        for (topic, histogram) in self.histograms.lock().unwrap().iter() {
+            series.entry(label(topic)).or_default().merge(histogram);
+        }
+
+        let name = "kafka_topic_delivery_latency_ms";
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            "# HELP {} Time from append to fetch of fetched batches",
+            name
+        );
+        let _ = writeln!(output, "# TYPE {} histogram", name);
+        for (label, histogram) in &series {
+            let label = escape_label_value(label);
+            let mut cumulative = 0;
+            for (bucket, &observations) in histogram.buckets.iter().enumerate() {
+                cumulative += observations;
+                let bound = BUCKET_BOUNDS_MS
+                    .get(bucket)
+                    .map_or_else(|| "+Inf".to_string(), i64::to_string);
+                let _ = writeln!(
+                    output,
+                    "{}_bucket{{topic=\"{}\",le=\"{}\"}} {}",
+                    name, label, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                output,
+                "{}_sum{{topic=\"{}\"}} {}",
+                name, label, histogram.sum_ms
+            );
+            let _ = writeln!(
+                output,
+                "{}_count{{topic=\"{}\"}} {}",
+                name, label, histogram.count
+            );
+        }
+        output
+    }
+}
+
+impl Default for DeliveryLatencyTracker {
+    fn default() -> Self {
+        Self::from_properties(&[]).expect("broker config defaults are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::clock::MockClock;
+
+    fn tracker(clock: &Arc<MockClock>) -> DeliveryLatencyTracker {
+        DeliveryLatencyTracker::new(clock.clone(), 60_000)
+    }
+
+    #[test]
+    fn test_produce_then_fetch_records_latency() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let tracker = tracker(&clock);
+
+        // Batch appended now, fetched 40ms later
+        let append_time = clock.now_ms();
+        clock.advance_ms(40);
+        assert!(tracker.record_fetched_batch("orders", append_time));
+
+        let histogram = tracker.histogram("orders").unwrap();
+        assert_eq!(histogram.count(), 1);
+        assert_eq!(histogram.max_ms(), 40);
+        assert_eq!(histogram.percentile_ms(50.0), 40);
+    }
+
+    #[test]
+    fn test_batches_past_horizon_are_skipped() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let tracker = tracker(&clock);
+
+        let append_time = clock.now_ms();
+        clock.advance_ms(120_000);
+
+        assert!(!tracker.record_fetched_batch("orders", append_time));
+        assert!(tracker.histogram("orders").is_none());
+    }
+
+    #[test]
+    fn test_future_timestamps_count_as_zero_latency() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let tracker = tracker(&clock);
+
+        assert!(tracker.record_fetched_batch("orders", clock.now_ms() + 500));
+        assert_eq!(tracker.histogram("orders").unwrap().max_ms(), 0);
+    }
+
+    #[test]
+    fn test_summary_per_topic() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let tracker = tracker(&clock);
+        let now = clock.now_ms();
+
+        for latency in [3, 8, 20, 90] {
+            tracker.record_fetched_batch("b-topic", now - latency);
+        }
+        tracker.record_fetched_batch("a-topic", now - 2_000);
+
+        let summary = tracker.summary();
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].topic, "a-topic");
+        assert_eq!(summary[0].p99_ms, 2_000);
+        assert_eq!(summary[1].count, 4);
+        assert_eq!(summary[1].mean_ms, 30);
+        assert_eq!(summary[1].p50_ms, 10);
+        assert_eq!(summary[1].p99_ms, 90);
+    }
+
+    #[test]
+    fn test_prometheus_exposition() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let tracker = tracker(&clock);
+        let now = clock.now_ms();
+        tracker.record_fetched_batch("orders", now - 3);
+        tracker.record_fetched_batch("orders", now - 50_000);
+
+        let text = tracker.render_prometheus(str::to_string);
+        assert!(text.contains("# TYPE kafka_topic_delivery_latency_ms histogram"));
+        assert!(
+            text.contains("kafka_topic_delivery_latency_ms_bucket{topic=\"orders\",le=\"1\"} 0")
+        );
+        assert!(
+            text.contains("kafka_topic_delivery_latency_ms_bucket{topic=\"orders\",le=\"5\"} 1")
+        );
+        assert!(text
+            .contains("kafka_topic_delivery_latency_ms_bucket{topic=\"orders\",le=\"60000\"} 2"));
+        assert!(
+            text.contains("kafka_topic_delivery_latency_ms_bucket{topic=\"orders\",le=\"+Inf\"} 2")
+        );
+        assert!(text.contains("kafka_topic_delivery_latency_ms_sum{topic=\"orders\"} 50003"));
+        assert!(text.contains("kafka_topic_delivery_latency_ms_count{topic=\"orders\"} 2"));
+    }
+
+    #[test]
+    fn test_horizon_from_properties() {
+        let properties = [("delivery.latency.horizon.ms".to_string(), "-1".to_string())];
+        assert!(matches!(
+            DeliveryLatencyTracker::from_properties(&properties),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert_eq!(DeliveryLatencyTracker::default().horizon_ms, 300_000);
+    }
+
+    #[test]
+    fn test_overflow_bucket_reports_max() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(120_000);
+        assert_eq!(histogram.percentile_ms(99.0), 120_000);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::kafka::config::{broker_property, ConfigError};
+use crate::kafka::response_cache::DEFAULT_RESPONSE_CACHE_BYTES;
+use crate::protocol::decode_limits::DecodeLimits;
+use crate::protocol::spec::MAX_STRING_LENGTH;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Largest request head accepted by the debug endpoint
+const DEBUG_MAX_REQUEST_HEAD_BYTES: usize = 8 * 1024;
+
+/// Largest request body accepted by the debug endpoint
+const DEBUG_MAX_REQUEST_BODY_BYTES: usize = 1024 * 1024;
+
+/// In-flight requests above which the broker reports itself not ready
+const READINESS_MAX_IN_FLIGHT: u64 = 1000;
+
+/// Time a client connection may stay open before it is dropped
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// One configuration entry describing an effective limit
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LimitEntry {
+    pub name: &'static str,
+    pub value: String,
+    /// Whether the value comes from configuration rather than the protocol
+    /// or a fixed broker constant
+    pub configurable: bool,
+}
+
+/// Size, count and time limits enforced by the broker
+///
+/// The single source of truth for every limit: the frame reader, handlers,
+/// accept loop and debug endpoint all read their bound from here rather
+/// than from a constant of their own. Built once from broker configuration
+/// and shared by `Arc`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Largest request frame accepted from a client (`socket.request.max.bytes`)
+    pub max_request_bytes: usize,
+    /// Largest record batch accepted by produce (`message.max.bytes`)
+    pub max_message_bytes: usize,
+    /// Longest STRING or NULLABLE_STRING the protocol can carry
+    pub max_string_length: usize,
+    /// Total lifetime of a client connection
+    pub connection_timeout: Duration,
+    /// Largest HTTP request head accepted by the debug endpoint
+    pub debug_max_request_head_bytes: usize,
+    /// Largest HTTP request body accepted by the debug endpoint, which
+    /// bounds the size of an imported dynamic configuration document
+    pub debug_max_request_body_bytes: usize,
+    /// Total size of cached response bodies
+    pub response_cache_bytes: usize,
+    /// In-flight requests above which readiness fails
+    pub readiness_max_in_flight: u64,
+    /// Decoder strictness (`protocol.varint.strict`) and allocation caps,
+    /// installed process-wide at startup
+    pub decode: DecodeLimits,
+}
+
+impl Limits {
+    /// Builds limits from broker properties, using defaults for missing keys
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let bytes = |name: &str| -> Result<usize, ConfigError> {
+            let value = broker_property(properties, name)
+                .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))?;
+            value
+                .parse::<usize>()
+                .ok()
+                .filter(|&parsed| parsed > 0)
+                .ok_or_else(|| ConfigError::InvalidValue {
+                    key: name.to_string(),
+                    value: value.to_string(),
+                })
+        };
+
+        let strict_varints = broker_property(properties, "protocol.varint.strict")
+            .ok_or_else(|| ConfigError::UnknownKey("protocol.varint.strict".to_string()))?;
+        let strict_varints = match strict_varints {
+            "true" => true,
+            "false" => false,
+            value => {
+                return Err(ConfigError::InvalidValue {
+                    key: "protocol.varint.strict".to_string(),
+                    value: value.to_string(),
+                })
+            }
+        };
+
+        let max_request_bytes = bytes("socket.request.max.bytes")?;
+        Ok(Self {
+            max_request_bytes,
+            max_message_bytes: bytes("message.max.bytes")?,
+            max_string_length: MAX_STRING_LENGTH,
+            connection_timeout: CONNECTION_TIMEOUT,
+            debug_max_request_head_bytes: DEBUG_MAX_REQUEST_HEAD_BYTES,
+            debug_max_request_body_bytes: DEBUG_MAX_REQUEST_BODY_BYTES,
+            response_cache_bytes: DEFAULT_RESPONSE_CACHE_BYTES,
+            readiness_max_in_flight: READINESS_MAX_IN_FLIGHT,
+            decode: DecodeLimits {
+                strict_varints,
+                max_string_length: MAX_STRING_LENGTH,
+                // A BYTES field can never be longer than the frame holding it
+                max_bytes_length: max_request_bytes,
+                max_array_elements: bytes("protocol.array.max.elements")?,
+                max_nesting_depth: bytes("protocol.nesting.max.depth")?,
+                max_client_id_length: bytes("client.id.max.length")?,
+            },
+        })
+    }
+
+    /// Effective values as read-only broker configuration entries
+    ///
+    /// This is what DescribeConfigs on the broker resource reports, so
+    /// operators can inspect the limits with standard tooling.
+    pub fn describe(&self) -> Vec<LimitEntry> {
+        let entry = |name, value: String, configurable| LimitEntry {
+            name,
+            value,
+            configurable,
+        };
+        vec![
+            entry(
+                "socket.request.max.bytes",
+                self.max_request_bytes.to_string(),
+                true,
+            ),
+            entry(
+                "message.max.bytes",
+                self.max_message_bytes.to_string(),
+                true,
+            ),
+            entry(
+                "protocol.string.max.length",
+                self.max_string_length.to_string(),
+                false,
+            ),
+            entry(
+                "connection.timeout.ms",
+                self.connection_timeout.as_millis().to_string(),
+                false,
+            ),
+            entry(
+                "debug.request.head.max.bytes",
+                self.debug_max_request_head_bytes.to_string(),
+                false,
+            ),
+            entry(
+                "debug.request.body.max.bytes",
+                self.debug_max_request_body_bytes.to_string(),
+                false,
+            ),
+            entry(
+                "response.cache.max.bytes",
+                self.response_cache_bytes.to_string(),
+                false,
+            ),
+            entry(
+                "readiness.max.in.flight.requests",
+                self.readiness_max_in_flight.to_string(),
+                false,
+            ),
+            entry(
+                "protocol.varint.strict",
+                self.decode.strict_varints.to_string(),
+                true,
+            ),
+            entry(
+                "protocol.array.max.elements",
+                self.decode.max_array_elements.to_string(),
+                true,
+            ),
+            entry(
+                "protocol.nesting.max.depth",
+                self.decode.max_nesting_depth.to_string(),
+                true,
+            ),
+            entry(
+                "client.id.max.length",
+                self.decode.max_client_id_length.to_string(),
+                true,
+            ),
+        ]
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::from_properties(&[]).expect("broker config defaults are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_match_previous_constants() {
+        let limits = Limits::default();
+        assert_eq!(limits.max_request_bytes, 1024 * 1024);
+        assert_eq!(limits.max_message_bytes, 1048588);
+        assert_eq!(limits.max_string_length, i16::MAX as usize);
+        assert_eq!(limits.connection_timeout, Duration::from_secs(300));
+        assert_eq!(limits.debug_max_request_head_bytes, 8 * 1024);
+        assert!(limits.decode.strict_varints);
+        assert_eq!(limits.decode.max_bytes_length, limits.max_request_bytes);
+        assert_eq!(limits.decode.max_array_elements, 100_000);
+        assert_eq!(limits.decode.max_nesting_depth, 16);
+        assert_eq!(limits.decode.max_client_id_length, 256);
+    }
+
+    #[test]
+    fn test_properties_override_defaults() {
+        let properties = vec![("socket.request.max.bytes".to_string(), "4096".to_string())];
+        let limits = Limits::from_properties(&properties).unwrap();
+        assert_eq!(limits.max_request_bytes, 4096);
+
+        let lenient = vec![("protocol.varint.strict".to_string(), "false".to_string())];
+        assert!(
+            !Limits::from_properties(&lenient)
+                .unwrap()
+                .decode
+                .strict_varints
+        );
+
+        let invalid = vec![("message.max.bytes".to_string(), "-1".to_string())];
+        assert!(matches!(
+            Limits::from_properties(&invalid),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_describe_reports_effective_values() {
+        let entries = Limits::default().describe();
+        let frame = entries
+            .iter()
+            .find(|entry| entry.name == "socket.request.max.bytes")
+            .unwrap();
+        assert_eq!(frame.value, "1048576");
+        assert!(frame.configurable);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::kafka::storage::log_dir::LogDirError;
+use crate::kafka::storage::metadata_log::{
+    decode_batches, MetadataRecord, METADATA_VERSION_FEATURE,
+};
+use crate::logging::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// A partition as the cluster metadata log assigns it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterPartition {
+    pub partition_index: i32,
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+    pub leader: i32,
+    pub leader_epoch: i32,
+}
+
+/// A topic of the cluster metadata log with its partitions
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterTopic {
+    pub name: String,
+    pub topic_id: Uuid,
+    /// By partition index
+    pub partitions: BTreeMap<i32, ClusterPartition>,
+}
+
+/// The state a cluster metadata log leaves behind once replayed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClusterMetadata {
+    /// Finalized feature levels, by feature name
+    pub features: BTreeMap<String, i16>,
+    /// Ids of the registered brokers, in registration order
+    pub brokers: Vec<i32>,
+    /// Topics by name
+    pub topics: BTreeMap<String, ClusterTopic>,
+}
+
+impl ClusterMetadata {
+    /// The finalized metadata.version, if the log set one
+    pub fn metadata_version(&self) -> Option<i16> {
+        self.features.get(METADATA_VERSION_FEATURE).copied()
+    }
+
+    pub fn topic_by_id(&self, topic_id: Uuid) -> Option<&ClusterTopic> {
+        self.topics
+            .values()
+            .find(|topic| topic.topic_id == topic_id)
+    }
+
+    /// Applies one record on top of the current state
+    ///
+    /// A partition whose topic is unknown is dropped with a warning, as the
+    /// controller always writes the TopicRecord first.
+    pub fn apply(&mut self, record: MetadataRecord) {
+        match record {
+            MetadataRecord::FeatureLevel {
+                name,
+                feature_level,
+            } => {
+                self.features.insert(name, feature_level);
+            }
+            MetadataRecord::RegisterBroker { broker_id, .. } => {
+                if !self.brokers.contains(&broker_id) {
+                    self.brokers.push(broker_id);
+                }
+            }
+            MetadataRecord::Topic { name, topic_id } => {
+                self.topics.insert(
+                    name.clone(),
+                    ClusterTopic {
+                        name,
+                        topic_id,
+                        partitions: BTreeMap::new(),
+                    },
+                );
+            }
+            MetadataRecord::Partition {
+                partition_id,
+                topic_id,
+                replicas,
+                isr,
+                leader,
+                leader_epoch,
+                ..
+            } => {
+                let Some(topic) = self
+                    .topics
+                    .values_mut()
+                    .find(|topic| topic.topic_id == topic_id)
+                else {
+                    warn!(
+                        topic_id = %topic_id,
+                        partition = partition_id,
+                        "Partition of an unknown topic in the metadata log"
+                    );
+                    return;
+                };
+                topic.partitions.insert(
+                    partition_id,
+                    ClusterPartition {
+                        partition_index: partition_id,
+                        replicas,
+                        isr,
+                        leader,
+                        leader_epoch,
+                    },
+                );
+            }
+            MetadataRecord::Unknown { .. } => {}
+        }
+    }
+}
+
+/// The records of a `__cluster_metadata` log segment, with their offsets
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClusterMetadataLog {
+    records: Vec<(i64, MetadataRecord)>,
+}
+
+impl ClusterMetadataLog {
+    /// Reads and decodes the segment file at `path`
+    ///
+    /// A missing file is an empty log, as on a directory that was formatted
+    /// without one.
+    pub fn load(path: &Path) -> Result<Self, LogDirError> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let records =
+            decode_batches(&data).map_err(|e| LogDirError::CorruptMetadataLog(e.to_string()))?;
+        Ok(Self { records })
+    }
+
+    pub fn records(&self) -> &[(i64, MetadataRecord)] {
+        &self.records
+    }
+
+    /// Replays every record into a snapshot
+    pub fn metadata(&self) -> ClusterMetadata {
+        let mut metadata = ClusterMetadata::default();
+        for (_, record) in &self.records {
+            metadata.apply(record.clone());
+        }
+        metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Written by a controller: metadata.version 20 and broker 1, topic
+    /// "bar" with one partition and topic "foo" with two, the second led by
+    /// broker 2
+    const FIXTURE_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/kafka/testdata/cluster_metadata.log"
+    );
+
+    fn bar_id() -> Uuid {
+        Uuid::from_u128(0x71a5_9a51_1111_4000_8000_0000_0000_0091)
+    }
+
+    fn foo_id() -> Uuid {
+        Uuid::from_u128(0x71a5_9a51_2222_4000_8000_0000_0000_0092)
+    }
+
+    #[test]
+    fn test_fixture_topics_and_assignments() {
+        let log = ClusterMetadataLog::load(Path::new(FIXTURE_PATH)).unwrap();
+        let metadata = log.metadata();
+        assert_eq!(metadata.metadata_version(), Some(20));
+        assert_eq!(metadata.brokers, vec![1]);
+        assert_eq!(
+            metadata.topics.keys().collect::<Vec<_>>(),
+            vec!["bar", "foo"]
+        );
+
+        let bar = &metadata.topics["bar"];
+        assert_eq!(bar.topic_id, bar_id());
+        assert_eq!(
+            bar.partitions.values().collect::<Vec<_>>(),
+            vec![&ClusterPartition {
+                partition_index: 0,
+                replicas: vec![1],
+                isr: vec![1],
+                leader: 1,
+                leader_epoch: 0,
+            }]
+        );
+
+        let foo = metadata.topic_by_id(foo_id()).unwrap();
+        assert_eq!(foo.name, "foo");
+        assert_eq!(foo.partitions.len(), 2);
+        assert_eq!(foo.partitions[&1].replicas, vec![2, 1]);
+        assert_eq!(foo.partitions[&1].isr, vec![2]);
+        assert_eq!(foo.partitions[&1].leader, 2);
+        assert_eq!(foo.partitions[&1].leader_epoch, 3);
+    }
+
+    #[test]
+    fn test_missing_segment_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = ClusterMetadataLog::load(&dir.path().join("00000000000000000000.log")).unwrap();
+        assert!(log.records().is_empty());
+        assert_eq!(log.metadata(), ClusterMetadata::default());
+    }
+
+    #[test]
+    fn test_partition_of_unknown_topic_is_dropped() {
+        let mut metadata = ClusterMetadata::default();
+        metadata.apply(MetadataRecord::Partition {
+            partition_id: 0,
+            topic_id: bar_id(),
+            replicas: vec![1],
+            isr: vec![1],
+            leader: 1,
+            leader_epoch: 0,
+            partition_epoch: 0,
+        });
+        assert!(metadata.topics.is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::kafka::events::{BrokerEvent, EventBus};
+use crate::logging::debug;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// File in the log directory holding the last metadata epoch
+pub const METADATA_EPOCH_FILE: &str = "metadata.epoch";
+
+/// Broker-local counter of metadata changes
+///
+/// Bumped once for every batch of topic, partition, config or listener
+/// changes, so clients and the response cache can tell that metadata
+/// changed by comparing one number. When backed by a log directory the
+/// epoch is persisted before the change it stands for is applied: after a
+/// crash the epoch may be ahead of the applied state, but it never goes
+/// backwards across a restart.
+#[derive(Debug)]
+pub struct MetadataEpoch {
+    epoch: AtomicU64,
+    /// Where the epoch is persisted; in memory only when unset
+    path: Option<PathBuf>,
+    /// Serializes bumps so epochs are persisted and applied in order
+    bump: Mutex<()>,
+}
+
+impl MetadataEpoch {
+    /// Creates an in-memory epoch starting at zero
+    pub fn in_memory() -> Self {
+        Self {
+            epoch: AtomicU64::new(0),
+            path: None,
+            bump: Mutex::new(()),
+        }
+    }
+
+    /// Loads the epoch persisted in `dir`, starting at zero when there is none
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(METADATA_EPOCH_FILE);
+        let epoch = load_counter(&path)?.unwrap_or(0);
+        Ok(Self {
+            epoch: AtomicU64::new(epoch),
+            path: Some(path),
+            bump: Mutex::new(()),
+        })
+    }
+
+    /// The current epoch
+    pub fn current(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Applies one batch of metadata changes under a new epoch
+    ///
+    /// The next epoch is made durable first, then `apply` runs, then the
+    /// epoch becomes current and is published on `events`. If persisting
+    /// fails, `apply` is not run.
+    ///
+    /// # Examples
+    /// ```
+    /// use codecrafters_kafka::kafka::events::{BrokerEvent, EventBus};
+    /// use codecrafters_kafka::kafka::metadata_epoch::MetadataEpoch;
+    ///
+    /// let events = EventBus::default();
+    /// let mut subscriber = events.subscribe("example");
+    /// let epoch = MetadataEpoch::in_memory();
+    ///
+    /// let (advanced, created) = epoch.advance(&events, || vec!["orders", "audit"]).unwrap();
+    /// assert_eq!((advanced, created.len()), (1, 2));
+    /// assert_eq!(
+    ///     subscriber.try_recv(),
+    ///     Some(BrokerEvent::MetadataEpochAdvanced { epoch: 1 })
+    /// );
+    /// ```
+    pub fn advance<T>(&self, events: &EventBus, apply: impl FnOnce() -> T) -> io::Result<(u64, T)> {
+        let _bump = self.bump.lock().unwrap();
+        let next = self.current() + 1;
+        if let Some(path) = &self.path {
+            persist_counter(path, next)?;
+        }
+        let applied = apply();
+        self.epoch.store(next, Ordering::Release);
+        debug!(metadata_epoch = next, "Metadata epoch advanced");
+        events.publish(BrokerEvent::MetadataEpochAdvanced { epoch: next });
+        Ok((next, applied))
+    }
+}
+
+impl Default for MetadataEpoch {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+/// Reads a counter persisted by [`persist_counter`], `None` when the file
+/// does not exist
+pub(crate) fn load_counter<T: FromStr>(path: &Path) -> io::Result<Option<T>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents.trim().parse().map(Some).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a counter: {:?}", path.display(), contents),
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Atomically replaces a persisted counter: written to a temporary file,
+/// synced, then renamed over the old one
+pub(crate) fn persist_counter(path: &Path, value: impl Display) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = File::create(&temporary)?;
+    file.write_all(format!("{}\n", value).as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)?;
+    if let Some(dir) = path.parent() {
+        // Make the rename itself durable
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_increments_once_per_batch() {
+        let events = EventBus::default();
+        let mut subscriber = events.subscribe("test");
+        let epoch = MetadataEpoch::in_memory();
+
+        let (advanced, applied) = epoch
+            .advance(&events, || ["orders", "audit", "events"].len())
+            .unwrap();
+        assert_eq!((advanced, applied), (1, 3));
+        assert_eq!(epoch.current(), 1);
+        assert_eq!(
+            subscriber.try_recv(),
+            Some(BrokerEvent::MetadataEpochAdvanced { epoch: 1 })
+        );
+        assert_eq!(subscriber.try_recv(), None);
+    }
+
+    #[test]
+    fn test_epoch_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = EventBus::default();
+
+        let epoch = MetadataEpoch::load(dir.path()).unwrap();
+        assert_eq!(epoch.current(), 0);
+        for _ in 0..3 {
+            epoch.advance(&events, || ()).unwrap();
+        }
+        drop(epoch);
+
+        let reloaded = MetadataEpoch::load(dir.path()).unwrap();
+        assert_eq!(reloaded.current(), 3);
+        assert_eq!(reloaded.advance(&events, || ()).unwrap().0, 4);
+    }
+
+    #[test]
+    fn test_epoch_is_persisted_before_change_applies() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = EventBus::default();
+        let epoch = MetadataEpoch::load(dir.path()).unwrap();
+
+        // A crash inside apply must find the new epoch already on disk
+        epoch
+            .advance(&events, || {
+                assert_eq!(MetadataEpoch::load(dir.path()).unwrap().current(), 1);
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_epoch_file_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(METADATA_EPOCH_FILE), b"garbage").unwrap();
+        assert_eq!(
+            MetadataEpoch::load(dir.path()).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
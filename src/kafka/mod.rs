@@ -1 +1,38 @@
 pub mod broker;
+pub mod clock;
+pub mod config;
+pub mod connection;
+pub mod connection_registry;
+pub mod diagnostics;
+pub mod dynamic_config;
+pub mod error;
+pub mod events;
+pub mod fetch_session;
+pub mod group_coordinator;
+pub mod group_state;
+pub mod handler;
+pub mod health;
+pub mod latency;
+pub mod limits;
+pub mod metadata;
+pub mod metadata_epoch;
+pub mod offset_store;
+pub mod producer_id_manager;
+pub mod producer_state;
+pub mod purgatory;
+pub mod quarantine;
+pub mod replay;
+pub mod request_queue;
+pub mod response_cache;
+pub mod sasl;
+pub mod state_dump;
+pub mod storage;
+pub mod supported_apis;
+pub mod throughput;
+pub mod topic_metrics;
+pub mod watermark;
+
+#[cfg(test)]
+mod compat;
+#[cfg(test)]
+pub(crate) mod test_util;
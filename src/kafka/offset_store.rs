@@ -0,0 +1,229 @@
+use crate::kafka::clock::{Clock, SystemClock};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// Longest metadata string a commit may carry (`offset.metadata.max.bytes`)
+pub const MAX_OFFSET_METADATA_BYTES: usize = 4096;
+
+/// Errors raised when committing offsets
+#[derive(Error, Debug, PartialEq)]
+pub enum OffsetStoreError {
+    #[error(
+        "Metadata of {length} bytes for {topic}-{partition} in group '{group_id}' \
+         exceeds {limit} bytes"
+    )]
+    MetadataTooLarge {
+        group_id: String,
+        topic: String,
+        partition: i32,
+        length: usize,
+        limit: usize,
+    },
+}
+
+/// Offset a group committed for one partition
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommittedOffset {
+    pub offset: i64,
+    /// -1 when the committer did not know it
+    pub leader_epoch: i32,
+    pub metadata: String,
+    /// Milliseconds since the Unix epoch at which the commit was stored
+    pub commit_timestamp_ms: i64,
+}
+
+/// A committed offset together with whose it is, as dumped
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommittedOffsetSnapshot {
+    pub group_id: String,
+    pub topic: String,
+    pub partition: i32,
+    #[serde(flatten)]
+    pub committed: CommittedOffset,
+}
+
+/// (group, topic, partition)
+type OffsetKey = (String, String, i32);
+
+/// Offsets committed by consumer groups
+///
+/// Commits overwrite the previous offset of the partition; the store does
+/// not check them against group membership, which is up to the caller.
+/// Offsets are kept in memory only.
+#[derive(Debug)]
+pub struct OffsetStore {
+    clock: Arc<dyn Clock>,
+    offsets: Mutex<BTreeMap<OffsetKey, CommittedOffset>>,
+}
+
+impl OffsetStore {
+    /// Creates an empty store stamping commits from `clock`
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            offsets: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Stores `offset` as the group's position in a partition
+    ///
+    /// A missing metadata string is stored as an empty one.
+    pub fn commit(
+        &self,
+        group_id: &str,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+        leader_epoch: i32,
+        metadata: Option<&str>,
+    ) -> Result<(), OffsetStoreError> {
+        let metadata = metadata.unwrap_or_default();
+        if metadata.len() > MAX_OFFSET_METADATA_BYTES {
+            return Err(OffsetStoreError::MetadataTooLarge {
+                group_id: group_id.to_string(),
+                topic: topic.to_string(),
+                partition,
+                length: metadata.len(),
+                limit: MAX_OFFSET_METADATA_BYTES,
+            });
+        }
+        let committed = CommittedOffset {
+            offset,
+            leader_epoch,
+            metadata: metadata.to_string(),
+            commit_timestamp_ms: self.clock.now_ms(),
+        };
+        self.offsets.lock().unwrap().insert(
+            (group_id.to_string(), topic.to_string(), partition),
+            committed,
+        );
+        Ok(())
+    }
+
+    /// The group's committed offset for a partition, if any
+    pub fn committed(
+        &self,
+        group_id: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Option<CommittedOffset> {
+        self.offsets
+            .lock()
+            .unwrap()
+            .get(&(group_id.to_string(), topic.to_string(), partition))
+            .cloned()
+    }
+
+    /// Every offset the group committed, ordered by topic and partition
+    pub fn group_offsets(&self, group_id: &str) -> Vec<(String, i32, CommittedOffset)> {
+        self.offsets
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((group, _, _), _)| group == group_id)
+            .map(|((_, topic, partition), committed)| {
+                (topic.clone(), *partition, committed.clone())
+            })
+            .collect()
+    }
+
+    /// Every committed offset, ordered by group, topic and partition
+    pub fn snapshot(&self) -> Vec<CommittedOffsetSnapshot> {
+        self.offsets
+            .lock()
+            .unwrap()
+            .iter()
+            .map(
+                |((group_id, topic, partition), committed)| CommittedOffsetSnapshot {
+                    group_id: group_id.clone(),
+                    topic: topic.clone(),
+                    partition: *partition,
+                    committed: committed.clone(),
+                },
+            )
+            .collect()
+    }
+}
+
+impl Default for OffsetStore {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::clock::MockClock;
+
+    #[test]
+    fn test_commit_overwrites_and_stamps() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let store = OffsetStore::new(clock.clone());
+        store
+            .commit("billing", "orders", 0, 5, -1, Some("first"))
+            .unwrap();
+        clock.advance_ms(250);
+        store.commit("billing", "orders", 0, 9, 2, None).unwrap();
+
+        assert_eq!(
+            store.committed("billing", "orders", 0),
+            Some(CommittedOffset {
+                offset: 9,
+                leader_epoch: 2,
+                metadata: String::new(),
+                commit_timestamp_ms: 1_250,
+            })
+        );
+        assert_eq!(store.committed("billing", "orders", 1), None);
+        assert_eq!(store.committed("audit", "orders", 0), None);
+    }
+
+    #[test]
+    fn test_group_offsets_are_per_group() {
+        let store = OffsetStore::new(Arc::new(MockClock::new(0)));
+        for (group_id, topic, partition) in [
+            ("billing", "payments", 0),
+            ("billing", "orders", 1),
+            ("audit", "orders", 0),
+            ("billing", "orders", 0),
+        ] {
+            store
+                .commit(group_id, topic, partition, 7, -1, None)
+                .unwrap();
+        }
+        let partitions: Vec<_> = store
+            .group_offsets("billing")
+            .into_iter()
+            .map(|(topic, partition, _)| (topic, partition))
+            .collect();
+        assert_eq!(
+            partitions,
+            [
+                ("orders".to_string(), 0),
+                ("orders".to_string(), 1),
+                ("payments".to_string(), 0),
+            ]
+        );
+        assert!(store.group_offsets("search").is_empty());
+    }
+
+    #[test]
+    fn test_oversized_metadata_is_rejected() {
+        let store = OffsetStore::default();
+        let metadata = "x".repeat(MAX_OFFSET_METADATA_BYTES + 1);
+        assert_eq!(
+            store.commit("billing", "orders", 0, 5, -1, Some(&metadata)),
+            Err(OffsetStoreError::MetadataTooLarge {
+                group_id: "billing".to_string(),
+                topic: "orders".to_string(),
+                partition: 0,
+                length: MAX_OFFSET_METADATA_BYTES + 1,
+                limit: MAX_OFFSET_METADATA_BYTES,
+            })
+        );
+        assert_eq!(store.committed("billing", "orders", 0), None);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::kafka::metadata_epoch::{load_counter, persist_counter};
+use crate::kafka::producer_state::ProducerStateError;
+use crate::logging::{debug, info};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// File in the log directory holding the next producer id to hand out
+pub const PRODUCER_ID_FILE: &str = "producer.id";
+
+/// Errors raised while initializing a producer
+#[derive(Error, Debug)]
+pub enum InitProducerIdError {
+    #[error(transparent)]
+    ProducerState(#[from] ProducerStateError),
+
+    #[error("Failed to persist the producer id counter: {0}")]
+    Persist(#[from] io::Error),
+}
+
+/// Hands out producer ids to idempotent producers and bumps their epochs
+///
+/// Ids increase monotonically from zero and every producer starts at epoch
+/// 0. When backed by a log directory the next id is persisted before an id
+/// is handed out, so no id is given to two producers across a restart. The
+/// epochs handed out are kept in memory only.
+#[derive(Debug)]
+pub struct ProducerIdManager {
+    /// Where the next id is persisted; in memory only when unset
+    path: Option<PathBuf>,
+    /// Also serializes allocations so ids are persisted in order
+    ids: Mutex<ProducerIds>,
+}
+
+/// Producer ids handed out so far, as dumped
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProducerIdSnapshot {
+    pub next_id: i64,
+    /// Current epoch of every producer id handed out since startup
+    pub epochs: BTreeMap<i64, i16>,
+}
+
+#[derive(Debug, Default)]
+struct ProducerIds {
+    next_id: i64,
+    epochs: HashMap<i64, i16>,
+}
+
+impl ProducerIdManager {
+    /// Creates an in-memory manager starting at id zero
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            ids: Mutex::new(ProducerIds::default()),
+        }
+    }
+
+    /// Loads the counter persisted in `dir`, starting at zero when there is none
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(PRODUCER_ID_FILE);
+        let next_id = load_counter(&path)?.unwrap_or(0);
+        Ok(Self {
+            path: Some(path),
+            ids: Mutex::new(ProducerIds {
+                next_id,
+                epochs: HashMap::new(),
+            }),
+        })
+    }
+
+    /// The id the next producer will be given
+    pub fn next_id(&self) -> i64 {
+        self.ids.lock().unwrap().next_id
+    }
+
+    /// The next id and the epochs handed out
+    pub fn snapshot(&self) -> ProducerIdSnapshot {
+        let ids = self.ids.lock().unwrap();
+        ProducerIdSnapshot {
+            next_id: ids.next_id,
+            epochs: ids.epochs.iter().map(|(&id, &epoch)| (id, epoch)).collect(),
+        }
+    }
+
+    /// Handles an InitProducerId request
+    ///
+    /// With `current_producer_id` of -1 a fresh id is allocated at epoch 0.
+    /// Otherwise (v3+) the client is re-initializing: its epoch must match
+    /// the one handed out, and the epoch is bumped in place, fencing off
+    /// batches still sent under the old one. A retried bump that already
+    /// took effect returns the bumped epoch again. When the epoch would
+    /// overflow a fresh id is allocated instead. If persisting the counter
+    /// fails no id is handed out.
+    pub fn init_producer_id(
+        &self,
+        current_producer_id: i64,
+        current_epoch: i16,
+    ) -> Result<(i64, i16), InitProducerIdError> {
+        let mut ids = self.ids.lock().unwrap();
+        if current_producer_id < 0 {
+            return Ok(self.allocate(&mut ids)?);
+        }
+
+        let Some(&epoch) = ids.epochs.get(&current_producer_id) else {
+            return Err(ProducerStateError::InvalidProducerIdMapping {
+                producer_id: current_producer_id,
+                epoch: current_epoch,
+            }
+            .into());
+        };
+
+        if current_epoch == epoch {
+            if epoch >= i16::MAX - 1 {
+                ids.epochs.remove(&current_producer_id);
+                return Ok(self.allocate(&mut ids)?);
+            }
+            ids.epochs.insert(current_producer_id, epoch + 1);
+            info!(
+                producer_id = current_producer_id,
+                epoch = epoch + 1,
+                "Bumped producer epoch"
+            );
+            return Ok((current_producer_id, epoch + 1));
+        }
+        if current_epoch == epoch - 1 {
+            return Ok((current_producer_id, epoch));
+        }
+        Err(ProducerStateError::InvalidProducerEpoch {
+            producer_id: current_producer_id,
+            epoch: current_epoch,
+            current_epoch: epoch,
+        }
+        .into())
+    }
+
+    /// Allocates a producer id no other producer has been given, at epoch 0
+    fn allocate(&self, ids: &mut ProducerIds) -> io::Result<(i64, i16)> {
+        let id = ids.next_id;
+        if let Some(path) = &self.path {
+            persist_counter(path, id + 1)?;
+        }
+        ids.next_id = id + 1;
+        ids.epochs.insert(id, 0);
+        debug!(producer_id = id, "Allocated producer id");
+        Ok((id, 0))
+    }
+}
+
+impl Default for ProducerIdManager {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_ids_are_unique_across_threads() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = Arc::new(ProducerIdManager::load(dir.path()).unwrap());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let manager = Arc::clone(&manager);
+                std::thread::spawn(move || {
+                    (0..25)
+                        .map(|_| manager.init_producer_id(-1, -1).unwrap().0)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let ids: HashSet<i64> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+        assert_eq!(ids, (0..200).collect());
+        assert_eq!(manager.next_id(), 200);
+    }
+
+    #[test]
+    fn test_counter_resumes_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = ProducerIdManager::load(dir.path()).unwrap();
+        assert_eq!(manager.init_producer_id(-1, -1).unwrap(), (0, 0));
+        assert_eq!(manager.init_producer_id(-1, -1).unwrap(), (1, 0));
+        drop(manager);
+
+        let reloaded = ProducerIdManager::load(dir.path()).unwrap();
+        assert_eq!(reloaded.init_producer_id(-1, -1).unwrap(), (2, 0));
+    }
+
+    #[test]
+    fn test_corrupt_counter_file_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(PRODUCER_ID_FILE), b"garbage").unwrap();
+        assert_eq!(
+            ProducerIdManager::load(dir.path()).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_epoch_bump_happy_path() {
+        let ids = ProducerIdManager::in_memory();
+        let (producer_id, epoch) = ids.init_producer_id(-1, -1).unwrap();
+        assert_eq!((producer_id, epoch), (0, 0));
+
+        assert_eq!(
+            ids.init_producer_id(producer_id, 0).unwrap(),
+            (producer_id, 1)
+        );
+        // A retried bump is idempotent
+        assert_eq!(
+            ids.init_producer_id(producer_id, 0).unwrap(),
+            (producer_id, 1)
+        );
+    }
+
+    #[test]
+    fn test_epoch_bump_rejects_stale_and_unknown() {
+        let ids = ProducerIdManager::in_memory();
+        let (producer_id, _) = ids.init_producer_id(-1, -1).unwrap();
+        ids.init_producer_id(producer_id, 0).unwrap();
+        ids.init_producer_id(producer_id, 1).unwrap();
+
+        assert!(matches!(
+            ids.init_producer_id(producer_id, 0),
+            Err(InitProducerIdError::ProducerState(
+                ProducerStateError::InvalidProducerEpoch { .. }
+            ))
+        ));
+        assert!(matches!(
+            ids.init_producer_id(42, 0),
+            Err(InitProducerIdError::ProducerState(
+                ProducerStateError::InvalidProducerIdMapping { .. }
+            ))
+        ));
+    }
+}
@@ -0,0 +1,390 @@
+use crate::kafka::clock::Clock;
+use crate::logging::{debug, info};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Default idle time after which producer state is dropped (`producer.id.expiration.ms`)
+pub const DEFAULT_PRODUCER_ID_EXPIRATION_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// How often idle producer state is looked for
+/// (`producer.id.expiration.check.interval.ms`)
+pub const PRODUCER_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Number of recent batches remembered per producer for duplicate detection
+pub const DEDUP_WINDOW_BATCHES: usize = 5;
+
+/// Errors raised when validating idempotent producer state
+#[derive(Error, Debug, PartialEq)]
+pub enum ProducerStateError {
+    #[error("Unknown producer id {producer_id}")]
+    UnknownProducerId { producer_id: i64 },
+
+    #[error("Producer {producer_id} epoch {epoch} is older than current epoch {current_epoch}")]
+    InvalidProducerEpoch {
+        producer_id: i64,
+        epoch: i16,
+        current_epoch: i16,
+    },
+
+    #[error("Producer {producer_id} sent sequence {received}, expected {expected}")]
+    OutOfOrderSequence {
+        producer_id: i64,
+        expected: i32,
+        received: i32,
+    },
+
+    #[error("Producer id {producer_id} with epoch {epoch} does not match any known producer")]
+    InvalidProducerIdMapping { producer_id: i64, epoch: i16 },
+}
+
+/// Outcome of validating a batch from an idempotent producer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SequenceCheck {
+    /// The batch is new and should be appended
+    Accepted,
+    /// The batch was already appended at `base_offset`
+    Duplicate { base_offset: i64 },
+}
+
+/// Sequence state of one producer on a partition
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProducerSnapshot {
+    pub producer_id: i64,
+    pub epoch: i16,
+    /// Last sequence of the newest remembered batch
+    pub last_sequence: i32,
+    /// Base offset of the newest remembered batch
+    pub last_base_offset: i64,
+    pub idle_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BatchMetadata {
+    first_sequence: i32,
+    last_sequence: i32,
+    base_offset: i64,
+}
+
+#[derive(Debug, Clone)]
+struct ProducerEntry {
+    epoch: i16,
+    batches: VecDeque<BatchMetadata>,
+    last_seen: Instant,
+}
+
+/// Idempotent producer sequence state of a single partition
+///
+/// Remembers the last few batches of every producer so retried batches are
+/// acknowledged with their original offset instead of being appended twice.
+/// Producers idle for longer than the expiration are dropped to keep the
+/// map bounded; a dropped producer that resumes mid-sequence is told to
+/// re-initialize with `UNKNOWN_PRODUCER_ID`.
+#[derive(Debug, Clone)]
+pub struct ProducerStateManager {
+    clock: Arc<dyn Clock>,
+    expiration_ms: i64,
+    producers: HashMap<i64, ProducerEntry>,
+    /// Duplicate detections by how many batches behind the newest the
+    /// duplicate was found (index 0 is a retry of the latest batch)
+    duplicate_histogram: [u64; DEDUP_WINDOW_BATCHES],
+}
+
+impl ProducerStateManager {
+    /// Creates an empty manager expiring producers idle for `expiration_ms`
+    pub fn new(clock: Arc<dyn Clock>, expiration_ms: i64) -> Self {
+        Self {
+            clock,
+            expiration_ms,
+            producers: HashMap::new(),
+            duplicate_histogram: [0; DEDUP_WINDOW_BATCHES],
+        }
+    }
+
+    /// Validates a batch before it is appended
+    pub fn check_batch(
+        &mut self,
+        producer_id: i64,
+        epoch: i16,
+        first_sequence: i32,
+        last_sequence: i32,
+    ) -> Result<SequenceCheck, ProducerStateError> {
+        let Some(entry) = self.producers.get(&producer_id) else {
+            // Unknown producers may only start a fresh sequence
+            return if first_sequence == 0 {
+                Ok(SequenceCheck::Accepted)
+            } else {
+                Err(ProducerStateError::UnknownProducerId { producer_id })
+            };
+        };
+
+        if epoch < entry.epoch {
+            return Err(ProducerStateError::InvalidProducerEpoch {
+                producer_id,
+                epoch,
+                current_epoch: entry.epoch,
+            });
+        }
+        if epoch > entry.epoch {
+            // A bumped epoch restarts the sequence
+            return if first_sequence == 0 {
+                Ok(SequenceCheck::Accepted)
+            } else {
+                Err(ProducerStateError::OutOfOrderSequence {
+                    producer_id,
+                    expected: 0,
+                    received: first_sequence,
+                })
+            };
+        }
+
+        let duplicate = entry.batches.iter().rev().enumerate().find(|(_, batch)| {
+            batch.first_sequence == first_sequence && batch.last_sequence == last_sequence
+        });
+        if let Some((age, batch)) = duplicate {
+            let base_offset = batch.base_offset;
+            self.duplicate_histogram[age] += 1;
+            debug!(
+                producer_id = producer_id,
+                first_sequence = first_sequence,
+                base_offset = base_offset,
+                "Duplicate batch from idempotent producer"
+            );
+            return Ok(SequenceCheck::Duplicate { base_offset });
+        }
+
+        let expected = entry
+            .batches
+            .back()
+            .map(|batch| next_sequence(batch.last_sequence))
+            .unwrap_or(0);
+        if first_sequence != expected {
+            return Err(ProducerStateError::OutOfOrderSequence {
+                producer_id,
+                expected,
+                received: first_sequence,
+            });
+        }
+        Ok(SequenceCheck::Accepted)
+    }
+
+    /// Records a batch that was appended at `base_offset`
+    pub fn record_batch(
+        &mut self,
+        producer_id: i64,
+        epoch: i16,
+        first_sequence: i32,
+        last_sequence: i32,
+        base_offset: i64,
+    ) {
+        let now = self.clock.now_instant();
+        let entry = self
+            .producers
+            .entry(producer_id)
+            .or_insert_with(|| ProducerEntry {
+                epoch,
+                batches: VecDeque::with_capacity(DEDUP_WINDOW_BATCHES),
+                last_seen: now,
+            });
+        if epoch != entry.epoch {
+            entry.epoch = epoch;
+            entry.batches.clear();
+        }
+        if entry.batches.len() == DEDUP_WINDOW_BATCHES {
+            entry.batches.pop_front();
+        }
+        entry.batches.push_back(BatchMetadata {
+            first_sequence,
+            last_sequence,
+            base_offset,
+        });
+        entry.last_seen = now;
+    }
+
+    /// Drops producers idle for at least the expiration, returning how many
+    pub fn expire_idle_producers(&mut self) -> usize {
+        // Idle time is measured on the monotonic clock so a wall-clock step
+        // backwards cannot keep producers alive forever
+        let now = self.clock.now_instant();
+        let expiration = Duration::from_millis(self.expiration_ms.max(0) as u64);
+        let before = self.producers.len();
+        self.producers
+            .retain(|_, entry| now.duration_since(entry.last_seen) < expiration);
+        let expired = before - self.producers.len();
+        if expired > 0 {
+            info!(
+                expired_producers = expired,
+                remaining_producers = self.producers.len(),
+                "Expired idle producer state"
+            );
+        }
+        expired
+    }
+
+    /// Tracked producers ordered by id
+    pub fn snapshot(&self) -> Vec<ProducerSnapshot> {
+        let now = self.clock.now_instant();
+        let mut producers: Vec<ProducerSnapshot> = self
+            .producers
+            .iter()
+            .filter_map(|(&producer_id, entry)| {
+                let newest = entry.batches.back()?;
+                Some(ProducerSnapshot {
+                    producer_id,
+                    epoch: entry.epoch,
+                    last_sequence: newest.last_sequence,
+                    last_base_offset: newest.base_offset,
+                    idle_ms: now.duration_since(entry.last_seen).as_millis() as u64,
+                })
+            })
+            .collect();
+        producers.sort_by_key(|producer| producer.producer_id);
+        producers
+    }
+
+    /// Number of producers with tracked state
+    pub fn tracked_producer_count(&self) -> usize {
+        self.producers.len()
+    }
+
+    /// Number of batch entries held for duplicate detection
+    pub fn dedup_cache_size(&self) -> usize {
+        self.producers
+            .values()
+            .map(|entry| entry.batches.len())
+            .sum()
+    }
+
+    /// Duplicate detections bucketed by batch age within the window
+    pub fn duplicate_histogram(&self) -> [u64; DEDUP_WINDOW_BATCHES] {
+        self.duplicate_histogram
+    }
+}
+
+fn next_sequence(sequence: i32) -> i32 {
+    if sequence == i32::MAX {
+        0
+    } else {
+        sequence + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::clock::MockClock;
+
+    fn manager(clock: Arc<MockClock>) -> ProducerStateManager {
+        ProducerStateManager::new(clock, 1_000)
+    }
+
+    fn append(
+        state: &mut ProducerStateManager,
+        pid: i64,
+        epoch: i16,
+        first: i32,
+        last: i32,
+        offset: i64,
+    ) {
+        assert_eq!(
+            state.check_batch(pid, epoch, first, last),
+            Ok(SequenceCheck::Accepted)
+        );
+        state.record_batch(pid, epoch, first, last, offset);
+    }
+
+    #[test]
+    fn test_duplicate_batch_returns_original_offset() {
+        let mut state = manager(Arc::new(MockClock::new(0)));
+        append(&mut state, 7, 0, 0, 4, 100);
+        append(&mut state, 7, 0, 5, 9, 105);
+
+        assert_eq!(
+            state.check_batch(7, 0, 0, 4),
+            Ok(SequenceCheck::Duplicate { base_offset: 100 })
+        );
+        assert_eq!(state.duplicate_histogram(), [0, 1, 0, 0, 0]);
+        assert_eq!(state.tracked_producer_count(), 1);
+        assert_eq!(state.dedup_cache_size(), 2);
+    }
+
+    #[test]
+    fn test_sequence_gap_is_rejected() {
+        let mut state = manager(Arc::new(MockClock::new(0)));
+        append(&mut state, 7, 0, 0, 4, 0);
+
+        assert_eq!(
+            state.check_batch(7, 0, 8, 9),
+            Err(ProducerStateError::OutOfOrderSequence {
+                producer_id: 7,
+                expected: 5,
+                received: 8,
+            })
+        );
+    }
+
+    #[test]
+    fn test_dedup_window_is_bounded() {
+        let mut state = manager(Arc::new(MockClock::new(0)));
+        for i in 0..10 {
+            append(&mut state, 1, 0, i, i, i as i64);
+        }
+        assert_eq!(state.dedup_cache_size(), DEDUP_WINDOW_BATCHES);
+    }
+
+    #[test]
+    fn test_idle_producers_expire_under_mock_time() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut state = manager(clock.clone());
+        append(&mut state, 1, 0, 0, 0, 0);
+        clock.advance_ms(600);
+        append(&mut state, 2, 0, 0, 0, 1);
+
+        clock.advance_ms(500);
+        assert_eq!(state.expire_idle_producers(), 1);
+        assert_eq!(state.tracked_producer_count(), 1);
+
+        // The evicted producer resuming mid-sequence must re-initialize
+        let error = state.check_batch(1, 0, 1, 1).unwrap_err();
+        assert!(matches!(
+            error,
+            ProducerStateError::UnknownProducerId { producer_id: 1 }
+        ));
+    }
+
+    #[test]
+    fn test_expiry_survives_wall_clock_stepping_back() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let mut state = manager(clock.clone());
+        append(&mut state, 1, 0, 0, 0, 0);
+
+        clock.set_ms(0);
+        clock.advance_ms(1_000);
+        assert_eq!(state.expire_idle_producers(), 1);
+    }
+
+    #[test]
+    fn test_stale_epoch_is_rejected() {
+        let mut state = manager(Arc::new(MockClock::new(0)));
+        append(&mut state, 3, 1, 0, 0, 0);
+
+        let error = state.check_batch(3, 0, 1, 1).unwrap_err();
+        assert!(matches!(
+            error,
+            ProducerStateError::InvalidProducerEpoch {
+                epoch: 0,
+                current_epoch: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_bumped_epoch_restarts_sequence() {
+        let mut state = manager(Arc::new(MockClock::new(0)));
+        append(&mut state, 5, 0, 0, 9, 0);
+        append(&mut state, 5, 1, 0, 0, 10);
+    }
+}
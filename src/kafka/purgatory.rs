@@ -0,0 +1,282 @@
+use crate::kafka::config::{broker_property, ConfigError};
+use crate::logging::{info, warn};
+use crate::protocol::ErrorCode;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How operations still parked at shutdown are answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownCompletion {
+    /// Answer as if the wait had been satisfied
+    Success,
+    /// Answer REQUEST_TIMED_OUT, so the client retries elsewhere
+    TimedOut,
+}
+
+impl ShutdownCompletion {
+    fn error_code(self) -> ErrorCode {
+        match self {
+            Self::Success => ErrorCode::NONE,
+            Self::TimedOut => ErrorCode::REQUEST_TIMED_OUT,
+        }
+    }
+}
+
+/// Settings for parking acks=-1 produces
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurgatoryConfig {
+    /// Artificial wait of an acks=-1 produce, standing in for replication
+    /// (`produce.purgatory.delay.ms`); zero answers without parking
+    pub delay: Duration,
+    /// `produce.purgatory.shutdown.completion`: `success` or `timeout`
+    pub shutdown_completion: ShutdownCompletion,
+}
+
+impl PurgatoryConfig {
+    /// Builds the settings from broker properties, using defaults for missing keys
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let property = |name: &str| {
+            broker_property(properties, name)
+                .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))
+        };
+        let invalid = |name: &str, value: &str| ConfigError::InvalidValue {
+            key: name.to_string(),
+            value: value.to_string(),
+        };
+
+        let delay = property("produce.purgatory.delay.ms")?;
+        let delay = delay
+            .parse::<u64>()
+            .map_err(|_| invalid("produce.purgatory.delay.ms", delay))?;
+        let shutdown_completion = match property("produce.purgatory.shutdown.completion")? {
+            "success" => ShutdownCompletion::Success,
+            "timeout" => ShutdownCompletion::TimedOut,
+            other => return Err(invalid("produce.purgatory.shutdown.completion", other)),
+        };
+        Ok(Self {
+            delay: Duration::from_millis(delay),
+            shutdown_completion,
+        })
+    }
+}
+
+impl Default for PurgatoryConfig {
+    fn default() -> Self {
+        Self::from_properties(&[]).expect("broker config defaults are valid")
+    }
+}
+
+#[derive(Debug)]
+struct ParkedEntry {
+    correlation_id: i32,
+    /// Taken when the operation is completed
+    completion: Option<oneshot::Sender<ErrorCode>>,
+}
+
+/// Produce requests waiting for acks=-1 to be satisfied
+///
+/// Shutdown contract: [`drain`](Self::drain) runs first, while connections
+/// are still open, and completes every parked operation with the configured
+/// [`ShutdownCompletion`]. Handlers then return, connections flush those
+/// responses and only close afterwards. Operations parked after the drain
+/// complete immediately the same way. An operation whose handler has not
+/// finished stays listed in [`pending`](Self::pending), which the server
+/// logs if the drain deadline passes.
+#[derive(Debug)]
+pub struct Purgatory {
+    config: PurgatoryConfig,
+    next_id: AtomicU64,
+    parked: Mutex<BTreeMap<u64, ParkedEntry>>,
+    closed: AtomicBool,
+}
+
+impl Purgatory {
+    pub fn new(config: PurgatoryConfig) -> Self {
+        Self {
+            config,
+            next_id: AtomicU64::new(0),
+            parked: Mutex::new(BTreeMap::new()),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn config(&self) -> &PurgatoryConfig {
+        &self.config
+    }
+
+    /// Parks an operation until [`ParkedOperation::wait`] is satisfied or
+    /// the purgatory is drained
+    pub fn park(&self, correlation_id: i32) -> ParkedOperation<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        let mut parked = self.parked.lock().unwrap();
+        let completion = if self.closed.load(Ordering::Acquire) {
+            let _ = sender.send(self.config.shutdown_completion.error_code());
+            None
+        } else {
+            Some(sender)
+        };
+        parked.insert(
+            id,
+            ParkedEntry {
+                correlation_id,
+                completion,
+            },
+        );
+        ParkedOperation {
+            purgatory: self,
+            id,
+            receiver,
+        }
+    }
+
+    /// Completes every parked operation with the shutdown completion and
+    /// answers later ones immediately; returns how many were completed
+    pub fn drain(&self) -> usize {
+        let mut parked = self.parked.lock().unwrap();
+        self.closed.store(true, Ordering::Release);
+        let code = self.config.shutdown_completion.error_code();
+        let mut completed = 0;
+        for entry in parked.values_mut() {
+            if let Some(sender) = entry.completion.take() {
+                let _ = sender.send(code);
+                completed += 1;
+            }
+        }
+        info!(
+            completed = completed,
+            error_code = %code,
+            "Drained produce purgatory for shutdown"
+        );
+        completed
+    }
+
+    /// Correlation ids of operations whose handler has not finished
+    pub fn pending(&self) -> Vec<i32> {
+        self.parked
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.correlation_id)
+            .collect()
+    }
+
+    /// Logs every operation still pending, for when the drain deadline passed
+    pub fn log_pending(&self) {
+        for correlation_id in self.pending() {
+            warn!(
+                correlation_id = correlation_id,
+                "Produce still pending after the shutdown drain deadline"
+            );
+        }
+    }
+}
+
+impl Default for Purgatory {
+    fn default() -> Self {
+        Self::new(PurgatoryConfig::default())
+    }
+}
+
+/// An operation parked in the [`Purgatory`], removed from it on drop
+#[derive(Debug)]
+pub struct ParkedOperation<'a> {
+    purgatory: &'a Purgatory,
+    id: u64,
+    receiver: oneshot::Receiver<ErrorCode>,
+}
+
+impl ParkedOperation<'_> {
+    /// Waits for the operation to be satisfied
+    ///
+    /// Returns NONE once `delay` has passed, REQUEST_TIMED_OUT if `timeout`
+    /// passes first, or whatever the purgatory completed it with.
+    pub async fn wait(mut self, delay: Duration, timeout: Duration) -> ErrorCode {
+        let (wait, satisfied) = if timeout < delay {
+            (timeout, ErrorCode::REQUEST_TIMED_OUT)
+        } else {
+            (delay, ErrorCode::NONE)
+        };
+        tokio::select! {
+            code = &mut self.receiver => code.unwrap_or(ErrorCode::REQUEST_TIMED_OUT),
+            _ = tokio::time::sleep(wait) => satisfied,
+        }
+    }
+}
+
+impl Drop for ParkedOperation<'_> {
+    fn drop(&mut self) {
+        self.purgatory.parked.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn purgatory(shutdown_completion: ShutdownCompletion) -> Purgatory {
+        Purgatory::new(PurgatoryConfig {
+            delay: Duration::from_secs(60),
+            shutdown_completion,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_delay_and_timeout() {
+        let purgatory = Purgatory::default();
+        let short = Duration::from_millis(1);
+        let long = Duration::from_secs(60);
+        assert_eq!(purgatory.park(1).wait(short, long).await, ErrorCode::NONE);
+        assert_eq!(
+            purgatory.park(2).wait(long, short).await,
+            ErrorCode::REQUEST_TIMED_OUT
+        );
+        assert!(purgatory.pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drain_completes_parked_and_later_operations() {
+        for (completion, expected) in [
+            (ShutdownCompletion::TimedOut, ErrorCode::REQUEST_TIMED_OUT),
+            (ShutdownCompletion::Success, ErrorCode::NONE),
+        ] {
+            let purgatory = purgatory(completion);
+            let parked = purgatory.park(7);
+            assert_eq!(purgatory.pending(), vec![7]);
+            assert_eq!(purgatory.drain(), 1);
+            // Still pending until its handler is done with it
+            assert_eq!(purgatory.pending(), vec![7]);
+            let long = Duration::from_secs(60);
+            assert_eq!(parked.wait(long, long).await, expected);
+            assert!(purgatory.pending().is_empty());
+
+            assert_eq!(purgatory.park(8).wait(long, long).await, expected);
+        }
+    }
+
+    #[test]
+    fn test_config_from_properties() {
+        let config = PurgatoryConfig::default();
+        assert_eq!(config.delay, Duration::ZERO);
+        assert_eq!(config.shutdown_completion, ShutdownCompletion::TimedOut);
+
+        let properties = vec![(
+            "produce.purgatory.shutdown.completion".to_string(),
+            "success".to_string(),
+        )];
+        assert_eq!(
+            PurgatoryConfig::from_properties(&properties)
+                .unwrap()
+                .shutdown_completion,
+            ShutdownCompletion::Success
+        );
+        let invalid = vec![(
+            "produce.purgatory.shutdown.completion".to_string(),
+            "never".to_string(),
+        )];
+        assert!(PurgatoryConfig::from_properties(&invalid).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use crate::kafka::clock::{Clock, SystemClock};
+use crate::kafka::config::{broker_property, ConfigError};
+use crate::logging::{error, info};
+use crate::protocol::ProtocolError;
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most bytes of a frame kept for quarantine; longer frames are saved
+/// truncated so the copy taken before decoding stays cheap
+pub const QUARANTINE_MAX_FRAME_BYTES: usize = 64 * 1024;
+
+/// Settings for saving unparseable requests
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuarantineConfig {
+    /// False only for [`Quarantine::disabled`]; otherwise frames are saved
+    /// while the diagnostics level allows it
+    pub enabled: bool,
+    /// `quarantine.dir`, or `quarantine` under the log directory when unset
+    pub dir: PathBuf,
+    /// Frames saved per window (`quarantine.max.frames`)
+    pub max_frames: usize,
+    /// Length of the rolling window (`quarantine.window.ms`)
+    pub window: Duration,
+    /// Bound on the size of the quarantine directory (`quarantine.max.bytes`)
+    pub max_bytes: u64,
+}
+
+impl QuarantineConfig {
+    /// Builds the settings from broker properties, using defaults for missing keys
+    pub fn from_properties(
+        properties: &[(String, String)],
+        log_dir: &Path,
+    ) -> Result<Self, ConfigError> {
+        let property = |name: &str| {
+            broker_property(properties, name)
+                .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))
+        };
+        let number = |name: &str| -> Result<u64, ConfigError> {
+            let value = property(name)?;
+            value.parse::<u64>().map_err(|_| ConfigError::InvalidValue {
+                key: name.to_string(),
+                value: value.to_string(),
+            })
+        };
+
+        let dir = match property("quarantine.dir")? {
+            "" => log_dir.join("quarantine"),
+            dir => PathBuf::from(dir),
+        };
+        Ok(Self {
+            enabled: true,
+            dir,
+            max_frames: number("quarantine.max.frames")? as usize,
+            window: Duration::from_millis(number("quarantine.window.ms")?),
+            max_bytes: number("quarantine.max.bytes")?,
+        })
+    }
+}
+
+/// Metadata saved next to each quarantined frame
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuarantinedFrame {
+    pub peer: String,
+    pub timestamp_ms: i64,
+    pub error: String,
+    /// Read from the first bytes of the frame, which may be garbage
+    pub api_key_guess: Option<i16>,
+    pub api_version_guess: Option<i16>,
+    pub frame_len: usize,
+    /// Whether `frame_hex` stops at [`QUARANTINE_MAX_FRAME_BYTES`]
+    pub truncated: bool,
+    pub frame_hex: String,
+}
+
+/// Counters of the quarantine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct QuarantineMetrics {
+    pub saved: u64,
+    /// Failures past the per-window or size limit, counted but not saved
+    pub suppressed: u64,
+}
+
+#[derive(Debug, Default)]
+struct QuarantineState {
+    /// When each frame of the current window was saved
+    saved_at: VecDeque<Instant>,
+    /// Size of the quarantine directory, read from disk on first use
+    dir_bytes: Option<u64>,
+    sequence: u64,
+}
+
+/// Safety net that saves the first few unparseable requests to disk
+///
+/// On from the basic diagnostics level, the default, so the bytes of a
+/// parse failure are there to look at without the level having been
+/// raised beforehand. At most
+/// `max_frames` frames are saved per rolling window and the directory is
+/// kept under `max_bytes`; further failures only bump a counter. Saving is
+/// synchronous, which is fine at a handful of files per day.
+#[derive(Debug)]
+pub struct Quarantine {
+    config: QuarantineConfig,
+    clock: Arc<dyn Clock>,
+    state: Mutex<QuarantineState>,
+    saved: AtomicU64,
+    suppressed: AtomicU64,
+}
+
+impl Quarantine {
+    pub fn new(config: QuarantineConfig) -> Self {
+        Self {
+            config,
+            clock: Arc::new(SystemClock),
+            state: Mutex::new(QuarantineState::default()),
+            saved: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    /// A quarantine that saves nothing
+    pub fn disabled() -> Self {
+        Self::new(QuarantineConfig {
+            enabled: false,
+            dir: PathBuf::new(),
+            max_frames: 0,
+            window: Duration::ZERO,
+            max_bytes: 0,
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn metrics(&self) -> QuarantineMetrics {
+        QuarantineMetrics {
+            saved: self.saved.load(Ordering::Relaxed),
+            suppressed: self.suppressed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Prefix of a frame to keep in case it fails to parse, or `None` when
+    /// quarantine is disabled
+    ///
+    /// The prefix shares the frame's memory, so keeping it costs nothing
+    /// for the frames that parse.
+    pub fn keep(&self, frame: &Bytes) -> Option<(Bytes, usize)> {
+        self.is_enabled().then(|| {
+            (
+                frame.slice(..frame.len().min(QUARANTINE_MAX_FRAME_BYTES)),
+                frame.len(),
+            )
+        })
+    }
+
+    /// Records a frame that failed to parse
+    ///
+    /// `frame` is the prefix returned by [`keep`](Self::keep) and
+    /// `frame_len` the length of the whole frame. Returns the path written,
+    /// if the frame was saved.
+    pub fn record(
+        &self,
+        peer: SocketAddr,
+        frame: &[u8],
+        frame_len: usize,
+        error: &ProtocolError,
+    ) -> Option<PathBuf> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let guess = |at: usize| {
+            frame
+                .get(at..at + 2)
+                .map(|bytes| i16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        let entry = QuarantinedFrame {
+            peer: peer.to_string(),
+            timestamp_ms: self.clock.now_ms(),
+            error: error.to_string(),
+            api_key_guess: guess(0),
+            api_version_guess: guess(2),
+            frame_len,
+            truncated: frame.len() < frame_len,
+            frame_hex: hex::encode(frame),
+        };
+        let json = match serde_json::to_vec_pretty(&entry) {
+            Ok(json) => json,
+            Err(e) => {
+                error!(error = %e, "Failed to serialize quarantined frame");
+                return None;
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let now = self.clock.now_instant();
+        while let Some(&oldest) = state.saved_at.front() {
+            if now.duration_since(oldest) < self.config.window {
+                break;
+            }
+            state.saved_at.pop_front();
+        }
+        let dir_bytes = *state
+            .dir_bytes
+            .get_or_insert_with(|| directory_size(&self.config.dir));
+        if state.saved_at.len() >= self.config.max_frames
+            || dir_bytes + json.len() as u64 > self.config.max_bytes
+        {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        state.sequence += 1;
+        let path = self.config.dir.join(format!(
+            "request-{}-{}.json",
+            entry.timestamp_ms, state.sequence
+        ));
+        match std::fs::create_dir_all(&self.config.dir).and_then(|_| std::fs::write(&path, &json)) {
+            Ok(()) => {
+                state.saved_at.push_back(now);
+                state.dir_bytes = Some(dir_bytes + json.len() as u64);
+                self.saved.fetch_add(1, Ordering::Relaxed);
+                info!(path = %path.display(), peer = %peer, "Quarantined unparseable request");
+                Some(path)
+            }
+            Err(e) => {
+                error!(path = %path.display(), error = %e, "Failed to quarantine request");
+                self.suppressed.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+/// Whether an error means the request bytes did not parse, as opposed to
+/// a failure encoding the response
+pub fn is_decode_failure(error: &ProtocolError) -> bool {
+    !matches!(
+        error.root(),
+        ProtocolError::SerializationError(_) | ProtocolError::BufferOverflow { .. }
+    )
+}
+
+fn directory_size(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.metadata().ok())
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::broker::KafkaBroker;
+    use crate::kafka::clock::MockClock;
+    use crate::kafka::diagnostics::DiagnosticsLevel;
+    use crate::kafka::test_util::{frame, read_response, spawn_connection_with, test_peer_addr};
+    use tokio::io::AsyncWriteExt;
+
+    fn config(dir: &Path) -> QuarantineConfig {
+        QuarantineConfig::from_properties(&[], Path::new("/unused"))
+            .map(|config| QuarantineConfig {
+                dir: dir.to_path_buf(),
+                ..config
+            })
+            .unwrap()
+    }
+
+    fn saved_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map(|entries| entries.map(|entry| entry.unwrap().path()).collect())
+            .unwrap_or_default();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = QuarantineConfig::from_properties(&[], Path::new("/var/lib/kafka")).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.dir, PathBuf::from("/var/lib/kafka/quarantine"));
+        assert_eq!(config.max_frames, 10);
+        assert_eq!(config.window, Duration::from_secs(24 * 60 * 60));
+
+        let invalid = vec![("quarantine.max.frames".to_string(), "many".to_string())];
+        assert!(QuarantineConfig::from_properties(&invalid, Path::new("/tmp")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_twelve_failures_save_ten_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let broker =
+            Arc::new(KafkaBroker::new().with_quarantine(Quarantine::new(config(dir.path()))));
+        let (mut client, _handle) = spawn_connection_with(Arc::clone(&broker));
+
+        for garbage in 0..12u8 {
+            // Too short for a request header
+            client
+                .write_all(&frame(&[0x00, 0x12, garbage]))
+                .await
+                .unwrap();
+        }
+        // Requests are handled in order, so once this is answered every
+        // failure above has been recorded
+        client
+            .write_all(&frame(&[
+                0x00, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0xff, 0xff,
+            ]))
+            .await
+            .unwrap();
+        read_response(&mut client).await;
+
+        let files = saved_files(dir.path());
+        assert_eq!(files.len(), 10);
+        assert_eq!(
+            broker.quarantine().metrics(),
+            QuarantineMetrics {
+                saved: 10,
+                suppressed: 2
+            }
+        );
+
+        let saved: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&files[0]).unwrap()).unwrap();
+        assert_eq!(saved["peer"], test_peer_addr().to_string());
+        assert_eq!(saved["api_key_guess"], 18);
+        assert_eq!(saved["api_version_guess"], serde_json::Value::Null);
+        assert_eq!(saved["frame_len"], 3);
+        assert!(saved["error"].as_str().unwrap().contains("Insufficient"));
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_level_gates_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let broker =
+            Arc::new(KafkaBroker::new().with_quarantine(Quarantine::new(config(dir.path()))));
+        let (mut client, _handle) = spawn_connection_with(Arc::clone(&broker));
+        let valid = frame(&[0x00, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0xff, 0xff]);
+
+        for (level, saved) in [
+            (DiagnosticsLevel::Off, 0),
+            (DiagnosticsLevel::Basic, 1),
+            (DiagnosticsLevel::Off, 1),
+            (DiagnosticsLevel::Verbose, 2),
+        ] {
+            broker.dynamic_config().diagnostics().set_level(level);
+            client.write_all(&frame(&[0x00, 0x12, 0x01])).await.unwrap();
+            client.write_all(&valid).await.unwrap();
+            read_response(&mut client).await;
+            assert_eq!(saved_files(dir.path()).len(), saved, "{}", level);
+        }
+    }
+
+    #[test]
+    fn test_window_rolls_and_size_is_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(MockClock::new(0));
+        let quarantine = Quarantine::new(QuarantineConfig {
+            max_frames: 1,
+            ..config(dir.path())
+        })
+        .with_clock(clock.clone());
+        let error = ProtocolError::insufficient_bytes(8, 3);
+
+        assert!(quarantine
+            .record(test_peer_addr(), b"abc", 3, &error)
+            .is_some());
+        assert!(quarantine
+            .record(test_peer_addr(), b"abc", 3, &error)
+            .is_none());
+        clock.advance_ms(24 * 60 * 60 * 1000);
+        assert!(quarantine
+            .record(test_peer_addr(), b"abc", 3, &error)
+            .is_some());
+        assert_eq!(saved_files(dir.path()).len(), 2);
+
+        let tiny = Quarantine::new(QuarantineConfig {
+            max_bytes: 10,
+            ..config(dir.path())
+        });
+        assert!(tiny.record(test_peer_addr(), b"abc", 3, &error).is_none());
+        assert_eq!(tiny.metrics().suppressed, 1);
+    }
+
+    #[test]
+    fn test_disabled_keeps_nothing() {
+        let quarantine = Quarantine::disabled();
+        assert!(quarantine.keep(&Bytes::from_static(b"abc")).is_none());
+        let error = ProtocolError::insufficient_bytes(8, 3);
+        assert!(quarantine
+            .record(test_peer_addr(), b"abc", 3, &error)
+            .is_none());
+        assert_eq!(quarantine.metrics(), QuarantineMetrics::default());
+    }
+}
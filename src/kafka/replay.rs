@@ -0,0 +1,432 @@
+//! Replays captured client traffic against an in-process broker
+//!
+//! A capture is a text file with one frame per line, without its length
+//! prefix:
+//!
+//! ```text
+//! # comment
+//! > 0 00120000000000010004...   request sent 0ms into the capture
+//! < 3 00000001000000...         response to the request with that correlation id
+//! ```
+//!
+//! Responses are compared with the typed decoders where we have them, so
+//! fields that legitimately differ between runs (throttle time, log append
+//! time) don't show up as regressions.
+
+use crate::kafka::broker::KafkaBroker;
+use crate::protocol::api_versions::ApiVersionsResponse;
+use crate::protocol::produce::ProduceResponse;
+use crate::protocol::trace::{DecodeCursor, DecodeTrace};
+use crate::protocol::ApiKey;
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::time::{sleep_until, timeout, Instant};
+
+/// How long to wait for the broker to answer one replayed request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One captured request and, unless the client expected none, its response
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedExchange {
+    pub offset_ms: u64,
+    pub request: Bytes,
+    pub response: Option<Bytes>,
+}
+
+impl CapturedExchange {
+    fn api_key(&self) -> i16 {
+        i16::from_be_bytes([self.request[0], self.request[1]])
+    }
+
+    fn api_version(&self) -> i16 {
+        i16::from_be_bytes([self.request[2], self.request[3]])
+    }
+
+    fn correlation_id(&self) -> i32 {
+        correlation_id(&self.request[4..])
+    }
+}
+
+/// Captured request log, in the order the client sent it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capture {
+    pub exchanges: Vec<CapturedExchange>,
+}
+
+impl Capture {
+    /// Parses the text capture format
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut exchanges: Vec<CapturedExchange> = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let context = || format!("capture line {}", line_number + 1);
+            let mut fields = line.split_whitespace();
+            let (Some(direction), Some(offset_ms), Some(frame), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(anyhow!("expected '<direction> <offset_ms> <hex>'"))
+                    .with_context(context);
+            };
+            let offset_ms = offset_ms.parse().with_context(context)?;
+            let frame = Bytes::from(hex::decode(frame).with_context(context)?);
+
+            match direction {
+                ">" => {
+                    if frame.len() < 8 {
+                        return Err(anyhow!("request shorter than its header"))
+                            .with_context(context);
+                    }
+                    exchanges.push(CapturedExchange {
+                        offset_ms,
+                        request: frame,
+                        response: None,
+                    });
+                }
+                "<" => {
+                    if frame.len() < 4 {
+                        return Err(anyhow!("response without correlation id"))
+                            .with_context(context);
+                    }
+                    let id = correlation_id(&frame);
+                    let exchange = exchanges
+                        .iter_mut()
+                        .rev()
+                        .find(|exchange| exchange.correlation_id() == id)
+                        .ok_or_else(|| anyhow!("response to unknown correlation id {}", id))
+                        .with_context(context)?;
+                    exchange.response = Some(frame);
+                }
+                other => {
+                    return Err(anyhow!("unknown direction {:?}", other)).with_context(context)
+                }
+            }
+        }
+        Ok(Self { exchanges })
+    }
+}
+
+/// Pacing of a replay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayTiming {
+    /// Send each request at its captured offset
+    Original,
+    /// Send each request as soon as the previous response arrived
+    AsFastAsPossible,
+}
+
+/// Structural differences found in one response
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseDiff {
+    pub index: usize,
+    pub api_key: i16,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    pub differences: Vec<String>,
+}
+
+/// Outcome of replaying a capture
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayReport {
+    pub exchanges: usize,
+    pub diffs: Vec<ResponseDiff>,
+}
+
+impl ReplayReport {
+    /// Returns true when every response matched
+    pub fn is_clean(&self) -> bool {
+        self.diffs.is_empty()
+    }
+}
+
+/// Replays `capture` against a fresh broker and compares the responses
+pub async fn replay(capture: &Capture, timing: ReplayTiming) -> Result<ReplayReport> {
+    let broker = Arc::new(KafkaBroker::new());
+    let (mut client, mut server) = tokio::io::duplex(64 * 1024);
+    let connection = tokio::spawn(async move {
+        broker
+            .handle_connection(&mut server, "127.0.0.1:0".parse().unwrap())
+            .await
+    });
+
+    let start = Instant::now();
+    let mut report = ReplayReport::default();
+    for (index, exchange) in capture.exchanges.iter().enumerate() {
+        if timing == ReplayTiming::Original {
+            sleep_until(start + Duration::from_millis(exchange.offset_ms)).await;
+        }
+        client
+            .write_all(&(exchange.request.len() as i32).to_be_bytes())
+            .await?;
+        client.write_all(&exchange.request).await?;
+        report.exchanges += 1;
+
+        let Some(expected) = &exchange.response else {
+            continue;
+        };
+        let differences = match timeout(RESPONSE_TIMEOUT, read_frame(&mut client)).await {
+            Ok(Ok(actual)) => diff_response(
+                exchange.api_key(),
+                exchange.api_version(),
+                expected,
+                &actual,
+            ),
+            Ok(Err(e)) => vec![format!("connection failed: {}", e)],
+            Err(_) => vec!["no response".to_string()],
+        };
+        if !differences.is_empty() {
+            report.diffs.push(ResponseDiff {
+                index,
+                api_key: exchange.api_key(),
+                api_version: exchange.api_version(),
+                correlation_id: exchange.correlation_id(),
+                differences,
+            });
+        }
+    }
+
+    drop(client);
+    connection.await??;
+    Ok(report)
+}
+
+async fn read_frame(client: &mut DuplexStream) -> Result<Bytes> {
+    let length = client.read_i32().await?;
+    let mut frame = vec![0u8; usize::try_from(length)?];
+    client.read_exact(&mut frame).await?;
+    Ok(frame.into())
+}
+
+fn correlation_id(frame: &[u8]) -> i32 {
+    i32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]])
+}
+
+/// Compares two response frames, returning human-readable differences
+///
+/// Both frames start with a v0 response header. Bodies of APIs with a typed
+/// decoder are compared field by field, skipping fields that vary between
+/// runs; anything else must match byte for byte.
+pub fn diff_response(
+    api_key: i16,
+    api_version: i16,
+    expected: &[u8],
+    actual: &[u8],
+) -> Vec<String> {
+    if actual.len() < 4 {
+        return vec![format!("response too short: {} bytes", actual.len())];
+    }
+    let mut differences = Vec::new();
+    if correlation_id(expected) != correlation_id(actual) {
+        differences.push(format!(
+            "correlation_id: expected {}, got {}",
+            correlation_id(expected),
+            correlation_id(actual)
+        ));
+    }
+
+    let (expected_body, actual_body) = (&expected[4..], &actual[4..]);
+    let typed = match ApiKey::try_from(api_key) {
+        Ok(ApiKey::Produce) => diff_produce(api_version, expected_body, actual_body),
+        Ok(ApiKey::ApiVersions) => diff_api_versions(api_version, expected_body, actual_body),
+        _ => None,
+    };
+    match typed {
+        Some(typed) => differences.extend(typed),
+        None if expected_body != actual_body => differences.push(format!(
+            "body: expected {}, got {}",
+            hex::encode(expected_body),
+            hex::encode(actual_body)
+        )),
+        None => {}
+    }
+    differences
+}
+
+/// Produce responses, ignoring throttle time and log append time
+fn diff_produce(version: i16, expected: &[u8], actual: &[u8]) -> Option<Vec<String>> {
+    let expected = ProduceResponse::decode(&mut Bytes::copy_from_slice(expected), version).ok()?;
+    let actual = ProduceResponse::decode(&mut Bytes::copy_from_slice(actual), version).ok()?;
+
+    let mut differences = Vec::new();
+    let partitions = |response: &ProduceResponse| {
+        response
+            .topics
+            .iter()
+            .flat_map(|topic| {
+                topic.partitions.iter().map(move |partition| {
+                    (
+                        format!("{}/{}", topic.name, partition.index),
+                        (partition.error_code, partition.base_offset),
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    let (expected, actual) = (partitions(&expected), partitions(&actual));
+    if expected.len() != actual.len() {
+        differences.push(format!(
+            "partition count: expected {}, got {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    for ((name, expected), (actual_name, actual)) in expected.iter().zip(&actual) {
+        if name != actual_name {
+            differences.push(format!("partition: expected {}, got {}", name, actual_name));
+        } else if expected.0 != actual.0 {
+            differences.push(format!(
+                "{}.error_code: expected {}, got {}",
+                name, expected.0, actual.0
+            ));
+        } else if expected.1 != actual.1 {
+            differences.push(format!(
+                "{}.base_offset: expected {}, got {}",
+                name, expected.1, actual.1
+            ));
+        }
+    }
+    Some(differences)
+}
+
+/// ApiVersions responses, ignoring throttle time
+///
+/// When they differ, the first difference names the first field at which
+/// the two bodies diverge.
+fn diff_api_versions(version: i16, expected: &[u8], actual: &[u8]) -> Option<Vec<String>> {
+    let decode = |body: &[u8]| {
+        // Unsupported versions are answered with a v0 body
+        [version, 0].into_iter().find_map(|version| {
+            let mut buffer = Bytes::copy_from_slice(body);
+            let mut trace = DecodeTrace::new();
+            let response = ApiVersionsResponse::decode_from(
+                &mut DecodeCursor::traced(&mut buffer, &mut trace),
+                version,
+            )
+            .ok()?;
+            Some((response, trace))
+        })
+    };
+    let ((expected, expected_trace), (actual, actual_trace)) = (decode(expected)?, decode(actual)?);
+
+    let mut differences = Vec::new();
+    if expected.error_code != actual.error_code {
+        differences.push(format!(
+            "error_code: expected {}, got {}",
+            expected.error_code, actual.error_code
+        ));
+    }
+    if expected.api_keys != actual.api_keys {
+        differences.push(format!(
+            "api_versions: expected {:?}, got {:?}",
+            expected.api_keys, actual.api_keys
+        ));
+    }
+    if !differences.is_empty() {
+        if let Some(divergence) = expected_trace.first_divergence(&actual_trace) {
+            let render = |value: Option<String>| value.unwrap_or_else(|| "nothing".to_string());
+            differences.insert(
+                0,
+                format!(
+                    "first divergent field {}: expected {}, got {}",
+                    divergence.path,
+                    render(divergence.expected),
+                    render(divergence.actual)
+                ),
+            );
+        }
+    }
+    Some(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASIC_CAPTURE: &str = include_str!("testdata/basic.capture");
+
+    #[tokio::test]
+    async fn test_checked_in_capture_replays_cleanly() {
+        let capture = Capture::parse(BASIC_CAPTURE).unwrap();
+        let report = replay(&capture, ReplayTiming::AsFastAsPossible)
+            .await
+            .unwrap();
+        assert_eq!(report.exchanges, capture.exchanges.len());
+        assert!(report.is_clean(), "{:#?}", report.diffs);
+    }
+
+    #[tokio::test]
+    async fn test_changed_error_code_is_reported() {
+        let mut capture = Capture::parse(BASIC_CAPTURE).unwrap();
+        let produce = capture
+            .exchanges
+            .iter_mut()
+            .find(|exchange| exchange.api_key() == ApiKey::Produce.code())
+            .unwrap();
+        // Pretend the recorded broker accepted the write
+        let mut response = produce.response.clone().unwrap().to_vec();
+        let error_code_at = response.len() - 10;
+        response[error_code_at..error_code_at + 2].copy_from_slice(&0i16.to_be_bytes());
+        produce.response = Some(response.into());
+
+        let report = replay(&capture, ReplayTiming::AsFastAsPossible)
+            .await
+            .unwrap();
+        assert_eq!(report.diffs.len(), 1);
+        assert_eq!(
+            report.diffs[0].differences,
+            vec![
+                "test/0.error_code: expected NONE (0), got UNKNOWN_TOPIC_OR_PARTITION (3)"
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_throttle_time_is_ignored() {
+        let mut expected = 1i32.to_be_bytes().to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 100]);
+        let mut actual = expected.clone();
+        actual[13] = 0;
+
+        // ApiVersions v1: error, empty api list, throttle time
+        assert!(diff_response(ApiKey::ApiVersions.code(), 1, &expected, &actual).is_empty());
+        // Unknown APIs must match byte for byte
+        assert_eq!(
+            diff_response(ApiKey::Metadata.code(), 1, &expected, &actual).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_malformed_capture_is_rejected() {
+        assert!(Capture::parse("> 0 zz").is_err());
+        assert!(Capture::parse("< 0 00000001").is_err());
+        assert!(Capture::parse("? 0 00").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_api_versions_diff_names_first_divergent_field() {
+        let mut capture = Capture::parse(BASIC_CAPTURE).unwrap();
+        let api_versions = &mut capture.exchanges[0];
+        // Pretend the recorded broker served ApiVersions up to v5
+        let mut response = api_versions.response.clone().unwrap().to_vec();
+        // ApiVersions is the thirteenth of twenty-one advertised ranges
+        let max_version_at = response.len() - 50;
+        response[max_version_at..max_version_at + 2].copy_from_slice(&5i16.to_be_bytes());
+        api_versions.response = Some(response.into());
+
+        let report = replay(&capture, ReplayTiming::AsFastAsPossible)
+            .await
+            .unwrap();
+        assert_eq!(report.diffs.len(), 1);
+        assert_eq!(
+            report.diffs[0].differences[0],
+            "first divergent field api_keys[12].max_version: expected 5, got 4"
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use crate::kafka::config::{broker_property, ConfigError};
+use crate::kafka::latency::LatencyHistogram;
+use crate::protocol::ApiKey;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::oneshot;
+
+/// API keys only ever sent by a controller or another broker
+///
+/// The broker has a single listener, so the API key is all a request is
+/// classified by.
+const CONTROL_PLANE_API_KEYS: [i16; 5] = [
+    ApiKey::LeaderAndIsr.code(),
+    ApiKey::StopReplica.code(),
+    ApiKey::UpdateMetadata.code(),
+    ApiKey::ControlledShutdown.code(),
+    ApiKey::WriteTxnMarkers.code(),
+];
+
+/// Queue a request waits in for a worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestPriority {
+    /// Controller and inter-broker requests
+    Control,
+    /// Client produce, fetch and admin requests
+    Data,
+}
+
+impl RequestPriority {
+    /// Classifies a request by its API key
+    pub fn classify(api_key: i16) -> Self {
+        if CONTROL_PLANE_API_KEYS.contains(&api_key) {
+            Self::Control
+        } else {
+            Self::Data
+        }
+    }
+}
+
+/// Settings for the request worker pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestQueueConfig {
+    /// Requests served at once (`request.queue.workers`); zero serves
+    /// every request as soon as it arrives
+    ///
+    /// A request keeps its worker while it waits, a long-polling Fetch or
+    /// a JoinGroup waiting for its rebalance included, so the pool has to
+    /// be larger than the number of such requests expected at once.
+    pub workers: usize,
+    /// Control requests dispatched in a row while data requests are
+    /// waiting (`request.queue.control.max.consecutive`)
+    pub max_consecutive_control: u32,
+}
+
+impl RequestQueueConfig {
+    /// Builds the settings from broker properties, using defaults for missing keys
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let parse = |name: &str| {
+            let value = broker_property(properties, name)
+                .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))?;
+            value.parse::<u32>().map_err(|_| ConfigError::InvalidValue {
+                key: name.to_string(),
+                value: value.to_string(),
+            })
+        };
+        Ok(Self {
+            workers: parse("request.queue.workers")? as usize,
+            max_consecutive_control: parse("request.queue.control.max.consecutive")?.max(1),
+        })
+    }
+}
+
+impl Default for RequestQueueConfig {
+    fn default() -> Self {
+        Self::from_properties(&[]).expect("broker config defaults are valid")
+    }
+}
+
+/// Depth and wait time of one queue
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueueStats {
+    pub depth: usize,
+    pub dispatched: u64,
+    pub mean_wait_ms: i64,
+    pub p99_wait_ms: i64,
+    pub max_wait_ms: i64,
+}
+
+/// Worker use and the state of both queues
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestQueueMetrics {
+    pub workers: usize,
+    pub busy_workers: usize,
+    pub control: QueueStats,
+    pub data: QueueStats,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    enqueued_at: Instant,
+    grant: oneshot::Sender<DispatchPermit>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    busy: usize,
+    control: VecDeque<Waiter>,
+    data: VecDeque<Waiter>,
+    consecutive_control: u32,
+    control_wait: LatencyHistogram,
+    data_wait: LatencyHistogram,
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: RequestQueueConfig,
+    state: Mutex<QueueState>,
+}
+
+/// Two-level queue in front of a fixed number of request workers
+///
+/// Connections take a worker before serving a request and give it back
+/// once the response is built. Control-plane requests wait in their own
+/// queue, which is always drained first, so a ControlledShutdown does not
+/// wait behind a backlog of consumer fetches. To keep a steady stream of
+/// control requests from starving clients, at most
+/// `max_consecutive_control` of them are dispatched in a row while data
+/// requests are waiting.
+#[derive(Debug)]
+pub struct RequestQueue {
+    shared: Arc<Shared>,
+}
+
+impl RequestQueue {
+    pub fn new(config: RequestQueueConfig) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                config,
+                state: Mutex::new(QueueState::default()),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &RequestQueueConfig {
+        &self.shared.config
+    }
+
+    /// Waits for a worker to serve a request of `priority`
+    ///
+    /// The worker is held until the permit is dropped. Returns `None` at
+    /// once when the pool is disabled. A caller that stops waiting gives
+    /// its place up, and a worker handed to it goes to the next request.
+    pub async fn acquire(&self, priority: RequestPriority) -> Option<DispatchPermit> {
+        if self.shared.config.workers == 0 {
+            return None;
+        }
+        let receiver = {
+            let mut state = self.shared.state.lock().unwrap();
+            let idle = state.busy < self.shared.config.workers
+                && state.control.is_empty()
+                && state.data.is_empty();
+            if idle {
+                state.busy += 1;
+                match priority {
+                    RequestPriority::Control => state.control_wait.record(0),
+                    RequestPriority::Data => state.data_wait.record(0),
+                }
+                return Some(DispatchPermit {
+                    shared: Arc::clone(&self.shared),
+                });
+            }
+            let (grant, receiver) = oneshot::channel();
+            let waiter = Waiter {
+                enqueued_at: Instant::now(),
+                grant,
+            };
+            match priority {
+                RequestPriority::Control => state.control.push_back(waiter),
+                RequestPriority::Data => state.data.push_back(waiter),
+            }
+            receiver
+        };
+        // The sender is only dropped unsent along with the queue
+        receiver.await.ok()
+    }
+
+    /// Worker use and the depth and wait times of both queues
+    pub fn metrics(&self) -> RequestQueueMetrics {
+        let state = self.shared.state.lock().unwrap();
+        let stats = |depth: usize, wait: &LatencyHistogram| QueueStats {
+            depth,
+            dispatched: wait.count(),
+            mean_wait_ms: wait.mean_ms(),
+            p99_wait_ms: wait.percentile_ms(99.0),
+            max_wait_ms: wait.max_ms(),
+        };
+        RequestQueueMetrics {
+            workers: self.shared.config.workers,
+            busy_workers: state.busy,
+            control: stats(state.control.len(), &state.control_wait),
+            data: stats(state.data.len(), &state.data_wait),
+        }
+    }
+}
+
+impl Default for RequestQueue {
+    fn default() -> Self {
+        Self::new(RequestQueueConfig::default())
+    }
+}
+
+impl Shared {
+    /// Hands a freed worker to the next waiting request, or idles it
+    fn release(self: &Arc<Self>) {
+        let next = {
+            let mut state = self.state.lock().unwrap();
+            let next = self.take_next(&mut state);
+            if next.is_none() {
+                state.busy -= 1;
+            }
+            next
+        };
+        if let Some(waiter) = next {
+            let permit = DispatchPermit {
+                shared: Arc::clone(self),
+            };
+            // A waiter that gave up drops the permit sent back here, which
+            // passes the worker on again
+            let _ = waiter.grant.send(permit);
+        }
+    }
+
+    fn take_next(&self, state: &mut QueueState) -> Option<Waiter> {
+        let control_turn = !state.control.is_empty()
+            && (state.data.is_empty()
+                || state.consecutive_control < self.config.max_consecutive_control);
+        let (waiter, wait) = if control_turn {
+            state.consecutive_control += 1;
+            (state.control.pop_front()?, &mut state.control_wait)
+        } else {
+            state.consecutive_control = 0;
+            (state.data.pop_front()?, &mut state.data_wait)
+        };
+        wait.record(waiter.enqueued_at.elapsed().as_millis() as i64);
+        Some(waiter)
+    }
+}
+
+/// A request worker, given back to the queue when dropped
+#[derive(Debug)]
+pub struct DispatchPermit {
+    shared: Arc<Shared>,
+}
+
+impl Drop for DispatchPermit {
+    fn drop(&mut self) {
+        self.shared.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn queue(workers: usize, max_consecutive_control: u32) -> Arc<RequestQueue> {
+        Arc::new(RequestQueue::new(RequestQueueConfig {
+            workers,
+            max_consecutive_control,
+        }))
+    }
+
+    /// Queues a request that records its label once it gets a worker
+    fn spawn_request(
+        queue: &Arc<RequestQueue>,
+        priority: RequestPriority,
+        label: &'static str,
+        order: &Arc<Mutex<Vec<&'static str>>>,
+    ) -> tokio::task::JoinHandle<()> {
+        let queue = Arc::clone(queue);
+        let order = Arc::clone(order);
+        tokio::spawn(async move {
+            let _permit = queue.acquire(priority).await;
+            order.lock().unwrap().push(label);
+        })
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            RequestPriority::classify(ApiKey::ControlledShutdown.code()),
+            RequestPriority::Control
+        );
+        assert_eq!(
+            RequestPriority::classify(ApiKey::Fetch.code()),
+            RequestPriority::Data
+        );
+    }
+
+    #[test]
+    fn test_config_from_properties() {
+        assert_eq!(
+            RequestQueueConfig::default(),
+            RequestQueueConfig {
+                workers: 0,
+                max_consecutive_control: 8,
+            }
+        );
+        let properties = [
+            ("request.queue.workers".to_string(), "4".to_string()),
+            (
+                "request.queue.control.max.consecutive".to_string(),
+                "0".to_string(),
+            ),
+        ];
+        assert_eq!(
+            RequestQueueConfig::from_properties(&properties).unwrap(),
+            RequestQueueConfig {
+                workers: 4,
+                max_consecutive_control: 1,
+            }
+        );
+        let properties = [("request.queue.workers".to_string(), "-1".to_string())];
+        assert!(RequestQueueConfig::from_properties(&properties).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_pool_never_waits() {
+        let queue = queue(0, 8);
+        assert!(queue.acquire(RequestPriority::Data).await.is_none());
+        assert_eq!(queue.metrics().data.dispatched, 0);
+    }
+
+    #[tokio::test]
+    async fn test_control_goes_first_within_the_starvation_bound() {
+        let queue = queue(1, 2);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let running = queue.acquire(RequestPriority::Data).await.unwrap();
+
+        let mut requests = Vec::new();
+        for (priority, label) in [
+            (RequestPriority::Data, "data-1"),
+            (RequestPriority::Data, "data-2"),
+            (RequestPriority::Control, "control-1"),
+            (RequestPriority::Control, "control-2"),
+            (RequestPriority::Control, "control-3"),
+        ] {
+            requests.push(spawn_request(&queue, priority, label, &order));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let metrics = queue.metrics();
+        assert_eq!((metrics.control.depth, metrics.data.depth), (3, 2));
+        assert_eq!(metrics.busy_workers, 1);
+
+        drop(running);
+        for request in requests {
+            request.await.unwrap();
+        }
+        // Two control requests in a row, then a waiting data request
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["control-1", "control-2", "data-1", "control-3", "data-2"]
+        );
+        let metrics = queue.metrics();
+        assert_eq!((metrics.control.depth, metrics.data.depth), (0, 0));
+        assert_eq!(metrics.busy_workers, 0);
+        assert_eq!(metrics.control.dispatched, 3);
+        assert_eq!(metrics.data.dispatched, 3);
+        assert!(metrics.data.max_wait_ms >= 25);
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_wait_passes_the_worker_on() {
+        let queue = queue(1, 8);
+        let running = queue.acquire(RequestPriority::Data).await.unwrap();
+        let abandoned = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.acquire(RequestPriority::Control).await.is_some() }
+        });
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        abandoned.abort();
+        let _ = abandoned.await;
+
+        let waiting = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.acquire(RequestPriority::Data).await.is_some() }
+        });
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        drop(running);
+        let granted = tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(granted);
+        assert_eq!(queue.metrics().busy_workers, 0);
+    }
+}
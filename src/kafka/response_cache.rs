@@ -0,0 +1,382 @@
+use crate::kafka::events::{BrokerEvent, EventSubscriber};
+use crate::logging::debug;
+use crate::protocol::ApiKey;
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Default cap on the total size of cached response bodies
+pub const DEFAULT_RESPONSE_CACHE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Read-only APIs whose responses only change with cluster topology
+const CACHEABLE_API_KEYS: [i16; 3] = [
+    ApiKey::Metadata.code(),
+    ApiKey::ApiVersions.code(),
+    ApiKey::DescribeCluster.code(),
+];
+
+/// Identifies a cacheable request
+///
+/// The request header is not part of the key: the correlation id and client
+/// id differ between clients asking the same question, and the response
+/// header is written fresh for every response anyway. The metadata epoch
+/// is, so a response built before a metadata change is never served after
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub api_key: i16,
+    pub api_version: i16,
+    pub metadata_epoch: u64,
+    pub request: Bytes,
+}
+
+/// Result of a cache lookup
+#[derive(Debug)]
+pub enum CacheLookup {
+    /// Encoded response body from an earlier request
+    Hit(Bytes),
+    /// Nothing cached; encode the response and hand it to
+    /// [`ResponseCache::store`] with this ticket
+    Miss(CacheTicket),
+    /// The API does not participate in caching
+    Uncacheable,
+}
+
+/// Permission to store the response for a missed key
+///
+/// Carries the cache generation seen at lookup time, so a response encoded
+/// before an invalidation is not stored after it.
+#[derive(Debug)]
+pub struct CacheTicket {
+    key: CacheKey,
+    generation: u64,
+}
+
+/// Hit, miss and size counters of a [`ResponseCache`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResponseCacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    entries: HashMap<CacheKey, Bytes>,
+    /// Insertion order, oldest first, for eviction
+    order: VecDeque<CacheKey>,
+    bytes: usize,
+    generation: u64,
+    /// Newest metadata epoch looked up; entries for older ones are dropped
+    metadata_epoch: u64,
+    events: EventSubscriber,
+}
+
+/// Cache of encoded response bodies for metadata-style requests
+///
+/// Metadata, ApiVersions and DescribeCluster answer every client the same
+/// way until the topology changes, so under a herd of reconnecting clients
+/// the encoded body is reused rather than rebuilt. Entries are keyed on the
+/// metadata epoch, and the whole cache is dropped once a newer epoch is
+/// looked up, when a topic, config or listener change event arrives, or
+/// when events were missed because the subscriber lagged. Entries are evicted
+/// oldest first once the total body size exceeds the cap.
+#[derive(Debug)]
+pub struct ResponseCache {
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl ResponseCache {
+    /// Creates a cache holding up to `max_bytes` of response bodies,
+    /// invalidated by events from `events`
+    pub fn new(max_bytes: usize, events: EventSubscriber) -> Self {
+        Self {
+            max_bytes,
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+                bytes: 0,
+                generation: 0,
+                metadata_epoch: 0,
+                events,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns whether responses to `api_key` may be cached
+    pub fn is_cacheable(api_key: i16) -> bool {
+        CACHEABLE_API_KEYS.contains(&api_key)
+    }
+
+    /// Looks up the response body for a request made at `metadata_epoch`
+    pub fn lookup(
+        &self,
+        api_key: i16,
+        api_version: i16,
+        metadata_epoch: u64,
+        request: Bytes,
+    ) -> CacheLookup {
+        if !Self::is_cacheable(api_key) {
+            return CacheLookup::Uncacheable;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        self.apply_events(&mut state);
+        if metadata_epoch > state.metadata_epoch {
+            state.metadata_epoch = metadata_epoch;
+            self.clear(&mut state);
+        }
+
+        let key = CacheKey {
+            api_key,
+            api_version,
+            metadata_epoch,
+            request,
+        };
+        match state.entries.get(&key) {
+            Some(body) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                CacheLookup::Hit(body.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                CacheLookup::Miss(CacheTicket {
+                    key,
+                    generation: state.generation,
+                })
+            }
+        }
+    }
+
+    /// Stores the body encoded after a miss
+    ///
+    /// Bodies larger than the whole cache, encoded before an invalidation
+    /// or for a metadata epoch that has since moved on, are not stored.
+    pub fn store(&self, ticket: CacheTicket, body: Bytes) {
+        let mut state = self.state.lock().unwrap();
+        self.apply_events(&mut state);
+        if ticket.generation != state.generation
+            || ticket.key.metadata_epoch < state.metadata_epoch
+            || body.len() > self.max_bytes
+        {
+            return;
+        }
+
+        state.bytes += body.len();
+        if let Some(replaced) = state.entries.insert(ticket.key.clone(), body) {
+            state.bytes -= replaced.len();
+        } else {
+            state.order.push_back(ticket.key);
+        }
+        while state.bytes > self.max_bytes {
+            let Some(oldest) = state.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = state.entries.remove(&oldest) {
+                state.bytes -= evicted.len();
+            }
+        }
+    }
+
+    /// Drops every cached response
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.clear(&mut state);
+    }
+
+    /// Current hit, miss and size counters
+    pub fn metrics(&self) -> ResponseCacheMetrics {
+        let mut state = self.state.lock().unwrap();
+        self.apply_events(&mut state);
+        ResponseCacheMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            entries: state.entries.len(),
+            bytes: state.bytes,
+        }
+    }
+
+    /// Drains pending events, clearing the cache if any affect responses
+    fn apply_events(&self, state: &mut CacheState) {
+        let dropped_before = state.events.dropped_events();
+        let mut stale = false;
+        while let Some(event) = state.events.try_recv() {
+            stale |= matches!(
+                event,
+                BrokerEvent::TopicCreated { .. }
+                    | BrokerEvent::TopicDeleted { .. }
+                    | BrokerEvent::ConfigUpdated { .. }
+                    | BrokerEvent::ListenersChanged { .. }
+                    | BrokerEvent::MetadataEpochAdvanced { .. }
+            );
+        }
+        if stale || state.events.dropped_events() != dropped_before {
+            self.clear(state);
+        }
+    }
+
+    fn clear(&self, state: &mut CacheState) {
+        state.generation += 1;
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+        if !state.entries.is_empty() {
+            debug!(
+                entries = state.entries.len(),
+                bytes = state.bytes,
+                "Invalidating response cache"
+            );
+        }
+        state.entries.clear();
+        state.order.clear();
+        state.bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::events::EventBus;
+    use std::cell::Cell;
+
+    fn cache(bus: &EventBus, max_bytes: usize) -> ResponseCache {
+        ResponseCache::new(max_bytes, bus.subscribe("response-cache"))
+    }
+
+    /// Looks up a metadata request, encoding on a miss
+    fn metadata_response(
+        cache: &ResponseCache,
+        request: &'static [u8],
+        encodes: &Cell<u32>,
+    ) -> Bytes {
+        match cache.lookup(ApiKey::Metadata.code(), 12, 0, Bytes::from_static(request)) {
+            CacheLookup::Hit(body) => body,
+            CacheLookup::Miss(ticket) => {
+                encodes.set(encodes.get() + 1);
+                let body = Bytes::from(format!("topics for {:?}", request));
+                cache.store(ticket, body.clone());
+                body
+            }
+            CacheLookup::Uncacheable => panic!("metadata is cacheable"),
+        }
+    }
+
+    #[test]
+    fn test_identical_requests_encode_once() {
+        let bus = EventBus::default();
+        let cache = cache(&bus, DEFAULT_RESPONSE_CACHE_BYTES);
+        let encodes = Cell::new(0);
+
+        let first = metadata_response(&cache, b"all-topics", &encodes);
+        for _ in 0..99 {
+            assert_eq!(metadata_response(&cache, b"all-topics", &encodes), first);
+        }
+        assert_eq!(encodes.get(), 1);
+        let metrics = cache.metrics();
+        assert_eq!((metrics.hits, metrics.misses), (99, 1));
+
+        // A different request is a different entry
+        metadata_response(&cache, b"orders-only", &encodes);
+        assert_eq!(encodes.get(), 2);
+    }
+
+    #[test]
+    fn test_topology_events_invalidate() {
+        let bus = EventBus::default();
+        let cache = cache(&bus, DEFAULT_RESPONSE_CACHE_BYTES);
+        let encodes = Cell::new(0);
+
+        metadata_response(&cache, b"all-topics", &encodes);
+        bus.publish(BrokerEvent::HighWatermarkAdvanced {
+            topic: "orders".to_string(),
+            partition: 0,
+            high_watermark: 10,
+        });
+        metadata_response(&cache, b"all-topics", &encodes);
+        assert_eq!(encodes.get(), 1);
+
+        bus.publish(BrokerEvent::TopicCreated {
+            topic: "orders".to_string(),
+            partitions: 3,
+        });
+        metadata_response(&cache, b"all-topics", &encodes);
+        assert_eq!(encodes.get(), 2);
+    }
+
+    #[test]
+    fn test_response_encoded_before_invalidation_is_not_stored() {
+        let bus = EventBus::default();
+        let cache = cache(&bus, DEFAULT_RESPONSE_CACHE_BYTES);
+
+        let CacheLookup::Miss(ticket) =
+            cache.lookup(ApiKey::DescribeCluster.code(), 0, 0, Bytes::new())
+        else {
+            panic!("expected a miss");
+        };
+        bus.publish(BrokerEvent::ListenersChanged { broker_id: 1 });
+        cache.store(ticket, Bytes::from_static(b"stale"));
+
+        assert_eq!(cache.metrics().entries, 0);
+    }
+
+    #[test]
+    fn test_size_cap_evicts_oldest() {
+        let bus = EventBus::default();
+        let cache = cache(&bus, 20);
+        let encodes = Cell::new(0);
+
+        // Each body is 15 bytes, so only one fits
+        metadata_response(&cache, b"a", &encodes);
+        metadata_response(&cache, b"b", &encodes);
+        let metrics = cache.metrics();
+        assert_eq!(metrics.entries, 1);
+        assert_eq!(metrics.bytes, 15);
+
+        metadata_response(&cache, b"b", &encodes);
+        assert_eq!(encodes.get(), 2);
+    }
+
+    #[test]
+    fn test_only_whitelisted_apis_participate() {
+        let bus = EventBus::default();
+        let cache = cache(&bus, DEFAULT_RESPONSE_CACHE_BYTES);
+        assert!(matches!(
+            cache.lookup(ApiKey::Fetch.code(), 11, 0, Bytes::new()),
+            CacheLookup::Uncacheable
+        ));
+    }
+
+    #[test]
+    fn test_entries_are_keyed_on_metadata_epoch() {
+        let bus = EventBus::default();
+        let cache = cache(&bus, DEFAULT_RESPONSE_CACHE_BYTES);
+        let lookup = |epoch| cache.lookup(ApiKey::Metadata.code(), 12, epoch, Bytes::new());
+
+        let CacheLookup::Miss(ticket) = lookup(4) else {
+            panic!("expected a miss");
+        };
+        cache.store(ticket, Bytes::from_static(b"epoch 4"));
+        assert!(matches!(lookup(4), CacheLookup::Hit(body) if body == "epoch 4"));
+
+        // A newer epoch misses and drops what was cached for the old one
+        let CacheLookup::Miss(ticket) = lookup(5) else {
+            panic!("expected a miss");
+        };
+        assert_eq!(cache.metrics().entries, 0);
+        cache.store(ticket, Bytes::from_static(b"epoch 5"));
+        assert!(matches!(lookup(5), CacheLookup::Hit(body) if body == "epoch 5"));
+
+        // A straggler still at the old epoch never sees the new body
+        assert!(matches!(lookup(4), CacheLookup::Miss(_)));
+    }
+}
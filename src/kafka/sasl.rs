@@ -0,0 +1,249 @@
+use crate::kafka::config::{broker_property, ConfigError};
+use thiserror::Error;
+
+/// The only mechanism whose exchange the broker implements
+pub const PLAIN_MECHANISM: &str = "PLAIN";
+
+/// SASL settings of the listener
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaslConfig {
+    /// Mechanisms offered in SaslHandshake (`sasl.enabled.mechanisms`),
+    /// matched case-sensitively as Kafka does
+    pub enabled_mechanisms: Vec<String>,
+}
+
+impl SaslConfig {
+    /// Builds the settings from broker properties, using defaults for missing keys
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let name = "sasl.enabled.mechanisms";
+        let value = broker_property(properties, name)
+            .ok_or_else(|| ConfigError::UnknownKey(name.into()))?;
+        let enabled_mechanisms: Vec<String> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|mechanism| !mechanism.is_empty())
+            .map(str::to_string)
+            .collect();
+        if enabled_mechanisms.is_empty() {
+            return Err(ConfigError::InvalidValue {
+                key: name.to_string(),
+                value: value.to_string(),
+            });
+        }
+        Ok(Self { enabled_mechanisms })
+    }
+
+    /// Whether a client may pick `mechanism` in SaslHandshake
+    pub fn is_enabled(&self, mechanism: &str) -> bool {
+        self.enabled_mechanisms.iter().any(|m| m == mechanism)
+    }
+}
+
+impl Default for SaslConfig {
+    fn default() -> Self {
+        Self::from_properties(&[]).expect("broker config defaults are valid")
+    }
+}
+
+/// Errors raised while a connection authenticates
+#[derive(Error, Debug, PartialEq)]
+pub enum SaslError {
+    #[error("SASL mechanism '{mechanism}' is not enabled")]
+    UnsupportedMechanism { mechanism: String },
+
+    #[error("{request} is not allowed {state}")]
+    IllegalState {
+        request: &'static str,
+        state: &'static str,
+    },
+
+    #[error("Authentication failed: {reason}")]
+    AuthenticationFailed { reason: String },
+}
+
+/// Where a connection is in SASL authentication
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum SaslState {
+    /// No handshake yet; SaslAuthenticate is refused
+    #[default]
+    AwaitingHandshake,
+    /// The client picked a mechanism and may send its token
+    Handshaken { mechanism: String },
+    /// Authentication completed; the connection cannot authenticate again
+    Authenticated { principal: String },
+}
+
+impl SaslState {
+    /// Picks the mechanism the connection authenticates with
+    ///
+    /// Only allowed once. A mechanism that is not enabled leaves the state
+    /// unchanged, so the client may retry with another.
+    pub fn handshake(&mut self, config: &SaslConfig, mechanism: &str) -> Result<(), SaslError> {
+        if *self != Self::AwaitingHandshake {
+            return Err(self.illegal("SaslHandshake"));
+        }
+        if !config.is_enabled(mechanism) {
+            return Err(SaslError::UnsupportedMechanism {
+                mechanism: mechanism.to_string(),
+            });
+        }
+        *self = Self::Handshaken {
+            mechanism: mechanism.to_string(),
+        };
+        Ok(())
+    }
+
+    /// Checks the client's token and returns the authenticated principal
+    ///
+    /// PLAIN is the one mechanism whose exchange is implemented. There is
+    /// no credential store yet, so any well-formed token authenticates its
+    /// username.
+    pub fn authenticate(&mut self, token: &[u8]) -> Result<String, SaslError> {
+        let Self::Handshaken { mechanism } = self else {
+            return Err(self.illegal("SaslAuthenticate"));
+        };
+        let failed = |reason: &str| SaslError::AuthenticationFailed {
+            reason: reason.to_string(),
+        };
+        if mechanism != PLAIN_MECHANISM {
+            return Err(failed(&format!("{} is not implemented", mechanism)));
+        }
+        let credentials =
+            parse_plain_token(token).ok_or_else(|| failed("malformed PLAIN token"))?;
+        if !credentials.authorization_id.is_empty()
+            && credentials.authorization_id != credentials.username
+        {
+            return Err(failed("authorization id must match the username"));
+        }
+        *self = Self::Authenticated {
+            principal: credentials.username.clone(),
+        };
+        Ok(credentials.username)
+    }
+
+    fn illegal(&self, request: &'static str) -> SaslError {
+        let state = match self {
+            Self::AwaitingHandshake => "before a successful SaslHandshake",
+            Self::Handshaken { .. } => "after SaslHandshake",
+            Self::Authenticated { .. } => "once authenticated",
+        };
+        SaslError::IllegalState { request, state }
+    }
+}
+
+/// Identity carried by a SASL/PLAIN token (RFC 4616)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlainCredentials {
+    /// Identity to act as; empty when it is the authenticating user
+    pub authorization_id: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// Splits a PLAIN token `authzid NUL authcid NUL passwd`
+///
+/// Returns `None` for a token that is not valid UTF-8, does not have three
+/// fields, or has an empty username or password.
+pub fn parse_plain_token(token: &[u8]) -> Option<PlainCredentials> {
+    let token = std::str::from_utf8(token).ok()?;
+    let mut fields = token.split('\0');
+    let (Some(authorization_id), Some(username), Some(password), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return None;
+    };
+    if username.is_empty() || password.is_empty() {
+        return None;
+    }
+    Some(PlainCredentials {
+        authorization_id: authorization_id.to_string(),
+        username: username.to_string(),
+        password: password.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mechanisms_from_properties() {
+        assert_eq!(SaslConfig::default().enabled_mechanisms, ["PLAIN"]);
+
+        let properties = vec![(
+            "sasl.enabled.mechanisms".to_string(),
+            "PLAIN, SCRAM-SHA-256".to_string(),
+        )];
+        let config = SaslConfig::from_properties(&properties).unwrap();
+        assert!(config.is_enabled("SCRAM-SHA-256"));
+        assert!(!config.is_enabled("plain"));
+
+        let properties = vec![("sasl.enabled.mechanisms".to_string(), " , ".to_string())];
+        assert!(SaslConfig::from_properties(&properties).is_err());
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let config = SaslConfig::default();
+        let mut state = SaslState::default();
+        assert_eq!(
+            state.authenticate(b"\0alice\0secret"),
+            Err(SaslError::IllegalState {
+                request: "SaslAuthenticate",
+                state: "before a successful SaslHandshake",
+            })
+        );
+        assert_eq!(
+            state.handshake(&config, "GSSAPI"),
+            Err(SaslError::UnsupportedMechanism {
+                mechanism: "GSSAPI".to_string(),
+            })
+        );
+        assert_eq!(state, SaslState::AwaitingHandshake);
+
+        state.handshake(&config, "PLAIN").unwrap();
+        assert!(matches!(
+            state.handshake(&config, "PLAIN"),
+            Err(SaslError::IllegalState { .. })
+        ));
+        assert!(matches!(
+            state.authenticate(b"bob\0alice\0secret"),
+            Err(SaslError::AuthenticationFailed { .. })
+        ));
+        assert_eq!(
+            state.authenticate(b"alice\0alice\0secret").unwrap(),
+            "alice"
+        );
+        assert_eq!(
+            state,
+            SaslState::Authenticated {
+                principal: "alice".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_plain_token() {
+        assert_eq!(
+            parse_plain_token(b"\0alice\0secret"),
+            Some(PlainCredentials {
+                authorization_id: String::new(),
+                username: "alice".to_string(),
+                password: "secret".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_plain_token(b"admin\0alice\0secret").map(|c| c.authorization_id),
+            Some("admin".to_string())
+        );
+        for malformed in [
+            &b"alice\0secret"[..],
+            b"\0\0secret",
+            b"\0alice\0",
+            b"\0alice\0secret\0extra",
+            b"\0\xff\0secret",
+        ] {
+            assert_eq!(parse_plain_token(malformed), None, "{:?}", malformed);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::kafka::config::TopicConfig;
+use crate::kafka::connection_registry::ConnectionInfo;
+use crate::kafka::group_coordinator::GroupSnapshot;
+use crate::kafka::health::HealthStatus;
+use crate::kafka::limits::LimitEntry;
+use crate::kafka::offset_store::CommittedOffsetSnapshot;
+use crate::kafka::producer_id_manager::ProducerIdSnapshot;
+use crate::kafka::producer_state::{ProducerSnapshot, ProducerStateManager};
+use crate::kafka::quarantine::QuarantineMetrics;
+use crate::kafka::response_cache::ResponseCacheMetrics;
+use crate::kafka::storage::partition_log::{PartitionLog, PartitionSnapshot};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// State of one partition: its log offsets and idempotent producers
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PartitionState {
+    #[serde(flatten)]
+    pub log: PartitionSnapshot,
+    pub producers: Vec<ProducerSnapshot>,
+}
+
+/// Dump of the broker's in-memory state for debugging
+///
+/// Every component is snapshotted under its own lock rather than one global
+/// lock, so each section is internally consistent while the broker keeps
+/// serving. Record payloads are never included and sensitive configuration
+/// values are redacted, but topic names and client ids are, so exporting a
+/// dump over the network is opt-in.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateSnapshot {
+    pub taken_at_ms: i64,
+    pub cluster_id: Option<String>,
+    pub metadata_epoch: u64,
+    pub liveness: HealthStatus,
+    pub readiness: HealthStatus,
+    pub connections: Vec<ConnectionInfo>,
+    pub limits: Vec<LimitEntry>,
+    pub response_cache: ResponseCacheMetrics,
+    pub event_subscribers: usize,
+    pub cancelled_by_disconnect: u64,
+    pub quarantine: QuarantineMetrics,
+    pub fetch_sessions: usize,
+    pub partitions: Vec<PartitionState>,
+    pub groups: Vec<GroupSnapshot>,
+    pub committed_offsets: Vec<CommittedOffsetSnapshot>,
+    pub producer_ids: ProducerIdSnapshot,
+    /// Topic configuration overrides by topic
+    pub topic_configs: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl StateSnapshot {
+    /// Adds a partition, with the producer state kept for it if any
+    pub fn add_partition(&mut self, log: &PartitionLog, producers: Option<&ProducerStateManager>) {
+        self.partitions.push(PartitionState {
+            log: log.snapshot(),
+            producers: producers
+                .map(ProducerStateManager::snapshot)
+                .unwrap_or_default(),
+        });
+        self.partitions
+            .sort_by(|a, b| (&a.log.topic, a.log.partition).cmp(&(&b.log.topic, b.log.partition)));
+    }
+
+    /// Adds the configuration overrides of a topic, redacting sensitive values
+    pub fn add_topic_config(&mut self, topic: &str, config: &TopicConfig) {
+        self.topic_configs
+            .insert(topic.to_string(), config.redacted_overrides());
+    }
+
+    /// Describes every cross-component invariant the snapshot violates
+    ///
+    /// An empty result means the snapshot is consistent, which makes a dump
+    /// a one-line assertion in tests.
+    pub fn inconsistencies(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for partition in &self.partitions {
+            let log = &partition.log;
+            let name = format!("{}-{}", log.topic, log.partition);
+            if !(log.log_start_offset <= log.high_watermark
+                && log.high_watermark <= log.log_end_offset)
+            {
+                problems.push(format!(
+                    "{}: expected log start {} <= high watermark {} <= log end {}",
+                    name, log.log_start_offset, log.high_watermark, log.log_end_offset
+                ));
+            }
+            for producer in &partition.producers {
+                if producer.last_base_offset >= log.log_end_offset {
+                    problems.push(format!(
+                        "{}: producer {} last batch at offset {} is beyond log end {}",
+                        name, producer.producer_id, producer.last_base_offset, log.log_end_offset
+                    ));
+                }
+            }
+        }
+        for committed in &self.committed_offsets {
+            let partition = self.partitions.iter().find(|partition| {
+                partition.log.topic == committed.topic
+                    && partition.log.partition == committed.partition
+            });
+            if let Some(partition) = partition {
+                if committed.committed.offset > partition.log.high_watermark {
+                    problems.push(format!(
+                        "{}-{}: group '{}' committed offset {} past high watermark {}",
+                        committed.topic,
+                        committed.partition,
+                        committed.group_id,
+                        committed.committed.offset,
+                        partition.log.high_watermark
+                    ));
+                }
+            }
+        }
+        if self
+            .connections
+            .windows(2)
+            .any(|pair| pair[0].id >= pair[1].id)
+        {
+            problems.push("connections are not unique and ordered by id".to_string());
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::broker::KafkaBroker;
+    use crate::kafka::clock::MockClock;
+    use crate::kafka::test_util::{frame, read_response, spawn_connection_with};
+    use crate::protocol::{ProtocolEncode, RequestHeaderV2};
+    use bytes::Bytes;
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_dump_reflects_broker_activity() {
+        let broker = Arc::new(KafkaBroker::new().with_cluster_id("dump-cluster"));
+        let (mut client, _handle) = spawn_connection_with(Arc::clone(&broker));
+        let header = RequestHeaderV2::with_client_id(18, 0, 1, "dumper");
+        for _ in 0..2 {
+            client
+                .write_all(&frame(&header.encode().unwrap()))
+                .await
+                .unwrap();
+            read_response(&mut client).await;
+        }
+
+        let snapshot = broker.dump();
+        assert_eq!(snapshot.cluster_id.as_deref(), Some("dump-cluster"));
+        assert_eq!(snapshot.connections.len(), 1);
+        assert_eq!(snapshot.connections[0].client_id.as_deref(), Some("dumper"));
+        assert_eq!(
+            (snapshot.response_cache.misses, snapshot.response_cache.hits),
+            (1, 1)
+        );
+        assert!(snapshot.inconsistencies().is_empty());
+    }
+
+    #[test]
+    fn test_partition_sections_are_consistent() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let log = PartitionLog::new("orders", 0).with_clock(clock.clone());
+        let mut producers = ProducerStateManager::new(clock, 60_000);
+        for sequence in 0..3 {
+            let base_offset = log.append(Bytes::from_static(b"secret payload"), 1);
+            producers.record_batch(7, 0, sequence, sequence, base_offset);
+        }
+        log.advance_high_watermark(2);
+
+        let mut snapshot = KafkaBroker::new().dump();
+        snapshot.add_partition(&log, Some(&producers));
+        assert!(snapshot.inconsistencies().is_empty());
+        let partition = &snapshot.partitions[0];
+        assert_eq!(
+            (
+                partition.log.high_watermark,
+                partition.log.log_end_offset,
+                partition.producers[0].last_base_offset
+            ),
+            (2, 3, 2)
+        );
+
+        // Truncating the log without updating producer state is caught
+        log.truncate_to(1).unwrap();
+        let mut snapshot = KafkaBroker::new().dump();
+        snapshot.add_partition(&log, Some(&producers));
+        assert_eq!(
+            snapshot.inconsistencies(),
+            vec!["orders-0: producer 7 last batch at offset 2 is beyond log end 1"]
+        );
+    }
+
+    #[test]
+    fn test_dump_excludes_record_payloads() {
+        let log = PartitionLog::new("orders", 0);
+        log.append(Bytes::from_static(b"secret payload"), 1);
+        let mut config = TopicConfig::default();
+        config.set("orders", "retention.ms", "1000").unwrap();
+
+        let mut snapshot = KafkaBroker::new().dump();
+        snapshot.add_partition(&log, None);
+        snapshot.add_topic_config("orders", &config);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(!json.contains("secret payload"));
+        assert!(json.contains("\"retention.ms\":\"1000\""));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::kafka::config::parse_properties;
+use crate::kafka::metadata::{ClusterMetadata, ClusterMetadataLog};
+use crate::kafka::storage::metadata_log::{
+    encode_batch, BrokerEndpoint, MetadataRecord, DEFAULT_METADATA_VERSION, METADATA_TOPIC,
+    METADATA_VERSION_FEATURE,
+};
+use crate::logging::{info, warn};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// File holding the identity of a formatted log directory
+pub const META_PROPERTIES_FILE: &str = "meta.properties";
+
+/// Name of the first segment of a partition log
+pub const FIRST_SEGMENT_FILE: &str = "00000000000000000000.log";
+
+/// Version of the meta.properties layout written for KRaft
+const META_PROPERTIES_VERSION: i32 = 1;
+
+/// Errors raised while formatting or loading a log directory
+#[derive(Error, Debug)]
+pub enum LogDirError {
+    #[error("Log directory {0} is not empty; pass --force to format it anyway")]
+    NotEmpty(PathBuf),
+
+    #[error("Log directory {0} has not been formatted")]
+    NotFormatted(PathBuf),
+
+    #[error("Invalid meta.properties: {0}")]
+    InvalidMetaProperties(String),
+
+    #[error("Corrupt cluster metadata log: {0}")]
+    CorruptMetadataLog(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Contents of `meta.properties`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaProperties {
+    pub version: i32,
+    pub cluster_id: String,
+    pub node_id: i32,
+    pub directory_id: String,
+}
+
+impl MetaProperties {
+    /// Renders the file contents
+    pub fn to_properties_string(&self) -> String {
+        format!(
+            "#\n#Written by the broker format tool\nversion={}\ncluster.id={}\nnode.id={}\ndirectory.id={}\n",
+            self.version, self.cluster_id, self.node_id, self.directory_id
+        )
+    }
+
+    /// Parses the file contents
+    pub fn parse(contents: &str) -> Result<Self, LogDirError> {
+        let properties = parse_properties(contents);
+        let get = |key: &str| {
+            properties
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| LogDirError::InvalidMetaProperties(format!("missing {}", key)))
+        };
+        let parse_int = |key: &str| {
+            get(key)?.parse::<i32>().map_err(|_| {
+                LogDirError::InvalidMetaProperties(format!("{} is not an integer", key))
+            })
+        };
+
+        Ok(Self {
+            version: parse_int("version")?,
+            cluster_id: get("cluster.id")?,
+            node_id: parse_int("node.id")?,
+            directory_id: get("directory.id").unwrap_or_default(),
+        })
+    }
+}
+
+/// What startup learned from a formatted log directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootstrapMetadata {
+    pub meta: MetaProperties,
+    /// State of the `__cluster_metadata` log
+    pub cluster: ClusterMetadata,
+}
+
+impl BootstrapMetadata {
+    /// Cluster id the directory was formatted with
+    pub fn cluster_id(&self) -> &str {
+        &self.meta.cluster_id
+    }
+
+    /// metadata.version finalized in the metadata log
+    pub fn metadata_version(&self) -> Option<i16> {
+        self.cluster.metadata_version()
+    }
+}
+
+/// Generates a random cluster id in Kafka's 22 character base64 form
+pub fn generate_cluster_id() -> String {
+    URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes())
+}
+
+fn metadata_log_dir(dir: &Path) -> PathBuf {
+    partition_dir(dir, METADATA_TOPIC, 0)
+}
+
+/// Directory holding the log of one partition, `<topic>-<partition>`
+pub fn partition_dir(dir: &Path, topic: &str, partition: i32) -> PathBuf {
+    dir.join(format!("{}-{}", topic, partition))
+}
+
+/// Returns whether `dir` already holds a meta.properties file
+pub fn is_formatted(dir: &Path) -> bool {
+    dir.join(META_PROPERTIES_FILE).is_file()
+}
+
+/// Returns whether `dir` is missing or has no entries
+pub fn is_empty_dir(dir: &Path) -> io::Result<bool> {
+    match fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Formats `dir` as a single-node KRaft log directory
+///
+/// Writes meta.properties with a fresh cluster id and a `__cluster_metadata-0`
+/// log holding the metadata.version feature level and this broker's
+/// registration. A non-empty directory is refused unless `force` is set, in
+/// which case meta.properties and the metadata log are replaced and any other
+/// contents are left alone.
+pub fn format_log_dir(
+    dir: &Path,
+    node_id: i32,
+    force: bool,
+) -> Result<MetaProperties, LogDirError> {
+    if !is_empty_dir(dir)? {
+        if !force {
+            return Err(LogDirError::NotEmpty(dir.to_path_buf()));
+        }
+        warn!(log_dir = %dir.display(), "Formatting non-empty log directory");
+        let metadata_dir = metadata_log_dir(dir);
+        if metadata_dir.exists() {
+            fs::remove_dir_all(&metadata_dir)?;
+        }
+    }
+
+    let meta = MetaProperties {
+        version: META_PROPERTIES_VERSION,
+        cluster_id: generate_cluster_id(),
+        node_id,
+        directory_id: URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes()),
+    };
+
+    let records = [
+        MetadataRecord::FeatureLevel {
+            name: METADATA_VERSION_FEATURE.to_string(),
+            feature_level: DEFAULT_METADATA_VERSION,
+        },
+        MetadataRecord::RegisterBroker {
+            broker_id: node_id,
+            incarnation_id: Uuid::new_v4(),
+            broker_epoch: 0,
+            endpoints: vec![BrokerEndpoint {
+                name: "PLAINTEXT".to_string(),
+                host: "localhost".to_string(),
+                port: 9092,
+                security_protocol: 0,
+            }],
+            rack: None,
+            fenced: false,
+        },
+    ];
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or(0);
+    let batch = encode_batch(0, now_ms, &records)
+        .map_err(|e| LogDirError::CorruptMetadataLog(e.to_string()))?;
+
+    let metadata_dir = metadata_log_dir(dir);
+    fs::create_dir_all(&metadata_dir)?;
+    fs::write(metadata_dir.join(FIRST_SEGMENT_FILE), &batch)?;
+    // meta.properties goes last so a crash mid-format leaves the directory
+    // unformatted rather than half formatted
+    fs::write(dir.join(META_PROPERTIES_FILE), meta.to_properties_string())?;
+
+    info!(
+        log_dir = %dir.display(),
+        cluster_id = %meta.cluster_id,
+        node_id = node_id,
+        "Formatted log directory"
+    );
+    Ok(meta)
+}
+
+/// Loads meta.properties and the cluster metadata log from `dir`
+pub fn load_log_dir(dir: &Path) -> Result<BootstrapMetadata, LogDirError> {
+    if !is_formatted(dir) {
+        return Err(LogDirError::NotFormatted(dir.to_path_buf()));
+    }
+    let meta = MetaProperties::parse(&fs::read_to_string(dir.join(META_PROPERTIES_FILE))?)?;
+
+    let cluster =
+        ClusterMetadataLog::load(&metadata_log_dir(dir).join(FIRST_SEGMENT_FILE))?.metadata();
+    Ok(BootstrapMetadata { meta, cluster })
+}
+
+/// Loads `dir` for startup, formatting it first when allowed
+///
+/// Returns `None` when the directory is unformatted and either not empty or
+/// `auto_format` is off; the broker then runs without cluster metadata.
+pub fn prepare_log_dir(
+    dir: &Path,
+    node_id: i32,
+    auto_format: bool,
+) -> Result<Option<BootstrapMetadata>, LogDirError> {
+    if !is_formatted(dir) {
+        if !(auto_format && is_empty_dir(dir)?) {
+            return Ok(None);
+        }
+        format_log_dir(dir, node_id, false)?;
+    }
+    load_log_dir(dir).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_then_load() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let meta = format_log_dir(dir.path(), 1, false).unwrap();
+        assert_eq!(meta.cluster_id.len(), 22);
+
+        let loaded = load_log_dir(dir.path()).unwrap();
+        assert_eq!(loaded.meta, meta);
+        assert_eq!(loaded.metadata_version(), Some(DEFAULT_METADATA_VERSION));
+        assert_eq!(loaded.cluster.brokers, vec![1]);
+        assert!(loaded.cluster.topics.is_empty());
+    }
+
+    #[test]
+    fn test_non_empty_dir_requires_force() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("stray"), b"x").unwrap();
+
+        assert!(matches!(
+            format_log_dir(dir.path(), 1, false),
+            Err(LogDirError::NotEmpty(_))
+        ));
+        assert!(!is_formatted(dir.path()));
+
+        format_log_dir(dir.path(), 1, true).unwrap();
+        assert!(dir.path().join("stray").exists());
+        assert!(load_log_dir(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn test_prepare_auto_formats_only_empty_dirs() {
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(prepare_log_dir(empty.path(), 1, false).unwrap(), None);
+        let formatted = prepare_log_dir(empty.path(), 1, true).unwrap().unwrap();
+
+        // A second start loads what the first one wrote
+        let reloaded = prepare_log_dir(empty.path(), 1, true).unwrap().unwrap();
+        assert_eq!(reloaded.cluster_id(), formatted.cluster_id());
+
+        let occupied = tempfile::tempdir().unwrap();
+        fs::write(occupied.path().join("stray"), b"x").unwrap();
+        assert_eq!(prepare_log_dir(occupied.path(), 1, true).unwrap(), None);
+    }
+
+    #[test]
+    fn test_meta_properties_roundtrip() {
+        let meta = MetaProperties {
+            version: 1,
+            cluster_id: "MkU3OEVBNTcwNTJENDM2Qk".to_string(),
+            node_id: 3,
+            directory_id: "abc".to_string(),
+        };
+        assert_eq!(
+            MetaProperties::parse(&meta.to_properties_string()).unwrap(),
+            meta
+        );
+    }
+}
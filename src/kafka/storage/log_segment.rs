@@ -0,0 +1,1116 @@
+use crate::kafka::storage::offset_index::{index_file_name, OffsetIndex};
+use crate::kafka::storage::partition_log_reader::{BatchPosition, HEADER_PREFIX};
+use crate::kafka::storage::segment_handles::{SegmentHandleCache, SegmentHandleError, SegmentId};
+use crate::kafka::storage::time_index::{time_index_file_name, TimeIndex};
+use crate::kafka::storage::{StorageError, StorageResult};
+use crate::logging::{info, warn};
+use bytes::Bytes;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Size at which a segment is rolled when `log.segment.bytes` is not set
+pub const DEFAULT_SEGMENT_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Log data between offset index entries when `log.index.interval.bytes`
+/// is not set
+pub const DEFAULT_INDEX_INTERVAL_BYTES: u64 = 4096;
+
+/// How the segments of a partition are sized and indexed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogConfig {
+    /// Size at which the active segment rolls, `log.segment.bytes`
+    pub segment_bytes: u64,
+    /// Log data between offset index entries, `log.index.interval.bytes`
+    pub index_interval_bytes: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            segment_bytes: DEFAULT_SEGMENT_BYTES,
+            index_interval_bytes: DEFAULT_INDEX_INTERVAL_BYTES,
+        }
+    }
+}
+
+/// Name of the segment file starting at `base_offset`, such as
+/// `00000000000000000042.log`
+pub fn segment_file_name(base_offset: i64) -> String {
+    format!("{base_offset:020}.log")
+}
+
+/// Base offset of the segment file at `path`, if it is named like one
+pub fn segment_base_offset(path: &Path) -> Option<i64> {
+    if path.extension()? != "log" {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+fn io_error(path: &Path) -> impl Fn(io::Error) -> StorageError + '_ {
+    move |e| StorageError::Io {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    }
+}
+
+fn handle_error(path: &Path) -> impl Fn(SegmentHandleError) -> StorageError + '_ {
+    move |e| StorageError::Io {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    }
+}
+
+/// Reads an index file with `decode`, `None` when it is missing or corrupt
+fn load_index<T>(path: &Path, decode: impl FnOnce(&[u8]) -> Option<T>) -> StorageResult<Option<T>> {
+    match fs::read(path) {
+        Ok(data) => Ok(decode(&data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(io_error(path)(e)),
+    }
+}
+
+/// Replaces the index file at `path` with `data`, returning it open for
+/// appending further entries
+fn rewrite_index(path: &Path, data: &[u8]) -> StorageResult<File> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)
+        .map_err(io_error(path))?;
+    file.write_all(data).map_err(io_error(path))?;
+    Ok(file)
+}
+
+/// The files an active segment appends to
+#[derive(Debug)]
+struct SegmentWriter {
+    file: File,
+    index_file: File,
+    time_index_file: File,
+}
+
+/// One `.log` file of a partition, holding record batches back to back,
+/// with the `.index` and `.timeindex` files next to it
+///
+/// Batches are stored exactly as the broker assigned their offsets, in the
+/// layout the reference broker's tools read. The segment is read through
+/// the shared [`SegmentHandleCache`] it is registered with, so it holds no
+/// file open for reading; only while it takes appends does it keep its
+/// files open for writing.
+#[derive(Debug)]
+pub struct LogSegment {
+    id: SegmentId,
+    path: PathBuf,
+    handles: Arc<SegmentHandleCache>,
+    /// `None` once the segment stopped taking appends
+    writer: Option<SegmentWriter>,
+    size: u64,
+    next_offset: i64,
+    index_path: PathBuf,
+    index: OffsetIndex,
+    index_interval_bytes: u64,
+    /// Log data appended since the last index entry
+    bytes_since_index_entry: u64,
+    time_index_path: PathBuf,
+    time_index: TimeIndex,
+    /// Largest batch timestamp in the segment and the last offset of the
+    /// batch that first carried it, `None` while the segment is empty
+    max_timestamp: Option<(i64, i64)>,
+    /// Base offset of the first batch, `None` while the segment is empty
+    first_offset: Option<i64>,
+    batch_count: usize,
+}
+
+impl LogSegment {
+    /// Creates an empty segment for `id` in `dir`, registered with `handles`
+    pub fn create(
+        dir: &Path,
+        id: SegmentId,
+        config: &LogConfig,
+        handles: &Arc<SegmentHandleCache>,
+    ) -> StorageResult<Self> {
+        let base_offset = id.base_offset;
+        let create = |path: &Path| {
+            OpenOptions::new()
+                .create(true)
+                .truncate(true)
+                .write(true)
+                .open(path)
+                .map_err(io_error(path))
+        };
+        let path = dir.join(segment_file_name(base_offset));
+        let index_path = dir.join(index_file_name(base_offset));
+        let time_index_path = dir.join(time_index_file_name(base_offset));
+        let writer = SegmentWriter {
+            file: create(&path)?,
+            index_file: create(&index_path)?,
+            time_index_file: create(&time_index_path)?,
+        };
+        handles.register(id.clone(), &path, 0);
+        Ok(Self {
+            id,
+            path,
+            handles: Arc::clone(handles),
+            writer: Some(writer),
+            size: 0,
+            next_offset: base_offset,
+            index_path,
+            index: OffsetIndex::new(base_offset),
+            index_interval_bytes: config.index_interval_bytes,
+            bytes_since_index_entry: 0,
+            time_index_path,
+            time_index: TimeIndex::new(base_offset),
+            max_timestamp: None,
+            first_offset: None,
+            batch_count: 0,
+        })
+    }
+
+    /// Opens the segment file at `path` for `id`, registered with `handles`
+    ///
+    /// Scans the batch headers to find the next offset. A batch cut short
+    /// at the end of the file, as a crash mid-write leaves it, is truncated
+    /// away so the next append starts on a batch boundary. An offset or time
+    /// index that is missing or corrupt is rebuilt from the scan. The
+    /// segment is opened to take appends; [`close`](Self::close) it if it
+    /// will not.
+    pub fn open(
+        path: &Path,
+        id: SegmentId,
+        config: &LogConfig,
+        handles: &Arc<SegmentHandleCache>,
+    ) -> StorageResult<Self> {
+        let base_offset = id.base_offset;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(io_error(path))?;
+        let file_len = file.metadata().map_err(io_error(path))?.len();
+
+        let mut size = 0;
+        let mut next_offset = base_offset;
+        let mut rebuilt = OffsetIndex::new(base_offset);
+        let mut rebuilt_time_index = TimeIndex::new(base_offset);
+        let mut max_timestamp: Option<(i64, i64)> = None;
+        let mut first_offset = None;
+        let mut batch_count = 0;
+        let mut bytes_since_index_entry = 0;
+        let mut header = [0; HEADER_PREFIX];
+        while size + HEADER_PREFIX as u64 <= file_len {
+            file.seek(SeekFrom::Start(size)).map_err(io_error(path))?;
+            file.read_exact(&mut header).map_err(io_error(path))?;
+            let Some(batch) = BatchPosition::from_header(&header, size, file_len) else {
+                break;
+            };
+            first_offset.get_or_insert(batch.base_offset);
+            if max_timestamp.map_or(true, |(timestamp, _)| batch.max_timestamp > timestamp) {
+                max_timestamp = Some((batch.max_timestamp, batch.last_offset));
+            }
+            if bytes_since_index_entry > config.index_interval_bytes {
+                rebuilt.push(batch.last_offset, batch.position);
+                if let Some((timestamp, offset)) = max_timestamp {
+                    rebuilt_time_index.maybe_push(timestamp, offset);
+                }
+                bytes_since_index_entry = 0;
+            }
+            bytes_since_index_entry += batch.size;
+            size += batch.size;
+            next_offset = batch.last_offset + 1;
+            batch_count += 1;
+        }
+        if size < file_len {
+            warn!(
+                path = %path.display(),
+                valid_bytes = size,
+                truncated_bytes = file_len - size,
+                "Truncating incomplete batch at the end of the segment"
+            );
+            file.set_len(size).map_err(io_error(path))?;
+        }
+        file.seek(SeekFrom::Start(size)).map_err(io_error(path))?;
+
+        let index_path = path.with_extension("index");
+        let index = match load_index(&index_path, |data| OffsetIndex::decode(base_offset, data))? {
+            Some(mut index) => {
+                index.truncate_to_position(size);
+                index
+            }
+            None => {
+                warn!(
+                    path = %index_path.display(),
+                    entries = rebuilt.entries().len(),
+                    "Rebuilding missing or corrupt offset index"
+                );
+                rebuilt
+            }
+        };
+        let index_file = rewrite_index(&index_path, &index.encode())?;
+
+        let time_index_path = path.with_extension("timeindex");
+        let time_index = match load_index(&time_index_path, |data| {
+            TimeIndex::decode(base_offset, data)
+        })? {
+            Some(mut time_index) => {
+                time_index.truncate_to_offset(next_offset);
+                time_index
+            }
+            None => {
+                warn!(
+                    path = %time_index_path.display(),
+                    entries = rebuilt_time_index.entries().len(),
+                    "Rebuilding missing or corrupt time index"
+                );
+                rebuilt_time_index
+            }
+        };
+        let time_index_file = rewrite_index(&time_index_path, &time_index.encode())?;
+
+        handles.register(id.clone(), path, size);
+        Ok(Self {
+            id,
+            path: path.to_path_buf(),
+            handles: Arc::clone(handles),
+            writer: Some(SegmentWriter {
+                file,
+                index_file,
+                time_index_file,
+            }),
+            size,
+            next_offset,
+            index_path,
+            index,
+            index_interval_bytes: config.index_interval_bytes,
+            bytes_since_index_entry,
+            time_index_path,
+            time_index,
+            max_timestamp,
+            first_offset,
+            batch_count,
+        })
+    }
+
+    /// Offset of the first batch the segment holds or will hold
+    pub fn base_offset(&self) -> i64 {
+        self.id.base_offset
+    }
+
+    /// Identifies the segment in the handle cache
+    pub fn id(&self) -> &SegmentId {
+        &self.id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Bytes written to the segment
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Offset after the last batch in the segment
+    pub fn next_offset(&self) -> i64 {
+        self.next_offset
+    }
+
+    /// Base offset of the first batch, `None` while the segment is empty
+    pub fn first_offset(&self) -> Option<i64> {
+        self.first_offset
+    }
+
+    /// Batches written to the segment
+    pub fn batch_count(&self) -> usize {
+        self.batch_count
+    }
+
+    pub fn offset_index(&self) -> &OffsetIndex {
+        &self.index
+    }
+
+    pub fn time_index(&self) -> &TimeIndex {
+        &self.time_index
+    }
+
+    /// Largest batch timestamp in the segment, `None` while it is empty
+    pub fn max_timestamp(&self) -> Option<i64> {
+        self.max_timestamp.map(|(timestamp, _)| timestamp)
+    }
+
+    /// Writes one batch whose offsets end at `last_offset`
+    ///
+    /// The batch is indexed once more than `index.interval.bytes` of data
+    /// went in since the last entry, in the offset index and, when the
+    /// segment's max timestamp moved on, the time index. It is written
+    /// before its index entries, so an entry never points past the end of
+    /// the segment. Only a segment that was not closed takes appends.
+    pub fn append(&mut self, data: &[u8], last_offset: i64) -> StorageResult<()> {
+        let position = self.size;
+        let batch = data
+            .get(..HEADER_PREFIX)
+            .and_then(|header| BatchPosition::from_header(header.try_into().unwrap(), 0, u64::MAX));
+        self.writer()?
+            .file
+            .write_all(data)
+            .map_err(io_error(&self.path))?;
+        self.size += data.len() as u64;
+        self.handles
+            .set_expected_len(&self.id, self.size)
+            .map_err(handle_error(&self.path))?;
+        self.next_offset = last_offset + 1;
+        self.batch_count += 1;
+        if let Some(batch) = batch {
+            self.first_offset.get_or_insert(batch.base_offset);
+            if self
+                .max_timestamp
+                .map_or(true, |(timestamp, _)| batch.max_timestamp > timestamp)
+            {
+                self.max_timestamp = Some((batch.max_timestamp, last_offset));
+            }
+        }
+
+        if self.bytes_since_index_entry > self.index_interval_bytes {
+            let entry = self.index.push(last_offset, position);
+            self.writer()?
+                .index_file
+                .write_all(&entry.encode())
+                .map_err(io_error(&self.index_path))?;
+            self.append_time_index_entry()?;
+            self.bytes_since_index_entry = 0;
+        }
+        self.bytes_since_index_entry += data.len() as u64;
+        Ok(())
+    }
+
+    /// Indexes the segment's max timestamp so far, unless already indexed
+    fn append_time_index_entry(&mut self) -> StorageResult<()> {
+        let Some((timestamp, offset)) = self.max_timestamp else {
+            return Ok(());
+        };
+        if let Some(entry) = self.time_index.maybe_push(timestamp, offset) {
+            self.writer()?
+                .time_index_file
+                .write_all(&entry.encode())
+                .map_err(io_error(&self.time_index_path))?;
+        }
+        Ok(())
+    }
+
+    fn writer(&mut self) -> StorageResult<&mut SegmentWriter> {
+        self.writer.as_mut().ok_or_else(|| StorageError::Io {
+            path: self.path.clone(),
+            message: "segment no longer takes appends".to_string(),
+        })
+    }
+
+    /// Indexes the final max timestamp of a segment that stops taking
+    /// appends, so lookups in it never scan past its last entry, and closes
+    /// its files
+    fn seal(&mut self) -> StorageResult<()> {
+        self.append_time_index_entry()?;
+        self.close();
+        Ok(())
+    }
+
+    /// Closes the files the segment appends to; it is still read through
+    /// the handle cache
+    pub fn close(&mut self) {
+        self.writer = None;
+    }
+
+    /// The first batch whose max timestamp is at or after `timestamp`,
+    /// with its data
+    ///
+    /// Starts scanning at the offset the time index points to, positioned
+    /// through the offset index.
+    pub fn find_by_timestamp(
+        &self,
+        timestamp: i64,
+    ) -> StorageResult<Option<(BatchPosition, Bytes)>> {
+        if self.max_timestamp().map_or(true, |max| max < timestamp) {
+            return Ok(None);
+        }
+        let mut position = self.index.lookup(self.time_index.lookup(timestamp));
+        while let Some(batch) = self.batch_at(position)? {
+            if batch.max_timestamp >= timestamp {
+                let data = self.read_at(position, batch.size as usize)?;
+                return Ok(Some((batch, Bytes::from(data))));
+            }
+            position += batch.size;
+        }
+        Ok(None)
+    }
+
+    /// Returns the batches from the one holding `offset`, with their data
+    ///
+    /// The scan for the first batch starts where the offset index points,
+    /// the index being the one kept up to date by appends rather than read
+    /// back from disk. Stops once `max_bytes` would be exceeded, but always
+    /// returns the first batch. The batches are read in one go and share one
+    /// buffer. Returns nothing when every batch ends before `offset`.
+    pub fn read_from(
+        &self,
+        offset: i64,
+        max_bytes: usize,
+    ) -> StorageResult<Vec<(BatchPosition, Bytes)>> {
+        let mut position = self.index.lookup(offset);
+        let first = loop {
+            match self.batch_at(position)? {
+                Some(batch) if batch.last_offset >= offset => break batch,
+                Some(batch) => position += batch.size,
+                None => return Ok(Vec::new()),
+            }
+        };
+
+        let mut batches = vec![first];
+        let mut length = first.size;
+        while let Some(batch) = self.batch_at(first.position + length)? {
+            if length + batch.size > max_bytes as u64 {
+                break;
+            }
+            length += batch.size;
+            batches.push(batch);
+        }
+        let data = Bytes::from(self.read_at(first.position, length as usize)?);
+        Ok(batches
+            .into_iter()
+            .map(|batch| {
+                let start = (batch.position - first.position) as usize;
+                (batch, data.slice(start..start + batch.size as usize))
+            })
+            .collect())
+    }
+
+    /// Position of the first batch ending at or after `offset`, the end of
+    /// the segment when every batch ends before it
+    fn position_of(&self, offset: i64) -> StorageResult<u64> {
+        let mut position = self.index.lookup(offset);
+        while let Some(batch) = self.batch_at(position)? {
+            if batch.last_offset >= offset {
+                break;
+            }
+            position += batch.size;
+        }
+        Ok(position.min(self.size))
+    }
+
+    /// When the segment file was last written to, in milliseconds since the
+    /// epoch
+    fn modified_ms(&self) -> StorageResult<i64> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(io_error(&self.path))?;
+        Ok(modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as i64))
+    }
+
+    /// Deletes the segment file and its indexes, dropping it from the
+    /// handle cache
+    fn delete(self) -> StorageResult<()> {
+        let paths = [
+            self.path.clone(),
+            self.index_path.clone(),
+            self.time_index_path.clone(),
+        ];
+        drop(self);
+        for path in &paths {
+            match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(io_error(path)(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads `length` bytes at `position` through the handle cache
+    fn read_at(&self, position: u64, length: usize) -> StorageResult<Vec<u8>> {
+        self.handles
+            .read_at(&self.id, position, length)
+            .map_err(handle_error(&self.path))
+    }
+
+    /// The batch at `position`, `None` past the last one written
+    fn batch_at(&self, position: u64) -> StorageResult<Option<BatchPosition>> {
+        if position + HEADER_PREFIX as u64 > self.size {
+            return Ok(None);
+        }
+        let header = self.read_at(position, HEADER_PREFIX)?;
+        Ok(BatchPosition::from_header(
+            header[..].try_into().unwrap(),
+            position,
+            self.size,
+        ))
+    }
+}
+
+impl Drop for LogSegment {
+    fn drop(&mut self) {
+        self.handles.remove(&self.id);
+    }
+}
+
+/// The segments of one partition directory, the last one active
+///
+/// Appends go to the active segment until it would grow past
+/// `segment_bytes`, at which point a new segment starting at the log end
+/// offset takes over. Only the active segment keeps files open for
+/// writing, and it stays pinned in the handle cache the segments are read
+/// through; the others are opened for reads as the cache's budget allows.
+#[derive(Debug)]
+pub struct Log {
+    dir: PathBuf,
+    config: LogConfig,
+    handles: Arc<SegmentHandleCache>,
+    topic: String,
+    partition: i32,
+    /// In base offset order, never empty
+    segments: Vec<LogSegment>,
+}
+
+impl Log {
+    /// Opens the directory `dir` holding partition `partition` of `topic`,
+    /// creating it if needed, with its segments read through `handles`
+    ///
+    /// Existing segments are recovered in base offset order; a directory
+    /// without any gets an empty segment at offset zero.
+    pub fn open(
+        dir: &Path,
+        config: LogConfig,
+        handles: Arc<SegmentHandleCache>,
+        topic: &str,
+        partition: i32,
+    ) -> StorageResult<Self> {
+        fs::create_dir_all(dir).map_err(io_error(dir))?;
+        let mut base_offsets = Vec::new();
+        for entry in fs::read_dir(dir).map_err(io_error(dir))? {
+            let path = entry.map_err(io_error(dir))?.path();
+            if path.extension().is_some_and(|extension| extension == "log") {
+                match segment_base_offset(&path) {
+                    Some(base_offset) => base_offsets.push(base_offset),
+                    None => warn!(path = %path.display(), "Ignoring oddly named segment file"),
+                }
+            }
+        }
+        base_offsets.sort();
+
+        let mut log = Self {
+            dir: dir.to_path_buf(),
+            config,
+            handles,
+            topic: topic.to_string(),
+            partition,
+            segments: Vec::new(),
+        };
+        if base_offsets.is_empty() {
+            let segment = LogSegment::create(dir, log.segment_id(0), &config, &log.handles)?;
+            log.segments.push(segment);
+        }
+        for base_offset in base_offsets {
+            let path = dir.join(segment_file_name(base_offset));
+            let segment =
+                LogSegment::open(&path, log.segment_id(base_offset), &config, &log.handles)?;
+            if let Some(previous) = log.segments.last_mut() {
+                previous.close();
+            }
+            log.segments.push(segment);
+        }
+        log.pin_active()?;
+        Ok(log)
+    }
+
+    fn segment_id(&self, base_offset: i64) -> SegmentId {
+        SegmentId {
+            topic: self.topic.clone(),
+            partition: self.partition,
+            base_offset,
+        }
+    }
+
+    /// Keeps the active segment open in the handle cache
+    fn pin_active(&self) -> StorageResult<()> {
+        let active = self.active();
+        self.handles
+            .pin(active.id())
+            .map_err(handle_error(active.path()))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn segments(&self) -> &[LogSegment] {
+        &self.segments
+    }
+
+    /// Base offset of the first batch, the log end offset when there is
+    /// none
+    pub fn log_start_offset(&self) -> i64 {
+        self.segments
+            .iter()
+            .find_map(LogSegment::first_offset)
+            .unwrap_or_else(|| self.log_end_offset())
+    }
+
+    /// Offset the next appended batch will start at
+    pub fn log_end_offset(&self) -> i64 {
+        self.active().next_offset()
+    }
+
+    /// Batches across every segment
+    pub fn batch_count(&self) -> usize {
+        self.segments.iter().map(LogSegment::batch_count).sum()
+    }
+
+    /// Bytes across every segment
+    pub fn size(&self) -> u64 {
+        self.segments.iter().map(LogSegment::size).sum()
+    }
+
+    /// Largest batch timestamp of the newest segment holding any batch
+    pub fn max_timestamp(&self) -> Option<i64> {
+        self.segments
+            .iter()
+            .rev()
+            .find_map(LogSegment::max_timestamp)
+    }
+
+    /// Removes every batch at or after `offset`, returning the new log end
+    /// offset
+    ///
+    /// Batches are removed whole. The segments after the one holding
+    /// `offset` are deleted along with their indexes, and that segment is
+    /// cut back to the batch holding the offset and reopened as the active
+    /// segment, which brings its indexes in line with what is left.
+    pub fn truncate_to(&mut self, offset: i64) -> StorageResult<i64> {
+        if offset >= self.log_end_offset() {
+            return Ok(self.log_end_offset());
+        }
+        let keep = self
+            .segments
+            .partition_point(|segment| segment.base_offset() <= offset)
+            .max(1);
+        for segment in self.segments.drain(keep..) {
+            info!(
+                path = %segment.path().display(),
+                "Deleting segment past the truncation offset"
+            );
+            segment.delete()?;
+        }
+
+        let segment = self.segments.pop().unwrap();
+        let cut = segment.position_of(offset)?;
+        let (path, id) = (segment.path().to_path_buf(), segment.id().clone());
+        drop(segment);
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_len(cut))
+            .map_err(io_error(&path))?;
+        self.segments
+            .push(LogSegment::open(&path, id, &self.config, &self.handles)?);
+        self.pin_active()?;
+        Ok(self.log_end_offset())
+    }
+
+    /// Deletes the oldest segments while every batch in them is below
+    /// `high_watermark` and older than `retention_ms`
+    ///
+    /// A segment's age is judged by its max timestamp, unless that is more
+    /// than `max_future_ms` ahead of when the segment was last written, in
+    /// which case the write time is used. The active segment is never
+    /// deleted. Returns the number of batches deleted.
+    pub fn delete_expired_segments(
+        &mut self,
+        now_ms: i64,
+        retention_ms: i64,
+        max_future_ms: i64,
+        high_watermark: i64,
+    ) -> StorageResult<usize> {
+        let mut deleted = 0;
+        while self.segments.len() > 1 {
+            let segment = &self.segments[0];
+            let Some(max_timestamp) = segment.max_timestamp() else {
+                break;
+            };
+            let modified_ms = segment.modified_ms()?;
+            let timestamp = if max_timestamp > modified_ms.saturating_add(max_future_ms) {
+                modified_ms
+            } else {
+                max_timestamp
+            };
+            if segment.next_offset() > high_watermark
+                || now_ms.saturating_sub(timestamp) <= retention_ms
+            {
+                break;
+            }
+            let segment = self.segments.remove(0);
+            deleted += segment.batch_count();
+            info!(
+                path = %segment.path().display(),
+                "Deleting expired segment"
+            );
+            segment.delete()?;
+        }
+        Ok(deleted)
+    }
+
+    /// The first batch whose max timestamp is at or after `timestamp`,
+    /// with its data, from the oldest segment reaching that timestamp
+    pub fn find_by_timestamp(
+        &self,
+        timestamp: i64,
+    ) -> StorageResult<Option<(BatchPosition, Bytes)>> {
+        for segment in &self.segments {
+            if segment.max_timestamp().is_some_and(|max| max >= timestamp) {
+                return segment.find_by_timestamp(timestamp);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the batches from the one holding `offset`, with their data
+    ///
+    /// Stops once `max_bytes` would be exceeded, but always returns at least
+    /// one batch when any is available. A read never crosses into the next
+    /// segment. Reading at or past the log end returns nothing.
+    pub fn read_from(
+        &self,
+        offset: i64,
+        max_bytes: usize,
+    ) -> StorageResult<Vec<(BatchPosition, Bytes)>> {
+        let first = self
+            .segments
+            .partition_point(|segment| segment.base_offset() <= offset)
+            .saturating_sub(1);
+        for segment in &self.segments[first..] {
+            let batches = segment.read_from(offset, max_bytes)?;
+            if !batches.is_empty() {
+                return Ok(batches);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn active(&self) -> &LogSegment {
+        self.segments.last().unwrap()
+    }
+
+    /// Appends one batch whose offsets end at `last_offset`
+    ///
+    /// The batch must already carry the log end offset as its base offset.
+    /// Rolls first when the batch would take a non-empty active segment
+    /// past `segment_bytes`, so a batch larger than that still gets written,
+    /// alone in its segment. Also rolls before an offset would no longer
+    /// fit the index's 32-bit relative offsets.
+    pub fn append(&mut self, data: &[u8], last_offset: i64) -> StorageResult<()> {
+        let active = self.active();
+        let full = active.size() + data.len() as u64 > self.config.segment_bytes
+            || last_offset - active.base_offset() > u32::MAX as i64;
+        if active.size() > 0 && full {
+            let base_offset = active.next_offset();
+            let sealed = self.segments.last_mut().unwrap();
+            sealed.seal()?;
+            self.handles.unpin(sealed.id());
+            let id = self.segment_id(base_offset);
+            let segment = LogSegment::create(&self.dir, id, &self.config, &self.handles)?;
+            info!(
+                dir = %self.dir.display(),
+                base_offset = base_offset,
+                "Rolled to a new segment"
+            );
+            self.segments.push(segment);
+            self.pin_active()?;
+        }
+        self.segments.last_mut().unwrap().append(data, last_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::storage::partition_log_reader::PartitionLogReader;
+    use crate::protocol::record_batch::{encode_test_batch, split_record_batches};
+
+    /// Appends a batch of `record_count` records at the log end offset
+    fn append(log: &mut Log, record_count: i32) -> i64 {
+        let batch = split_record_batches(&encode_test_batch(record_count, 0))
+            .unwrap()
+            .remove(0);
+        let base_offset = log.log_end_offset();
+        let data = batch.with_base_offset(base_offset);
+        log.append(&data, base_offset + batch.offset_count() - 1)
+            .unwrap();
+        base_offset
+    }
+
+    fn batch_size() -> u64 {
+        encode_test_batch(2, 0).len() as u64
+    }
+
+    fn segment_bytes(segment_bytes: u64) -> LogConfig {
+        LogConfig {
+            segment_bytes,
+            ..LogConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_segment_file_name() {
+        assert_eq!(segment_file_name(0), "00000000000000000000.log");
+        assert_eq!(segment_file_name(42), "00000000000000000042.log");
+    }
+
+    #[tokio::test]
+    async fn test_rolls_and_recovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let partition_dir = dir.path().join("orders-0");
+        // Two batches fit in a segment, the third rolls
+        let mut log = Log::open(
+            &partition_dir,
+            segment_bytes(batch_size() * 2),
+            Arc::default(),
+            "orders",
+            0,
+        )
+        .unwrap();
+        for _ in 0..5 {
+            append(&mut log, 2);
+        }
+        let base_offsets: Vec<_> = log.segments().iter().map(|s| s.base_offset()).collect();
+        assert_eq!(base_offsets, vec![0, 4, 8]);
+        assert_eq!(log.log_end_offset(), 10);
+        drop(log);
+
+        let mut log = Log::open(
+            &partition_dir,
+            segment_bytes(batch_size() * 2),
+            Arc::default(),
+            "orders",
+            0,
+        )
+        .unwrap();
+        assert_eq!(log.segments().len(), 3);
+        assert_eq!(log.log_start_offset(), 0);
+        assert_eq!(log.log_end_offset(), 10);
+        assert_eq!(append(&mut log, 2), 10);
+        assert_eq!(log.segments().len(), 3);
+
+        let reader = PartitionLogReader::open_dir(&partition_dir).await.unwrap();
+        let offsets: Vec<_> = reader
+            .batches()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| (batch.base_offset, batch.last_offset))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![(0, 1), (2, 3), (4, 5), (6, 7), (8, 9), (10, 11)]
+        );
+        let records = reader.read_from(5, usize::MAX).await.unwrap();
+        let batches = split_record_batches(&records).unwrap();
+        // Reads stop at the end of the segment holding the offset
+        let read: Vec<_> = batches.iter().map(|batch| batch.base_offset()).collect();
+        assert_eq!(read, vec![4, 6]);
+    }
+
+    #[test]
+    fn test_recovery_truncates_partial_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = Log::open(
+            dir.path(),
+            LogConfig::default(),
+            Arc::default(),
+            "orders",
+            0,
+        )
+        .unwrap();
+        append(&mut log, 3);
+        append(&mut log, 2);
+        drop(log);
+
+        let segment = dir.path().join(segment_file_name(0));
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        let torn = encode_test_batch(1, 0);
+        file.write_all(&torn[..torn.len() / 2]).unwrap();
+        drop(file);
+
+        let mut log = Log::open(
+            dir.path(),
+            LogConfig::default(),
+            Arc::default(),
+            "orders",
+            0,
+        )
+        .unwrap();
+        assert_eq!(log.log_end_offset(), 5);
+        assert_eq!(
+            fs::metadata(&segment).unwrap().len(),
+            log.segments()[0].size()
+        );
+        assert_eq!(append(&mut log, 1), 5);
+        assert_eq!(log.log_end_offset(), 6);
+    }
+
+    /// Appends one-record batches stamped with `timestamps`
+    fn append_stamped(log: &mut Log, timestamps: &[i64]) {
+        for &timestamp in timestamps {
+            let batch = split_record_batches(&encode_test_batch(1, timestamp))
+                .unwrap()
+                .remove(0);
+            let offset = log.log_end_offset();
+            log.append(&batch.with_base_offset(offset), offset).unwrap();
+        }
+    }
+
+    fn offset_for(log: &Log, timestamp: i64) -> Option<i64> {
+        log.find_by_timestamp(timestamp)
+            .unwrap()
+            .map(|(batch, _)| batch.base_offset)
+    }
+
+    #[test]
+    fn test_time_index_lookups() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LogConfig {
+            segment_bytes: batch_size() * 3,
+            index_interval_bytes: 0,
+        };
+        let mut log = Log::open(dir.path(), config, Arc::default(), "orders", 0).unwrap();
+        // The third batch is stamped earlier than the second
+        append_stamped(&mut log, &[100, 300, 200, 400, 500, 600]);
+        assert_eq!(log.segments().len(), 2);
+        let entries = log.segments()[0].time_index().entries();
+        assert!(entries
+            .windows(2)
+            .all(|pair| pair[0].timestamp < pair[1].timestamp));
+
+        let expected = [
+            (50, Some(0)),
+            (100, Some(0)),
+            (150, Some(1)),
+            (200, Some(1)),
+            (300, Some(1)),
+            (350, Some(3)),
+            (450, Some(4)),
+            (600, Some(5)),
+            (700, None),
+        ];
+        for (timestamp, offset) in expected {
+            assert_eq!(
+                offset_for(&log, timestamp),
+                offset,
+                "timestamp {}",
+                timestamp
+            );
+        }
+        drop(log);
+
+        // Rebuilt from the segments, the indexes answer the same
+        for segment in ["00000000000000000000", "00000000000000000003"] {
+            fs::remove_file(dir.path().join(format!("{segment}.timeindex"))).unwrap();
+        }
+        let log = Log::open(dir.path(), config, Arc::default(), "orders", 0).unwrap();
+        for (timestamp, offset) in expected {
+            assert_eq!(
+                offset_for(&log, timestamp),
+                offset,
+                "timestamp {}",
+                timestamp
+            );
+        }
+    }
+
+    #[test]
+    fn test_reads_seek_through_the_offset_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LogConfig {
+            index_interval_bytes: batch_size() * 8,
+            ..LogConfig::default()
+        };
+        let mut log = Log::open(dir.path(), config, Arc::default(), "orders", 0).unwrap();
+        for _ in 0..100 {
+            append(&mut log, 2);
+        }
+        assert!(log.segments()[0].offset_index().entries().len() > 10);
+
+        // Garble the first batch: reads that seek past it never see it
+        let segment = dir.path().join(segment_file_name(0));
+        let mut file = OpenOptions::new().write(true).open(&segment).unwrap();
+        file.write_all(&[0xFF; HEADER_PREFIX]).unwrap();
+        drop(file);
+
+        for offset in (40..200).step_by(7) {
+            let batches = log.read_from(offset, 0).unwrap();
+            assert_eq!(batches.len(), 1);
+            assert_eq!(batches[0].0.base_offset, offset - offset % 2);
+            let batch = split_record_batches(&batches[0].1).unwrap().remove(0);
+            assert_eq!(batch.base_offset(), offset - offset % 2);
+        }
+    }
+
+    #[test]
+    fn test_reads_across_partitions_stay_within_the_handle_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let handles = Arc::new(SegmentHandleCache::new(4));
+        // Three partitions of four segments, each active segment pinned
+        let mut logs: Vec<Log> = (0..3)
+            .map(|partition| {
+                let partition_dir = dir.path().join(format!("orders-{partition}"));
+                let config = segment_bytes(batch_size() * 2);
+                Log::open(
+                    &partition_dir,
+                    config,
+                    Arc::clone(&handles),
+                    "orders",
+                    partition,
+                )
+                .unwrap()
+            })
+            .collect();
+        for log in &mut logs {
+            for _ in 0..8 {
+                append(log, 2);
+            }
+            assert_eq!(log.segments().len(), 4);
+        }
+        assert_eq!(handles.metrics().pinned_handles, 3);
+
+        for _ in 0..2 {
+            for log in &logs {
+                for offset in (0..16).step_by(3) {
+                    let batches = log.read_from(offset, 0).unwrap();
+                    assert_eq!(batches[0].0.base_offset, offset - offset % 2);
+                    assert!(handles.metrics().open_handles <= 4);
+                }
+            }
+        }
+        let metrics = handles.metrics();
+        assert!(metrics.reopens > 0);
+        assert_eq!(
+            metrics.opens,
+            metrics.evictions + metrics.open_handles as u64
+        );
+
+        // Dropping a log releases the handles of its segments
+        logs.pop();
+        assert_eq!(handles.metrics().pinned_handles, 2);
+    }
+
+    #[test]
+    fn test_batch_larger_than_segment_is_written_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = Log::open(dir.path(), segment_bytes(1), Arc::default(), "orders", 0).unwrap();
+        append(&mut log, 1);
+        append(&mut log, 1);
+        let sizes: Vec<_> = log.segments().iter().map(|s| s.size()).collect();
+        assert_eq!(sizes.len(), 2);
+        assert!(sizes.iter().all(|&size| size > 1));
+    }
+}
@@ -0,0 +1,469 @@
+use crate::protocol::record_batch::CONTROL_ATTRIBUTE;
+use crate::protocol::tagged_fields::skip_tagged_fields;
+use crate::protocol::{ProtocolError, ProtocolResult, WireFormat};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use uuid::Uuid;
+
+/// Name of the KRaft metadata topic
+pub const METADATA_TOPIC: &str = "__cluster_metadata";
+
+/// Feature name carrying the metadata version
+pub const METADATA_VERSION_FEATURE: &str = "metadata.version";
+
+/// metadata.version written when formatting (3.7-IV4)
+pub const DEFAULT_METADATA_VERSION: i16 = 19;
+
+/// Frame version prefixed to every serialized metadata record
+const RECORD_FRAME_VERSION: u32 = 1;
+
+const REGISTER_BROKER_RECORD: u32 = 0;
+const TOPIC_RECORD: u32 = 2;
+const PARTITION_RECORD: u32 = 3;
+const FEATURE_LEVEL_RECORD: u32 = 12;
+
+/// Newest PartitionRecord version decoded; later ones only add tagged fields
+const PARTITION_RECORD_MAX_VERSION: u32 = 2;
+
+/// Size of a v2 record batch header, excluding base offset and length
+const BATCH_HEADER_AFTER_LENGTH: usize = 4 + 1 + 4 + 2 + 4 + 8 + 8 + 8 + 2 + 4 + 4;
+
+/// Listener advertised in a broker registration
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokerEndpoint {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub security_protocol: i16,
+}
+
+/// A record of the `__cluster_metadata` log
+///
+/// Feature levels, broker registrations, topics and partitions are decoded;
+/// anything else is kept as `Unknown` so logs written by a real controller
+/// still load.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataRecord {
+    FeatureLevel {
+        name: String,
+        feature_level: i16,
+    },
+    RegisterBroker {
+        broker_id: i32,
+        incarnation_id: Uuid,
+        broker_epoch: i64,
+        endpoints: Vec<BrokerEndpoint>,
+        rack: Option<String>,
+        fenced: bool,
+    },
+    Topic {
+        name: String,
+        topic_id: Uuid,
+    },
+    Partition {
+        partition_id: i32,
+        topic_id: Uuid,
+        replicas: Vec<i32>,
+        isr: Vec<i32>,
+        leader: i32,
+        leader_epoch: i32,
+        partition_epoch: i32,
+    },
+    Unknown {
+        record_type: u32,
+        version: u32,
+    },
+}
+
+impl MetadataRecord {
+    /// Serializes the record as a metadata log record value
+    pub fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_unsigned_varint(&mut buffer, RECORD_FRAME_VERSION);
+        match self {
+            Self::FeatureLevel {
+                name,
+                feature_level,
+            } => {
+                WireFormat::encode_unsigned_varint(&mut buffer, FEATURE_LEVEL_RECORD);
+                WireFormat::encode_unsigned_varint(&mut buffer, 0);
+                WireFormat::encode_compact_string(&mut buffer, name)?;
+                buffer.put_i16(*feature_level);
+            }
+            Self::RegisterBroker {
+                broker_id,
+                incarnation_id,
+                broker_epoch,
+                endpoints,
+                rack,
+                fenced,
+            } => {
+                WireFormat::encode_unsigned_varint(&mut buffer, REGISTER_BROKER_RECORD);
+                WireFormat::encode_unsigned_varint(&mut buffer, 0);
+                buffer.put_i32(*broker_id);
+                WireFormat::encode_uuid(&mut buffer, incarnation_id);
+                buffer.put_i64(*broker_epoch);
+                WireFormat::encode_compact_array(&mut buffer, endpoints, |buffer, endpoint| {
+                    WireFormat::encode_compact_string(buffer, &endpoint.name)?;
+                    WireFormat::encode_compact_string(buffer, &endpoint.host)?;
+                    buffer.put_u16(endpoint.port);
+                    buffer.put_i16(endpoint.security_protocol);
+                    WireFormat::encode_unsigned_varint(buffer, 0);
+                    Ok(())
+                })?;
+                // Supported features: metadata.version only
+                WireFormat::encode_compact_array(
+                    &mut buffer,
+                    &[(METADATA_VERSION_FEATURE, 1, DEFAULT_METADATA_VERSION)],
+                    |buffer, &(name, min, max)| {
+                        WireFormat::encode_compact_string(buffer, name)?;
+                        buffer.put_i16(min);
+                        buffer.put_i16(max);
+                        WireFormat::encode_unsigned_varint(buffer, 0);
+                        Ok(())
+                    },
+                )?;
+                WireFormat::encode_compact_nullable_string(&mut buffer, rack.as_deref())?;
+                buffer.put_u8(*fenced as u8);
+            }
+            Self::Topic { name, topic_id } => {
+                WireFormat::encode_unsigned_varint(&mut buffer, TOPIC_RECORD);
+                WireFormat::encode_unsigned_varint(&mut buffer, 0);
+                WireFormat::encode_compact_string(&mut buffer, name)?;
+                WireFormat::encode_uuid(&mut buffer, topic_id);
+            }
+            Self::Partition {
+                partition_id,
+                topic_id,
+                replicas,
+                isr,
+                leader,
+                leader_epoch,
+                partition_epoch,
+            } => {
+                let put_ids = |buffer: &mut BytesMut, ids: &[i32]| {
+                    WireFormat::encode_compact_array(buffer, ids, |buffer, id| {
+                        buffer.put_i32(*id);
+                        Ok(())
+                    })
+                };
+                WireFormat::encode_unsigned_varint(&mut buffer, PARTITION_RECORD);
+                WireFormat::encode_unsigned_varint(&mut buffer, 0);
+                buffer.put_i32(*partition_id);
+                WireFormat::encode_uuid(&mut buffer, topic_id);
+                put_ids(&mut buffer, replicas)?;
+                put_ids(&mut buffer, isr)?;
+                put_ids(&mut buffer, &[])?; // removing replicas
+                put_ids(&mut buffer, &[])?; // adding replicas
+                buffer.put_i32(*leader);
+                buffer.put_i32(*leader_epoch);
+                buffer.put_i32(*partition_epoch);
+            }
+            Self::Unknown { record_type, .. } => {
+                return Err(ProtocolError::SerializationError(format!(
+                    "cannot encode unknown metadata record type {}",
+                    record_type
+                )));
+            }
+        }
+        // Empty tagged fields
+        WireFormat::encode_unsigned_varint(&mut buffer, 0);
+        Ok(buffer)
+    }
+
+    /// Parses a metadata log record value
+    pub fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        let frame_version = WireFormat::decode_unsigned_varint(buffer)?;
+        if frame_version != RECORD_FRAME_VERSION {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "unsupported metadata record frame version {}",
+                frame_version
+            )));
+        }
+        let record_type = WireFormat::decode_unsigned_varint(buffer)?;
+        let version = WireFormat::decode_unsigned_varint(buffer)?;
+
+        match record_type {
+            FEATURE_LEVEL_RECORD => Ok(Self::FeatureLevel {
+                name: WireFormat::decode_compact_string(buffer)?,
+                feature_level: WireFormat::decode_i16(buffer)?,
+            }),
+            REGISTER_BROKER_RECORD if version == 0 => {
+                let broker_id = WireFormat::decode_i32(buffer)?;
+                let incarnation_id = WireFormat::decode_uuid(buffer)?;
+                let broker_epoch = WireFormat::decode_i64(buffer)?;
+                let endpoints = WireFormat::decode_compact_array(buffer, |buffer| {
+                    let endpoint = BrokerEndpoint {
+                        name: WireFormat::decode_compact_string(buffer)?,
+                        host: WireFormat::decode_compact_string(buffer)?,
+                        port: WireFormat::decode_i16(buffer)? as u16,
+                        security_protocol: WireFormat::decode_i16(buffer)?,
+                    };
+                    skip_tagged_fields(buffer, true)?;
+                    Ok(endpoint)
+                })?;
+                WireFormat::decode_compact_array(buffer, |buffer| {
+                    WireFormat::decode_compact_string(buffer)?;
+                    WireFormat::decode_i16(buffer)?;
+                    WireFormat::decode_i16(buffer)?;
+                    skip_tagged_fields(buffer, true)
+                })?;
+                let rack = WireFormat::decode_compact_nullable_string(buffer)?;
+                let fenced = WireFormat::decode_u8(buffer)? != 0;
+                Ok(Self::RegisterBroker {
+                    broker_id,
+                    incarnation_id,
+                    broker_epoch,
+                    endpoints,
+                    rack,
+                    fenced,
+                })
+            }
+            TOPIC_RECORD if version == 0 => Ok(Self::Topic {
+                name: WireFormat::decode_compact_string(buffer)?,
+                topic_id: WireFormat::decode_uuid(buffer)?,
+            }),
+            PARTITION_RECORD if version <= PARTITION_RECORD_MAX_VERSION => {
+                let decode_ids = |buffer: &mut Bytes| {
+                    WireFormat::decode_compact_array(buffer, WireFormat::decode_i32)
+                };
+                let partition_id = WireFormat::decode_i32(buffer)?;
+                let topic_id = WireFormat::decode_uuid(buffer)?;
+                let replicas = decode_ids(buffer)?;
+                let isr = decode_ids(buffer)?;
+                let _removing_replicas = decode_ids(buffer)?;
+                let _adding_replicas = decode_ids(buffer)?;
+                let leader = WireFormat::decode_i32(buffer)?;
+                let leader_epoch = WireFormat::decode_i32(buffer)?;
+                let partition_epoch = WireFormat::decode_i32(buffer)?;
+                if version >= 1 {
+                    let _directories =
+                        WireFormat::decode_compact_array(buffer, WireFormat::decode_uuid)?;
+                }
+                Ok(Self::Partition {
+                    partition_id,
+                    topic_id,
+                    replicas,
+                    isr,
+                    leader,
+                    leader_epoch,
+                    partition_epoch,
+                })
+            }
+            _ => Ok(Self::Unknown {
+                record_type,
+                version,
+            }),
+        }
+    }
+}
+
+/// Encodes metadata records as one v2 record batch starting at `base_offset`
+pub fn encode_batch(
+    base_offset: i64,
+    timestamp_ms: i64,
+    records: &[MetadataRecord],
+) -> ProtocolResult<BytesMut> {
+    let mut encoded_records = BytesMut::new();
+    for (offset_delta, record) in records.iter().enumerate() {
+        let value = record.encode()?;
+        let mut body = BytesMut::new();
+        body.put_i8(0); // attributes
+        WireFormat::encode_varlong(&mut body, 0); // timestamp delta
+        WireFormat::encode_varint(&mut body, offset_delta as i32);
+        WireFormat::encode_varint(&mut body, -1); // null key
+        WireFormat::encode_varint(&mut body, value.len() as i32);
+        body.put_slice(&value);
+        WireFormat::encode_varint(&mut body, 0); // no headers
+
+        WireFormat::encode_varint(&mut encoded_records, body.len() as i32);
+        encoded_records.put_slice(&body);
+    }
+
+    let mut crc_covered = BytesMut::new();
+    crc_covered.put_i16(0); // attributes
+    crc_covered.put_i32(records.len().saturating_sub(1) as i32);
+    crc_covered.put_i64(timestamp_ms);
+    crc_covered.put_i64(timestamp_ms);
+    crc_covered.put_i64(-1); // producer id
+    crc_covered.put_i16(-1); // producer epoch
+    crc_covered.put_i32(-1); // base sequence
+    crc_covered.put_i32(records.len() as i32);
+    crc_covered.put_slice(&encoded_records);
+
+    let mut batch = BytesMut::new();
+    batch.put_i64(base_offset);
+    batch.put_i32((4 + 1 + 4 + crc_covered.len()) as i32);
+    batch.put_i32(0); // partition leader epoch
+    batch.put_i8(2); // magic
+    batch.put_u32(crc32c::crc32c(&crc_covered));
+    batch.put_slice(&crc_covered);
+    Ok(batch)
+}
+
+/// Decodes every record of a metadata log segment with its offset
+///
+/// Each batch's CRC-32C is verified; a torn batch at the end of the data is
+/// reported as an error rather than silently dropped. Control batches, such
+/// as the LeaderChange a KRaft controller writes, carry no metadata records
+/// and are skipped.
+pub fn decode_batches(data: &[u8]) -> ProtocolResult<Vec<(i64, MetadataRecord)>> {
+    let mut buffer = Bytes::copy_from_slice(data);
+    let mut records = Vec::new();
+
+    while buffer.has_remaining() {
+        let base_offset = WireFormat::decode_i64(&mut buffer)?;
+        let batch_length = WireFormat::decode_i32(&mut buffer)?;
+        if batch_length < BATCH_HEADER_AFTER_LENGTH as i32 {
+            return Err(ProtocolError::invalid_length(batch_length));
+        }
+        let batch_length = batch_length as usize;
+        if buffer.remaining() < batch_length {
+            return Err(ProtocolError::insufficient_bytes(
+                batch_length,
+                buffer.remaining(),
+            ));
+        }
+        let mut batch = buffer.split_to(batch_length);
+
+        let _partition_leader_epoch = batch.get_i32();
+        let magic = batch.get_i8();
+        if magic != 2 {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "unsupported record batch magic {}",
+                magic
+            )));
+        }
+        let crc = batch.get_u32();
+        let computed = crc32c::crc32c(&batch);
+        if crc != computed {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "record batch at offset {} has crc {:#010x}, computed {:#010x}",
+                base_offset, crc, computed
+            )));
+        }
+        if batch.get_i16() & CONTROL_ATTRIBUTE != 0 {
+            continue;
+        }
+        batch.advance(4 + 8 + 8 + 8 + 2 + 4);
+        let record_count = batch.get_i32();
+
+        for _ in 0..record_count {
+            let length = WireFormat::decode_varint(&mut batch)?;
+            if length < 0 || batch.remaining() < length as usize {
+                return Err(ProtocolError::invalid_length(length));
+            }
+            let mut record = batch.split_to(length as usize);
+            let _attributes = WireFormat::decode_u8(&mut record)?;
+            let _timestamp_delta = WireFormat::decode_varlong(&mut record)?;
+            let offset_delta = WireFormat::decode_varint(&mut record)? as i64;
+            let key_length = WireFormat::decode_varint(&mut record)?;
+            if key_length > 0 {
+                if record.remaining() < key_length as usize {
+                    return Err(ProtocolError::invalid_length(key_length));
+                }
+                record.advance(key_length as usize);
+            }
+            let value_length = WireFormat::decode_varint(&mut record)?;
+            if value_length < 0 || record.remaining() < value_length as usize {
+                return Err(ProtocolError::invalid_length(value_length));
+            }
+            let mut value = record.split_to(value_length as usize);
+            records.push((
+                base_offset + offset_delta,
+                MetadataRecord::decode(&mut value)?,
+            ));
+        }
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registration() -> MetadataRecord {
+        MetadataRecord::RegisterBroker {
+            broker_id: 1,
+            incarnation_id: Uuid::from_u128(7),
+            broker_epoch: 0,
+            endpoints: vec![BrokerEndpoint {
+                name: "PLAINTEXT".to_string(),
+                host: "localhost".to_string(),
+                port: 9092,
+                security_protocol: 0,
+            }],
+            rack: None,
+            fenced: false,
+        }
+    }
+
+    #[test]
+    fn test_feature_level_record_layout() {
+        let record = MetadataRecord::FeatureLevel {
+            name: METADATA_VERSION_FEATURE.to_string(),
+            feature_level: 19,
+        };
+        let value = record.encode().unwrap();
+
+        // frame version, record type 12, version 0
+        assert_eq!(&value[..3], &[0x01, 0x0c, 0x00]);
+        assert_eq!(
+            MetadataRecord::decode(&mut Bytes::copy_from_slice(&value[..])).unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn test_batch_roundtrip() {
+        let records = vec![
+            MetadataRecord::FeatureLevel {
+                name: METADATA_VERSION_FEATURE.to_string(),
+                feature_level: DEFAULT_METADATA_VERSION,
+            },
+            registration(),
+        ];
+        let batch = encode_batch(0, 1_700_000_000_000, &records).unwrap();
+
+        let decoded = decode_batches(&batch).unwrap();
+        assert_eq!(
+            decoded,
+            vec![(0, records[0].clone()), (1, records[1].clone())]
+        );
+    }
+
+    #[test]
+    fn test_control_batches_are_skipped() {
+        use crate::protocol::record_batch::leader_change_test_batch;
+
+        let mut log = BytesMut::from(&leader_change_test_batch()[..]);
+        log.put_slice(&encode_batch(1, 0, &[registration()]).unwrap());
+        assert_eq!(decode_batches(&log).unwrap(), vec![(1, registration())]);
+    }
+
+    #[test]
+    fn test_corrupt_batch_is_rejected() {
+        let mut batch = encode_batch(0, 0, &[registration()]).unwrap();
+        let last = batch.len() - 1;
+        batch[last] ^= 0xFF;
+
+        assert!(decode_batches(&batch).is_err());
+    }
+
+    #[test]
+    fn test_unknown_record_types_are_preserved() {
+        let mut value = BytesMut::new();
+        WireFormat::encode_unsigned_varint(&mut value, RECORD_FRAME_VERSION);
+        WireFormat::encode_unsigned_varint(&mut value, 9); // RemoveTopicRecord
+        WireFormat::encode_unsigned_varint(&mut value, 0);
+
+        let mut value = value.freeze();
+        assert_eq!(
+            MetadataRecord::decode(&mut value).unwrap(),
+            MetadataRecord::Unknown {
+                record_type: 9,
+                version: 0
+            }
+        );
+    }
+}
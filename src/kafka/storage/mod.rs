@@ -0,0 +1,68 @@
+//! Partition storage
+//!
+//! - `partition_log`: the record batches of a single partition with its
+//!   offsets and high watermark
+//! - `log_segment`: segment files produced batches are written through to
+//! - `offset_index`: the sparse offset index kept next to each segment
+//! - `time_index`: the sparse timestamp index kept next to each segment
+//! - `partition_log_reader`: raw batches read from a partition's segment files
+//! - `metadata_log`: records of the KRaft `__cluster_metadata` log
+//! - `log_dir`: formatting and loading of a log directory
+//! - `segment_handles`: the shared budget of open segment files
+//! - `topic_store`: the topics the broker hosts and their partition logs
+
+pub mod log_dir;
+pub mod log_segment;
+pub mod metadata_log;
+pub mod offset_index;
+pub mod partition_log;
+pub mod partition_log_reader;
+pub mod segment_handles;
+pub mod time_index;
+pub mod topic_store;
+
+pub use partition_log::PartitionLog;
+pub use topic_store::{Topic, TopicStore};
+
+use crate::kafka::producer_state::ProducerStateError;
+use thiserror::Error;
+
+/// Errors raised by partition storage
+#[derive(Error, Debug, PartialEq)]
+pub enum StorageError {
+    #[error(
+        "Offset {offset} is out of range (log start {log_start_offset}, log end {log_end_offset})"
+    )]
+    OffsetOutOfRange {
+        offset: i64,
+        log_start_offset: i64,
+        log_end_offset: i64,
+    },
+
+    #[error("Topic '{topic}' already exists")]
+    TopicAlreadyExists { topic: String },
+
+    #[error("Invalid partition count {num_partitions} for topic '{topic}'")]
+    InvalidPartitions { topic: String, num_partitions: i32 },
+
+    #[error("Invalid topic name '{topic}': {reason}")]
+    InvalidTopic { topic: String, reason: String },
+
+    #[error("Unknown topic '{topic}'")]
+    UnknownTopic { topic: String },
+
+    #[error("Unknown topic id {topic_id}")]
+    UnknownTopicId { topic_id: uuid::Uuid },
+
+    #[error("I/O error on {}: {message}", path.display())]
+    Io {
+        path: std::path::PathBuf,
+        message: String,
+    },
+
+    #[error(transparent)]
+    ProducerState(#[from] ProducerStateError),
+}
+
+/// Type alias for storage operation results
+pub type StorageResult<T> = Result<T, StorageError>;
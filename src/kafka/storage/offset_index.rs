@@ -0,0 +1,174 @@
+/// Bytes of one index entry: relative offset and file position
+pub const INDEX_ENTRY_SIZE: usize = 8;
+
+/// Name of the offset index of the segment starting at `base_offset`, such
+/// as `00000000000000000042.index`
+pub fn index_file_name(base_offset: i64) -> String {
+    format!("{base_offset:020}.index")
+}
+
+/// One entry of an offset index
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexEntry {
+    /// Last offset of the indexed batch, relative to the segment's base offset
+    pub relative_offset: u32,
+    /// Byte position of the batch in the segment file
+    pub position: u32,
+}
+
+impl IndexEntry {
+    pub fn encode(&self) -> [u8; INDEX_ENTRY_SIZE] {
+        let mut entry = [0; INDEX_ENTRY_SIZE];
+        entry[..4].copy_from_slice(&self.relative_offset.to_be_bytes());
+        entry[4..].copy_from_slice(&self.position.to_be_bytes());
+        entry
+    }
+}
+
+/// The sparse offset index of one segment, as a `.index` file holds it
+///
+/// Every `index.interval.bytes` of log data, the batch being appended gets
+/// an entry mapping its last offset to its position in the segment file. A
+/// lookup finds the closest entry at or before the wanted offset, and the
+/// segment is scanned forward from there rather than from its start.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OffsetIndex {
+    base_offset: i64,
+    entries: Vec<IndexEntry>,
+}
+
+impl OffsetIndex {
+    /// An empty index for the segment starting at `base_offset`
+    pub fn new(base_offset: i64) -> Self {
+        Self {
+            base_offset,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Decodes the contents of an index file
+    ///
+    /// Returns `None` for a file that is not a whole number of entries, or
+    /// whose entries are not in offset and position order.
+    pub fn decode(base_offset: i64, data: &[u8]) -> Option<Self> {
+        if data.len() % INDEX_ENTRY_SIZE != 0 {
+            return None;
+        }
+        let entries: Vec<_> = data
+            .chunks_exact(INDEX_ENTRY_SIZE)
+            .map(|entry| IndexEntry {
+                relative_offset: u32::from_be_bytes(entry[..4].try_into().unwrap()),
+                position: u32::from_be_bytes(entry[4..].try_into().unwrap()),
+            })
+            .collect();
+        let ordered = entries.windows(2).all(|pair| {
+            pair[0].relative_offset < pair[1].relative_offset && pair[0].position < pair[1].position
+        });
+        ordered.then_some(Self {
+            base_offset,
+            entries,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(IndexEntry::encode).collect()
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Indexes the batch at `position` whose offsets end at `last_offset`,
+    /// returning the entry to write
+    pub fn push(&mut self, last_offset: i64, position: u64) -> IndexEntry {
+        let entry = IndexEntry {
+            relative_offset: (last_offset - self.base_offset) as u32,
+            position: position as u32,
+        };
+        self.entries.push(entry);
+        entry
+    }
+
+    /// Drops the entries of batches at or past `position`
+    pub fn truncate_to_position(&mut self, position: u64) {
+        let keep = self
+            .entries
+            .partition_point(|entry| (entry.position as u64) < position);
+        self.entries.truncate(keep);
+    }
+
+    /// Position to scan from for the batch holding `offset`
+    ///
+    /// That is the position of the last indexed batch ending at or before
+    /// `offset`, or the start of the segment when there is none.
+    pub fn lookup(&self, offset: i64) -> u64 {
+        let relative = offset - self.base_offset;
+        let after = self
+            .entries
+            .partition_point(|entry| entry.relative_offset as i64 <= relative);
+        after
+            .checked_sub(1)
+            .map_or(0, |index| self.entries[index].position as u64)
+    }
+
+    /// Position of the last indexed batch, zero when there is none
+    pub fn last_position(&self) -> u64 {
+        self.entries.last().map_or(0, |entry| entry.position as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> OffsetIndex {
+        let mut index = OffsetIndex::new(100);
+        index.push(104, 4096);
+        index.push(119, 8200);
+        index.push(130, 12400);
+        index
+    }
+
+    #[test]
+    fn test_lookup_finds_preceding_entry() {
+        let index = index();
+        for (offset, position) in [
+            (100, 0),
+            (103, 0),
+            (104, 4096),
+            (118, 4096),
+            (119, 8200),
+            (129, 8200),
+            (130, 12400),
+            (1000, 12400),
+        ] {
+            assert_eq!(index.lookup(offset), position, "offset {}", offset);
+        }
+        assert_eq!(OffsetIndex::new(0).lookup(5), 0);
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let index = index();
+        let data = index.encode();
+        assert_eq!(data.len(), 3 * INDEX_ENTRY_SIZE);
+        assert_eq!(&data[..8], &[0, 0, 0, 4, 0, 0, 0x10, 0]);
+        assert_eq!(OffsetIndex::decode(100, &data), Some(index));
+    }
+
+    #[test]
+    fn test_decode_rejects_corrupt_files() {
+        let data = index().encode();
+        assert_eq!(OffsetIndex::decode(100, &data[..data.len() - 3]), None);
+        let reversed = [&data[8..16], &data[..8]].concat();
+        assert_eq!(OffsetIndex::decode(100, &reversed), None);
+    }
+
+    #[test]
+    fn test_truncate_to_position() {
+        let mut index = index();
+        index.truncate_to_position(8200);
+        assert_eq!(index.entries().len(), 1);
+        assert_eq!(index.last_position(), 4096);
+    }
+}
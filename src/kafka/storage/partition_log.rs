@@ -0,0 +1,1012 @@
+use crate::kafka::clock::{Clock, SystemClock};
+use crate::kafka::events::{BrokerEvent, EventBus};
+use crate::kafka::producer_state::{
+    ProducerStateManager, SequenceCheck, DEFAULT_PRODUCER_ID_EXPIRATION_MS,
+};
+use crate::kafka::storage::log_segment::Log;
+use crate::kafka::storage::{StorageError, StorageResult};
+use crate::kafka::watermark::{HighWatermark, HighWatermarkSubscriber};
+use crate::logging::{info, warn};
+use crate::protocol::record_batch::{
+    split_record_batches, RecordBatch, NO_PRODUCER_ID, NO_TIMESTAMP,
+};
+use bytes::Bytes;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+/// A record batch stored in a partition log, kept in wire format
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredBatch {
+    pub base_offset: i64,
+    pub last_offset: i64,
+    /// Largest record timestamp in the batch
+    pub max_timestamp: i64,
+    /// Wall-clock time the broker appended the batch
+    pub append_time_ms: i64,
+    pub data: Bytes,
+}
+
+/// How the timestamps of an appended batch are assigned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchTimestamp {
+    /// The producer's timestamps are kept; carries the batch's max timestamp
+    CreateTime(i64),
+    /// The broker stamps the batch with its append time
+    LogAppendTime,
+}
+
+/// Offsets and timestamp assigned to an appended batch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AppendInfo {
+    pub base_offset: i64,
+    pub max_timestamp: i64,
+}
+
+/// A record found by its timestamp
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimestampOffset {
+    pub timestamp: i64,
+    pub offset: i64,
+}
+
+/// Offsets and size of a partition log, without any record data
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PartitionSnapshot {
+    pub topic: String,
+    pub partition: i32,
+    pub log_start_offset: i64,
+    pub high_watermark: i64,
+    pub log_end_offset: i64,
+    pub batch_count: usize,
+    pub size_bytes: usize,
+}
+
+#[derive(Debug, Default)]
+struct LogState {
+    log_start_offset: i64,
+    log_end_offset: i64,
+    /// The batches of a log without segments; a disk-backed log keeps none
+    batches: Vec<StoredBatch>,
+    /// Segment files holding the batches of a disk-backed log
+    segments: Option<Log>,
+}
+
+impl LogState {
+    fn out_of_range(&self, offset: i64) -> StorageError {
+        StorageError::OffsetOutOfRange {
+            offset,
+            log_start_offset: self.log_start_offset,
+            log_end_offset: self.log_end_offset,
+        }
+    }
+
+    /// Index of the first batch containing an offset at or after `offset`
+    fn batch_index_for(&self, offset: i64) -> usize {
+        self.batches
+            .partition_point(|batch| batch.last_offset < offset)
+    }
+
+    /// Max timestamp of the newest batch, `i64::MIN` for an empty log
+    fn previous_max_timestamp(&self) -> i64 {
+        match &self.segments {
+            Some(segments) => segments.max_timestamp(),
+            None => self.batches.last().map(|batch| batch.max_timestamp),
+        }
+        .unwrap_or(i64::MIN)
+    }
+}
+
+/// The log of a single topic partition
+///
+/// Batches, offsets and the high watermark are guarded together by one
+/// lock so readers observe either the state before or after an append or
+/// truncation, never anything in between.
+#[derive(Debug)]
+pub struct PartitionLog {
+    topic: String,
+    partition: i32,
+    state: RwLock<LogState>,
+    /// Sequence state of the idempotent producers appending here, only
+    /// locked while holding the state lock for writing
+    producers: Mutex<ProducerStateManager>,
+    high_watermark: HighWatermark,
+    events: Option<EventBus>,
+    clock: Arc<dyn Clock>,
+}
+
+impl PartitionLog {
+    /// Creates an empty log for the given topic partition
+    pub fn new(topic: impl Into<String>, partition: i32) -> Self {
+        Self {
+            topic: topic.into(),
+            partition,
+            state: RwLock::new(LogState::default()),
+            producers: Mutex::new(ProducerStateManager::new(
+                Arc::new(SystemClock),
+                DEFAULT_PRODUCER_ID_EXPIRATION_MS,
+            )),
+            high_watermark: HighWatermark::default(),
+            events: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Uses `clock` for append times, retention and producer expiry
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.producers = Mutex::new(ProducerStateManager::new(
+            Arc::clone(&clock),
+            DEFAULT_PRODUCER_ID_EXPIRATION_MS,
+        ));
+        self.clock = clock;
+        self
+    }
+
+    /// Sequence state of the idempotent producers appending to the log
+    pub fn producers(&self) -> MutexGuard<'_, ProducerStateManager> {
+        self.producers.lock().unwrap()
+    }
+
+    /// Keeps the batches in the segments of `log` instead of in memory
+    ///
+    /// The log continues from the recovered offsets, all of them committed.
+    /// Neither recovered nor produced batches are held in memory; reads,
+    /// truncation and retention all go to the segment files.
+    pub fn with_segments(self, log: Log) -> Self {
+        {
+            let mut state = self.state.write().unwrap();
+            state.log_start_offset = log.log_start_offset();
+            state.log_end_offset = log.log_end_offset();
+            self.high_watermark.advance_to(state.log_end_offset);
+            state.segments = Some(log);
+        }
+        self
+    }
+
+    /// Publishes high watermark and truncation events on the given bus
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Topic this log belongs to
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Partition index of this log
+    pub fn partition(&self) -> i32 {
+        self.partition
+    }
+
+    /// Offset of the first record still in the log
+    pub fn log_start_offset(&self) -> i64 {
+        self.state.read().unwrap().log_start_offset
+    }
+
+    /// Offset the next appended record will receive
+    pub fn log_end_offset(&self) -> i64 {
+        self.state.read().unwrap().log_end_offset
+    }
+
+    /// Current high watermark
+    pub fn high_watermark(&self) -> i64 {
+        self.high_watermark.get()
+    }
+
+    /// Offsets and size taken together under the log lock
+    ///
+    /// Record payloads are never included.
+    pub fn snapshot(&self) -> PartitionSnapshot {
+        let state = self.state.read().unwrap();
+        let (batch_count, size_bytes) = match &state.segments {
+            Some(segments) => (segments.batch_count(), segments.size() as usize),
+            None => (
+                state.batches.len(),
+                state.batches.iter().map(|batch| batch.data.len()).sum(),
+            ),
+        };
+        PartitionSnapshot {
+            topic: self.topic.clone(),
+            partition: self.partition,
+            log_start_offset: state.log_start_offset,
+            high_watermark: self.high_watermark.get(),
+            log_end_offset: state.log_end_offset,
+            batch_count,
+            size_bytes,
+        }
+    }
+
+    /// Subscribes to high watermark changes
+    pub fn subscribe_high_watermark(&self) -> HighWatermarkSubscriber {
+        self.high_watermark.subscribe()
+    }
+
+    /// Appends a batch holding `record_count` records and returns its base offset
+    ///
+    /// The batch is stamped with the log append time.
+    pub fn append(&self, data: Bytes, record_count: i64) -> i64 {
+        self.append_batch(data, record_count, BatchTimestamp::LogAppendTime)
+            .base_offset
+    }
+
+    /// Appends a batch holding `record_count` records
+    ///
+    /// Log append time never goes backwards within a partition: if the wall
+    /// clock was stepped back, the batch is stamped with the previous batch's
+    /// max timestamp instead and a warning is logged. As `data` need not be
+    /// a record batch, this is only for logs without segments.
+    pub fn append_batch(
+        &self,
+        data: Bytes,
+        record_count: i64,
+        timestamp: BatchTimestamp,
+    ) -> AppendInfo {
+        let now_ms = self.clock.now_ms();
+        let mut state = self.state.write().unwrap();
+        debug_assert!(state.segments.is_none(), "disk-backed logs take batches");
+        let max_timestamp = self.max_timestamp(&state, now_ms, timestamp);
+        let info = self.assign_offsets(&mut state, record_count, max_timestamp);
+        self.keep_batch(&mut state, info, now_ms, data);
+        info
+    }
+
+    /// Appends the record batches of one produce partition entry
+    ///
+    /// All batches are appended under one lock, so readers see either none
+    /// or all of them. Each batch's base offset is rewritten to the offset
+    /// assigned to it, and the next batch continues after its last offset.
+    /// With `log_append_time` every batch is stamped with the same append
+    /// time, rewritten into the stored batch as its max timestamp under the
+    /// LogAppendTime attribute. Returns the base offset of the first batch and the largest
+    /// timestamp, or `None` when there are no batches.
+    ///
+    /// A disk-backed log writes each batch to its active segment instead of
+    /// keeping it; if a write fails, the batches before it stay appended.
+    ///
+    /// Batches of an idempotent producer must continue its sequence. One
+    /// it already appended is not appended again: when it is the first, the
+    /// append reports the base offset it got then, with no timestamp. A
+    /// sequence gap or a stale epoch fails the append like a write does.
+    pub fn append_record_batches(
+        &self,
+        batches: &[RecordBatch],
+        log_append_time: bool,
+    ) -> StorageResult<Option<AppendInfo>> {
+        let now_ms = self.clock.now_ms();
+        let mut state = self.state.write().unwrap();
+        let mut producers = self.producers.lock().unwrap();
+        let mut appended: Option<AppendInfo> = None;
+        for batch in batches {
+            let idempotent = batch.producer_id() != NO_PRODUCER_ID;
+            if idempotent {
+                let check = producers.check_batch(
+                    batch.producer_id(),
+                    batch.producer_epoch(),
+                    batch.base_sequence(),
+                    batch.last_sequence(),
+                )?;
+                if let SequenceCheck::Duplicate { base_offset } = check {
+                    appended.get_or_insert(AppendInfo {
+                        base_offset,
+                        max_timestamp: NO_TIMESTAMP,
+                    });
+                    continue;
+                }
+            }
+            let (max_timestamp, data) = if log_append_time {
+                let append_time = self.max_timestamp(&state, now_ms, BatchTimestamp::LogAppendTime);
+                let data = batch.with_log_append_time(state.log_end_offset, append_time);
+                (append_time, data)
+            } else {
+                let data = batch.with_base_offset(state.log_end_offset);
+                (batch.max_timestamp(), data)
+            };
+            let last_offset = state.log_end_offset + batch.offset_count().max(1) - 1;
+            if let Some(segments) = &mut state.segments {
+                segments.append(&data, last_offset)?;
+            }
+            let info = self.assign_offsets(&mut state, batch.offset_count(), max_timestamp);
+            if state.segments.is_none() {
+                self.keep_batch(&mut state, info, now_ms, data);
+            }
+            if idempotent {
+                producers.record_batch(
+                    batch.producer_id(),
+                    batch.producer_epoch(),
+                    batch.base_sequence(),
+                    batch.last_sequence(),
+                    info.base_offset,
+                );
+            }
+            appended = Some(match appended {
+                Some(first) => AppendInfo {
+                    base_offset: first.base_offset,
+                    max_timestamp: first.max_timestamp.max(info.max_timestamp),
+                },
+                None => info,
+            });
+        }
+        Ok(appended)
+    }
+
+    /// The max timestamp a batch appended now is stored with
+    fn max_timestamp(&self, state: &LogState, now_ms: i64, timestamp: BatchTimestamp) -> i64 {
+        match timestamp {
+            BatchTimestamp::CreateTime(max_timestamp) => max_timestamp,
+            BatchTimestamp::LogAppendTime => {
+                let previous = state.previous_max_timestamp();
+                if now_ms < previous {
+                    warn!(
+                        topic = %self.topic,
+                        partition = self.partition,
+                        now_ms = now_ms,
+                        previous_max_timestamp = previous,
+                        "Wall clock went backwards, clamping log append time"
+                    );
+                }
+                now_ms.max(previous)
+            }
+        }
+    }
+
+    /// Moves the log end past a batch of `record_count` records, returning
+    /// its base offset and max timestamp
+    fn assign_offsets(
+        &self,
+        state: &mut LogState,
+        record_count: i64,
+        max_timestamp: i64,
+    ) -> AppendInfo {
+        let base_offset = state.log_end_offset;
+        state.log_end_offset = base_offset + record_count.max(1);
+        AppendInfo {
+            base_offset,
+            max_timestamp,
+        }
+    }
+
+    /// Keeps the batch `assign_offsets` just placed in memory
+    fn keep_batch(&self, state: &mut LogState, info: AppendInfo, now_ms: i64, data: Bytes) {
+        state.batches.push(StoredBatch {
+            base_offset: info.base_offset,
+            last_offset: state.log_end_offset - 1,
+            max_timestamp: info.max_timestamp,
+            append_time_ms: now_ms,
+            data,
+        });
+    }
+
+    /// Advances the high watermark, never past the log end offset
+    pub fn advance_high_watermark(&self, offset: i64) {
+        let offset = offset.min(self.log_end_offset());
+        if self.high_watermark.advance_to(offset) {
+            self.publish(BrokerEvent::HighWatermarkAdvanced {
+                topic: self.topic.clone(),
+                partition: self.partition,
+                high_watermark: offset,
+            });
+        }
+    }
+
+    /// Returns batches starting with the one containing `offset`
+    ///
+    /// Stops once `max_bytes` would be exceeded, but always returns at least
+    /// one batch when any is available so large batches can make progress.
+    /// Reading at the log end offset returns nothing.
+    ///
+    /// A disk-backed log reads its segment files through the segments it
+    /// keeps open, and a read stops at the end of a segment. The append
+    /// time of a batch read from disk is not known, so its max timestamp
+    /// stands in for it.
+    pub fn read_from(&self, offset: i64, max_bytes: usize) -> StorageResult<Vec<StoredBatch>> {
+        let state = self.state.read().unwrap();
+        if offset < state.log_start_offset || offset > state.log_end_offset {
+            return Err(state.out_of_range(offset));
+        }
+        if let Some(segments) = &state.segments {
+            return Ok(segments
+                .read_from(offset, max_bytes)?
+                .into_iter()
+                .map(|(batch, data)| StoredBatch {
+                    base_offset: batch.base_offset,
+                    last_offset: batch.last_offset,
+                    max_timestamp: batch.max_timestamp,
+                    append_time_ms: batch.max_timestamp,
+                    data,
+                })
+                .collect());
+        }
+
+        let mut batches = Vec::new();
+        let mut total_bytes = 0;
+        for batch in &state.batches[state.batch_index_for(offset)..] {
+            if !batches.is_empty() && total_bytes + batch.data.len() > max_bytes {
+                break;
+            }
+            total_bytes += batch.data.len();
+            batches.push(batch.clone());
+        }
+        Ok(batches)
+    }
+
+    /// The first record stamped at or after `timestamp`
+    ///
+    /// A disk-backed log looks the batch up through its segments' time
+    /// indexes, judging batches by the max timestamp they carry on disk;
+    /// any other log searches its batches in memory. Returns `None` when
+    /// every record is older.
+    pub fn offset_for_timestamp(&self, timestamp: i64) -> StorageResult<Option<TimestampOffset>> {
+        let state = self.state.read().unwrap();
+        if let Some(segments) = &state.segments {
+            return Ok(segments.find_by_timestamp(timestamp)?.map(|(batch, data)| {
+                first_record_at(&data, batch.base_offset, batch.max_timestamp, timestamp)
+            }));
+        }
+        Ok(state
+            .batches
+            .iter()
+            .find(|batch| batch.max_timestamp >= timestamp)
+            .map(|batch| {
+                first_record_at(
+                    &batch.data,
+                    batch.base_offset,
+                    batch.max_timestamp,
+                    timestamp,
+                )
+            }))
+    }
+
+    /// Removes every record at or after `offset`
+    ///
+    /// Batches are removed whole, so when `offset` falls inside a batch the
+    /// log end moves back to that batch's base offset. The high watermark is
+    /// clamped down to the new log end and a `LogTruncated` event is
+    /// published so fetchers positioned past it can be invalidated. Returns
+    /// the new log end offset.
+    ///
+    /// A disk-backed log truncates its segment files, deleting the segments
+    /// past the offset, so the removed batches do not come back on a read
+    /// or after a restart.
+    pub fn truncate_to(&self, offset: i64) -> StorageResult<i64> {
+        let mut state = self.state.write().unwrap();
+        if offset < state.log_start_offset {
+            return Err(state.out_of_range(offset));
+        }
+        if offset >= state.log_end_offset {
+            return Ok(state.log_end_offset);
+        }
+
+        let (new_log_end_offset, removed) = match &mut state.segments {
+            Some(segments) => {
+                let before = segments.batch_count();
+                let log_end_offset = segments.truncate_to(offset)?;
+                (log_end_offset, before - segments.batch_count())
+            }
+            None => {
+                let keep = state.batch_index_for(offset);
+                let new_log_end_offset = state
+                    .batches
+                    .get(keep)
+                    .map(|batch| batch.base_offset)
+                    .unwrap_or(state.log_end_offset);
+                let removed = state.batches.len() - keep;
+                state.batches.truncate(keep);
+                (new_log_end_offset, removed)
+            }
+        };
+        state.log_end_offset = new_log_end_offset.max(state.log_start_offset);
+        let log_end_offset = state.log_end_offset;
+
+        // Clamp while still holding the lock so no reader sees a watermark
+        // beyond the truncated log end
+        self.high_watermark.clamp_to(log_end_offset);
+        drop(state);
+
+        info!(
+            topic = %self.topic,
+            partition = self.partition,
+            requested_offset = offset,
+            log_end_offset = log_end_offset,
+            removed_batches = removed,
+            "Truncated partition log"
+        );
+        self.publish(BrokerEvent::LogTruncated {
+            topic: self.topic.clone(),
+            partition: self.partition,
+            log_end_offset,
+        });
+        Ok(log_end_offset)
+    }
+
+    /// Deletes batches older than `retention_ms` from the start of the log
+    ///
+    /// A batch's age is judged by its max timestamp. Timestamps more than
+    /// `max_future_ms` ahead of the batch's append time are not trusted, and
+    /// the append time is used instead, so records stamped far in the future
+    /// are not retained forever. Only whole batches below the high watermark
+    /// are removed. Returns the number of batches deleted.
+    ///
+    /// A disk-backed log deletes whole segments instead, oldest first, with
+    /// their indexes; the active segment stays. A segment that cannot be
+    /// deleted stops retention with a warning.
+    pub fn delete_expired_batches(&self, retention_ms: i64, max_future_ms: i64) -> usize {
+        if retention_ms < 0 {
+            return 0;
+        }
+        let now_ms = self.clock.now_ms();
+        let high_watermark = self.high_watermark.get();
+        let mut state = self.state.write().unwrap();
+
+        if let Some(segments) = &mut state.segments {
+            let deleted = segments
+                .delete_expired_segments(now_ms, retention_ms, max_future_ms, high_watermark)
+                .unwrap_or_else(|e| {
+                    warn!(
+                        topic = %self.topic,
+                        partition = self.partition,
+                        error = %e,
+                        "Failed to delete expired segments"
+                    );
+                    0
+                });
+            if deleted > 0 {
+                state.log_start_offset = segments.log_start_offset();
+                info!(
+                    topic = %self.topic,
+                    partition = self.partition,
+                    deleted_batches = deleted,
+                    log_start_offset = state.log_start_offset,
+                    "Deleted expired segments"
+                );
+            }
+            return deleted;
+        }
+
+        let expired = state
+            .batches
+            .iter()
+            .take_while(|batch| {
+                let timestamp =
+                    if batch.max_timestamp > batch.append_time_ms.saturating_add(max_future_ms) {
+                        batch.append_time_ms
+                    } else {
+                        batch.max_timestamp
+                    };
+                batch.last_offset < high_watermark
+                    && now_ms.saturating_sub(timestamp) > retention_ms
+            })
+            .count();
+        if expired == 0 {
+            return 0;
+        }
+
+        state.batches.drain(..expired);
+        state.log_start_offset = state
+            .batches
+            .first()
+            .map_or(state.log_end_offset, |batch| batch.base_offset);
+        info!(
+            topic = %self.topic,
+            partition = self.partition,
+            deleted_batches = expired,
+            log_start_offset = state.log_start_offset,
+            "Deleted expired batches"
+        );
+        expired
+    }
+
+    fn publish(&self, event: BrokerEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event);
+        }
+    }
+}
+
+/// The first record of the stored batch `data` stamped at or after
+/// `timestamp`
+///
+/// Falls back to the batch's base offset and max timestamp when its records
+/// cannot be read or none of them qualifies, as when the broker stamped the
+/// batch with its append time.
+fn first_record_at(
+    data: &Bytes,
+    base_offset: i64,
+    max_timestamp: i64,
+    timestamp: i64,
+) -> TimestampOffset {
+    split_record_batches(data)
+        .ok()
+        .and_then(|batches| batches.into_iter().next())
+        .and_then(|batch| batch.records().ok())
+        .and_then(|records| {
+            records
+                .into_iter()
+                .find(|record| record.timestamp >= timestamp)
+        })
+        .map_or(
+            TimestampOffset {
+                timestamp: max_timestamp,
+                offset: base_offset,
+            },
+            |record| TimestampOffset {
+                timestamp: record.timestamp,
+                offset: record.offset,
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::clock::MockClock;
+    use crate::kafka::storage::log_segment::{segment_file_name, LogConfig};
+    use crate::protocol::record_batch::{encode_test_batch, split_record_batches};
+
+    /// Builds a log with batches of 3, 2 and 4 records (offsets 0-2, 3-4, 5-8)
+    fn log_with_batches() -> PartitionLog {
+        let log = PartitionLog::new("orders", 0);
+        log.append(Bytes::from_static(b"aaa"), 3);
+        log.append(Bytes::from_static(b"bb"), 2);
+        log.append(Bytes::from_static(b"cccc"), 4);
+        log.advance_high_watermark(log.log_end_offset());
+        log
+    }
+
+    #[test]
+    fn test_append_assigns_offsets() {
+        let log = log_with_batches();
+        assert_eq!(log.log_end_offset(), 9);
+        assert_eq!(log.high_watermark(), 9);
+
+        let batches = log.read_from(4, 1024).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].base_offset, 3);
+        assert_eq!(batches[1].base_offset, 5);
+    }
+
+    #[test]
+    fn test_read_respects_max_bytes_but_returns_one_batch() {
+        let log = log_with_batches();
+        assert_eq!(log.read_from(0, 1).unwrap().len(), 1);
+        assert_eq!(log.read_from(0, 5).unwrap().len(), 2);
+        assert!(log.read_from(9, 1024).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_truncate_at_batch_boundary() {
+        let log = log_with_batches();
+
+        assert_eq!(log.truncate_to(5), Ok(5));
+        assert_eq!(log.log_end_offset(), 5);
+        assert_eq!(log.high_watermark(), 5);
+        assert_eq!(log.read_from(0, 1024).unwrap().len(), 2);
+
+        // Appends continue from the new log end
+        assert_eq!(log.append(Bytes::from_static(b"d"), 1), 5);
+    }
+
+    #[test]
+    fn test_truncate_mid_batch_removes_whole_batch() {
+        let log = log_with_batches();
+
+        assert_eq!(log.truncate_to(4), Ok(3));
+        assert_eq!(log.log_end_offset(), 3);
+        assert_eq!(log.high_watermark(), 3);
+    }
+
+    #[test]
+    fn test_reads_past_truncated_end_are_out_of_range() {
+        let log = log_with_batches();
+        log.truncate_to(5).unwrap();
+
+        assert_eq!(
+            log.read_from(7, 1024),
+            Err(StorageError::OffsetOutOfRange {
+                offset: 7,
+                log_start_offset: 0,
+                log_end_offset: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_truncate_beyond_end_is_noop() {
+        let log = log_with_batches();
+        assert_eq!(log.truncate_to(20), Ok(9));
+        assert_eq!(log.high_watermark(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_truncation_publishes_event() {
+        let events = EventBus::default();
+        let mut subscriber = events.subscribe("test");
+        let log = PartitionLog::new("orders", 2).with_events(events);
+        log.append(Bytes::from_static(b"aa"), 2);
+        log.advance_high_watermark(2);
+
+        log.truncate_to(0).unwrap();
+
+        assert_eq!(
+            subscriber.recv().await,
+            Some(BrokerEvent::HighWatermarkAdvanced {
+                topic: "orders".to_string(),
+                partition: 2,
+                high_watermark: 2,
+            })
+        );
+        assert_eq!(
+            subscriber.recv().await,
+            Some(BrokerEvent::LogTruncated {
+                topic: "orders".to_string(),
+                partition: 2,
+                log_end_offset: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_concurrent_reads_never_see_half_truncated_log() {
+        let log = Arc::new(PartitionLog::new("orders", 0));
+        for _ in 0..1000 {
+            log.append(Bytes::from_static(b"x"), 1);
+        }
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let log = log.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        if let Ok(batches) = log.read_from(0, usize::MAX) {
+                            // Offsets are contiguous from the start
+                            for (i, batch) in batches.iter().enumerate() {
+                                assert_eq!(batch.base_offset, i as i64);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for offset in (0..1000).rev().step_by(50) {
+            log.truncate_to(offset).unwrap();
+        }
+        log.truncate_to(0).unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(log.log_end_offset(), 0);
+    }
+
+    #[test]
+    fn test_log_append_time_never_regresses() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let log = PartitionLog::new("orders", 0).with_clock(clock.clone());
+
+        let mut timestamps = Vec::new();
+        for step in [10, 10, -5_000, 10, 6_000] {
+            if step < 0 {
+                // NTP steps the wall clock back mid-stream
+                clock.set_ms(clock.now_ms() + step);
+            } else {
+                clock.advance_ms(step);
+            }
+            let appended =
+                log.append_batch(Bytes::from_static(b"x"), 1, BatchTimestamp::LogAppendTime);
+            timestamps.push(appended.max_timestamp);
+        }
+
+        assert!(timestamps.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(timestamps[2], timestamps[1]);
+        assert_eq!(timestamps[4], clock.now_ms());
+    }
+
+    #[test]
+    fn test_retention_deletes_old_prefix() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let log = PartitionLog::new("orders", 0).with_clock(clock.clone());
+        log.append(Bytes::from_static(b"old"), 2);
+        clock.advance_ms(5_000);
+        log.append(Bytes::from_static(b"new"), 1);
+        log.advance_high_watermark(log.log_end_offset());
+
+        clock.advance_ms(1_000);
+        assert_eq!(log.delete_expired_batches(3_000, 60_000), 1);
+        assert_eq!(log.log_start_offset(), 2);
+        assert!(log.read_from(0, 1024).is_err());
+
+        // A wall clock stepped far back expires nothing and still returns
+        clock.set_ms(0);
+        assert_eq!(log.delete_expired_batches(3_000, 60_000), 0);
+        assert_eq!(log.log_start_offset(), 2);
+    }
+
+    #[test]
+    fn test_retention_bounds_future_timestamps() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let log = PartitionLog::new("orders", 0).with_clock(clock.clone());
+        let far_future = clock.now_ms() + 365 * 24 * 3_600_000;
+        log.append_batch(
+            Bytes::from_static(b"x"),
+            1,
+            BatchTimestamp::CreateTime(far_future),
+        );
+        let near_future = clock.now_ms() + 500;
+        log.append_batch(
+            Bytes::from_static(b"y"),
+            1,
+            BatchTimestamp::CreateTime(near_future),
+        );
+        log.advance_high_watermark(log.log_end_offset());
+
+        // Past retention of the append time, the far-future batch is judged
+        // by when it was appended; the near-future one by its own timestamp
+        clock.advance_ms(3_100);
+        assert_eq!(log.delete_expired_batches(3_000, 60_000), 1);
+        clock.advance_ms(500);
+        assert_eq!(log.delete_expired_batches(3_000, 60_000), 1);
+        assert_eq!(log.log_start_offset(), 2);
+    }
+
+    #[test]
+    fn test_retention_keeps_data_above_high_watermark() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let log = PartitionLog::new("orders", 0).with_clock(clock.clone());
+        log.append(Bytes::from_static(b"x"), 1);
+
+        clock.advance_ms(10_000);
+        assert_eq!(log.delete_expired_batches(1_000, 60_000), 0);
+    }
+
+    #[test]
+    fn test_multi_batch_append_assigns_consecutive_offsets() {
+        let clock = Arc::new(MockClock::new(5_000));
+        let log = PartitionLog::new("orders", 0).with_clock(clock);
+        log.append(Bytes::from_static(b"earlier"), 2);
+
+        let payload: Bytes = [
+            encode_test_batch(1, 100),
+            encode_test_batch(4, 300),
+            encode_test_batch(2, 200),
+        ]
+        .concat()
+        .into();
+        let batches = split_record_batches(&payload).unwrap();
+        let info = log.append_record_batches(&batches, false).unwrap().unwrap();
+        assert_eq!(info.base_offset, 2);
+        assert_eq!(info.max_timestamp, 300);
+
+        // Fetch back: each stored batch carries its own rewritten base offset
+        let stored = log.read_from(2, usize::MAX).unwrap();
+        let offsets: Vec<(i64, i64, i64)> = stored
+            .iter()
+            .map(|batch| {
+                let reparsed = split_record_batches(&batch.data).unwrap().remove(0);
+                (batch.base_offset, batch.last_offset, reparsed.base_offset())
+            })
+            .collect();
+        assert_eq!(offsets, vec![(2, 2, 2), (3, 6, 3), (7, 8, 7)]);
+
+        // The next produce continues after the cumulative record count
+        let next = split_record_batches(&encode_test_batch(1, 0)).unwrap();
+        assert_eq!(
+            log.append_record_batches(&next, false)
+                .unwrap()
+                .unwrap()
+                .base_offset,
+            9
+        );
+        assert_eq!(log.append_record_batches(&[], false), Ok(None));
+    }
+
+    /// Opens a disk-backed log in `dir` whose segments hold two batches of
+    /// two records, with five such batches appended and committed
+    fn disk_log_with_batches(dir: &std::path::Path, clock: Arc<MockClock>) -> PartitionLog {
+        let config = LogConfig {
+            segment_bytes: encode_test_batch(2, 0).len() as u64 * 2,
+            ..LogConfig::default()
+        };
+        let log = PartitionLog::new("orders", 0)
+            .with_clock(clock)
+            .with_segments(Log::open(dir, config, Arc::default(), "orders", 0).unwrap());
+        for _ in 0..5 {
+            let batch = split_record_batches(&encode_test_batch(2, 1_000)).unwrap();
+            log.append_record_batches(&batch, false).unwrap();
+        }
+        log.advance_high_watermark(log.log_end_offset());
+        log
+    }
+
+    #[test]
+    fn test_disk_backed_log_keeps_no_batches_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = disk_log_with_batches(dir.path(), Arc::new(MockClock::new(1_000_000)));
+
+        assert!(log.state.read().unwrap().batches.is_empty());
+        let snapshot = log.snapshot();
+        assert_eq!(snapshot.batch_count, 5);
+        assert_eq!(snapshot.size_bytes, encode_test_batch(2, 0).len() * 5);
+        let read: Vec<_> = log
+            .read_from(3, usize::MAX)
+            .unwrap()
+            .iter()
+            .map(|batch| batch.base_offset)
+            .collect();
+        // Reads stop at the end of the segment holding the offset
+        assert_eq!(read, vec![2]);
+    }
+
+    #[test]
+    fn test_disk_backed_truncation_cuts_segment_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = disk_log_with_batches(dir.path(), Arc::new(MockClock::new(1_000_000)));
+
+        // Offset 5 sits mid-batch in the second segment; the third goes
+        assert_eq!(log.truncate_to(5), Ok(4));
+        assert_eq!(log.high_watermark(), 4);
+        assert_eq!(log.snapshot().batch_count, 2);
+        assert!(!dir.path().join(segment_file_name(8)).exists());
+        assert!(log.read_from(4, usize::MAX).unwrap().is_empty());
+        assert!(log.read_from(6, usize::MAX).is_err());
+
+        // The appends after the truncation continue from it, and only they
+        // come back after a restart
+        let batch = split_record_batches(&encode_test_batch(1, 1_000)).unwrap();
+        let info = log.append_record_batches(&batch, false).unwrap().unwrap();
+        assert_eq!(info.base_offset, 4);
+        drop(log);
+        let reopened = Log::open(
+            dir.path(),
+            LogConfig::default(),
+            Arc::default(),
+            "orders",
+            0,
+        )
+        .unwrap();
+        assert_eq!(reopened.log_end_offset(), 5);
+        assert_eq!(reopened.batch_count(), 3);
+    }
+
+    #[test]
+    fn test_disk_backed_retention_deletes_segment_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let log = disk_log_with_batches(dir.path(), clock.clone());
+
+        // Every batch is long expired, but the active segment stays
+        assert_eq!(log.delete_expired_batches(3_000, 60_000), 4);
+        assert_eq!(log.log_start_offset(), 8);
+        assert_eq!(log.snapshot().batch_count, 1);
+        for base_offset in [0, 4] {
+            let log_file = dir.path().join(segment_file_name(base_offset));
+            assert!(!log_file.exists());
+            assert!(!log_file.with_extension("index").exists());
+            assert!(!log_file.with_extension("timeindex").exists());
+        }
+        assert!(log.read_from(0, usize::MAX).is_err());
+        assert_eq!(log.read_from(8, usize::MAX).unwrap().len(), 1);
+        assert_eq!(log.delete_expired_batches(3_000, 60_000), 0);
+    }
+
+    #[test]
+    fn test_multi_batch_append_shares_log_append_time() {
+        let clock = Arc::new(MockClock::new(7_000));
+        let log = PartitionLog::new("orders", 0).with_clock(clock);
+        let payload: Bytes = [encode_test_batch(1, 100), encode_test_batch(1, 99_999)]
+            .concat()
+            .into();
+        let batches = split_record_batches(&payload).unwrap();
+
+        let info = log.append_record_batches(&batches, true).unwrap().unwrap();
+        assert_eq!(info.max_timestamp, 7_000);
+        let stored = log.read_from(0, usize::MAX).unwrap();
+        assert!(stored.iter().all(|batch| batch.max_timestamp == 7_000));
+
+        // The stored batches carry the append time, under a valid CRC
+        for batch in &stored {
+            let reparsed = split_record_batches(&batch.data).unwrap().remove(0);
+            assert!(reparsed.is_log_append_time());
+            assert_eq!(reparsed.max_timestamp(), 7_000);
+            assert_eq!(reparsed.base_offset(), batch.base_offset);
+        }
+    }
+}
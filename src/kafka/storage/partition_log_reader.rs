@@ -0,0 +1,485 @@
+use crate::kafka::storage::log_segment::segment_base_offset;
+use crate::kafka::storage::offset_index::OffsetIndex;
+use crate::kafka::storage::{StorageError, StorageResult};
+use crate::logging::warn;
+use bytes::Bytes;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+
+/// Bytes of a v2 batch header up to and including the max timestamp:
+/// base offset, length, leader epoch, magic, crc, attributes, last offset
+/// delta and base timestamp come first
+pub(crate) const HEADER_PREFIX: usize = 8 + 4 + 4 + 1 + 4 + 2 + 4 + 8 + 8;
+
+/// Where the last offset delta sits in a batch
+const LAST_OFFSET_DELTA_POSITION: usize = 8 + 4 + 4 + 1 + 4 + 2;
+
+/// Where the max timestamp sits in a batch
+const MAX_TIMESTAMP_POSITION: usize = HEADER_PREFIX - 8;
+
+/// Where one record batch sits in a segment file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchPosition {
+    pub base_offset: i64,
+    pub last_offset: i64,
+    /// Largest record timestamp in the batch
+    pub max_timestamp: i64,
+    /// Byte position of the batch in the file
+    pub position: u64,
+    /// Size of the batch, its base offset and length fields included
+    pub size: u64,
+}
+
+impl BatchPosition {
+    /// Reads the batch whose header starts at `position`
+    ///
+    /// Returns `None` for a batch cut short by the end of the file at
+    /// `file_len`, or whose length cannot even cover its header.
+    pub(crate) fn from_header(
+        header: &[u8; HEADER_PREFIX],
+        position: u64,
+        file_len: u64,
+    ) -> Option<Self> {
+        let base_offset = i64::from_be_bytes(header[..8].try_into().unwrap());
+        let batch_length = i32::from_be_bytes(header[8..12].try_into().unwrap());
+        let last_offset_delta = i32::from_be_bytes(
+            header[LAST_OFFSET_DELTA_POSITION..LAST_OFFSET_DELTA_POSITION + 4]
+                .try_into()
+                .unwrap(),
+        );
+        let max_timestamp =
+            i64::from_be_bytes(header[MAX_TIMESTAMP_POSITION..].try_into().unwrap());
+        let size = 12 + batch_length as u64;
+        if batch_length < (HEADER_PREFIX - 12) as i32 || position + size > file_len {
+            return None;
+        }
+        Some(Self {
+            base_offset,
+            last_offset: base_offset + last_offset_delta as i64,
+            max_timestamp,
+            position,
+            size,
+        })
+    }
+}
+
+/// One segment file with its offset index, as found when opened
+#[derive(Debug)]
+struct ReaderSegment {
+    path: PathBuf,
+    base_offset: i64,
+    index: OffsetIndex,
+    /// Length of the file when opened; batches appended since are not read
+    len: u64,
+}
+
+/// Raw record batches of a partition's segment files
+///
+/// Opening reads each segment's offset index; a read binary-searches the
+/// index for the closest preceding batch and scans batch headers forward
+/// from there, then copies whole batches out of the file exactly as they
+/// are stored, without decoding their records.
+#[derive(Debug)]
+pub struct PartitionLogReader {
+    /// In base offset order, empty segments left out
+    segments: Vec<ReaderSegment>,
+    log_start_offset: i64,
+    log_end_offset: i64,
+}
+
+impl PartitionLogReader {
+    /// Opens the segment file at `path`
+    ///
+    /// The file's offset index is used if it has one next to it. A batch
+    /// cut short at the end of the file, as a crash mid-write leaves it,
+    /// ends the log.
+    pub async fn open(path: &Path) -> StorageResult<Self> {
+        let base_offset = segment_base_offset(path).unwrap_or(0);
+        let segment = open_segment(path, base_offset).await?;
+        Self::from_segments(vec![segment]).await
+    }
+
+    /// Opens every `.log` segment of the partition directory `dir`
+    pub async fn open_dir(dir: &Path) -> StorageResult<Self> {
+        let io_error = |e: io::Error| StorageError::Io {
+            path: dir.to_path_buf(),
+            message: e.to_string(),
+        };
+        let mut paths = Vec::new();
+        let mut entries = tokio::fs::read_dir(dir).await.map_err(io_error)?;
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let path = entry.path();
+            if let Some(base_offset) = segment_base_offset(&path) {
+                paths.push((base_offset, path));
+            }
+        }
+        paths.sort();
+
+        let mut segments = Vec::with_capacity(paths.len());
+        for (base_offset, path) in paths {
+            segments.push(open_segment(&path, base_offset).await?);
+        }
+        Self::from_segments(segments).await
+    }
+
+    async fn from_segments(mut segments: Vec<ReaderSegment>) -> StorageResult<Self> {
+        segments.retain(|segment| segment.len > 0);
+        let mut log_start_offset = None;
+        for segment in &segments {
+            let mut file = open_file(&segment.path).await?;
+            if let Some(batch) = read_header(&mut file, segment, 0).await? {
+                log_start_offset = Some(batch.base_offset);
+                break;
+            }
+        }
+        let mut log_end_offset = None;
+        for segment in segments.iter().rev() {
+            let mut file = open_file(&segment.path).await?;
+            let mut position = segment.index.last_position();
+            let mut last = None;
+            while let Some(batch) = read_header(&mut file, segment, position).await? {
+                position += batch.size;
+                last = Some(batch);
+            }
+            if let Some(last) = last {
+                log_end_offset = Some(last.last_offset + 1);
+                break;
+            }
+        }
+        Ok(Self {
+            segments,
+            log_start_offset: log_start_offset.unwrap_or(0),
+            log_end_offset: log_end_offset.unwrap_or(0),
+        })
+    }
+
+    /// The batches of every segment, in offset order
+    ///
+    /// Scans every segment from its start, so it is meant for inspection
+    /// rather than serving reads.
+    pub async fn batches(&self) -> StorageResult<Vec<BatchPosition>> {
+        let mut batches = Vec::new();
+        for segment in &self.segments {
+            let mut file = open_file(&segment.path).await?;
+            let mut position = 0;
+            while let Some(batch) = read_header(&mut file, segment, position).await? {
+                position += batch.size;
+                batches.push(batch);
+            }
+        }
+        Ok(batches)
+    }
+
+    /// Base offset of the first batch, zero for an empty log
+    pub fn log_start_offset(&self) -> i64 {
+        self.log_start_offset
+    }
+
+    /// Offset after the last batch, zero for an empty log
+    pub fn log_end_offset(&self) -> i64 {
+        self.log_end_offset
+    }
+
+    /// Returns the batches from the one containing `offset` as stored
+    ///
+    /// Stops once `max_bytes` would be exceeded, but always returns at least
+    /// one batch when any is available, as the in-memory log does. A read
+    /// never crosses into the next segment. An offset before the first batch
+    /// is out of range; one at or past the end reads nothing.
+    pub async fn read_from(&self, offset: i64, max_bytes: usize) -> StorageResult<Bytes> {
+        if offset < self.log_start_offset {
+            return Err(StorageError::OffsetOutOfRange {
+                offset,
+                log_start_offset: self.log_start_offset,
+                log_end_offset: self.log_end_offset,
+            });
+        }
+        if offset >= self.log_end_offset {
+            return Ok(Bytes::new());
+        }
+        let Some((segment, start)) = self.find_batch(offset).await? else {
+            return Ok(Bytes::new());
+        };
+
+        let mut file = open_file(&segment.path).await?;
+        let mut length = start.size;
+        let mut position = start.position + start.size;
+        while let Some(batch) = read_header(&mut file, segment, position).await? {
+            if length + batch.size > max_bytes as u64 {
+                break;
+            }
+            length += batch.size;
+            position += batch.size;
+        }
+
+        let io_error = |e: io::Error| StorageError::Io {
+            path: segment.path.clone(),
+            message: e.to_string(),
+        };
+        file.seek(SeekFrom::Start(start.position))
+            .await
+            .map_err(io_error)?;
+        let mut data = vec![0; length as usize];
+        file.read_exact(&mut data).await.map_err(io_error)?;
+        Ok(Bytes::from(data))
+    }
+
+    /// Segment and position to scan from for the batch holding `offset`
+    fn scan_start(&self, offset: i64) -> (usize, u64) {
+        let segment = self
+            .segments
+            .partition_point(|segment| segment.base_offset <= offset)
+            .saturating_sub(1);
+        (segment, self.segments[segment].index.lookup(offset))
+    }
+
+    /// Finds the first batch ending at or after `offset`
+    async fn find_batch(
+        &self,
+        offset: i64,
+    ) -> StorageResult<Option<(&ReaderSegment, BatchPosition)>> {
+        let (first, mut position) = self.scan_start(offset);
+        for segment in &self.segments[first..] {
+            let mut file = open_file(&segment.path).await?;
+            while let Some(batch) = read_header(&mut file, segment, position).await? {
+                if batch.last_offset >= offset {
+                    return Ok(Some((segment, batch)));
+                }
+                position += batch.size;
+            }
+            // The offset falls past this segment's last batch
+            position = 0;
+        }
+        Ok(None)
+    }
+}
+
+fn storage_io_error(path: &Path) -> impl Fn(io::Error) -> StorageError + '_ {
+    move |e| StorageError::Io {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    }
+}
+
+async fn open_file(path: &Path) -> StorageResult<File> {
+    File::open(path).await.map_err(storage_io_error(path))
+}
+
+/// Notes the length of a segment file and reads its offset index
+///
+/// A missing or corrupt index is ignored, and the segment is then scanned
+/// from its start.
+async fn open_segment(path: &Path, base_offset: i64) -> StorageResult<ReaderSegment> {
+    // The index first: the file only ever grows past what it points to
+    let index_path = path.with_extension("index");
+    let mut index = match tokio::fs::read(&index_path).await {
+        Ok(data) => OffsetIndex::decode(base_offset, &data).unwrap_or_else(|| {
+            warn!(path = %index_path.display(), "Ignoring corrupt offset index");
+            OffsetIndex::new(base_offset)
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => OffsetIndex::new(base_offset),
+        Err(e) => return Err(storage_io_error(&index_path)(e)),
+    };
+    let len = tokio::fs::metadata(path)
+        .await
+        .map_err(storage_io_error(path))?
+        .len();
+    index.truncate_to_position(len);
+    Ok(ReaderSegment {
+        path: path.to_path_buf(),
+        base_offset,
+        index,
+        len,
+    })
+}
+
+/// Reads the header of the batch at `position`, `None` past the last
+/// complete batch
+async fn read_header(
+    file: &mut File,
+    segment: &ReaderSegment,
+    position: u64,
+) -> StorageResult<Option<BatchPosition>> {
+    if position + HEADER_PREFIX as u64 > segment.len {
+        return Ok(None);
+    }
+    let io_error = storage_io_error(&segment.path);
+    file.seek(SeekFrom::Start(position))
+        .await
+        .map_err(&io_error)?;
+    let mut header = [0; HEADER_PREFIX];
+    file.read_exact(&mut header).await.map_err(&io_error)?;
+    let batch = BatchPosition::from_header(&header, position, segment.len);
+    if batch.is_none() {
+        warn!(
+            path = %segment.path.display(),
+            position = position,
+            "Ignoring incomplete batch at the end of the segment"
+        );
+    }
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::storage::log_segment::{Log, LogConfig};
+    use crate::kafka::storage::metadata_log::encode_batch;
+    use crate::protocol::record_batch::{encode_test_batch, split_record_batches};
+    use std::sync::Arc;
+
+    /// The checked-in metadata log: batches at offsets 0, 2 and 4 holding
+    /// two, two and three records
+    const FIXTURE_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/kafka/testdata/cluster_metadata.log"
+    );
+
+    fn fixture() -> Vec<u8> {
+        std::fs::read(FIXTURE_PATH).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_indexes_batch_headers() {
+        let reader = PartitionLogReader::open(Path::new(FIXTURE_PATH))
+            .await
+            .unwrap();
+        let batches = reader.batches().await.unwrap();
+        let offsets: Vec<_> = batches
+            .iter()
+            .map(|batch| (batch.base_offset, batch.last_offset))
+            .collect();
+        assert_eq!(offsets, vec![(0, 1), (2, 3), (4, 6)]);
+        assert_eq!(reader.log_end_offset(), 7);
+        let last = batches[2];
+        assert_eq!(last.position + last.size, fixture().len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_reads_batches_as_stored() {
+        let reader = PartitionLogReader::open(Path::new(FIXTURE_PATH))
+            .await
+            .unwrap();
+        let data = fixture();
+        let batches = reader.batches().await.unwrap();
+        assert_eq!(reader.read_from(0, usize::MAX).await.unwrap(), data);
+
+        // From inside the second batch, the whole batch comes back
+        let second = batches[1].position as usize;
+        assert_eq!(
+            reader.read_from(3, usize::MAX).await.unwrap(),
+            data[second..]
+        );
+
+        // A limit smaller than one batch still returns that batch
+        let third = batches[2];
+        assert_eq!(
+            reader.read_from(2, 1).await.unwrap(),
+            data[second..third.position as usize]
+        );
+        assert!(reader.read_from(7, usize::MAX).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_offset_before_first_batch_is_out_of_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("00000000000000000000.log");
+        std::fs::write(&path, encode_batch(5, 0, &[]).unwrap()).unwrap();
+
+        let reader = PartitionLogReader::open(&path).await.unwrap();
+        assert!(matches!(
+            reader.read_from(4, usize::MAX).await,
+            Err(StorageError::OffsetOutOfRange {
+                log_start_offset: 5,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_torn_batch_ends_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("00000000000000000000.log");
+        let data = fixture();
+        std::fs::write(&path, &data[..data.len() - 1]).unwrap();
+
+        let reader = PartitionLogReader::open(&path).await.unwrap();
+        assert_eq!(reader.batches().await.unwrap().len(), 2);
+        assert_eq!(reader.log_end_offset(), 4);
+    }
+
+    /// Writes `count` one-record batches through a [`Log`] into `dir`
+    fn write_log(dir: &Path, count: i64, config: LogConfig) -> u64 {
+        let batch = split_record_batches(&encode_test_batch(1, 0))
+            .unwrap()
+            .remove(0);
+        let mut log = Log::open(dir, config, Arc::default(), "orders", 0).unwrap();
+        for offset in 0..count {
+            log.append(&batch.with_base_offset(offset), offset).unwrap();
+        }
+        batch.with_base_offset(0).len() as u64
+    }
+
+    #[tokio::test]
+    async fn test_index_positions_reads_in_a_large_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LogConfig::default();
+        let batch_size = write_log(dir.path(), 5000, config);
+
+        let reader = PartitionLogReader::open_dir(dir.path()).await.unwrap();
+        assert_eq!(reader.log_end_offset(), 5000);
+        assert!(reader.segments[0].index.entries().len() > 50);
+        for offset in [0, 1, 999, 2500, 4321, 4999] {
+            // The scan starts at most one index interval before the batch
+            let (segment, position) = reader.scan_start(offset);
+            assert_eq!(segment, 0);
+            let batch_position = offset as u64 * batch_size;
+            assert!(position <= batch_position);
+            assert!(
+                batch_position - position <= config.index_interval_bytes + batch_size,
+                "offset {} scans from {} for a batch at {}",
+                offset,
+                position,
+                batch_position
+            );
+
+            let records = reader.read_from(offset, 1).await.unwrap();
+            let batches = split_record_batches(&records).unwrap();
+            assert_eq!(batches.len(), 1);
+            assert_eq!(batches[0].base_offset(), offset);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_index_is_rebuilt_on_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LogConfig {
+            index_interval_bytes: 256,
+            ..LogConfig::default()
+        };
+        write_log(dir.path(), 200, config);
+        let index_path = dir.path().join("00000000000000000000.index");
+        let intact = std::fs::read(&index_path).unwrap();
+        assert!(!intact.is_empty());
+
+        // A torn last entry leaves a size that is not a multiple of eight
+        std::fs::write(&index_path, &intact[..intact.len() - 3]).unwrap();
+        let reader = PartitionLogReader::open_dir(dir.path()).await.unwrap();
+        assert!(reader.segments[0].index.entries().is_empty());
+        let records = reader.read_from(150, 1).await.unwrap();
+        assert_eq!(
+            split_record_batches(&records).unwrap()[0].base_offset(),
+            150
+        );
+
+        let log = Log::open(dir.path(), config, Arc::default(), "orders", 0).unwrap();
+        assert_eq!(log.log_end_offset(), 200);
+        assert_eq!(std::fs::read(&index_path).unwrap(), intact);
+
+        std::fs::remove_file(&index_path).unwrap();
+        Log::open(dir.path(), config, Arc::default(), "orders", 0).unwrap();
+        assert_eq!(std::fs::read(&index_path).unwrap(), intact);
+    }
+}
@@ -0,0 +1,409 @@
+use crate::kafka::config::{broker_property, ConfigError};
+use crate::logging::debug;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Errors raised while accessing segment files
+#[derive(Error, Debug)]
+pub enum SegmentHandleError {
+    #[error("Unknown segment {0:?}")]
+    UnknownSegment(SegmentId),
+
+    #[error("Segment {path} is {actual} bytes but its index expects {expected}; it was truncated outside the broker")]
+    Truncated {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("All {budget} open segment handles are pinned")]
+    BudgetExhausted { budget: usize },
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Identifies one segment of a partition log by its base offset
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SegmentId {
+    pub topic: String,
+    pub partition: i32,
+    pub base_offset: i64,
+}
+
+/// Counters describing how the open-file budget is used
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SegmentHandleMetrics {
+    pub budget: usize,
+    pub open_handles: usize,
+    pub pinned_handles: usize,
+    /// Every file open, first opens included
+    pub opens: u64,
+    /// Opens of segments that had been closed to stay within budget
+    pub reopens: u64,
+    /// Handles closed to make room for another segment
+    pub evictions: u64,
+}
+
+impl SegmentHandleMetrics {
+    /// Share of the budget in use
+    pub fn pressure_percent(&self) -> u64 {
+        (self.open_handles * 100 / self.budget.max(1)) as u64
+    }
+}
+
+#[derive(Debug)]
+struct Segment {
+    path: PathBuf,
+    /// Length the index says the file has
+    expected_len: u64,
+    file: Option<File>,
+    pinned: bool,
+    last_used: u64,
+    evicted: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    segments: HashMap<SegmentId, Segment>,
+    /// Open, unpinned segments by last use, oldest first
+    lru: BTreeMap<u64, SegmentId>,
+    tick: u64,
+    open_handles: usize,
+    pinned_handles: usize,
+    opens: u64,
+    reopens: u64,
+    evictions: u64,
+}
+
+/// Bounded set of open segment files shared by every partition
+///
+/// Segments are registered with their path and the length their index
+/// expects. Reads open the file on demand; once `budget` handles are open
+/// the least recently used unpinned segment is closed, and it is reopened
+/// transparently the next time it is read. The active segment of each
+/// partition should be pinned so appends never pay for a reopen. Every
+/// open checks the file length against the index so a segment truncated
+/// outside the broker is reported instead of served short.
+#[derive(Debug)]
+pub struct SegmentHandleCache {
+    budget: usize,
+    state: Mutex<State>,
+}
+
+impl Default for SegmentHandleCache {
+    fn default() -> Self {
+        Self::from_properties(&[]).expect("broker config defaults are valid")
+    }
+}
+
+impl SegmentHandleCache {
+    /// Creates a cache allowing at most `budget` open files
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget: budget.max(1),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Builds the cache from `log.segment.open.files.max`
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let name = "log.segment.open.files.max";
+        let value = broker_property(properties, name)
+            .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))?;
+        let budget = value
+            .parse::<usize>()
+            .ok()
+            .filter(|&budget| budget > 0)
+            .ok_or_else(|| ConfigError::InvalidValue {
+                key: name.to_string(),
+                value: value.to_string(),
+            })?;
+        Ok(Self::new(budget))
+    }
+
+    /// Registers a segment file without opening it
+    pub fn register(&self, id: SegmentId, path: impl AsRef<Path>, expected_len: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.segments.insert(
+            id,
+            Segment {
+                path: path.as_ref().to_path_buf(),
+                expected_len,
+                file: None,
+                pinned: false,
+                last_used: 0,
+                evicted: false,
+            },
+        );
+    }
+
+    /// Records the length the index now expects, e.g. after an append
+    pub fn set_expected_len(
+        &self,
+        id: &SegmentId,
+        expected_len: u64,
+    ) -> Result<(), SegmentHandleError> {
+        let mut state = self.state.lock().unwrap();
+        let segment = state
+            .segments
+            .get_mut(id)
+            .ok_or_else(|| SegmentHandleError::UnknownSegment(id.clone()))?;
+        segment.expected_len = expected_len;
+        Ok(())
+    }
+
+    /// Closes and forgets a segment, e.g. once retention deleted it
+    pub fn remove(&self, id: &SegmentId) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(segment) = state.segments.remove(id) {
+            if segment.file.is_some() {
+                state.open_handles -= 1;
+                if segment.pinned {
+                    state.pinned_handles -= 1;
+                } else {
+                    state.lru.remove(&segment.last_used);
+                }
+            }
+        }
+    }
+
+    /// Opens a segment and keeps it open until [`unpin`](Self::unpin)
+    pub fn pin(&self, id: &SegmentId) -> Result<(), SegmentHandleError> {
+        let mut state = self.state.lock().unwrap();
+        self.ensure_open(&mut state, id)?;
+        let segment = state.segments.get_mut(id).unwrap();
+        if !segment.pinned {
+            segment.pinned = true;
+            let last_used = segment.last_used;
+            state.lru.remove(&last_used);
+            state.pinned_handles += 1;
+        }
+        Ok(())
+    }
+
+    /// Lets a pinned segment be closed again, e.g. after a roll
+    pub fn unpin(&self, id: &SegmentId) {
+        let mut state = self.state.lock().unwrap();
+        let Some(segment) = state.segments.get_mut(id) else {
+            return;
+        };
+        if segment.pinned {
+            segment.pinned = false;
+            let last_used = segment.last_used;
+            state.lru.insert(last_used, id.clone());
+            state.pinned_handles -= 1;
+        }
+    }
+
+    /// Reads `length` bytes at `position` of a segment
+    pub fn read_at(
+        &self,
+        id: &SegmentId,
+        position: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, SegmentHandleError> {
+        let mut state = self.state.lock().unwrap();
+        self.ensure_open(&mut state, id)?;
+        let file = state
+            .segments
+            .get_mut(id)
+            .and_then(|segment| segment.file.as_mut())
+            .expect("segment was just opened");
+        file.seek(SeekFrom::Start(position))?;
+        let mut data = vec![0; length];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Current handle counts and totals
+    pub fn metrics(&self) -> SegmentHandleMetrics {
+        let state = self.state.lock().unwrap();
+        SegmentHandleMetrics {
+            budget: self.budget,
+            open_handles: state.open_handles,
+            pinned_handles: state.pinned_handles,
+            opens: state.opens,
+            reopens: state.reopens,
+            evictions: state.evictions,
+        }
+    }
+
+    /// Makes `id` open and most recently used, evicting if needed
+    fn ensure_open(&self, state: &mut State, id: &SegmentId) -> Result<(), SegmentHandleError> {
+        state.tick += 1;
+        let tick = state.tick;
+        let segment = state
+            .segments
+            .get_mut(id)
+            .ok_or_else(|| SegmentHandleError::UnknownSegment(id.clone()))?;
+        let previous = segment.last_used;
+        segment.last_used = tick;
+        if segment.file.is_some() {
+            if !segment.pinned {
+                state.lru.remove(&previous);
+                state.lru.insert(tick, id.clone());
+            }
+            return Ok(());
+        }
+
+        if state.open_handles >= self.budget {
+            let (_, victim) = state
+                .lru
+                .pop_first()
+                .ok_or(SegmentHandleError::BudgetExhausted {
+                    budget: self.budget,
+                })?;
+            let closed = state.segments.get_mut(&victim).unwrap();
+            closed.file = None;
+            closed.evicted = true;
+            state.open_handles -= 1;
+            state.evictions += 1;
+            debug!(
+                topic = %victim.topic,
+                partition = victim.partition,
+                base_offset = victim.base_offset,
+                "Closed cold segment to stay within the open file budget"
+            );
+        }
+
+        let segment = state.segments.get_mut(id).unwrap();
+        let file = File::open(&segment.path)?;
+        let actual = file.metadata()?.len();
+        if actual < segment.expected_len {
+            return Err(SegmentHandleError::Truncated {
+                path: segment.path.clone(),
+                expected: segment.expected_len,
+                actual,
+            });
+        }
+        segment.file = Some(file);
+        let reopened = std::mem::take(&mut segment.evicted);
+        let pinned = segment.pinned;
+        state.open_handles += 1;
+        state.opens += 1;
+        if reopened {
+            state.reopens += 1;
+        }
+        if !pinned {
+            state.lru.insert(tick, id.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn segment(partition: i32, base_offset: i64) -> SegmentId {
+        SegmentId {
+            topic: "orders".to_string(),
+            partition,
+            base_offset,
+        }
+    }
+
+    /// Writes `partitions` x `segments` files whose bytes name their segment
+    fn write_segments(
+        dir: &Path,
+        cache: &SegmentHandleCache,
+        partitions: i32,
+        segments: i64,
+    ) -> Vec<(SegmentId, Vec<u8>)> {
+        let mut written = Vec::new();
+        for partition in 0..partitions {
+            for base_offset in 0..segments {
+                let id = segment(partition, base_offset * 100);
+                let contents = format!("p{}-s{}", partition, base_offset).into_bytes();
+                let path = dir.join(format!("orders-{}-{}.log", partition, base_offset));
+                fs::write(&path, &contents).unwrap();
+                cache.register(id.clone(), &path, contents.len() as u64);
+                written.push((id, contents));
+            }
+        }
+        written
+    }
+
+    #[test]
+    fn test_reads_across_partitions_stay_within_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SegmentHandleCache::new(4);
+        let segments = write_segments(dir.path(), &cache, 10, 3);
+        // Two partitions have their active segment pinned
+        cache.pin(&segment(0, 200)).unwrap();
+        cache.pin(&segment(1, 200)).unwrap();
+
+        for _ in 0..3 {
+            for (id, contents) in &segments {
+                assert_eq!(&cache.read_at(id, 0, contents.len()).unwrap(), contents);
+                assert!(cache.metrics().open_handles <= 4);
+            }
+        }
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.open_handles, 4);
+        assert_eq!(metrics.pinned_handles, 2);
+        assert_eq!(metrics.pressure_percent(), 100);
+        assert!(metrics.reopens > 0);
+        assert_eq!(metrics.opens, metrics.reopens + 30);
+        assert_eq!(metrics.opens, metrics.evictions + 4);
+    }
+
+    #[test]
+    fn test_budget_of_pinned_segments_is_exhausted() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SegmentHandleCache::new(2);
+        write_segments(dir.path(), &cache, 3, 1);
+        cache.pin(&segment(0, 0)).unwrap();
+        cache.pin(&segment(1, 0)).unwrap();
+
+        assert!(matches!(
+            cache.read_at(&segment(2, 0), 0, 1),
+            Err(SegmentHandleError::BudgetExhausted { budget: 2 })
+        ));
+        cache.unpin(&segment(1, 0));
+        assert!(cache.read_at(&segment(2, 0), 0, 1).is_ok());
+    }
+
+    #[test]
+    fn test_reopen_detects_external_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SegmentHandleCache::new(1);
+        let segments = write_segments(dir.path(), &cache, 2, 1);
+        cache.read_at(&segments[0].0, 0, 1).unwrap();
+        // Opening the second segment closes the first
+        cache.read_at(&segments[1].0, 0, 1).unwrap();
+
+        let path = dir.path().join("orders-0-0.log");
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(2)
+            .unwrap();
+        assert!(matches!(
+            cache.read_at(&segments[0].0, 0, 1),
+            Err(SegmentHandleError::Truncated {
+                expected: 5,
+                actual: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_budget_comes_from_properties() {
+        let cache = SegmentHandleCache::from_properties(&[]).unwrap();
+        assert_eq!(cache.metrics().budget, 1000);
+
+        let invalid = vec![("log.segment.open.files.max".to_string(), "0".to_string())];
+        assert!(SegmentHandleCache::from_properties(&invalid).is_err());
+    }
+}
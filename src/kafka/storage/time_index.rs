@@ -0,0 +1,189 @@
+/// Bytes of one time index entry: timestamp and relative offset
+pub const TIME_INDEX_ENTRY_SIZE: usize = 12;
+
+/// Name of the time index of the segment starting at `base_offset`, such
+/// as `00000000000000000042.timeindex`
+pub fn time_index_file_name(base_offset: i64) -> String {
+    format!("{base_offset:020}.timeindex")
+}
+
+/// One entry of a time index
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeIndexEntry {
+    /// Largest timestamp in the segment up to the entry's offset
+    pub timestamp: i64,
+    /// Offset that first reached the timestamp, relative to the segment's
+    /// base offset
+    pub relative_offset: u32,
+}
+
+impl TimeIndexEntry {
+    pub fn encode(&self) -> [u8; TIME_INDEX_ENTRY_SIZE] {
+        let mut entry = [0; TIME_INDEX_ENTRY_SIZE];
+        entry[..8].copy_from_slice(&self.timestamp.to_be_bytes());
+        entry[8..].copy_from_slice(&self.relative_offset.to_be_bytes());
+        entry
+    }
+}
+
+/// The sparse time index of one segment, as a `.timeindex` file holds it
+///
+/// Written alongside the offset index, each entry records the largest
+/// timestamp seen so far in the segment and the offset that first reached
+/// it. Entries only ever grow in both timestamp and offset: a batch stamped
+/// earlier than one before it does not get an entry, so the index stays
+/// searchable whatever order producers stamp their records in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeIndex {
+    base_offset: i64,
+    entries: Vec<TimeIndexEntry>,
+}
+
+impl TimeIndex {
+    /// An empty index for the segment starting at `base_offset`
+    pub fn new(base_offset: i64) -> Self {
+        Self {
+            base_offset,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Decodes the contents of a time index file
+    ///
+    /// Returns `None` for a file that is not a whole number of entries, or
+    /// whose entries do not grow in timestamp and offset.
+    pub fn decode(base_offset: i64, data: &[u8]) -> Option<Self> {
+        if data.len() % TIME_INDEX_ENTRY_SIZE != 0 {
+            return None;
+        }
+        let entries: Vec<_> = data
+            .chunks_exact(TIME_INDEX_ENTRY_SIZE)
+            .map(|entry| TimeIndexEntry {
+                timestamp: i64::from_be_bytes(entry[..8].try_into().unwrap()),
+                relative_offset: u32::from_be_bytes(entry[8..].try_into().unwrap()),
+            })
+            .collect();
+        let ordered = entries.windows(2).all(|pair| {
+            pair[0].timestamp < pair[1].timestamp
+                && pair[0].relative_offset < pair[1].relative_offset
+        });
+        ordered.then_some(Self {
+            base_offset,
+            entries,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(TimeIndexEntry::encode)
+            .collect()
+    }
+
+    pub fn entries(&self) -> &[TimeIndexEntry] {
+        &self.entries
+    }
+
+    /// Records that `timestamp` was first reached at `offset`
+    ///
+    /// Returns the entry to write, or `None` when the timestamp or offset
+    /// does not move past the last entry.
+    pub fn maybe_push(&mut self, timestamp: i64, offset: i64) -> Option<TimeIndexEntry> {
+        let relative_offset = (offset - self.base_offset) as u32;
+        if let Some(last) = self.entries.last() {
+            if timestamp <= last.timestamp || relative_offset <= last.relative_offset {
+                return None;
+            }
+        }
+        let entry = TimeIndexEntry {
+            timestamp,
+            relative_offset,
+        };
+        self.entries.push(entry);
+        Some(entry)
+    }
+
+    /// Drops the entries at or past `offset`
+    pub fn truncate_to_offset(&mut self, offset: i64) {
+        let keep = self
+            .entries
+            .partition_point(|entry| (entry.relative_offset as i64) < offset - self.base_offset);
+        self.entries.truncate(keep);
+    }
+
+    /// Offset to scan from for the first record stamped at or after
+    /// `timestamp`
+    ///
+    /// Every record before the offset of the last entry stamped earlier
+    /// than `timestamp` is itself earlier, so the scan can start there; with
+    /// no such entry it starts at the base offset.
+    pub fn lookup(&self, timestamp: i64) -> i64 {
+        let earlier = self
+            .entries
+            .partition_point(|entry| entry.timestamp < timestamp);
+        earlier.checked_sub(1).map_or(self.base_offset, |index| {
+            self.base_offset + self.entries[index].relative_offset as i64
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_stay_monotonic() {
+        let mut index = TimeIndex::new(100);
+        assert!(index.maybe_push(1_000, 101).is_some());
+        // An earlier or equal timestamp, or a repeated offset, is skipped
+        assert!(index.maybe_push(900, 105).is_none());
+        assert!(index.maybe_push(1_000, 106).is_none());
+        assert!(index.maybe_push(2_000, 101).is_none());
+        assert!(index.maybe_push(3_000, 110).is_some());
+        assert_eq!(
+            index.entries(),
+            &[
+                TimeIndexEntry {
+                    timestamp: 1_000,
+                    relative_offset: 1,
+                },
+                TimeIndexEntry {
+                    timestamp: 3_000,
+                    relative_offset: 10,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lookup_starts_after_earlier_entries() {
+        let mut index = TimeIndex::new(100);
+        index.maybe_push(1_000, 101);
+        index.maybe_push(3_000, 110);
+        for (timestamp, offset) in [
+            (0, 100),
+            (1_000, 100),
+            (1_001, 101),
+            (3_000, 101),
+            (5_000, 110),
+        ] {
+            assert_eq!(index.lookup(timestamp), offset, "timestamp {}", timestamp);
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trip_and_corruption() {
+        let mut index = TimeIndex::new(100);
+        index.maybe_push(1_000, 101);
+        index.maybe_push(3_000, 110);
+        let data = index.encode();
+        assert_eq!(data.len(), 2 * TIME_INDEX_ENTRY_SIZE);
+        assert_eq!(TimeIndex::decode(100, &data), Some(index.clone()));
+        assert_eq!(TimeIndex::decode(100, &data[..data.len() - 1]), None);
+        let reversed = [&data[12..], &data[..12]].concat();
+        assert_eq!(TimeIndex::decode(100, &reversed), None);
+
+        index.truncate_to_offset(110);
+        assert_eq!(index.entries().len(), 1);
+    }
+}
@@ -0,0 +1,505 @@
+use crate::kafka::events::{BrokerEvent, EventBus};
+use crate::kafka::storage::log_dir::partition_dir;
+use crate::kafka::storage::log_segment::{Log, LogConfig};
+use crate::kafka::storage::segment_handles::SegmentHandleCache;
+use crate::kafka::storage::{PartitionLog, StorageError, StorageResult};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+/// Longest legal topic name
+pub const MAX_TOPIC_NAME_LENGTH: usize = 249;
+
+/// Prefix of the names of the broker's own topics
+pub const INTERNAL_TOPIC_PREFIX: &str = "__";
+
+/// A topic with the logs of its partitions
+#[derive(Debug)]
+pub struct Topic {
+    pub name: String,
+    pub topic_id: Uuid,
+    /// Indexed by partition number
+    pub partitions: Vec<Arc<PartitionLog>>,
+    /// Configs set when the topic was created or altered since
+    configs: RwLock<BTreeMap<String, String>>,
+}
+
+impl Topic {
+    pub fn partition_count(&self) -> i32 {
+        self.partitions.len() as i32
+    }
+
+    /// Whether this is one of the broker's own topics
+    pub fn is_internal(&self) -> bool {
+        self.name.starts_with(INTERNAL_TOPIC_PREFIX)
+    }
+
+    /// The topic's configs as they are now
+    pub fn configs(&self) -> BTreeMap<String, String> {
+        self.configs.read().unwrap().clone()
+    }
+
+    /// Applies `change` to the configs, entirely or not at all
+    ///
+    /// The change works on a copy that replaces the configs only if it
+    /// succeeds, so a failure halfway leaves them untouched.
+    pub fn alter_configs<E>(
+        &self,
+        change: impl FnOnce(&mut BTreeMap<String, String>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut configs = self.configs.write().unwrap();
+        let mut altered = configs.clone();
+        change(&mut altered)?;
+        *configs = altered;
+        Ok(())
+    }
+}
+
+/// The topics this broker hosts, by name
+#[derive(Debug, Default)]
+pub struct TopicStore {
+    topics: RwLock<BTreeMap<String, Arc<Topic>>>,
+    events: Option<EventBus>,
+    /// Directory the partition logs are written to, if any
+    log_dir: Option<PathBuf>,
+    /// How partition segments are sized and indexed
+    log_config: LogConfig,
+    /// The open-file budget every partition's segments are read through
+    segment_handles: Arc<SegmentHandleCache>,
+}
+
+impl TopicStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publishes topic events on the given bus, as do the partition logs
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Keeps the partition logs of topics created from now on in segment
+    /// files under `log_dir`, recovering whatever is already there
+    pub fn with_log_dir(mut self, log_dir: impl Into<PathBuf>) -> Self {
+        self.log_dir = Some(log_dir.into());
+        self
+    }
+
+    /// Sizes and indexes partition segments by `log_config` instead of the
+    /// defaults
+    pub fn with_log_config(mut self, log_config: LogConfig) -> Self {
+        self.log_config = log_config;
+        self
+    }
+
+    /// Reads partition segments through `segment_handles` instead of a
+    /// cache with the default budget
+    pub fn with_segment_handles(mut self, segment_handles: Arc<SegmentHandleCache>) -> Self {
+        self.segment_handles = segment_handles;
+        self
+    }
+
+    /// The open segment files shared by every partition
+    pub fn segment_handles(&self) -> &SegmentHandleCache {
+        &self.segment_handles
+    }
+
+    /// Checks that a topic could be created, without creating it
+    pub fn validate_new_topic(&self, name: &str, num_partitions: i32) -> StorageResult<()> {
+        validate_topic_name(name)?;
+        if num_partitions < 1 {
+            return Err(StorageError::InvalidPartitions {
+                topic: name.to_string(),
+                num_partitions,
+            });
+        }
+        if self.topics.read().unwrap().contains_key(name) {
+            return Err(StorageError::TopicAlreadyExists {
+                topic: name.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Creates a topic with empty partition logs and a random id
+    ///
+    /// The name is checked again under the write lock, so of two concurrent
+    /// creations of the same topic exactly one succeeds.
+    pub fn create_topic(
+        &self,
+        name: &str,
+        num_partitions: i32,
+        configs: BTreeMap<String, String>,
+    ) -> StorageResult<Arc<Topic>> {
+        self.create_topic_with_id(name, Uuid::new_v4(), num_partitions, configs)
+    }
+
+    /// Creates a topic with empty partition logs under an existing id, as
+    /// when restoring the topics of the cluster metadata log
+    ///
+    /// With a log directory, each partition opens its segments there, so a
+    /// restored topic continues from the offsets it had on disk.
+    pub fn create_topic_with_id(
+        &self,
+        name: &str,
+        topic_id: Uuid,
+        num_partitions: i32,
+        configs: BTreeMap<String, String>,
+    ) -> StorageResult<Arc<Topic>> {
+        self.validate_new_topic(name, num_partitions)?;
+        let mut topics = self.topics.write().unwrap();
+        if topics.contains_key(name) {
+            return Err(StorageError::TopicAlreadyExists {
+                topic: name.to_string(),
+            });
+        }
+        let partitions = (0..num_partitions)
+            .map(|partition| {
+                let mut log = PartitionLog::new(name, partition);
+                if let Some(events) = &self.events {
+                    log = log.with_events(events.clone());
+                }
+                if let Some(log_dir) = &self.log_dir {
+                    let segments = Log::open(
+                        &partition_dir(log_dir, name, partition),
+                        self.log_config,
+                        Arc::clone(&self.segment_handles),
+                        name,
+                        partition,
+                    )?;
+                    log = log.with_segments(segments);
+                }
+                Ok(Arc::new(log))
+            })
+            .collect::<StorageResult<_>>()?;
+        let topic = Arc::new(Topic {
+            name: name.to_string(),
+            topic_id,
+            partitions,
+            configs: RwLock::new(configs),
+        });
+        topics.insert(name.to_string(), Arc::clone(&topic));
+        if let Some(events) = &self.events {
+            events.publish(BrokerEvent::TopicCreated {
+                topic: name.to_string(),
+                partitions: num_partitions,
+            });
+        }
+        Ok(topic)
+    }
+
+    /// Removes a topic, returning it so its logs can be cleaned up
+    ///
+    /// Internal topics, whose names start with `__`, cannot be deleted.
+    pub fn delete_topic(&self, name: &str) -> StorageResult<Arc<Topic>> {
+        if name.starts_with(INTERNAL_TOPIC_PREFIX) {
+            return Err(StorageError::InvalidTopic {
+                topic: name.to_string(),
+                reason: "internal topics cannot be deleted".to_string(),
+            });
+        }
+        let topic = self.topics.write().unwrap().remove(name).ok_or_else(|| {
+            StorageError::UnknownTopic {
+                topic: name.to_string(),
+            }
+        })?;
+        if let Some(events) = &self.events {
+            events.publish(BrokerEvent::TopicDeleted {
+                topic: name.to_string(),
+            });
+        }
+        Ok(topic)
+    }
+
+    pub fn get_topic(&self, name: &str) -> Option<Arc<Topic>> {
+        self.topics.read().unwrap().get(name).cloned()
+    }
+
+    pub fn get_topic_by_id(&self, topic_id: Uuid) -> Option<Arc<Topic>> {
+        self.topics
+            .read()
+            .unwrap()
+            .values()
+            .find(|topic| topic.topic_id == topic_id)
+            .cloned()
+    }
+
+    /// All topics, sorted by name
+    pub fn list_topics(&self) -> Vec<Arc<Topic>> {
+        self.topics.read().unwrap().values().cloned().collect()
+    }
+}
+
+/// Checks a topic name against the rules the reference broker applies
+pub fn validate_topic_name(name: &str) -> StorageResult<()> {
+    let invalid = |reason: &str| {
+        Err(StorageError::InvalidTopic {
+            topic: name.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+    if name.is_empty() {
+        return invalid("the name is empty");
+    }
+    if name == "." || name == ".." {
+        return invalid("'.' and '..' are not allowed");
+    }
+    if name.len() > MAX_TOPIC_NAME_LENGTH {
+        return invalid("the name is longer than 249 characters");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return invalid("only ASCII alphanumerics, '.', '_' and '-' are allowed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_and_get() {
+        let store = TopicStore::new();
+        let topic = store.create_topic("orders", 3, BTreeMap::new()).unwrap();
+        assert_eq!(topic.partition_count(), 3);
+        assert!(!topic.topic_id.is_nil());
+        assert_eq!(topic.partitions[2].partition(), 2);
+        assert_eq!(store.get_topic("orders").unwrap().topic_id, topic.topic_id);
+        assert!(store.get_topic("payments").is_none());
+    }
+
+    #[test]
+    fn test_failed_alter_leaves_configs() {
+        let store = TopicStore::new();
+        let configs = BTreeMap::from([("retention.ms".to_string(), "1000".to_string())]);
+        let topic = store.create_topic("orders", 1, configs.clone()).unwrap();
+
+        let failed = topic.alter_configs(|configs| {
+            configs.clear();
+            Err("refused")
+        });
+        assert_eq!(failed, Err("refused"));
+        assert_eq!(topic.configs(), configs);
+
+        topic
+            .alter_configs(|configs| {
+                configs.insert("cleanup.policy".to_string(), "compact".to_string());
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(topic.configs().len(), 2);
+    }
+
+    #[test]
+    fn test_create_with_id() {
+        let store = TopicStore::new();
+        let topic_id = Uuid::from_u128(0x91);
+        store
+            .create_topic_with_id("orders", topic_id, 2, BTreeMap::new())
+            .unwrap();
+        assert_eq!(store.get_topic_by_id(topic_id).unwrap().name, "orders");
+    }
+
+    #[test]
+    fn test_duplicate_is_rejected() {
+        let store = TopicStore::new();
+        store.create_topic("orders", 1, BTreeMap::new()).unwrap();
+        assert_eq!(
+            store
+                .create_topic("orders", 2, BTreeMap::new())
+                .unwrap_err(),
+            StorageError::TopicAlreadyExists {
+                topic: "orders".to_string()
+            }
+        );
+        assert_eq!(store.get_topic("orders").unwrap().partition_count(), 1);
+    }
+
+    #[test]
+    fn test_validation_does_not_create() {
+        let store = TopicStore::new();
+        store.validate_new_topic("orders", 1).unwrap();
+        assert!(store.list_topics().is_empty());
+        assert!(matches!(
+            store.validate_new_topic("orders", 0),
+            Err(StorageError::InvalidPartitions {
+                num_partitions: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_topic_names() {
+        for name in ["orders", "a.b_c-1", &"x".repeat(249)] {
+            assert!(validate_topic_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", ".", "..", "with space", "émoji", &"x".repeat(250)] {
+            assert!(
+                matches!(
+                    validate_topic_name(name),
+                    Err(StorageError::InvalidTopic { .. })
+                ),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_creation_is_published() {
+        let events = EventBus::default();
+        let mut subscriber = events.subscribe("test");
+        let store = TopicStore::new().with_events(events);
+        store.create_topic("orders", 2, BTreeMap::new()).unwrap();
+        assert_eq!(
+            subscriber.try_recv(),
+            Some(BrokerEvent::TopicCreated {
+                topic: "orders".to_string(),
+                partitions: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_delete() {
+        let events = EventBus::default();
+        let mut subscriber = events.subscribe("test");
+        let store = TopicStore::new().with_events(events);
+        let topic = store.create_topic("orders", 1, BTreeMap::new()).unwrap();
+        assert_eq!(
+            store.get_topic_by_id(topic.topic_id).unwrap().name,
+            "orders"
+        );
+        subscriber.try_recv();
+
+        assert_eq!(
+            store.delete_topic("orders").unwrap().topic_id,
+            topic.topic_id
+        );
+        assert!(store.get_topic_by_id(topic.topic_id).is_none());
+        assert_eq!(
+            subscriber.try_recv(),
+            Some(BrokerEvent::TopicDeleted {
+                topic: "orders".to_string()
+            })
+        );
+        assert_eq!(
+            store.delete_topic("orders").unwrap_err(),
+            StorageError::UnknownTopic {
+                topic: "orders".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_internal_topics_cannot_be_deleted() {
+        let store = TopicStore::new();
+        store
+            .create_topic("__consumer_offsets", 1, BTreeMap::new())
+            .unwrap();
+        assert!(matches!(
+            store.delete_topic("__consumer_offsets"),
+            Err(StorageError::InvalidTopic { .. })
+        ));
+        assert!(store.get_topic("__consumer_offsets").is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_appends_and_reads() {
+        let store = Arc::new(TopicStore::new());
+        store.create_topic("orders", 1, BTreeMap::new()).unwrap();
+
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let store = Arc::clone(&store);
+                tokio::spawn(async move {
+                    let log = Arc::clone(&store.get_topic("orders").unwrap().partitions[0]);
+                    let mut previous = -1;
+                    for _ in 0..250 {
+                        // Two records per batch; each writer sees its own
+                        // base offsets only ever grow
+                        let base_offset = log.append(bytes::Bytes::from_static(b"xy"), 2);
+                        assert!(base_offset > previous);
+                        assert_eq!(base_offset % 2, 0);
+                        previous = base_offset;
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let store = Arc::clone(&store);
+                tokio::spawn(async move {
+                    let log = Arc::clone(&store.get_topic("orders").unwrap().partitions[0]);
+                    for _ in 0..250 {
+                        let batches = log.read_from(0, usize::MAX).unwrap();
+                        // Readers see contiguous offsets from the start
+                        for (i, batch) in batches.iter().enumerate() {
+                            assert_eq!(batch.base_offset, 2 * i as i64);
+                            assert_eq!(batch.last_offset, 2 * i as i64 + 1);
+                        }
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for task in writers.into_iter().chain(readers) {
+            task.await.unwrap();
+        }
+
+        let log = &store.get_topic("orders").unwrap().partitions[0];
+        assert_eq!(log.log_end_offset(), 2000);
+        assert_eq!(log.read_from(0, usize::MAX).unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn test_log_dir_partitions_recover_offsets() {
+        use crate::protocol::record_batch::{encode_test_batch, split_record_batches};
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = TopicStore::new().with_log_dir(dir.path());
+        let topic = store.create_topic("orders", 2, BTreeMap::new()).unwrap();
+        let batches = split_record_batches(&encode_test_batch(3, 0)).unwrap();
+        topic.partitions[1]
+            .append_record_batches(&batches, false)
+            .unwrap();
+        assert!(dir
+            .path()
+            .join("orders-0/00000000000000000000.log")
+            .exists());
+
+        let restarted = TopicStore::new().with_log_dir(dir.path());
+        let topic = restarted
+            .create_topic_with_id("orders", topic.topic_id, 2, BTreeMap::new())
+            .unwrap();
+        assert_eq!(topic.partitions[0].log_end_offset(), 0);
+        assert_eq!(topic.partitions[1].log_end_offset(), 3);
+        assert_eq!(topic.partitions[1].high_watermark(), 3);
+        let info = topic.partitions[1]
+            .append_record_batches(&batches, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.base_offset, 3);
+    }
+
+    #[test]
+    fn test_list_is_sorted() {
+        let store = TopicStore::new();
+        for name in ["b", "c", "a"] {
+            store.create_topic(name, 1, BTreeMap::new()).unwrap();
+        }
+        let names: Vec<_> = store
+            .list_topics()
+            .iter()
+            .map(|topic| topic.name.clone())
+            .collect();
+        assert_eq!(names, ["a", "b", "c"]);
+    }
+}
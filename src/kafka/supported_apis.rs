@@ -0,0 +1,93 @@
+use crate::protocol::api_versions::ApiVersionRange;
+use crate::protocol::KafkaRequest;
+use std::collections::BTreeMap;
+
+/// The APIs a broker serves, with the versions of each
+///
+/// The one table both request validation and ApiVersions read, so what is
+/// advertised is always what is accepted. Starts from the built-in typed
+/// requests; registered handlers add or replace entries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupportedApis {
+    ranges: BTreeMap<i16, ApiVersionRange>,
+}
+
+impl SupportedApis {
+    /// The APIs served by the broker's built-in dispatch
+    pub fn builtin() -> Self {
+        Self {
+            ranges: KafkaRequest::VERSION_RANGES
+                .iter()
+                .map(|range| (range.api_key, *range))
+                .collect(),
+        }
+    }
+
+    /// Adds an API, returning the range it replaces
+    pub fn insert(&mut self, range: ApiVersionRange) -> Option<ApiVersionRange> {
+        self.ranges.insert(range.api_key, range)
+    }
+
+    /// The versions served for `api_key`, if it is served at all
+    pub fn get(&self, api_key: i16) -> Option<ApiVersionRange> {
+        self.ranges.get(&api_key).copied()
+    }
+
+    /// Whether `version` of `api_key` is served
+    pub fn supports(&self, api_key: i16, version: i16) -> bool {
+        self.get(api_key)
+            .is_some_and(|range| (range.min_version..=range.max_version).contains(&version))
+    }
+
+    /// Every served API, in api key order, as ApiVersions lists them
+    pub fn entries(&self) -> Vec<ApiVersionRange> {
+        self.ranges.values().copied().collect()
+    }
+}
+
+impl Default for SupportedApis {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::fetch::{FETCH_MAX_VERSION, FETCH_MIN_VERSION};
+    use crate::protocol::ApiKey;
+
+    #[test]
+    fn test_builtin_matches_typed_requests() {
+        let apis = SupportedApis::builtin();
+        assert_eq!(apis.entries(), KafkaRequest::VERSION_RANGES);
+        let fetch = ApiKey::Fetch.code();
+        assert!(apis.supports(fetch, FETCH_MIN_VERSION));
+        assert!(apis.supports(fetch, FETCH_MAX_VERSION));
+        assert!(!apis.supports(fetch, 999));
+        assert!(!apis.supports(fetch, -1));
+        assert!(!apis.supports(1000, 0));
+    }
+
+    #[test]
+    fn test_insert_adds_and_replaces() {
+        let mut apis = SupportedApis::builtin();
+        let added = ApiVersionRange {
+            api_key: 1000,
+            min_version: 0,
+            max_version: 2,
+        };
+        assert_eq!(apis.insert(added), None);
+        assert!(apis.supports(1000, 2));
+        assert_eq!(apis.entries().last(), Some(&added));
+
+        let narrowed = ApiVersionRange {
+            api_key: ApiKey::Fetch.code(),
+            min_version: 4,
+            max_version: 4,
+        };
+        assert!(apis.insert(narrowed).is_some());
+        assert_eq!(apis.get(ApiKey::Fetch.code()), Some(narrowed));
+        assert!(!apis.supports(ApiKey::Fetch.code(), 5));
+    }
+}
@@ -0,0 +1,45 @@
+//! Shared helpers for driving the broker over in-memory streams in tests
+
+use crate::kafka::broker::KafkaBroker;
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, DuplexStream};
+use tokio::task::JoinHandle;
+
+/// Peer address reported for in-memory connections
+pub fn test_peer_addr() -> SocketAddr {
+    "127.0.0.1:50000".parse().unwrap()
+}
+
+/// Spawns a connection to a fresh broker over an in-memory duplex pipe and
+/// returns the client half together with the connection task
+pub fn spawn_connection() -> (DuplexStream, JoinHandle<Result<()>>) {
+    spawn_connection_with(Arc::new(KafkaBroker::new()))
+}
+
+/// Spawns a connection to the given broker over an in-memory duplex pipe
+pub fn spawn_connection_with(broker: Arc<KafkaBroker>) -> (DuplexStream, JoinHandle<Result<()>>) {
+    let (client, mut server) = tokio::io::duplex(64 * 1024);
+    let handle = tokio::spawn(async move {
+        broker
+            .handle_connection(&mut server, test_peer_addr())
+            .await
+    });
+    (client, handle)
+}
+
+/// Prefixes a request (header and body) with its INT32 length
+pub fn frame(request: &[u8]) -> Vec<u8> {
+    let mut frame = (request.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(request);
+    frame
+}
+
+/// Reads one length-prefixed response from the client half
+pub async fn read_response(client: &mut DuplexStream) -> Vec<u8> {
+    let length = client.read_u32().await.unwrap() as usize;
+    let mut response = vec![0u8; length];
+    client.read_exact(&mut response).await.unwrap();
+    response
+}
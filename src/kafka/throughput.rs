@@ -0,0 +1,343 @@
+use crate::kafka::config::{broker_property, ConfigError};
+use crate::logging::warn;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Client ids remembered per partition when looking for the dominant one;
+/// traffic from further clients still counts towards the partition
+const MAX_TRACKED_CLIENTS: usize = 8;
+
+/// Settings for hot-partition detection
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThroughputConfig {
+    /// Length of one counting window (`partition.throughput.window.ms`)
+    pub window: Duration,
+    /// Share of its topic's bytes above which a partition is flagged
+    /// (`partition.skew.threshold.percent`)
+    pub skew_threshold_percent: u64,
+}
+
+impl ThroughputConfig {
+    /// Builds the settings from broker properties, using defaults for missing keys
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let positive = |name: &str| -> Result<u64, ConfigError> {
+            let value = broker_property(properties, name)
+                .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))?;
+            value
+                .parse::<u64>()
+                .ok()
+                .filter(|&parsed| parsed > 0)
+                .ok_or_else(|| ConfigError::InvalidValue {
+                    key: name.to_string(),
+                    value: value.to_string(),
+                })
+        };
+
+        let skew_threshold_percent = positive("partition.skew.threshold.percent")?;
+        if skew_threshold_percent > 100 {
+            return Err(ConfigError::InvalidValue {
+                key: "partition.skew.threshold.percent".to_string(),
+                value: skew_threshold_percent.to_string(),
+            });
+        }
+        Ok(Self {
+            window: Duration::from_millis(positive("partition.throughput.window.ms")?),
+            skew_threshold_percent,
+        })
+    }
+}
+
+impl Default for ThroughputConfig {
+    fn default() -> Self {
+        Self::from_properties(&[]).expect("broker config defaults are valid")
+    }
+}
+
+/// Traffic of one partition over a window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PartitionThroughput {
+    pub topic: String,
+    pub partition: i32,
+    pub produce_bytes: u64,
+    pub produce_records: u64,
+    pub fetch_bytes: u64,
+    pub fetch_records: u64,
+    /// Client responsible for most of the partition's bytes
+    pub dominant_client_id: Option<String>,
+}
+
+impl PartitionThroughput {
+    /// Produce and fetch bytes together
+    pub fn total_bytes(&self) -> u64 {
+        self.produce_bytes + self.fetch_bytes
+    }
+}
+
+/// A partition flagged for taking a skewed share of its topic's traffic
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkewedPartition {
+    pub topic: String,
+    pub partition: i32,
+    pub share_percent: u64,
+    pub dominant_client_id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct PartitionCounters {
+    produce_bytes: AtomicU64,
+    produce_records: AtomicU64,
+    fetch_bytes: AtomicU64,
+    fetch_records: AtomicU64,
+    client_bytes: Mutex<HashMap<String, u64>>,
+}
+
+impl PartitionCounters {
+    fn record_client(&self, client_id: Option<&str>, bytes: u64) {
+        let Some(client_id) = client_id else {
+            return;
+        };
+        let mut clients = self.client_bytes.lock().unwrap();
+        if let Some(total) = clients.get_mut(client_id) {
+            *total += bytes;
+        } else if clients.len() < MAX_TRACKED_CLIENTS {
+            clients.insert(client_id.to_string(), bytes);
+        }
+    }
+
+    fn snapshot(&self, topic: &str, partition: i32) -> PartitionThroughput {
+        let dominant_client_id = self
+            .client_bytes
+            .lock()
+            .unwrap()
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(client_id, _)| client_id.clone());
+        PartitionThroughput {
+            topic: topic.to_string(),
+            partition,
+            produce_bytes: self.produce_bytes.load(Ordering::Relaxed),
+            produce_records: self.produce_records.load(Ordering::Relaxed),
+            fetch_bytes: self.fetch_bytes.load(Ordering::Relaxed),
+            fetch_records: self.fetch_records.load(Ordering::Relaxed),
+            dominant_client_id,
+        }
+    }
+}
+
+/// Per-partition produce and fetch throughput, for spotting hot partitions
+///
+/// Requests add to atomic counters; the map is only write-locked the first
+/// time a partition is seen in a window. [`evaluate`](Self::evaluate) closes
+/// the window: it flags partitions whose share of their topic's bytes exceeds
+/// the skew threshold, keeps the totals for reporting and drops every
+/// counter, so memory is bounded by the partitions active in one window.
+#[derive(Debug)]
+pub struct ThroughputTracker {
+    config: ThroughputConfig,
+    current: RwLock<HashMap<(String, i32), Arc<PartitionCounters>>>,
+    last_window: Mutex<Vec<PartitionThroughput>>,
+}
+
+impl ThroughputTracker {
+    /// Creates a tracker with the given settings
+    pub fn new(config: ThroughputConfig) -> Self {
+        Self {
+            config,
+            current: RwLock::new(HashMap::new()),
+            last_window: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Settings the tracker was built with
+    pub fn config(&self) -> &ThroughputConfig {
+        &self.config
+    }
+
+    /// Counts records appended to a partition
+    pub fn record_produce(
+        &self,
+        topic: &str,
+        partition: i32,
+        client_id: Option<&str>,
+        bytes: u64,
+        records: u64,
+    ) {
+        let counters = self.counters(topic, partition);
+        counters.produce_bytes.fetch_add(bytes, Ordering::Relaxed);
+        counters
+            .produce_records
+            .fetch_add(records, Ordering::Relaxed);
+        counters.record_client(client_id, bytes);
+    }
+
+    /// Counts records read from a partition
+    pub fn record_fetch(
+        &self,
+        topic: &str,
+        partition: i32,
+        client_id: Option<&str>,
+        bytes: u64,
+        records: u64,
+    ) {
+        let counters = self.counters(topic, partition);
+        counters.fetch_bytes.fetch_add(bytes, Ordering::Relaxed);
+        counters.fetch_records.fetch_add(records, Ordering::Relaxed);
+        counters.record_client(client_id, bytes);
+    }
+
+    fn counters(&self, topic: &str, partition: i32) -> Arc<PartitionCounters> {
+        let key = (topic.to_string(), partition);
+        if let Some(counters) = self.current.read().unwrap().get(&key) {
+            return Arc::clone(counters);
+        }
+        Arc::clone(self.current.write().unwrap().entry(key).or_default())
+    }
+
+    /// Closes the current window and returns the partitions it flags
+    ///
+    /// Each flagged partition is logged as a warning naming the partition
+    /// and its dominant client. Topics with a single partition are never
+    /// flagged.
+    pub fn evaluate(&self) -> Vec<SkewedPartition> {
+        let window = std::mem::take(&mut *self.current.write().unwrap());
+        let mut window: Vec<PartitionThroughput> = window
+            .iter()
+            .map(|((topic, partition), counters)| counters.snapshot(topic, *partition))
+            .collect();
+        window.sort_by(|a, b| {
+            b.total_bytes()
+                .cmp(&a.total_bytes())
+                .then_with(|| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)))
+        });
+
+        let mut topics: BTreeMap<&str, (u64, usize)> = BTreeMap::new();
+        for partition in &window {
+            let (bytes, partitions) = topics.entry(&partition.topic).or_default();
+            *bytes += partition.total_bytes();
+            *partitions += 1;
+        }
+
+        let mut skewed = Vec::new();
+        for partition in &window {
+            let (topic_bytes, partitions) = topics[partition.topic.as_str()];
+            if partitions < 2 || topic_bytes == 0 {
+                continue;
+            }
+            let share_percent = partition.total_bytes() * 100 / topic_bytes;
+            if share_percent > self.config.skew_threshold_percent {
+                warn!(
+                    topic = %partition.topic,
+                    partition = partition.partition,
+                    share_percent = share_percent,
+                    threshold_percent = self.config.skew_threshold_percent,
+                    dominant_client_id = ?partition.dominant_client_id,
+                    "Partition is taking a skewed share of its topic's traffic"
+                );
+                skewed.push(SkewedPartition {
+                    topic: partition.topic.clone(),
+                    partition: partition.partition,
+                    share_percent,
+                    dominant_client_id: partition.dominant_client_id.clone(),
+                });
+            }
+        }
+
+        *self.last_window.lock().unwrap() = window;
+        skewed
+    }
+
+    /// The `k` busiest partitions of the last completed window
+    pub fn top_partitions(&self, k: usize) -> Vec<PartitionThroughput> {
+        self.last_window
+            .lock()
+            .unwrap()
+            .iter()
+            .take(k)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for ThroughputTracker {
+    fn default() -> Self {
+        Self::new(ThroughputConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn produce_spread(tracker: &ThroughputTracker, topic: &str, weights: [u64; 4]) {
+        for (partition, weight) in weights.into_iter().enumerate() {
+            for _ in 0..weight {
+                tracker.record_produce(topic, partition as i32, Some("loader"), 100, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_skewed_partition_is_flagged() {
+        let tracker = ThroughputTracker::default();
+        // 90% of traffic to partition 2, from two clients
+        produce_spread(&tracker, "orders", [10, 10, 260, 10]);
+        tracker.record_produce("orders", 2, Some("bulk-import"), 1000, 10);
+
+        let skewed = tracker.evaluate();
+        assert_eq!(
+            skewed,
+            vec![SkewedPartition {
+                topic: "orders".to_string(),
+                partition: 2,
+                share_percent: 90,
+                dominant_client_id: Some("loader".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_balanced_topics_are_not_flagged() {
+        let tracker = ThroughputTracker::default();
+        produce_spread(&tracker, "orders", [25, 25, 25, 25]);
+        tracker.record_fetch("orders", 0, Some("reader"), 500, 5);
+        // A single-partition topic holds all of its own traffic
+        tracker.record_produce("audit", 0, None, 10_000, 100);
+
+        assert!(tracker.evaluate().is_empty());
+    }
+
+    #[test]
+    fn test_window_resets_and_reports_top_partitions() {
+        let tracker = ThroughputTracker::default();
+        produce_spread(&tracker, "orders", [1, 2, 3, 4]);
+        tracker.evaluate();
+
+        let top = tracker.top_partitions(2);
+        assert_eq!(
+            top.iter()
+                .map(|p| (p.partition, p.produce_bytes, p.produce_records))
+                .collect::<Vec<_>>(),
+            vec![(3, 400, 4), (2, 300, 3)]
+        );
+
+        // The next window starts empty
+        assert!(tracker.evaluate().is_empty());
+        assert!(tracker.top_partitions(10).is_empty());
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let config = ThroughputConfig::default();
+        assert_eq!(config.window, Duration::from_secs(60));
+        assert_eq!(config.skew_threshold_percent, 50);
+
+        let invalid = vec![(
+            "partition.skew.threshold.percent".to_string(),
+            "150".to_string(),
+        )];
+        assert!(ThroughputConfig::from_properties(&invalid).is_err());
+    }
+}
@@ -0,0 +1,450 @@
+use crate::kafka::config::{broker_property, ConfigError};
+use crate::kafka::events::{BrokerEvent, EventSubscriber};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Label of the series that aggregates every topic without its own
+pub const OTHER_TOPICS_LABEL: &str = "__other__";
+
+/// Settings for per-topic metrics
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicMetricsConfig {
+    /// Topics with their own series (`topic.metrics.max.series`)
+    pub max_series: usize,
+    /// How often the topics with their own series are chosen again
+    /// (`topic.metrics.rerank.interval.ms`)
+    pub rerank_interval: Duration,
+    /// Label series with a hash of the topic name instead of the name
+    /// (`topic.metrics.hash.names`)
+    pub hash_names: bool,
+}
+
+impl TopicMetricsConfig {
+    /// Builds the settings from broker properties, using defaults for missing keys
+    pub fn from_properties(properties: &[(String, String)]) -> Result<Self, ConfigError> {
+        let property = |name: &str| {
+            broker_property(properties, name)
+                .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))
+        };
+        let positive = |name: &str| -> Result<u64, ConfigError> {
+            let value = property(name)?;
+            value
+                .parse::<u64>()
+                .ok()
+                .filter(|&parsed| parsed > 0)
+                .ok_or_else(|| ConfigError::InvalidValue {
+                    key: name.to_string(),
+                    value: value.to_string(),
+                })
+        };
+
+        Ok(Self {
+            max_series: positive("topic.metrics.max.series")? as usize,
+            rerank_interval: Duration::from_millis(positive("topic.metrics.rerank.interval.ms")?),
+            hash_names: property("topic.metrics.hash.names")? == "true",
+        })
+    }
+}
+
+impl Default for TopicMetricsConfig {
+    fn default() -> Self {
+        Self::from_properties(&[]).expect("broker config defaults are valid")
+    }
+}
+
+/// Reads one counter out of [`TopicCounters`]
+type CounterField = fn(&TopicCounters) -> u64;
+
+/// Counters of one series
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TopicCounters {
+    pub produce_bytes: u64,
+    pub produce_records: u64,
+    pub fetch_bytes: u64,
+    pub fetch_records: u64,
+}
+
+impl TopicCounters {
+    fn add(&mut self, other: &TopicCounters) {
+        self.produce_bytes += other.produce_bytes;
+        self.produce_records += other.produce_records;
+        self.fetch_bytes += other.fetch_bytes;
+        self.fetch_records += other.fetch_records;
+    }
+}
+
+#[derive(Debug, Default)]
+struct TopicMetricsState {
+    /// Topics with their own series
+    tracked: HashSet<String>,
+    /// Series by topic; the aggregate lives under [`OTHER_TOPICS_LABEL`]
+    series: HashMap<String, TopicCounters>,
+    other: TopicCounters,
+    /// Bytes per topic since the last rerank, for choosing `tracked`
+    traffic: HashMap<String, u64>,
+    /// Topic creations and deletions, when subscribed
+    events: Option<EventSubscriber>,
+}
+
+/// Per-topic traffic counters with bounded cardinality
+///
+/// Topic names are chosen by clients, so a series per topic could grow
+/// without bound. Only the `max_series` topics with the most traffic in the
+/// last rerank interval get their own series; everything else is counted
+/// under `__other__`. When a topic loses its series its counts move into
+/// `__other__`, and a topic that gains one starts from zero, so every
+/// series stays monotonic and the sum over series never drops.
+///
+/// Until the first rerank, topics get a series as they are first seen,
+/// up to the cap. When subscribed to broker events, a created topic is
+/// seen at once, and a deleted one gives up its series to `__other__`.
+#[derive(Debug)]
+pub struct TopicMetrics {
+    config: TopicMetricsConfig,
+    state: Mutex<TopicMetricsState>,
+}
+
+impl TopicMetrics {
+    pub fn new(config: TopicMetricsConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(TopicMetricsState::default()),
+        }
+    }
+
+    /// Registers and retires topics as `events` reports them
+    pub fn with_events(self, events: EventSubscriber) -> Self {
+        self.state.lock().unwrap().events = Some(events);
+        self
+    }
+
+    pub fn config(&self) -> &TopicMetricsConfig {
+        &self.config
+    }
+
+    /// Counts records appended to a topic
+    pub fn record_produce(&self, topic: &str, bytes: u64, records: u64) {
+        self.record(
+            topic,
+            TopicCounters {
+                produce_bytes: bytes,
+                produce_records: records,
+                ..TopicCounters::default()
+            },
+        );
+    }
+
+    /// Counts records read from a topic
+    pub fn record_fetch(&self, topic: &str, bytes: u64, records: u64) {
+        self.record(
+            topic,
+            TopicCounters {
+                fetch_bytes: bytes,
+                fetch_records: records,
+                ..TopicCounters::default()
+            },
+        );
+    }
+
+    fn record(&self, topic: &str, counters: TopicCounters) {
+        let mut state = self.state.lock().unwrap();
+        self.apply_events(&mut state);
+        let bytes = counters.produce_bytes + counters.fetch_bytes;
+        match state.traffic.get_mut(topic) {
+            Some(traffic) => *traffic += bytes,
+            None => {
+                state.traffic.insert(topic.to_string(), bytes);
+            }
+        }
+        if !state.tracked.contains(topic) && state.tracked.len() < self.config.max_series {
+            state.tracked.insert(topic.to_string());
+        }
+        if state.tracked.contains(topic) {
+            state
+                .series
+                .entry(topic.to_string())
+                .or_default()
+                .add(&counters);
+        } else {
+            state.other.add(&counters);
+        }
+    }
+
+    /// Gives the busiest topics of the interval their own series
+    ///
+    /// Topics are ranked by bytes since the previous rerank, ties broken by
+    /// name. Returns the topics that now have their own series.
+    pub fn rerank(&self) -> Vec<String> {
+        let mut guard = self.state.lock().unwrap();
+        self.apply_events(&mut guard);
+        let state = &mut *guard;
+        let mut ranked: Vec<(&String, &u64)> = state.traffic.iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let tracked: HashSet<String> = ranked
+            .into_iter()
+            .take(self.config.max_series)
+            .map(|(topic, _)| topic.clone())
+            .collect();
+
+        for topic in state.tracked.difference(&tracked) {
+            if let Some(counters) = state.series.remove(topic) {
+                state.other.add(&counters);
+            }
+        }
+        state.tracked = tracked;
+        state.traffic.clear();
+
+        let mut topics: Vec<String> = state.tracked.iter().cloned().collect();
+        topics.sort();
+        topics
+    }
+
+    /// Counters by label, with `__other__` last when it has seen traffic
+    pub fn series(&self) -> Vec<(String, TopicCounters)> {
+        let mut state = self.state.lock().unwrap();
+        self.apply_events(&mut state);
+        let mut series: BTreeMap<String, TopicCounters> = BTreeMap::new();
+        for (topic, counters) in &state.series {
+            // Colliding hashes share a series
+            series.entry(self.label(topic)).or_default().add(counters);
+        }
+        let mut series: Vec<(String, TopicCounters)> = series.into_iter().collect();
+        if state.other != TopicCounters::default() {
+            series.push((OTHER_TOPICS_LABEL.to_string(), state.other));
+        }
+        series
+    }
+
+    /// Drains pending topic events
+    fn apply_events(&self, state: &mut TopicMetricsState) {
+        let Some(mut events) = state.events.take() else {
+            return;
+        };
+        while let Some(event) = events.try_recv() {
+            match event {
+                BrokerEvent::TopicCreated { topic, .. }
+                    if state.tracked.len() < self.config.max_series =>
+                {
+                    state.series.entry(topic.clone()).or_default();
+                    state.tracked.insert(topic);
+                }
+                BrokerEvent::TopicDeleted { topic } => {
+                    state.tracked.remove(&topic);
+                    state.traffic.remove(&topic);
+                    if let Some(counters) = state.series.remove(&topic) {
+                        state.other.add(&counters);
+                    }
+                }
+                _ => {}
+            }
+        }
+        state.events = Some(events);
+    }
+
+    /// Series label of a topic: its name, or a short stable hash of it
+    /// when names must not leave the broker
+    pub fn label(&self, topic: &str) -> String {
+        if self.config.hash_names {
+            format!("t_{:08x}", crc32c::crc32c(topic.as_bytes()))
+        } else {
+            topic.to_string()
+        }
+    }
+
+    /// Prometheus text exposition of the counters
+    pub fn render_prometheus(&self) -> String {
+        let series = self.series();
+        let metrics: [(&str, &str, CounterField); 4] = [
+            (
+                "kafka_topic_produce_bytes_total",
+                "Bytes appended to the topic",
+                |counters| counters.produce_bytes,
+            ),
+            (
+                "kafka_topic_produce_records_total",
+                "Records appended to the topic",
+                |counters| counters.produce_records,
+            ),
+            (
+                "kafka_topic_fetch_bytes_total",
+                "Bytes read from the topic",
+                |counters| counters.fetch_bytes,
+            ),
+            (
+                "kafka_topic_fetch_records_total",
+                "Records read from the topic",
+                |counters| counters.fetch_records,
+            ),
+        ];
+
+        let mut output = String::new();
+        for (name, help, value) in metrics {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} counter", name);
+            for (label, counters) in &series {
+                let _ = writeln!(
+                    output,
+                    "{}{{topic=\"{}\"}} {}",
+                    name,
+                    escape_label_value(label),
+                    value(counters)
+                );
+            }
+        }
+        output
+    }
+}
+
+impl Default for TopicMetrics {
+    fn default() -> Self {
+        Self::new(TopicMetricsConfig::default())
+    }
+}
+
+/// Escapes a Prometheus label value
+pub(crate) fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::events::EventBus;
+
+    fn metrics(max_series: usize) -> TopicMetrics {
+        TopicMetrics::new(TopicMetricsConfig {
+            max_series,
+            ..TopicMetricsConfig::default()
+        })
+    }
+
+    fn total(metrics: &TopicMetrics) -> u64 {
+        metrics
+            .series()
+            .iter()
+            .map(|(_, counters)| counters.produce_bytes)
+            .sum()
+    }
+
+    fn labels(metrics: &TopicMetrics) -> Vec<String> {
+        metrics
+            .series()
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect()
+    }
+
+    #[test]
+    fn test_top_topics_keep_series_and_tail_aggregates() {
+        let metrics = metrics(3);
+        // topic-0 is the quietest, topic-9 the busiest
+        for round in 0..2 {
+            for topic in 0..10u64 {
+                metrics.record_produce(&format!("topic-{}", topic), (topic + 1) * 100, 1);
+            }
+            if round == 0 {
+                assert_eq!(metrics.rerank(), vec!["topic-7", "topic-8", "topic-9"]);
+            }
+        }
+
+        assert_eq!(
+            labels(&metrics),
+            vec!["topic-7", "topic-8", "topic-9", OTHER_TOPICS_LABEL]
+        );
+        let series = metrics.series();
+        assert_eq!(series[2].1.produce_bytes, 1000);
+        assert_eq!(series[2].1.produce_records, 1);
+        // Nothing is lost by relabeling
+        assert_eq!(total(&metrics), 2 * (1..=10).map(|n| n * 100).sum::<u64>());
+    }
+
+    #[test]
+    fn test_demoted_topic_moves_its_counts_to_other() {
+        let metrics = metrics(1);
+        metrics.record_produce("orders", 500, 5);
+        metrics.record_produce("payments", 100, 1);
+        assert_eq!(labels(&metrics), vec!["orders", OTHER_TOPICS_LABEL]);
+        metrics.rerank();
+
+        metrics.record_produce("payments", 1000, 10);
+        assert_eq!(metrics.rerank(), vec!["payments"]);
+        let series = metrics.series();
+        assert_eq!(
+            series,
+            vec![(
+                OTHER_TOPICS_LABEL.to_string(),
+                TopicCounters {
+                    produce_bytes: 1600,
+                    produce_records: 16,
+                    ..TopicCounters::default()
+                }
+            )]
+        );
+
+        metrics.record_fetch("payments", 10, 1);
+        assert_eq!(labels(&metrics), vec!["payments", OTHER_TOPICS_LABEL]);
+    }
+
+    #[test]
+    fn test_hashed_labels_and_exposition() {
+        let metrics = TopicMetrics::new(TopicMetricsConfig {
+            max_series: 1,
+            hash_names: true,
+            ..TopicMetricsConfig::default()
+        });
+        metrics.record_produce("secret-customer-topic", 10, 1);
+        metrics.record_produce("another", 5, 1);
+
+        let label = metrics.label("secret-customer-topic");
+        assert_eq!(label, metrics.label("secret-customer-topic"));
+        assert!(label.starts_with("t_") && label.len() == 10);
+
+        let text = metrics.render_prometheus();
+        assert!(!text.contains("secret-customer-topic"));
+        assert!(text.contains("# TYPE kafka_topic_produce_bytes_total counter"));
+        assert!(text.contains(&format!(
+            "kafka_topic_produce_bytes_total{{topic=\"{}\"}} 10",
+            label
+        )));
+        assert!(text.contains("kafka_topic_produce_bytes_total{topic=\"__other__\"} 5"));
+    }
+
+    #[test]
+    fn test_topic_events_register_and_retire_series() {
+        let bus = EventBus::default();
+        let metrics = metrics(2).with_events(bus.subscribe("topic-metrics"));
+        bus.publish(BrokerEvent::TopicCreated {
+            topic: "orders".to_string(),
+            partitions: 1,
+        });
+        assert_eq!(labels(&metrics), vec!["orders"]);
+
+        metrics.record_produce("orders", 100, 1);
+        bus.publish(BrokerEvent::TopicDeleted {
+            topic: "orders".to_string(),
+        });
+        assert_eq!(labels(&metrics), vec![OTHER_TOPICS_LABEL]);
+        assert_eq!(total(&metrics), 100);
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        assert_eq!(escape_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_config_from_properties() {
+        let config = TopicMetricsConfig::default();
+        assert_eq!(config.max_series, 100);
+        assert!(!config.hash_names);
+
+        let invalid = vec![("topic.metrics.max.series".to_string(), "0".to_string())];
+        assert!(TopicMetricsConfig::from_properties(&invalid).is_err());
+    }
+}
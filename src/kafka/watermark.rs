@@ -0,0 +1,197 @@
+use tokio::sync::watch;
+
+/// High watermark of a single partition with change notifications
+///
+/// The partition log owns one of these and advances it after every append.
+/// Interested parties (parked fetches, fetch sessions, replica fetchers)
+/// subscribe instead of polling storage. Notifications use `watch`
+/// semantics: only the latest value is kept, so a slow subscriber can never
+/// block an append, it simply observes the newest watermark when it wakes.
+#[derive(Debug)]
+pub struct HighWatermark {
+    sender: watch::Sender<i64>,
+}
+
+impl HighWatermark {
+    /// Creates a watermark starting at the given offset
+    pub fn new(initial: i64) -> Self {
+        let (sender, _) = watch::channel(initial);
+        Self { sender }
+    }
+
+    /// Returns the current high watermark
+    pub fn get(&self) -> i64 {
+        *self.sender.borrow()
+    }
+
+    /// Advances the watermark to `offset`
+    ///
+    /// The watermark never moves backwards; returns `true` when the value
+    /// changed and subscribers were notified.
+    pub fn advance_to(&self, offset: i64) -> bool {
+        self.sender.send_if_modified(|current| {
+            if offset > *current {
+                *current = offset;
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Lowers the watermark to `offset` if it is currently above it
+    ///
+    /// Only log truncation may move the watermark backwards. Returns `true`
+    /// when the value changed.
+    pub fn clamp_to(&self, offset: i64) -> bool {
+        self.sender.send_if_modified(|current| {
+            if offset < *current {
+                *current = offset;
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Subscribes to watermark changes
+    pub fn subscribe(&self) -> HighWatermarkSubscriber {
+        HighWatermarkSubscriber {
+            receiver: self.sender.subscribe(),
+        }
+    }
+
+    /// Returns the number of live subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for HighWatermark {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// Receiving side of a [`HighWatermark`]
+#[derive(Debug, Clone)]
+pub struct HighWatermarkSubscriber {
+    receiver: watch::Receiver<i64>,
+}
+
+impl HighWatermarkSubscriber {
+    /// Returns the latest observed watermark
+    pub fn current(&self) -> i64 {
+        *self.receiver.borrow()
+    }
+
+    /// Waits until the watermark advances past `offset`
+    ///
+    /// Returns the new watermark, or `None` if the partition was dropped
+    /// before that happened.
+    pub async fn wait_past(&mut self, offset: i64) -> Option<i64> {
+        self.receiver
+            .wait_for(|watermark| *watermark > offset)
+            .await
+            .map(|watermark| *watermark)
+            .ok()
+    }
+
+    /// Waits for the next change and returns the new watermark
+    ///
+    /// Returns `None` if the partition was dropped.
+    pub async fn changed(&mut self) -> Option<i64> {
+        self.receiver.changed().await.ok()?;
+        Some(*self.receiver.borrow_and_update())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_advance_is_monotonic() {
+        let watermark = HighWatermark::new(5);
+
+        assert!(watermark.advance_to(10));
+        assert!(!watermark.advance_to(10));
+        assert!(!watermark.advance_to(3));
+        assert_eq!(watermark.get(), 10);
+    }
+
+    #[test]
+    fn test_clamp_only_lowers() {
+        let watermark = HighWatermark::new(10);
+
+        assert!(!watermark.clamp_to(12));
+        assert!(watermark.clamp_to(4));
+        assert_eq!(watermark.get(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_observes_advances_in_order() {
+        let watermark = HighWatermark::new(0);
+        let mut subscriber = watermark.subscribe();
+
+        watermark.advance_to(1);
+        assert_eq!(subscriber.changed().await, Some(1));
+
+        watermark.advance_to(2);
+        assert_eq!(subscriber.changed().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_sees_latest_value() {
+        let watermark = HighWatermark::new(0);
+        let mut subscriber = watermark.subscribe();
+
+        for offset in 1..=100 {
+            watermark.advance_to(offset);
+        }
+
+        assert_eq!(subscriber.changed().await, Some(100));
+    }
+
+    #[tokio::test]
+    async fn test_wait_past_wakes_on_advance() {
+        let watermark = std::sync::Arc::new(HighWatermark::new(0));
+        let mut subscriber = watermark.subscribe();
+
+        let waiter = tokio::spawn(async move { subscriber.wait_past(4).await });
+
+        watermark.advance_to(3);
+        watermark.advance_to(5);
+
+        let result = tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result, Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_subscriber_does_not_block_or_leak() {
+        let watermark = HighWatermark::new(0);
+        let subscriber = watermark.subscribe();
+        assert_eq!(watermark.subscriber_count(), 1);
+
+        drop(subscriber);
+        assert_eq!(watermark.subscriber_count(), 0);
+
+        // Advancing with no subscribers still updates the value
+        assert!(watermark.advance_to(7));
+        assert_eq!(watermark.get(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_wait_past_returns_none_when_partition_dropped() {
+        let watermark = HighWatermark::new(0);
+        let mut subscriber = watermark.subscribe();
+
+        drop(watermark);
+
+        assert_eq!(subscriber.wait_past(0).await, None);
+    }
+}
@@ -0,0 +1,21 @@
+//! Kafka broker implementation
+//!
+//! The binary in `main.rs` is a thin wrapper around this library: it parses
+//! the command line, builds a [`kafka::broker::KafkaBroker`] and hands it to
+//! a [`network::server::NetworkServer`]. Everything else lives here so the
+//! protocol and broker APIs can be used, and their examples compile-tested,
+//! on their own.
+//!
+//! - [`protocol`]: wire format, headers and request/response messages
+//! - [`kafka`]: the broker, its storage and its per-request state
+//! - [`network`]: the TCP server and the debug HTTP endpoint
+//! - [`client`]: client-side helpers such as the default partitioner
+//! - [`cli`]: command line options of the binary
+//! - [`logging`]: tracing setup
+
+pub mod cli;
+pub mod client;
+pub mod kafka;
+pub mod logging;
+pub mod network;
+pub mod protocol;
@@ -1,10 +1,12 @@
+use crate::protocol::api_key::api_key_name;
 use anyhow::Result;
 use serde::Serialize;
 use std::io;
+use std::path::PathBuf;
 use tracing::Span;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::{
-    fmt::{self, time::ChronoUtc},
+    fmt::{self, time::ChronoUtc, MakeWriter},
     layer::SubscriberExt,
     util::SubscriberInitExt,
     EnvFilter, Layer,
@@ -28,8 +30,6 @@ pub struct LogConfig {
     pub file_prefix: String,
     /// Whether to use JSON format
     pub json_format: bool,
-    /// Whether to include timestamps
-    pub with_timestamp: bool,
     /// Whether to include thread IDs
     pub with_thread_ids: bool,
     /// Whether to include span information
@@ -45,13 +45,28 @@ impl Default for LogConfig {
             log_dir: "./logs".to_string(),
             file_prefix: "kafka-broker".to_string(),
             json_format: false,
-            with_timestamp: true,
             with_thread_ids: true,
             with_spans: true,
         }
     }
 }
 
+/// Outcome of logging initialization
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LoggingState {
+    /// Directory file logs are written to, when file logging is active
+    pub file_log_dir: Option<PathBuf>,
+    /// Why file logging was requested but could not be enabled
+    pub degraded_reason: Option<String>,
+}
+
+impl LoggingState {
+    /// Returns true when file logging was requested but is unavailable
+    pub fn is_degraded(&self) -> bool {
+        self.degraded_reason.is_some()
+    }
+}
+
 /// Logger component responsible for initializing and managing logging
 ///
 /// This struct follows the Single Responsibility Principle by focusing
@@ -63,11 +78,39 @@ impl Logger {
     /// Initialize the logging system with the given configuration
     ///
     /// This method sets up tracing subscribers for both console and file output
-    /// based on the provided configuration.
-    pub fn init(config: LogConfig) -> Result<()> {
-        // Create the logs directory if it doesn't exist
+    /// based on the provided configuration. A log directory that cannot be
+    /// created or written to does not stop the broker: logging falls back to
+    /// console only, a warning is logged and the returned state is marked
+    /// degraded.
+    pub fn init(config: LogConfig) -> Result<LoggingState> {
+        let (subscriber, state) = Self::build(&config, io::stdout);
+        subscriber.try_init()?;
+        Self::announce(&config, &state);
+        Ok(state)
+    }
+
+    /// Builds the subscriber without installing it
+    fn build<W>(
+        config: &LogConfig,
+        console_writer: W,
+    ) -> (impl tracing::Subscriber + Send + Sync, LoggingState)
+    where
+        W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+    {
+        let mut state = LoggingState {
+            file_log_dir: None,
+            degraded_reason: None,
+        };
         if config.file {
-            std::fs::create_dir_all(&config.log_dir)?;
+            match Self::prepare_log_dir(&config.log_dir) {
+                Ok(dir) => state.file_log_dir = Some(dir),
+                Err(e) => {
+                    state.degraded_reason = Some(format!(
+                        "log directory {} is unusable: {}",
+                        config.log_dir, e
+                    ))
+                }
+            }
         }
 
         // Create the environment filter
@@ -88,7 +131,7 @@ impl Logger {
                     } else {
                         fmt::format::FmtSpan::NONE
                     })
-                    .with_writer(io::stdout)
+                    .with_writer(console_writer)
                     .boxed()
             } else {
                 fmt::layer()
@@ -99,18 +142,18 @@ impl Logger {
                     } else {
                         fmt::format::FmtSpan::NONE
                     })
-                    .with_writer(io::stdout)
+                    .with_writer(console_writer)
                     .boxed()
             };
             layers.push(console_layer);
         }
 
         // File layer
-        if config.file {
+        if let Some(log_dir) = &state.file_log_dir {
             let file_appender = RollingFileAppender::new(
                 Rotation::DAILY,
-                &config.log_dir,
-                &format!("{}.log", config.file_prefix),
+                log_dir,
+                format!("{}.log", config.file_prefix),
             );
 
             let file_layer = if config.json_format {
@@ -140,30 +183,42 @@ impl Logger {
             layers.push(file_layer);
         }
 
-        // Initialize the subscriber
-        tracing_subscriber::registry()
-            .with(env_filter)
-            .with(layers)
-            .init();
+        let subscriber = tracing_subscriber::registry().with(env_filter).with(layers);
+        (subscriber, state)
+    }
 
+    /// Logs the effective configuration and any degradation
+    fn announce(config: &LogConfig, state: &LoggingState) {
         tracing::info!(
             config = ?config,
             "Logging system initialized"
         );
-
-        Ok(())
+        if let Some(reason) = &state.degraded_reason {
+            tracing::warn!(
+                log_dir = %config.log_dir,
+                reason = %reason,
+                "File logging disabled, continuing with console logging only"
+            );
+        }
     }
 
-    /// Initialize with default configuration
-    pub fn init_default() -> Result<()> {
-        Self::init(LogConfig::default())
+    /// Expands `~`, creates the directory and checks it is writable
+    fn prepare_log_dir(log_dir: &str) -> io::Result<PathBuf> {
+        let dir = expand_home(log_dir);
+        std::fs::create_dir_all(&dir)?;
+        // Creating the directory can succeed on a read-only mount when it
+        // already exists, so prove a file can actually be written
+        let probe = dir.join(".write-probe");
+        std::fs::write(&probe, b"")?;
+        std::fs::remove_file(&probe)?;
+        Ok(dir)
     }
 
     /// Initialize with environment-aware defaults
     ///
     /// This method adjusts logging configuration based on environment variables
     /// and deployment context.
-    pub fn init_with_env() -> Result<()> {
+    pub fn init_with_env() -> Result<LoggingState> {
         let config = LogConfig {
             level: std::env::var("KAFKA_LOG_LEVEL").unwrap_or_else(|_| "info".to_string()),
             console: std::env::var("KAFKA_LOG_CONSOLE")
@@ -178,7 +233,6 @@ impl Logger {
             json_format: std::env::var("KAFKA_LOG_JSON")
                 .map(|v| v.parse().unwrap_or(false))
                 .unwrap_or(false),
-            with_timestamp: true,
             with_thread_ids: true,
             with_spans: true,
         };
@@ -187,6 +241,16 @@ impl Logger {
     }
 }
 
+/// Expands a leading `~` to the user's home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
 /// Utility functions for structured logging
 pub struct LogUtils;
 
@@ -201,10 +265,10 @@ impl LogUtils {
     }
 
     /// Create a span for request processing
-    pub fn request_span(api_key: u16, correlation_id: i32, client_id: Option<&str>) -> Span {
+    pub fn request_span(api_key: i16, correlation_id: i32, client_id: Option<&str>) -> Span {
         tracing::info_span!(
             "request",
-            api_key = api_key,
+            api_key = %api_key_name(api_key),
             correlation_id = correlation_id,
             client_id = client_id,
             request_size = tracing::field::Empty,
@@ -212,34 +276,9 @@ impl LogUtils {
         )
     }
 
-    /// Create a span for broker operations
-    pub fn broker_span(operation: &str) -> Span {
-        tracing::info_span!(
-            "broker_operation",
-            operation = operation,
-            duration_ms = tracing::field::Empty,
-        )
-    }
-
-    /// Log connection metrics
-    pub fn log_connection_metrics(
-        peer_addr: &std::net::SocketAddr,
-        bytes_read: usize,
-        bytes_written: usize,
-        duration_ms: u64,
-    ) {
-        tracing::info!(
-            peer_addr = %peer_addr,
-            bytes_read = bytes_read,
-            bytes_written = bytes_written,
-            duration_ms = duration_ms,
-            "Connection completed"
-        );
-    }
-
     /// Log request metrics
     pub fn log_request_metrics(
-        api_key: u16,
+        api_key: i16,
         correlation_id: i32,
         request_size: usize,
         response_size: usize,
@@ -248,7 +287,7 @@ impl LogUtils {
     ) {
         if success {
             tracing::info!(
-                api_key = api_key,
+                api_key = %api_key_name(api_key),
                 correlation_id = correlation_id,
                 request_size = request_size,
                 response_size = response_size,
@@ -257,7 +296,7 @@ impl LogUtils {
             );
         } else {
             tracing::warn!(
-                api_key = api_key,
+                api_key = %api_key_name(api_key),
                 correlation_id = correlation_id,
                 request_size = request_size,
                 processing_time_ms = processing_time_ms,
@@ -309,7 +348,7 @@ mod tests {
                 file: false,
                 ..Default::default()
             };
-            Logger::init(config).unwrap();
+            let _ = Logger::init(config);
         });
     }
 
@@ -347,4 +386,75 @@ mod tests {
         warn!("This is a warning message");
         error!("This is an error message");
     }
+
+    /// Console writer capturing output for assertions
+    #[derive(Clone, Default)]
+    struct CapturedOutput(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CapturedOutput {
+        fn contents(&self) -> String {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+        }
+    }
+
+    #[test]
+    fn test_unwritable_log_dir_falls_back_to_console() {
+        // A directory below a regular file can never be created, even as root
+        let blocker = tempfile::NamedTempFile::new().unwrap();
+        let config = LogConfig {
+            log_dir: blocker.path().join("logs").display().to_string(),
+            ..Default::default()
+        };
+        let output = CapturedOutput::default();
+        let writer = output.clone();
+
+        let (subscriber, state) = Logger::build(&config, move || writer.clone());
+        assert!(state.is_degraded());
+        assert_eq!(state.file_log_dir, None);
+
+        tracing::subscriber::with_default(subscriber, || {
+            Logger::announce(&config, &state);
+            info!("console still works");
+        });
+        let logged = output.contents();
+        assert!(logged.contains("File logging disabled"));
+        assert!(logged.contains("console still works"));
+    }
+
+    #[test]
+    fn test_writable_log_dir_enables_file_logging() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LogConfig {
+            log_dir: dir.path().display().to_string(),
+            ..Default::default()
+        };
+        let (_, state) = Logger::build(&config, io::sink);
+        assert!(!state.is_degraded());
+        assert_eq!(state.file_log_dir.as_deref(), Some(dir.path()));
+        // The probe file is cleaned up
+        assert!(!dir.path().join(".write-probe").exists());
+    }
+
+    #[test]
+    fn test_home_expansion() {
+        let home = std::env::var("HOME").unwrap_or_default();
+        if home.is_empty() {
+            return;
+        }
+        assert_eq!(expand_home("~/logs"), PathBuf::from(&home).join("logs"));
+        assert_eq!(expand_home("~"), PathBuf::from(&home));
+        assert_eq!(expand_home("/var/log"), PathBuf::from("/var/log"));
+        assert_eq!(expand_home("~other/logs"), PathBuf::from("~other/logs"));
+    }
 }
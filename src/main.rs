@@ -1,24 +1,216 @@
-#![allow(unused_imports)]
 use anyhow::Result;
-use std::net::SocketAddr;
+use std::sync::Arc;
 
-mod kafka;
-mod logging;
-mod network;
-mod protocol;
+use codecrafters_kafka::{kafka, logging, network, protocol};
 
+use codecrafters_kafka::cli::{CliOptions, ConfigCommand};
 use kafka::broker::KafkaBroker;
-use logging::{LogUtils, Logger};
+use kafka::config::{broker_property, check_config_file, parse_properties, BrokerConfig};
+use kafka::diagnostics::DiagnosticsLevel;
+use kafka::latency::DeliveryLatencyTracker;
+use kafka::limits::Limits;
+use kafka::metadata_epoch::MetadataEpoch;
+use kafka::producer_id_manager::ProducerIdManager;
+use kafka::purgatory::{Purgatory, PurgatoryConfig};
+use kafka::quarantine::{Quarantine, QuarantineConfig};
+use kafka::replay::{replay, Capture, ReplayTiming};
+use kafka::request_queue::{RequestQueue, RequestQueueConfig};
+use kafka::sasl::SaslConfig;
+use kafka::storage::log_dir::{format_log_dir, prepare_log_dir};
+use kafka::storage::segment_handles::SegmentHandleCache;
+use kafka::throughput::{ThroughputConfig, ThroughputTracker};
+use kafka::topic_metrics::{TopicMetrics, TopicMetricsConfig};
+use logging::{info, warn, LogUtils, Logger};
+use network::debug_client::debug_request;
 use network::server::NetworkServer;
+use protocol::trace::{trace_request, trace_response, DecodeTrace};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging system
     Logger::init_with_env()?;
 
-    let addr: SocketAddr = "127.0.0.1:9092".parse()?;
-    let broker = KafkaBroker::new();
-    let server = NetworkServer::new(broker);
+    let options = CliOptions::parse(std::env::args().skip(1))?;
+    let properties = match &options.config_path {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)?;
+            check_config_file(&contents, options.strict_config)?;
+            parse_properties(&contents)
+        }
+        None => Vec::new(),
+    };
+    let config = BrokerConfig::from_properties(&properties);
+    let log_dir = options
+        .log_dir
+        .clone()
+        .or_else(|| config.log_dir())
+        .unwrap_or_default();
+    let node_id = match options.node_id {
+        Some(node_id) => node_id,
+        None => config.node_id()?,
+    };
+
+    if let (Some(command), Some(debug_addr)) = (&options.config_command, options.debug_addr) {
+        let (target, body) = match command {
+            ConfigCommand::Export {
+                include_secrets, ..
+            } => (
+                format!("/dynamic-config?include_secrets={}", include_secrets),
+                None,
+            ),
+            ConfigCommand::Import { input } => {
+                ("/dynamic-config".to_string(), Some(std::fs::read(input)?))
+            }
+        };
+        let method = if body.is_some() { "PUT" } else { "GET" };
+        let reply =
+            debug_request(debug_addr, method, &target, body.as_deref().unwrap_or(&[])).await?;
+        if reply.status != 200 {
+            return Err(anyhow::anyhow!(
+                "debug endpoint answered {}: {}",
+                reply.status,
+                reply.body
+            ));
+        }
+        match command {
+            ConfigCommand::Export {
+                output: Some(output),
+                ..
+            } => std::fs::write(output, &reply.body)?,
+            _ => println!("{}", reply.body),
+        }
+        return Ok(());
+    }
+
+    if let Some(capture_path) = &options.replay {
+        let capture = Capture::parse(&std::fs::read_to_string(capture_path)?)?;
+        let timing = if options.original_timing {
+            ReplayTiming::Original
+        } else {
+            ReplayTiming::AsFastAsPossible
+        };
+        let report = replay(&capture, timing).await?;
+        for diff in &report.diffs {
+            println!(
+                "#{} api_key={} v{} correlation_id={}",
+                diff.index, diff.api_key, diff.api_version, diff.correlation_id
+            );
+            for difference in &diff.differences {
+                println!("  {}", difference);
+            }
+        }
+        println!(
+            "Replayed {} requests, {} responses differ",
+            report.exchanges,
+            report.diffs.len()
+        );
+        if !report.is_clean() {
+            return Err(anyhow::anyhow!("replay found differences"));
+        }
+        return Ok(());
+    }
+
+    if let Some(frame) = &options.decode_hex {
+        let frame = hex::decode(frame)?;
+        let mut trace = DecodeTrace::new();
+        let result = match options.response_to {
+            Some((api_key, version)) => trace_response(api_key, version, &frame, &mut trace),
+            None => trace_request(&frame, &mut trace),
+        };
+        print!("{}", trace.render(&frame));
+        return result.map_err(|e| anyhow::anyhow!("decoding stopped at {}: {}", trace.end(), e));
+    }
+
+    if options.format {
+        let meta = format_log_dir(&log_dir, node_id, options.force)?;
+        println!(
+            "Formatted {} with cluster id {}",
+            log_dir.display(),
+            meta.cluster_id
+        );
+        return Ok(());
+    }
+
+    let limits = Limits::from_properties(&properties)?;
+    limits.decode.install();
+
+    let addr = config.listen_addr()?;
+    let log_config = config.log_config()?;
+    let auto_format = config.get("auto.format.empty.dirs") == Some("true");
+    let throughput = ThroughputConfig::from_properties(&properties)?;
+    let mut broker = KafkaBroker::new()
+        .with_node_id(node_id)
+        .with_config(config)
+        .with_log_dir(&log_dir)
+        .with_log_config(log_config)
+        .with_segment_handles(Arc::new(SegmentHandleCache::from_properties(&properties)?))
+        .with_limits(Arc::new(limits))
+        .with_diagnostics_level(DiagnosticsLevel::from_properties(&properties)?)
+        .with_throughput(ThroughputTracker::new(throughput))
+        .with_topic_metrics(TopicMetrics::new(TopicMetricsConfig::from_properties(
+            &properties,
+        )?))
+        .with_delivery_latency(DeliveryLatencyTracker::from_properties(&properties)?)
+        .with_quarantine(Quarantine::new(QuarantineConfig::from_properties(
+            &properties,
+            &log_dir,
+        )?))
+        .with_purgatory(Purgatory::new(PurgatoryConfig::from_properties(
+            &properties,
+        )?))
+        .with_request_queue(RequestQueue::new(RequestQueueConfig::from_properties(
+            &properties,
+        )?))
+        .with_sasl(SaslConfig::from_properties(&properties)?);
+    if let Some(rack) = broker_property(&properties, "broker.rack").filter(|r| !r.is_empty()) {
+        broker = broker.with_rack(rack);
+    }
+    match prepare_log_dir(&log_dir, node_id, auto_format) {
+        Ok(Some(bootstrap)) => {
+            info!(
+                log_dir = %log_dir.display(),
+                cluster_id = %bootstrap.cluster_id(),
+                metadata_version = ?bootstrap.metadata_version(),
+                topics = bootstrap.cluster.topics.len(),
+                "Loaded log directory"
+            );
+            broker = broker
+                .with_cluster_id(bootstrap.cluster_id())
+                .with_cluster_metadata(&bootstrap.cluster);
+            match MetadataEpoch::load(&log_dir) {
+                Ok(metadata_epoch) => broker = broker.with_metadata_epoch(metadata_epoch),
+                Err(e) => {
+                    warn!(log_dir = %log_dir.display(), error = %e, "Failed to load metadata epoch");
+                    broker
+                        .health()
+                        .mark_log_dir_failed(log_dir.display().to_string(), e.to_string());
+                }
+            }
+            match ProducerIdManager::load(&log_dir) {
+                Ok(producer_ids) => broker = broker.with_producer_ids(producer_ids),
+                Err(e) => {
+                    warn!(log_dir = %log_dir.display(), error = %e, "Failed to load producer ids");
+                    broker
+                        .health()
+                        .mark_log_dir_failed(log_dir.display().to_string(), e.to_string());
+                }
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            warn!(log_dir = %log_dir.display(), error = %e, "Failed to load log directory");
+            broker
+                .health()
+                .mark_log_dir_failed(log_dir.display().to_string(), e.to_string());
+        }
+    }
+    broker.health().mark_recovery_complete();
+
+    let server = NetworkServer::new(broker).with_dump_dir(&log_dir);
+    if let Some(debug_addr) = options.debug_addr {
+        let state_dump = broker_property(&properties, "debug.state.dump.enable") == Some("true");
+        server.start_debug_endpoint(debug_addr, state_dump).await?;
+    }
 
     // Log server startup
     LogUtils::log_server_startup(&addr);
@@ -0,0 +1,70 @@
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Response read back from the debug endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugReply {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Sends one request to a broker's debug endpoint and reads the reply
+///
+/// The endpoint closes the connection after each response, so the reply
+/// is read to the end.
+///
+/// # Examples
+/// ```
+/// use codecrafters_kafka::kafka::broker::KafkaBroker;
+/// use codecrafters_kafka::network::debug_client::debug_request;
+/// use codecrafters_kafka::network::debug_endpoint::DebugEndpoint;
+/// use std::sync::Arc;
+/// use tokio::net::TcpListener;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> anyhow::Result<()> {
+/// let listener = TcpListener::bind("127.0.0.1:0").await?;
+/// let addr = listener.local_addr()?;
+/// let endpoint = Arc::new(DebugEndpoint::new(Arc::new(KafkaBroker::new())));
+/// tokio::spawn(endpoint.serve(listener));
+///
+/// let reply = debug_request(addr, "GET", "/connections", &[]).await?;
+/// assert_eq!((reply.status, reply.body.as_str()), (200, "[]"));
+/// # Ok(())
+/// # }
+/// ```
+pub async fn debug_request(
+    addr: SocketAddr,
+    method: &str,
+    target: &str,
+    body: &[u8],
+) -> Result<DebugReply> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        target,
+        addr,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("malformed response from debug endpoint {}", addr))?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| anyhow!("malformed status line from debug endpoint {}", addr))?;
+    Ok(DebugReply {
+        status,
+        body: body.to_string(),
+    })
+}
@@ -0,0 +1,676 @@
+use crate::kafka::broker::KafkaBroker;
+use crate::kafka::connection_registry::{ConnectionError, ConnectionFilter};
+use crate::kafka::diagnostics::{DiagnosticFeature, DiagnosticsLevel};
+use crate::kafka::dynamic_config::DynamicConfigDocument;
+use crate::kafka::health::HealthStatus;
+use crate::logging::{debug, info, warn};
+use crate::protocol::decode_limits::non_canonical_varint_count;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Response produced by a debug endpoint route
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugResponse {
+    pub status: u16,
+    pub body: Value,
+    /// Set for bodies sent as text rather than JSON; `body` is then a
+    /// JSON string holding the text
+    pub content_type: Option<&'static str>,
+}
+
+impl DebugResponse {
+    fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            body,
+            content_type: None,
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.into() }),
+            content_type: None,
+        }
+    }
+
+    /// Prometheus text exposition
+    fn prometheus(text: String) -> Self {
+        Self {
+            status: 200,
+            body: Value::String(text),
+            content_type: Some("text/plain; version=0.0.4"),
+        }
+    }
+
+    fn reason_phrase(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// Minimal HTTP endpoint for operator diagnostics and actions
+///
+/// Routes:
+/// - `GET /connections[?client_id=..&ip=..]` lists live connections
+/// - `POST /connections/<id>/close[?reason=..]` closes one connection after
+///   its in-flight request completes
+/// - `GET /metrics` reports broker counters such as response cache hits
+/// - `GET /metrics/prometheus` exposes per-topic traffic counters and
+///   delivery latency histograms in the Prometheus text format
+/// - `GET /limits` reports the effective broker limits
+/// - `GET /events` lists the latest broker events, oldest first
+/// - `GET /partitions/hot[?k=..]` lists the busiest partitions of the last
+///   throughput window (10 by default)
+/// - `GET /healthz` is 200 while the accept loop is alive (liveness)
+/// - `GET /readyz` is 200 while the broker can serve data (readiness)
+/// - `GET /dynamic-config[?include_secrets=true]` exports runtime
+///   configuration changes as a versioned document, with sensitive values
+///   replaced by references unless asked for
+/// - `PUT /dynamic-config` imports such a document, all or nothing
+/// - `GET /diagnostics` reports the diagnostics level and which features
+///   it turns on; `PUT /diagnostics?level=..` changes it
+/// - `GET /dump` returns a snapshot of broker state, only when enabled with
+///   `debug.state.dump.enable` since it exposes topic names and client ids
+///
+/// Only meant to be bound to a loopback or otherwise trusted address.
+pub struct DebugEndpoint {
+    broker: Arc<KafkaBroker>,
+    state_dump_enabled: bool,
+}
+
+impl DebugEndpoint {
+    /// Creates an endpoint serving the given broker
+    pub fn new(broker: Arc<KafkaBroker>) -> Self {
+        Self {
+            broker,
+            state_dump_enabled: false,
+        }
+    }
+
+    /// Enables the `GET /dump` route
+    pub fn with_state_dump(mut self, enabled: bool) -> Self {
+        self.state_dump_enabled = enabled;
+        self
+    }
+
+    /// Accepts and serves HTTP requests until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        info!(addr = %listener.local_addr()?, "Debug endpoint listening");
+        loop {
+            let (stream, peer_addr) = listener.accept().await?;
+            let endpoint = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = endpoint.handle(stream, peer_addr).await {
+                    warn!(peer_addr = %peer_addr, error = %e, "Debug endpoint request failed");
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream, peer_addr: SocketAddr) -> Result<()> {
+        let mut head = Vec::new();
+        let mut chunk = [0u8; 1024];
+        let max_head = self.broker.limits().debug_max_request_head_bytes;
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            if head.len() > max_head {
+                return Err(anyhow::anyhow!("request head too large"));
+            }
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                return Err(anyhow::anyhow!("connection closed before request head"));
+            }
+            head.extend_from_slice(&chunk[..read]);
+        }
+
+        let head_end = head
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .map_or(head.len(), |position| position + 4);
+        let mut body = head.split_off(head_end);
+        let head = String::from_utf8_lossy(&head);
+        let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+        let method = request_line.next().unwrap_or("");
+        let target = request_line.next().unwrap_or("");
+        debug!(peer_addr = %peer_addr, method = method, target = target, "Debug endpoint request");
+
+        let content_length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        let max_body = self.broker.limits().debug_max_request_body_bytes;
+        let response = if content_length > max_body {
+            DebugResponse::error(413, format!("request body over {} bytes", max_body))
+        } else {
+            while body.len() < content_length {
+                let read = stream.read(&mut chunk).await?;
+                if read == 0 {
+                    return Err(anyhow::anyhow!("connection closed before request body"));
+                }
+                body.extend_from_slice(&chunk[..read]);
+            }
+            body.truncate(content_length);
+            self.route_with_body(method, target, &body)
+        };
+        let (content_type, body) = match (response.content_type, &response.body) {
+            (Some(content_type), Value::String(text)) => (content_type, text.clone()),
+            _ => ("application/json", response.body.to_string()),
+        };
+        let reply = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            response.reason_phrase(),
+            content_type,
+            body.len(),
+            body
+        );
+        stream.write_all(reply.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Maps a health check to 200 or 503 with the failing condition named
+    fn probe(status: HealthStatus) -> DebugResponse {
+        DebugResponse {
+            status: if status.healthy { 200 } else { 503 },
+            body: json!(status),
+            content_type: None,
+        }
+    }
+
+    /// The current diagnostics level and the features it turns on
+    fn diagnostics(&self) -> DebugResponse {
+        let level = self.broker.dynamic_config().diagnostics().level();
+        let features: serde_json::Map<String, Value> = DiagnosticFeature::ALL
+            .into_iter()
+            .map(|feature| (feature.as_str().to_string(), json!(level.allows(feature))))
+            .collect();
+        DebugResponse::ok(json!({ "level": level, "features": features }))
+    }
+
+    /// Dispatches one request without a body to its route
+    #[cfg(test)]
+    pub fn route(&self, method: &str, target: &str) -> DebugResponse {
+        self.route_with_body(method, target, &[])
+    }
+
+    /// Dispatches one request to its route
+    pub fn route_with_body(&self, method: &str, target: &str, body: &[u8]) -> DebugResponse {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let params = parse_query(query);
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (method, segments.as_slice()) {
+            ("GET", ["connections"]) => {
+                let ip = match param("ip").map(|ip| ip.parse::<IpAddr>()) {
+                    Some(Ok(ip)) => Some(ip),
+                    Some(Err(_)) => return DebugResponse::error(400, "invalid ip filter"),
+                    None => None,
+                };
+                let filter = ConnectionFilter {
+                    client_id: param("client_id"),
+                    ip,
+                };
+                DebugResponse::ok(json!(self.broker.connections().list(&filter)))
+            }
+            ("POST", ["connections", id, "close"]) => {
+                let Ok(id) = id.parse() else {
+                    return DebugResponse::error(400, "invalid connection id");
+                };
+                let reason = param("reason").unwrap_or_else(|| "unspecified".to_string());
+                match self.broker.connections().request_close(id, &reason) {
+                    Ok(()) => DebugResponse::ok(json!({ "closing": id, "reason": reason })),
+                    Err(e @ ConnectionError::NotFound(_)) => {
+                        DebugResponse::error(404, e.to_string())
+                    }
+                }
+            }
+            ("GET", ["metrics"]) => DebugResponse::ok(json!({
+                "metadata_epoch": self.broker.metadata_epoch().current(),
+                "response_cache": self.broker.response_cache().metrics(),
+                "request_queue": self.broker.request_queue().metrics(),
+                "segment_handles": self.broker.topics().segment_handles().metrics(),
+                "fetch_sessions": self.broker.fetch_sessions().len(),
+                "delivery_latency": self.broker.delivery_latency().summary(),
+                "event_subscribers": self.broker.events().subscriber_count(),
+                "cancelled_by_disconnect": self.broker.cancelled_by_disconnect(),
+                "non_canonical_varints": non_canonical_varint_count(),
+            })),
+            ("GET", ["metrics", "prometheus"]) => {
+                let topic_metrics = self.broker.topic_metrics();
+                let mut text = topic_metrics.render_prometheus();
+                text.push_str(
+                    &self
+                        .broker
+                        .delivery_latency()
+                        .render_prometheus(|topic| topic_metrics.label(topic)),
+                );
+                DebugResponse::prometheus(text)
+            }
+            ("GET", ["healthz"]) => Self::probe(self.broker.health().liveness()),
+            ("GET", ["readyz"]) => Self::probe(self.broker.readiness()),
+            ("GET", ["diagnostics"]) => self.diagnostics(),
+            ("PUT", ["diagnostics"]) => {
+                let level = match param("level").map(|level| level.parse::<DiagnosticsLevel>()) {
+                    Some(Ok(level)) => level,
+                    Some(Err(e)) => return DebugResponse::error(400, e),
+                    None => return DebugResponse::error(400, "missing level"),
+                };
+                let previous = self.broker.dynamic_config().diagnostics().set_level(level);
+                info!(from = %previous, to = %level, "Diagnostics level changed");
+                self.diagnostics()
+            }
+            ("GET", ["dump"]) if !self.state_dump_enabled => {
+                DebugResponse::error(403, "state dump disabled (debug.state.dump.enable)")
+            }
+            ("GET", ["dump"]) => DebugResponse::ok(json!(self.broker.dump())),
+            ("GET", ["partitions", "hot"]) => match param("k").map(|k| k.parse::<usize>()) {
+                Some(Err(_)) => DebugResponse::error(400, "invalid k"),
+                k => DebugResponse::ok(json!(self
+                    .broker
+                    .throughput()
+                    .top_partitions(k.and_then(Result::ok).unwrap_or(10)))),
+            },
+            ("GET", ["limits"]) => DebugResponse::ok(json!(self.broker.limits().describe())),
+            ("GET", ["events"]) => DebugResponse::ok(json!(self.broker.recent_events().snapshot())),
+            ("GET", ["dynamic-config"]) => {
+                let include_secrets = param("include_secrets").as_deref() == Some("true");
+                DebugResponse::ok(json!(self.broker.dynamic_config().export(include_secrets)))
+            }
+            ("PUT", ["dynamic-config"]) => {
+                let document = match serde_json::from_slice::<DynamicConfigDocument>(body) {
+                    Ok(document) => document,
+                    Err(e) => return DebugResponse::error(400, format!("invalid document: {}", e)),
+                };
+                match self.broker.import_dynamic_config(&document) {
+                    Ok(summary) => DebugResponse::ok(json!(summary)),
+                    Err(e) => DebugResponse::error(400, e.to_string()),
+                }
+            }
+            (_, ["connections"])
+            | (_, ["connections", _, "close"])
+            | (_, ["metrics"])
+            | (_, ["metrics", "prometheus"])
+            | (_, ["healthz"])
+            | (_, ["readyz"])
+            | (_, ["diagnostics"])
+            | (_, ["dump"])
+            | (_, ["partitions", "hot"])
+            | (_, ["limits"])
+            | (_, ["events"])
+            | (_, ["dynamic-config"]) => DebugResponse::error(405, "method not allowed"),
+            _ => {
+                debug!(method = method, path = path, "Unknown debug endpoint route");
+                DebugResponse::error(404, "not found")
+            }
+        }
+    }
+}
+
+/// Splits a query string into decoded key/value pairs
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::clock::{Clock, SystemClock};
+    use crate::kafka::test_util::{frame, read_response, spawn_connection_with};
+    use crate::protocol::{ProtocolEncode, RequestHeaderV2};
+    use std::collections::BTreeMap;
+    use tokio::io::AsyncReadExt;
+
+    fn api_versions_frame(correlation_id: i32, client_id: &str) -> Vec<u8> {
+        let header = RequestHeaderV2::with_client_id(18, 0, correlation_id, client_id);
+        frame(&header.encode().unwrap())
+    }
+
+    #[test]
+    fn test_percent_decoding() {
+        assert_eq!(percent_decode("too+many%20requests"), "too many requests");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[tokio::test]
+    async fn test_close_one_connection_leaves_other_untouched() {
+        let broker = Arc::new(KafkaBroker::new());
+        let endpoint = DebugEndpoint::new(Arc::clone(&broker));
+        let (mut noisy, noisy_handle) = spawn_connection_with(Arc::clone(&broker));
+        let (mut quiet, quiet_handle) = spawn_connection_with(Arc::clone(&broker));
+
+        noisy
+            .write_all(&api_versions_frame(1, "noisy"))
+            .await
+            .unwrap();
+        read_response(&mut noisy).await;
+        quiet
+            .write_all(&api_versions_frame(1, "quiet"))
+            .await
+            .unwrap();
+        read_response(&mut quiet).await;
+
+        let listed = endpoint.route("GET", "/connections?client_id=noisy");
+        assert_eq!(listed.status, 200);
+        let connections = listed.body.as_array().unwrap();
+        assert_eq!(connections.len(), 1);
+        let id = connections[0]["id"].as_u64().unwrap();
+
+        // Close mid-stream: anything already being processed is answered,
+        // then the stream ends cleanly
+        noisy
+            .write_all(&api_versions_frame(2, "noisy"))
+            .await
+            .unwrap();
+        let closed = endpoint.route("POST", &format!("/connections/{}/close?reason=abuse", id));
+        assert_eq!(closed.status, 200);
+        let mut rest = Vec::new();
+        noisy.read_to_end(&mut rest).await.unwrap();
+        assert!(noisy_handle.await.unwrap().is_ok());
+
+        // The other connection keeps working
+        quiet
+            .write_all(&api_versions_frame(3, "quiet"))
+            .await
+            .unwrap();
+        let response = read_response(&mut quiet).await;
+        assert_eq!(&response[0..4], &3i32.to_be_bytes());
+        assert_eq!(broker.connections().len(), 1);
+
+        // Closing the finished connection again is reported, not a panic
+        let again = endpoint.route("POST", &format!("/connections/{}/close", id));
+        assert_eq!(again.status, 404);
+
+        drop(quiet);
+        assert!(quiet_handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_serves_http_over_tcp() {
+        let broker = Arc::new(KafkaBroker::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Arc::new(DebugEndpoint::new(broker)).serve(listener));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /connections HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("[]"));
+    }
+
+    #[test]
+    fn test_bad_requests() {
+        let endpoint = DebugEndpoint::new(Arc::new(KafkaBroker::new()));
+        assert_eq!(endpoint.route("POST", "/connections/abc/close").status, 400);
+        assert_eq!(endpoint.route("DELETE", "/connections").status, 405);
+        assert_eq!(endpoint.route("GET", "/nope").status, 404);
+        assert_eq!(endpoint.route("GET", "/connections?ip=bogus").status, 400);
+        assert_eq!(endpoint.route("POST", "/metrics").status, 405);
+    }
+
+    #[test]
+    fn test_failed_log_dir_flips_readyz_but_not_healthz() {
+        let broker = Arc::new(KafkaBroker::new());
+        let endpoint = DebugEndpoint::new(Arc::clone(&broker));
+        broker.health().heartbeat();
+        assert_eq!(endpoint.route("GET", "/readyz").status, 503);
+        broker.health().mark_recovery_complete();
+        assert_eq!(endpoint.route("GET", "/readyz").status, 200);
+
+        broker
+            .health()
+            .mark_log_dir_failed("/var/lib/kafka", "read-only file system");
+        let readyz = endpoint.route("GET", "/readyz");
+        assert_eq!(readyz.status, 503);
+        assert_eq!(
+            readyz.body["reason"],
+            "log directory /var/lib/kafka failed: read-only file system"
+        );
+        let healthz = endpoint.route("GET", "/healthz");
+        assert_eq!(healthz.status, 200);
+        assert_eq!(healthz.body["healthy"], true);
+    }
+
+    #[test]
+    fn test_dump_requires_opt_in() {
+        let broker = Arc::new(KafkaBroker::new().with_cluster_id("dump-cluster"));
+        let disabled = DebugEndpoint::new(Arc::clone(&broker));
+        assert_eq!(disabled.route("GET", "/dump").status, 403);
+
+        let enabled = DebugEndpoint::new(broker).with_state_dump(true);
+        let dump = enabled.route("GET", "/dump");
+        assert_eq!(dump.status, 200);
+        assert_eq!(dump.body["cluster_id"], "dump-cluster");
+    }
+
+    #[test]
+    fn test_hot_partitions_lists_busiest_first() {
+        let broker = Arc::new(KafkaBroker::new());
+        let endpoint = DebugEndpoint::new(Arc::clone(&broker));
+        for (partition, bytes) in [(0, 100), (1, 900), (2, 300)] {
+            broker
+                .throughput()
+                .record_produce("orders", partition, Some("loader"), bytes, 1);
+        }
+        broker.throughput().evaluate();
+
+        let hot = endpoint.route("GET", "/partitions/hot?k=2");
+        assert_eq!(hot.status, 200);
+        let partitions: Vec<i64> = hot
+            .body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|partition| partition["partition"].as_i64().unwrap())
+            .collect();
+        assert_eq!(partitions, vec![1, 2]);
+        assert_eq!(endpoint.route("GET", "/partitions/hot?k=x").status, 400);
+    }
+
+    #[test]
+    fn test_events_lists_recent_broker_events() {
+        let broker = Arc::new(KafkaBroker::new());
+        broker
+            .topics()
+            .create_topic("orders", 2, BTreeMap::new())
+            .unwrap();
+        let endpoint = DebugEndpoint::new(Arc::clone(&broker));
+
+        let response = endpoint.route("GET", "/events");
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.body["events"][0],
+            json!({"type": "TopicCreated", "topic": "orders", "partitions": 2})
+        );
+        assert_eq!(response.body["dropped"], 0);
+        assert_eq!(endpoint.route("POST", "/events").status, 405);
+    }
+
+    #[test]
+    fn test_metrics_report_response_cache() {
+        let endpoint = DebugEndpoint::new(Arc::new(KafkaBroker::new()));
+        let metrics = endpoint.route("GET", "/metrics");
+        assert_eq!(metrics.status, 200);
+        assert_eq!(metrics.body["response_cache"]["hits"], 0);
+    }
+
+    #[test]
+    fn test_metrics_report_request_queue() {
+        let endpoint = DebugEndpoint::new(Arc::new(KafkaBroker::new()));
+        let metrics = endpoint.route("GET", "/metrics");
+        assert_eq!(metrics.body["request_queue"]["workers"], 0);
+        assert_eq!(metrics.body["request_queue"]["control"]["depth"], 0);
+        assert_eq!(metrics.body["request_queue"]["data"]["dispatched"], 0);
+    }
+
+    #[test]
+    fn test_diagnostics_level_is_changed_at_runtime() {
+        let broker = Arc::new(KafkaBroker::new());
+        let endpoint = DebugEndpoint::new(Arc::clone(&broker));
+
+        let current = endpoint.route("GET", "/diagnostics");
+        assert_eq!(current.body["level"], "basic");
+        assert_eq!(current.body["features"]["quarantine"], true);
+        assert_eq!(current.body["features"]["hex_dump"], false);
+
+        let changed = endpoint.route("PUT", "/diagnostics?level=verbose");
+        assert_eq!(changed.status, 200);
+        assert_eq!(changed.body["features"]["hex_dump"], true);
+        assert_eq!(changed.body["features"]["capture"], false);
+        assert_eq!(
+            broker.dynamic_config().diagnostics().level(),
+            DiagnosticsLevel::Verbose
+        );
+
+        assert_eq!(endpoint.route("PUT", "/diagnostics?level=loud").status, 400);
+        assert_eq!(endpoint.route("PUT", "/diagnostics").status, 400);
+        assert_eq!(endpoint.route("POST", "/diagnostics").status, 405);
+        assert_eq!(
+            endpoint.route("GET", "/diagnostics").body["level"],
+            "verbose"
+        );
+    }
+
+    #[test]
+    fn test_prometheus_exposition_of_topic_metrics() {
+        let broker = Arc::new(KafkaBroker::new());
+        let endpoint = DebugEndpoint::new(Arc::clone(&broker));
+        broker.topic_metrics().record_produce("orders", 100, 2);
+        broker
+            .delivery_latency()
+            .record_fetched_batch("orders", SystemClock.now_ms());
+
+        let response = endpoint.route("GET", "/metrics/prometheus");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.content_type, Some("text/plain; version=0.0.4"));
+        let text = response.body.as_str().unwrap();
+        assert!(text.contains("kafka_topic_produce_records_total{topic=\"orders\"} 2"));
+        assert!(text.contains("kafka_topic_delivery_latency_ms_count{topic=\"orders\"} 1"));
+        let metrics = endpoint.route("GET", "/metrics");
+        assert_eq!(metrics.body["delivery_latency"][0]["count"], 1);
+        assert_eq!(endpoint.route("POST", "/metrics/prometheus").status, 405);
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_config_export_and_import_over_http() {
+        let source = Arc::new(KafkaBroker::new());
+        source
+            .dynamic_config()
+            .set_topic_config("orders", "retention.ms", "3600000")
+            .unwrap();
+        let exported = DebugEndpoint::new(source).route("GET", "/dynamic-config");
+        assert_eq!(exported.status, 200);
+        assert_eq!(exported.body["format_version"], 1);
+
+        let target = Arc::new(KafkaBroker::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Arc::new(DebugEndpoint::new(Arc::clone(&target))).serve(listener));
+
+        // Through the client `config import` uses
+        let body = exported.body.to_string();
+        let reply = crate::network::debug_client::debug_request(
+            addr,
+            "PUT",
+            "/dynamic-config",
+            body.as_bytes(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reply.status, 200, "{}", reply.body);
+        assert_eq!(
+            target
+                .dynamic_config()
+                .topic_config("orders")
+                .get("retention.ms"),
+            Some("3600000")
+        );
+
+        // One import is one metadata change; a rejected one is none
+        let endpoint = DebugEndpoint::new(target);
+        assert_eq!(endpoint.route("GET", "/metrics").body["metadata_epoch"], 1);
+        assert_eq!(
+            endpoint
+                .route_with_body("PUT", "/dynamic-config", b"{\"format_version\":9}")
+                .status,
+            400
+        );
+        assert_eq!(endpoint.route("GET", "/metrics").body["metadata_epoch"], 1);
+        assert_eq!(endpoint.route("POST", "/dynamic-config").status, 405);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_is_rejected() {
+        let broker = Arc::new(KafkaBroker::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Arc::new(DebugEndpoint::new(broker)).serve(listener));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"PUT /dynamic-config HTTP/1.1\r\nContent-Length: 999999999\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    }
+}
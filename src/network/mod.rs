@@ -1 +1,3 @@
+pub mod debug_client;
+pub mod debug_endpoint;
 pub mod server;
@@ -1,7 +1,12 @@
 use crate::kafka::broker::KafkaBroker;
+use crate::kafka::group_coordinator::SESSION_EXPIRY_INTERVAL;
+use crate::kafka::health::ACCEPT_HEARTBEAT_INTERVAL;
+use crate::kafka::producer_state::PRODUCER_EXPIRY_CHECK_INTERVAL;
 use crate::logging::{error, info, warn, LogUtils};
+use crate::network::debug_endpoint::DebugEndpoint;
 use anyhow::Result;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::net::{TcpListener, TcpStream};
@@ -9,6 +14,10 @@ use tokio::signal;
 use tokio::sync::{broadcast, Notify};
 use tokio::time::{timeout, Duration};
 
+/// How often retention runs when `log.retention.check.interval.ms` is not
+/// a usable interval
+const DEFAULT_RETENTION_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
 /// Network server responsible for handling TCP connections
 ///
 /// This struct follows the Single Responsibility Principle by focusing
@@ -17,6 +26,8 @@ use tokio::time::{timeout, Duration};
 /// KafkaBroker abstraction rather than concrete implementations.
 pub struct NetworkServer {
     broker: Arc<KafkaBroker>,
+    /// Directory state dumps are written to on SIGUSR1
+    dump_dir: PathBuf,
 }
 
 impl NetworkServer {
@@ -24,17 +35,59 @@ impl NetworkServer {
     pub fn new(broker: KafkaBroker) -> Self {
         Self {
             broker: Arc::new(broker),
+            dump_dir: std::env::temp_dir(),
         }
     }
 
+    /// Writes SIGUSR1 state dumps to `dir` instead of the temp directory
+    pub fn with_dump_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dump_dir = dir.into();
+        self
+    }
+
+    /// Binds the debug endpoint on `addr` and serves it in the background
+    ///
+    /// `state_dump` enables its `GET /dump` route.
+    pub async fn start_debug_endpoint(&self, addr: SocketAddr, state_dump: bool) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        let endpoint =
+            Arc::new(DebugEndpoint::new(Arc::clone(&self.broker)).with_state_dump(state_dump));
+        tokio::spawn(async move {
+            if let Err(e) = endpoint.serve(listener).await {
+                error!(error = %e, "Debug endpoint stopped");
+            }
+        });
+        Ok(())
+    }
+
     /// Starts the server and listens for incoming connections with graceful shutdown
     ///
     /// This method sets up the TCP listener and handles incoming connections
     /// asynchronously, delegating request processing to the broker.
     /// It supports graceful shutdown via SIGINT (Ctrl+C) and SIGTERM signals.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use codecrafters_kafka::kafka::broker::KafkaBroker;
+    /// use codecrafters_kafka::network::server::NetworkServer;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let server = NetworkServer::new(KafkaBroker::new()).with_dump_dir("/var/lib/kafka");
+    /// // Runs until SIGINT or SIGTERM
+    /// server.start("127.0.0.1:9092".parse()?).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn start(&self, addr: SocketAddr) -> Result<()> {
         let listener = TcpListener::bind(addr).await?;
-        info!(addr = %addr, "Server listening for connections");
+        // Advertise the bound address, which differs from `addr` for port 0
+        self.broker.set_listen_addr(listener.local_addr()?);
+        info!(
+            addr = %addr,
+            cluster_id = ?self.broker.cluster_id(),
+            "Server listening for connections"
+        );
 
         // Create shutdown coordination primitives
         let (shutdown_tx, mut shutdown_rx) = broadcast::channel::<()>(1);
@@ -56,9 +109,94 @@ impl NetworkServer {
             }
         });
 
+        #[cfg(unix)]
+        {
+            let broker = Arc::clone(&self.broker);
+            let dump_dir = self.dump_dir.clone();
+            tokio::spawn(async move {
+                if let Err(e) = Self::dump_state_on_signal(broker, dump_dir).await {
+                    error!(error = %e, "Error setting up state dump signal handler");
+                }
+            });
+            let broker = Arc::clone(&self.broker);
+            tokio::spawn(async move {
+                if let Err(e) = Self::cycle_diagnostics_on_signal(broker).await {
+                    error!(error = %e, "Error setting up diagnostics signal handler");
+                }
+            });
+        }
+
+        // Remove group members that stopped heartbeating
+        let broker = Arc::clone(&self.broker);
+        tokio::spawn(async move {
+            broker
+                .groups()
+                .expire_sessions_every(SESSION_EXPIRY_INTERVAL)
+                .await;
+        });
+
+        // Heartbeat so liveness probes can tell the accept loop is turning
+        let mut heartbeat = tokio::time::interval(ACCEPT_HEARTBEAT_INTERVAL);
+
+        // Close a throughput window on every tick to look for hot partitions
+        let throughput_window = self.broker.throughput().config().window;
+        let mut throughput = tokio::time::interval_at(
+            tokio::time::Instant::now() + throughput_window,
+            throughput_window,
+        );
+
+        // Choose which topics get their own metrics series
+        let rerank_interval = self.broker.topic_metrics().config().rerank_interval;
+        let mut topic_metrics = tokio::time::interval_at(
+            tokio::time::Instant::now() + rerank_interval,
+            rerank_interval,
+        );
+
+        // Forget idempotent producers that stopped producing
+        let mut producer_expiry = tokio::time::interval_at(
+            tokio::time::Instant::now() + PRODUCER_EXPIRY_CHECK_INTERVAL,
+            PRODUCER_EXPIRY_CHECK_INTERVAL,
+        );
+
+        // Delete batches past their topic's retention
+        let retention_check = match self.broker.config().log_retention_check_interval() {
+            Ok(interval) if !interval.is_zero() => interval,
+            _ => {
+                warn!(
+                    interval_ms = DEFAULT_RETENTION_CHECK_INTERVAL.as_millis() as u64,
+                    "Invalid log.retention.check.interval.ms, using the default"
+                );
+                DEFAULT_RETENTION_CHECK_INTERVAL
+            }
+        };
+        let mut retention = tokio::time::interval_at(
+            tokio::time::Instant::now() + retention_check,
+            retention_check,
+        );
+
         // Main server loop
         loop {
             tokio::select! {
+                _ = throughput.tick() => {
+                    self.broker.throughput().evaluate();
+                }
+
+                _ = topic_metrics.tick() => {
+                    self.broker.topic_metrics().rerank();
+                }
+
+                _ = producer_expiry.tick() => {
+                    self.broker.expire_idle_producers();
+                }
+
+                _ = retention.tick() => {
+                    self.broker.enforce_retention();
+                }
+
+                _ = heartbeat.tick() => {
+                    self.broker.health().heartbeat();
+                }
+
                 // Handle incoming connections
                 accept_result = listener.accept() => {
                     match accept_result {
@@ -67,7 +205,6 @@ impl NetworkServer {
 
                             // Spawn a task to handle this connection
                             let broker_clone = Arc::clone(&self.broker);
-                            let mut connection_shutdown = shutdown_tx.subscribe();
                             let active_connections_clone = active_connections.clone();
 
                             tokio::spawn(async move {
@@ -75,18 +212,9 @@ impl NetworkServer {
                                 let span = LogUtils::connection_span(&peer_addr);
                                 let _enter = span.enter();
 
-                                // Handle the connection with shutdown awareness
-                                let result = tokio::select! {
-                                    // Normal connection handling
-                                    handle_result = Self::handle_connection_with_timeout(&broker_clone, stream, peer_addr) => {
-                                        handle_result
-                                    }
-                                    // Shutdown signal received
-                                    _ = connection_shutdown.recv() => {
-                                        info!("Connection shutting down due to server shutdown");
-                                        Ok(())
-                                    }
-                                };
+                                // On shutdown the broker closes the connection
+                                // itself once the in-flight response is flushed
+                                let result = Self::handle_connection_with_timeout(&broker_clone, stream, peer_addr).await;
 
                                 let duration = connection_start.elapsed();
 
@@ -125,25 +253,34 @@ impl NetworkServer {
             }
         }
 
-        // Graceful shutdown: wait for active connections to finish
+        // Graceful shutdown: complete parked produces, then let every
+        // connection flush its in-flight response and close
         info!("Waiting for active connections to finish");
+        self.broker.begin_shutdown();
 
-        // Give connections up to 30 seconds to finish gracefully
         let shutdown_timeout = Duration::from_secs(30);
         let wait_result = timeout(shutdown_timeout, async {
-            // Wait for all connections to finish
-            // This is a simple approach - in a real implementation you might want
-            // to track the exact number of active connections
-            tokio::time::sleep(Duration::from_millis(100)).await;
+            while !self.broker.connections().is_empty() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
         })
         .await;
 
         match wait_result {
             Ok(_) => info!("All connections finished gracefully"),
-            Err(_) => warn!("Shutdown timeout reached, forcing exit"),
+            Err(_) => {
+                warn!(
+                    open_connections = self.broker.connections().len(),
+                    "Shutdown timeout reached, forcing exit"
+                );
+                self.broker.purgatory().log_pending();
+            }
         }
 
-        info!("Network server shutdown complete");
+        info!(
+            cancelled_by_disconnect = self.broker.cancelled_by_disconnect(),
+            "Network server shutdown complete"
+        );
         Ok(())
     }
 
@@ -153,15 +290,50 @@ impl NetworkServer {
         mut stream: TcpStream,
         peer_addr: SocketAddr,
     ) -> Result<()> {
-        // Set a reasonable timeout for connection handling
-        let connection_timeout = Duration::from_secs(300); // 5 minutes
-
-        timeout(connection_timeout, broker.handle_connection(&mut stream))
-            .await
-            .map_err(|_| {
-                warn!(timeout_sec = 300, "Connection timed out");
-                anyhow::anyhow!("Connection {} timed out", peer_addr)
-            })?
+        let connection_timeout = broker.limits().connection_timeout;
+
+        timeout(
+            connection_timeout,
+            broker.handle_connection(&mut stream, peer_addr),
+        )
+        .await
+        .map_err(|_| {
+            warn!(
+                timeout_sec = connection_timeout.as_secs(),
+                "Connection timed out"
+            );
+            anyhow::anyhow!("Connection {} timed out", peer_addr)
+        })?
+    }
+
+    /// Writes a state dump to `dump_dir` every time SIGUSR1 is received
+    #[cfg(unix)]
+    async fn dump_state_on_signal(broker: Arc<KafkaBroker>, dump_dir: PathBuf) -> Result<()> {
+        let mut sigusr1 = signal::unix::signal(signal::unix::SignalKind::user_defined1())?;
+        while sigusr1.recv().await.is_some() {
+            let snapshot = broker.dump();
+            let path = dump_dir.join(format!("state-dump-{}.json", snapshot.taken_at_ms));
+            match serde_json::to_vec_pretty(&snapshot)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| std::fs::write(&path, json).map_err(anyhow::Error::from))
+            {
+                Ok(()) => info!(path = %path.display(), "Wrote state dump"),
+                Err(e) => error!(path = %path.display(), error = %e, "Failed to write state dump"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves to the next diagnostics level every time SIGUSR2 is received,
+    /// wrapping from capture back to off
+    #[cfg(unix)]
+    async fn cycle_diagnostics_on_signal(broker: Arc<KafkaBroker>) -> Result<()> {
+        let mut sigusr2 = signal::unix::signal(signal::unix::SignalKind::user_defined2())?;
+        while sigusr2.recv().await.is_some() {
+            let level = broker.dynamic_config().diagnostics().cycle();
+            info!(diagnostics_level = %level, "Diagnostics level changed by SIGUSR2");
+        }
+        Ok(())
     }
 
     /// Wait for shutdown signals (SIGINT, SIGTERM)
@@ -193,3 +365,37 @@ impl NetworkServer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::config::BrokerConfig;
+    use bytes::Bytes;
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn test_retention_runs_on_the_server_tick() {
+        let properties = [(
+            "log.retention.check.interval.ms".to_string(),
+            "20".to_string(),
+        )];
+        let broker = KafkaBroker::new().with_config(BrokerConfig::from_properties(&properties));
+        let configs = BTreeMap::from([("retention.ms".to_string(), "0".to_string())]);
+        let topic = broker.topics().create_topic("orders", 1, configs).unwrap();
+        let log = &topic.partitions[0];
+        log.append(Bytes::from_static(b"expired"), 1);
+        log.advance_high_watermark(log.log_end_offset());
+
+        let server = NetworkServer::new(broker);
+        let running = tokio::spawn(async move { server.start("127.0.0.1:0".parse()?).await });
+        timeout(Duration::from_secs(5), async {
+            while log.log_start_offset() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("retention never ran");
+        assert!(log.read_from(0, 1024).is_err());
+        running.abort();
+    }
+}
@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+use std::fmt;
+
+/// Defines [`ApiKey`] from one `Variant = code => "Name"` line per API
+macro_rules! api_keys {
+    ($($variant:ident = $code:literal => $name:literal,)+) => {
+        /// An API this crate knows by name
+        ///
+        /// Converting from the INT16 on the wire fails with the raw value for
+        /// keys that are not listed, so callers can still report or answer
+        /// them.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum ApiKey {
+            $($variant = $code,)+
+        }
+
+        impl ApiKey {
+            /// Every known API, in api key order
+            pub const ALL: &'static [ApiKey] = &[$(ApiKey::$variant,)+];
+
+            /// The INT16 written to the wire
+            pub const fn code(self) -> i16 {
+                self as i16
+            }
+
+            /// The canonical name, as the Java client spells it
+            pub const fn name(self) -> &'static str {
+                match self {
+                    $(ApiKey::$variant => $name,)+
+                }
+            }
+        }
+
+        impl TryFrom<i16> for ApiKey {
+            type Error = i16;
+
+            fn try_from(code: i16) -> Result<Self, i16> {
+                match code {
+                    $($code => Ok(ApiKey::$variant),)+
+                    _ => Err(code),
+                }
+            }
+        }
+    };
+}
+
+api_keys! {
+    Produce = 0 => "Produce",
+    Fetch = 1 => "Fetch",
+    ListOffsets = 2 => "ListOffsets",
+    Metadata = 3 => "Metadata",
+    LeaderAndIsr = 4 => "LeaderAndIsr",
+    StopReplica = 5 => "StopReplica",
+    UpdateMetadata = 6 => "UpdateMetadata",
+    ControlledShutdown = 7 => "ControlledShutdown",
+    OffsetCommit = 8 => "OffsetCommit",
+    OffsetFetch = 9 => "OffsetFetch",
+    FindCoordinator = 10 => "FindCoordinator",
+    JoinGroup = 11 => "JoinGroup",
+    Heartbeat = 12 => "Heartbeat",
+    LeaveGroup = 13 => "LeaveGroup",
+    SyncGroup = 14 => "SyncGroup",
+    DescribeGroups = 15 => "DescribeGroups",
+    ListGroups = 16 => "ListGroups",
+    SaslHandshake = 17 => "SaslHandshake",
+    ApiVersions = 18 => "ApiVersions",
+    CreateTopics = 19 => "CreateTopics",
+    DeleteTopics = 20 => "DeleteTopics",
+    InitProducerId = 22 => "InitProducerId",
+    WriteTxnMarkers = 27 => "WriteTxnMarkers",
+    DescribeConfigs = 32 => "DescribeConfigs",
+    SaslAuthenticate = 36 => "SaslAuthenticate",
+    IncrementalAlterConfigs = 44 => "IncrementalAlterConfigs",
+    DescribeCluster = 60 => "DescribeCluster",
+    DescribeTopicPartitions = 75 => "DescribeTopicPartitions",
+}
+
+impl From<ApiKey> for i16 {
+    fn from(api_key: ApiKey) -> Self {
+        api_key.code()
+    }
+}
+
+impl fmt::Display for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Name of a raw api key for logs, or the number itself when unknown
+pub fn api_key_name(code: i16) -> Cow<'static, str> {
+    match ApiKey::try_from(code) {
+        Ok(api_key) => Cow::Borrowed(api_key.name()),
+        Err(code) => Cow::Owned(code.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_codes_convert_both_ways() {
+        for &api_key in ApiKey::ALL {
+            assert_eq!(ApiKey::try_from(api_key.code()), Ok(api_key));
+            assert_eq!(i16::from(api_key), api_key.code());
+        }
+        assert_eq!(ApiKey::try_from(1), Ok(ApiKey::Fetch));
+        assert_eq!(ApiKey::try_from(75), Ok(ApiKey::DescribeTopicPartitions));
+    }
+
+    #[test]
+    fn test_unknown_codes_are_returned() {
+        for code in [-1, 21, 76, 1000] {
+            assert_eq!(ApiKey::try_from(code), Err(code));
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(ApiKey::Fetch.to_string(), "Fetch");
+        assert_eq!(ApiKey::ApiVersions.to_string(), "ApiVersions");
+        assert_eq!(api_key_name(1), "Fetch");
+        assert_eq!(api_key_name(999), "999");
+    }
+}
@@ -0,0 +1,407 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::tagged_fields::skip_tagged_fields;
+use crate::protocol::trace::DecodeCursor;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest ApiVersions version we serve
+pub const API_VERSIONS_MIN_VERSION: i16 = 0;
+
+/// Highest ApiVersions version we serve
+pub const API_VERSIONS_MAX_VERSION: i16 = 4;
+
+/// First flexible ApiVersions version
+const FIRST_FLEXIBLE_VERSION: i16 = 3;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "ApiVersions",
+        version,
+        API_VERSIONS_MIN_VERSION..=API_VERSIONS_MAX_VERSION,
+    )
+}
+
+/// ApiVersions request (API key 18)
+///
+/// v0-v2 have an empty body; v3 adds the client software name and version.
+/// v4 only changes the error handling contract, so it shares v3's layout.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApiVersionsRequest {
+    pub client_software_name: String,
+    pub client_software_version: String,
+    /// Set when body fields were missing or cut short and left empty
+    pub truncated: bool,
+}
+
+impl ApiVersionsRequest {
+    /// Decodes what follows the client id of a lenient request header
+    ///
+    /// For flexible versions this starts with the header's tag section,
+    /// which [`RequestHeaderV2::decode_lenient`](crate::protocol::RequestHeaderV2::decode_lenient)
+    /// leaves in place. Some proxies forward a v3 header with the body
+    /// missing or truncated, so any field that runs out of bytes is left
+    /// empty and flagged with `truncated` instead of failing the request.
+    /// Only a software name or version that is not valid UTF-8 is an error.
+    pub fn decode_lenient(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        let mut request = Self::default();
+        if version < FIRST_FLEXIBLE_VERSION {
+            return Ok(request);
+        }
+        if skip_tagged_fields(buffer, true).is_err() {
+            request.truncated = true;
+            return Ok(request);
+        }
+        for index in 0..2 {
+            match WireFormat::decode_compact_string(buffer) {
+                Ok(value) if index == 0 => request.client_software_name = value,
+                Ok(value) => request.client_software_version = value,
+                Err(e @ ProtocolError::InvalidUtf8(_)) => return Err(e),
+                Err(_) => {
+                    request.truncated = true;
+                    return Ok(request);
+                }
+            }
+        }
+        if skip_tagged_fields(buffer, true).is_err() {
+            request.truncated = true;
+        }
+        Ok(request)
+    }
+
+    /// Decodes the body that follows a fully decoded request header
+    ///
+    /// Unlike [`decode_lenient`](Self::decode_lenient), a missing or
+    /// truncated body is an error.
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let mut request = Self::default();
+        if version >= FIRST_FLEXIBLE_VERSION {
+            request.client_software_name = WireFormat::decode_compact_string(buffer)?;
+            request.client_software_version = WireFormat::decode_compact_string(buffer)?;
+            skip_tagged_fields(buffer, true)?;
+        }
+        Ok(request)
+    }
+}
+
+/// Version range served for one API
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApiVersionRange {
+    pub api_key: i16,
+    pub min_version: i16,
+    pub max_version: i16,
+}
+
+/// ApiVersions response (API key 18)
+///
+/// - v0: error code and the supported version ranges
+/// - v1-v2: adds a trailing `throttle_time_ms`
+/// - v3: flexible, with compact arrays and tag sections
+///
+/// The response header is always v0, even for flexible versions, so a
+/// client can read it before knowing what the broker supports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiVersionsResponse {
+    pub error_code: ErrorCode,
+    pub api_keys: Vec<ApiVersionRange>,
+    pub throttle_time_ms: i32,
+}
+
+impl ApiVersionsResponse {
+    /// Builds the response to a request at `requested_version`
+    ///
+    /// Unsupported versions, however far out of range, are answered with
+    /// UNSUPPORTED_VERSION and the full range list in a v0 body (KIP-511),
+    /// so the client can pick a version both sides support and retry.
+    /// Returns the version the body must be encoded with.
+    ///
+    /// # Examples
+    /// ```
+    /// use codecrafters_kafka::protocol::api_versions::{ApiVersionRange, ApiVersionsResponse};
+    /// use codecrafters_kafka::protocol::ErrorCode;
+    ///
+    /// let ranges = vec![ApiVersionRange { api_key: 18, min_version: 0, max_version: 3 }];
+    /// let (version, response) = ApiVersionsResponse::negotiate(9, ranges);
+    /// assert_eq!(version, 0);
+    /// assert_eq!(response.error_code, ErrorCode::UNSUPPORTED_VERSION);
+    ///
+    /// // The client can still read the ranges and retry at a version we serve
+    /// let mut body = response.encode(version).unwrap().freeze();
+    /// let decoded = ApiVersionsResponse::decode(&mut body, version).unwrap();
+    /// assert_eq!(decoded.api_keys[0].max_version, 3);
+    /// ```
+    pub fn negotiate(requested_version: i16, api_keys: Vec<ApiVersionRange>) -> (i16, Self) {
+        let supported = check_version(requested_version).is_ok();
+        let response = Self {
+            error_code: if supported {
+                ErrorCode::NONE
+            } else {
+                ErrorCode::UNSUPPORTED_VERSION
+            },
+            api_keys,
+            throttle_time_ms: 0,
+        };
+        let version = if supported {
+            requested_version
+        } else {
+            API_VERSIONS_MIN_VERSION
+        };
+        (version, response)
+    }
+
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    ///
+    /// Tagged fields are skipped.
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode_from(&mut DecodeCursor::new(buffer), version)
+    }
+
+    /// Decodes the response body from a cursor, which may be tracing
+    pub fn decode_from(cursor: &mut DecodeCursor<'_>, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let error_code = cursor.field("error_code", |buffer| {
+            WireFormat::decode_i16(buffer).map(ErrorCode::from_wire)
+        })?;
+        let api_keys = cursor.array("api_keys", flexible, |cursor| {
+            let range = ApiVersionRange {
+                api_key: cursor.field("api_key", WireFormat::decode_i16)?,
+                min_version: cursor.field("min_version", WireFormat::decode_i16)?,
+                max_version: cursor.field("max_version", WireFormat::decode_i16)?,
+            };
+            if flexible {
+                cursor.skip_tagged_fields()?;
+            }
+            Ok(range)
+        })?;
+        let throttle_time_ms = if version >= 1 {
+            cursor.field("throttle_time_ms", WireFormat::decode_i32)?
+        } else {
+            0
+        };
+        if flexible {
+            cursor.skip_tagged_fields()?;
+        }
+        Ok(Self {
+            error_code,
+            api_keys,
+            throttle_time_ms,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for ApiVersionsResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        buffer.put_i16(self.error_code.code());
+        let encode_range = |buffer: &mut BytesMut, range: &ApiVersionRange| {
+            buffer.put_i16(range.api_key);
+            buffer.put_i16(range.min_version);
+            buffer.put_i16(range.max_version);
+            if version >= FIRST_FLEXIBLE_VERSION {
+                WireFormat::encode_unsigned_varint(buffer, 0);
+            }
+            Ok(())
+        };
+        if version >= FIRST_FLEXIBLE_VERSION {
+            WireFormat::encode_compact_array(buffer, &self.api_keys, encode_range)?;
+        } else {
+            WireFormat::encode_array(buffer, &self.api_keys, encode_range)?;
+        }
+        if version >= 1 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        if version >= FIRST_FLEXIBLE_VERSION {
+            WireFormat::encode_unsigned_varint(buffer, 0);
+        }
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let count = self.api_keys.len();
+        // Each range is three INT16s, plus an empty tag section when flexible
+        let ranges = count * if flexible { 7 } else { 6 };
+        let array_length = if flexible {
+            WireFormat::unsigned_varint_size(count as u32 + 1)
+        } else {
+            4
+        };
+        let throttle_time = if version >= 1 { 4 } else { 0 };
+        let tagged_fields = if flexible { 1 } else { 0 };
+        2 + array_length + ranges + throttle_time + tagged_fields
+    }
+}
+
+impl ProtocolDecodeVersioned for ApiVersionsResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges() -> Vec<ApiVersionRange> {
+        vec![ApiVersionRange {
+            api_key: 18,
+            min_version: API_VERSIONS_MIN_VERSION,
+            max_version: API_VERSIONS_MAX_VERSION,
+        }]
+    }
+
+    fn v3_body(name: &[u8], version: &[u8]) -> BytesMut {
+        let mut buffer = BytesMut::new();
+        // header tag section
+        buffer.put_u8(0);
+        for field in [name, version] {
+            WireFormat::encode_unsigned_varint(&mut buffer, field.len() as u32 + 1);
+            buffer.put_slice(field);
+        }
+        buffer.put_u8(0);
+        buffer
+    }
+
+    #[test]
+    fn test_request_decodes_software_name_and_version() {
+        let mut buffer = v3_body(b"librdkafka", b"2.3.0").freeze();
+        let request = ApiVersionsRequest::decode_lenient(&mut buffer, 3).unwrap();
+        assert_eq!(request.client_software_name, "librdkafka");
+        assert_eq!(request.client_software_version, "2.3.0");
+        assert!(!request.truncated);
+    }
+
+    #[test]
+    fn test_request_tolerates_missing_or_truncated_body() {
+        let request = ApiVersionsRequest::decode_lenient(&mut Bytes::new(), 3).unwrap();
+        assert_eq!(request.client_software_name, "");
+        assert!(request.truncated);
+
+        let body = v3_body(b"librdkafka", b"2.3.0");
+        let mut truncated = Bytes::copy_from_slice(&body[..6]);
+        let request = ApiVersionsRequest::decode_lenient(&mut truncated, 3).unwrap();
+        assert_eq!(request.client_software_name, "");
+        assert_eq!(request.client_software_version, "");
+        assert!(request.truncated);
+    }
+
+    #[test]
+    fn test_strict_request_decode() {
+        // Without the header tag section the lenient decoder expects
+        let body = v3_body(b"librdkafka", b"2.3.0");
+        let mut buffer = body.clone().freeze().split_off(1);
+        let request = ApiVersionsRequest::decode(&mut buffer, 3).unwrap();
+        assert_eq!(request.client_software_name, "librdkafka");
+        assert_eq!(request.client_software_version, "2.3.0");
+        assert!(buffer.is_empty());
+
+        let mut truncated = Bytes::copy_from_slice(&body[1..6]);
+        assert!(ApiVersionsRequest::decode(&mut truncated, 3).is_err());
+        assert_eq!(
+            ApiVersionsRequest::decode(&mut Bytes::new(), 0).unwrap(),
+            ApiVersionsRequest::default()
+        );
+        assert!(ApiVersionsRequest::decode(&mut Bytes::new(), 5).is_err());
+    }
+
+    #[test]
+    fn test_request_rejects_invalid_utf8() {
+        let mut buffer = v3_body(&[0xff, 0xfe], b"1.0").freeze();
+        assert!(matches!(
+            ApiVersionsRequest::decode_lenient(&mut buffer, 3),
+            Err(ProtocolError::InvalidUtf8(_))
+        ));
+    }
+
+    #[test]
+    fn test_round_trip_every_version() {
+        for version in API_VERSIONS_MIN_VERSION..=API_VERSIONS_MAX_VERSION {
+            let (negotiated, response) = ApiVersionsResponse::negotiate(version, ranges());
+            assert_eq!(negotiated, version);
+            let mut encoded = response.encode(version).unwrap().freeze();
+            assert_eq!(encoded.len(), response.encoded_size(version));
+            assert_eq!(
+                ApiVersionsResponse::decode(&mut encoded, version).unwrap(),
+                response
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v3_body_layout() {
+        let (_, response) = ApiVersionsResponse::negotiate(3, ranges());
+        let encoded = response.encode(3).unwrap();
+        assert_eq!(
+            &encoded[..],
+            &[0, 0, 2, 0, 18, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0][..]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_versions_fall_back_to_v0() {
+        for requested in [5, 32000, -1] {
+            let (version, response) = ApiVersionsResponse::negotiate(requested, ranges());
+            assert_eq!(version, 0);
+            assert_eq!(response.error_code, ErrorCode::UNSUPPORTED_VERSION);
+            assert_eq!(response.api_keys, ranges());
+        }
+    }
+
+    #[test]
+    fn test_trace_of_v3_response_frame() {
+        use crate::protocol::headers::ResponseHeaderV0;
+        use crate::protocol::trace::DecodeTrace;
+
+        let frame = hex::decode("0000002a00000300000000000200001200000003000000000000").unwrap();
+        let mut buffer = Bytes::from(frame.clone());
+        let mut trace = DecodeTrace::new();
+        let mut cursor = DecodeCursor::traced(&mut buffer, &mut trace);
+        ResponseHeaderV0::decode_from(&mut cursor).unwrap();
+        let response = ApiVersionsResponse::decode_from(&mut cursor, 3).unwrap();
+        drop(cursor);
+        assert_eq!(response.api_keys.len(), 2);
+
+        let entries: Vec<(&str, std::ops::Range<usize>, &str)> = trace
+            .entries()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.path.as_str(),
+                    entry.range.clone(),
+                    entry.value.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("correlation_id", 0..4, "42"),
+                ("error_code", 4..6, "ErrorCode(0)"),
+                ("api_keys.length", 6..7, "3"),
+                ("api_keys[0].api_key", 7..9, "0"),
+                ("api_keys[0].min_version", 9..11, "0"),
+                ("api_keys[0].max_version", 11..13, "2"),
+                ("api_keys[0]._tagged_fields", 13..14, "0"),
+                ("api_keys[1].api_key", 14..16, "18"),
+                ("api_keys[1].min_version", 16..18, "0"),
+                ("api_keys[1].max_version", 18..20, "3"),
+                ("api_keys[1]._tagged_fields", 20..21, "0"),
+                ("throttle_time_ms", 21..25, "0"),
+                ("_tagged_fields", 25..26, "0"),
+            ]
+        );
+        assert_eq!(trace.end(), frame.len());
+    }
+}
@@ -0,0 +1,269 @@
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fmt;
+use std::io::{Read, Write};
+
+/// Mask of the compression codec bits in the record batch attributes
+const COMPRESSION_CODEC_MASK: i16 = 0x07;
+
+/// Header the Java client's snappy stream (xerial framing) starts with
+const XERIAL_MAGIC: [u8; 8] = [0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0];
+
+/// Version and compatible version written after [`XERIAL_MAGIC`]
+const XERIAL_VERSION: i32 = 1;
+
+/// Uncompressed size of each block of a xerial snappy stream
+const XERIAL_BLOCK_SIZE: usize = 32 * 1024;
+
+/// Codec of the records section of a record batch
+///
+/// Gzip and snappy are always available; lz4 and zstd need the `lz4` and
+/// `zstd` cargo features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionType {
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl CompressionType {
+    /// The codec selected by a batch's attributes
+    ///
+    /// Codec ids past zstd are rejected as unsupported.
+    pub fn from_attributes(attributes: i16) -> ProtocolResult<Self> {
+        match attributes & COMPRESSION_CODEC_MASK {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Gzip),
+            2 => Ok(Self::Snappy),
+            3 => Ok(Self::Lz4),
+            4 => Ok(Self::Zstd),
+            codec => Err(ProtocolError::UnsupportedCompression { codec }),
+        }
+    }
+
+    /// The codec id stored in the attributes
+    pub fn id(self) -> i16 {
+        match self {
+            Self::None => 0,
+            Self::Gzip => 1,
+            Self::Snappy => 2,
+            Self::Lz4 => 3,
+            Self::Zstd => 4,
+        }
+    }
+
+    /// Whether this build can compress and decompress with the codec
+    pub fn is_available(self) -> bool {
+        match self {
+            Self::None | Self::Gzip | Self::Snappy => true,
+            Self::Lz4 => cfg!(feature = "lz4"),
+            Self::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    /// Compresses a records section
+    pub fn compress(self, data: &[u8]) -> ProtocolResult<Vec<u8>> {
+        let codec_error = |error: std::io::Error| {
+            ProtocolError::SerializationError(format!("{} compression failed: {}", self, error))
+        };
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).map_err(codec_error)?;
+                encoder.finish().map_err(codec_error)
+            }
+            Self::Snappy => xerial_compress(data),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(data).map_err(codec_error)?;
+                encoder.finish().map_err(|error| codec_error(error.into()))
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => {
+                zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL).map_err(codec_error)
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(ProtocolError::UnsupportedCompression { codec: self.id() }),
+        }
+    }
+
+    /// Decompresses a records section
+    pub fn decompress(self, data: &[u8]) -> ProtocolResult<Vec<u8>> {
+        let codec_error = |error: std::io::Error| {
+            ProtocolError::InvalidFormat(format!("{} decompression failed: {}", self, error))
+        };
+        let mut decompressed = Vec::new();
+        match self {
+            Self::None => decompressed.extend_from_slice(data),
+            Self::Gzip => {
+                MultiGzDecoder::new(data)
+                    .read_to_end(&mut decompressed)
+                    .map_err(codec_error)?;
+            }
+            Self::Snappy => return xerial_decompress(data),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => {
+                lz4_flex::frame::FrameDecoder::new(data)
+                    .read_to_end(&mut decompressed)
+                    .map_err(codec_error)?;
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => return zstd::decode_all(data).map_err(codec_error),
+            #[allow(unreachable_patterns)]
+            _ => return Err(ProtocolError::UnsupportedCompression { codec: self.id() }),
+        }
+        Ok(decompressed)
+    }
+}
+
+impl fmt::Display for CompressionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Snappy => "snappy",
+            Self::Lz4 => "lz4",
+            Self::Zstd => "zstd",
+        })
+    }
+}
+
+/// Snappy-compresses `data` in the xerial framing the Java client writes
+///
+/// The stream is [`XERIAL_MAGIC`], the version and compatible version, then
+/// one length-prefixed raw snappy block per [`XERIAL_BLOCK_SIZE`] of input.
+fn xerial_compress(data: &[u8]) -> ProtocolResult<Vec<u8>> {
+    let mut encoder = snap::raw::Encoder::new();
+    let mut compressed = Vec::with_capacity(XERIAL_MAGIC.len() + 8 + data.len());
+    compressed.extend_from_slice(&XERIAL_MAGIC);
+    compressed.extend_from_slice(&XERIAL_VERSION.to_be_bytes());
+    compressed.extend_from_slice(&XERIAL_VERSION.to_be_bytes());
+    for block in data.chunks(XERIAL_BLOCK_SIZE) {
+        let block = encoder.compress_vec(block).map_err(|error| {
+            ProtocolError::SerializationError(format!("snappy compression failed: {}", error))
+        })?;
+        compressed.extend_from_slice(&(block.len() as i32).to_be_bytes());
+        compressed.extend_from_slice(&block);
+    }
+    Ok(compressed)
+}
+
+/// Decompresses snappy data, xerial framed or a single raw block
+///
+/// librdkafka-based clients send raw snappy, so anything without the
+/// xerial header is taken to be one block.
+fn xerial_decompress(data: &[u8]) -> ProtocolResult<Vec<u8>> {
+    let mut decoder = snap::raw::Decoder::new();
+    let mut decompress = |block: &[u8], into: &mut Vec<u8>| {
+        let block = decoder.decompress_vec(block).map_err(|error| {
+            ProtocolError::InvalidFormat(format!("snappy decompression failed: {}", error))
+        })?;
+        into.extend_from_slice(&block);
+        Ok::<_, ProtocolError>(())
+    };
+
+    let mut decompressed = Vec::new();
+    let Some(mut blocks) = data.strip_prefix(&XERIAL_MAGIC[..]) else {
+        decompress(data, &mut decompressed)?;
+        return Ok(decompressed);
+    };
+    if blocks.len() < 8 {
+        return Err(ProtocolError::insufficient_bytes(8, blocks.len()));
+    }
+    blocks = &blocks[8..];
+    while !blocks.is_empty() {
+        if blocks.len() < 4 {
+            return Err(ProtocolError::insufficient_bytes(4, blocks.len()));
+        }
+        let length = i32::from_be_bytes(blocks[..4].try_into().unwrap());
+        if length < 0 || blocks.len() - 4 < length as usize {
+            return Err(ProtocolError::invalid_length(length));
+        }
+        let (block, rest) = blocks[4..].split_at(length as usize);
+        decompress(block, &mut decompressed)?;
+        blocks = rest;
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records_section() -> Vec<u8> {
+        (0..100_000u32)
+            .flat_map(|i| (i % 251).to_be_bytes())
+            .collect()
+    }
+
+    fn assert_roundtrip(codec: CompressionType) {
+        let data = records_section();
+        let compressed = codec.compress(&data).unwrap();
+        assert_eq!(codec.decompress(&compressed).unwrap(), data, "{}", codec);
+    }
+
+    #[test]
+    fn test_codec_ids() {
+        for codec in [
+            CompressionType::None,
+            CompressionType::Gzip,
+            CompressionType::Snappy,
+            CompressionType::Lz4,
+            CompressionType::Zstd,
+        ] {
+            // Bits above the codec are other attributes
+            assert_eq!(
+                CompressionType::from_attributes(0x30 | codec.id()).unwrap(),
+                codec
+            );
+        }
+        assert!(matches!(
+            CompressionType::from_attributes(0x05),
+            Err(ProtocolError::UnsupportedCompression { codec: 5 })
+        ));
+    }
+
+    #[test]
+    fn test_gzip_roundtrip() {
+        assert_roundtrip(CompressionType::Gzip);
+    }
+
+    #[test]
+    fn test_snappy_roundtrip() {
+        assert_roundtrip(CompressionType::Snappy);
+    }
+
+    #[test]
+    fn test_raw_snappy_is_accepted() {
+        let data = records_section();
+        let raw = snap::raw::Encoder::new().compress_vec(&data).unwrap();
+        assert_eq!(CompressionType::Snappy.decompress(&raw).unwrap(), data);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4_roundtrip() {
+        assert_roundtrip(CompressionType::Lz4);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_roundtrip() {
+        assert_roundtrip(CompressionType::Zstd);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_unavailable_codec_is_unsupported() {
+        assert!(!CompressionType::Zstd.is_available());
+        assert!(matches!(
+            CompressionType::Zstd.decompress(b"data"),
+            Err(ProtocolError::UnsupportedCompression { codec: 4 })
+        ));
+    }
+}
@@ -0,0 +1,634 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+use uuid::Uuid;
+
+/// Lowest CreateTopics version we serve
+pub const CREATE_TOPICS_MIN_VERSION: i16 = 0;
+
+/// Highest CreateTopics version we serve
+pub const CREATE_TOPICS_MAX_VERSION: i16 = 7;
+
+/// First flexible CreateTopics version
+const FIRST_FLEXIBLE_VERSION: i16 = 5;
+
+/// `config_source` of a config set on the topic itself
+pub const TOPIC_CONFIG_SOURCE: i8 = 1;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "CreateTopics",
+        version,
+        CREATE_TOPICS_MIN_VERSION..=CREATE_TOPICS_MAX_VERSION,
+    )
+}
+
+/// CreateTopics request (API key 19)
+///
+/// - v0: topics and `timeout_ms`
+/// - v1+: `validate_only`
+/// - v4+: `num_partitions` and `replication_factor` may be -1 for the
+///   broker default
+/// - v5+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTopicsRequest {
+    pub topics: Vec<CreatableTopic>,
+    pub timeout_ms: i32,
+    /// v1+; always false before
+    pub validate_only: bool,
+}
+
+/// A topic to create in a [`CreateTopicsRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatableTopic {
+    pub name: String,
+    /// -1 when `assignments` is given or the broker default is wanted
+    pub num_partitions: i32,
+    /// -1 when `assignments` is given or the broker default is wanted
+    pub replication_factor: i16,
+    pub assignments: Vec<CreatableReplicaAssignment>,
+    pub configs: Vec<CreatableTopicConfig>,
+}
+
+/// Manual replica assignment of one partition
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatableReplicaAssignment {
+    pub partition_index: i32,
+    pub broker_ids: Vec<i32>,
+}
+
+/// A config to set on a created topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatableTopicConfig {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl CreateTopicsRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let num_partitions = WireFormat::decode_i32(buffer)?;
+            let replication_factor = WireFormat::decode_i16(buffer)?;
+            let assignments = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let assignment = CreatableReplicaAssignment {
+                    partition_index: WireFormat::decode_i32(buffer)?,
+                    broker_ids: WireFormat::decode_flexible_array(
+                        buffer,
+                        flexible,
+                        WireFormat::decode_i32,
+                    )?,
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(assignment)
+            })?;
+            let configs = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let config = CreatableTopicConfig {
+                    name: WireFormat::decode_flexible_string(buffer, flexible)?,
+                    value: WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(config)
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(CreatableTopic {
+                name,
+                num_partitions,
+                replication_factor,
+                assignments,
+                configs,
+            })
+        })?;
+        let timeout_ms = WireFormat::decode_i32(buffer)?;
+        let validate_only = version >= 1 && WireFormat::decode_bool(buffer)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            topics,
+            timeout_ms,
+            validate_only,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for CreateTopicsRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+            buffer.put_i32(topic.num_partitions);
+            buffer.put_i16(topic.replication_factor);
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.assignments,
+                flexible,
+                |buffer, assignment| {
+                    buffer.put_i32(assignment.partition_index);
+                    WireFormat::encode_flexible_array(
+                        buffer,
+                        &assignment.broker_ids,
+                        flexible,
+                        |buffer, broker_id| {
+                            buffer.put_i32(*broker_id);
+                            Ok(())
+                        },
+                    )?;
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.configs,
+                flexible,
+                |buffer, config| {
+                    WireFormat::encode_flexible_string(buffer, &config.name, flexible)?;
+                    WireFormat::encode_flexible_nullable_string(
+                        buffer,
+                        config.value.as_deref(),
+                        flexible,
+                    )?;
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        buffer.put_i32(self.timeout_ms);
+        if version >= 1 {
+            WireFormat::encode_bool(buffer, self.validate_only);
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                let assignments: usize = topic
+                    .assignments
+                    .iter()
+                    .map(|assignment| {
+                        4 + WireFormat::flexible_array_length_size(
+                            Some(assignment.broker_ids.len()),
+                            flexible,
+                        ) + 4 * assignment.broker_ids.len()
+                            + tags
+                    })
+                    .sum();
+                let configs: usize = topic
+                    .configs
+                    .iter()
+                    .map(|config| {
+                        WireFormat::flexible_string_size(&config.name, flexible)
+                            + WireFormat::flexible_nullable_string_size(
+                                config.value.as_deref(),
+                                flexible,
+                            )
+                            + tags
+                    })
+                    .sum();
+                WireFormat::flexible_string_size(&topic.name, flexible)
+                    + 4
+                    + 2
+                    + WireFormat::flexible_array_length_size(
+                        Some(topic.assignments.len()),
+                        flexible,
+                    )
+                    + assignments
+                    + WireFormat::flexible_array_length_size(Some(topic.configs.len()), flexible)
+                    + configs
+                    + tags
+            })
+            .sum();
+        WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + 4
+            + usize::from(version >= 1)
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for CreateTopicsRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// CreateTopics response (API key 19)
+///
+/// - v0: per-topic name and error code
+/// - v1+: per-topic `error_message`
+/// - v2+: `throttle_time_ms`
+/// - v5+: flexible, with the created topic's partition count, replication
+///   factor and configs
+/// - v7+: `topic_id`
+///
+/// The `topic_config_error_code` tag of v5+ is never written; tagged fields
+/// are skipped on decode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTopicsResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<CreatableTopicResult>,
+}
+
+/// Per-topic result of a [`CreateTopicsResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatableTopicResult {
+    pub name: String,
+    /// v7+; nil when the topic was not created
+    pub topic_id: Uuid,
+    pub error_code: ErrorCode,
+    pub error_message: Option<String>,
+    /// v5+; -1 when the topic was not created
+    pub num_partitions: i32,
+    /// v5+; -1 when the topic was not created
+    pub replication_factor: i16,
+    /// v5+; `None` when the topic was not created
+    pub configs: Option<Vec<CreatableTopicConfigs>>,
+}
+
+/// A config of a created topic, as reported in a [`CreatableTopicResult`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatableTopicConfigs {
+    pub name: String,
+    pub value: Option<String>,
+    pub read_only: bool,
+    pub config_source: i8,
+    pub is_sensitive: bool,
+}
+
+impl CreatableTopicResult {
+    /// The result for a topic that was not created
+    pub fn error(
+        name: impl Into<String>,
+        error_code: ErrorCode,
+        error_message: Option<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            topic_id: Uuid::nil(),
+            error_code,
+            error_message,
+            num_partitions: -1,
+            replication_factor: -1,
+            configs: None,
+        }
+    }
+}
+
+impl CreateTopicsResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 2 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let topic_id = if version >= 7 {
+                WireFormat::decode_uuid(buffer)?
+            } else {
+                Uuid::nil()
+            };
+            let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+            let error_message = if version >= 1 {
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+            } else {
+                None
+            };
+            let mut result = CreatableTopicResult {
+                name,
+                topic_id,
+                error_code,
+                error_message,
+                num_partitions: -1,
+                replication_factor: -1,
+                configs: None,
+            };
+            if version >= 5 {
+                result.num_partitions = WireFormat::decode_i32(buffer)?;
+                result.replication_factor = WireFormat::decode_i16(buffer)?;
+                result.configs =
+                    WireFormat::decode_flexible_nullable_array(buffer, flexible, |buffer| {
+                        let config = CreatableTopicConfigs {
+                            name: WireFormat::decode_flexible_string(buffer, flexible)?,
+                            value: WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                            read_only: WireFormat::decode_bool(buffer)?,
+                            config_source: WireFormat::decode_i8(buffer)?,
+                            is_sensitive: WireFormat::decode_bool(buffer)?,
+                        };
+                        skip_tagged_fields(buffer, flexible)?;
+                        Ok(config)
+                    })?;
+            }
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(result)
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            topics,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for CreateTopicsResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 2 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+            if version >= 7 {
+                WireFormat::encode_uuid(buffer, &topic.topic_id);
+            }
+            buffer.put_i16(topic.error_code.code());
+            if version >= 1 {
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    topic.error_message.as_deref(),
+                    flexible,
+                )?;
+            }
+            if version >= 5 {
+                buffer.put_i32(topic.num_partitions);
+                buffer.put_i16(topic.replication_factor);
+                WireFormat::encode_flexible_nullable_array(
+                    buffer,
+                    topic.configs.as_deref(),
+                    flexible,
+                    |buffer, config| {
+                        WireFormat::encode_flexible_string(buffer, &config.name, flexible)?;
+                        WireFormat::encode_flexible_nullable_string(
+                            buffer,
+                            config.value.as_deref(),
+                            flexible,
+                        )?;
+                        WireFormat::encode_bool(buffer, config.read_only);
+                        buffer.put_i8(config.config_source);
+                        WireFormat::encode_bool(buffer, config.is_sensitive);
+                        put_empty_tagged_fields(buffer, flexible);
+                        Ok(())
+                    },
+                )?;
+            }
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                let configs = topic.configs.as_deref().map_or(0, |configs| {
+                    configs
+                        .iter()
+                        .map(|config| {
+                            WireFormat::flexible_string_size(&config.name, flexible)
+                                + WireFormat::flexible_nullable_string_size(
+                                    config.value.as_deref(),
+                                    flexible,
+                                )
+                                + 1
+                                + 1
+                                + 1
+                                + tags
+                        })
+                        .sum()
+                });
+                WireFormat::flexible_string_size(&topic.name, flexible)
+                    + if version >= 7 { 16 } else { 0 }
+                    + 2
+                    + if version >= 1 {
+                        WireFormat::flexible_nullable_string_size(
+                            topic.error_message.as_deref(),
+                            flexible,
+                        )
+                    } else {
+                        0
+                    }
+                    + if version >= 5 {
+                        4 + 2
+                            + WireFormat::flexible_array_length_size(
+                                topic.configs.as_ref().map(Vec::len),
+                                flexible,
+                            )
+                            + configs
+                    } else {
+                        0
+                    }
+                    + tags
+            })
+            .sum();
+        let throttle_time = if version >= 2 { 4 } else { 0 };
+        throttle_time
+            + WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for CreateTopicsResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> CreateTopicsRequest {
+        CreateTopicsRequest {
+            topics: vec![
+                CreatableTopic {
+                    name: "orders".to_string(),
+                    num_partitions: 3,
+                    replication_factor: 1,
+                    assignments: Vec::new(),
+                    configs: vec![CreatableTopicConfig {
+                        name: "cleanup.policy".to_string(),
+                        value: Some("compact".to_string()),
+                    }],
+                },
+                CreatableTopic {
+                    name: "payments".to_string(),
+                    num_partitions: -1,
+                    replication_factor: -1,
+                    assignments: vec![CreatableReplicaAssignment {
+                        partition_index: 0,
+                        broker_ids: vec![1],
+                    }],
+                    configs: Vec::new(),
+                },
+            ],
+            timeout_ms: 30_000,
+            validate_only: true,
+        }
+    }
+
+    fn response() -> CreateTopicsResponse {
+        CreateTopicsResponse {
+            throttle_time_ms: 5,
+            topics: vec![
+                CreatableTopicResult {
+                    name: "orders".to_string(),
+                    topic_id: Uuid::from_u128(0x42),
+                    error_code: ErrorCode::NONE,
+                    error_message: None,
+                    num_partitions: 3,
+                    replication_factor: 1,
+                    configs: Some(vec![CreatableTopicConfigs {
+                        name: "cleanup.policy".to_string(),
+                        value: Some("compact".to_string()),
+                        read_only: false,
+                        config_source: TOPIC_CONFIG_SOURCE,
+                        is_sensitive: false,
+                    }]),
+                },
+                CreatableTopicResult::error(
+                    "payments",
+                    ErrorCode::TOPIC_ALREADY_EXISTS,
+                    Some("Topic 'payments' already exists.".to_string()),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_v0_layout() {
+        let request = CreateTopicsRequest {
+            topics: vec![CreatableTopic {
+                name: "t".to_string(),
+                num_partitions: 2,
+                replication_factor: 1,
+                assignments: Vec::new(),
+                configs: Vec::new(),
+            }],
+            timeout_ms: 1000,
+            validate_only: true,
+        };
+        let expected = [
+            "00000001", // topics
+            "000174",   // name
+            "00000002", // num_partitions
+            "0001",     // replication_factor
+            "00000000", // assignments
+            "00000000", // configs
+            "000003e8", // timeout_ms
+        ]
+        .concat();
+        assert_eq!(hex::encode(request.encode(0).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in CREATE_TOPICS_MIN_VERSION..=CREATE_TOPICS_MAX_VERSION {
+            let mut expected = request();
+            if version < 1 {
+                expected.validate_only = false;
+            }
+            let encoded = expected.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                expected.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                CreateTopicsRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        for version in CREATE_TOPICS_MIN_VERSION..=CREATE_TOPICS_MAX_VERSION {
+            let response = response();
+            let mut expected = response.clone();
+            for topic in &mut expected.topics {
+                if version < 1 {
+                    topic.error_message = None;
+                }
+                if version < 5 {
+                    topic.num_partitions = -1;
+                    topic.replication_factor = -1;
+                    topic.configs = None;
+                }
+                if version < 7 {
+                    topic.topic_id = Uuid::nil();
+                }
+            }
+            if version < 2 {
+                expected.throttle_time_ms = 0;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                CreateTopicsResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_unsupported_version_is_rejected() {
+        assert!(request().encode(CREATE_TOPICS_MAX_VERSION + 1).is_err());
+        let mut empty = Bytes::new();
+        assert!(CreateTopicsRequest::decode(&mut empty, -1).is_err());
+    }
+}
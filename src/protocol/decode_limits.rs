@@ -0,0 +1,158 @@
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::spec::MAX_STRING_LENGTH;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+/// Default cap on the length of a BYTES field
+pub const DEFAULT_MAX_BYTES_LENGTH: usize = 100 * 1024 * 1024;
+
+/// Default cap on the element count of one array
+pub const DEFAULT_MAX_ARRAY_ELEMENTS: usize = 100_000;
+
+/// Default cap on how deeply arrays may nest
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 16;
+
+/// Default cap on the length of a request header's client id
+pub const DEFAULT_MAX_CLIENT_ID_LENGTH: usize = 256;
+
+/// Whether the installed limits reject non-minimal varints
+static STRICT_VARINTS: AtomicBool = AtomicBool::new(true);
+
+static MAX_STRING: AtomicUsize = AtomicUsize::new(MAX_STRING_LENGTH);
+static MAX_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_BYTES_LENGTH);
+static MAX_ARRAY_ELEMENTS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_ARRAY_ELEMENTS);
+static MAX_NESTING_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_NESTING_DEPTH);
+static MAX_CLIENT_ID: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_CLIENT_ID_LENGTH);
+
+thread_local! {
+    /// Arrays being decoded on this thread, outermost first
+    static NESTING_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Non-minimal varints accepted while in warn-only mode
+static NON_CANONICAL_VARINTS: AtomicU64 = AtomicU64::new(0);
+
+/// Process-wide strictness settings for the decoders
+///
+/// The decoders are free functions called from deep inside message
+/// parsing, so the settings are installed once at startup rather than
+/// threaded through every call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Reject varints with redundant continuation bytes, such as
+    /// `0x80 0x00` for zero. When off they are accepted and logged, for
+    /// clients that historically emitted them.
+    pub strict_varints: bool,
+    /// Longest string a length prefix may claim
+    pub max_string_length: usize,
+    /// Longest BYTES field a length prefix may claim
+    pub max_bytes_length: usize,
+    /// Most elements an array count may claim
+    pub max_array_elements: usize,
+    /// Deepest nesting of arrays within arrays
+    pub max_nesting_depth: usize,
+    /// Longest client id accepted in a request header, in bytes
+    pub max_client_id_length: usize,
+}
+
+impl DecodeLimits {
+    /// Makes these the limits every decoder applies
+    pub fn install(self) {
+        STRICT_VARINTS.store(self.strict_varints, Ordering::Relaxed);
+        MAX_STRING.store(self.max_string_length, Ordering::Relaxed);
+        MAX_BYTES.store(self.max_bytes_length, Ordering::Relaxed);
+        MAX_ARRAY_ELEMENTS.store(self.max_array_elements, Ordering::Relaxed);
+        MAX_NESTING_DEPTH.store(self.max_nesting_depth, Ordering::Relaxed);
+        MAX_CLIENT_ID.store(self.max_client_id_length, Ordering::Relaxed);
+    }
+
+    /// The limits currently applied
+    pub fn current() -> Self {
+        Self {
+            strict_varints: STRICT_VARINTS.load(Ordering::Relaxed),
+            max_string_length: MAX_STRING.load(Ordering::Relaxed),
+            max_bytes_length: MAX_BYTES.load(Ordering::Relaxed),
+            max_array_elements: MAX_ARRAY_ELEMENTS.load(Ordering::Relaxed),
+            max_nesting_depth: MAX_NESTING_DEPTH.load(Ordering::Relaxed),
+            max_client_id_length: MAX_CLIENT_ID.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            strict_varints: true,
+            max_string_length: MAX_STRING_LENGTH,
+            max_bytes_length: DEFAULT_MAX_BYTES_LENGTH,
+            max_array_elements: DEFAULT_MAX_ARRAY_ELEMENTS,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            max_client_id_length: DEFAULT_MAX_CLIENT_ID_LENGTH,
+        }
+    }
+}
+
+fn check(field: &'static str, requested: usize, limit: &AtomicUsize) -> ProtocolResult<()> {
+    let limit = limit.load(Ordering::Relaxed);
+    if requested > limit {
+        return Err(ProtocolError::LimitExceeded {
+            field,
+            limit,
+            requested,
+        });
+    }
+    Ok(())
+}
+
+/// Rejects a string length prefix above the installed limit
+pub(in crate::protocol) fn check_string_length(requested: usize) -> ProtocolResult<()> {
+    check("string length", requested, &MAX_STRING)
+}
+
+/// Rejects a BYTES length prefix above the installed limit
+pub(in crate::protocol) fn check_bytes_length(requested: usize) -> ProtocolResult<()> {
+    check("bytes length", requested, &MAX_BYTES)
+}
+
+/// Rejects an array count above the installed limit
+pub(in crate::protocol) fn check_array_elements(requested: usize) -> ProtocolResult<()> {
+    check("array elements", requested, &MAX_ARRAY_ELEMENTS)
+}
+
+/// Rejects a client id longer than the installed limit
+pub(in crate::protocol) fn check_client_id_length(length: usize) -> ProtocolResult<()> {
+    let limit = MAX_CLIENT_ID.load(Ordering::Relaxed);
+    if length > limit {
+        return Err(ProtocolError::InvalidClientId { length, limit });
+    }
+    Ok(())
+}
+
+/// One level of array nesting, held while an array's elements decode
+pub(in crate::protocol) struct NestingGuard(());
+
+impl NestingGuard {
+    /// Enters one more level, failing when that is deeper than allowed
+    pub(in crate::protocol) fn enter() -> ProtocolResult<Self> {
+        let depth = NESTING_DEPTH.with(|depth| depth.get()) + 1;
+        check("nesting depth", depth, &MAX_NESTING_DEPTH)?;
+        NESTING_DEPTH.with(|current| current.set(depth));
+        Ok(Self(()))
+    }
+}
+
+impl Drop for NestingGuard {
+    fn drop(&mut self) {
+        NESTING_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Counts a non-minimal varint accepted in warn-only mode
+pub(in crate::protocol) fn record_non_canonical_varint() {
+    NON_CANONICAL_VARINTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns how many non-minimal varints were accepted in warn-only mode
+pub fn non_canonical_varint_count() -> u64 {
+    NON_CANONICAL_VARINTS.load(Ordering::Relaxed)
+}
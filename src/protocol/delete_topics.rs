@@ -0,0 +1,417 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+use uuid::Uuid;
+
+/// Lowest DeleteTopics version we serve
+pub const DELETE_TOPICS_MIN_VERSION: i16 = 0;
+
+/// Highest DeleteTopics version we serve
+pub const DELETE_TOPICS_MAX_VERSION: i16 = 6;
+
+/// First flexible DeleteTopics version
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+/// First DeleteTopics version that may name topics by id
+const FIRST_TOPIC_ID_VERSION: i16 = 6;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "DeleteTopics",
+        version,
+        DELETE_TOPICS_MIN_VERSION..=DELETE_TOPICS_MAX_VERSION,
+    )
+}
+
+/// DeleteTopics request (API key 20)
+///
+/// - v0-v3: topic names and `timeout_ms`
+/// - v4+: flexible
+/// - v6+: topics given by name or by id
+///
+/// Before v6 every topic is a name with a nil id. Tagged fields are skipped
+/// on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteTopicsRequest {
+    pub topics: Vec<DeleteTopicState>,
+    pub timeout_ms: i32,
+}
+
+/// A topic to delete in a [`DeleteTopicsRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteTopicState {
+    /// `None` when the topic is given by id
+    pub name: Option<String>,
+    /// Nil when the topic is given by name
+    pub topic_id: Uuid,
+}
+
+impl DeleteTopicsRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let topics = if version >= FIRST_TOPIC_ID_VERSION {
+            WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let topic = DeleteTopicState {
+                    name: WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                    topic_id: WireFormat::decode_uuid(buffer)?,
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(topic)
+            })?
+        } else {
+            WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                Ok(DeleteTopicState {
+                    name: Some(WireFormat::decode_flexible_string(buffer, flexible)?),
+                    topic_id: Uuid::nil(),
+                })
+            })?
+        };
+        let timeout_ms = WireFormat::decode_i32(buffer)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self { topics, timeout_ms })
+    }
+
+    /// Encodes the request body for the given version
+    ///
+    /// Before v6 topics given only by id are written with an empty name.
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for DeleteTopicsRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            if version >= FIRST_TOPIC_ID_VERSION {
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    topic.name.as_deref(),
+                    flexible,
+                )?;
+                WireFormat::encode_uuid(buffer, &topic.topic_id);
+                put_empty_tagged_fields(buffer, flexible);
+            } else {
+                WireFormat::encode_flexible_string(
+                    buffer,
+                    topic.name.as_deref().unwrap_or_default(),
+                    flexible,
+                )?;
+            }
+            Ok(())
+        })?;
+        buffer.put_i32(self.timeout_ms);
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                if version >= FIRST_TOPIC_ID_VERSION {
+                    WireFormat::flexible_nullable_string_size(topic.name.as_deref(), flexible)
+                        + 16
+                        + tags
+                } else {
+                    WireFormat::flexible_string_size(
+                        topic.name.as_deref().unwrap_or_default(),
+                        flexible,
+                    )
+                }
+            })
+            .sum();
+        WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + 4
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for DeleteTopicsRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// DeleteTopics response (API key 20)
+///
+/// - v0: per-topic name and error code
+/// - v1+: `throttle_time_ms`
+/// - v4+: flexible
+/// - v5+: per-topic `error_message`
+/// - v6+: nullable names and topic ids
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteTopicsResponse {
+    pub throttle_time_ms: i32,
+    pub responses: Vec<DeletableTopicResult>,
+}
+
+/// Per-topic result of a [`DeleteTopicsResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeletableTopicResult {
+    /// Nullable from v6, for a topic given by an unknown id
+    pub name: Option<String>,
+    /// v6+
+    pub topic_id: Uuid,
+    pub error_code: ErrorCode,
+    /// v5+
+    pub error_message: Option<String>,
+}
+
+impl DeleteTopicsResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 1 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let responses = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = if version >= FIRST_TOPIC_ID_VERSION {
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+            } else {
+                Some(WireFormat::decode_flexible_string(buffer, flexible)?)
+            };
+            let topic_id = if version >= FIRST_TOPIC_ID_VERSION {
+                WireFormat::decode_uuid(buffer)?
+            } else {
+                Uuid::nil()
+            };
+            let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+            let error_message = if version >= 5 {
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+            } else {
+                None
+            };
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(DeletableTopicResult {
+                name,
+                topic_id,
+                error_code,
+                error_message,
+            })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            responses,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for DeleteTopicsResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 1 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.responses, flexible, |buffer, topic| {
+            if version >= FIRST_TOPIC_ID_VERSION {
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    topic.name.as_deref(),
+                    flexible,
+                )?;
+                WireFormat::encode_uuid(buffer, &topic.topic_id);
+            } else {
+                WireFormat::encode_flexible_string(
+                    buffer,
+                    topic.name.as_deref().unwrap_or_default(),
+                    flexible,
+                )?;
+            }
+            buffer.put_i16(topic.error_code.code());
+            if version >= 5 {
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    topic.error_message.as_deref(),
+                    flexible,
+                )?;
+            }
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let responses: usize = self
+            .responses
+            .iter()
+            .map(|topic| {
+                let name = if version >= FIRST_TOPIC_ID_VERSION {
+                    WireFormat::flexible_nullable_string_size(topic.name.as_deref(), flexible) + 16
+                } else {
+                    WireFormat::flexible_string_size(
+                        topic.name.as_deref().unwrap_or_default(),
+                        flexible,
+                    )
+                };
+                let error_message = if version >= 5 {
+                    WireFormat::flexible_nullable_string_size(
+                        topic.error_message.as_deref(),
+                        flexible,
+                    )
+                } else {
+                    0
+                };
+                name + 2 + error_message + tags
+            })
+            .sum();
+        let throttle_time = if version >= 1 { 4 } else { 0 };
+        throttle_time
+            + WireFormat::flexible_array_length_size(Some(self.responses.len()), flexible)
+            + responses
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for DeleteTopicsResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> DeleteTopicsRequest {
+        DeleteTopicsRequest {
+            topics: vec![
+                DeleteTopicState {
+                    name: Some("orders".to_string()),
+                    topic_id: Uuid::nil(),
+                },
+                DeleteTopicState {
+                    name: Some("payments".to_string()),
+                    topic_id: Uuid::nil(),
+                },
+            ],
+            timeout_ms: 30_000,
+        }
+    }
+
+    #[test]
+    fn test_v0_layout() {
+        let expected = [
+            "00000002",         // topic_names
+            "00066f7264657273", // orders
+            "00087061796d656e7473",
+            "00007530", // timeout_ms
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(0).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in DELETE_TOPICS_MIN_VERSION..=DELETE_TOPICS_MAX_VERSION {
+            let request = request();
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                DeleteTopicsRequest::decode(&mut encoded, version).unwrap(),
+                request,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v6_topic_by_id() {
+        let request = DeleteTopicsRequest {
+            topics: vec![DeleteTopicState {
+                name: None,
+                topic_id: Uuid::from_u128(7),
+            }],
+            timeout_ms: 1000,
+        };
+        let mut encoded = request.encode(6).unwrap().freeze();
+        assert_eq!(
+            DeleteTopicsRequest::decode(&mut encoded, 6).unwrap(),
+            request
+        );
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = DeleteTopicsResponse {
+            throttle_time_ms: 3,
+            responses: vec![
+                DeletableTopicResult {
+                    name: Some("orders".to_string()),
+                    topic_id: Uuid::from_u128(0x42),
+                    error_code: ErrorCode::NONE,
+                    error_message: None,
+                },
+                DeletableTopicResult {
+                    name: Some("missing".to_string()),
+                    topic_id: Uuid::nil(),
+                    error_code: ErrorCode::UNKNOWN_TOPIC_OR_PARTITION,
+                    error_message: Some("Unknown topic 'missing'".to_string()),
+                },
+            ],
+        };
+        for version in DELETE_TOPICS_MIN_VERSION..=DELETE_TOPICS_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 1 {
+                expected.throttle_time_ms = 0;
+            }
+            for topic in &mut expected.responses {
+                if version < 5 {
+                    topic.error_message = None;
+                }
+                if version < 6 {
+                    topic.topic_id = Uuid::nil();
+                }
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                DeleteTopicsResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecode, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::TaggedFields;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest DescribeCluster version we serve
+pub const DESCRIBE_CLUSTER_MIN_VERSION: i16 = 0;
+
+/// Highest DescribeCluster version we serve
+pub const DESCRIBE_CLUSTER_MAX_VERSION: i16 = 1;
+
+/// `endpoint_type` asking for the brokers' endpoints
+pub const ENDPOINT_TYPE_BROKERS: i8 = 1;
+
+/// `endpoint_type` asking for the controllers' endpoints
+pub const ENDPOINT_TYPE_CONTROLLERS: i8 = 2;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "DescribeCluster",
+        version,
+        DESCRIBE_CLUSTER_MIN_VERSION..=DESCRIBE_CLUSTER_MAX_VERSION,
+    )
+}
+
+/// DescribeCluster request (API key 60)
+///
+/// - v0: whether to include the cluster's authorized operations
+/// - v1+: `endpoint_type`
+///
+/// Every version is flexible. Tagged fields are skipped on decode and
+/// written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeClusterRequest {
+    pub include_cluster_authorized_operations: bool,
+    /// v1+; brokers before that
+    pub endpoint_type: i8,
+}
+
+impl DescribeClusterRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let include_cluster_authorized_operations = WireFormat::decode_bool(buffer)?;
+        let endpoint_type = if version >= 1 {
+            WireFormat::decode_i8(buffer)?
+        } else {
+            ENDPOINT_TYPE_BROKERS
+        };
+        TaggedFields::decode(buffer)?;
+        Ok(Self {
+            include_cluster_authorized_operations,
+            endpoint_type,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for DescribeClusterRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        WireFormat::encode_bool(buffer, self.include_cluster_authorized_operations);
+        if version >= 1 {
+            buffer.put_i8(self.endpoint_type);
+        }
+        WireFormat::encode_unsigned_varint(buffer, 0);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let endpoint_type = if version >= 1 { 1 } else { 0 };
+        1 + endpoint_type + 1
+    }
+}
+
+impl ProtocolDecodeVersioned for DescribeClusterRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// One node of a [`DescribeClusterResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeClusterBroker {
+    pub broker_id: i32,
+    pub host: String,
+    pub port: i32,
+    pub rack: Option<String>,
+}
+
+impl DescribeClusterBroker {
+    fn encoded_size(&self) -> usize {
+        4 + WireFormat::compact_string_size(&self.host)
+            + 4
+            + WireFormat::compact_nullable_string_size(self.rack.as_deref())
+            + 1
+    }
+}
+
+/// DescribeCluster response (API key 60)
+///
+/// - v0: error, cluster id, controller and brokers
+/// - v1+: `endpoint_type`
+///
+/// Every version is flexible. Tagged fields are skipped on decode and
+/// written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeClusterResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+    pub error_message: Option<String>,
+    /// v1+
+    pub endpoint_type: i8,
+    pub cluster_id: String,
+    pub controller_id: i32,
+    pub brokers: Vec<DescribeClusterBroker>,
+    pub cluster_authorized_operations: i32,
+}
+
+impl DescribeClusterResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let throttle_time_ms = WireFormat::decode_i32(buffer)?;
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        let error_message = WireFormat::decode_compact_nullable_string(buffer)?;
+        let endpoint_type = if version >= 1 {
+            WireFormat::decode_i8(buffer)?
+        } else {
+            ENDPOINT_TYPE_BROKERS
+        };
+        let cluster_id = WireFormat::decode_compact_string(buffer)?;
+        let controller_id = WireFormat::decode_i32(buffer)?;
+        let brokers = WireFormat::decode_compact_array(buffer, |buffer| {
+            let broker = DescribeClusterBroker {
+                broker_id: WireFormat::decode_i32(buffer)?,
+                host: WireFormat::decode_compact_string(buffer)?,
+                port: WireFormat::decode_i32(buffer)?,
+                rack: WireFormat::decode_compact_nullable_string(buffer)?,
+            };
+            TaggedFields::decode(buffer)?;
+            Ok(broker)
+        })?;
+        let cluster_authorized_operations = WireFormat::decode_i32(buffer)?;
+        TaggedFields::decode(buffer)?;
+        Ok(Self {
+            throttle_time_ms,
+            error_code,
+            error_message,
+            endpoint_type,
+            cluster_id,
+            controller_id,
+            brokers,
+            cluster_authorized_operations,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for DescribeClusterResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        buffer.put_i32(self.throttle_time_ms);
+        buffer.put_i16(self.error_code.code());
+        WireFormat::encode_compact_nullable_string(buffer, self.error_message.as_deref())?;
+        if version >= 1 {
+            buffer.put_i8(self.endpoint_type);
+        }
+        WireFormat::encode_compact_string(buffer, &self.cluster_id)?;
+        buffer.put_i32(self.controller_id);
+        WireFormat::encode_compact_array(buffer, &self.brokers, |buffer, broker| {
+            buffer.put_i32(broker.broker_id);
+            WireFormat::encode_compact_string(buffer, &broker.host)?;
+            buffer.put_i32(broker.port);
+            WireFormat::encode_compact_nullable_string(buffer, broker.rack.as_deref())?;
+            WireFormat::encode_unsigned_varint(buffer, 0);
+            Ok(())
+        })?;
+        buffer.put_i32(self.cluster_authorized_operations);
+        WireFormat::encode_unsigned_varint(buffer, 0);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let endpoint_type = if version >= 1 { 1 } else { 0 };
+        let brokers: usize = self
+            .brokers
+            .iter()
+            .map(DescribeClusterBroker::encoded_size)
+            .sum();
+        4 + 2
+            + WireFormat::compact_nullable_string_size(self.error_message.as_deref())
+            + endpoint_type
+            + WireFormat::compact_string_size(&self.cluster_id)
+            + 4
+            + WireFormat::unsigned_varint_size(self.brokers.len() as u32 + 1)
+            + brokers
+            + 4
+            + 1
+    }
+}
+
+impl ProtocolDecodeVersioned for DescribeClusterResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> DescribeClusterResponse {
+        DescribeClusterResponse {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            error_message: None,
+            endpoint_type: ENDPOINT_TYPE_BROKERS,
+            cluster_id: "c1".to_string(),
+            controller_id: 1,
+            brokers: vec![DescribeClusterBroker {
+                broker_id: 1,
+                host: "h".to_string(),
+                port: 9092,
+                rack: Some("r1".to_string()),
+            }],
+            cluster_authorized_operations: i32::MIN,
+        }
+    }
+
+    #[test]
+    fn test_v1_response_layout() {
+        let expected = [
+            "00000000", // throttle_time_ms
+            "0000",     // error_code
+            "00",       // error_message
+            "01",       // endpoint_type
+            "036331",   // cluster_id
+            "00000001", // controller_id
+            "02",       // brokers.length
+            "00000001", // brokers[0].broker_id
+            "0268",     // brokers[0].host
+            "00002384", // brokers[0].port
+            "03723100", // brokers[0].rack, tagged fields
+            "80000000", // cluster_authorized_operations
+            "00",       // tagged fields
+        ]
+        .concat();
+        assert_eq!(hex::encode(response().encode(1).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        let request = DescribeClusterRequest {
+            include_cluster_authorized_operations: true,
+            endpoint_type: ENDPOINT_TYPE_CONTROLLERS,
+        };
+        for version in DESCRIBE_CLUSTER_MIN_VERSION..=DESCRIBE_CLUSTER_MAX_VERSION {
+            let mut expected = request.clone();
+            if version < 1 {
+                expected.endpoint_type = ENDPOINT_TYPE_BROKERS;
+            }
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                DescribeClusterRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = DescribeClusterResponse {
+            error_code: ErrorCode::UNSUPPORTED_ENDPOINT_TYPE,
+            error_message: Some("no".to_string()),
+            endpoint_type: 7,
+            ..response()
+        };
+        for version in DESCRIBE_CLUSTER_MIN_VERSION..=DESCRIBE_CLUSTER_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 1 {
+                expected.endpoint_type = ENDPOINT_TYPE_BROKERS;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                DescribeClusterResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v2_is_rejected() {
+        assert!(response().encode(2).is_err());
+    }
+}
@@ -0,0 +1,642 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest DescribeConfigs version we serve
+pub const DESCRIBE_CONFIGS_MIN_VERSION: i16 = 0;
+
+/// Highest DescribeConfigs version we serve
+pub const DESCRIBE_CONFIGS_MAX_VERSION: i16 = 4;
+
+/// First flexible DescribeConfigs version
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+/// First version reporting `config_source` and synonyms instead of `is_default`
+const CONFIG_SOURCE_VERSION: i16 = 1;
+
+/// First version carrying `config_type` and documentation
+const CONFIG_TYPE_VERSION: i16 = 3;
+
+/// `resource_type` of a topic
+pub const RESOURCE_TYPE_TOPIC: i8 = 2;
+
+/// `resource_type` of a broker
+pub const RESOURCE_TYPE_BROKER: i8 = 4;
+
+/// `config_source` of a config whose origin is not known, as v0 reports
+/// every config that is not a default
+pub const CONFIG_SOURCE_UNKNOWN: i8 = 0;
+
+/// `config_source` of a config set on the topic itself
+pub const CONFIG_SOURCE_DYNAMIC_TOPIC: i8 = 1;
+
+/// `config_source` of a config set in the broker's properties file
+pub const CONFIG_SOURCE_STATIC_BROKER: i8 = 4;
+
+/// `config_source` of a config left at its default
+pub const CONFIG_SOURCE_DEFAULT: i8 = 5;
+
+/// `config_type` of a config whose type is not known
+pub const CONFIG_TYPE_UNKNOWN: i8 = 0;
+
+/// `config_type` of a boolean config
+pub const CONFIG_TYPE_BOOLEAN: i8 = 1;
+
+/// `config_type` of a string config
+pub const CONFIG_TYPE_STRING: i8 = 2;
+
+/// `config_type` of a long config
+pub const CONFIG_TYPE_LONG: i8 = 5;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "DescribeConfigs",
+        version,
+        DESCRIBE_CONFIGS_MIN_VERSION..=DESCRIBE_CONFIGS_MAX_VERSION,
+    )
+}
+
+/// DescribeConfigs request (API key 32)
+///
+/// - v0: the resources to describe, each with an optional key filter
+/// - v1+: `include_synonyms`
+/// - v3+: `include_documentation`
+/// - v4+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeConfigsRequest {
+    pub resources: Vec<DescribeConfigsResource>,
+    /// v1+
+    pub include_synonyms: bool,
+    /// v3+
+    pub include_documentation: bool,
+}
+
+/// A resource named in a [`DescribeConfigsRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeConfigsResource {
+    pub resource_type: i8,
+    pub resource_name: String,
+    /// Keys to describe; null for every key
+    pub configuration_keys: Option<Vec<String>>,
+}
+
+impl DescribeConfigsRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let resources = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let resource = DescribeConfigsResource {
+                resource_type: WireFormat::decode_i8(buffer)?,
+                resource_name: WireFormat::decode_flexible_string(buffer, flexible)?,
+                configuration_keys: WireFormat::decode_flexible_nullable_array(
+                    buffer,
+                    flexible,
+                    |buffer| WireFormat::decode_flexible_string(buffer, flexible),
+                )?,
+            };
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(resource)
+        })?;
+        let include_synonyms = if version >= CONFIG_SOURCE_VERSION {
+            WireFormat::decode_bool(buffer)?
+        } else {
+            false
+        };
+        let include_documentation = if version >= CONFIG_TYPE_VERSION {
+            WireFormat::decode_bool(buffer)?
+        } else {
+            false
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            resources,
+            include_synonyms,
+            include_documentation,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for DescribeConfigsRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_array(
+            buffer,
+            &self.resources,
+            flexible,
+            |buffer, resource| {
+                buffer.put_i8(resource.resource_type);
+                WireFormat::encode_flexible_string(buffer, &resource.resource_name, flexible)?;
+                WireFormat::encode_flexible_nullable_array(
+                    buffer,
+                    resource.configuration_keys.as_deref(),
+                    flexible,
+                    |buffer, key| WireFormat::encode_flexible_string(buffer, key, flexible),
+                )?;
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            },
+        )?;
+        if version >= CONFIG_SOURCE_VERSION {
+            WireFormat::encode_bool(buffer, self.include_synonyms);
+        }
+        if version >= CONFIG_TYPE_VERSION {
+            WireFormat::encode_bool(buffer, self.include_documentation);
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let resources: usize = self
+            .resources
+            .iter()
+            .map(|resource| {
+                let keys: usize = resource.configuration_keys.as_deref().map_or(0, |keys| {
+                    keys.iter()
+                        .map(|key| WireFormat::flexible_string_size(key, flexible))
+                        .sum()
+                });
+                1 + WireFormat::flexible_string_size(&resource.resource_name, flexible)
+                    + WireFormat::flexible_array_length_size(
+                        resource.configuration_keys.as_ref().map(Vec::len),
+                        flexible,
+                    )
+                    + keys
+                    + tags
+            })
+            .sum();
+        let include_synonyms = usize::from(version >= CONFIG_SOURCE_VERSION);
+        let include_documentation = usize::from(version >= CONFIG_TYPE_VERSION);
+        WireFormat::flexible_array_length_size(Some(self.resources.len()), flexible)
+            + resources
+            + include_synonyms
+            + include_documentation
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for DescribeConfigsRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// DescribeConfigs response (API key 32)
+///
+/// - v0: configs flag whether they are defaults
+/// - v1+: configs carry their source and synonyms instead
+/// - v3+: configs carry their type and documentation
+/// - v4+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeConfigsResponse {
+    pub throttle_time_ms: i32,
+    pub results: Vec<DescribeConfigsResult>,
+}
+
+/// The configs of one resource, in a [`DescribeConfigsResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeConfigsResult {
+    pub error_code: ErrorCode,
+    pub error_message: Option<String>,
+    pub resource_type: i8,
+    pub resource_name: String,
+    pub configs: Vec<DescribeConfigsResourceResult>,
+}
+
+/// One config of a [`DescribeConfigsResult`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeConfigsResourceResult {
+    pub name: String,
+    /// Null for a sensitive config
+    pub value: Option<String>,
+    pub read_only: bool,
+    /// Sent as `is_default` in v0, where the other sources read back as
+    /// [`CONFIG_SOURCE_UNKNOWN`]
+    pub config_source: i8,
+    pub is_sensitive: bool,
+    /// v1+
+    pub synonyms: Vec<DescribeConfigsSynonym>,
+    /// v3+
+    pub config_type: i8,
+    /// v3+
+    pub documentation: Option<String>,
+}
+
+/// Where else a config's value could come from, most specific first
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeConfigsSynonym {
+    pub name: String,
+    pub value: Option<String>,
+    pub source: i8,
+}
+
+impl DescribeConfigsResult {
+    /// The result for a resource that could not be described
+    pub fn error(
+        resource_type: i8,
+        resource_name: impl Into<String>,
+        error_code: ErrorCode,
+        error_message: Option<String>,
+    ) -> Self {
+        Self {
+            error_code,
+            error_message,
+            resource_type,
+            resource_name: resource_name.into(),
+            configs: Vec::new(),
+        }
+    }
+}
+
+impl DescribeConfigsResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = WireFormat::decode_i32(buffer)?;
+        let results = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+            let error_message = WireFormat::decode_flexible_nullable_string(buffer, flexible)?;
+            let resource_type = WireFormat::decode_i8(buffer)?;
+            let resource_name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let configs = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                decode_config(buffer, version, flexible)
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(DescribeConfigsResult {
+                error_code,
+                error_message,
+                resource_type,
+                resource_name,
+                configs,
+            })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            results,
+        })
+    }
+}
+
+fn decode_config(
+    buffer: &mut Bytes,
+    version: i16,
+    flexible: bool,
+) -> ProtocolResult<DescribeConfigsResourceResult> {
+    let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+    let value = WireFormat::decode_flexible_nullable_string(buffer, flexible)?;
+    let read_only = WireFormat::decode_bool(buffer)?;
+    let config_source = if version >= CONFIG_SOURCE_VERSION {
+        WireFormat::decode_i8(buffer)?
+    } else if WireFormat::decode_bool(buffer)? {
+        CONFIG_SOURCE_DEFAULT
+    } else {
+        CONFIG_SOURCE_UNKNOWN
+    };
+    let is_sensitive = WireFormat::decode_bool(buffer)?;
+    let synonyms = if version >= CONFIG_SOURCE_VERSION {
+        WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let synonym = DescribeConfigsSynonym {
+                name: WireFormat::decode_flexible_string(buffer, flexible)?,
+                value: WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                source: WireFormat::decode_i8(buffer)?,
+            };
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(synonym)
+        })?
+    } else {
+        Vec::new()
+    };
+    let (config_type, documentation) = if version >= CONFIG_TYPE_VERSION {
+        (
+            WireFormat::decode_i8(buffer)?,
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+        )
+    } else {
+        (CONFIG_TYPE_UNKNOWN, None)
+    };
+    skip_tagged_fields(buffer, flexible)?;
+    Ok(DescribeConfigsResourceResult {
+        name,
+        value,
+        read_only,
+        config_source,
+        is_sensitive,
+        synonyms,
+        config_type,
+        documentation,
+    })
+}
+
+impl ProtocolEncodeVersioned for DescribeConfigsResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        buffer.put_i32(self.throttle_time_ms);
+        WireFormat::encode_flexible_array(buffer, &self.results, flexible, |buffer, result| {
+            buffer.put_i16(result.error_code.code());
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                result.error_message.as_deref(),
+                flexible,
+            )?;
+            buffer.put_i8(result.resource_type);
+            WireFormat::encode_flexible_string(buffer, &result.resource_name, flexible)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &result.configs,
+                flexible,
+                |buffer, config| encode_config(buffer, config, version, flexible),
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let results: usize = self
+            .results
+            .iter()
+            .map(|result| {
+                let configs: usize = result
+                    .configs
+                    .iter()
+                    .map(|config| config_size(config, version, flexible))
+                    .sum();
+                2 + WireFormat::flexible_nullable_string_size(
+                    result.error_message.as_deref(),
+                    flexible,
+                ) + 1
+                    + WireFormat::flexible_string_size(&result.resource_name, flexible)
+                    + WireFormat::flexible_array_length_size(Some(result.configs.len()), flexible)
+                    + configs
+                    + tags
+            })
+            .sum();
+        4 + WireFormat::flexible_array_length_size(Some(self.results.len()), flexible)
+            + results
+            + tags
+    }
+}
+
+fn encode_config(
+    buffer: &mut BytesMut,
+    config: &DescribeConfigsResourceResult,
+    version: i16,
+    flexible: bool,
+) -> ProtocolResult<()> {
+    WireFormat::encode_flexible_string(buffer, &config.name, flexible)?;
+    WireFormat::encode_flexible_nullable_string(buffer, config.value.as_deref(), flexible)?;
+    WireFormat::encode_bool(buffer, config.read_only);
+    if version >= CONFIG_SOURCE_VERSION {
+        buffer.put_i8(config.config_source);
+    } else {
+        WireFormat::encode_bool(buffer, config.config_source == CONFIG_SOURCE_DEFAULT);
+    }
+    WireFormat::encode_bool(buffer, config.is_sensitive);
+    if version >= CONFIG_SOURCE_VERSION {
+        WireFormat::encode_flexible_array(
+            buffer,
+            &config.synonyms,
+            flexible,
+            |buffer, synonym| {
+                WireFormat::encode_flexible_string(buffer, &synonym.name, flexible)?;
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    synonym.value.as_deref(),
+                    flexible,
+                )?;
+                buffer.put_i8(synonym.source);
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            },
+        )?;
+    }
+    if version >= CONFIG_TYPE_VERSION {
+        buffer.put_i8(config.config_type);
+        WireFormat::encode_flexible_nullable_string(
+            buffer,
+            config.documentation.as_deref(),
+            flexible,
+        )?;
+    }
+    put_empty_tagged_fields(buffer, flexible);
+    Ok(())
+}
+
+fn config_size(config: &DescribeConfigsResourceResult, version: i16, flexible: bool) -> usize {
+    let tags = usize::from(flexible);
+    let synonyms = if version >= CONFIG_SOURCE_VERSION {
+        let synonyms: usize = config
+            .synonyms
+            .iter()
+            .map(|synonym| {
+                WireFormat::flexible_string_size(&synonym.name, flexible)
+                    + WireFormat::flexible_nullable_string_size(synonym.value.as_deref(), flexible)
+                    + 1
+                    + tags
+            })
+            .sum();
+        WireFormat::flexible_array_length_size(Some(config.synonyms.len()), flexible) + synonyms
+    } else {
+        0
+    };
+    let config_type = if version >= CONFIG_TYPE_VERSION {
+        1 + WireFormat::flexible_nullable_string_size(config.documentation.as_deref(), flexible)
+    } else {
+        0
+    };
+    WireFormat::flexible_string_size(&config.name, flexible)
+        + WireFormat::flexible_nullable_string_size(config.value.as_deref(), flexible)
+        + 1
+        + 1
+        + 1
+        + synonyms
+        + config_type
+        + tags
+}
+
+impl ProtocolDecodeVersioned for DescribeConfigsResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> DescribeConfigsRequest {
+        DescribeConfigsRequest {
+            resources: vec![
+                DescribeConfigsResource {
+                    resource_type: RESOURCE_TYPE_TOPIC,
+                    resource_name: "orders".to_string(),
+                    configuration_keys: Some(vec!["retention.ms".to_string()]),
+                },
+                DescribeConfigsResource {
+                    resource_type: RESOURCE_TYPE_BROKER,
+                    resource_name: "1".to_string(),
+                    configuration_keys: None,
+                },
+            ],
+            include_synonyms: true,
+            include_documentation: true,
+        }
+    }
+
+    fn response() -> DescribeConfigsResponse {
+        DescribeConfigsResponse {
+            throttle_time_ms: 0,
+            results: vec![
+                DescribeConfigsResult {
+                    error_code: ErrorCode::NONE,
+                    error_message: None,
+                    resource_type: RESOURCE_TYPE_TOPIC,
+                    resource_name: "orders".to_string(),
+                    configs: vec![DescribeConfigsResourceResult {
+                        name: "retention.ms".to_string(),
+                        value: Some("1000".to_string()),
+                        read_only: false,
+                        config_source: CONFIG_SOURCE_DYNAMIC_TOPIC,
+                        is_sensitive: false,
+                        synonyms: vec![DescribeConfigsSynonym {
+                            name: "retention.ms".to_string(),
+                            value: Some("1000".to_string()),
+                            source: CONFIG_SOURCE_DYNAMIC_TOPIC,
+                        }],
+                        config_type: CONFIG_TYPE_LONG,
+                        documentation: None,
+                    }],
+                },
+                DescribeConfigsResult::error(
+                    RESOURCE_TYPE_TOPIC,
+                    "missing",
+                    ErrorCode::UNKNOWN_TOPIC_OR_PARTITION,
+                    Some("Unknown topic: missing".to_string()),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_v4_request_layout() {
+        let request = DescribeConfigsRequest {
+            resources: vec![request().resources.remove(0)],
+            ..request()
+        };
+        let expected = [
+            "02",                         // resources.length
+            "02",                         // resources[0].resource_type
+            "076f7264657273",             // resources[0].resource_name
+            "02",                         // resources[0].configuration_keys.length
+            "0d726574656e74696f6e2e6d73", // resources[0].configuration_keys[0]
+            "00",                         // resources[0] tagged fields
+            "01",                         // include_synonyms
+            "01",                         // include_documentation
+            "00",                         // tagged fields
+        ]
+        .concat();
+        assert_eq!(hex::encode(request.encode(4).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        let request = request();
+        for version in DESCRIBE_CONFIGS_MIN_VERSION..=DESCRIBE_CONFIGS_MAX_VERSION {
+            let mut expected = request.clone();
+            expected.include_synonyms &= version >= CONFIG_SOURCE_VERSION;
+            expected.include_documentation &= version >= CONFIG_TYPE_VERSION;
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                DescribeConfigsRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = response();
+        for version in DESCRIBE_CONFIGS_MIN_VERSION..=DESCRIBE_CONFIGS_MAX_VERSION {
+            let mut expected = response.clone();
+            for config in &mut expected.results[0].configs {
+                if version < CONFIG_SOURCE_VERSION {
+                    config.config_source = CONFIG_SOURCE_UNKNOWN;
+                    config.synonyms.clear();
+                }
+                if version < CONFIG_TYPE_VERSION {
+                    config.config_type = CONFIG_TYPE_UNKNOWN;
+                }
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                DescribeConfigsResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v0_reports_defaults() {
+        let mut response = response();
+        response.results[0].configs[0].config_source = CONFIG_SOURCE_DEFAULT;
+        let mut encoded = response.encode(0).unwrap().freeze();
+        let decoded = DescribeConfigsResponse::decode(&mut encoded, 0).unwrap();
+        assert_eq!(
+            decoded.results[0].configs[0].config_source,
+            CONFIG_SOURCE_DEFAULT
+        );
+    }
+
+    #[test]
+    fn test_v5_is_rejected() {
+        assert!(request().encode(5).is_err());
+    }
+}
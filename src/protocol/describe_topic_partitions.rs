@@ -0,0 +1,483 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecode, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::tagged_fields::TaggedFields;
+use bytes::{BufMut, Bytes, BytesMut};
+use uuid::Uuid;
+
+/// Lowest DescribeTopicPartitions version we serve
+pub const DESCRIBE_TOPIC_PARTITIONS_MIN_VERSION: i16 = 0;
+
+/// Highest DescribeTopicPartitions version we serve
+pub const DESCRIBE_TOPIC_PARTITIONS_MAX_VERSION: i16 = 0;
+
+/// `topic_authorized_operations` when authorized operations were not
+/// computed
+pub const AUTHORIZED_OPERATIONS_OMITTED: i32 = i32::MIN;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "DescribeTopicPartitions",
+        version,
+        DESCRIBE_TOPIC_PARTITIONS_MIN_VERSION..=DESCRIBE_TOPIC_PARTITIONS_MAX_VERSION,
+    )
+}
+
+/// Where a paginated DescribeTopicPartitions listing continues
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeTopicPartitionsCursor {
+    pub topic_name: String,
+    pub partition_index: i32,
+}
+
+impl DescribeTopicPartitionsCursor {
+    /// Decodes a nullable cursor: -1 for null, 1 followed by the fields
+    fn decode_nullable(buffer: &mut Bytes) -> ProtocolResult<Option<Self>> {
+        match WireFormat::decode_i8(buffer)? {
+            -1 => Ok(None),
+            1 => {
+                let cursor = Self {
+                    topic_name: WireFormat::decode_compact_string(buffer)?,
+                    partition_index: WireFormat::decode_i32(buffer)?,
+                };
+                TaggedFields::decode(buffer)?;
+                Ok(Some(cursor))
+            }
+            marker => Err(ProtocolError::InvalidFormat(format!(
+                "invalid cursor presence marker {}",
+                marker
+            ))),
+        }
+    }
+
+    fn encode_nullable(cursor: Option<&Self>, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        let Some(cursor) = cursor else {
+            buffer.put_i8(-1);
+            return Ok(());
+        };
+        buffer.put_i8(1);
+        WireFormat::encode_compact_string(buffer, &cursor.topic_name)?;
+        buffer.put_i32(cursor.partition_index);
+        WireFormat::encode_unsigned_varint(buffer, 0);
+        Ok(())
+    }
+
+    fn nullable_size(cursor: Option<&Self>) -> usize {
+        1 + cursor.map_or(0, |cursor| {
+            WireFormat::compact_string_size(&cursor.topic_name) + 4 + 1
+        })
+    }
+}
+
+/// DescribeTopicPartitions request (API key 75)
+///
+/// Only v0 exists. It is flexible, so every string and array is compact and
+/// each struct ends in a tag section; tags we do not know are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeTopicPartitionsRequest {
+    pub topics: Vec<String>,
+    /// Most partitions to return across all topics
+    pub response_partition_limit: i32,
+    /// Where to resume a listing cut short by the partition limit
+    pub cursor: Option<DescribeTopicPartitionsCursor>,
+}
+
+impl DescribeTopicPartitionsRequest {
+    /// Decodes the request body for the given version
+    ///
+    /// # Examples
+    /// ```
+    /// use codecrafters_kafka::protocol::describe_topic_partitions::DescribeTopicPartitionsRequest;
+    /// use codecrafters_kafka::protocol::ProtocolEncodeVersioned;
+    /// use bytes::BytesMut;
+    ///
+    /// let request = DescribeTopicPartitionsRequest {
+    ///     topics: vec!["orders".to_string()],
+    ///     response_partition_limit: 100,
+    ///     cursor: None,
+    /// };
+    /// let mut buffer = BytesMut::new();
+    /// request.encode_versioned(0, &mut buffer).unwrap();
+    /// let decoded = DescribeTopicPartitionsRequest::decode(&mut buffer.freeze(), 0).unwrap();
+    /// assert_eq!(decoded, request);
+    /// ```
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let topics = WireFormat::decode_compact_array(buffer, |buffer| {
+            let name = WireFormat::decode_compact_string(buffer)?;
+            TaggedFields::decode(buffer)?;
+            Ok(name)
+        })?;
+        let response_partition_limit = WireFormat::decode_i32(buffer)?;
+        let cursor = DescribeTopicPartitionsCursor::decode_nullable(buffer)?;
+        TaggedFields::decode(buffer)?;
+        Ok(Self {
+            topics,
+            response_partition_limit,
+            cursor,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for DescribeTopicPartitionsRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        WireFormat::encode_compact_array(buffer, &self.topics, |buffer, name| {
+            WireFormat::encode_compact_string(buffer, name)?;
+            WireFormat::encode_unsigned_varint(buffer, 0);
+            Ok(())
+        })?;
+        buffer.put_i32(self.response_partition_limit);
+        DescribeTopicPartitionsCursor::encode_nullable(self.cursor.as_ref(), buffer)?;
+        WireFormat::encode_unsigned_varint(buffer, 0);
+        Ok(())
+    }
+
+    fn encoded_size(&self, _version: i16) -> usize {
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|name| WireFormat::compact_string_size(name) + 1)
+            .sum();
+        compact_array_length_size(self.topics.len())
+            + topics
+            + 4
+            + DescribeTopicPartitionsCursor::nullable_size(self.cursor.as_ref())
+            + 1
+    }
+}
+
+impl ProtocolDecodeVersioned for DescribeTopicPartitionsRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// DescribeTopicPartitions response (API key 75)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeTopicPartitionsResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<DescribeTopicPartitionsResponseTopic>,
+    /// Set when the partition limit cut the listing short
+    pub next_cursor: Option<DescribeTopicPartitionsCursor>,
+}
+
+/// Per-topic result of a [`DescribeTopicPartitionsResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeTopicPartitionsResponseTopic {
+    pub error_code: ErrorCode,
+    pub name: Option<String>,
+    /// Nil for a topic that does not exist
+    pub topic_id: Uuid,
+    pub is_internal: bool,
+    pub partitions: Vec<DescribeTopicPartitionsResponsePartition>,
+    pub topic_authorized_operations: i32,
+}
+
+impl DescribeTopicPartitionsResponseTopic {
+    /// The result for a topic that does not exist, echoing its name
+    pub fn unknown(name: impl Into<String>) -> Self {
+        Self {
+            error_code: ErrorCode::UNKNOWN_TOPIC_OR_PARTITION,
+            name: Some(name.into()),
+            topic_id: Uuid::nil(),
+            is_internal: false,
+            partitions: Vec::new(),
+            topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        }
+    }
+}
+
+/// Per-partition result of a [`DescribeTopicPartitionsResponseTopic`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeTopicPartitionsResponsePartition {
+    pub error_code: ErrorCode,
+    pub partition_index: i32,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub replica_nodes: Vec<i32>,
+    pub isr_nodes: Vec<i32>,
+    pub eligible_leader_replicas: Option<Vec<i32>>,
+    pub last_known_elr: Option<Vec<i32>>,
+    pub offline_replicas: Vec<i32>,
+}
+
+impl DescribeTopicPartitionsResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    ///
+    /// Tagged fields are skipped.
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let throttle_time_ms = WireFormat::decode_i32(buffer)?;
+        let topics = WireFormat::decode_compact_array(buffer, |buffer| {
+            let topic = DescribeTopicPartitionsResponseTopic {
+                error_code: ErrorCode::from_wire(WireFormat::decode_i16(buffer)?),
+                name: WireFormat::decode_compact_nullable_string(buffer)?,
+                topic_id: WireFormat::decode_uuid(buffer)?,
+                is_internal: WireFormat::decode_bool(buffer)?,
+                partitions: WireFormat::decode_compact_array(buffer, decode_partition)?,
+                topic_authorized_operations: WireFormat::decode_i32(buffer)?,
+            };
+            TaggedFields::decode(buffer)?;
+            Ok(topic)
+        })?;
+        let next_cursor = DescribeTopicPartitionsCursor::decode_nullable(buffer)?;
+        TaggedFields::decode(buffer)?;
+        Ok(Self {
+            throttle_time_ms,
+            topics,
+            next_cursor,
+        })
+    }
+}
+
+fn decode_partition(
+    buffer: &mut Bytes,
+) -> ProtocolResult<DescribeTopicPartitionsResponsePartition> {
+    let node_ids = |buffer: &mut Bytes| WireFormat::decode_compact_array(buffer, decode_node_id);
+    let partition = DescribeTopicPartitionsResponsePartition {
+        error_code: ErrorCode::from_wire(WireFormat::decode_i16(buffer)?),
+        partition_index: WireFormat::decode_i32(buffer)?,
+        leader_id: WireFormat::decode_i32(buffer)?,
+        leader_epoch: WireFormat::decode_i32(buffer)?,
+        replica_nodes: node_ids(buffer)?,
+        isr_nodes: node_ids(buffer)?,
+        eligible_leader_replicas: WireFormat::decode_compact_nullable_array(
+            buffer,
+            decode_node_id,
+        )?,
+        last_known_elr: WireFormat::decode_compact_nullable_array(buffer, decode_node_id)?,
+        offline_replicas: node_ids(buffer)?,
+    };
+    TaggedFields::decode(buffer)?;
+    Ok(partition)
+}
+
+fn decode_node_id(buffer: &mut Bytes) -> ProtocolResult<i32> {
+    WireFormat::decode_i32(buffer)
+}
+
+fn encode_node_id(buffer: &mut BytesMut, node_id: &i32) -> ProtocolResult<()> {
+    buffer.put_i32(*node_id);
+    Ok(())
+}
+
+/// Encoded size of a COMPACT_ARRAY length prefix for `count` elements
+fn compact_array_length_size(count: usize) -> usize {
+    WireFormat::unsigned_varint_size(count as u32 + 1)
+}
+
+fn node_ids_size(node_ids: Option<&[i32]>) -> usize {
+    node_ids.map_or(1, |node_ids| {
+        compact_array_length_size(node_ids.len()) + 4 * node_ids.len()
+    })
+}
+
+impl ProtocolEncodeVersioned for DescribeTopicPartitionsResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        buffer.put_i32(self.throttle_time_ms);
+        WireFormat::encode_compact_array(buffer, &self.topics, |buffer, topic| {
+            buffer.put_i16(topic.error_code.code());
+            WireFormat::encode_compact_nullable_string(buffer, topic.name.as_deref())?;
+            WireFormat::encode_uuid(buffer, &topic.topic_id);
+            WireFormat::encode_bool(buffer, topic.is_internal);
+            WireFormat::encode_compact_array(buffer, &topic.partitions, |buffer, partition| {
+                buffer.put_i16(partition.error_code.code());
+                buffer.put_i32(partition.partition_index);
+                buffer.put_i32(partition.leader_id);
+                buffer.put_i32(partition.leader_epoch);
+                WireFormat::encode_compact_array(buffer, &partition.replica_nodes, encode_node_id)?;
+                WireFormat::encode_compact_array(buffer, &partition.isr_nodes, encode_node_id)?;
+                WireFormat::encode_compact_nullable_array(
+                    buffer,
+                    partition.eligible_leader_replicas.as_deref(),
+                    encode_node_id,
+                )?;
+                WireFormat::encode_compact_nullable_array(
+                    buffer,
+                    partition.last_known_elr.as_deref(),
+                    encode_node_id,
+                )?;
+                WireFormat::encode_compact_array(
+                    buffer,
+                    &partition.offline_replicas,
+                    encode_node_id,
+                )?;
+                WireFormat::encode_unsigned_varint(buffer, 0);
+                Ok(())
+            })?;
+            buffer.put_i32(topic.topic_authorized_operations);
+            WireFormat::encode_unsigned_varint(buffer, 0);
+            Ok(())
+        })?;
+        DescribeTopicPartitionsCursor::encode_nullable(self.next_cursor.as_ref(), buffer)?;
+        WireFormat::encode_unsigned_varint(buffer, 0);
+        Ok(())
+    }
+
+    fn encoded_size(&self, _version: i16) -> usize {
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                let partitions: usize = topic
+                    .partitions
+                    .iter()
+                    .map(|partition| {
+                        2 + 4
+                            + 4
+                            + 4
+                            + node_ids_size(Some(&partition.replica_nodes))
+                            + node_ids_size(Some(&partition.isr_nodes))
+                            + node_ids_size(partition.eligible_leader_replicas.as_deref())
+                            + node_ids_size(partition.last_known_elr.as_deref())
+                            + node_ids_size(Some(&partition.offline_replicas))
+                            + 1
+                    })
+                    .sum();
+                2 + WireFormat::compact_nullable_string_size(topic.name.as_deref())
+                    + 16
+                    + 1
+                    + compact_array_length_size(topic.partitions.len())
+                    + partitions
+                    + 4
+                    + 1
+            })
+            .sum();
+        4 + compact_array_length_size(self.topics.len())
+            + topics
+            + DescribeTopicPartitionsCursor::nullable_size(self.next_cursor.as_ref())
+            + 1
+    }
+}
+
+impl ProtocolDecodeVersioned for DescribeTopicPartitionsResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Request body as sent by the stage tester for two topics
+    fn request_fixture() -> Vec<u8> {
+        let mut body = vec![0x03];
+        for name in [&b"foo"[..], &b"bar"[..]] {
+            body.push(name.len() as u8 + 1);
+            body.extend_from_slice(name);
+            body.push(0);
+        }
+        body.extend_from_slice(&100i32.to_be_bytes());
+        body.push(0xff); // null cursor
+        body.push(0);
+        body
+    }
+
+    #[test]
+    fn test_request_decodes_tester_bytes() {
+        let mut buffer = Bytes::from(request_fixture());
+        let request = DescribeTopicPartitionsRequest::decode(&mut buffer, 0).unwrap();
+        assert_eq!(request.topics, vec!["foo", "bar"]);
+        assert_eq!(request.response_partition_limit, 100);
+        assert_eq!(request.cursor, None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_request_roundtrip_with_cursor() {
+        let request = DescribeTopicPartitionsRequest {
+            topics: vec!["a".to_string()],
+            response_partition_limit: 1,
+            cursor: Some(DescribeTopicPartitionsCursor {
+                topic_name: "a".to_string(),
+                partition_index: 3,
+            }),
+        };
+        let mut buffer = BytesMut::new();
+        request.encode_versioned(0, &mut buffer).unwrap();
+        assert_eq!(buffer.len(), request.encoded_size(0));
+        let mut buffer = buffer.freeze();
+        assert_eq!(
+            DescribeTopicPartitionsRequest::decode(&mut buffer, 0).unwrap(),
+            request
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_topic_layout() {
+        let response = DescribeTopicPartitionsResponse {
+            throttle_time_ms: 0,
+            topics: vec![DescribeTopicPartitionsResponseTopic::unknown("missing")],
+            next_cursor: None,
+        };
+        let encoded = response.encode(0).unwrap();
+        let mut expected = vec![0, 0, 0, 0, 0x02, 0x00, 0x03, 0x08];
+        expected.extend_from_slice(b"missing");
+        expected.extend_from_slice(&[0; 16]); // nil topic id
+        expected.extend_from_slice(&[0x00, 0x01]); // not internal, no partitions
+        expected.extend_from_slice(&i32::MIN.to_be_bytes());
+        expected.extend_from_slice(&[0x00, 0xff, 0x00]);
+        assert_eq!(&encoded[..], &expected[..]);
+        assert_eq!(encoded.len(), response.encoded_size(0));
+    }
+
+    #[test]
+    fn test_response_roundtrip() {
+        let partition = |index| DescribeTopicPartitionsResponsePartition {
+            error_code: ErrorCode::NONE,
+            partition_index: index,
+            leader_id: 1,
+            leader_epoch: 0,
+            replica_nodes: vec![1, 2],
+            isr_nodes: vec![1],
+            eligible_leader_replicas: None,
+            last_known_elr: Some(vec![]),
+            offline_replicas: vec![2],
+        };
+        let response = DescribeTopicPartitionsResponse {
+            throttle_time_ms: 5,
+            topics: vec![
+                DescribeTopicPartitionsResponseTopic {
+                    error_code: ErrorCode::NONE,
+                    name: Some("orders".to_string()),
+                    topic_id: Uuid::from_u128(0x42),
+                    is_internal: false,
+                    partitions: vec![partition(0), partition(1)],
+                    topic_authorized_operations: 0x0df8,
+                },
+                DescribeTopicPartitionsResponseTopic::unknown("missing"),
+            ],
+            next_cursor: Some(DescribeTopicPartitionsCursor {
+                topic_name: "orders".to_string(),
+                partition_index: 2,
+            }),
+        };
+        let encoded = response.encode(0).unwrap();
+        assert_eq!(encoded.len(), response.encoded_size(0));
+        let mut buffer = encoded.freeze();
+        assert_eq!(
+            DescribeTopicPartitionsResponse::decode(&mut buffer, 0).unwrap(),
+            response
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_version_is_rejected() {
+        let mut buffer = Bytes::from(request_fixture());
+        assert!(DescribeTopicPartitionsRequest::decode(&mut buffer, 1).is_err());
+    }
+}
@@ -1,5 +1,11 @@
+use crate::logging::{trace, warn};
+use crate::protocol::decode_limits::{self, DecodeLimits};
 use crate::protocol::errors::{ProtocolError, ProtocolResult};
-use bytes::{Buf, BufMut, BytesMut};
+use crate::protocol::spec::{MAX_STRING_LENGTH, NULL_STRING_MARKER};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use uuid::Uuid;
 
 /// Trait for encoding protocol messages to bytes
 ///
@@ -8,63 +14,175 @@ use bytes::{Buf, BufMut, BytesMut};
 pub trait ProtocolEncode {
     /// Encodes the message to bytes
     fn encode(&self) -> ProtocolResult<BytesMut>;
+
+    /// Appends the encoded message to `buffer`
+    ///
+    /// The default goes through [`encode`](Self::encode) and copies;
+    /// implementations should write into `buffer` directly so a whole
+    /// response frame can be built in one allocation.
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        buffer.extend_from_slice(&self.encode()?);
+        Ok(())
+    }
+
+    /// Number of bytes [`encode_to`](Self::encode_to) appends
+    ///
+    /// Exact or an upper bound, so callers can size a buffer up front and
+    /// encode into it without reallocating.
+    fn encoded_size(&self) -> usize;
 }
 
 /// Trait for decoding protocol messages from bytes
 ///
 /// This trait provides a common interface for decoding various Kafka protocol
-/// structures from byte buffers.
+/// structures from byte buffers. Decoders read from a frozen frame, so
+/// BYTES fields come back as views into it rather than copies.
 pub trait ProtocolDecode: Sized {
     /// Decodes the message from a byte buffer
-    fn decode(buffer: &mut BytesMut) -> ProtocolResult<Self>;
+    fn decode(buffer: &mut Bytes) -> ProtocolResult<Self>;
+}
+
+/// Trait for encoding messages whose layout depends on the API version
+///
+/// One struct covers every version of a message; fields a version does not
+/// carry are skipped on encode and defaulted on decode.
+pub trait ProtocolEncodeVersioned {
+    /// Appends the message as laid out at `version` to `buffer`
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()>;
+
+    /// Number of bytes [`encode_versioned`](Self::encode_versioned) appends
+    /// at `version`, exact or an upper bound
+    fn encoded_size(&self, version: i16) -> usize;
+}
+
+/// Decoding counterpart of [`ProtocolEncodeVersioned`]
+pub trait ProtocolDecodeVersioned: Sized {
+    /// Decodes the message as laid out at `version`
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self>;
+}
+
+/// Rejects a `version` of `message` outside `supported`
+///
+/// ```
+/// use codecrafters_kafka::protocol::encoding::check_version;
+///
+/// assert!(check_version("Produce", 2, 0..=2).is_ok());
+/// let error = check_version("Produce", 3, 0..=2).unwrap_err();
+/// assert_eq!(error.to_string(), "Invalid message format: unsupported Produce version 3");
+/// ```
+pub fn check_version(
+    message: &str,
+    version: i16,
+    supported: RangeInclusive<i16>,
+) -> ProtocolResult<()> {
+    if supported.contains(&version) {
+        Ok(())
+    } else {
+        Err(ProtocolError::InvalidFormat(format!(
+            "unsupported {} version {}",
+            message, version
+        )))
+    }
+}
+
+/// Topic and incarnation ids are plain [`Uuid`]s, so message structs can
+/// embed them and encode them like any other field
+impl ProtocolEncode for Uuid {
+    fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(16);
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        WireFormat::encode_uuid(buffer, self);
+        Ok(())
+    }
+
+    fn encoded_size(&self) -> usize {
+        16
+    }
+}
+
+impl ProtocolDecode for Uuid {
+    fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        WireFormat::decode_uuid(buffer)
+    }
 }
 
 /// Utility functions for Kafka wire protocol encoding/decoding
 pub struct WireFormat;
 
 impl WireFormat {
-    /// Prints a hex dump of the buffer for debugging
-    pub fn debug_hex_dump(buffer: &BytesMut, label: &str) {
-        println!("{}: {} bytes", label, buffer.len());
-        let bytes: Vec<u8> = buffer.iter().copied().collect();
-        for (i, chunk) in bytes.chunks(16).enumerate() {
-            print!("{:04X}: ", i * 16);
+    /// Formats a hex dump of at most `max_bytes` of the buffer, 16 bytes per
+    /// line with an ASCII column
+    ///
+    /// A longer buffer ends with a line counting the bytes left out. Only
+    /// called when something will show the dump, since it allocates and
+    /// formats every byte.
+    pub fn hex_dump(buffer: &[u8], max_bytes: usize) -> String {
+        let shown = &buffer[..buffer.len().min(max_bytes)];
+        let mut dump = String::new();
+        for (i, chunk) in shown.chunks(16).enumerate() {
+            let _ = write!(dump, "{:04X}: ", i * 16);
             for (j, byte) in chunk.iter().enumerate() {
-                print!("{:02X} ", byte);
+                let _ = write!(dump, "{:02X} ", byte);
                 if j == 7 {
-                    print!(" ");
+                    dump.push(' ');
                 }
             }
             // Pad if less than 16 bytes
             for _ in chunk.len()..16 {
-                print!("   ");
+                dump.push_str("   ");
             }
-            print!(" |");
+            if chunk.len() <= 8 {
+                dump.push(' ');
+            }
+            dump.push_str(" |");
             for &byte in chunk {
                 if byte.is_ascii_graphic() || byte == b' ' {
-                    print!("{}", byte as char);
+                    dump.push(byte as char);
                 } else {
-                    print!(".");
+                    dump.push('.');
                 }
             }
-            println!("|");
+            dump.push_str("|\n");
+        }
+        if shown.len() < buffer.len() {
+            let _ = writeln!(dump, "... {} more bytes", buffer.len() - shown.len());
         }
-        println!();
+        dump
+    }
+
+    /// Logs a hex dump of at most `max_bytes` of the buffer at trace level
+    ///
+    /// Returns before formatting anything unless trace events from this
+    /// module are enabled, so it can stay on the request path.
+    pub fn trace_hex_dump(label: &str, buffer: &[u8], max_bytes: usize) {
+        if !tracing::enabled!(tracing::Level::TRACE) {
+            return;
+        }
+        trace!(
+            label = label,
+            len = buffer.len(),
+            "Hex dump:\n{}",
+            Self::hex_dump(buffer, max_bytes)
+        );
     }
 
     /// Safely peeks at the next i16 without consuming it
-    pub fn peek_i16(buffer: &BytesMut) -> ProtocolResult<i16> {
-        if buffer.remaining() < 2 {
-            return Err(ProtocolError::insufficient_bytes(2, buffer.remaining()));
+    pub fn peek_i16(buffer: &[u8]) -> ProtocolResult<i16> {
+        if buffer.len() < 2 {
+            return Err(ProtocolError::insufficient_bytes(2, buffer.len()));
         }
         let bytes = &buffer[0..2];
         Ok(i16::from_be_bytes([bytes[0], bytes[1]]))
     }
 
     /// Safely peeks at the next i32 without consuming it
-    pub fn peek_i32(buffer: &BytesMut) -> ProtocolResult<i32> {
-        if buffer.remaining() < 4 {
-            return Err(ProtocolError::insufficient_bytes(4, buffer.remaining()));
+    pub fn peek_i32(buffer: &[u8]) -> ProtocolResult<i32> {
+        if buffer.len() < 4 {
+            return Err(ProtocolError::insufficient_bytes(4, buffer.len()));
         }
         let bytes = &buffer[0..4];
         Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
@@ -79,23 +197,25 @@ impl WireFormat {
     ///
     /// # Examples
     /// ```
-    /// use bytes::BytesMut;
-    /// use kafka_protocol::encoding::WireFormat;
+    /// use bytes::Bytes;
+    /// use codecrafters_kafka::protocol::WireFormat;
     ///
-    /// let mut buffer = BytesMut::new();
-    /// buffer.put_i16(-1); // Null string
+    /// let mut buffer = Bytes::from_static(&[0xff, 0xff]); // Null string
     /// let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
     /// assert_eq!(result, None);
+    ///
+    /// let mut buffer = Bytes::from_static(&[0x00, 0x02, b'h', b'i']);
+    /// let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
+    /// assert_eq!(result.as_deref(), Some("hi"));
     /// ```
-    pub fn decode_nullable_string(buffer: &mut BytesMut) -> ProtocolResult<Option<String>> {
+    pub fn decode_nullable_string(buffer: &mut Bytes) -> ProtocolResult<Option<String>> {
         if buffer.remaining() < 2 {
             return Err(ProtocolError::insufficient_bytes(2, buffer.remaining()));
         }
 
         let length = buffer.get_i16();
 
-        if length == -1 {
-            // Null string
+        if length == NULL_STRING_MARKER {
             return Ok(None);
         }
 
@@ -104,6 +224,7 @@ impl WireFormat {
         }
 
         let length = length as usize;
+        decode_limits::check_string_length(length)?;
 
         if buffer.remaining() < length {
             return Err(ProtocolError::insufficient_bytes(
@@ -112,23 +233,33 @@ impl WireFormat {
             ));
         }
 
-        let bytes = buffer.copy_to_bytes(length);
-        let string = String::from_utf8(bytes.to_vec())
-            .map_err(|e| ProtocolError::InvalidUtf8(e.to_string()))?;
+        let string = Self::take_string(buffer, length)?;
 
         Ok(Some(string))
     }
 
+    /// Takes the next `length` bytes as a String
+    ///
+    /// The bytes are validated where they lie in the frame, so the String's
+    /// own allocation is the only copy.
+    fn take_string(buffer: &mut Bytes, length: usize) -> ProtocolResult<String> {
+        let string = std::str::from_utf8(&buffer[..length])
+            .map_err(|e| ProtocolError::InvalidUtf8(e.to_string()))?
+            .to_owned();
+        buffer.advance(length);
+        Ok(string)
+    }
+
     /// Encodes a NULLABLE_STRING to the buffer
     ///
     /// # Examples
     /// ```
     /// use bytes::BytesMut;
-    /// use kafka_protocol::encoding::WireFormat;
+    /// use codecrafters_kafka::protocol::WireFormat;
     ///
     /// let mut buffer = BytesMut::new();
     /// WireFormat::encode_nullable_string(&mut buffer, Some("test")).unwrap();
-    /// // buffer now contains: [0x00, 0x04, 't', 'e', 's', 't']
+    /// assert_eq!(&buffer[..], b"\x00\x04test");
     /// ```
     pub fn encode_nullable_string(
         buffer: &mut BytesMut,
@@ -136,15 +267,14 @@ impl WireFormat {
     ) -> ProtocolResult<()> {
         match value {
             None => {
-                // Null string: length = -1
-                buffer.put_i16(-1);
+                buffer.put_i16(NULL_STRING_MARKER);
             }
             Some(s) => {
                 let bytes = s.as_bytes();
-                if bytes.len() > i16::MAX as usize {
+                if bytes.len() > MAX_STRING_LENGTH {
                     return Err(ProtocolError::string_too_long(
                         bytes.len(),
-                        i16::MAX as usize,
+                        MAX_STRING_LENGTH,
                     ));
                 }
                 buffer.put_i16(bytes.len() as i16);
@@ -159,7 +289,7 @@ impl WireFormat {
     /// STRING format:
     /// - Length N as INT16 (i16)
     /// - N bytes of UTF-8 encoded string
-    pub fn decode_string(buffer: &mut BytesMut) -> ProtocolResult<String> {
+    pub fn decode_string(buffer: &mut Bytes) -> ProtocolResult<String> {
         if buffer.remaining() < 2 {
             return Err(ProtocolError::insufficient_bytes(2, buffer.remaining()));
         }
@@ -171,6 +301,7 @@ impl WireFormat {
         }
 
         let length = length as usize;
+        decode_limits::check_string_length(length)?;
 
         if buffer.remaining() < length {
             return Err(ProtocolError::insufficient_bytes(
@@ -179,9 +310,7 @@ impl WireFormat {
             ));
         }
 
-        let bytes = buffer.copy_to_bytes(length);
-        let string = String::from_utf8(bytes.to_vec())
-            .map_err(|e| ProtocolError::InvalidUtf8(e.to_string()))?;
+        let string = Self::take_string(buffer, length)?;
 
         Ok(string)
     }
@@ -189,10 +318,10 @@ impl WireFormat {
     /// Encodes a regular STRING to the buffer
     pub fn encode_string(buffer: &mut BytesMut, value: &str) -> ProtocolResult<()> {
         let bytes = value.as_bytes();
-        if bytes.len() > i16::MAX as usize {
+        if bytes.len() > MAX_STRING_LENGTH {
             return Err(ProtocolError::string_too_long(
                 bytes.len(),
-                i16::MAX as usize,
+                MAX_STRING_LENGTH,
             ));
         }
         buffer.put_i16(bytes.len() as i16);
@@ -201,7 +330,7 @@ impl WireFormat {
     }
 
     /// Safely reads an i16 from the buffer with bounds checking
-    pub fn decode_i16(buffer: &mut BytesMut) -> ProtocolResult<i16> {
+    pub fn decode_i16(buffer: &mut Bytes) -> ProtocolResult<i16> {
         if buffer.remaining() < 2 {
             return Err(ProtocolError::insufficient_bytes(2, buffer.remaining()));
         }
@@ -209,7 +338,7 @@ impl WireFormat {
     }
 
     /// Safely reads an i32 from the buffer with bounds checking
-    pub fn decode_i32(buffer: &mut BytesMut) -> ProtocolResult<i32> {
+    pub fn decode_i32(buffer: &mut Bytes) -> ProtocolResult<i32> {
         if buffer.remaining() < 4 {
             return Err(ProtocolError::insufficient_bytes(4, buffer.remaining()));
         }
@@ -217,86 +346,1992 @@ impl WireFormat {
     }
 
     /// Safely reads a u8 from the buffer with bounds checking
-    pub fn decode_u8(buffer: &mut BytesMut) -> ProtocolResult<u8> {
+    pub fn decode_u8(buffer: &mut Bytes) -> ProtocolResult<u8> {
         if buffer.remaining() < 1 {
             return Err(ProtocolError::insufficient_bytes(1, buffer.remaining()));
         }
         Ok(buffer.get_u8())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Safely reads an i8 from the buffer with bounds checking
+    pub fn decode_i8(buffer: &mut Bytes) -> ProtocolResult<i8> {
+        if buffer.remaining() < 1 {
+            return Err(ProtocolError::insufficient_bytes(1, buffer.remaining()));
+        }
+        Ok(buffer.get_i8())
+    }
 
-    #[test]
-    fn test_decode_nullable_string_null() {
-        let mut buffer = BytesMut::new();
-        buffer.put_i16(-1);
+    /// Safely reads an i64 from the buffer with bounds checking
+    pub fn decode_i64(buffer: &mut Bytes) -> ProtocolResult<i64> {
+        if buffer.remaining() < 8 {
+            return Err(ProtocolError::insufficient_bytes(8, buffer.remaining()));
+        }
+        Ok(buffer.get_i64())
+    }
 
-        let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
-        assert_eq!(result, None);
+    /// Safely reads an f64 from the buffer with bounds checking
+    pub fn decode_f64(buffer: &mut Bytes) -> ProtocolResult<f64> {
+        if buffer.remaining() < 8 {
+            return Err(ProtocolError::insufficient_bytes(8, buffer.remaining()));
+        }
+        Ok(buffer.get_f64())
     }
 
-    #[test]
-    fn test_decode_nullable_string_empty() {
-        let mut buffer = BytesMut::new();
-        buffer.put_i16(0);
+    /// Safely reads a BOOLEAN from the buffer; any nonzero byte is true
+    pub fn decode_bool(buffer: &mut Bytes) -> ProtocolResult<bool> {
+        Self::decode_u8(buffer).map(|byte| byte != 0)
+    }
 
-        let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
-        assert_eq!(result, Some(String::new()));
+    /// Encodes an i8
+    pub fn encode_i8(buffer: &mut BytesMut, value: i8) {
+        buffer.put_i8(value);
     }
 
-    #[test]
-    fn test_decode_nullable_string_valid() {
-        let mut buffer = BytesMut::new();
-        let test_string = "kafka-client";
-        buffer.put_i16(test_string.len() as i16);
-        buffer.put_slice(test_string.as_bytes());
+    /// Encodes an i64, big-endian
+    pub fn encode_i64(buffer: &mut BytesMut, value: i64) {
+        buffer.put_i64(value);
+    }
 
-        let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
-        assert_eq!(result, Some(test_string.to_string()));
+    /// Encodes an f64 as its IEEE 754 bits, big-endian
+    pub fn encode_f64(buffer: &mut BytesMut, value: f64) {
+        buffer.put_f64(value);
     }
 
-    #[test]
-    fn test_encode_nullable_string_roundtrip() {
-        let mut buffer = BytesMut::new();
-        let test_string = "test-client";
+    /// Encodes a BOOLEAN; true is always written as 0x01
+    pub fn encode_bool(buffer: &mut BytesMut, value: bool) {
+        buffer.put_u8(value as u8);
+    }
 
-        WireFormat::encode_nullable_string(&mut buffer, Some(test_string)).unwrap();
-        let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
+    /// Encodes a UUID as its 16 bytes, most significant first
+    pub fn encode_uuid(buffer: &mut BytesMut, value: &Uuid) {
+        buffer.put_slice(value.as_bytes());
+    }
 
-        assert_eq!(result, Some(test_string.to_string()));
+    /// Decodes a UUID from the buffer
+    ///
+    /// Kafka uses the nil UUID for "no id", so it is returned like any other.
+    pub fn decode_uuid(buffer: &mut Bytes) -> ProtocolResult<Uuid> {
+        if buffer.remaining() < 16 {
+            return Err(ProtocolError::insufficient_bytes(16, buffer.remaining()));
+        }
+        let mut bytes = [0u8; 16];
+        buffer.copy_to_slice(&mut bytes);
+        Ok(Uuid::from_bytes(bytes))
     }
 
-    #[test]
-    fn test_safe_decode_insufficient_bytes() {
-        let mut buffer = BytesMut::new();
-        buffer.put_u8(0);
+    /// Decodes a BYTES from the buffer
+    ///
+    /// A null value (length -1) is rejected since the field does not allow
+    /// it. The result is a slice of the buffer's memory, not a copy.
+    pub fn decode_bytes(buffer: &mut Bytes) -> ProtocolResult<Bytes> {
+        Self::decode_nullable_bytes(buffer)?.ok_or_else(|| ProtocolError::invalid_length(-1))
+    }
 
-        let result = WireFormat::decode_i16(&mut buffer);
-        assert!(matches!(
-            result,
-            Err(ProtocolError::InsufficientBytes { .. })
-        ));
+    /// Encodes a BYTES to the buffer
+    pub fn encode_bytes(buffer: &mut BytesMut, value: &[u8]) -> ProtocolResult<()> {
+        Self::encode_nullable_bytes(buffer, Some(value))
     }
 
-    #[test]
-    fn test_peek_functions() {
-        let mut buffer = BytesMut::new();
-        buffer.put_i16(0x1234);
-        buffer.put_i32(0x56789ABC);
+    /// Decodes a NULLABLE_BYTES from the buffer
+    ///
+    /// NULLABLE_BYTES format:
+    /// - Length N as INT32
+    /// - If N == -1: null value (returns None)
+    /// - If N >= 0: N raw bytes
+    pub fn decode_nullable_bytes(buffer: &mut Bytes) -> ProtocolResult<Option<Bytes>> {
+        let length = Self::decode_i32(buffer)?;
+        if length == -1 {
+            return Ok(None);
+        }
+        if length < 0 {
+            return Err(ProtocolError::invalid_length(length));
+        }
+        let length = length as usize;
+        decode_limits::check_bytes_length(length)?;
+        if buffer.remaining() < length {
+            return Err(ProtocolError::insufficient_bytes(
+                length,
+                buffer.remaining(),
+            ));
+        }
+        Ok(Some(buffer.split_to(length)))
+    }
 
-        // Test peek doesn't consume bytes
-        assert_eq!(WireFormat::peek_i16(&buffer).unwrap(), 0x1234);
-        assert_eq!(buffer.len(), 6); // Should still have all 6 bytes
+    /// Encodes a NULLABLE_BYTES to the buffer
+    pub fn encode_nullable_bytes(
+        buffer: &mut BytesMut,
+        value: Option<&[u8]>,
+    ) -> ProtocolResult<()> {
+        let Some(bytes) = value else {
+            buffer.put_i32(-1);
+            return Ok(());
+        };
+        if bytes.len() > i32::MAX as usize {
+            return Err(ProtocolError::invalid_length(-1));
+        }
+        buffer.put_i32(bytes.len() as i32);
+        buffer.put_slice(bytes);
+        Ok(())
+    }
 
-        // Now actually consume and verify
-        assert_eq!(WireFormat::decode_i16(&mut buffer).unwrap(), 0x1234);
-        assert_eq!(buffer.len(), 4); // Should have 4 bytes left
+    /// Encodes an UNSIGNED_VARINT to the buffer
+    ///
+    /// The value is written 7 bits at a time, least significant group first,
+    /// with the high bit of each byte set when more bytes follow.
+    ///
+    /// # Examples
+    /// ```
+    /// use bytes::BytesMut;
+    /// use codecrafters_kafka::protocol::WireFormat;
+    ///
+    /// let mut buffer = BytesMut::new();
+    /// WireFormat::encode_unsigned_varint(&mut buffer, 300);
+    /// assert_eq!(&buffer[..], &[0xac, 0x02]);
+    ///
+    /// let mut buffer = buffer.freeze();
+    /// assert_eq!(WireFormat::decode_unsigned_varint(&mut buffer).unwrap(), 300);
+    /// ```
+    pub fn encode_unsigned_varint(buffer: &mut BytesMut, value: u32) {
+        Self::encode_unsigned_varlong(buffer, value as u64);
+    }
 
-        assert_eq!(WireFormat::peek_i32(&buffer).unwrap(), 0x56789ABC);
-        assert_eq!(WireFormat::decode_i32(&mut buffer).unwrap(), 0x56789ABC);
-        assert_eq!(buffer.len(), 0); // Should be empty
+    /// Decodes an UNSIGNED_VARINT from the buffer
+    ///
+    /// Fails with `InvalidFormat` for encodings longer than 5 bytes or
+    /// values that do not fit in 32 bits, and with `NonCanonicalVarint` for
+    /// non-minimal encodings unless [`DecodeLimits`] allows them.
+    pub fn decode_unsigned_varint(buffer: &mut Bytes) -> ProtocolResult<u32> {
+        Self::decode_unsigned_varint_bits(buffer, u32::BITS, DecodeLimits::current())
+            .map(|value| value as u32)
+    }
+
+    /// Encodes a 64-bit unsigned varint to the buffer
+    ///
+    /// Always the minimal form: no trailing zero groups.
+    pub fn encode_unsigned_varlong(buffer: &mut BytesMut, value: u64) {
+        let mut value = value;
+        while value >= 0x80 {
+            buffer.put_u8((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        buffer.put_u8(value as u8);
+    }
+
+    /// Decodes a 64-bit unsigned varint from the buffer
+    ///
+    /// Fails with `InvalidFormat` for encodings longer than 10 bytes, and
+    /// like [`decode_unsigned_varint`](Self::decode_unsigned_varint) for
+    /// non-minimal ones.
+    pub fn decode_unsigned_varlong(buffer: &mut Bytes) -> ProtocolResult<u64> {
+        Self::decode_unsigned_varint_bits(buffer, u64::BITS, DecodeLimits::current())
+    }
+
+    /// Decodes an unsigned varint holding at most `bits` bits
+    fn decode_unsigned_varint_bits(
+        buffer: &mut Bytes,
+        bits: u32,
+        limits: DecodeLimits,
+    ) -> ProtocolResult<u64> {
+        let max_bytes = bits.div_ceil(7);
+        let mut value: u64 = 0;
+        for i in 0..max_bytes {
+            let byte = Self::decode_u8(buffer)?;
+            let shift = i * 7;
+            let group = (byte & 0x7F) as u64;
+            if bits - shift < 7 && group >> (bits - shift) != 0 {
+                return Err(ProtocolError::InvalidFormat(format!(
+                    "unsigned varint does not fit in {} bits",
+                    bits
+                )));
+            }
+            value |= group << shift;
+            if byte & 0x80 == 0 {
+                // A minimal encoding never ends in an empty group
+                if i > 0 && byte == 0 {
+                    let length = i as usize + 1;
+                    let minimal = Self::unsigned_varlong_len(value);
+                    if limits.strict_varints {
+                        return Err(ProtocolError::NonCanonicalVarint { length, minimal });
+                    }
+                    decode_limits::record_non_canonical_varint();
+                    warn!(
+                        value = value,
+                        length = length,
+                        minimal = minimal,
+                        "Accepted non-minimal varint"
+                    );
+                }
+                return Ok(value);
+            }
+        }
+        Err(ProtocolError::InvalidFormat(format!(
+            "unsigned varint is longer than {} bytes",
+            max_bytes
+        )))
+    }
+
+    /// Length of the minimal unsigned varint encoding of `value`
+    fn unsigned_varlong_len(value: u64) -> usize {
+        (u64::BITS - value.leading_zeros()).div_ceil(7).max(1) as usize
+    }
+
+    /// Encoded size of an UNSIGNED_VARINT
+    pub fn unsigned_varint_size(value: u32) -> usize {
+        Self::unsigned_varlong_len(value as u64)
+    }
+
+    /// Encoded size of a VARINT
+    pub fn varint_size(value: i32) -> usize {
+        Self::unsigned_varlong_len(((value << 1) ^ (value >> 31)) as u32 as u64)
+    }
+
+    /// Encoded size of a VARLONG
+    pub fn varlong_size(value: i64) -> usize {
+        Self::unsigned_varlong_len(((value << 1) ^ (value >> 63)) as u64)
+    }
+
+    /// Encoded size of a STRING
+    pub fn string_size(value: &str) -> usize {
+        2 + value.len()
+    }
+
+    /// Encoded size of a NULLABLE_STRING; null is the length field alone
+    ///
+    /// # Examples
+    /// ```
+    /// use codecrafters_kafka::protocol::WireFormat;
+    ///
+    /// assert_eq!(WireFormat::nullable_string_size(Some("test")), 6);
+    /// assert_eq!(WireFormat::nullable_string_size(None), 2);
+    /// ```
+    pub fn nullable_string_size(value: Option<&str>) -> usize {
+        2 + value.map_or(0, str::len)
+    }
+
+    /// Encoded size of a COMPACT_STRING
+    pub fn compact_string_size(value: &str) -> usize {
+        Self::compact_nullable_bytes_size(Some(value.as_bytes()))
+    }
+
+    /// Encoded size of a COMPACT_NULLABLE_STRING
+    pub fn compact_nullable_string_size(value: Option<&str>) -> usize {
+        Self::compact_nullable_bytes_size(value.map(str::as_bytes))
+    }
+
+    /// Encoded size of a BYTES
+    pub fn bytes_size(value: &[u8]) -> usize {
+        4 + value.len()
+    }
+
+    /// Encoded size of a NULLABLE_BYTES; null is the length field alone
+    pub fn nullable_bytes_size(value: Option<&[u8]>) -> usize {
+        4 + value.map_or(0, <[u8]>::len)
+    }
+
+    /// Encoded size of a COMPACT_BYTES
+    pub fn compact_bytes_size(value: &[u8]) -> usize {
+        Self::compact_nullable_bytes_size(Some(value))
+    }
+
+    /// Encoded size of a COMPACT_NULLABLE_BYTES
+    pub fn compact_nullable_bytes_size(value: Option<&[u8]>) -> usize {
+        value.map_or(1, |bytes| {
+            Self::unsigned_varlong_len(bytes.len() as u64 + 1) + bytes.len()
+        })
+    }
+
+    /// Encodes a VARINT to the buffer
+    ///
+    /// The value is zigzag encoded, so small negative numbers such as -1
+    /// stay short, and then written as an unsigned varint.
+    pub fn encode_varint(buffer: &mut BytesMut, value: i32) {
+        Self::encode_unsigned_varint(buffer, ((value << 1) ^ (value >> 31)) as u32);
+    }
+
+    /// Decodes a VARINT from the buffer
+    pub fn decode_varint(buffer: &mut Bytes) -> ProtocolResult<i32> {
+        let zigzag = Self::decode_unsigned_varint(buffer)?;
+        Ok((zigzag >> 1) as i32 ^ -((zigzag & 1) as i32))
+    }
+
+    /// Encodes a VARLONG to the buffer
+    pub fn encode_varlong(buffer: &mut BytesMut, value: i64) {
+        Self::encode_unsigned_varlong(buffer, ((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    /// Decodes a VARLONG from the buffer
+    pub fn decode_varlong(buffer: &mut Bytes) -> ProtocolResult<i64> {
+        let zigzag = Self::decode_unsigned_varlong(buffer)?;
+        Ok((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64))
+    }
+
+    /// Encodes a non-nullable ARRAY to the buffer
+    ///
+    /// ARRAY format:
+    /// - Length N as INT32
+    /// - N elements, each written by `encode_item`
+    pub fn encode_array<T, F>(
+        buffer: &mut BytesMut,
+        items: &[T],
+        encode_item: F,
+    ) -> ProtocolResult<()>
+    where
+        F: FnMut(&mut BytesMut, &T) -> ProtocolResult<()>,
+    {
+        Self::encode_nullable_array(buffer, Some(items), encode_item)
+    }
+
+    /// Encodes a nullable ARRAY to the buffer
+    ///
+    /// A `None` value is written as length -1, which clients treat differently
+    /// from an empty array (length 0).
+    pub fn encode_nullable_array<T, F>(
+        buffer: &mut BytesMut,
+        items: Option<&[T]>,
+        mut encode_item: F,
+    ) -> ProtocolResult<()>
+    where
+        F: FnMut(&mut BytesMut, &T) -> ProtocolResult<()>,
+    {
+        match items {
+            None => buffer.put_i32(-1),
+            Some(items) => {
+                if items.len() > i32::MAX as usize {
+                    return Err(ProtocolError::SerializationError(format!(
+                        "array of {} elements exceeds INT32 length",
+                        items.len()
+                    )));
+                }
+                buffer.put_i32(items.len() as i32);
+                for item in items {
+                    encode_item(buffer, item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes a non-nullable ARRAY from the buffer
+    ///
+    /// A null array (length -1) is rejected since the field does not allow it.
+    pub fn decode_array<T, F>(buffer: &mut Bytes, decode_item: F) -> ProtocolResult<Vec<T>>
+    where
+        F: FnMut(&mut Bytes) -> ProtocolResult<T>,
+    {
+        Self::decode_nullable_array(buffer, decode_item)?
+            .ok_or_else(|| ProtocolError::invalid_length(-1))
+    }
+
+    /// Decodes a nullable ARRAY from the buffer
+    ///
+    /// Returns `None` for length -1 and `Some(vec![])` for length 0.
+    pub fn decode_nullable_array<T, F>(
+        buffer: &mut Bytes,
+        mut decode_item: F,
+    ) -> ProtocolResult<Option<Vec<T>>>
+    where
+        F: FnMut(&mut Bytes) -> ProtocolResult<T>,
+    {
+        let length = Self::decode_i32(buffer)?;
+
+        if length == -1 {
+            return Ok(None);
+        }
+
+        if length < 0 {
+            return Err(ProtocolError::invalid_length(length));
+        }
+
+        Self::check_element_count(buffer, length as usize)?;
+        let _depth = decode_limits::NestingGuard::enter()?;
+        let mut items = Vec::with_capacity(length as usize);
+        for _ in 0..length {
+            items.push(decode_item(buffer)?);
+        }
+
+        Ok(Some(items))
+    }
+
+    /// Encodes an ARRAY of INT32, such as a replica or ISR list
+    pub fn encode_i32_array(buffer: &mut BytesMut, items: &[i32]) -> ProtocolResult<()> {
+        Self::encode_array(buffer, items, |buffer, item| {
+            buffer.put_i32(*item);
+            Ok(())
+        })
+    }
+
+    /// Decodes an ARRAY of INT32
+    ///
+    /// A null array (length -1) decodes as empty when `nullable`, and is
+    /// an error otherwise.
+    pub fn decode_i32_array(buffer: &mut Bytes, nullable: bool) -> ProtocolResult<Vec<i32>> {
+        Self::decode_fixed_width_array(buffer, nullable, 4, Buf::get_i32)
+    }
+
+    /// Encodes an ARRAY of INT16
+    pub fn encode_i16_array(buffer: &mut BytesMut, items: &[i16]) -> ProtocolResult<()> {
+        Self::encode_array(buffer, items, |buffer, item| {
+            buffer.put_i16(*item);
+            Ok(())
+        })
+    }
+
+    /// Decodes an ARRAY of INT16, treating a null array as for
+    /// [`decode_i32_array`](Self::decode_i32_array)
+    pub fn decode_i16_array(buffer: &mut Bytes, nullable: bool) -> ProtocolResult<Vec<i16>> {
+        Self::decode_fixed_width_array(buffer, nullable, 2, Buf::get_i16)
+    }
+
+    /// Decodes an ARRAY whose elements are all `width` bytes
+    ///
+    /// The whole array is bounds checked before allocating, so a bogus
+    /// count fails fast instead of partway through.
+    fn decode_fixed_width_array<T>(
+        buffer: &mut Bytes,
+        nullable: bool,
+        width: usize,
+        get: fn(&mut Bytes) -> T,
+    ) -> ProtocolResult<Vec<T>> {
+        let length = Self::decode_i32(buffer)?;
+        if length == -1 && nullable {
+            return Ok(Vec::new());
+        }
+        if length < 0 {
+            return Err(ProtocolError::invalid_length(length));
+        }
+        decode_limits::check_array_elements(length as usize)?;
+        let needed = length as usize * width;
+        if buffer.remaining() < needed {
+            return Err(ProtocolError::insufficient_bytes(
+                needed,
+                buffer.remaining(),
+            ));
+        }
+        Ok((0..length).map(|_| get(buffer)).collect())
+    }
+
+    /// Encodes a non-nullable COMPACT_ARRAY to the buffer
+    ///
+    /// COMPACT_ARRAY format:
+    /// - Length N + 1 as UNSIGNED_VARINT
+    /// - N elements, each written by `encode_item`
+    pub fn encode_compact_array<T, F>(
+        buffer: &mut BytesMut,
+        items: &[T],
+        encode_item: F,
+    ) -> ProtocolResult<()>
+    where
+        F: FnMut(&mut BytesMut, &T) -> ProtocolResult<()>,
+    {
+        Self::encode_compact_nullable_array(buffer, Some(items), encode_item)
+    }
+
+    /// Encodes a nullable COMPACT_ARRAY to the buffer
+    ///
+    /// A `None` value is written as length 0, an empty array as length 1.
+    pub fn encode_compact_nullable_array<T, F>(
+        buffer: &mut BytesMut,
+        items: Option<&[T]>,
+        mut encode_item: F,
+    ) -> ProtocolResult<()>
+    where
+        F: FnMut(&mut BytesMut, &T) -> ProtocolResult<()>,
+    {
+        match items {
+            None => Self::encode_unsigned_varint(buffer, 0),
+            Some(items) => {
+                if items.len() >= u32::MAX as usize {
+                    return Err(ProtocolError::SerializationError(format!(
+                        "array of {} elements exceeds UNSIGNED_VARINT length",
+                        items.len()
+                    )));
+                }
+                Self::encode_unsigned_varint(buffer, items.len() as u32 + 1);
+                for item in items {
+                    encode_item(buffer, item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes a non-nullable COMPACT_ARRAY from the buffer
+    ///
+    /// A null array (length 0) is rejected since the field does not allow it.
+    pub fn decode_compact_array<T, F>(buffer: &mut Bytes, decode_item: F) -> ProtocolResult<Vec<T>>
+    where
+        F: FnMut(&mut Bytes) -> ProtocolResult<T>,
+    {
+        Self::decode_compact_nullable_array(buffer, decode_item)?
+            .ok_or_else(|| ProtocolError::invalid_length(-1))
+    }
+
+    /// Decodes a nullable COMPACT_ARRAY from the buffer
+    ///
+    /// Returns `None` for length 0 and `Some(vec![])` for length 1.
+    pub fn decode_compact_nullable_array<T, F>(
+        buffer: &mut Bytes,
+        mut decode_item: F,
+    ) -> ProtocolResult<Option<Vec<T>>>
+    where
+        F: FnMut(&mut Bytes) -> ProtocolResult<T>,
+    {
+        let length = Self::decode_unsigned_varint(buffer)?;
+
+        if length == 0 {
+            return Ok(None);
+        }
+
+        let count = length as usize - 1;
+        Self::check_element_count(buffer, count)?;
+        let _depth = decode_limits::NestingGuard::enter()?;
+        let mut items = Vec::with_capacity(count);
+        for _ in 0..count {
+            items.push(decode_item(buffer)?);
+        }
+
+        Ok(Some(items))
+    }
+
+    /// Rejects an array claiming more elements than the buffer can hold
+    ///
+    /// Every element takes at least one byte, so a count above the remaining
+    /// length cannot be valid. Checking up front keeps a hostile length from
+    /// driving a large allocation or a long loop before the buffer runs out.
+    /// Counts above the installed
+    /// [`max_array_elements`](DecodeLimits::max_array_elements) fail first.
+    pub(crate) fn check_element_count(buffer: &Bytes, count: usize) -> ProtocolResult<()> {
+        decode_limits::check_array_elements(count)?;
+        if count > buffer.remaining() {
+            return Err(ProtocolError::insufficient_bytes(count, buffer.remaining()));
+        }
+        Ok(())
+    }
+
+    /// Encodes a COMPACT_NULLABLE_STRING to the buffer
+    ///
+    /// COMPACT_NULLABLE_STRING format:
+    /// - Length N + 1 as UNSIGNED_VARINT, 0 for null
+    /// - N bytes of UTF-8 encoded string
+    pub fn encode_compact_nullable_string(
+        buffer: &mut BytesMut,
+        value: Option<&str>,
+    ) -> ProtocolResult<()> {
+        let Some(value) = value else {
+            Self::encode_unsigned_varint(buffer, 0);
+            return Ok(());
+        };
+        if value.len() >= u32::MAX as usize {
+            return Err(ProtocolError::string_too_long(
+                value.len(),
+                u32::MAX as usize - 1,
+            ));
+        }
+        Self::encode_unsigned_varint(buffer, value.len() as u32 + 1);
+        buffer.put_slice(value.as_bytes());
+        Ok(())
+    }
+
+    /// Encodes a COMPACT_STRING to the buffer
+    pub fn encode_compact_string(buffer: &mut BytesMut, value: &str) -> ProtocolResult<()> {
+        Self::encode_compact_nullable_string(buffer, Some(value))
+    }
+
+    /// Decodes a COMPACT_NULLABLE_STRING from the buffer
+    pub fn decode_compact_nullable_string(buffer: &mut Bytes) -> ProtocolResult<Option<String>> {
+        let length = Self::decode_unsigned_varint(buffer)?;
+        if length == 0 {
+            return Ok(None);
+        }
+        let length = length as usize - 1;
+        decode_limits::check_string_length(length)?;
+        if buffer.remaining() < length {
+            return Err(ProtocolError::insufficient_bytes(
+                length,
+                buffer.remaining(),
+            ));
+        }
+        let string = Self::take_string(buffer, length)?;
+        Ok(Some(string))
+    }
+
+    /// Decodes a COMPACT_STRING from the buffer
+    ///
+    /// A null string (length 0) is rejected since the field does not allow it.
+    pub fn decode_compact_string(buffer: &mut Bytes) -> ProtocolResult<String> {
+        Self::decode_compact_nullable_string(buffer)?
+            .ok_or_else(|| ProtocolError::invalid_length(-1))
+    }
+
+    /// Encodes a COMPACT_NULLABLE_BYTES to the buffer
+    ///
+    /// COMPACT_NULLABLE_BYTES format:
+    /// - Length N + 1 as UNSIGNED_VARINT, 0 for null
+    /// - N raw bytes
+    pub fn encode_compact_nullable_bytes(
+        buffer: &mut BytesMut,
+        value: Option<&[u8]>,
+    ) -> ProtocolResult<()> {
+        let Some(bytes) = value else {
+            Self::encode_unsigned_varint(buffer, 0);
+            return Ok(());
+        };
+        if bytes.len() >= u32::MAX as usize {
+            return Err(ProtocolError::SerializationError(format!(
+                "{} bytes exceed UNSIGNED_VARINT length",
+                bytes.len()
+            )));
+        }
+        Self::encode_unsigned_varint(buffer, bytes.len() as u32 + 1);
+        buffer.put_slice(bytes);
+        Ok(())
+    }
+
+    /// Encodes a COMPACT_BYTES to the buffer
+    pub fn encode_compact_bytes(buffer: &mut BytesMut, value: &[u8]) -> ProtocolResult<()> {
+        Self::encode_compact_nullable_bytes(buffer, Some(value))
+    }
+
+    /// Decodes a COMPACT_NULLABLE_BYTES from the buffer
+    ///
+    /// The claimed length is checked against the remaining bytes before
+    /// anything is split off. The result shares the buffer's memory.
+    pub fn decode_compact_nullable_bytes(buffer: &mut Bytes) -> ProtocolResult<Option<Bytes>> {
+        let length = Self::decode_unsigned_varint(buffer)?;
+        if length == 0 {
+            return Ok(None);
+        }
+        let length = length as usize - 1;
+        decode_limits::check_bytes_length(length)?;
+        if buffer.remaining() < length {
+            return Err(ProtocolError::insufficient_bytes(
+                length,
+                buffer.remaining(),
+            ));
+        }
+        Ok(Some(buffer.split_to(length)))
+    }
+
+    /// Decodes a COMPACT_BYTES from the buffer
+    ///
+    /// A null value (length 0) is rejected since the field does not allow it.
+    pub fn decode_compact_bytes(buffer: &mut Bytes) -> ProtocolResult<Bytes> {
+        Self::decode_compact_nullable_bytes(buffer)?
+            .ok_or_else(|| ProtocolError::invalid_length(-1))
+    }
+
+    /// Decodes a COMPACT_ARRAY when `flexible`, otherwise an ARRAY
+    ///
+    /// For messages whose encoding changes at their first flexible version.
+    pub fn decode_flexible_array<T, F>(
+        buffer: &mut Bytes,
+        flexible: bool,
+        decode_item: F,
+    ) -> ProtocolResult<Vec<T>>
+    where
+        F: FnMut(&mut Bytes) -> ProtocolResult<T>,
+    {
+        if flexible {
+            Self::decode_compact_array(buffer, decode_item)
+        } else {
+            Self::decode_array(buffer, decode_item)
+        }
+    }
+
+    /// Nullable counterpart of [`decode_flexible_array`](Self::decode_flexible_array)
+    pub fn decode_flexible_nullable_array<T, F>(
+        buffer: &mut Bytes,
+        flexible: bool,
+        decode_item: F,
+    ) -> ProtocolResult<Option<Vec<T>>>
+    where
+        F: FnMut(&mut Bytes) -> ProtocolResult<T>,
+    {
+        if flexible {
+            Self::decode_compact_nullable_array(buffer, decode_item)
+        } else {
+            Self::decode_nullable_array(buffer, decode_item)
+        }
+    }
+
+    /// Encodes a COMPACT_ARRAY when `flexible`, otherwise an ARRAY
+    pub fn encode_flexible_array<T, F>(
+        buffer: &mut BytesMut,
+        items: &[T],
+        flexible: bool,
+        encode_item: F,
+    ) -> ProtocolResult<()>
+    where
+        F: FnMut(&mut BytesMut, &T) -> ProtocolResult<()>,
+    {
+        Self::encode_flexible_nullable_array(buffer, Some(items), flexible, encode_item)
+    }
+
+    /// Nullable counterpart of [`encode_flexible_array`](Self::encode_flexible_array)
+    pub fn encode_flexible_nullable_array<T, F>(
+        buffer: &mut BytesMut,
+        items: Option<&[T]>,
+        flexible: bool,
+        encode_item: F,
+    ) -> ProtocolResult<()>
+    where
+        F: FnMut(&mut BytesMut, &T) -> ProtocolResult<()>,
+    {
+        if flexible {
+            Self::encode_compact_nullable_array(buffer, items, encode_item)
+        } else {
+            Self::encode_nullable_array(buffer, items, encode_item)
+        }
+    }
+
+    /// Encoded size of the length prefix of a possibly null array of
+    /// `count` elements
+    pub fn flexible_array_length_size(count: Option<usize>, flexible: bool) -> usize {
+        if flexible {
+            Self::unsigned_varint_size(count.map_or(0, |count| count as u32 + 1))
+        } else {
+            4
+        }
+    }
+
+    /// Decodes a COMPACT_STRING when `flexible`, otherwise a STRING
+    pub fn decode_flexible_string(buffer: &mut Bytes, flexible: bool) -> ProtocolResult<String> {
+        if flexible {
+            Self::decode_compact_string(buffer)
+        } else {
+            Self::decode_string(buffer)
+        }
+    }
+
+    /// Encodes a COMPACT_STRING when `flexible`, otherwise a STRING
+    pub fn encode_flexible_string(
+        buffer: &mut BytesMut,
+        value: &str,
+        flexible: bool,
+    ) -> ProtocolResult<()> {
+        if flexible {
+            Self::encode_compact_string(buffer, value)
+        } else {
+            Self::encode_string(buffer, value)
+        }
+    }
+
+    pub fn flexible_string_size(value: &str, flexible: bool) -> usize {
+        if flexible {
+            Self::compact_string_size(value)
+        } else {
+            Self::string_size(value)
+        }
+    }
+
+    /// Decodes a COMPACT_NULLABLE_STRING when `flexible`, otherwise a
+    /// NULLABLE_STRING
+    pub fn decode_flexible_nullable_string(
+        buffer: &mut Bytes,
+        flexible: bool,
+    ) -> ProtocolResult<Option<String>> {
+        if flexible {
+            Self::decode_compact_nullable_string(buffer)
+        } else {
+            Self::decode_nullable_string(buffer)
+        }
+    }
+
+    /// Encodes a COMPACT_NULLABLE_STRING when `flexible`, otherwise a
+    /// NULLABLE_STRING
+    pub fn encode_flexible_nullable_string(
+        buffer: &mut BytesMut,
+        value: Option<&str>,
+        flexible: bool,
+    ) -> ProtocolResult<()> {
+        if flexible {
+            Self::encode_compact_nullable_string(buffer, value)
+        } else {
+            Self::encode_nullable_string(buffer, value)
+        }
+    }
+
+    pub fn flexible_nullable_string_size(value: Option<&str>, flexible: bool) -> usize {
+        if flexible {
+            Self::compact_nullable_string_size(value)
+        } else {
+            Self::nullable_string_size(value)
+        }
+    }
+
+    /// Decodes a COMPACT_BYTES when `flexible`, otherwise a BYTES
+    pub fn decode_flexible_bytes(buffer: &mut Bytes, flexible: bool) -> ProtocolResult<Bytes> {
+        if flexible {
+            Self::decode_compact_bytes(buffer)
+        } else {
+            Self::decode_bytes(buffer)
+        }
+    }
+
+    /// Encodes a COMPACT_BYTES when `flexible`, otherwise a BYTES
+    pub fn encode_flexible_bytes(
+        buffer: &mut BytesMut,
+        value: &[u8],
+        flexible: bool,
+    ) -> ProtocolResult<()> {
+        if flexible {
+            Self::encode_compact_bytes(buffer, value)
+        } else {
+            Self::encode_bytes(buffer, value)
+        }
+    }
+
+    pub fn flexible_bytes_size(value: &[u8], flexible: bool) -> usize {
+        if flexible {
+            Self::compact_bytes_size(value)
+        } else {
+            Self::bytes_size(value)
+        }
+    }
+
+    /// Decodes a COMPACT_NULLABLE_BYTES when `flexible`, otherwise a
+    /// NULLABLE_BYTES
+    pub fn decode_flexible_nullable_bytes(
+        buffer: &mut Bytes,
+        flexible: bool,
+    ) -> ProtocolResult<Option<Bytes>> {
+        if flexible {
+            Self::decode_compact_nullable_bytes(buffer)
+        } else {
+            Self::decode_nullable_bytes(buffer)
+        }
+    }
+
+    /// Encodes a COMPACT_NULLABLE_BYTES when `flexible`, otherwise a
+    /// NULLABLE_BYTES
+    pub fn encode_flexible_nullable_bytes(
+        buffer: &mut BytesMut,
+        value: Option<&[u8]>,
+        flexible: bool,
+    ) -> ProtocolResult<()> {
+        if flexible {
+            Self::encode_compact_nullable_bytes(buffer, value)
+        } else {
+            Self::encode_nullable_bytes(buffer, value)
+        }
+    }
+
+    pub fn flexible_nullable_bytes_size(value: Option<&[u8]>, flexible: bool) -> usize {
+        if flexible {
+            Self::compact_nullable_bytes_size(value)
+        } else {
+            Self::nullable_bytes_size(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_nullable_string_null() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i16(-1);
+
+        let mut buffer = buffer.freeze();
+        let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_decode_nullable_string_empty() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i16(0);
+
+        let mut buffer = buffer.freeze();
+        let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
+        assert_eq!(result, Some(String::new()));
+    }
+
+    #[test]
+    fn test_decode_nullable_string_valid() {
+        let mut buffer = BytesMut::new();
+        let test_string = "kafka-client";
+        buffer.put_i16(test_string.len() as i16);
+        buffer.put_slice(test_string.as_bytes());
+
+        let mut buffer = buffer.freeze();
+        let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
+        assert_eq!(result, Some(test_string.to_string()));
+    }
+
+    #[test]
+    fn test_encode_nullable_string_roundtrip() {
+        let mut buffer = BytesMut::new();
+        let test_string = "test-client";
+
+        WireFormat::encode_nullable_string(&mut buffer, Some(test_string)).unwrap();
+        let mut buffer = buffer.freeze();
+        let result = WireFormat::decode_nullable_string(&mut buffer).unwrap();
+
+        assert_eq!(result, Some(test_string.to_string()));
+    }
+
+    #[test]
+    fn test_safe_decode_insufficient_bytes() {
+        let mut buffer = BytesMut::new();
+        buffer.put_u8(0);
+
+        let mut buffer = buffer.freeze();
+        let result = WireFormat::decode_i16(&mut buffer);
+        assert!(matches!(
+            result,
+            Err(ProtocolError::InsufficientBytes { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let large = vec![0xAB; 4096];
+        for value in [&b""[..], &b"key"[..], &large[..]] {
+            let mut buffer = BytesMut::new();
+            WireFormat::encode_bytes(&mut buffer, value).unwrap();
+            assert_eq!(buffer.len(), 4 + value.len());
+            let mut buffer = buffer.freeze();
+            assert_eq!(&WireFormat::decode_bytes(&mut buffer).unwrap()[..], value);
+            assert!(buffer.is_empty());
+        }
+
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_nullable_bytes(&mut buffer, None).unwrap();
+        assert_eq!(&buffer[..], &(-1i32).to_be_bytes());
+        assert_eq!(
+            WireFormat::decode_nullable_bytes(&mut buffer.clone().freeze()).unwrap(),
+            None
+        );
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_bytes(&mut buffer),
+            Err(ProtocolError::InvalidLength { length: -1 })
+        ));
+    }
+
+    #[test]
+    fn test_bytes_rejects_bad_lengths() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i32(-2);
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_nullable_bytes(&mut buffer),
+            Err(ProtocolError::InvalidLength { length: -2 })
+        ));
+
+        let mut buffer = BytesMut::new();
+        buffer.put_i32(10);
+        buffer.put_slice(b"short");
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_bytes(&mut buffer),
+            Err(ProtocolError::InsufficientBytes {
+                expected: 10,
+                actual: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn test_peek_functions() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i16(0x1234);
+        buffer.put_i32(0x56789ABC);
+
+        // Test peek doesn't consume bytes
+        assert_eq!(WireFormat::peek_i16(&buffer).unwrap(), 0x1234);
+        assert_eq!(buffer.len(), 6); // Should still have all 6 bytes
+
+        // Now actually consume and verify
+        let mut buffer = buffer.freeze();
+        assert_eq!(WireFormat::decode_i16(&mut buffer).unwrap(), 0x1234);
+        assert_eq!(buffer.len(), 4); // Should have 4 bytes left
+
+        assert_eq!(WireFormat::peek_i32(&buffer).unwrap(), 0x56789ABC);
+        assert_eq!(WireFormat::decode_i32(&mut buffer).unwrap(), 0x56789ABC);
+        assert_eq!(buffer.len(), 0); // Should be empty
+    }
+
+    #[test]
+    fn test_unsigned_varint_roundtrip_boundaries() {
+        for (value, size) in [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            (16383, 2),
+            (16384, 3),
+            (u32::MAX, 5),
+        ] {
+            let mut buffer = BytesMut::new();
+            WireFormat::encode_unsigned_varint(&mut buffer, value);
+            assert_eq!(buffer.len(), size, "size of {}", value);
+            let mut buffer = buffer.freeze();
+            assert_eq!(
+                WireFormat::decode_unsigned_varint(&mut buffer).unwrap(),
+                value
+            );
+            assert!(buffer.is_empty());
+        }
+
+        for value in [0, 127, 128, u32::MAX as u64 + 1, u64::MAX] {
+            let mut buffer = BytesMut::new();
+            WireFormat::encode_unsigned_varlong(&mut buffer, value);
+            let mut buffer = buffer.freeze();
+            assert_eq!(
+                WireFormat::decode_unsigned_varlong(&mut buffer).unwrap(),
+                value
+            );
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_unsigned_varint_truncated_input() {
+        let mut encoded = BytesMut::new();
+        WireFormat::encode_unsigned_varint(&mut encoded, u32::MAX);
+        for length in 0..encoded.len() {
+            let mut buffer = Bytes::copy_from_slice(&encoded[..length]);
+            assert!(matches!(
+                WireFormat::decode_unsigned_varint(&mut buffer),
+                Err(ProtocolError::InsufficientBytes { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_unsigned_varint_rejects_overlong_input() {
+        let mut buffer = Bytes::from_static(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]);
+        assert!(matches!(
+            WireFormat::decode_unsigned_varint(&mut buffer),
+            Err(ProtocolError::InvalidFormat(_))
+        ));
+
+        // Five bytes, but more than 32 bits of payload
+        let mut buffer = Bytes::from_static(&[0xFF, 0xFF, 0xFF, 0xFF, 0x1F]);
+        assert!(matches!(
+            WireFormat::decode_unsigned_varint(&mut buffer),
+            Err(ProtocolError::InvalidFormat(_))
+        ));
+
+        let mut buffer = Bytes::from_static(&[0x80; 11]);
+        assert!(matches!(
+            WireFormat::decode_unsigned_varlong(&mut buffer),
+            Err(ProtocolError::InvalidFormat(_))
+        ));
+    }
+
+    fn decode_with(bytes: &[u8], strict_varints: bool) -> ProtocolResult<(u64, usize)> {
+        let mut buffer = Bytes::copy_from_slice(bytes);
+        let value = WireFormat::decode_unsigned_varint_bits(
+            &mut buffer,
+            u64::BITS,
+            DecodeLimits {
+                strict_varints,
+                ..DecodeLimits::default()
+            },
+        )?;
+        Ok((value, bytes.len() - buffer.len()))
+    }
+
+    #[test]
+    fn test_length_claims_above_the_limits_fail_before_the_bounds_check() {
+        // A compact string claiming 2GB, followed by nothing
+        let mut buffer = Bytes::from_static(&[0x81, 0x80, 0x80, 0x80, 0x08]);
+        assert!(matches!(
+            WireFormat::decode_compact_string(&mut buffer),
+            Err(ProtocolError::LimitExceeded {
+                field: "string length",
+                requested: 0x8000_0000,
+                ..
+            })
+        ));
+
+        let mut buffer = Bytes::from_static(&[0x7F, 0xFF, 0xFF, 0xFF]);
+        assert!(matches!(
+            WireFormat::decode_nullable_bytes(&mut buffer),
+            Err(ProtocolError::LimitExceeded {
+                field: "bytes length",
+                ..
+            })
+        ));
+
+        let mut buffer = Bytes::from_static(&[0x7F, 0xFF, 0xFF, 0xFF]);
+        assert!(matches!(
+            WireFormat::decode_i32_array(&mut buffer, false),
+            Err(ProtocolError::LimitExceeded {
+                field: "array elements",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_nesting_depth_is_limited() {
+        fn nested(buffer: &mut Bytes) -> ProtocolResult<usize> {
+            let inner = WireFormat::decode_compact_array(buffer, nested)?;
+            Ok(inner.into_iter().max().map_or(1, |depth| depth + 1))
+        }
+        let limit = DecodeLimits::default().max_nesting_depth;
+
+        // Arrays of one array each, ending in an empty one
+        let mut frame = vec![0x02; limit - 1];
+        frame.push(0x01);
+        assert_eq!(nested(&mut Bytes::from(frame.clone())).unwrap(), limit);
+
+        frame.insert(0, 0x02);
+        assert!(matches!(
+            nested(&mut Bytes::from(frame)),
+            Err(ProtocolError::LimitExceeded {
+                field: "nesting depth",
+                ..
+            })
+        ));
+        // The guard unwinds, so the next decode starts from the top again
+        assert_eq!(nested(&mut Bytes::from_static(&[0x01])).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_non_minimal_varints_rejected() {
+        // Overlong zero
+        let mut buffer = Bytes::from_static(&[0x80, 0x00]);
+        assert!(matches!(
+            WireFormat::decode_unsigned_varint(&mut buffer),
+            Err(ProtocolError::NonCanonicalVarint {
+                length: 2,
+                minimal: 1
+            })
+        ));
+        // Overlong compact length: an empty string padded to five bytes
+        let mut buffer = Bytes::from_static(&[0x81, 0x80, 0x80, 0x80, 0x00]);
+        assert!(matches!(
+            WireFormat::decode_compact_string(&mut buffer),
+            Err(ProtocolError::NonCanonicalVarint {
+                length: 5,
+                minimal: 1
+            })
+        ));
+        // Overlong max: u64::MAX is ten bytes already, so the longest value
+        // that can still be padded is the nine-byte maximum
+        let mut buffer =
+            Bytes::from_static(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+        assert!(matches!(
+            WireFormat::decode_unsigned_varlong(&mut buffer),
+            Err(ProtocolError::NonCanonicalVarint {
+                length: 10,
+                minimal: 9
+            })
+        ));
+        // Zigzag varints go through the same check
+        let mut buffer = Bytes::from_static(&[0x81, 0x00]);
+        assert!(matches!(
+            WireFormat::decode_varint(&mut buffer),
+            Err(ProtocolError::NonCanonicalVarint { .. })
+        ));
+    }
+
+    #[test]
+    fn test_warn_only_accepts_non_minimal_varints() {
+        let before = decode_limits::non_canonical_varint_count();
+        assert_eq!(decode_with(&[0x80, 0x00], false).unwrap(), (0, 2));
+        assert_eq!(
+            decode_with(&[0xFF, 0x80, 0x80, 0x00], false).unwrap(),
+            (127, 4)
+        );
+        assert!(decode_limits::non_canonical_varint_count() >= before + 2);
+        assert!(decode_with(&[0x80, 0x00], true).is_err());
+    }
+
+    #[test]
+    fn test_varint_canonicalization_property() {
+        // Every one and two byte input, then pseudo-random longer ones
+        let mut inputs: Vec<Vec<u8>> = (0..=0xFFFFu32)
+            .flat_map(|n| [vec![n as u8], vec![(n >> 8) as u8, n as u8]])
+            .collect();
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        for _ in 0..20_000 {
+            let length = 3 + (seed % 8) as usize;
+            let bytes = (0..length)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    // Bias towards continuation bytes and empty groups
+                    match seed >> 62 {
+                        0 => 0x80,
+                        1 => 0x00,
+                        _ => (seed >> 33) as u8,
+                    }
+                })
+                .collect();
+            inputs.push(bytes);
+        }
+
+        for input in inputs {
+            let Ok((value, consumed)) = decode_with(&input, false) else {
+                continue;
+            };
+            let mut encoded = BytesMut::new();
+            WireFormat::encode_unsigned_varlong(&mut encoded, value);
+            let canonical = encoded[..] == input[..consumed];
+            assert_eq!(
+                canonical,
+                decode_with(&input, true).is_ok(),
+                "input {:02x?}",
+                &input[..consumed]
+            );
+        }
+    }
+
+    #[test]
+    fn test_varint_zigzag_roundtrip() {
+        for (value, encoded) in [
+            (0, &[0x00][..]),
+            (-1, &[0x01][..]),
+            (1, &[0x02][..]),
+            (i32::MAX, &[0xFE, 0xFF, 0xFF, 0xFF, 0x0F][..]),
+            (i32::MIN, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F][..]),
+        ] {
+            let mut buffer = BytesMut::new();
+            WireFormat::encode_varint(&mut buffer, value);
+            assert_eq!(&buffer[..], encoded, "encoding of {}", value);
+            assert_eq!(WireFormat::varint_size(value), encoded.len());
+            let mut buffer = buffer.freeze();
+            assert_eq!(WireFormat::decode_varint(&mut buffer).unwrap(), value);
+        }
+
+        for value in [0, -1, i64::MAX, i64::MIN, i32::MIN as i64 - 1] {
+            let mut buffer = BytesMut::new();
+            WireFormat::encode_varlong(&mut buffer, value);
+            assert_eq!(WireFormat::varlong_size(value), buffer.len());
+            let mut buffer = buffer.freeze();
+            assert_eq!(WireFormat::decode_varlong(&mut buffer).unwrap(), value);
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_varint_rejects_truncated_and_overlong_input() {
+        let mut buffer = Bytes::from_static(&[0xFF, 0xFF]);
+        assert!(matches!(
+            WireFormat::decode_varint(&mut buffer),
+            Err(ProtocolError::InsufficientBytes { .. })
+        ));
+
+        // A VARLONG does not fit in a VARINT
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_varlong(&mut buffer, i32::MIN as i64 - 1);
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_varint(&mut buffer),
+            Err(ProtocolError::InvalidFormat(_))
+        ));
+
+        let mut buffer = Bytes::from_static(&[0xFF; 11]);
+        assert!(matches!(
+            WireFormat::decode_varlong(&mut buffer),
+            Err(ProtocolError::InvalidFormat(_))
+        ));
+    }
+
+    fn encode_i32_item(buffer: &mut BytesMut, value: &i32) -> ProtocolResult<()> {
+        buffer.put_i32(*value);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nullable_array_null_vs_empty() {
+        let mut null_buffer = BytesMut::new();
+        WireFormat::encode_nullable_array::<i32, _>(&mut null_buffer, None, encode_i32_item)
+            .unwrap();
+
+        let mut empty_buffer = BytesMut::new();
+        WireFormat::encode_nullable_array(&mut empty_buffer, Some(&[][..]), encode_i32_item)
+            .unwrap();
+
+        assert_eq!(&null_buffer[..], &[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(&empty_buffer[..], &[0, 0, 0, 0]);
+
+        let mut null_buffer = null_buffer.freeze();
+        let null = WireFormat::decode_nullable_array(&mut null_buffer, WireFormat::decode_i32);
+        let mut empty_buffer = empty_buffer.freeze();
+        let empty = WireFormat::decode_nullable_array(&mut empty_buffer, WireFormat::decode_i32);
+        assert_eq!(null.unwrap(), None);
+        assert_eq!(empty.unwrap(), Some(vec![]));
+    }
+
+    #[test]
+    fn test_compact_nullable_array_null_vs_empty() {
+        let mut null_buffer = BytesMut::new();
+        WireFormat::encode_compact_nullable_array::<i32, _>(
+            &mut null_buffer,
+            None,
+            encode_i32_item,
+        )
+        .unwrap();
+
+        let mut empty_buffer = BytesMut::new();
+        WireFormat::encode_compact_nullable_array(
+            &mut empty_buffer,
+            Some(&[][..]),
+            encode_i32_item,
+        )
+        .unwrap();
+
+        assert_eq!(&null_buffer[..], &[0x00]);
+        assert_eq!(&empty_buffer[..], &[0x01]);
+
+        let mut null_buffer = null_buffer.freeze();
+        let null =
+            WireFormat::decode_compact_nullable_array(&mut null_buffer, WireFormat::decode_i32);
+        let mut empty_buffer = empty_buffer.freeze();
+        let empty =
+            WireFormat::decode_compact_nullable_array(&mut empty_buffer, WireFormat::decode_i32);
+        assert_eq!(null.unwrap(), None);
+        assert_eq!(empty.unwrap(), Some(vec![]));
+    }
+
+    #[test]
+    fn test_array_roundtrip() {
+        let values = vec![1, -2, 300];
+
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_array(&mut buffer, &values, encode_i32_item).unwrap();
+        let mut buffer = buffer.freeze();
+        let decoded = WireFormat::decode_array(&mut buffer, WireFormat::decode_i32).unwrap();
+        assert_eq!(decoded, values);
+
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_compact_array(&mut buffer, &values, encode_i32_item).unwrap();
+        let mut buffer = buffer.freeze();
+        let decoded =
+            WireFormat::decode_compact_array(&mut buffer, WireFormat::decode_i32).unwrap();
+        assert_eq!(decoded, values);
+    }
+
+    #[test]
+    fn test_array_counts_are_capped_by_remaining_bytes() {
+        // Counts under the element limit, which would reject them first
+        let mut buffer = BytesMut::new();
+        buffer.put_i32(50_000);
+        buffer.put_i32(1);
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_array(&mut buffer, WireFormat::decode_i32),
+            Err(ProtocolError::InsufficientBytes { .. })
+        ));
+
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_unsigned_varint(&mut buffer, 50_001);
+        buffer.put_u8(0);
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_compact_array(&mut buffer, |_| Ok(())),
+            Err(ProtocolError::InsufficientBytes { .. })
+        ));
+    }
+
+    #[test]
+    fn test_non_nullable_array_rejects_null() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i32(-1);
+        let mut buffer = buffer.freeze();
+        let result = WireFormat::decode_array(&mut buffer, WireFormat::decode_i32);
+        assert!(matches!(result, Err(ProtocolError::InvalidLength { .. })));
+
+        let mut buffer = BytesMut::new();
+        buffer.put_u8(0);
+        let mut buffer = buffer.freeze();
+        let result = WireFormat::decode_compact_array(&mut buffer, WireFormat::decode_i32);
+        assert!(matches!(result, Err(ProtocolError::InvalidLength { .. })));
+    }
+
+    #[test]
+    fn test_compact_string_null_vs_empty() {
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_compact_nullable_string(&mut buffer, None).unwrap();
+        WireFormat::encode_compact_string(&mut buffer, "").unwrap();
+        WireFormat::encode_compact_string(&mut buffer, "kafka").unwrap();
+        assert_eq!(&buffer[..2], &[0x00, 0x01]);
+
+        let mut buffer = buffer.freeze();
+        assert_eq!(
+            WireFormat::decode_compact_nullable_string(&mut buffer).unwrap(),
+            None
+        );
+        assert_eq!(WireFormat::decode_compact_string(&mut buffer).unwrap(), "");
+        assert_eq!(
+            WireFormat::decode_compact_string(&mut buffer).unwrap(),
+            "kafka"
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_compact_bytes_roundtrip() {
+        // Crosses a 4KB read boundary and needs a two-byte length
+        let large = vec![0xCD; 4096 + 17];
+        for value in [&b""[..], &b"key"[..], &large[..]] {
+            let mut buffer = BytesMut::new();
+            WireFormat::encode_compact_bytes(&mut buffer, value).unwrap();
+            let length_bytes = if value.len() < 127 { 1 } else { 2 };
+            assert_eq!(buffer.len(), length_bytes + value.len());
+            let mut buffer = buffer.freeze();
+            assert_eq!(
+                &WireFormat::decode_compact_bytes(&mut buffer).unwrap()[..],
+                value
+            );
+            assert!(buffer.is_empty());
+        }
+
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_compact_nullable_bytes(&mut buffer, None).unwrap();
+        WireFormat::encode_compact_bytes(&mut buffer, b"").unwrap();
+        assert_eq!(&buffer[..], &[0x00, 0x01]);
+        assert_eq!(
+            WireFormat::decode_compact_nullable_bytes(&mut buffer.clone().freeze()).unwrap(),
+            None
+        );
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_compact_bytes(&mut buffer),
+            Err(ProtocolError::InvalidLength { length: -1 })
+        ));
+        assert_eq!(
+            WireFormat::decode_compact_nullable_bytes(&mut buffer).unwrap(),
+            Some(Bytes::new())
+        );
+    }
+
+    #[test]
+    fn test_compact_bytes_length_checked_against_remaining() {
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_unsigned_varint(&mut buffer, 4096 + 1);
+        buffer.put_slice(&[0u8; 100]);
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_compact_bytes(&mut buffer),
+            Err(ProtocolError::InsufficientBytes {
+                expected: 4096,
+                actual: 100
+            })
+        ));
+    }
+
+    #[test]
+    fn test_uuid_roundtrip() {
+        let id = Uuid::from_u128(0x0123_4567_89ab_cdef_0011_2233_4455_6677);
+        let mut buffer = id.encode().unwrap();
+        WireFormat::encode_uuid(&mut buffer, &Uuid::nil());
+        assert_eq!(buffer.len(), 32);
+        assert_eq!(buffer[0], 0x01);
+
+        let mut buffer = buffer.freeze();
+        assert_eq!(Uuid::decode(&mut buffer).unwrap(), id);
+        assert_eq!(WireFormat::decode_uuid(&mut buffer).unwrap(), Uuid::nil());
+        assert!(buffer.is_empty());
+        assert_eq!(id.to_string(), "01234567-89ab-cdef-0011-223344556677");
+    }
+
+    #[test]
+    fn test_uuid_needs_sixteen_bytes() {
+        let mut buffer = Bytes::from_static(&[0u8; 15]);
+        assert!(matches!(
+            WireFormat::decode_uuid(&mut buffer),
+            Err(ProtocolError::InsufficientBytes {
+                expected: 16,
+                actual: 15
+            })
+        ));
+    }
+
+    #[test]
+    fn test_fixed_width_roundtrip() {
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_i8(&mut buffer, -2);
+        WireFormat::encode_i64(&mut buffer, i64::MIN + 1);
+        WireFormat::encode_f64(&mut buffer, -1.5);
+        WireFormat::encode_bool(&mut buffer, true);
+        WireFormat::encode_bool(&mut buffer, false);
+        assert_eq!(buffer.len(), 1 + 8 + 8 + 2);
+        assert_eq!(buffer[0], 0xfe);
+        assert_eq!(&buffer[9..17], &(-1.5f64).to_bits().to_be_bytes());
+        assert_eq!(&buffer[17..], &[0x01, 0x00]);
+
+        let mut buffer = buffer.freeze();
+        assert_eq!(WireFormat::decode_i8(&mut buffer).unwrap(), -2);
+        assert_eq!(WireFormat::decode_i64(&mut buffer).unwrap(), i64::MIN + 1);
+        assert_eq!(WireFormat::decode_f64(&mut buffer).unwrap(), -1.5);
+        assert!(WireFormat::decode_bool(&mut buffer).unwrap());
+        assert!(!WireFormat::decode_bool(&mut buffer).unwrap());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_decode_bool_treats_nonzero_as_true() {
+        let mut buffer = Bytes::from_static(&[0x02, 0xff]);
+        assert!(WireFormat::decode_bool(&mut buffer).unwrap());
+        assert!(WireFormat::decode_bool(&mut buffer).unwrap());
+    }
+
+    #[test]
+    fn test_fixed_width_insufficient_bytes() {
+        let insufficient = |result: ProtocolResult<()>, expected: usize, actual: usize| {
+            assert!(
+                matches!(
+                    result,
+                    Err(ProtocolError::InsufficientBytes { expected: e, actual: a })
+                        if e == expected && a == actual
+                ),
+                "expected {} of {} bytes",
+                expected,
+                actual
+            );
+        };
+        insufficient(WireFormat::decode_i8(&mut Bytes::new()).map(drop), 1, 0);
+        insufficient(
+            WireFormat::decode_i64(&mut Bytes::from_static(&[0u8; 7])).map(drop),
+            8,
+            7,
+        );
+        insufficient(
+            WireFormat::decode_f64(&mut Bytes::from_static(&[0u8; 3])).map(drop),
+            8,
+            3,
+        );
+        insufficient(WireFormat::decode_bool(&mut Bytes::new()).map(drop), 1, 0);
+    }
+
+    #[test]
+    fn test_integer_arrays_roundtrip() {
+        let replicas: Vec<i32> = (0..10_000).map(|id| id * 3 - 7).collect();
+        let mut buffer = BytesMut::new();
+        WireFormat::encode_i32_array(&mut buffer, &replicas).unwrap();
+        WireFormat::encode_i16_array(&mut buffer, &[]).unwrap();
+        WireFormat::encode_i16_array(&mut buffer, &[1, -1, i16::MAX]).unwrap();
+        assert_eq!(buffer.len(), 4 + 10_000 * 4 + 4 + 4 + 3 * 2);
+
+        let mut buffer = buffer.freeze();
+        assert_eq!(
+            WireFormat::decode_i32_array(&mut buffer, false).unwrap(),
+            replicas
+        );
+        assert!(WireFormat::decode_i16_array(&mut buffer, false)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            WireFormat::decode_i16_array(&mut buffer, false).unwrap(),
+            vec![1, -1, i16::MAX]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_integer_arrays_null() {
+        let null = || Bytes::copy_from_slice(&(-1i32).to_be_bytes());
+        assert!(WireFormat::decode_i32_array(&mut null(), true)
+            .unwrap()
+            .is_empty());
+        assert!(WireFormat::decode_i16_array(&mut null(), true)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            WireFormat::decode_i32_array(&mut null(), false),
+            Err(ProtocolError::InvalidLength { length: -1 })
+        ));
+        assert!(matches!(
+            WireFormat::decode_i16_array(&mut null(), false),
+            Err(ProtocolError::InvalidLength { length: -1 })
+        ));
+    }
+
+    #[test]
+    fn test_integer_arrays_bogus_count_fails_fast() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i32(10_000);
+        buffer.put_i32(5);
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_i32_array(&mut buffer, false),
+            Err(ProtocolError::InsufficientBytes {
+                expected: 40_000,
+                actual: 4
+            })
+        ));
+
+        let mut buffer = BytesMut::new();
+        buffer.put_i32(3);
+        buffer.put_i16(1);
+        buffer.put_i16(2);
+        let mut buffer = buffer.freeze();
+        assert!(matches!(
+            WireFormat::decode_i16_array(&mut buffer, true),
+            Err(ProtocolError::InsufficientBytes {
+                expected: 6,
+                actual: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_hex_dump_layout() {
+        let dump = WireFormat::hex_dump(b"\x00\x12kafka-client-id!\xff", 1024);
+        assert_eq!(
+            dump,
+            "0000: 00 12 6B 61 66 6B 61 2D  63 6C 69 65 6E 74 2D 69  |..kafka-client-i|\n\
+             0010: 64 21 FF                                          |d!.|\n"
+        );
+    }
+
+    #[test]
+    fn test_hex_dump_is_capped_at_max_bytes() {
+        let buffer: Vec<u8> = (0x20..0x40).collect();
+        assert_eq!(
+            WireFormat::hex_dump(&buffer, 20),
+            "0000: 20 21 22 23 24 25 26 27  28 29 2A 2B 2C 2D 2E 2F  | !\"#$%&'()*+,-./|\n\
+             0010: 30 31 32 33                                       |0123|\n\
+             ... 12 more bytes\n"
+        );
+        assert_eq!(WireFormat::hex_dump(&buffer, 0), "... 32 more bytes\n");
+        assert_eq!(WireFormat::hex_dump(&[], 16), "");
+    }
+
+    /// Generated roundtrip and panic-freedom properties for every codec
+    mod properties {
+        use super::*;
+        use crate::protocol::headers::{RequestHeaderV2, ResponseHeaderV0};
+        use crate::protocol::tagged_fields::TaggedFields;
+        use proptest::collection::{btree_map, vec};
+        use proptest::prelude::*;
+
+        /// Arbitrary UTF-8, mostly short, including non-ASCII characters
+        fn any_string() -> impl Strategy<Value = String> {
+            prop_oneof![
+                Just(String::new()),
+                "\\PC{0,32}",
+                vec(any::<char>(), 0..64).prop_map(String::from_iter),
+            ]
+        }
+
+        /// Strings of exactly MAX_STRING_LENGTH bytes, with an arbitrary prefix
+        fn max_length_string() -> impl Strategy<Value = String> {
+            "\\PC{0,16}".prop_map(|prefix: String| {
+                let padding = MAX_STRING_LENGTH - prefix.len();
+                prefix + &"x".repeat(padding)
+            })
+        }
+
+        fn any_bytes() -> impl Strategy<Value = Vec<u8>> {
+            vec(any::<u8>(), 0..256)
+        }
+
+        fn tagged_fields() -> impl Strategy<Value = TaggedFields> {
+            btree_map(any::<u32>(), any_bytes(), 0..4).prop_map(|fields| {
+                let mut tagged_fields = TaggedFields::new();
+                for (tag, value) in fields {
+                    tagged_fields.insert(tag, value);
+                }
+                tagged_fields
+            })
+        }
+
+        fn request_header() -> impl Strategy<Value = RequestHeaderV2> {
+            (
+                any::<i16>(),
+                any::<i16>(),
+                any::<i32>(),
+                // Decoding escapes control characters, so ids are drawn
+                // without them
+                proptest::option::of("\\PC{0,64}"),
+                tagged_fields(),
+            )
+                .prop_map(|(api_key, api_version, correlation_id, client_id, tags)| {
+                    let mut header =
+                        RequestHeaderV2::new(api_key, api_version, correlation_id, client_id);
+                    header.tagged_fields = tags;
+                    header
+                })
+        }
+
+        /// Adapts an encoder that cannot fail for [`roundtrip`]
+        fn infallible<T: Copy>(
+            encode: impl Fn(&mut BytesMut, T),
+        ) -> impl FnOnce(&mut BytesMut, &T) -> ProtocolResult<()> {
+            move |buffer, value| {
+                encode(buffer, *value);
+                Ok(())
+            }
+        }
+
+        /// Encodes with `encode`, then checks `decode` gives `value` back
+        /// and consumes every byte
+        fn roundtrip<T, E, D>(value: &T, encode: E, decode: D) -> Result<(), TestCaseError>
+        where
+            T: PartialEq + std::fmt::Debug,
+            E: FnOnce(&mut BytesMut, &T) -> ProtocolResult<()>,
+            D: FnOnce(&mut Bytes) -> ProtocolResult<T>,
+        {
+            let mut buffer = BytesMut::new();
+            encode(&mut buffer, value).map_err(|e| TestCaseError::fail(e.to_string()))?;
+            let mut buffer = buffer.freeze();
+            let decoded = decode(&mut buffer).map_err(|e| TestCaseError::fail(e.to_string()))?;
+            prop_assert_eq!(&decoded, value);
+            prop_assert!(buffer.is_empty(), "{} bytes left over", buffer.len());
+            Ok(())
+        }
+
+        proptest! {
+            #[test]
+            fn test_strings_roundtrip(value in any_string()) {
+                roundtrip(
+                    &value,
+                    |b, v| WireFormat::encode_string(b, v),
+                    WireFormat::decode_string,
+                )?;
+                roundtrip(
+                    &value,
+                    |b, v| WireFormat::encode_compact_string(b, v),
+                    WireFormat::decode_compact_string,
+                )?;
+            }
+
+            #[test]
+            fn test_nullable_strings_roundtrip(value in proptest::option::of(any_string())) {
+                roundtrip(
+                    &value,
+                    |b, v| WireFormat::encode_nullable_string(b, v.as_deref()),
+                    WireFormat::decode_nullable_string,
+                )?;
+                roundtrip(
+                    &value,
+                    |b, v| WireFormat::encode_compact_nullable_string(b, v.as_deref()),
+                    WireFormat::decode_compact_nullable_string,
+                )?;
+            }
+
+            #[test]
+            fn test_max_length_strings_roundtrip(value in max_length_string()) {
+                roundtrip(
+                    &value,
+                    |b, v| WireFormat::encode_string(b, v),
+                    WireFormat::decode_string,
+                )?;
+                let mut buffer = BytesMut::new();
+                let too_long = value + "x";
+                prop_assert!(WireFormat::encode_string(&mut buffer, &too_long).is_err());
+            }
+
+            #[test]
+            fn test_bytes_roundtrip(value in proptest::option::of(any_bytes())) {
+                let value = value.map(Bytes::from);
+                roundtrip(
+                    &value,
+                    |b, v| WireFormat::encode_nullable_bytes(b, v.as_deref()),
+                    WireFormat::decode_nullable_bytes,
+                )?;
+                roundtrip(
+                    &value,
+                    |b, v| WireFormat::encode_compact_nullable_bytes(b, v.as_deref()),
+                    WireFormat::decode_compact_nullable_bytes,
+                )?;
+                if let Some(value) = value {
+                    roundtrip(
+                    &value,
+                    |b, v| WireFormat::encode_bytes(b, v),
+                    WireFormat::decode_bytes,
+                )?;
+                    roundtrip(
+                        &value,
+                        |b, v| WireFormat::encode_compact_bytes(b, v),
+                        WireFormat::decode_compact_bytes,
+                    )?;
+                }
+            }
+
+            #[test]
+            fn test_fixed_width_integers_roundtrip(
+                (a, b, c, d) in (any::<i8>(), any::<i16>(), any::<i32>(), any::<i64>()),
+                flag in any::<bool>(),
+                float in any::<f64>(),
+                uuid in any::<u128>(),
+            ) {
+                roundtrip(&a, infallible(WireFormat::encode_i8), WireFormat::decode_i8)?;
+                roundtrip(&b, infallible(|buf, v| buf.put_i16(v)), WireFormat::decode_i16)?;
+                roundtrip(&c, infallible(|buf, v| buf.put_i32(v)), WireFormat::decode_i32)?;
+                roundtrip(&d, infallible(WireFormat::encode_i64), WireFormat::decode_i64)?;
+                roundtrip(&flag, infallible(WireFormat::encode_bool), WireFormat::decode_bool)?;
+                roundtrip(
+                    &Uuid::from_u128(uuid),
+                    infallible(|buf, v: Uuid| WireFormat::encode_uuid(buf, &v)),
+                    WireFormat::decode_uuid,
+                )?;
+                // Compared by bits so NaN payloads count as equal
+                roundtrip(
+                    &float.to_bits(),
+                    infallible(|buf, v| WireFormat::encode_f64(buf, f64::from_bits(v))),
+                    |buf| WireFormat::decode_f64(buf).map(f64::to_bits),
+                )?;
+            }
+
+            #[test]
+            fn test_varints_roundtrip(
+                (unsigned, signed) in (any::<u32>(), any::<i32>()),
+                (unsigned_long, signed_long) in (any::<u64>(), any::<i64>()),
+            ) {
+                let encoded_len = |encode: &dyn Fn(&mut BytesMut)| {
+                    let mut buffer = BytesMut::new();
+                    encode(&mut buffer);
+                    buffer.len()
+                };
+                roundtrip(
+                    &unsigned,
+                    |b, v| { WireFormat::encode_unsigned_varint(b, *v); Ok(()) },
+                    WireFormat::decode_unsigned_varint,
+                )?;
+                prop_assert_eq!(
+                    encoded_len(&|b| WireFormat::encode_unsigned_varint(b, unsigned)),
+                    WireFormat::unsigned_varint_size(unsigned)
+                );
+                roundtrip(
+                    &signed,
+                    |b, v| { WireFormat::encode_varint(b, *v); Ok(()) },
+                    WireFormat::decode_varint,
+                )?;
+                roundtrip(
+                    &unsigned_long,
+                    |b, v| { WireFormat::encode_unsigned_varlong(b, *v); Ok(()) },
+                    WireFormat::decode_unsigned_varlong,
+                )?;
+                roundtrip(
+                    &signed_long,
+                    |b, v| { WireFormat::encode_varlong(b, *v); Ok(()) },
+                    WireFormat::decode_varlong,
+                )?;
+            }
+
+            #[test]
+            fn test_arrays_roundtrip(
+                items in proptest::option::of(vec(any::<i32>(), 0..32)),
+                names in vec(any_string(), 0..8),
+            ) {
+                roundtrip(
+                    &items,
+                    |b, v| WireFormat::encode_nullable_array(b, v.as_deref(), |b, item| {
+                        b.put_i32(*item);
+                        Ok(())
+                    }),
+                    |b| WireFormat::decode_nullable_array(b, WireFormat::decode_i32),
+                )?;
+                roundtrip(
+                    &items,
+                    |b, v| WireFormat::encode_compact_nullable_array(b, v.as_deref(), |b, item| {
+                        b.put_i32(*item);
+                        Ok(())
+                    }),
+                    |b| WireFormat::decode_compact_nullable_array(b, WireFormat::decode_i32),
+                )?;
+                if let Some(items) = &items {
+                    roundtrip(
+                        items,
+                        |b, v| WireFormat::encode_i32_array(b, v),
+                        |b| WireFormat::decode_i32_array(b, false),
+                    )?;
+                }
+                roundtrip(
+                    &names,
+                    |b, v| WireFormat::encode_array(b, v, |b, name| {
+                        WireFormat::encode_string(b, name)
+                    }),
+                    |b| WireFormat::decode_array(b, WireFormat::decode_string),
+                )?;
+                roundtrip(
+                    &names,
+                    |b, v| WireFormat::encode_compact_array(b, v, |b, name| {
+                        WireFormat::encode_compact_string(b, name)
+                    }),
+                    |b| WireFormat::decode_compact_array(b, WireFormat::decode_compact_string),
+                )?;
+            }
+
+            #[test]
+            fn test_tagged_fields_roundtrip(fields in tagged_fields()) {
+                roundtrip(&fields, |b, v| v.encode_to(b), TaggedFields::decode)?;
+                prop_assert_eq!(fields.encode().unwrap().len(), fields.encoded_size());
+            }
+
+            #[test]
+            fn test_request_header_roundtrips_at_every_version(header in request_header()) {
+                roundtrip(&header, |b, v| v.encode_to(b), RequestHeaderV2::decode)?;
+                // Lower versions drop the fields they do not carry
+                let mut v1 = header.clone();
+                v1.tagged_fields = TaggedFields::new();
+                roundtrip(
+                    &v1,
+                    |b, v| v.encode_versioned(1, b),
+                    |b| RequestHeaderV2::decode_versioned(b, 1),
+                )?;
+                let mut v0 = v1;
+                v0.client_id = None;
+                roundtrip(
+                    &v0,
+                    |b, v| v.encode_versioned(0, b),
+                    |b| RequestHeaderV2::decode_versioned(b, 0),
+                )?;
+            }
+
+            #[test]
+            fn test_response_header_roundtrips(correlation_id in any::<i32>()) {
+                roundtrip(
+                    &ResponseHeaderV0::new(correlation_id),
+                    |b, v| v.encode_to(b),
+                    ResponseHeaderV0::decode,
+                )?;
+            }
+
+            /// Every decoder returns `Ok` or `Err` on arbitrary input; a
+            /// missing bounds check shows up as a panic
+            #[test]
+            fn test_decoders_never_panic(input in vec(any::<u8>(), 0..64)) {
+                let input = Bytes::from(input);
+                let run = |decode: &dyn Fn(&mut Bytes)| decode(&mut input.clone());
+
+                run(&|b| drop(WireFormat::decode_string(b)));
+                run(&|b| drop(WireFormat::decode_nullable_string(b)));
+                run(&|b| drop(WireFormat::decode_compact_string(b)));
+                run(&|b| drop(WireFormat::decode_compact_nullable_string(b)));
+                run(&|b| drop(WireFormat::decode_bytes(b)));
+                run(&|b| drop(WireFormat::decode_nullable_bytes(b)));
+                run(&|b| drop(WireFormat::decode_compact_bytes(b)));
+                run(&|b| drop(WireFormat::decode_compact_nullable_bytes(b)));
+                run(&|b| drop(WireFormat::decode_i8(b)));
+                run(&|b| drop(WireFormat::decode_u8(b)));
+                run(&|b| drop(WireFormat::decode_i16(b)));
+                run(&|b| drop(WireFormat::decode_i32(b)));
+                run(&|b| drop(WireFormat::decode_i64(b)));
+                run(&|b| drop(WireFormat::decode_f64(b)));
+                run(&|b| drop(WireFormat::decode_bool(b)));
+                run(&|b| drop(WireFormat::decode_uuid(b)));
+                run(&|b| drop(WireFormat::decode_unsigned_varint(b)));
+                run(&|b| drop(WireFormat::decode_unsigned_varlong(b)));
+                run(&|b| drop(WireFormat::decode_varint(b)));
+                run(&|b| drop(WireFormat::decode_varlong(b)));
+                run(&|b| drop(WireFormat::decode_array(b, WireFormat::decode_string)));
+                run(&|b| drop(WireFormat::decode_nullable_array(b, WireFormat::decode_i64)));
+                run(&|b| {
+                    drop(WireFormat::decode_compact_array(b, WireFormat::decode_compact_bytes))
+                });
+                run(&|b| {
+                    drop(WireFormat::decode_compact_nullable_array(b, WireFormat::decode_uuid))
+                });
+                run(&|b| drop(WireFormat::decode_i32_array(b, true)));
+                run(&|b| drop(WireFormat::decode_i16_array(b, false)));
+                run(&|b| drop(TaggedFields::decode(b)));
+                run(&|b| drop(RequestHeaderV2::decode(b)));
+                run(&|b| drop(RequestHeaderV2::decode_lenient(b)));
+                run(&|b| drop(RequestHeaderV2::decode_versioned(b, 0)));
+                run(&|b| drop(RequestHeaderV2::decode_versioned(b, 1)));
+                run(&|b| drop(ResponseHeaderV0::decode(b)));
+            }
+        }
     }
 }
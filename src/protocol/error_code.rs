@@ -0,0 +1,393 @@
+use std::fmt;
+
+/// Whether a row of [`kafka_error_codes!`] is marked `retriable`
+macro_rules! retriable {
+    (retriable) => {
+        true
+    };
+    (fatal) => {
+        false
+    };
+}
+
+/// Defines [`KafkaErrorCode`] and the matching [`ErrorCode`] constants from
+/// one `Variant = code => NAME, retriable|fatal, "message";` row per code
+macro_rules! kafka_error_codes {
+    ($($variant:ident = $code:literal => $name:ident, $retry:ident, $message:literal;)+) => {
+        /// An error code this crate knows by name
+        ///
+        /// Converting from the INT16 on the wire fails with the raw value for
+        /// codes that are not listed, the same way [`ApiKey`](crate::protocol::ApiKey)
+        /// does.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(i16)]
+        pub enum KafkaErrorCode {
+            $($variant = $code,)+
+        }
+
+        impl KafkaErrorCode {
+            /// Every known code, in code order
+            pub const ALL: &'static [KafkaErrorCode] = &[$(KafkaErrorCode::$variant,)+];
+
+            /// The INT16 written to the wire
+            pub const fn code(self) -> i16 {
+                self as i16
+            }
+
+            /// The canonical name, as the protocol guide spells it
+            pub const fn name(self) -> &'static str {
+                match self {
+                    $(KafkaErrorCode::$variant => stringify!($name),)+
+                }
+            }
+
+            /// The protocol guide's description of the code
+            pub const fn message(self) -> &'static str {
+                match self {
+                    $(KafkaErrorCode::$variant => $message,)+
+                }
+            }
+
+            /// Whether a client may retry the request unchanged and expect
+            /// it to succeed eventually
+            pub const fn is_retriable(self) -> bool {
+                match self {
+                    $(KafkaErrorCode::$variant => retriable!($retry),)+
+                }
+            }
+        }
+
+        impl TryFrom<i16> for KafkaErrorCode {
+            type Error = i16;
+
+            fn try_from(code: i16) -> Result<Self, i16> {
+                match code {
+                    $($code => Ok(KafkaErrorCode::$variant),)+
+                    _ => Err(code),
+                }
+            }
+        }
+
+        impl ErrorCode {
+            $(pub const $name: Self = Self($code);)+
+        }
+    };
+}
+
+kafka_error_codes! {
+    UnknownServerError = -1 => UNKNOWN_SERVER_ERROR, fatal,
+        "The server experienced an unexpected error when processing the request.";
+    None = 0 => NONE, fatal,
+        "";
+    OffsetOutOfRange = 1 => OFFSET_OUT_OF_RANGE, fatal,
+        "The requested offset is not within the range of offsets maintained by the server.";
+    CorruptMessage = 2 => CORRUPT_MESSAGE, retriable,
+        "This message has failed its CRC checksum, exceeds the valid size, has a null key for a compacted topic, or is otherwise corrupt.";
+    UnknownTopicOrPartition = 3 => UNKNOWN_TOPIC_OR_PARTITION, retriable,
+        "This server does not host this topic-partition.";
+    InvalidFetchSize = 4 => INVALID_FETCH_SIZE, fatal,
+        "The requested fetch size is invalid.";
+    LeaderNotAvailable = 5 => LEADER_NOT_AVAILABLE, retriable,
+        "There is no leader for this topic-partition as we are in the middle of a leadership election.";
+    NotLeaderForPartition = 6 => NOT_LEADER_FOR_PARTITION, retriable,
+        "This broker is not the leader or a replica of the topic-partition.";
+    RequestTimedOut = 7 => REQUEST_TIMED_OUT, retriable,
+        "The request timed out.";
+    BrokerNotAvailable = 8 => BROKER_NOT_AVAILABLE, fatal,
+        "The broker is not available.";
+    ReplicaNotAvailable = 9 => REPLICA_NOT_AVAILABLE, retriable,
+        "The replica is not available for the requested topic-partition.";
+    MessageTooLarge = 10 => MESSAGE_TOO_LARGE, fatal,
+        "The request included a message larger than the max message size the server will accept.";
+    StaleControllerEpoch = 11 => STALE_CONTROLLER_EPOCH, fatal,
+        "The controller moved to another broker.";
+    OffsetMetadataTooLarge = 12 => OFFSET_METADATA_TOO_LARGE, fatal,
+        "The metadata field of the offset request was too large.";
+    NetworkException = 13 => NETWORK_EXCEPTION, retriable,
+        "The server disconnected before a response was received.";
+    CoordinatorLoadInProgress = 14 => COORDINATOR_LOAD_IN_PROGRESS, retriable,
+        "The coordinator is loading and hence can't process requests.";
+    CoordinatorNotAvailable = 15 => COORDINATOR_NOT_AVAILABLE, retriable,
+        "The coordinator is not available.";
+    NotCoordinator = 16 => NOT_COORDINATOR, retriable,
+        "This is not the correct coordinator.";
+    InvalidTopicException = 17 => INVALID_TOPIC_EXCEPTION, fatal,
+        "The request attempted to perform an operation on an invalid topic.";
+    RecordListTooLarge = 18 => RECORD_LIST_TOO_LARGE, fatal,
+        "The request included message batch larger than the configured segment size on the server.";
+    NotEnoughReplicas = 19 => NOT_ENOUGH_REPLICAS, retriable,
+        "Messages are rejected since there are fewer in-sync replicas than required.";
+    NotEnoughReplicasAfterAppend = 20 => NOT_ENOUGH_REPLICAS_AFTER_APPEND, retriable,
+        "Messages are written to the log, but to fewer in-sync replicas than required.";
+    InvalidRequiredAcks = 21 => INVALID_REQUIRED_ACKS, fatal,
+        "Produce request specified an invalid value for required acks.";
+    IllegalGeneration = 22 => ILLEGAL_GENERATION, fatal,
+        "Specified group generation id is not valid.";
+    InconsistentGroupProtocol = 23 => INCONSISTENT_GROUP_PROTOCOL, fatal,
+        "The group member's supported protocols are incompatible with those of existing members.";
+    InvalidGroupId = 24 => INVALID_GROUP_ID, fatal,
+        "The configured groupId is invalid.";
+    UnknownMemberId = 25 => UNKNOWN_MEMBER_ID, fatal,
+        "The coordinator is not aware of this member.";
+    InvalidSessionTimeout = 26 => INVALID_SESSION_TIMEOUT, fatal,
+        "The session timeout is not within the range allowed by the broker.";
+    RebalanceInProgress = 27 => REBALANCE_IN_PROGRESS, fatal,
+        "The group is rebalancing, so a rejoin is needed.";
+    InvalidCommitOffsetSize = 28 => INVALID_COMMIT_OFFSET_SIZE, fatal,
+        "The committing offset data size is not valid.";
+    TopicAuthorizationFailed = 29 => TOPIC_AUTHORIZATION_FAILED, fatal,
+        "Topic authorization failed.";
+    GroupAuthorizationFailed = 30 => GROUP_AUTHORIZATION_FAILED, fatal,
+        "Group authorization failed.";
+    ClusterAuthorizationFailed = 31 => CLUSTER_AUTHORIZATION_FAILED, fatal,
+        "Cluster authorization failed.";
+    InvalidTimestamp = 32 => INVALID_TIMESTAMP, fatal,
+        "The timestamp of the message is out of acceptable range.";
+    UnsupportedSaslMechanism = 33 => UNSUPPORTED_SASL_MECHANISM, fatal,
+        "The broker does not support the requested SASL mechanism.";
+    IllegalSaslState = 34 => ILLEGAL_SASL_STATE, fatal,
+        "Request is not valid given the current SASL state.";
+    UnsupportedVersion = 35 => UNSUPPORTED_VERSION, fatal,
+        "The version of API is not supported.";
+    TopicAlreadyExists = 36 => TOPIC_ALREADY_EXISTS, fatal,
+        "Topic with this name already exists.";
+    InvalidPartitions = 37 => INVALID_PARTITIONS, fatal,
+        "Number of partitions is below 1.";
+    InvalidReplicationFactor = 38 => INVALID_REPLICATION_FACTOR, fatal,
+        "Replication factor is below 1 or larger than the number of available brokers.";
+    InvalidReplicaAssignment = 39 => INVALID_REPLICA_ASSIGNMENT, fatal,
+        "Replica assignment is invalid.";
+    InvalidConfig = 40 => INVALID_CONFIG, fatal,
+        "Configuration is invalid.";
+    NotController = 41 => NOT_CONTROLLER, retriable,
+        "This is not the correct controller for this cluster.";
+    InvalidRequest = 42 => INVALID_REQUEST, fatal,
+        "The request was malformed or sent to an incompatible broker.";
+    UnsupportedForMessageFormat = 43 => UNSUPPORTED_FOR_MESSAGE_FORMAT, fatal,
+        "The message format version on the broker does not support the request.";
+    PolicyViolation = 44 => POLICY_VIOLATION, fatal,
+        "Request parameters do not satisfy the configured policy.";
+    OutOfOrderSequenceNumber = 45 => OUT_OF_ORDER_SEQUENCE_NUMBER, fatal,
+        "The broker received an out of order sequence number.";
+    DuplicateSequenceNumber = 46 => DUPLICATE_SEQUENCE_NUMBER, fatal,
+        "The broker received a duplicate sequence number.";
+    InvalidProducerEpoch = 47 => INVALID_PRODUCER_EPOCH, fatal,
+        "Producer attempted to produce with an old epoch.";
+    InvalidTxnState = 48 => INVALID_TXN_STATE, fatal,
+        "The producer attempted a transactional operation in an invalid state.";
+    InvalidProducerIdMapping = 49 => INVALID_PRODUCER_ID_MAPPING, fatal,
+        "The producer attempted to use a producer id which is not currently assigned to its transactional id.";
+    InvalidTransactionTimeout = 50 => INVALID_TRANSACTION_TIMEOUT, fatal,
+        "The transaction timeout is larger than the maximum value allowed by the broker.";
+    ConcurrentTransactions = 51 => CONCURRENT_TRANSACTIONS, retriable,
+        "The producer attempted to update a transaction while another concurrent operation on the same transaction was ongoing.";
+    TransactionCoordinatorFenced = 52 => TRANSACTION_COORDINATOR_FENCED, fatal,
+        "The transaction coordinator sending a WriteTxnMarker is no longer the current coordinator for the producer.";
+    TransactionalIdAuthorizationFailed = 53 => TRANSACTIONAL_ID_AUTHORIZATION_FAILED, fatal,
+        "Transactional Id authorization failed.";
+    SecurityDisabled = 54 => SECURITY_DISABLED, fatal,
+        "Security features are disabled.";
+    OperationNotAttempted = 55 => OPERATION_NOT_ATTEMPTED, fatal,
+        "The broker did not attempt to execute this operation.";
+    KafkaStorageError = 56 => KAFKA_STORAGE_ERROR, retriable,
+        "Disk error when trying to access log file on the disk.";
+    LogDirNotFound = 57 => LOG_DIR_NOT_FOUND, fatal,
+        "The user-specified log directory is not found in the broker config.";
+    SaslAuthenticationFailed = 58 => SASL_AUTHENTICATION_FAILED, fatal,
+        "SASL Authentication failed.";
+    UnknownProducerId = 59 => UNKNOWN_PRODUCER_ID, fatal,
+        "The broker could not locate the producer metadata associated with the producer id.";
+    ReassignmentInProgress = 60 => REASSIGNMENT_IN_PROGRESS, fatal,
+        "A partition reassignment is in progress.";
+    DelegationTokenAuthDisabled = 61 => DELEGATION_TOKEN_AUTH_DISABLED, fatal,
+        "Delegation Token feature is not enabled.";
+    DelegationTokenNotFound = 62 => DELEGATION_TOKEN_NOT_FOUND, fatal,
+        "Delegation Token is not found on server.";
+    DelegationTokenOwnerMismatch = 63 => DELEGATION_TOKEN_OWNER_MISMATCH, fatal,
+        "Specified Principal is not valid Owner/Renewer.";
+    DelegationTokenRequestNotAllowed = 64 => DELEGATION_TOKEN_REQUEST_NOT_ALLOWED, fatal,
+        "Delegation Token requests are not allowed on PLAINTEXT/1-way SSL channels and on delegation token authenticated channels.";
+    DelegationTokenAuthorizationFailed = 65 => DELEGATION_TOKEN_AUTHORIZATION_FAILED, fatal,
+        "Delegation Token authorization failed.";
+    DelegationTokenExpired = 66 => DELEGATION_TOKEN_EXPIRED, fatal,
+        "Delegation Token is expired.";
+    InvalidPrincipalType = 67 => INVALID_PRINCIPAL_TYPE, fatal,
+        "Supplied principalType is not supported.";
+    NonEmptyGroup = 68 => NON_EMPTY_GROUP, fatal,
+        "The group is not empty.";
+    GroupIdNotFound = 69 => GROUP_ID_NOT_FOUND, fatal,
+        "The group id does not exist.";
+    FetchSessionIdNotFound = 70 => FETCH_SESSION_ID_NOT_FOUND, retriable,
+        "The fetch session ID was not found.";
+    InvalidFetchSessionEpoch = 71 => INVALID_FETCH_SESSION_EPOCH, retriable,
+        "The fetch session epoch is invalid.";
+    ListenerNotFound = 72 => LISTENER_NOT_FOUND, retriable,
+        "There is no listener on the leader broker that matches the listener on which metadata request was processed.";
+    TopicDeletionDisabled = 73 => TOPIC_DELETION_DISABLED, fatal,
+        "Topic deletion is disabled.";
+    FencedLeaderEpoch = 74 => FENCED_LEADER_EPOCH, retriable,
+        "The leader epoch in the request is older than the epoch on the broker.";
+    UnknownLeaderEpoch = 75 => UNKNOWN_LEADER_EPOCH, retriable,
+        "The leader epoch in the request is newer than the epoch on the broker.";
+    UnsupportedCompressionType = 76 => UNSUPPORTED_COMPRESSION_TYPE, fatal,
+        "The requesting client does not support the compression type of given partition.";
+    StaleBrokerEpoch = 77 => STALE_BROKER_EPOCH, fatal,
+        "Broker epoch has changed.";
+    OffsetNotAvailable = 78 => OFFSET_NOT_AVAILABLE, retriable,
+        "The leader high watermark has not caught up from a recent leader election so the offsets cannot be guaranteed to be monotonically increasing.";
+    MemberIdRequired = 79 => MEMBER_ID_REQUIRED, fatal,
+        "The group member needs to have a valid member id before actually entering a consumer group.";
+    PreferredLeaderNotAvailable = 80 => PREFERRED_LEADER_NOT_AVAILABLE, retriable,
+        "The preferred leader was not available.";
+    GroupMaxSizeReached = 81 => GROUP_MAX_SIZE_REACHED, fatal,
+        "The consumer group has reached its max size.";
+    FencedInstanceId = 82 => FENCED_INSTANCE_ID, fatal,
+        "The broker rejected this static consumer since another consumer with the same group.instance.id has registered with a different member.id.";
+    UnknownTopicId = 100 => UNKNOWN_TOPIC_ID, retriable,
+        "This server does not host this topic ID.";
+    MismatchedEndpointType = 114 => MISMATCHED_ENDPOINT_TYPE, fatal,
+        "The request was sent to an endpoint of the wrong type.";
+    UnsupportedEndpointType = 115 => UNSUPPORTED_ENDPOINT_TYPE, fatal,
+        "This endpoint type is not supported yet.";
+}
+
+impl From<KafkaErrorCode> for i16 {
+    fn from(code: KafkaErrorCode) -> Self {
+        code.code()
+    }
+}
+
+impl fmt::Display for KafkaErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name(), self.code())
+    }
+}
+
+/// Error code carried in a response
+///
+/// Only the protocol layer can build one from a raw integer: everything
+/// else gets its codes from [`KafkaErrorCode`], the named constants or, for
+/// broker failures, from [`wire_error`](crate::kafka::error::wire_error), so
+/// the mapping from failures to codes lives in one place. Unlike
+/// [`KafkaErrorCode`] it can hold codes this crate does not know, which a
+/// decoded response may carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorCode(i16);
+
+impl ErrorCode {
+    /// Builds a code read off the wire
+    pub(in crate::protocol) const fn from_wire(code: i16) -> Self {
+        Self(code)
+    }
+
+    /// The INT16 written to the wire
+    pub const fn code(self) -> i16 {
+        self.0
+    }
+
+    /// Whether this code reports a failure
+    pub fn is_error(self) -> bool {
+        self != Self::NONE
+    }
+
+    /// The named code, or the raw value when this crate does not know it
+    pub fn kind(self) -> Result<KafkaErrorCode, i16> {
+        KafkaErrorCode::try_from(self.0)
+    }
+
+    /// The protocol guide's description, or a placeholder for unknown codes
+    pub fn message(self) -> &'static str {
+        self.kind()
+            .map_or("Unknown error code.", KafkaErrorCode::message)
+    }
+
+    /// Whether a client may retry; unknown codes are treated as fatal
+    pub fn is_retriable(self) -> bool {
+        self.kind().is_ok_and(KafkaErrorCode::is_retriable)
+    }
+}
+
+impl From<KafkaErrorCode> for ErrorCode {
+    fn from(code: KafkaErrorCode) -> Self {
+        Self(code.code())
+    }
+}
+
+impl PartialEq<KafkaErrorCode> for ErrorCode {
+    fn eq(&self, other: &KafkaErrorCode) -> bool {
+        self.0 == other.code()
+    }
+}
+
+/// Prints known codes like `UNKNOWN_TOPIC_OR_PARTITION (3)` and unknown ones
+/// as the bare number
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind() {
+            Ok(code) => code.fmt(f),
+            Err(code) => write!(f, "{}", code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_codes_convert_both_ways() {
+        for &code in KafkaErrorCode::ALL {
+            assert_eq!(KafkaErrorCode::try_from(code.code()), Ok(code));
+            assert_eq!(ErrorCode::from(code).kind(), Ok(code));
+        }
+        assert_eq!(KafkaErrorCode::UnknownServerError.code(), -1);
+        assert_eq!(KafkaErrorCode::None.code(), 0);
+        assert_eq!(KafkaErrorCode::UnsupportedVersion.code(), 35);
+        assert_eq!(KafkaErrorCode::UnknownTopicId.code(), 100);
+        assert_eq!(
+            ErrorCode::UNKNOWN_TOPIC_OR_PARTITION,
+            KafkaErrorCode::UnknownTopicOrPartition
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            KafkaErrorCode::UnknownTopicOrPartition.to_string(),
+            "UNKNOWN_TOPIC_OR_PARTITION (3)"
+        );
+        assert_eq!(ErrorCode::NONE.to_string(), "NONE (0)");
+        assert_eq!(
+            ErrorCode::UNKNOWN_SERVER_ERROR.to_string(),
+            "UNKNOWN_SERVER_ERROR (-1)"
+        );
+        assert_eq!(
+            KafkaErrorCode::UnsupportedVersion.message(),
+            "The version of API is not supported."
+        );
+    }
+
+    #[test]
+    fn test_unknown_codes_fall_back() {
+        for raw in [-2, 83, 99, 1000] {
+            assert_eq!(KafkaErrorCode::try_from(raw), Err(raw));
+            let code = ErrorCode::from_wire(raw);
+            assert_eq!(code.kind(), Err(raw));
+            assert_eq!(code.to_string(), raw.to_string());
+            assert_eq!(code.message(), "Unknown error code.");
+            assert!(!code.is_retriable());
+            assert!(code.is_error());
+        }
+    }
+
+    #[test]
+    fn test_retriability() {
+        assert!(KafkaErrorCode::RequestTimedOut.is_retriable());
+        assert!(!KafkaErrorCode::InvalidRequest.is_retriable());
+        assert!(ErrorCode::REQUEST_TIMED_OUT.is_retriable());
+        assert!(!ErrorCode::INVALID_REQUEST.is_retriable());
+        assert!(KafkaErrorCode::NotLeaderForPartition.is_retriable());
+        assert!(!KafkaErrorCode::UnsupportedVersion.is_retriable());
+        assert!(!KafkaErrorCode::None.is_retriable());
+    }
+}
@@ -24,8 +24,49 @@ pub enum ProtocolError {
     #[error("Invalid length field: {length}")]
     InvalidLength { length: i32 },
 
+    #[error("Non-minimal varint: {length} bytes where {minimal} suffice")]
+    NonCanonicalVarint { length: usize, minimal: usize },
+
+    /// A length or count claimed more than the installed
+    /// [`DecodeLimits`](crate::protocol::decode_limits::DecodeLimits) allow;
+    /// raised before anything is allocated for it
+    #[error("Decode limit exceeded: {field} of {requested} is above the limit of {limit}")]
+    LimitExceeded {
+        field: &'static str,
+        limit: usize,
+        requested: usize,
+    },
+
+    /// A record batch compressed with a codec this build cannot decode
+    #[error("Unsupported compression codec {codec}")]
+    UnsupportedCompression { codec: i16 },
+
+    #[error("Invalid client id: {length} bytes exceeds the limit of {limit}")]
+    InvalidClientId { length: usize, limit: usize },
+
     #[error("Buffer overflow: attempted to read {attempted} bytes from {available}")]
     BufferOverflow { attempted: usize, available: usize },
+
+    /// Another error, located at the field that was being decoded
+    #[error("{error} at byte {offset} in {path}")]
+    At {
+        /// Offset from the start of the buffer handed to the decoder
+        offset: usize,
+        /// Dotted field path, with array indices, such as `topics[2].name`
+        path: String,
+        error: Box<ProtocolError>,
+    },
+
+    #[error(
+        "API {api_key} v{api_version} body is encoded as {}, but its header version implies {}; header and body flexibility look mismatched",
+        if *header_flexible { "non-flexible" } else { "flexible" },
+        if *header_flexible { "flexible" } else { "non-flexible" }
+    )]
+    FlexibilityMismatch {
+        api_key: i16,
+        api_version: i16,
+        header_flexible: bool,
+    },
 }
 
 /// Type alias for protocol operation results
@@ -54,4 +95,58 @@ impl ProtocolError {
     pub fn invalid_length(length: i32) -> Self {
         Self::InvalidLength { length }
     }
+
+    /// Locates the error at `field`, starting at `offset`
+    ///
+    /// An error that is already located keeps its innermost location.
+    pub fn at(self, offset: usize, field: &str) -> Self {
+        match self {
+            located @ Self::At { .. } => located,
+            error => Self::At {
+                offset,
+                path: field.to_string(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Prefixes the path of a located error with the enclosing field
+    pub fn within(self, parent: &str) -> Self {
+        match self {
+            Self::At {
+                offset,
+                path,
+                error,
+            } => Self::At {
+                offset,
+                path: format!("{}.{}", parent, path),
+                error,
+            },
+            error => error,
+        }
+    }
+
+    /// Byte offset the error was located at, if it was
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Self::At { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Field path the error was located at, if it was
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::At { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The underlying error, without its location
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::At { error, .. } => error.root(),
+            error => error,
+        }
+    }
 }
@@ -0,0 +1,971 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecode, ProtocolDecodeVersioned, ProtocolEncode, ProtocolEncodeVersioned,
+    WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields, TaggedFields};
+use bytes::{BufMut, Bytes, BytesMut};
+use uuid::Uuid;
+
+/// Lowest Fetch version we serve
+///
+/// Versions before 4 have no isolation level or last stable offset and
+/// were dropped by the reference broker as well.
+pub const FETCH_MIN_VERSION: i16 = 4;
+
+/// Highest Fetch version we serve
+pub const FETCH_MAX_VERSION: i16 = 16;
+
+/// First flexible Fetch version
+const FIRST_FLEXIBLE_VERSION: i16 = 12;
+
+/// First Fetch version naming topics by id rather than by name
+const FIRST_TOPIC_ID_VERSION: i16 = 13;
+
+/// First Fetch version carrying the replica id in a tagged field
+const FIRST_REPLICA_STATE_VERSION: i16 = 15;
+
+/// Request tag holding the cluster id
+const CLUSTER_ID_TAG: u32 = 0;
+
+/// Request tag holding the replica state
+const REPLICA_STATE_TAG: u32 = 1;
+
+/// `replica_id` of a fetch sent by a consumer rather than a follower
+pub const CONSUMER_REPLICA_ID: i32 = -1;
+
+/// `isolation_level` of a consumer reading every record, aborted or not
+pub const READ_UNCOMMITTED: i8 = 0;
+
+/// `isolation_level` of a consumer reading only committed transactions
+pub const READ_COMMITTED: i8 = 1;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version("Fetch", version, FETCH_MIN_VERSION..=FETCH_MAX_VERSION)
+}
+
+/// Fetch request (API key 1)
+///
+/// Fields a version does not carry keep their default on decode and are
+/// left out on encode: -1 for epochs, offsets and `replica_id`, an empty
+/// name before v13 or a nil id from v13. From v12 the request is flexible;
+/// the cluster id and replica state travel as tagged fields and any other
+/// tags are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchRequest {
+    /// Tagged from v12
+    pub cluster_id: Option<String>,
+    pub replica_id: i32,
+    /// v15+, tagged
+    pub replica_epoch: i64,
+    pub max_wait_ms: i32,
+    pub min_bytes: i32,
+    pub max_bytes: i32,
+    /// [`READ_UNCOMMITTED`] or [`READ_COMMITTED`]
+    pub isolation_level: i8,
+    /// v7+
+    pub session_id: i32,
+    /// v7+
+    pub session_epoch: i32,
+    pub topics: Vec<FetchTopic>,
+    /// v7+
+    pub forgotten_topics_data: Vec<FetchForgottenTopic>,
+    /// v11+
+    pub rack_id: String,
+}
+
+/// Per-topic data of a [`FetchRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchTopic {
+    /// Up to v12
+    pub topic: String,
+    /// v13+
+    pub topic_id: Uuid,
+    pub partitions: Vec<FetchPartition>,
+}
+
+/// Per-partition data of a [`FetchTopic`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchPartition {
+    pub partition: i32,
+    /// v9+
+    pub current_leader_epoch: i32,
+    pub fetch_offset: i64,
+    /// v12+
+    pub last_fetched_epoch: i32,
+    /// v5+
+    pub log_start_offset: i64,
+    pub partition_max_bytes: i32,
+}
+
+/// Partitions an incremental fetch session stops fetching (v7+)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchForgottenTopic {
+    /// Up to v12
+    pub topic: String,
+    /// v13+
+    pub topic_id: Uuid,
+    pub partitions: Vec<i32>,
+}
+
+impl FetchRequest {
+    /// Decodes the request body for the given version
+    ///
+    /// # Examples
+    /// ```
+    /// use codecrafters_kafka::protocol::fetch::{FetchPartition, FetchRequest, FetchTopic};
+    /// use codecrafters_kafka::protocol::ProtocolEncodeVersioned;
+    /// use bytes::BytesMut;
+    /// use uuid::Uuid;
+    ///
+    /// let request = FetchRequest {
+    ///     cluster_id: None,
+    ///     replica_id: -1,
+    ///     replica_epoch: -1,
+    ///     max_wait_ms: 500,
+    ///     min_bytes: 1,
+    ///     max_bytes: 52428800,
+    ///     isolation_level: 0,
+    ///     session_id: 0,
+    ///     session_epoch: 0,
+    ///     topics: vec![FetchTopic {
+    ///         topic: String::new(),
+    ///         topic_id: Uuid::from_u128(7),
+    ///         partitions: vec![FetchPartition {
+    ///             partition: 0,
+    ///             current_leader_epoch: 0,
+    ///             fetch_offset: 0,
+    ///             last_fetched_epoch: -1,
+    ///             log_start_offset: -1,
+    ///             partition_max_bytes: 1048576,
+    ///         }],
+    ///     }],
+    ///     forgotten_topics_data: vec![],
+    ///     rack_id: String::new(),
+    /// };
+    /// let mut buffer = BytesMut::new();
+    /// request.encode_versioned(16, &mut buffer).unwrap();
+    /// let decoded = FetchRequest::decode(&mut buffer.freeze(), 16).unwrap();
+    /// assert_eq!(decoded, request);
+    /// ```
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let mut replica_id = if version < FIRST_REPLICA_STATE_VERSION {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            CONSUMER_REPLICA_ID
+        };
+        let max_wait_ms = WireFormat::decode_i32(buffer)?;
+        let min_bytes = WireFormat::decode_i32(buffer)?;
+        let max_bytes = WireFormat::decode_i32(buffer)?;
+        let isolation_level = WireFormat::decode_i8(buffer)?;
+        let (session_id, session_epoch) = if version >= 7 {
+            (
+                WireFormat::decode_i32(buffer)?,
+                WireFormat::decode_i32(buffer)?,
+            )
+        } else {
+            (0, -1)
+        };
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let (topic, topic_id) = decode_topic_key(buffer, version)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let partition = FetchPartition {
+                    partition: WireFormat::decode_i32(buffer)?,
+                    current_leader_epoch: if version >= 9 {
+                        WireFormat::decode_i32(buffer)?
+                    } else {
+                        -1
+                    },
+                    fetch_offset: WireFormat::decode_i64(buffer)?,
+                    last_fetched_epoch: if version >= 12 {
+                        WireFormat::decode_i32(buffer)?
+                    } else {
+                        -1
+                    },
+                    log_start_offset: if version >= 5 {
+                        WireFormat::decode_i64(buffer)?
+                    } else {
+                        -1
+                    },
+                    partition_max_bytes: WireFormat::decode_i32(buffer)?,
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(partition)
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(FetchTopic {
+                topic,
+                topic_id,
+                partitions,
+            })
+        })?;
+        let forgotten_topics_data = if version >= 7 {
+            WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let (topic, topic_id) = decode_topic_key(buffer, version)?;
+                let partitions =
+                    WireFormat::decode_flexible_array(buffer, flexible, WireFormat::decode_i32)?;
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(FetchForgottenTopic {
+                    topic,
+                    topic_id,
+                    partitions,
+                })
+            })?
+        } else {
+            Vec::new()
+        };
+        let rack_id = if version >= 11 {
+            WireFormat::decode_flexible_string(buffer, flexible)?
+        } else {
+            String::new()
+        };
+
+        let mut cluster_id = None;
+        let mut replica_epoch = -1;
+        if flexible {
+            let tagged_fields = TaggedFields::decode(buffer)?;
+            if let Some(value) = tagged_fields.get(CLUSTER_ID_TAG) {
+                cluster_id = WireFormat::decode_compact_nullable_string(&mut value.clone())?;
+            }
+            if version >= FIRST_REPLICA_STATE_VERSION {
+                if let Some(value) = tagged_fields.get(REPLICA_STATE_TAG) {
+                    let mut value = value.clone();
+                    replica_id = WireFormat::decode_i32(&mut value)?;
+                    replica_epoch = WireFormat::decode_i64(&mut value)?;
+                    TaggedFields::decode(&mut value)?;
+                }
+            }
+        }
+
+        Ok(Self {
+            cluster_id,
+            replica_id,
+            replica_epoch,
+            max_wait_ms,
+            min_bytes,
+            max_bytes,
+            isolation_level,
+            session_id,
+            session_epoch,
+            topics,
+            forgotten_topics_data,
+            rack_id,
+        })
+    }
+
+    /// The top-level tag section written for `version`
+    fn tagged_fields(&self, version: i16) -> ProtocolResult<TaggedFields> {
+        let mut tagged_fields = TaggedFields::new();
+        if let Some(cluster_id) = &self.cluster_id {
+            let mut value = BytesMut::new();
+            WireFormat::encode_compact_string(&mut value, cluster_id)?;
+            tagged_fields.insert(CLUSTER_ID_TAG, value);
+        }
+        let has_replica_state = self.replica_id != CONSUMER_REPLICA_ID || self.replica_epoch != -1;
+        if version >= FIRST_REPLICA_STATE_VERSION && has_replica_state {
+            let mut value = BytesMut::with_capacity(13);
+            value.put_i32(self.replica_id);
+            value.put_i64(self.replica_epoch);
+            WireFormat::encode_unsigned_varint(&mut value, 0);
+            tagged_fields.insert(REPLICA_STATE_TAG, value);
+        }
+        Ok(tagged_fields)
+    }
+}
+
+impl ProtocolEncodeVersioned for FetchRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version < FIRST_REPLICA_STATE_VERSION {
+            buffer.put_i32(self.replica_id);
+        }
+        buffer.put_i32(self.max_wait_ms);
+        buffer.put_i32(self.min_bytes);
+        buffer.put_i32(self.max_bytes);
+        buffer.put_i8(self.isolation_level);
+        if version >= 7 {
+            buffer.put_i32(self.session_id);
+            buffer.put_i32(self.session_epoch);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            encode_topic_key(buffer, &topic.topic, &topic.topic_id, version)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i32(partition.partition);
+                    if version >= 9 {
+                        buffer.put_i32(partition.current_leader_epoch);
+                    }
+                    buffer.put_i64(partition.fetch_offset);
+                    if version >= 12 {
+                        buffer.put_i32(partition.last_fetched_epoch);
+                    }
+                    if version >= 5 {
+                        buffer.put_i64(partition.log_start_offset);
+                    }
+                    buffer.put_i32(partition.partition_max_bytes);
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        if version >= 7 {
+            WireFormat::encode_flexible_array(
+                buffer,
+                &self.forgotten_topics_data,
+                flexible,
+                |buffer, topic| {
+                    encode_topic_key(buffer, &topic.topic, &topic.topic_id, version)?;
+                    WireFormat::encode_flexible_array(
+                        buffer,
+                        &topic.partitions,
+                        flexible,
+                        |buffer, partition| {
+                            buffer.put_i32(*partition);
+                            Ok(())
+                        },
+                    )?;
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+        }
+        if version >= 11 {
+            WireFormat::encode_flexible_string(buffer, &self.rack_id, flexible)?;
+        }
+        if flexible {
+            self.tagged_fields(version)?.encode_into(buffer)?;
+        }
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let partition_size = 4
+            + if version >= 9 { 4 } else { 0 }
+            + 8
+            + if version >= 12 { 4 } else { 0 }
+            + if version >= 5 { 8 } else { 0 }
+            + 4
+            + tags;
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                topic_key_size(&topic.topic, version)
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + topic.partitions.len() * partition_size
+                    + tags
+            })
+            .sum();
+        let forgotten_topics = if version >= 7 {
+            let topics: usize = self
+                .forgotten_topics_data
+                .iter()
+                .map(|topic| {
+                    topic_key_size(&topic.topic, version)
+                        + WireFormat::flexible_array_length_size(
+                            Some(topic.partitions.len()),
+                            flexible,
+                        )
+                        + 4 * topic.partitions.len()
+                        + tags
+                })
+                .sum();
+            WireFormat::flexible_array_length_size(Some(self.forgotten_topics_data.len()), flexible)
+                + topics
+        } else {
+            0
+        };
+        let rack_id = if version >= 11 {
+            WireFormat::flexible_string_size(&self.rack_id, flexible)
+        } else {
+            0
+        };
+        let tagged_fields = if flexible {
+            self.tagged_fields(version)
+                .map_or(1, |tagged_fields| tagged_fields.encoded_size())
+        } else {
+            0
+        };
+        let replica_id = if version < FIRST_REPLICA_STATE_VERSION {
+            4
+        } else {
+            0
+        };
+        let session = if version >= 7 { 8 } else { 0 };
+        replica_id
+            + 4
+            + 4
+            + 4
+            + 1
+            + session
+            + WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + forgotten_topics
+            + rack_id
+            + tagged_fields
+    }
+}
+
+impl ProtocolDecodeVersioned for FetchRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// Fetch response (API key 1)
+///
+/// Tagged fields are skipped on decode and written empty: this broker
+/// never sets the diverging epoch, current leader, snapshot id or node
+/// endpoints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchResponse {
+    pub throttle_time_ms: i32,
+    /// v7+
+    pub error_code: ErrorCode,
+    /// v7+; 0 when the fetch is not part of a session
+    pub session_id: i32,
+    pub responses: Vec<FetchResponseTopic>,
+}
+
+/// Per-topic result of a [`FetchResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchResponseTopic {
+    /// Up to v12
+    pub topic: String,
+    /// v13+
+    pub topic_id: Uuid,
+    pub partitions: Vec<FetchResponsePartition>,
+}
+
+/// Per-partition result of a [`FetchResponseTopic`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchResponsePartition {
+    pub partition_index: i32,
+    pub error_code: ErrorCode,
+    pub high_watermark: i64,
+    pub last_stable_offset: i64,
+    /// v5+
+    pub log_start_offset: i64,
+    pub aborted_transactions: Option<Vec<FetchAbortedTransaction>>,
+    /// v11+
+    pub preferred_read_replica: i32,
+    /// Record batches exactly as stored
+    pub records: Option<Bytes>,
+}
+
+impl FetchResponsePartition {
+    /// The result for a partition that cannot be read, with no offsets and
+    /// no records
+    pub fn error(partition_index: i32, error_code: impl Into<ErrorCode>) -> Self {
+        Self {
+            partition_index,
+            error_code: error_code.into(),
+            high_watermark: -1,
+            last_stable_offset: -1,
+            log_start_offset: -1,
+            aborted_transactions: None,
+            preferred_read_replica: -1,
+            records: Some(Bytes::new()),
+        }
+    }
+}
+
+/// A transaction aborted within the fetched range
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchAbortedTransaction {
+    pub producer_id: i64,
+    pub first_offset: i64,
+}
+
+impl FetchResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    ///
+    /// `records` are views into `buffer`, not copies.
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = WireFormat::decode_i32(buffer)?;
+        let (error_code, session_id) = if version >= 7 {
+            (
+                ErrorCode::from_wire(WireFormat::decode_i16(buffer)?),
+                WireFormat::decode_i32(buffer)?,
+            )
+        } else {
+            (ErrorCode::NONE, 0)
+        };
+        let responses = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let (topic, topic_id) = decode_topic_key(buffer, version)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let partition_index = WireFormat::decode_i32(buffer)?;
+                let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+                let high_watermark = WireFormat::decode_i64(buffer)?;
+                let last_stable_offset = WireFormat::decode_i64(buffer)?;
+                let log_start_offset = if version >= 5 {
+                    WireFormat::decode_i64(buffer)?
+                } else {
+                    -1
+                };
+                let decode_aborted = |buffer: &mut Bytes| {
+                    let aborted = FetchAbortedTransaction {
+                        producer_id: WireFormat::decode_i64(buffer)?,
+                        first_offset: WireFormat::decode_i64(buffer)?,
+                    };
+                    skip_tagged_fields(buffer, flexible)?;
+                    Ok(aborted)
+                };
+                let aborted_transactions =
+                    WireFormat::decode_flexible_nullable_array(buffer, flexible, decode_aborted)?;
+                let preferred_read_replica = if version >= 11 {
+                    WireFormat::decode_i32(buffer)?
+                } else {
+                    -1
+                };
+                let records = WireFormat::decode_flexible_nullable_bytes(buffer, flexible)?;
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(FetchResponsePartition {
+                    partition_index,
+                    error_code,
+                    high_watermark,
+                    last_stable_offset,
+                    log_start_offset,
+                    aborted_transactions,
+                    preferred_read_replica,
+                    records,
+                })
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(FetchResponseTopic {
+                topic,
+                topic_id,
+                partitions,
+            })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            error_code,
+            session_id,
+            responses,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for FetchResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        buffer.put_i32(self.throttle_time_ms);
+        if version >= 7 {
+            buffer.put_i16(self.error_code.code());
+            buffer.put_i32(self.session_id);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.responses, flexible, |buffer, topic| {
+            encode_topic_key(buffer, &topic.topic, &topic.topic_id, version)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i32(partition.partition_index);
+                    buffer.put_i16(partition.error_code.code());
+                    buffer.put_i64(partition.high_watermark);
+                    buffer.put_i64(partition.last_stable_offset);
+                    if version >= 5 {
+                        buffer.put_i64(partition.log_start_offset);
+                    }
+                    let encode_aborted =
+                        |buffer: &mut BytesMut, aborted: &FetchAbortedTransaction| {
+                            buffer.put_i64(aborted.producer_id);
+                            buffer.put_i64(aborted.first_offset);
+                            put_empty_tagged_fields(buffer, flexible);
+                            Ok(())
+                        };
+                    WireFormat::encode_flexible_nullable_array(
+                        buffer,
+                        partition.aborted_transactions.as_deref(),
+                        flexible,
+                        encode_aborted,
+                    )?;
+                    if version >= 11 {
+                        buffer.put_i32(partition.preferred_read_replica);
+                    }
+                    WireFormat::encode_flexible_nullable_bytes(
+                        buffer,
+                        partition.records.as_deref(),
+                        flexible,
+                    )?;
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let responses: usize = self
+            .responses
+            .iter()
+            .map(|topic| {
+                let partitions: usize = topic
+                    .partitions
+                    .iter()
+                    .map(|partition| {
+                        let aborted = partition.aborted_transactions.as_ref().map_or(
+                            WireFormat::flexible_array_length_size(None, flexible),
+                            |aborted| {
+                                WireFormat::flexible_array_length_size(
+                                    Some(aborted.len()),
+                                    flexible,
+                                ) + aborted.len() * (16 + tags)
+                            },
+                        );
+                        let records = WireFormat::flexible_nullable_bytes_size(
+                            partition.records.as_deref(),
+                            flexible,
+                        );
+                        4 + 2
+                            + 8
+                            + 8
+                            + if version >= 5 { 8 } else { 0 }
+                            + aborted
+                            + if version >= 11 { 4 } else { 0 }
+                            + records
+                            + tags
+                    })
+                    .sum();
+                topic_key_size(&topic.topic, version)
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + partitions
+                    + tags
+            })
+            .sum();
+        let session = if version >= 7 { 2 + 4 } else { 0 };
+        4 + session
+            + WireFormat::flexible_array_length_size(Some(self.responses.len()), flexible)
+            + responses
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for FetchResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// Decodes the topic name before v13 or the topic id from v13
+fn decode_topic_key(buffer: &mut Bytes, version: i16) -> ProtocolResult<(String, Uuid)> {
+    if version >= FIRST_TOPIC_ID_VERSION {
+        Ok((String::new(), WireFormat::decode_uuid(buffer)?))
+    } else {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        Ok((
+            WireFormat::decode_flexible_string(buffer, flexible)?,
+            Uuid::nil(),
+        ))
+    }
+}
+
+fn encode_topic_key(
+    buffer: &mut BytesMut,
+    topic: &str,
+    topic_id: &Uuid,
+    version: i16,
+) -> ProtocolResult<()> {
+    if version >= FIRST_TOPIC_ID_VERSION {
+        WireFormat::encode_uuid(buffer, topic_id);
+        Ok(())
+    } else {
+        WireFormat::encode_flexible_string(buffer, topic, version >= FIRST_FLEXIBLE_VERSION)
+    }
+}
+
+fn topic_key_size(topic: &str, version: i16) -> usize {
+    if version >= FIRST_TOPIC_ID_VERSION {
+        16
+    } else {
+        WireFormat::flexible_string_size(topic, version >= FIRST_FLEXIBLE_VERSION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Body of the first Fetch v16 kafka-console-consumer sends for one
+    /// partition, from `--from-beginning` with default consumer settings
+    const CONSOLE_CONSUMER_V16: &str = concat!(
+        "000001f4",                         // max_wait_ms 500
+        "00000001",                         // min_bytes 1
+        "03200000",                         // max_bytes 52428800
+        "00",                               // read_uncommitted
+        "00000000",                         // session_id
+        "00000000",                         // session_epoch: open a session
+        "02",                               // one topic
+        "7f2c9b1e4d3a4c8e9a6b1f0e2d3c4b5a", // topic id
+        "02",                               // one partition
+        "00000000",                         // partition
+        "00000000",                         // current_leader_epoch
+        "0000000000000000",                 // fetch_offset
+        "ffffffff",                         // last_fetched_epoch
+        "ffffffffffffffff",                 // log_start_offset
+        "00100000",                         // partition_max_bytes 1048576
+        "00",                               // partition tags
+        "00",                               // topic tags
+        "01",                               // no forgotten topics
+        "01",                               // empty rack id
+        "00",                               // request tags
+    );
+
+    fn partition(partition: i32, fetch_offset: i64) -> FetchPartition {
+        FetchPartition {
+            partition,
+            current_leader_epoch: 0,
+            fetch_offset,
+            last_fetched_epoch: -1,
+            log_start_offset: -1,
+            partition_max_bytes: 1048576,
+        }
+    }
+
+    fn request(version: i16) -> FetchRequest {
+        let by_id = version >= FIRST_TOPIC_ID_VERSION;
+        FetchRequest {
+            cluster_id: None,
+            replica_id: CONSUMER_REPLICA_ID,
+            replica_epoch: -1,
+            max_wait_ms: 500,
+            min_bytes: 1,
+            max_bytes: 52428800,
+            isolation_level: 1,
+            session_id: if version >= 7 { 12 } else { 0 },
+            session_epoch: if version >= 7 { 3 } else { -1 },
+            topics: vec![FetchTopic {
+                topic: if by_id {
+                    String::new()
+                } else {
+                    "orders".to_string()
+                },
+                topic_id: if by_id {
+                    Uuid::from_u128(0x42)
+                } else {
+                    Uuid::nil()
+                },
+                partitions: vec![partition(0, 17), partition(1, 0)],
+            }],
+            forgotten_topics_data: Vec::new(),
+            rack_id: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_request_decodes_console_consumer_bytes() {
+        let mut buffer = Bytes::from(hex::decode(CONSOLE_CONSUMER_V16).unwrap());
+        let request = FetchRequest::decode(&mut buffer, 16).unwrap();
+        assert!(buffer.is_empty());
+        assert_eq!(request.replica_id, CONSUMER_REPLICA_ID);
+        assert_eq!(request.max_bytes, 52428800);
+        assert_eq!((request.session_id, request.session_epoch), (0, 0));
+        assert_eq!(request.topics.len(), 1);
+        assert_eq!(
+            request.topics[0].topic_id,
+            Uuid::parse_str("7f2c9b1e-4d3a-4c8e-9a6b-1f0e2d3c4b5a").unwrap()
+        );
+        assert_eq!(request.topics[0].partitions, vec![partition(0, 0)]);
+        assert!(request.forgotten_topics_data.is_empty());
+
+        // Re-encoding writes the same bytes back
+        let mut encoded = BytesMut::new();
+        request.encode_versioned(16, &mut encoded).unwrap();
+        assert_eq!(hex::encode(&encoded), CONSOLE_CONSUMER_V16);
+        assert_eq!(encoded.len(), request.encoded_size(16));
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in FETCH_MIN_VERSION..=FETCH_MAX_VERSION {
+            let mut request = request(version);
+            if version < 9 {
+                for partition in &mut request.topics[0].partitions {
+                    partition.current_leader_epoch = -1;
+                }
+            }
+            if version >= 7 {
+                request.forgotten_topics_data.push(FetchForgottenTopic {
+                    topic: request.topics[0].topic.clone(),
+                    topic_id: request.topics[0].topic_id,
+                    partitions: vec![5, 6],
+                });
+            }
+            if version >= 11 {
+                request.rack_id = "rack-a".to_string();
+            }
+            let mut buffer = BytesMut::new();
+            request.encode_versioned(version, &mut buffer).unwrap();
+            assert_eq!(buffer.len(), request.encoded_size(version), "v{}", version);
+            let mut buffer = buffer.freeze();
+            assert_eq!(
+                FetchRequest::decode(&mut buffer, version).unwrap(),
+                request,
+                "v{}",
+                version
+            );
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_request_tagged_fields_roundtrip() {
+        let mut request = request(16);
+        request.cluster_id = Some("cluster".to_string());
+        request.replica_id = 2;
+        request.replica_epoch = 9;
+        let mut buffer = BytesMut::new();
+        request.encode_versioned(16, &mut buffer).unwrap();
+        assert_eq!(buffer.len(), request.encoded_size(16));
+        let mut buffer = buffer.freeze();
+        assert_eq!(FetchRequest::decode(&mut buffer, 16).unwrap(), request);
+    }
+
+    #[test]
+    fn test_unknown_topic_id_layout() {
+        let topic_id = Uuid::from_u128(0xdead);
+        let response = FetchResponse {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            session_id: 0,
+            responses: vec![FetchResponseTopic {
+                topic: String::new(),
+                topic_id,
+                partitions: vec![FetchResponsePartition::error(
+                    0,
+                    ErrorCode::UNKNOWN_TOPIC_ID,
+                )],
+            }],
+        };
+        let encoded = response.encode(16).unwrap();
+        let mut expected = vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02];
+        expected.extend_from_slice(topic_id.as_bytes());
+        expected.push(0x02);
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 100]);
+        expected.extend_from_slice(&[0xff; 24]); // no offsets
+        expected.push(0x00); // null aborted transactions
+        expected.extend_from_slice(&[0xff; 4]); // no preferred replica
+        expected.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+        assert_eq!(&encoded[..], &expected[..]);
+        assert_eq!(encoded.len(), response.encoded_size(16));
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        for version in FETCH_MIN_VERSION..=FETCH_MAX_VERSION {
+            let by_id = version >= FIRST_TOPIC_ID_VERSION;
+            let response = FetchResponse {
+                throttle_time_ms: 5,
+                error_code: ErrorCode::NONE,
+                session_id: if version >= 7 { 12 } else { 0 },
+                responses: vec![FetchResponseTopic {
+                    topic: if by_id {
+                        String::new()
+                    } else {
+                        "orders".to_string()
+                    },
+                    topic_id: if by_id {
+                        Uuid::from_u128(0x42)
+                    } else {
+                        Uuid::nil()
+                    },
+                    partitions: vec![
+                        FetchResponsePartition {
+                            partition_index: 0,
+                            error_code: ErrorCode::NONE,
+                            high_watermark: 10,
+                            last_stable_offset: 8,
+                            log_start_offset: if version >= 5 { 2 } else { -1 },
+                            aborted_transactions: Some(vec![FetchAbortedTransaction {
+                                producer_id: 7,
+                                first_offset: 8,
+                            }]),
+                            preferred_read_replica: -1,
+                            records: Some(Bytes::from_static(b"batches")),
+                        },
+                        FetchResponsePartition::error(1, ErrorCode::OFFSET_OUT_OF_RANGE),
+                    ],
+                }],
+            };
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut buffer = encoded.freeze();
+            assert_eq!(
+                FetchResponse::decode(&mut buffer, version).unwrap(),
+                response,
+                "v{}",
+                version
+            );
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_null_records_roundtrip() {
+        let mut partition = FetchResponsePartition::error(0, ErrorCode::NONE);
+        partition.records = None;
+        let response = FetchResponse {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            session_id: 0,
+            responses: vec![FetchResponseTopic {
+                topic: String::new(),
+                topic_id: Uuid::from_u128(1),
+                partitions: vec![partition],
+            }],
+        };
+        for version in [11, 16] {
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(encoded.len(), response.encoded_size(version));
+            let decoded = FetchResponse::decode(&mut encoded.freeze(), version).unwrap();
+            assert_eq!(decoded.responses[0].partitions[0].records, None);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_versions_are_rejected() {
+        assert!(FetchRequest::decode(&mut Bytes::new(), 3).is_err());
+        let response = FetchResponse {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            session_id: 0,
+            responses: Vec::new(),
+        };
+        assert!(response.encode(17).is_err());
+    }
+}
@@ -0,0 +1,508 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest FindCoordinator version we serve
+pub const FIND_COORDINATOR_MIN_VERSION: i16 = 0;
+
+/// Highest FindCoordinator version we serve
+pub const FIND_COORDINATOR_MAX_VERSION: i16 = 5;
+
+/// First flexible FindCoordinator version
+const FIRST_FLEXIBLE_VERSION: i16 = 3;
+
+/// First FindCoordinator version that looks up several keys at once
+pub const FIND_COORDINATOR_BATCHED_VERSION: i16 = 4;
+
+/// `key_type` of a consumer group id
+pub const COORDINATOR_TYPE_GROUP: i8 = 0;
+
+/// `key_type` of a transactional id
+pub const COORDINATOR_TYPE_TRANSACTION: i8 = 1;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "FindCoordinator",
+        version,
+        FIND_COORDINATOR_MIN_VERSION..=FIND_COORDINATOR_MAX_VERSION,
+    )
+}
+
+/// FindCoordinator request (API key 10)
+///
+/// - v0: a single group id in `key`
+/// - v1+: `key_type`
+/// - v3+: flexible
+/// - v4+: `coordinator_keys` replaces `key`
+///
+/// Before v1 the key type is always [`COORDINATOR_TYPE_GROUP`]. Tagged
+/// fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FindCoordinatorRequest {
+    /// v0-v3
+    pub key: String,
+    /// v1+
+    pub key_type: i8,
+    /// v4+
+    pub coordinator_keys: Vec<String>,
+}
+
+impl FindCoordinatorRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let key = if version < FIND_COORDINATOR_BATCHED_VERSION {
+            WireFormat::decode_flexible_string(buffer, flexible)?
+        } else {
+            String::new()
+        };
+        let key_type = if version >= 1 {
+            WireFormat::decode_i8(buffer)?
+        } else {
+            COORDINATOR_TYPE_GROUP
+        };
+        let coordinator_keys = if version >= FIND_COORDINATOR_BATCHED_VERSION {
+            WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                WireFormat::decode_flexible_string(buffer, flexible)
+            })?
+        } else {
+            Vec::new()
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            key,
+            key_type,
+            coordinator_keys,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    ///
+    /// Fails for v0 when `key_type` is not [`COORDINATOR_TYPE_GROUP`], as
+    /// that version has no way to carry it.
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// The keys looked up, whichever form the version uses
+    pub fn keys(&self, version: i16) -> Vec<String> {
+        if version >= FIND_COORDINATOR_BATCHED_VERSION {
+            self.coordinator_keys.clone()
+        } else {
+            vec![self.key.clone()]
+        }
+    }
+}
+
+impl ProtocolEncodeVersioned for FindCoordinatorRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version < FIND_COORDINATOR_BATCHED_VERSION {
+            WireFormat::encode_flexible_string(buffer, &self.key, flexible)?;
+        }
+        if version >= 1 {
+            buffer.put_i8(self.key_type);
+        } else if self.key_type != COORDINATOR_TYPE_GROUP {
+            return Err(ProtocolError::SerializationError(
+                "FindCoordinator v0 only looks up groups".to_string(),
+            ));
+        }
+        if version >= FIND_COORDINATOR_BATCHED_VERSION {
+            WireFormat::encode_flexible_array(
+                buffer,
+                &self.coordinator_keys,
+                flexible,
+                |buffer, key| WireFormat::encode_flexible_string(buffer, key, flexible),
+            )?;
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let key = if version < FIND_COORDINATOR_BATCHED_VERSION {
+            WireFormat::flexible_string_size(&self.key, flexible)
+        } else {
+            0
+        };
+        let key_type = if version >= 1 { 1 } else { 0 };
+        let coordinator_keys = if version >= FIND_COORDINATOR_BATCHED_VERSION {
+            WireFormat::flexible_array_length_size(Some(self.coordinator_keys.len()), flexible)
+                + self
+                    .coordinator_keys
+                    .iter()
+                    .map(|key| WireFormat::flexible_string_size(key, flexible))
+                    .sum::<usize>()
+        } else {
+            0
+        };
+        key + key_type + coordinator_keys + usize::from(flexible)
+    }
+}
+
+impl ProtocolDecodeVersioned for FindCoordinatorRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// FindCoordinator response (API key 10)
+///
+/// - v0: error code and the coordinator's `node_id`, `host` and `port`
+/// - v1+: `throttle_time_ms` and `error_message`
+/// - v3+: flexible
+/// - v4+: one entry per key in `coordinators` instead of the top-level
+///   fields
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FindCoordinatorResponse {
+    /// v1+
+    pub throttle_time_ms: i32,
+    /// v0-v3
+    pub error_code: ErrorCode,
+    /// v1-v3
+    pub error_message: Option<String>,
+    /// v0-v3
+    pub node_id: i32,
+    /// v0-v3
+    pub host: String,
+    /// v0-v3
+    pub port: i32,
+    /// v4+
+    pub coordinators: Vec<Coordinator>,
+}
+
+/// The coordinator of one key in a [`FindCoordinatorResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coordinator {
+    pub key: String,
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+    pub error_code: ErrorCode,
+    pub error_message: Option<String>,
+}
+
+impl Coordinator {
+    /// A failed lookup, with no coordinator
+    pub fn error(
+        key: String,
+        error_code: impl Into<ErrorCode>,
+        error_message: Option<String>,
+    ) -> Self {
+        Self {
+            key,
+            node_id: -1,
+            host: String::new(),
+            port: -1,
+            error_code: error_code.into(),
+            error_message,
+        }
+    }
+}
+
+impl FindCoordinatorResponse {
+    /// A response to a v0-v3 request, from the lookup of its single key
+    pub fn single(coordinator: Coordinator) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code: coordinator.error_code,
+            error_message: coordinator.error_message,
+            node_id: coordinator.node_id,
+            host: coordinator.host,
+            port: coordinator.port,
+            coordinators: Vec::new(),
+        }
+    }
+
+    /// A response to a v4+ request
+    pub fn batched(coordinators: Vec<Coordinator>) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code: ErrorCode::NONE,
+            error_message: None,
+            node_id: -1,
+            host: String::new(),
+            port: -1,
+            coordinators,
+        }
+    }
+
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 1 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        if version >= FIND_COORDINATOR_BATCHED_VERSION {
+            let coordinators = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let coordinator = Coordinator {
+                    key: WireFormat::decode_flexible_string(buffer, flexible)?,
+                    node_id: WireFormat::decode_i32(buffer)?,
+                    host: WireFormat::decode_flexible_string(buffer, flexible)?,
+                    port: WireFormat::decode_i32(buffer)?,
+                    error_code: ErrorCode::from_wire(WireFormat::decode_i16(buffer)?),
+                    error_message: WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(coordinator)
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            return Ok(Self {
+                throttle_time_ms,
+                ..Self::batched(coordinators)
+            });
+        }
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        let error_message = if version >= 1 {
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+        } else {
+            None
+        };
+        let node_id = WireFormat::decode_i32(buffer)?;
+        let host = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let port = WireFormat::decode_i32(buffer)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            error_code,
+            error_message,
+            node_id,
+            host,
+            port,
+            coordinators: Vec::new(),
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for FindCoordinatorResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 1 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        if version >= FIND_COORDINATOR_BATCHED_VERSION {
+            WireFormat::encode_flexible_array(
+                buffer,
+                &self.coordinators,
+                flexible,
+                |buffer, coordinator| {
+                    WireFormat::encode_flexible_string(buffer, &coordinator.key, flexible)?;
+                    buffer.put_i32(coordinator.node_id);
+                    WireFormat::encode_flexible_string(buffer, &coordinator.host, flexible)?;
+                    buffer.put_i32(coordinator.port);
+                    buffer.put_i16(coordinator.error_code.code());
+                    WireFormat::encode_flexible_nullable_string(
+                        buffer,
+                        coordinator.error_message.as_deref(),
+                        flexible,
+                    )?;
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+        } else {
+            buffer.put_i16(self.error_code.code());
+            if version >= 1 {
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    self.error_message.as_deref(),
+                    flexible,
+                )?;
+            }
+            buffer.put_i32(self.node_id);
+            WireFormat::encode_flexible_string(buffer, &self.host, flexible)?;
+            buffer.put_i32(self.port);
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let throttle_time = if version >= 1 { 4 } else { 0 };
+        let body = if version >= FIND_COORDINATOR_BATCHED_VERSION {
+            WireFormat::flexible_array_length_size(Some(self.coordinators.len()), flexible)
+                + self
+                    .coordinators
+                    .iter()
+                    .map(|coordinator| {
+                        WireFormat::flexible_string_size(&coordinator.key, flexible)
+                            + 4
+                            + WireFormat::flexible_string_size(&coordinator.host, flexible)
+                            + 4
+                            + 2
+                            + WireFormat::flexible_nullable_string_size(
+                                coordinator.error_message.as_deref(),
+                                flexible,
+                            )
+                            + tags
+                    })
+                    .sum::<usize>()
+        } else {
+            let error_message = if version >= 1 {
+                WireFormat::flexible_nullable_string_size(self.error_message.as_deref(), flexible)
+            } else {
+                0
+            };
+            2 + error_message + 4 + WireFormat::flexible_string_size(&self.host, flexible) + 4
+        };
+        throttle_time + body + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for FindCoordinatorResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> FindCoordinatorRequest {
+        FindCoordinatorRequest {
+            key: "billing".to_string(),
+            key_type: COORDINATOR_TYPE_GROUP,
+            coordinator_keys: vec!["billing".to_string(), "audit".to_string()],
+        }
+    }
+
+    fn coordinator(key: &str) -> Coordinator {
+        Coordinator {
+            key: key.to_string(),
+            node_id: 1,
+            host: "localhost".to_string(),
+            port: 9092,
+            error_code: ErrorCode::NONE,
+            error_message: None,
+        }
+    }
+
+    #[test]
+    fn test_v1_layout() {
+        let expected = [
+            "000762696c6c696e67", // key
+            "00",                 // key_type
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(1).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_v4_layout() {
+        let expected = [
+            "00",               // key_type
+            "03",               // coordinator_keys
+            "0862696c6c696e67", // billing
+            "06617564697400",   // audit, tags
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(4).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in FIND_COORDINATOR_MIN_VERSION..=FIND_COORDINATOR_MAX_VERSION {
+            let request = request();
+            let mut expected = request.clone();
+            if version < FIND_COORDINATOR_BATCHED_VERSION {
+                expected.coordinator_keys.clear();
+            } else {
+                expected.key.clear();
+            }
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            let decoded = FindCoordinatorRequest::decode(&mut encoded, version).unwrap();
+            assert_eq!(decoded, expected, "v{}", version);
+            assert!(encoded.is_empty());
+            let keys = if version < FIND_COORDINATOR_BATCHED_VERSION {
+                vec!["billing"]
+            } else {
+                vec!["billing", "audit"]
+            };
+            assert_eq!(decoded.keys(version), keys, "v{}", version);
+        }
+    }
+
+    #[test]
+    fn test_v0_cannot_carry_transaction_keys() {
+        let request = FindCoordinatorRequest {
+            key_type: COORDINATOR_TYPE_TRANSACTION,
+            ..request()
+        };
+        assert!(request.encode(0).is_err());
+        assert!(request.encode(1).is_ok());
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        for version in FIND_COORDINATOR_MIN_VERSION..=FIND_COORDINATOR_MAX_VERSION {
+            let response = if version < FIND_COORDINATOR_BATCHED_VERSION {
+                FindCoordinatorResponse::single(Coordinator::error(
+                    "billing".to_string(),
+                    ErrorCode::INVALID_REQUEST,
+                    Some("unknown key type".to_string()),
+                ))
+            } else {
+                FindCoordinatorResponse::batched(vec![
+                    coordinator("billing"),
+                    Coordinator::error("audit".to_string(), ErrorCode::INVALID_REQUEST, None),
+                ])
+            };
+            let response = FindCoordinatorResponse {
+                throttle_time_ms: 6,
+                ..response
+            };
+            let mut expected = response.clone();
+            if version < 1 {
+                expected.throttle_time_ms = 0;
+                expected.error_message = None;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                FindCoordinatorResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v6_is_rejected() {
+        assert!(request().encode(6).is_err());
+    }
+}
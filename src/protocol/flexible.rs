@@ -0,0 +1,105 @@
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::ApiKey;
+use bytes::Bytes;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Largest body decoded a second time when looking for a flexibility
+/// mismatch; bigger bodies, such as large produces, get the plain error
+pub const MAX_MISMATCH_PROBE_BYTES: usize = 64 * 1024;
+
+static FLEXIBILITY_MISMATCHES: AtomicU64 = AtomicU64::new(0);
+
+/// First flexible version of an API, or `None` when we do not know it
+///
+/// Flexible versions use request header v2 and compact encodings with tag
+/// sections in the body.
+pub fn first_flexible_version(api_key: i16) -> Option<i16> {
+    match ApiKey::try_from(api_key).ok()? {
+        ApiKey::Produce => Some(9),
+        ApiKey::Fetch => Some(12),
+        ApiKey::ListOffsets => Some(6),
+        ApiKey::Metadata => Some(9),
+        ApiKey::ControlledShutdown => Some(3),
+        ApiKey::OffsetCommit => Some(8),
+        ApiKey::OffsetFetch => Some(6),
+        ApiKey::FindCoordinator => Some(3),
+        ApiKey::JoinGroup => Some(6),
+        ApiKey::Heartbeat => Some(4),
+        ApiKey::LeaveGroup => Some(4),
+        ApiKey::SyncGroup => Some(4),
+        ApiKey::DescribeGroups => Some(5),
+        ApiKey::ListGroups => Some(3),
+        // No SaslHandshake version is flexible
+        ApiKey::SaslHandshake => Some(i16::MAX),
+        ApiKey::ApiVersions => Some(3),
+        ApiKey::CreateTopics => Some(5),
+        ApiKey::DeleteTopics => Some(4),
+        ApiKey::InitProducerId => Some(2),
+        ApiKey::DescribeConfigs => Some(4),
+        ApiKey::SaslAuthenticate => Some(2),
+        ApiKey::IncrementalAlterConfigs => Some(1),
+        ApiKey::DescribeCluster => Some(0),
+        ApiKey::DescribeTopicPartitions => Some(0),
+        ApiKey::LeaderAndIsr
+        | ApiKey::StopReplica
+        | ApiKey::UpdateMetadata
+        | ApiKey::WriteTxnMarkers => None,
+    }
+}
+
+/// Whether `version` of `api_key` is flexible, if known
+pub fn is_flexible(api_key: i16, version: i16) -> Option<bool> {
+    first_flexible_version(api_key).map(|first| version >= first)
+}
+
+/// Number of bodies rejected as a header/body flexibility mismatch
+pub fn flexibility_mismatch_count() -> u64 {
+    FLEXIBILITY_MISMATCHES.load(Ordering::Relaxed)
+}
+
+/// Decodes a request body in the mode its header version implies
+///
+/// `decode` is called with the flexibility to use and must consume the
+/// whole body. When that fails for a small body and decoding in the other
+/// mode succeeds, the body was most likely encoded for the wrong header
+/// version; that is reported as [`ProtocolError::FlexibilityMismatch`] and
+/// counted, instead of the parse error the first attempt produced.
+pub fn decode_body<T, F>(
+    api_key: i16,
+    api_version: i16,
+    flexible: bool,
+    body: &mut Bytes,
+    decode: F,
+) -> ProtocolResult<T>
+where
+    F: Fn(&mut Bytes, bool) -> ProtocolResult<T>,
+{
+    let decode_all = |flexible: bool| {
+        let mut buffer = body.clone();
+        let value = decode(&mut buffer, flexible)?;
+        if !buffer.is_empty() {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "{} trailing bytes after request body",
+                buffer.len()
+            )));
+        }
+        Ok(value)
+    };
+
+    let error = match decode_all(flexible) {
+        Ok(value) => {
+            body.clear();
+            return Ok(value);
+        }
+        Err(error) => error,
+    };
+    if body.len() <= MAX_MISMATCH_PROBE_BYTES && decode_all(!flexible).is_ok() {
+        FLEXIBILITY_MISMATCHES.fetch_add(1, Ordering::Relaxed);
+        return Err(ProtocolError::FlexibilityMismatch {
+            api_key,
+            api_version,
+            header_flexible: flexible,
+        });
+    }
+    Err(error)
+}
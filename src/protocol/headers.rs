@@ -1,6 +1,15 @@
-use crate::protocol::encoding::{ProtocolDecode, ProtocolEncode, WireFormat};
+use crate::protocol::api_key::ApiKey;
+use crate::protocol::decode_limits;
+use crate::protocol::encoding::{
+    self, ProtocolDecode, ProtocolDecodeVersioned, ProtocolEncode, ProtocolEncodeVersioned,
+    WireFormat,
+};
 use crate::protocol::errors::{ProtocolError, ProtocolResult};
-use bytes::{Buf, BufMut, BytesMut};
+use crate::protocol::flexible;
+use crate::protocol::tagged_fields::TaggedFields;
+use crate::protocol::trace::DecodeCursor;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::borrow::Cow;
 
 /// Kafka Response Header Version 0
 ///
@@ -22,32 +31,234 @@ impl ProtocolEncode for ResponseHeaderV0 {
     /// Encodes the header to bytes following Kafka wire protocol
     fn encode(&self) -> ProtocolResult<BytesMut> {
         let mut buffer = BytesMut::with_capacity(4); // 4 bytes per i32 field
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
 
+    /// Appends the header to a response being built in place
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
         buffer.put_i32(self.correlation_id);
 
-        Ok(buffer)
+        Ok(())
+    }
+
+    fn encoded_size(&self) -> usize {
+        4
     }
 }
 
-impl ProtocolDecode for ResponseHeaderV0 {
-    fn decode(buffer: &mut BytesMut) -> ProtocolResult<Self> {
-        let correlation_id = WireFormat::decode_i32(buffer)?;
+impl ResponseHeaderV0 {
+    /// Decodes the header from a cursor, which may be tracing
+    pub fn decode_from(cursor: &mut DecodeCursor<'_>) -> ProtocolResult<Self> {
+        let correlation_id = cursor.field("correlation_id", WireFormat::decode_i32)?;
 
         Ok(Self { correlation_id })
     }
 }
 
+impl ProtocolDecode for ResponseHeaderV0 {
+    fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        Self::decode_from(&mut DecodeCursor::new(buffer))
+    }
+}
+
+/// Kafka Response Header Version 1
+///
+/// The correlation id followed by a tag section, as used by responses to
+/// flexible requests other than ApiVersions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseHeaderV1 {
+    pub correlation_id: i32,
+    pub tagged_fields: TaggedFields,
+}
+
+impl ResponseHeaderV1 {
+    /// Creates a new response header with an empty tag section
+    pub fn new(correlation_id: i32) -> Self {
+        Self {
+            correlation_id,
+            tagged_fields: TaggedFields::new(),
+        }
+    }
+
+    /// Decodes the header from a cursor, which may be tracing
+    pub fn decode_from(cursor: &mut DecodeCursor<'_>) -> ProtocolResult<Self> {
+        let correlation_id = cursor.field("correlation_id", WireFormat::decode_i32)?;
+        let tagged_fields = cursor.tagged_fields()?;
+
+        Ok(Self {
+            correlation_id,
+            tagged_fields,
+        })
+    }
+}
+
+impl ProtocolEncode for ResponseHeaderV1 {
+    fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size());
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        buffer.put_i32(self.correlation_id);
+        self.tagged_fields.encode_into(buffer)
+    }
+
+    fn encoded_size(&self) -> usize {
+        4 + self.tagged_fields.encoded_size()
+    }
+}
+
+impl ProtocolDecode for ResponseHeaderV1 {
+    fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        Self::decode_from(&mut DecodeCursor::new(buffer))
+    }
+}
+
+/// Response header version used by `api_version` of `api_key`
+///
+/// Flexible versions reply with header v1, except ApiVersions, which
+/// always replies with header v0 so a client that sent a version the
+/// broker does not support can still read the error. As for requests,
+/// APIs whose flexible versions we do not know are assumed flexible.
+///
+/// # Examples
+/// ```
+/// use codecrafters_kafka::protocol::response_header_version;
+///
+/// assert_eq!(response_header_version(18, 4), 0); // ApiVersions v4
+/// assert_eq!(response_header_version(75, 0), 1); // DescribeTopicPartitions v0
+/// assert_eq!(response_header_version(0, 2), 0); // Produce v2
+/// ```
+pub fn response_header_version(api_key: i16, api_version: i16) -> i16 {
+    if api_key == ApiKey::ApiVersions.code() {
+        return 0;
+    }
+    match flexible::is_flexible(api_key, api_version) {
+        Some(false) => 0,
+        Some(true) | None => 1,
+    }
+}
+
+/// A response header at whichever version its request calls for
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseHeader {
+    V0(ResponseHeaderV0),
+    V1(ResponseHeaderV1),
+}
+
+impl ResponseHeader {
+    /// The header answering `api_version` of `api_key`, see
+    /// [`response_header_version`]
+    pub fn for_api(api_key: i16, api_version: i16, correlation_id: i32) -> Self {
+        match response_header_version(api_key, api_version) {
+            0 => Self::V0(ResponseHeaderV0::new(correlation_id)),
+            _ => Self::V1(ResponseHeaderV1::new(correlation_id)),
+        }
+    }
+
+    /// The header version this header is laid out at
+    pub fn version(&self) -> i16 {
+        match self {
+            Self::V0(_) => 0,
+            Self::V1(_) => 1,
+        }
+    }
+
+    pub fn correlation_id(&self) -> i32 {
+        match self {
+            Self::V0(header) => header.correlation_id,
+            Self::V1(header) => header.correlation_id,
+        }
+    }
+}
+
+impl ProtocolEncode for ResponseHeader {
+    fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size());
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        match self {
+            Self::V0(header) => header.encode_to(buffer),
+            Self::V1(header) => header.encode_to(buffer),
+        }
+    }
+
+    fn encoded_size(&self) -> usize {
+        match self {
+            Self::V0(header) => header.encoded_size(),
+            Self::V1(header) => header.encoded_size(),
+        }
+    }
+}
+
+/// Decodes a request header's client id
+///
+/// An id longer than the installed
+/// [`max_client_id_length`](decode_limits::DecodeLimits::max_client_id_length)
+/// is rejected, and control characters are escaped, since the id goes
+/// straight into log fields and spans.
+fn decode_client_id(buffer: &mut Bytes) -> ProtocolResult<Option<String>> {
+    let Some(client_id) = WireFormat::decode_nullable_string(buffer)? else {
+        return Ok(None);
+    };
+    decode_limits::check_client_id_length(client_id.len())?;
+    Ok(Some(match sanitize_client_id(&client_id) {
+        Cow::Borrowed(_) => client_id,
+        Cow::Owned(sanitized) => sanitized,
+    }))
+}
+
+/// Escapes the control characters in a client id, such as the newlines
+/// a forged log line would need
+///
+/// # Examples
+/// ```
+/// use codecrafters_kafka::protocol::headers::sanitize_client_id;
+///
+/// assert_eq!(sanitize_client_id("producer-1"), "producer-1");
+/// assert_eq!(sanitize_client_id("a\nb"), "a\\nb");
+/// ```
+pub fn sanitize_client_id(client_id: &str) -> Cow<'_, str> {
+    if !client_id.chars().any(char::is_control) {
+        return Cow::Borrowed(client_id);
+    }
+    let mut sanitized = String::with_capacity(client_id.len() + 8);
+    for c in client_id.chars() {
+        if c.is_control() {
+            sanitized.extend(c.escape_default());
+        } else {
+            sanitized.push(c);
+        }
+    }
+    Cow::Owned(sanitized)
+}
+
+/// Highest request header version we read and write
+pub const REQUEST_HEADER_MAX_VERSION: i16 = 2;
+
 /// Kafka Request Header Version 2
 ///
 /// This represents the header structure for Kafka protocol requests version 2.
-/// It includes support for nullable client_id and tagged fields.
+/// It includes support for nullable client_id and tagged fields. Through
+/// [`ProtocolEncodeVersioned`] the same struct also reads and writes the
+/// older layouts:
+/// - v0: api key, api version and correlation id
+/// - v1: adds the nullable client id
+/// - v2: adds the tag section
 #[derive(Debug, Clone, PartialEq)]
 pub struct RequestHeaderV2 {
     pub request_api_key: i16,
     pub request_api_version: i16,
     pub correlation_id: i32,
     pub client_id: Option<String>, // NULLABLE_STRING
-                                   // Tagged fields are currently not fully implemented
+    /// Header v2 tag section, kept so unknown tags survive re-encoding;
+    /// always empty for header v1
+    pub tagged_fields: TaggedFields,
 }
 
 impl RequestHeaderV2 {
@@ -63,6 +274,7 @@ impl RequestHeaderV2 {
             request_api_version,
             correlation_id,
             client_id,
+            tagged_fields: TaggedFields::new(),
         }
     }
 
@@ -81,6 +293,77 @@ impl RequestHeaderV2 {
         )
     }
 
+    /// Decodes a header that is followed by a non-flexible request body
+    ///
+    /// Non-flexible requests use header v1, which has no tag section after
+    /// the client id, so the body starts immediately after it.
+    pub fn decode_without_tagged_fields(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        Self::decode_from(&mut DecodeCursor::new(buffer), false)
+    }
+
+    /// Decodes the header from a cursor, which may be tracing
+    ///
+    /// `tagged` selects header v2, whose tag section follows the client id.
+    pub fn decode_from(cursor: &mut DecodeCursor<'_>, tagged: bool) -> ProtocolResult<Self> {
+        // Ensure we have at least the minimum required bytes for the fixed fields
+        if cursor.remaining() < 8 {
+            return Err(ProtocolError::insufficient_bytes(8, cursor.remaining())
+                .at(cursor.position(), "request_api_key"));
+        }
+
+        let request_api_key = cursor.field("request_api_key", WireFormat::decode_i16)?;
+        let request_api_version = cursor.field("request_api_version", WireFormat::decode_i16)?;
+        let correlation_id = cursor.field("correlation_id", WireFormat::decode_i32)?;
+        let client_id = cursor.field("client_id", decode_client_id)?;
+
+        // Tolerate a missing tag section at the very end of the frame, as
+        // sent by clients that treat it as optional
+        let tagged_fields = if tagged && cursor.remaining() >= 1 {
+            cursor.tagged_fields()?
+        } else {
+            TaggedFields::new()
+        };
+
+        Ok(Self {
+            request_api_key,
+            request_api_version,
+            correlation_id,
+            client_id,
+            tagged_fields,
+        })
+    }
+
+    /// Decodes only the fields every header version shares
+    ///
+    /// Used for ApiVersions, which must be answered even at versions whose
+    /// header layout this broker does not know. The api key, version and
+    /// correlation id are required; a client id that does not parse is
+    /// treated as absent and nothing after it is consumed.
+    pub fn decode_lenient(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        if buffer.remaining() < 8 {
+            return Err(ProtocolError::insufficient_bytes(8, buffer.remaining()));
+        }
+
+        let request_api_key = WireFormat::decode_i16(buffer)?;
+        let request_api_version = WireFormat::decode_i16(buffer)?;
+        let correlation_id = WireFormat::decode_i32(buffer)?;
+        let mut rest = buffer.clone();
+        let client_id = match decode_client_id(&mut rest) {
+            Ok(client_id) => {
+                *buffer = rest;
+                client_id
+            }
+            Err(_) => None,
+        };
+        Ok(Self {
+            request_api_key,
+            request_api_version,
+            correlation_id,
+            client_id,
+            tagged_fields: TaggedFields::new(),
+        })
+    }
+
     /// Convenience method to create a header without a client ID
     pub fn without_client_id(
         request_api_key: i16,
@@ -89,53 +372,325 @@ impl RequestHeaderV2 {
     ) -> Self {
         Self::new(request_api_key, request_api_version, correlation_id, None)
     }
+
+    /// The API the request is for, or the raw api key when it is unknown
+    pub fn api_key(&self) -> Result<ApiKey, i16> {
+        ApiKey::try_from(self.request_api_key)
+    }
 }
 
 impl ProtocolEncode for RequestHeaderV2 {
     fn encode(&self) -> ProtocolResult<BytesMut> {
-        let mut buffer = BytesMut::new();
+        let mut buffer = BytesMut::with_capacity(ProtocolEncode::encoded_size(self));
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        self.encode_versioned(2, buffer)
+    }
+
+    fn encoded_size(&self) -> usize {
+        ProtocolEncodeVersioned::encoded_size(self, 2)
+    }
+}
 
+impl ProtocolEncodeVersioned for RequestHeaderV2 {
+    /// Appends the header as laid out at header `version`
+    ///
+    /// The client id is dropped below v1 and the tag section below v2.
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        encoding::check_version("request header", version, 0..=REQUEST_HEADER_MAX_VERSION)?;
         buffer.put_i16(self.request_api_key);
         buffer.put_i16(self.request_api_version);
         buffer.put_i32(self.correlation_id);
 
-        WireFormat::encode_nullable_string(&mut buffer, self.client_id.as_deref())?;
+        if version >= 1 {
+            WireFormat::encode_nullable_string(buffer, self.client_id.as_deref())?;
+        }
+        if version >= 2 {
+            self.tagged_fields.encode_into(buffer)?;
+        }
 
-        // Empty tag section (tagged fields not implemented yet)
-        buffer.put_u8(0);
+        Ok(())
+    }
 
-        Ok(buffer)
+    fn encoded_size(&self, version: i16) -> usize {
+        let mut size = 2 + 2 + 4;
+        if version >= 1 {
+            size += WireFormat::nullable_string_size(self.client_id.as_deref());
+        }
+        if version >= 2 {
+            size += self.tagged_fields.encoded_size();
+        }
+        size
     }
 }
 
-impl ProtocolDecode for RequestHeaderV2 {
-    fn decode(buffer: &mut BytesMut) -> ProtocolResult<Self> {
-        // Ensure we have at least the minimum required bytes for the fixed fields
-        if buffer.remaining() < 8 {
-            return Err(ProtocolError::insufficient_bytes(8, buffer.remaining()));
+impl ProtocolDecodeVersioned for RequestHeaderV2 {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        encoding::check_version("request header", version, 0..=REQUEST_HEADER_MAX_VERSION)?;
+        if version >= 1 {
+            return Self::decode_from(&mut DecodeCursor::new(buffer), version >= 2);
         }
+        let mut cursor = DecodeCursor::new(buffer);
+        Ok(Self::without_client_id(
+            cursor.field("request_api_key", WireFormat::decode_i16)?,
+            cursor.field("request_api_version", WireFormat::decode_i16)?,
+            cursor.field("correlation_id", WireFormat::decode_i32)?,
+        ))
+    }
+}
 
-        let request_api_key = WireFormat::decode_i16(buffer)?;
-        let request_api_version = WireFormat::decode_i16(buffer)?;
-        let correlation_id = WireFormat::decode_i32(buffer)?;
+impl ProtocolDecode for RequestHeaderV2 {
+    fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        Self::decode_from(&mut DecodeCursor::new(buffer), true)
+    }
+}
 
-        // Decode the nullable client_id
-        let client_id = WireFormat::decode_nullable_string(buffer)?;
+/// Kafka Request Header Version 0
+///
+/// Api key, api version and correlation id only. Used by nothing but
+/// ControlledShutdown v0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestHeaderV0 {
+    pub request_api_key: i16,
+    pub request_api_version: i16,
+    pub correlation_id: i32,
+}
 
-        // Skip tagged fields for now (assuming empty tag section with 0 length)
-        if buffer.remaining() >= 1 {
-            let _tagged_fields = WireFormat::decode_u8(buffer)?; // Usually 0 for no tags
+impl RequestHeaderV0 {
+    /// Creates a new request header
+    pub fn new(request_api_key: i16, request_api_version: i16, correlation_id: i32) -> Self {
+        Self {
+            request_api_key,
+            request_api_version,
+            correlation_id,
         }
+    }
 
+    /// Decodes the header from a cursor, which may be tracing
+    pub fn decode_from(cursor: &mut DecodeCursor<'_>) -> ProtocolResult<Self> {
         Ok(Self {
+            request_api_key: cursor.field("request_api_key", WireFormat::decode_i16)?,
+            request_api_version: cursor.field("request_api_version", WireFormat::decode_i16)?,
+            correlation_id: cursor.field("correlation_id", WireFormat::decode_i32)?,
+        })
+    }
+}
+
+impl ProtocolEncode for RequestHeaderV0 {
+    fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size());
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        buffer.put_i16(self.request_api_key);
+        buffer.put_i16(self.request_api_version);
+        buffer.put_i32(self.correlation_id);
+        Ok(())
+    }
+
+    fn encoded_size(&self) -> usize {
+        2 + 2 + 4
+    }
+}
+
+impl ProtocolDecode for RequestHeaderV0 {
+    fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        Self::decode_from(&mut DecodeCursor::new(buffer))
+    }
+}
+
+/// Kafka Request Header Version 1
+///
+/// Adds the nullable client id to v0. Used by every non-flexible request
+/// except ControlledShutdown v0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestHeaderV1 {
+    pub request_api_key: i16,
+    pub request_api_version: i16,
+    pub correlation_id: i32,
+    pub client_id: Option<String>, // NULLABLE_STRING
+}
+
+impl RequestHeaderV1 {
+    /// Creates a new request header
+    pub fn new(
+        request_api_key: i16,
+        request_api_version: i16,
+        correlation_id: i32,
+        client_id: Option<String>,
+    ) -> Self {
+        Self {
             request_api_key,
             request_api_version,
             correlation_id,
             client_id,
+        }
+    }
+
+    /// Decodes the header from a cursor, which may be tracing
+    pub fn decode_from(cursor: &mut DecodeCursor<'_>) -> ProtocolResult<Self> {
+        Ok(Self {
+            request_api_key: cursor.field("request_api_key", WireFormat::decode_i16)?,
+            request_api_version: cursor.field("request_api_version", WireFormat::decode_i16)?,
+            correlation_id: cursor.field("correlation_id", WireFormat::decode_i32)?,
+            client_id: cursor.field("client_id", decode_client_id)?,
         })
     }
 }
 
+impl ProtocolEncode for RequestHeaderV1 {
+    fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size());
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        buffer.put_i16(self.request_api_key);
+        buffer.put_i16(self.request_api_version);
+        buffer.put_i32(self.correlation_id);
+        WireFormat::encode_nullable_string(buffer, self.client_id.as_deref())
+    }
+
+    fn encoded_size(&self) -> usize {
+        2 + 2 + 4 + WireFormat::nullable_string_size(self.client_id.as_deref())
+    }
+}
+
+impl ProtocolDecode for RequestHeaderV1 {
+    fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        Self::decode_from(&mut DecodeCursor::new(buffer))
+    }
+}
+
+/// A request header at whichever version its API and version call for
+///
+/// The version is not on the wire; it follows from the api key and
+/// version, see [`RequestHeader::version_for`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestHeader {
+    V0(RequestHeaderV0),
+    V1(RequestHeaderV1),
+    V2(RequestHeaderV2),
+}
+
+impl RequestHeader {
+    /// Request header version used by `api_version` of `api_key`
+    ///
+    /// Flexible versions use v2 and the rest v1, except ControlledShutdown
+    /// v0, which predates the client id. APIs whose flexible versions we do
+    /// not know are assumed to be flexible, as any API added since KIP-482
+    /// is.
+    ///
+    /// # Examples
+    /// ```
+    /// use codecrafters_kafka::protocol::headers::RequestHeader;
+    ///
+    /// assert_eq!(RequestHeader::version_for(18, 2), 1); // ApiVersions v2
+    /// assert_eq!(RequestHeader::version_for(18, 3), 2); // ApiVersions v3
+    /// assert_eq!(RequestHeader::version_for(7, 0), 0); // ControlledShutdown v0
+    /// ```
+    pub fn version_for(api_key: i16, api_version: i16) -> i16 {
+        if api_key == ApiKey::ControlledShutdown.code() && api_version == 0 {
+            return 0;
+        }
+        match flexible::is_flexible(api_key, api_version) {
+            Some(false) => 1,
+            Some(true) | None => 2,
+        }
+    }
+
+    /// Decodes the header of a request for `api_version` of `api_key`
+    ///
+    /// The caller peeks the api key and version, which every header
+    /// version starts with; they are decoded again as part of the header.
+    pub fn decode_for(api_key: i16, api_version: i16, buffer: &mut Bytes) -> ProtocolResult<Self> {
+        let mut cursor = DecodeCursor::new(buffer);
+        match Self::version_for(api_key, api_version) {
+            0 => RequestHeaderV0::decode_from(&mut cursor).map(Self::V0),
+            1 => RequestHeaderV1::decode_from(&mut cursor).map(Self::V1),
+            _ => RequestHeaderV2::decode_from(&mut cursor, true).map(Self::V2),
+        }
+    }
+
+    /// The header version this header is laid out at
+    pub fn version(&self) -> i16 {
+        match self {
+            Self::V0(_) => 0,
+            Self::V1(_) => 1,
+            Self::V2(_) => 2,
+        }
+    }
+
+    /// The API the request is for, or the raw api key when it is unknown
+    pub fn api_key(&self) -> Result<ApiKey, i16> {
+        ApiKey::try_from(self.api_key_code())
+    }
+
+    /// The api key as sent
+    pub fn api_key_code(&self) -> i16 {
+        match self {
+            Self::V0(header) => header.request_api_key,
+            Self::V1(header) => header.request_api_key,
+            Self::V2(header) => header.request_api_key,
+        }
+    }
+
+    pub fn api_version(&self) -> i16 {
+        match self {
+            Self::V0(header) => header.request_api_version,
+            Self::V1(header) => header.request_api_version,
+            Self::V2(header) => header.request_api_version,
+        }
+    }
+
+    pub fn correlation_id(&self) -> i32 {
+        match self {
+            Self::V0(header) => header.correlation_id,
+            Self::V1(header) => header.correlation_id,
+            Self::V2(header) => header.correlation_id,
+        }
+    }
+
+    /// The client id, always `None` for header v0
+    pub fn client_id(&self) -> Option<&str> {
+        match self {
+            Self::V0(_) => None,
+            Self::V1(header) => header.client_id.as_deref(),
+            Self::V2(header) => header.client_id.as_deref(),
+        }
+    }
+}
+
+impl ProtocolEncode for RequestHeader {
+    fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size());
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        match self {
+            Self::V0(header) => header.encode_to(buffer),
+            Self::V1(header) => header.encode_to(buffer),
+            Self::V2(header) => header.encode_to(buffer),
+        }
+    }
+
+    fn encoded_size(&self) -> usize {
+        match self {
+            Self::V0(header) => header.encoded_size(),
+            Self::V1(header) => header.encoded_size(),
+            Self::V2(header) => ProtocolEncode::encoded_size(header),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -158,26 +713,237 @@ mod tests {
         let bytes = encoded.as_ref();
 
         // Check correlation_id (7 in big-endian)
-        assert_eq!(&bytes[4..8], &[0, 0, 0, 7]);
+        assert_eq!(&bytes[0..4], &[0, 0, 0, 7]);
     }
 
     #[test]
     fn test_response_header_v0_roundtrip() {
         let original = ResponseHeaderV0::new(42);
         let encoded = original.encode().unwrap();
-        let mut buffer = encoded;
+        let mut buffer = encoded.freeze();
         let decoded = ResponseHeaderV0::decode(&mut buffer).unwrap();
 
         assert_eq!(original, decoded);
     }
 
+    #[test]
+    fn test_response_header_v1_roundtrip() {
+        let header = ResponseHeaderV1::new(42);
+        assert_eq!(&header.encode().unwrap()[..], &[0, 0, 0, 42, 0]);
+
+        let mut tagged = ResponseHeaderV1::new(7);
+        tagged.tagged_fields.insert(5, vec![0xaa, 0xbb]);
+        for original in [header, tagged] {
+            let encoded = original.encode().unwrap();
+            assert_eq!(encoded.len(), original.encoded_size());
+            let mut buffer = encoded.freeze();
+            assert_eq!(ResponseHeaderV1::decode(&mut buffer).unwrap(), original);
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_header_follows_the_api() {
+        // ApiVersions stays on v0 even at flexible versions
+        let header = ResponseHeader::for_api(ApiKey::ApiVersions.code(), 4, 9);
+        assert_eq!(header.version(), 0);
+        assert_eq!(&header.encode().unwrap()[..], &[0, 0, 0, 9]);
+
+        let header = ResponseHeader::for_api(ApiKey::DescribeTopicPartitions.code(), 0, 9);
+        assert_eq!(header.version(), 1);
+        assert_eq!(&header.encode().unwrap()[..], &[0, 0, 0, 9, 0]);
+
+        assert_eq!(response_header_version(ApiKey::Produce.code(), 8), 0);
+        assert_eq!(response_header_version(ApiKey::Produce.code(), 9), 1);
+    }
+
+    #[test]
+    fn test_encode_to_appends_to_the_callers_buffer() {
+        let mut frame = BytesMut::new();
+        frame.put_u32(0); // length placeholder
+        ResponseHeaderV0::new(7).encode_to(&mut frame).unwrap();
+        let request = RequestHeaderV2::with_client_id(18, 3, 7, "client");
+        request.encode_to(&mut frame).unwrap();
+
+        assert_eq!(&frame[..8], &[0, 0, 0, 0, 0, 0, 0, 7]);
+        assert_eq!(&frame[8..], &request.encode().unwrap()[..]);
+    }
+
+    #[test]
+    fn test_encoded_size_fills_the_buffer_without_reallocating() {
+        let mut tagged = RequestHeaderV2::with_client_id(0, 2, 9, "clïent");
+        tagged.tagged_fields.insert(1, vec![0xaa]);
+        tagged.tagged_fields.insert(300, vec![0; 200]);
+        let headers = [
+            tagged,
+            RequestHeaderV2::without_client_id(18, 4, -1),
+            RequestHeaderV2::with_client_id(18, 0, 1, ""),
+        ];
+        for header in &headers {
+            for version in 0..=REQUEST_HEADER_MAX_VERSION {
+                let size = ProtocolEncodeVersioned::encoded_size(header, version);
+                let mut buffer = BytesMut::with_capacity(size);
+                let capacity = buffer.capacity();
+                header.encode_versioned(version, &mut buffer).unwrap();
+                assert_eq!(buffer.len(), size, "{:?} at v{}", header, version);
+                assert_eq!(buffer.capacity(), capacity);
+            }
+        }
+
+        let response_header = ResponseHeaderV0::new(7);
+        let mut buffer = BytesMut::with_capacity(response_header.encoded_size());
+        let capacity = buffer.capacity();
+        response_header.encode_to(&mut buffer).unwrap();
+        assert_eq!((buffer.len(), buffer.capacity()), (4, capacity));
+    }
+
+    #[test]
+    fn test_one_struct_encodes_every_header_version() {
+        let mut header = RequestHeaderV2::with_client_id(0, 2, 9, "c");
+        header.tagged_fields.insert(1, vec![0xaa]);
+        let layout = |version| {
+            let mut buffer = BytesMut::new();
+            header.encode_versioned(version, &mut buffer).unwrap();
+            buffer
+        };
+
+        let fixed = [0, 0, 0, 2, 0, 0, 0, 9];
+        assert_eq!(&layout(0)[..], &fixed);
+        assert_eq!(&layout(1)[..], &[&fixed[..], &[0, 1, b'c']].concat()[..]);
+        assert_eq!(
+            &layout(2)[..],
+            &[&fixed[..], &[0, 1, b'c', 1, 1, 1, 0xaa]].concat()[..]
+        );
+        assert_eq!(layout(2), header.encode().unwrap());
+
+        let mut v0 = layout(0).freeze();
+        let decoded = RequestHeaderV2::decode_versioned(&mut v0, 0).unwrap();
+        assert_eq!(decoded, RequestHeaderV2::without_client_id(0, 2, 9));
+        let mut v1 = layout(1).freeze();
+        let decoded = RequestHeaderV2::decode_versioned(&mut v1, 1).unwrap();
+        assert_eq!(decoded, RequestHeaderV2::with_client_id(0, 2, 9, "c"));
+        let mut v2 = layout(2).freeze();
+        assert_eq!(
+            RequestHeaderV2::decode_versioned(&mut v2, 2).unwrap(),
+            header
+        );
+
+        let error = header
+            .encode_versioned(3, &mut BytesMut::new())
+            .unwrap_err();
+        assert!(matches!(error, ProtocolError::InvalidFormat(_)));
+    }
+
+    #[test]
+    fn test_request_header_v0_roundtrip() {
+        let original = RequestHeaderV0::new(7, 0, 42);
+        let encoded = original.encode().unwrap();
+        assert_eq!(&encoded[..], &[0, 7, 0, 0, 0, 0, 0, 42]);
+        let mut buffer = encoded.freeze();
+        assert_eq!(RequestHeaderV0::decode(&mut buffer).unwrap(), original);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_request_header_v1_roundtrip() {
+        for client_id in [Some("legacy".to_string()), None] {
+            let original = RequestHeaderV1::new(0, 2, 42, client_id);
+            let encoded = original.encode().unwrap();
+            assert_eq!(encoded.len(), original.encoded_size());
+            let mut buffer = encoded.freeze();
+            assert_eq!(RequestHeaderV1::decode(&mut buffer).unwrap(), original);
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_decode_for_picks_the_header_version() {
+        let cases = [
+            (ApiKey::ControlledShutdown.code(), 0, 0),
+            (ApiKey::ControlledShutdown.code(), 1, 1),
+            (ApiKey::ControlledShutdown.code(), 3, 2),
+            (ApiKey::Produce.code(), 2, 1),
+            (ApiKey::Produce.code(), 9, 2),
+            (ApiKey::ApiVersions.code(), 2, 1),
+            (ApiKey::ApiVersions.code(), 4, 2),
+            (ApiKey::DescribeCluster.code(), 0, 2),
+        ];
+        for (api_key, api_version, expected) in cases {
+            assert_eq!(
+                RequestHeader::version_for(api_key, api_version),
+                expected,
+                "api key {} v{}",
+                api_key,
+                api_version
+            );
+            let mut header = RequestHeaderV2::with_client_id(api_key, api_version, 5, "c");
+            header.tagged_fields.insert(3, vec![0xaa]);
+            let mut buffer = BytesMut::new();
+            header.encode_versioned(expected, &mut buffer).unwrap();
+            buffer.extend_from_slice(b"body");
+
+            let mut buffer = buffer.freeze();
+            let decoded = RequestHeader::decode_for(api_key, api_version, &mut buffer).unwrap();
+            assert_eq!(decoded.version(), expected);
+            assert_eq!(
+                (
+                    decoded.api_key_code(),
+                    decoded.api_version(),
+                    decoded.correlation_id()
+                ),
+                (api_key, api_version, 5)
+            );
+            assert_eq!(decoded.client_id(), (expected >= 1).then_some("c"));
+            assert_eq!(&buffer[..], b"body");
+
+            let mut reencoded = decoded.encode().unwrap();
+            reencoded.extend_from_slice(b"body");
+            let mut original = BytesMut::new();
+            header.encode_versioned(expected, &mut original).unwrap();
+            original.extend_from_slice(b"body");
+            assert_eq!(reencoded, original);
+        }
+    }
+
+    #[test]
+    fn test_header_version_mismatch_shifts_the_body() {
+        // A v2 header sent for a non-flexible version: the tag section is
+        // left behind and read as the start of the body
+        let header = RequestHeaderV2::with_client_id(ApiKey::Produce.code(), 2, 5, "c");
+        let mut buffer = header.encode().unwrap().freeze();
+        let decoded = RequestHeader::decode_for(ApiKey::Produce.code(), 2, &mut buffer).unwrap();
+        assert_eq!(decoded.version(), 1);
+        assert_eq!(&buffer[..], &[0]);
+
+        // A v1 header read as v0 leaves the client id in the body
+        let header =
+            RequestHeaderV1::new(ApiKey::ControlledShutdown.code(), 0, 5, Some("c".into()));
+        let mut buffer = header.encode().unwrap().freeze();
+        let decoded =
+            RequestHeader::decode_for(ApiKey::ControlledShutdown.code(), 0, &mut buffer).unwrap();
+        assert_eq!(decoded.client_id(), None);
+        assert_eq!(&buffer[..], &[0, 1, b'c']);
+
+        // A v1 header read as v2 eats the first body byte as a tag count
+        let header = RequestHeaderV1::new(ApiKey::ApiVersions.code(), 3, 5, None);
+        let mut buffer = header.encode().unwrap();
+        buffer.put_u8(0x05);
+        let mut buffer = buffer.freeze();
+        assert!(RequestHeader::decode_for(ApiKey::ApiVersions.code(), 3, &mut buffer).is_err());
+    }
+
     #[test]
     fn test_request_header_v2_with_client_id() {
         let header = RequestHeaderV2::with_client_id(1, 2, 42, "test-client");
         assert_eq!(header.request_api_key, 1);
+        assert_eq!(header.api_key(), Ok(ApiKey::Fetch));
         assert_eq!(header.request_api_version, 2);
         assert_eq!(header.correlation_id, 42);
         assert_eq!(header.client_id, Some("test-client".to_string()));
+        assert_eq!(
+            RequestHeaderV2::without_client_id(1000, 0, 1).api_key(),
+            Err(1000)
+        );
     }
 
     #[test]
@@ -193,7 +959,7 @@ mod tests {
     fn test_request_header_v2_roundtrip_with_client_id() {
         let original = RequestHeaderV2::with_client_id(1, 2, 42, "test-client");
         let encoded = original.encode().unwrap();
-        let mut buffer = encoded;
+        let mut buffer = encoded.freeze();
         let decoded = RequestHeaderV2::decode(&mut buffer).unwrap();
 
         assert_eq!(original, decoded);
@@ -203,7 +969,7 @@ mod tests {
     fn test_request_header_v2_roundtrip_with_null_client_id() {
         let original = RequestHeaderV2::without_client_id(1, 2, 42);
         let encoded = original.encode().unwrap();
-        let mut buffer = encoded;
+        let mut buffer = encoded.freeze();
         let decoded = RequestHeaderV2::decode(&mut buffer).unwrap();
 
         assert_eq!(original, decoded);
@@ -214,10 +980,127 @@ mod tests {
         let mut buffer = BytesMut::new();
         buffer.put_i32(42); // Only 4 bytes, but we need at least 8
 
-        let result = RequestHeaderV2::decode(&mut buffer);
+        let mut buffer = buffer.freeze();
+        let error = RequestHeaderV2::decode(&mut buffer).unwrap_err();
         assert!(matches!(
-            result,
-            Err(ProtocolError::InsufficientBytes { .. })
+            error.root(),
+            ProtocolError::InsufficientBytes { .. }
         ));
+        assert_eq!(error.offset(), Some(0));
+    }
+
+    #[test]
+    fn test_decode_error_reports_offset_and_field() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i16(3);
+        buffer.put_i16(9);
+        buffer.put_i32(7);
+        buffer.put_i16(10); // client_id claims 10 bytes
+        buffer.put_slice(b"abc");
+
+        let mut buffer = buffer.freeze();
+        let error = RequestHeaderV2::decode(&mut buffer).unwrap_err();
+        assert_eq!(error.offset(), Some(8));
+        assert_eq!(error.path(), Some("client_id"));
+        assert_eq!(
+            error.to_string(),
+            "Insufficient bytes in buffer: expected 10, got 3 at byte 8 in client_id"
+        );
+    }
+
+    #[test]
+    fn test_oversized_client_id_is_rejected() {
+        let header = RequestHeaderV2::with_client_id(18, 3, 1, "x".repeat(300));
+        let mut buffer = header.encode().unwrap().freeze();
+        let error = RequestHeaderV2::decode(&mut buffer).unwrap_err();
+        assert_eq!(error.path(), Some("client_id"));
+        assert!(matches!(
+            error.root(),
+            ProtocolError::InvalidClientId {
+                length: 300,
+                limit: 256
+            }
+        ));
+
+        let header = RequestHeaderV1::new(0, 2, 1, Some("x".repeat(257)));
+        let mut buffer = header.encode().unwrap().freeze();
+        assert!(RequestHeader::decode_for(0, 2, &mut buffer).is_err());
+
+        let header = RequestHeaderV2::with_client_id(18, 3, 1, "x".repeat(256));
+        let mut buffer = header.encode().unwrap().freeze();
+        assert!(RequestHeaderV2::decode(&mut buffer).is_ok());
+    }
+
+    #[test]
+    fn test_client_id_control_characters_are_escaped() {
+        let header = RequestHeaderV2::with_client_id(18, 3, 1, "app\nlevel=ERROR forged\r\x1b[2J");
+        let mut buffer = header.encode().unwrap().freeze();
+        let decoded = RequestHeaderV2::decode(&mut buffer).unwrap();
+        assert_eq!(
+            decoded.client_id.as_deref(),
+            Some("app\\nlevel=ERROR forged\\r\\u{1b}[2J")
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_non_ascii_client_id_is_kept() {
+        let client_id = "клиент-é-生産者";
+        let header = RequestHeaderV2::with_client_id(18, 3, 1, client_id);
+        let mut buffer = header.encode().unwrap().freeze();
+        let decoded = RequestHeaderV2::decode(&mut buffer).unwrap();
+        assert_eq!(decoded.client_id.as_deref(), Some(client_id));
+        assert!(matches!(sanitize_client_id(client_id), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_decode_lenient_tolerates_unknown_layout() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i16(18);
+        buffer.put_i16(32000);
+        buffer.put_i32(5);
+        buffer.put_i16(40); // client id length beyond the buffer
+        buffer.put_u8(0xff);
+
+        let mut buffer = buffer.freeze();
+        let header = RequestHeaderV2::decode_lenient(&mut buffer).unwrap();
+        assert_eq!(
+            (header.request_api_version, header.correlation_id),
+            (32000, 5)
+        );
+        assert_eq!(header.client_id, None);
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_decode_without_tagged_fields_leaves_body_intact() {
+        let mut buffer = BytesMut::new();
+        buffer.put_i16(0);
+        buffer.put_i16(1);
+        buffer.put_i32(9);
+        WireFormat::encode_nullable_string(&mut buffer, Some("legacy")).unwrap();
+        buffer.put_i16(1); // first body field (acks)
+
+        let mut buffer = buffer.freeze();
+        let header = RequestHeaderV2::decode_without_tagged_fields(&mut buffer).unwrap();
+        assert_eq!(header.correlation_id, 9);
+        assert_eq!(&buffer[..], &1i16.to_be_bytes());
+    }
+
+    #[test]
+    fn test_request_header_v2_preserves_unknown_tags() {
+        let mut original = RequestHeaderV2::with_client_id(18, 3, 9, "tagged");
+        original.tagged_fields.insert(0, vec![0x01, 0x02]);
+        original.tagged_fields.insert(42, vec![0xff]);
+        let mut buffer = original.encode().unwrap();
+        buffer.extend_from_slice(b"body");
+
+        let mut buffer = buffer.freeze();
+        let decoded = RequestHeaderV2::decode(&mut buffer).unwrap();
+        assert_eq!(decoded, original);
+        assert_eq!(decoded.tagged_fields.get(42).unwrap().as_ref(), &[0xff]);
+        // The body is untouched rather than eaten by the tag section
+        assert_eq!(&buffer[..], b"body");
+        assert_eq!(decoded.encode().unwrap(), original.encode().unwrap());
     }
 }
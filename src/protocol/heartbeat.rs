@@ -0,0 +1,263 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest Heartbeat version we serve
+pub const HEARTBEAT_MIN_VERSION: i16 = 0;
+
+/// Highest Heartbeat version we serve
+pub const HEARTBEAT_MAX_VERSION: i16 = 4;
+
+/// First flexible Heartbeat version
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "Heartbeat",
+        version,
+        HEARTBEAT_MIN_VERSION..=HEARTBEAT_MAX_VERSION,
+    )
+}
+
+/// Heartbeat request (API key 12)
+///
+/// - v0: group, generation and member id
+/// - v3+: `group_instance_id`
+/// - v4+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartbeatRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    /// v3+
+    pub group_instance_id: Option<String>,
+}
+
+impl HeartbeatRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let group_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let generation_id = WireFormat::decode_i32(buffer)?;
+        let member_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let group_instance_id = if version >= 3 {
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+        } else {
+            None
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for HeartbeatRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_string(buffer, &self.group_id, flexible)?;
+        buffer.put_i32(self.generation_id);
+        WireFormat::encode_flexible_string(buffer, &self.member_id, flexible)?;
+        if version >= 3 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.group_instance_id.as_deref(),
+                flexible,
+            )?;
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let group_instance_id = if version >= 3 {
+            WireFormat::flexible_nullable_string_size(self.group_instance_id.as_deref(), flexible)
+        } else {
+            0
+        };
+        WireFormat::flexible_string_size(&self.group_id, flexible)
+            + 4
+            + WireFormat::flexible_string_size(&self.member_id, flexible)
+            + group_instance_id
+            + usize::from(flexible)
+    }
+}
+
+impl ProtocolDecodeVersioned for HeartbeatRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// Heartbeat response (API key 12)
+///
+/// - v0: error code
+/// - v1+: `throttle_time_ms`
+/// - v4+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartbeatResponse {
+    /// v1+
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+}
+
+impl HeartbeatResponse {
+    pub fn new(error_code: impl Into<ErrorCode>) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code: error_code.into(),
+        }
+    }
+
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 1 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            error_code,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for HeartbeatResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 1 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        buffer.put_i16(self.error_code.code());
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time = if version >= 1 { 4 } else { 0 };
+        throttle_time + 2 + usize::from(flexible)
+    }
+}
+
+impl ProtocolDecodeVersioned for HeartbeatResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> HeartbeatRequest {
+        HeartbeatRequest {
+            group_id: "billing".to_string(),
+            generation_id: 2,
+            member_id: "consumer-1".to_string(),
+            group_instance_id: Some("host-a".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_v4_layout() {
+        let expected = [
+            "0862696c6c696e67",       // group_id
+            "00000002",               // generation_id
+            "0b636f6e73756d65722d31", // member_id
+            "07686f73742d61",         // group_instance_id
+            "00",                     // tagged fields
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(4).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in HEARTBEAT_MIN_VERSION..=HEARTBEAT_MAX_VERSION {
+            let request = request();
+            let mut expected = request.clone();
+            if version < 3 {
+                expected.group_instance_id = None;
+            }
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                HeartbeatRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = HeartbeatResponse {
+            throttle_time_ms: 1,
+            error_code: ErrorCode::REBALANCE_IN_PROGRESS,
+        };
+        for version in HEARTBEAT_MIN_VERSION..=HEARTBEAT_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 1 {
+                expected.throttle_time_ms = 0;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                HeartbeatResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v5_is_rejected() {
+        assert!(request().encode(5).is_err());
+    }
+}
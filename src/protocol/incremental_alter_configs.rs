@@ -0,0 +1,424 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest IncrementalAlterConfigs version we serve
+pub const INCREMENTAL_ALTER_CONFIGS_MIN_VERSION: i16 = 0;
+
+/// Highest IncrementalAlterConfigs version we serve
+pub const INCREMENTAL_ALTER_CONFIGS_MAX_VERSION: i16 = 1;
+
+/// First flexible IncrementalAlterConfigs version
+const FIRST_FLEXIBLE_VERSION: i16 = 1;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "IncrementalAlterConfigs",
+        version,
+        INCREMENTAL_ALTER_CONFIGS_MIN_VERSION..=INCREMENTAL_ALTER_CONFIGS_MAX_VERSION,
+    )
+}
+
+/// How an [`AlterableConfig`] changes a config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigOperation {
+    Set,
+    Delete,
+    /// Adds to a list config
+    Append,
+    /// Removes from a list config
+    Subtract,
+    /// A code this broker does not know, kept so it can be reported
+    Unknown(i8),
+}
+
+impl ConfigOperation {
+    /// The operation for a `config_operation` read off the wire
+    pub fn from_code(code: i8) -> Self {
+        match code {
+            0 => Self::Set,
+            1 => Self::Delete,
+            2 => Self::Append,
+            3 => Self::Subtract,
+            code => Self::Unknown(code),
+        }
+    }
+
+    /// The INT8 written to the wire
+    pub fn code(self) -> i8 {
+        match self {
+            Self::Set => 0,
+            Self::Delete => 1,
+            Self::Append => 2,
+            Self::Subtract => 3,
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+/// IncrementalAlterConfigs request (API key 44)
+///
+/// - v0: the changes to make to each resource
+/// - v1+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrementalAlterConfigsRequest {
+    pub resources: Vec<AlterConfigsResource>,
+    /// Check the changes without making them
+    pub validate_only: bool,
+}
+
+/// A resource to change, in an [`IncrementalAlterConfigsRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlterConfigsResource {
+    pub resource_type: i8,
+    pub resource_name: String,
+    pub configs: Vec<AlterableConfig>,
+}
+
+/// One change to a config of an [`AlterConfigsResource`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlterableConfig {
+    pub name: String,
+    pub config_operation: ConfigOperation,
+    /// Null for a delete
+    pub value: Option<String>,
+}
+
+impl IncrementalAlterConfigsRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let resources = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let resource_type = WireFormat::decode_i8(buffer)?;
+            let resource_name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let configs = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let config = AlterableConfig {
+                    name: WireFormat::decode_flexible_string(buffer, flexible)?,
+                    config_operation: ConfigOperation::from_code(WireFormat::decode_i8(buffer)?),
+                    value: WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(config)
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(AlterConfigsResource {
+                resource_type,
+                resource_name,
+                configs,
+            })
+        })?;
+        let validate_only = WireFormat::decode_bool(buffer)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            resources,
+            validate_only,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for IncrementalAlterConfigsRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_array(
+            buffer,
+            &self.resources,
+            flexible,
+            |buffer, resource| {
+                buffer.put_i8(resource.resource_type);
+                WireFormat::encode_flexible_string(buffer, &resource.resource_name, flexible)?;
+                WireFormat::encode_flexible_array(
+                    buffer,
+                    &resource.configs,
+                    flexible,
+                    |buffer, config| {
+                        WireFormat::encode_flexible_string(buffer, &config.name, flexible)?;
+                        buffer.put_i8(config.config_operation.code());
+                        WireFormat::encode_flexible_nullable_string(
+                            buffer,
+                            config.value.as_deref(),
+                            flexible,
+                        )?;
+                        put_empty_tagged_fields(buffer, flexible);
+                        Ok(())
+                    },
+                )?;
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            },
+        )?;
+        WireFormat::encode_bool(buffer, self.validate_only);
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let resources: usize = self
+            .resources
+            .iter()
+            .map(|resource| {
+                let configs: usize = resource
+                    .configs
+                    .iter()
+                    .map(|config| {
+                        WireFormat::flexible_string_size(&config.name, flexible)
+                            + 1
+                            + WireFormat::flexible_nullable_string_size(
+                                config.value.as_deref(),
+                                flexible,
+                            )
+                            + tags
+                    })
+                    .sum();
+                1 + WireFormat::flexible_string_size(&resource.resource_name, flexible)
+                    + WireFormat::flexible_array_length_size(Some(resource.configs.len()), flexible)
+                    + configs
+                    + tags
+            })
+            .sum();
+        WireFormat::flexible_array_length_size(Some(self.resources.len()), flexible)
+            + resources
+            + 1
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for IncrementalAlterConfigsRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// IncrementalAlterConfigs response (API key 44)
+///
+/// Every version carries the same fields; v1+ is flexible.
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrementalAlterConfigsResponse {
+    pub throttle_time_ms: i32,
+    pub responses: Vec<AlterConfigsResourceResponse>,
+}
+
+/// The outcome for one resource of an [`IncrementalAlterConfigsRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlterConfigsResourceResponse {
+    pub error_code: ErrorCode,
+    pub error_message: Option<String>,
+    pub resource_type: i8,
+    pub resource_name: String,
+}
+
+impl IncrementalAlterConfigsResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = WireFormat::decode_i32(buffer)?;
+        let responses = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let response = AlterConfigsResourceResponse {
+                error_code: ErrorCode::from_wire(WireFormat::decode_i16(buffer)?),
+                error_message: WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                resource_type: WireFormat::decode_i8(buffer)?,
+                resource_name: WireFormat::decode_flexible_string(buffer, flexible)?,
+            };
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(response)
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            responses,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for IncrementalAlterConfigsResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        buffer.put_i32(self.throttle_time_ms);
+        WireFormat::encode_flexible_array(
+            buffer,
+            &self.responses,
+            flexible,
+            |buffer, response| {
+                buffer.put_i16(response.error_code.code());
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    response.error_message.as_deref(),
+                    flexible,
+                )?;
+                buffer.put_i8(response.resource_type);
+                WireFormat::encode_flexible_string(buffer, &response.resource_name, flexible)?;
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            },
+        )?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let responses: usize = self
+            .responses
+            .iter()
+            .map(|response| {
+                2 + WireFormat::flexible_nullable_string_size(
+                    response.error_message.as_deref(),
+                    flexible,
+                ) + 1
+                    + WireFormat::flexible_string_size(&response.resource_name, flexible)
+                    + tags
+            })
+            .sum();
+        4 + WireFormat::flexible_array_length_size(Some(self.responses.len()), flexible)
+            + responses
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for IncrementalAlterConfigsResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> IncrementalAlterConfigsRequest {
+        IncrementalAlterConfigsRequest {
+            resources: vec![AlterConfigsResource {
+                resource_type: 2,
+                resource_name: "orders".to_string(),
+                configs: vec![
+                    AlterableConfig {
+                        name: "retention.ms".to_string(),
+                        config_operation: ConfigOperation::Set,
+                        value: Some("1000".to_string()),
+                    },
+                    AlterableConfig {
+                        name: "cleanup.policy".to_string(),
+                        config_operation: ConfigOperation::Unknown(9),
+                        value: None,
+                    },
+                ],
+            }],
+            validate_only: true,
+        }
+    }
+
+    #[test]
+    fn test_v1_request_layout() {
+        let expected = [
+            "02",                             // resources.length
+            "02",                             // resources[0].resource_type
+            "076f7264657273",                 // resources[0].resource_name
+            "03",                             // resources[0].configs.length
+            "0d726574656e74696f6e2e6d73",     // configs[0].name
+            "00",                             // configs[0].config_operation
+            "0531303030",                     // configs[0].value
+            "00",                             // configs[0] tagged fields
+            "0f636c65616e75702e706f6c696379", // configs[1].name
+            "09",                             // configs[1].config_operation
+            "00",                             // configs[1].value
+            "00",                             // configs[1] tagged fields
+            "00",                             // resources[0] tagged fields
+            "01",                             // validate_only
+            "00",                             // tagged fields
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(1).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_operation_codes() {
+        for code in 0..4 {
+            let operation = ConfigOperation::from_code(code);
+            assert_ne!(operation, ConfigOperation::Unknown(code));
+            assert_eq!(operation.code(), code);
+        }
+        assert_eq!(ConfigOperation::from_code(-1), ConfigOperation::Unknown(-1));
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        let request = request();
+        for version in INCREMENTAL_ALTER_CONFIGS_MIN_VERSION..=INCREMENTAL_ALTER_CONFIGS_MAX_VERSION
+        {
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                IncrementalAlterConfigsRequest::decode(&mut encoded, version).unwrap(),
+                request,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = IncrementalAlterConfigsResponse {
+            throttle_time_ms: 0,
+            responses: vec![AlterConfigsResourceResponse {
+                error_code: ErrorCode::INVALID_CONFIG,
+                error_message: Some("Unknown configuration key: no.such.key".to_string()),
+                resource_type: 2,
+                resource_name: "orders".to_string(),
+            }],
+        };
+        for version in INCREMENTAL_ALTER_CONFIGS_MIN_VERSION..=INCREMENTAL_ALTER_CONFIGS_MAX_VERSION
+        {
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                IncrementalAlterConfigsResponse::decode(&mut encoded, version).unwrap(),
+                response,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v2_is_rejected() {
+        assert!(request().encode(2).is_err());
+    }
+}
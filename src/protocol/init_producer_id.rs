@@ -0,0 +1,279 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest InitProducerId version we serve
+pub const INIT_PRODUCER_ID_MIN_VERSION: i16 = 0;
+
+/// Highest InitProducerId version we serve
+pub const INIT_PRODUCER_ID_MAX_VERSION: i16 = 5;
+
+/// First flexible InitProducerId version
+const FIRST_FLEXIBLE_VERSION: i16 = 2;
+
+/// First InitProducerId version carrying the producer's current id and epoch
+pub const INIT_PRODUCER_ID_EXISTING_PRODUCER_VERSION: i16 = 3;
+
+/// `producer_id` of a producer that has none yet
+pub const NO_PRODUCER_ID: i64 = -1;
+
+/// `producer_epoch` of a producer that has none yet
+pub const NO_PRODUCER_EPOCH: i16 = -1;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "InitProducerId",
+        version,
+        INIT_PRODUCER_ID_MIN_VERSION..=INIT_PRODUCER_ID_MAX_VERSION,
+    )
+}
+
+/// InitProducerId request (API key 22)
+///
+/// - v0: transactional id and transaction timeout
+/// - v2+: flexible
+/// - v3+: `producer_id` and `producer_epoch`, so a producer can keep its
+///   id when re-initializing
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitProducerIdRequest {
+    /// Null for a producer that is idempotent but not transactional
+    pub transactional_id: Option<String>,
+    pub transaction_timeout_ms: i32,
+    /// v3+
+    pub producer_id: i64,
+    /// v3+
+    pub producer_epoch: i16,
+}
+
+impl InitProducerIdRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let transactional_id = WireFormat::decode_flexible_nullable_string(buffer, flexible)?;
+        let transaction_timeout_ms = WireFormat::decode_i32(buffer)?;
+        let existing_producer = version >= INIT_PRODUCER_ID_EXISTING_PRODUCER_VERSION;
+        let producer_id = if existing_producer {
+            WireFormat::decode_i64(buffer)?
+        } else {
+            NO_PRODUCER_ID
+        };
+        let producer_epoch = if existing_producer {
+            WireFormat::decode_i16(buffer)?
+        } else {
+            NO_PRODUCER_EPOCH
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            transactional_id,
+            transaction_timeout_ms,
+            producer_id,
+            producer_epoch,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for InitProducerIdRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_nullable_string(
+            buffer,
+            self.transactional_id.as_deref(),
+            flexible,
+        )?;
+        buffer.put_i32(self.transaction_timeout_ms);
+        if version >= INIT_PRODUCER_ID_EXISTING_PRODUCER_VERSION {
+            buffer.put_i64(self.producer_id);
+            buffer.put_i16(self.producer_epoch);
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let existing_producer = if version >= INIT_PRODUCER_ID_EXISTING_PRODUCER_VERSION {
+            8 + 2
+        } else {
+            0
+        };
+        WireFormat::flexible_nullable_string_size(self.transactional_id.as_deref(), flexible)
+            + 4
+            + existing_producer
+            + usize::from(flexible)
+    }
+}
+
+impl ProtocolDecodeVersioned for InitProducerIdRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// InitProducerId response (API key 22)
+///
+/// Every version carries the same fields; v2+ is flexible.
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitProducerIdResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+}
+
+impl InitProducerIdResponse {
+    /// A response carrying only an error
+    pub fn error(error_code: impl Into<ErrorCode>) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code: error_code.into(),
+            producer_id: NO_PRODUCER_ID,
+            producer_epoch: NO_PRODUCER_EPOCH,
+        }
+    }
+
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = WireFormat::decode_i32(buffer)?;
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        let producer_id = WireFormat::decode_i64(buffer)?;
+        let producer_epoch = WireFormat::decode_i16(buffer)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            error_code,
+            producer_id,
+            producer_epoch,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for InitProducerIdResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        buffer.put_i32(self.throttle_time_ms);
+        buffer.put_i16(self.error_code.code());
+        buffer.put_i64(self.producer_id);
+        buffer.put_i16(self.producer_epoch);
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        4 + 2 + 8 + 2 + usize::from(flexible)
+    }
+}
+
+impl ProtocolDecodeVersioned for InitProducerIdResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> InitProducerIdRequest {
+        InitProducerIdRequest {
+            transactional_id: Some("tx-1".to_string()),
+            transaction_timeout_ms: 60_000,
+            producer_id: 7,
+            producer_epoch: 2,
+        }
+    }
+
+    #[test]
+    fn test_v4_layout() {
+        let expected = [
+            "0574782d31",       // transactional_id
+            "0000ea60",         // transaction_timeout_ms
+            "0000000000000007", // producer_id
+            "0002",             // producer_epoch
+            "00",               // tagged fields
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(4).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in INIT_PRODUCER_ID_MIN_VERSION..=INIT_PRODUCER_ID_MAX_VERSION {
+            let request = request();
+            let mut expected = request.clone();
+            if version < INIT_PRODUCER_ID_EXISTING_PRODUCER_VERSION {
+                expected.producer_id = NO_PRODUCER_ID;
+                expected.producer_epoch = NO_PRODUCER_EPOCH;
+            }
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                InitProducerIdRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = InitProducerIdResponse {
+            throttle_time_ms: 1,
+            error_code: ErrorCode::NONE,
+            producer_id: 1_000,
+            producer_epoch: 0,
+        };
+        for version in INIT_PRODUCER_ID_MIN_VERSION..=INIT_PRODUCER_ID_MAX_VERSION {
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                InitProducerIdResponse::decode(&mut encoded, version).unwrap(),
+                response,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v6_is_rejected() {
+        assert!(request().encode(6).is_err());
+    }
+}
@@ -0,0 +1,541 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest JoinGroup version we serve
+pub const JOIN_GROUP_MIN_VERSION: i16 = 0;
+
+/// Highest JoinGroup version we serve
+pub const JOIN_GROUP_MAX_VERSION: i16 = 9;
+
+/// First flexible JoinGroup version
+const FIRST_FLEXIBLE_VERSION: i16 = 6;
+
+/// First JoinGroup version whose clients retry with the member id from a
+/// MEMBER_ID_REQUIRED answer; older clients are given one straight away
+pub const JOIN_GROUP_MEMBER_ID_REQUIRED_VERSION: i16 = 4;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "JoinGroup",
+        version,
+        JOIN_GROUP_MIN_VERSION..=JOIN_GROUP_MAX_VERSION,
+    )
+}
+
+/// JoinGroup request (API key 11)
+///
+/// - v0: group, session timeout, member id, protocol type and protocols
+/// - v1+: `rebalance_timeout_ms`
+/// - v5+: `group_instance_id`
+/// - v6+: flexible
+/// - v8+: `reason`
+///
+/// Before v1 the rebalance timeout is the session timeout. Tagged fields
+/// are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinGroupRequest {
+    pub group_id: String,
+    pub session_timeout_ms: i32,
+    /// v1+
+    pub rebalance_timeout_ms: i32,
+    /// Empty on a member's first join
+    pub member_id: String,
+    /// v5+
+    pub group_instance_id: Option<String>,
+    pub protocol_type: String,
+    /// In the member's order of preference
+    pub protocols: Vec<JoinGroupRequestProtocol>,
+    /// v8+
+    pub reason: Option<String>,
+}
+
+/// An assignment protocol a member supports, with its metadata
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinGroupRequestProtocol {
+    pub name: String,
+    pub metadata: Bytes,
+}
+
+impl JoinGroupRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let group_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let session_timeout_ms = WireFormat::decode_i32(buffer)?;
+        let rebalance_timeout_ms = if version >= 1 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            session_timeout_ms
+        };
+        let member_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let group_instance_id = if version >= 5 {
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+        } else {
+            None
+        };
+        let protocol_type = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let protocols = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let protocol = JoinGroupRequestProtocol {
+                name: WireFormat::decode_flexible_string(buffer, flexible)?,
+                metadata: WireFormat::decode_flexible_bytes(buffer, flexible)?,
+            };
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(protocol)
+        })?;
+        let reason = if version >= 8 {
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+        } else {
+            None
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            group_id,
+            session_timeout_ms,
+            rebalance_timeout_ms,
+            member_id,
+            group_instance_id,
+            protocol_type,
+            protocols,
+            reason,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for JoinGroupRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_string(buffer, &self.group_id, flexible)?;
+        buffer.put_i32(self.session_timeout_ms);
+        if version >= 1 {
+            buffer.put_i32(self.rebalance_timeout_ms);
+        }
+        WireFormat::encode_flexible_string(buffer, &self.member_id, flexible)?;
+        if version >= 5 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.group_instance_id.as_deref(),
+                flexible,
+            )?;
+        }
+        WireFormat::encode_flexible_string(buffer, &self.protocol_type, flexible)?;
+        WireFormat::encode_flexible_array(
+            buffer,
+            &self.protocols,
+            flexible,
+            |buffer, protocol| {
+                WireFormat::encode_flexible_string(buffer, &protocol.name, flexible)?;
+                WireFormat::encode_flexible_bytes(buffer, &protocol.metadata, flexible)?;
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            },
+        )?;
+        if version >= 8 {
+            WireFormat::encode_flexible_nullable_string(buffer, self.reason.as_deref(), flexible)?;
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let rebalance_timeout = if version >= 1 { 4 } else { 0 };
+        let group_instance_id = if version >= 5 {
+            WireFormat::flexible_nullable_string_size(self.group_instance_id.as_deref(), flexible)
+        } else {
+            0
+        };
+        let protocols: usize = self
+            .protocols
+            .iter()
+            .map(|protocol| {
+                WireFormat::flexible_string_size(&protocol.name, flexible)
+                    + WireFormat::flexible_bytes_size(&protocol.metadata, flexible)
+                    + tags
+            })
+            .sum();
+        let reason = if version >= 8 {
+            WireFormat::flexible_nullable_string_size(self.reason.as_deref(), flexible)
+        } else {
+            0
+        };
+        WireFormat::flexible_string_size(&self.group_id, flexible)
+            + 4
+            + rebalance_timeout
+            + WireFormat::flexible_string_size(&self.member_id, flexible)
+            + group_instance_id
+            + WireFormat::flexible_string_size(&self.protocol_type, flexible)
+            + WireFormat::flexible_array_length_size(Some(self.protocols.len()), flexible)
+            + protocols
+            + reason
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for JoinGroupRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// JoinGroup response (API key 11)
+///
+/// - v0: generation, protocol, leader, member id and, for the leader, the
+///   members with their metadata
+/// - v2+: `throttle_time_ms`
+/// - v5+: per-member `group_instance_id`
+/// - v6+: flexible
+/// - v7+: `protocol_type`; `protocol_name` becomes nullable
+/// - v9+: `skip_assignment`
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinGroupResponse {
+    /// v2+
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+    pub generation_id: i32,
+    /// v7+
+    pub protocol_type: Option<String>,
+    /// Written empty when null before v7
+    pub protocol_name: Option<String>,
+    pub leader: String,
+    /// v9+
+    pub skip_assignment: bool,
+    pub member_id: String,
+    /// Empty for every member but the leader
+    pub members: Vec<JoinGroupResponseMember>,
+}
+
+/// A group member as the leader sees it in a [`JoinGroupResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinGroupResponseMember {
+    pub member_id: String,
+    /// v5+
+    pub group_instance_id: Option<String>,
+    /// The member's metadata for the chosen protocol
+    pub metadata: Bytes,
+}
+
+impl JoinGroupResponse {
+    /// A failed join, with the member id the client should use, if any
+    pub fn error(error_code: impl Into<ErrorCode>, member_id: String) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code: error_code.into(),
+            generation_id: -1,
+            protocol_type: None,
+            protocol_name: None,
+            leader: String::new(),
+            skip_assignment: false,
+            member_id,
+            members: Vec::new(),
+        }
+    }
+
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 2 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        let generation_id = WireFormat::decode_i32(buffer)?;
+        let (protocol_type, protocol_name) = if version >= 7 {
+            (
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+            )
+        } else {
+            (
+                None,
+                Some(WireFormat::decode_flexible_string(buffer, flexible)?),
+            )
+        };
+        let leader = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let skip_assignment = if version >= 9 {
+            WireFormat::decode_bool(buffer)?
+        } else {
+            false
+        };
+        let member_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let members = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let member_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let group_instance_id = if version >= 5 {
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+            } else {
+                None
+            };
+            let metadata = WireFormat::decode_flexible_bytes(buffer, flexible)?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(JoinGroupResponseMember {
+                member_id,
+                group_instance_id,
+                metadata,
+            })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            error_code,
+            generation_id,
+            protocol_type,
+            protocol_name,
+            leader,
+            skip_assignment,
+            member_id,
+            members,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for JoinGroupResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 2 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        buffer.put_i16(self.error_code.code());
+        buffer.put_i32(self.generation_id);
+        if version >= 7 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.protocol_type.as_deref(),
+                flexible,
+            )?;
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.protocol_name.as_deref(),
+                flexible,
+            )?;
+        } else {
+            WireFormat::encode_flexible_string(
+                buffer,
+                self.protocol_name.as_deref().unwrap_or_default(),
+                flexible,
+            )?;
+        }
+        WireFormat::encode_flexible_string(buffer, &self.leader, flexible)?;
+        if version >= 9 {
+            WireFormat::encode_bool(buffer, self.skip_assignment);
+        }
+        WireFormat::encode_flexible_string(buffer, &self.member_id, flexible)?;
+        WireFormat::encode_flexible_array(buffer, &self.members, flexible, |buffer, member| {
+            WireFormat::encode_flexible_string(buffer, &member.member_id, flexible)?;
+            if version >= 5 {
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    member.group_instance_id.as_deref(),
+                    flexible,
+                )?;
+            }
+            WireFormat::encode_flexible_bytes(buffer, &member.metadata, flexible)?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let throttle_time = if version >= 2 { 4 } else { 0 };
+        let protocol = if version >= 7 {
+            WireFormat::flexible_nullable_string_size(self.protocol_type.as_deref(), flexible)
+                + WireFormat::flexible_nullable_string_size(self.protocol_name.as_deref(), flexible)
+        } else {
+            WireFormat::flexible_string_size(
+                self.protocol_name.as_deref().unwrap_or_default(),
+                flexible,
+            )
+        };
+        let skip_assignment = if version >= 9 { 1 } else { 0 };
+        let members: usize = self
+            .members
+            .iter()
+            .map(|member| {
+                let group_instance_id = if version >= 5 {
+                    WireFormat::flexible_nullable_string_size(
+                        member.group_instance_id.as_deref(),
+                        flexible,
+                    )
+                } else {
+                    0
+                };
+                WireFormat::flexible_string_size(&member.member_id, flexible)
+                    + group_instance_id
+                    + WireFormat::flexible_bytes_size(&member.metadata, flexible)
+                    + tags
+            })
+            .sum();
+        throttle_time
+            + 2
+            + 4
+            + protocol
+            + WireFormat::flexible_string_size(&self.leader, flexible)
+            + skip_assignment
+            + WireFormat::flexible_string_size(&self.member_id, flexible)
+            + WireFormat::flexible_array_length_size(Some(self.members.len()), flexible)
+            + members
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for JoinGroupResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> JoinGroupRequest {
+        JoinGroupRequest {
+            group_id: "billing".to_string(),
+            session_timeout_ms: 10_000,
+            rebalance_timeout_ms: 30_000,
+            member_id: String::new(),
+            group_instance_id: Some("host-a".to_string()),
+            protocol_type: "consumer".to_string(),
+            protocols: vec![JoinGroupRequestProtocol {
+                name: "range".to_string(),
+                metadata: Bytes::from_static(b"\x00\x01"),
+            }],
+            reason: Some("rejoin".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_v1_layout() {
+        let expected = [
+            "000762696c6c696e67",   // group_id
+            "00002710",             // session_timeout_ms
+            "00007530",             // rebalance_timeout_ms
+            "0000",                 // member_id
+            "0008636f6e73756d6572", // protocol_type
+            "00000001",             // protocols
+            "000572616e6765",       // name
+            "000000020001",         // metadata
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(1).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in JOIN_GROUP_MIN_VERSION..=JOIN_GROUP_MAX_VERSION {
+            let request = request();
+            let mut expected = request.clone();
+            if version < 1 {
+                expected.rebalance_timeout_ms = expected.session_timeout_ms;
+            }
+            if version < 5 {
+                expected.group_instance_id = None;
+            }
+            if version < 8 {
+                expected.reason = None;
+            }
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                JoinGroupRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = JoinGroupResponse {
+            throttle_time_ms: 4,
+            error_code: ErrorCode::NONE,
+            generation_id: 3,
+            protocol_type: Some("consumer".to_string()),
+            protocol_name: Some("range".to_string()),
+            leader: "consumer-1".to_string(),
+            skip_assignment: true,
+            member_id: "consumer-1".to_string(),
+            members: vec![JoinGroupResponseMember {
+                member_id: "consumer-1".to_string(),
+                group_instance_id: Some("host-a".to_string()),
+                metadata: Bytes::from_static(b"\x00\x01"),
+            }],
+        };
+        for version in JOIN_GROUP_MIN_VERSION..=JOIN_GROUP_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 2 {
+                expected.throttle_time_ms = 0;
+            }
+            if version < 5 {
+                expected.members[0].group_instance_id = None;
+            }
+            if version < 7 {
+                expected.protocol_type = None;
+            }
+            if version < 9 {
+                expected.skip_assignment = false;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                JoinGroupResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_null_protocol_name_before_v7_is_empty() {
+        let response = JoinGroupResponse::error(ErrorCode::MEMBER_ID_REQUIRED, "m-1".to_string());
+        let mut encoded = response.encode(6).unwrap().freeze();
+        let decoded = JoinGroupResponse::decode(&mut encoded, 6).unwrap();
+        assert_eq!(decoded.protocol_name.as_deref(), Some(""));
+        assert_eq!(decoded.member_id, "m-1");
+    }
+
+    #[test]
+    fn test_v10_is_rejected() {
+        assert!(request().encode(10).is_err());
+    }
+}
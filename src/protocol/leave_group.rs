@@ -0,0 +1,426 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest LeaveGroup version we serve
+pub const LEAVE_GROUP_MIN_VERSION: i16 = 0;
+
+/// Highest LeaveGroup version we serve
+pub const LEAVE_GROUP_MAX_VERSION: i16 = 5;
+
+/// First LeaveGroup version that removes several members at once
+pub const LEAVE_GROUP_BATCHED_VERSION: i16 = 3;
+
+/// First flexible LeaveGroup version
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "LeaveGroup",
+        version,
+        LEAVE_GROUP_MIN_VERSION..=LEAVE_GROUP_MAX_VERSION,
+    )
+}
+
+/// LeaveGroup request (API key 13)
+///
+/// - v0: group and the id of the one member leaving
+/// - v3+: a list of members, each with an optional `group_instance_id`
+/// - v4+: flexible
+/// - v5+: per-member `reason`
+///
+/// Below v3 the request is held as a single entry of `members`.
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaveGroupRequest {
+    pub group_id: String,
+    pub members: Vec<LeavingMember>,
+}
+
+/// One member leaving the group
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeavingMember {
+    pub member_id: String,
+    /// v3+
+    pub group_instance_id: Option<String>,
+    /// v5+
+    pub reason: Option<String>,
+}
+
+impl LeaveGroupRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let group_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let members = if version >= LEAVE_GROUP_BATCHED_VERSION {
+            WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let member_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+                let group_instance_id =
+                    WireFormat::decode_flexible_nullable_string(buffer, flexible)?;
+                let reason = if version >= 5 {
+                    WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+                } else {
+                    None
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(LeavingMember {
+                    member_id,
+                    group_instance_id,
+                    reason,
+                })
+            })?
+        } else {
+            vec![LeavingMember {
+                member_id: WireFormat::decode_flexible_string(buffer, flexible)?,
+                group_instance_id: None,
+                reason: None,
+            }]
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self { group_id, members })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for LeaveGroupRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_string(buffer, &self.group_id, flexible)?;
+        if version >= LEAVE_GROUP_BATCHED_VERSION {
+            WireFormat::encode_flexible_array(
+                buffer,
+                &self.members,
+                flexible,
+                |buffer, member| {
+                    WireFormat::encode_flexible_string(buffer, &member.member_id, flexible)?;
+                    WireFormat::encode_flexible_nullable_string(
+                        buffer,
+                        member.group_instance_id.as_deref(),
+                        flexible,
+                    )?;
+                    if version >= 5 {
+                        WireFormat::encode_flexible_nullable_string(
+                            buffer,
+                            member.reason.as_deref(),
+                            flexible,
+                        )?;
+                    }
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+        } else {
+            let [member] = self.members.as_slice() else {
+                return Err(ProtocolError::SerializationError(format!(
+                    "LeaveGroup v{} removes exactly one member, not {}",
+                    version,
+                    self.members.len()
+                )));
+            };
+            WireFormat::encode_flexible_string(buffer, &member.member_id, flexible)?;
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let members = if version >= LEAVE_GROUP_BATCHED_VERSION {
+            WireFormat::flexible_array_length_size(Some(self.members.len()), flexible)
+                + self
+                    .members
+                    .iter()
+                    .map(|member| {
+                        let reason = if version >= 5 {
+                            WireFormat::flexible_nullable_string_size(
+                                member.reason.as_deref(),
+                                flexible,
+                            )
+                        } else {
+                            0
+                        };
+                        WireFormat::flexible_string_size(&member.member_id, flexible)
+                            + WireFormat::flexible_nullable_string_size(
+                                member.group_instance_id.as_deref(),
+                                flexible,
+                            )
+                            + reason
+                            + tags
+                    })
+                    .sum::<usize>()
+        } else {
+            self.members
+                .first()
+                .map(|member| WireFormat::flexible_string_size(&member.member_id, flexible))
+                .unwrap_or_default()
+        };
+        WireFormat::flexible_string_size(&self.group_id, flexible) + members + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for LeaveGroupRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// LeaveGroup response (API key 13)
+///
+/// - v0: error code
+/// - v1+: `throttle_time_ms`
+/// - v3+: an error code for each member
+/// - v4+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaveGroupResponse {
+    /// v1+
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+    /// v3+
+    pub members: Vec<LeftMember>,
+}
+
+/// Outcome for one member named in the request
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeftMember {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub error_code: ErrorCode,
+}
+
+impl LeaveGroupResponse {
+    /// A request that failed as a whole
+    pub fn error(error_code: impl Into<ErrorCode>) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code: error_code.into(),
+            members: Vec::new(),
+        }
+    }
+
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 1 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        let members = if version >= LEAVE_GROUP_BATCHED_VERSION {
+            WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let member = LeftMember {
+                    member_id: WireFormat::decode_flexible_string(buffer, flexible)?,
+                    group_instance_id: WireFormat::decode_flexible_nullable_string(
+                        buffer, flexible,
+                    )?,
+                    error_code: ErrorCode::from_wire(WireFormat::decode_i16(buffer)?),
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(member)
+            })?
+        } else {
+            Vec::new()
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            error_code,
+            members,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for LeaveGroupResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 1 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        buffer.put_i16(self.error_code.code());
+        if version >= LEAVE_GROUP_BATCHED_VERSION {
+            WireFormat::encode_flexible_array(
+                buffer,
+                &self.members,
+                flexible,
+                |buffer, member| {
+                    WireFormat::encode_flexible_string(buffer, &member.member_id, flexible)?;
+                    WireFormat::encode_flexible_nullable_string(
+                        buffer,
+                        member.group_instance_id.as_deref(),
+                        flexible,
+                    )?;
+                    buffer.put_i16(member.error_code.code());
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let throttle_time = if version >= 1 { 4 } else { 0 };
+        let members = if version >= LEAVE_GROUP_BATCHED_VERSION {
+            WireFormat::flexible_array_length_size(Some(self.members.len()), flexible)
+                + self
+                    .members
+                    .iter()
+                    .map(|member| {
+                        WireFormat::flexible_string_size(&member.member_id, flexible)
+                            + WireFormat::flexible_nullable_string_size(
+                                member.group_instance_id.as_deref(),
+                                flexible,
+                            )
+                            + 2
+                            + tags
+                    })
+                    .sum::<usize>()
+        } else {
+            0
+        };
+        throttle_time + 2 + members + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for LeaveGroupResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(member_id: &str) -> LeavingMember {
+        LeavingMember {
+            member_id: member_id.to_string(),
+            group_instance_id: Some("host-a".to_string()),
+            reason: Some("shutdown".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_v0_layout() {
+        let request = LeaveGroupRequest {
+            group_id: "billing".to_string(),
+            members: vec![member("consumer-1")],
+        };
+        let expected = [
+            "000762696c6c696e67",       // group_id
+            "000a636f6e73756d65722d31", // member_id
+        ]
+        .concat();
+        assert_eq!(hex::encode(request.encode(0).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_single_member_versions_reject_batches() {
+        let request = LeaveGroupRequest {
+            group_id: "billing".to_string(),
+            members: vec![member("consumer-1"), member("consumer-2")],
+        };
+        assert!(request.encode(2).is_err());
+        assert!(request.encode(3).is_ok());
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        let request = LeaveGroupRequest {
+            group_id: "billing".to_string(),
+            members: vec![member("consumer-1")],
+        };
+        for version in LEAVE_GROUP_MIN_VERSION..=LEAVE_GROUP_MAX_VERSION {
+            let mut expected = request.clone();
+            if version < 3 {
+                expected.members[0].group_instance_id = None;
+            }
+            if version < 5 {
+                expected.members[0].reason = None;
+            }
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                LeaveGroupRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = LeaveGroupResponse {
+            throttle_time_ms: 1,
+            error_code: ErrorCode::NONE,
+            members: vec![LeftMember {
+                member_id: "consumer-1".to_string(),
+                group_instance_id: None,
+                error_code: ErrorCode::UNKNOWN_MEMBER_ID,
+            }],
+        };
+        for version in LEAVE_GROUP_MIN_VERSION..=LEAVE_GROUP_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 1 {
+                expected.throttle_time_ms = 0;
+            }
+            if version < 3 {
+                expected.members.clear();
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                LeaveGroupResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v6_is_rejected() {
+        let request = LeaveGroupRequest {
+            group_id: "billing".to_string(),
+            members: vec![member("consumer-1")],
+        };
+        assert!(request.encode(6).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest ListOffsets version we serve
+///
+/// v0 answers with a list of offsets instead of one offset per partition
+/// and is not supported.
+pub const LIST_OFFSETS_MIN_VERSION: i16 = 1;
+
+/// Highest ListOffsets version we serve
+pub const LIST_OFFSETS_MAX_VERSION: i16 = 9;
+
+/// First flexible ListOffsets version
+const FIRST_FLEXIBLE_VERSION: i16 = 6;
+
+/// Timestamp asking for the offset of the next record to be appended
+pub const LATEST_TIMESTAMP: i64 = -1;
+
+/// Timestamp asking for the first offset still in the log
+pub const EARLIEST_TIMESTAMP: i64 = -2;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "ListOffsets",
+        version,
+        LIST_OFFSETS_MIN_VERSION..=LIST_OFFSETS_MAX_VERSION,
+    )
+}
+
+/// ListOffsets request (API key 2)
+///
+/// - v1: replica id and per-partition timestamps
+/// - v2+: `isolation_level`
+/// - v4+: per-partition `current_leader_epoch`
+/// - v6+: flexible
+/// - v7-v9: same layout, with more special timestamps
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOffsetsRequest {
+    pub replica_id: i32,
+    /// v2+; 0 (read uncommitted) before
+    pub isolation_level: i8,
+    pub topics: Vec<ListOffsetsTopic>,
+}
+
+/// Per-topic query of a [`ListOffsetsRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOffsetsTopic {
+    pub name: String,
+    pub partitions: Vec<ListOffsetsPartition>,
+}
+
+/// Per-partition query of a [`ListOffsetsRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOffsetsPartition {
+    pub partition_index: i32,
+    /// v4+; -1 when unknown
+    pub current_leader_epoch: i32,
+    /// A timestamp in milliseconds, or one of the special negative values
+    pub timestamp: i64,
+}
+
+impl ListOffsetsRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let replica_id = WireFormat::decode_i32(buffer)?;
+        let isolation_level = if version >= 2 {
+            WireFormat::decode_i8(buffer)?
+        } else {
+            0
+        };
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let partition_index = WireFormat::decode_i32(buffer)?;
+                let current_leader_epoch = if version >= 4 {
+                    WireFormat::decode_i32(buffer)?
+                } else {
+                    -1
+                };
+                let timestamp = WireFormat::decode_i64(buffer)?;
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(ListOffsetsPartition {
+                    partition_index,
+                    current_leader_epoch,
+                    timestamp,
+                })
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(ListOffsetsTopic { name, partitions })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            replica_id,
+            isolation_level,
+            topics,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for ListOffsetsRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        buffer.put_i32(self.replica_id);
+        if version >= 2 {
+            buffer.put_i8(self.isolation_level);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i32(partition.partition_index);
+                    if version >= 4 {
+                        buffer.put_i32(partition.current_leader_epoch);
+                    }
+                    buffer.put_i64(partition.timestamp);
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let partition_size = 4 + if version >= 4 { 4 } else { 0 } + 8 + tags;
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                WireFormat::flexible_string_size(&topic.name, flexible)
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + partition_size * topic.partitions.len()
+                    + tags
+            })
+            .sum();
+        4 + usize::from(version >= 2)
+            + WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for ListOffsetsRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// ListOffsets response (API key 2)
+///
+/// - v1: per-partition error code, timestamp and offset
+/// - v2+: `throttle_time_ms`
+/// - v4+: per-partition `leader_epoch`
+/// - v6+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOffsetsResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<ListOffsetsTopicResponse>,
+}
+
+/// Per-topic result of a [`ListOffsetsResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOffsetsTopicResponse {
+    pub name: String,
+    pub partitions: Vec<ListOffsetsPartitionResponse>,
+}
+
+/// Per-partition result of a [`ListOffsetsResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOffsetsPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: ErrorCode,
+    /// Timestamp of the returned offset, -1 for the special timestamps
+    pub timestamp: i64,
+    pub offset: i64,
+    /// v4+; -1 when unknown
+    pub leader_epoch: i32,
+}
+
+impl ListOffsetsPartitionResponse {
+    /// Creates a successful partition result
+    pub fn found(partition_index: i32, timestamp: i64, offset: i64) -> Self {
+        Self {
+            partition_index,
+            error_code: ErrorCode::NONE,
+            timestamp,
+            offset,
+            leader_epoch: -1,
+        }
+    }
+
+    /// Creates a failed partition result
+    pub fn error(partition_index: i32, error_code: impl Into<ErrorCode>) -> Self {
+        Self {
+            partition_index,
+            error_code: error_code.into(),
+            timestamp: -1,
+            offset: -1,
+            leader_epoch: -1,
+        }
+    }
+}
+
+impl ListOffsetsResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 2 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let partition_index = WireFormat::decode_i32(buffer)?;
+                let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+                let timestamp = WireFormat::decode_i64(buffer)?;
+                let offset = WireFormat::decode_i64(buffer)?;
+                let leader_epoch = if version >= 4 {
+                    WireFormat::decode_i32(buffer)?
+                } else {
+                    -1
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(ListOffsetsPartitionResponse {
+                    partition_index,
+                    error_code,
+                    timestamp,
+                    offset,
+                    leader_epoch,
+                })
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(ListOffsetsTopicResponse { name, partitions })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            topics,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for ListOffsetsResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 2 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i32(partition.partition_index);
+                    buffer.put_i16(partition.error_code.code());
+                    buffer.put_i64(partition.timestamp);
+                    buffer.put_i64(partition.offset);
+                    if version >= 4 {
+                        buffer.put_i32(partition.leader_epoch);
+                    }
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let partition_size = 4 + 2 + 8 + 8 + if version >= 4 { 4 } else { 0 } + tags;
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                WireFormat::flexible_string_size(&topic.name, flexible)
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + partition_size * topic.partitions.len()
+                    + tags
+            })
+            .sum();
+        let throttle_time = if version >= 2 { 4 } else { 0 };
+        throttle_time
+            + WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for ListOffsetsResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> ListOffsetsRequest {
+        ListOffsetsRequest {
+            replica_id: -1,
+            isolation_level: 1,
+            topics: vec![ListOffsetsTopic {
+                name: "orders".to_string(),
+                partitions: vec![
+                    ListOffsetsPartition {
+                        partition_index: 0,
+                        current_leader_epoch: 4,
+                        timestamp: LATEST_TIMESTAMP,
+                    },
+                    ListOffsetsPartition {
+                        partition_index: 1,
+                        current_leader_epoch: 4,
+                        timestamp: EARLIEST_TIMESTAMP,
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_v1_layout() {
+        let request = ListOffsetsRequest {
+            replica_id: -1,
+            isolation_level: 0,
+            topics: vec![ListOffsetsTopic {
+                name: "t".to_string(),
+                partitions: vec![ListOffsetsPartition {
+                    partition_index: 0,
+                    current_leader_epoch: -1,
+                    timestamp: EARLIEST_TIMESTAMP,
+                }],
+            }],
+        };
+        let expected = [
+            "ffffffff",         // replica_id
+            "00000001",         // topics
+            "000174",           // name
+            "00000001",         // partitions
+            "00000000",         // partition_index
+            "fffffffffffffffe", // timestamp
+        ]
+        .concat();
+        assert_eq!(hex::encode(request.encode(1).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in LIST_OFFSETS_MIN_VERSION..=LIST_OFFSETS_MAX_VERSION {
+            let mut expected = request();
+            if version < 2 {
+                expected.isolation_level = 0;
+            }
+            if version < 4 {
+                for partition in &mut expected.topics[0].partitions {
+                    partition.current_leader_epoch = -1;
+                }
+            }
+            let request = request();
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                ListOffsetsRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = ListOffsetsResponse {
+            throttle_time_ms: 2,
+            topics: vec![ListOffsetsTopicResponse {
+                name: "orders".to_string(),
+                partitions: vec![
+                    ListOffsetsPartitionResponse {
+                        leader_epoch: 4,
+                        ..ListOffsetsPartitionResponse::found(0, -1, 42)
+                    },
+                    ListOffsetsPartitionResponse::error(1, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION),
+                ],
+            }],
+        };
+        for version in LIST_OFFSETS_MIN_VERSION..=LIST_OFFSETS_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 2 {
+                expected.throttle_time_ms = 0;
+            }
+            if version < 4 {
+                expected.topics[0].partitions[0].leader_epoch = -1;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                ListOffsetsResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v0_is_rejected() {
+        assert!(request().encode(0).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::protocol::api_versions::ApiVersionRange;
+use crate::protocol::encoding::ProtocolEncodeVersioned;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::headers::RequestHeader;
+use crate::protocol::*;
+use bytes::{Bytes, BytesMut};
+
+/// Defines [`KafkaRequest`] and [`KafkaResponse`] from one
+/// `Variant => module::{Request, Response, MIN, MAX}` line per API
+macro_rules! kafka_messages {
+    ($($variant:ident => $module:ident::{$request:ident, $response:ident, $min:ident, $max:ident},)+) => {
+        /// A request body decoded for the API and version in its header
+        ///
+        /// APIs this crate has no message for, and versions outside the
+        /// range it decodes, are kept undecoded as [`KafkaRequest::Unknown`].
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum KafkaRequest {
+            $($variant($module::$request),)+
+            Unknown { api_key: i16, raw: Bytes },
+        }
+
+        impl KafkaRequest {
+            /// The versions decoded for each API, in api key order
+            pub const VERSION_RANGES: &'static [ApiVersionRange] = &[$(
+                ApiVersionRange {
+                    api_key: ApiKey::$variant.code(),
+                    min_version: $module::$min,
+                    max_version: $module::$max,
+                },
+            )+];
+
+            /// Decodes the body that follows `header`
+            ///
+            /// An unknown API or unsupported version takes the rest of
+            /// `buffer` as the raw body.
+            pub fn decode(header: &RequestHeader, buffer: &mut Bytes) -> ProtocolResult<Self> {
+                let version = header.api_version();
+                match header.api_key() {
+                    $(Ok(ApiKey::$variant) if ($module::$min..=$module::$max).contains(&version) => {
+                        $module::$request::decode(buffer, version).map(Self::$variant)
+                    })+
+                    _ => Ok(Self::Unknown {
+                        api_key: header.api_key_code(),
+                        raw: std::mem::take(buffer),
+                    }),
+                }
+            }
+
+            /// The api key the request was sent with
+            pub fn api_key(&self) -> i16 {
+                match self {
+                    $(Self::$variant(_) => ApiKey::$variant.code(),)+
+                    Self::Unknown { api_key, .. } => *api_key,
+                }
+            }
+        }
+
+        /// A response body, encoded at the version of the request it answers
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum KafkaResponse {
+            $($variant($module::$response),)+
+        }
+
+        impl KafkaResponse {
+            /// The API this response belongs to
+            pub fn api_key(&self) -> ApiKey {
+                match self {
+                    $(Self::$variant(_) => ApiKey::$variant,)+
+                }
+            }
+
+            /// Encodes the body into a new buffer of exactly its size
+            pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+                let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+                self.encode_versioned(version, &mut buffer)?;
+                Ok(buffer)
+            }
+        }
+
+        impl ProtocolEncodeVersioned for KafkaResponse {
+            fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+                match self {
+                    $(Self::$variant(response) => response.encode_versioned(version, buffer),)+
+                }
+            }
+
+            fn encoded_size(&self, version: i16) -> usize {
+                match self {
+                    $(Self::$variant(response) => {
+                        ProtocolEncodeVersioned::encoded_size(response, version)
+                    })+
+                }
+            }
+        }
+
+        $(
+            impl From<$module::$response> for KafkaResponse {
+                fn from(response: $module::$response) -> Self {
+                    Self::$variant(response)
+                }
+            }
+        )+
+    };
+}
+
+kafka_messages! {
+    Produce => produce::{ProduceRequest, ProduceResponse, PRODUCE_MIN_VERSION, PRODUCE_MAX_VERSION},
+    Fetch => fetch::{FetchRequest, FetchResponse, FETCH_MIN_VERSION, FETCH_MAX_VERSION},
+    ListOffsets => list_offsets::{
+        ListOffsetsRequest, ListOffsetsResponse, LIST_OFFSETS_MIN_VERSION, LIST_OFFSETS_MAX_VERSION
+    },
+    Metadata => metadata::{
+        MetadataRequest, MetadataResponse, METADATA_MIN_VERSION, METADATA_MAX_VERSION
+    },
+    OffsetCommit => offset_commit::{
+        OffsetCommitRequest, OffsetCommitResponse, OFFSET_COMMIT_MIN_VERSION,
+        OFFSET_COMMIT_MAX_VERSION
+    },
+    OffsetFetch => offset_fetch::{
+        OffsetFetchRequest, OffsetFetchResponse, OFFSET_FETCH_MIN_VERSION, OFFSET_FETCH_MAX_VERSION
+    },
+    FindCoordinator => find_coordinator::{
+        FindCoordinatorRequest, FindCoordinatorResponse, FIND_COORDINATOR_MIN_VERSION,
+        FIND_COORDINATOR_MAX_VERSION
+    },
+    JoinGroup => join_group::{
+        JoinGroupRequest, JoinGroupResponse, JOIN_GROUP_MIN_VERSION, JOIN_GROUP_MAX_VERSION
+    },
+    Heartbeat => heartbeat::{
+        HeartbeatRequest, HeartbeatResponse, HEARTBEAT_MIN_VERSION, HEARTBEAT_MAX_VERSION
+    },
+    LeaveGroup => leave_group::{
+        LeaveGroupRequest, LeaveGroupResponse, LEAVE_GROUP_MIN_VERSION, LEAVE_GROUP_MAX_VERSION
+    },
+    SyncGroup => sync_group::{
+        SyncGroupRequest, SyncGroupResponse, SYNC_GROUP_MIN_VERSION, SYNC_GROUP_MAX_VERSION
+    },
+    SaslHandshake => sasl_handshake::{
+        SaslHandshakeRequest, SaslHandshakeResponse, SASL_HANDSHAKE_MIN_VERSION,
+        SASL_HANDSHAKE_MAX_VERSION
+    },
+    ApiVersions => api_versions::{
+        ApiVersionsRequest, ApiVersionsResponse, API_VERSIONS_MIN_VERSION, API_VERSIONS_MAX_VERSION
+    },
+    CreateTopics => create_topics::{
+        CreateTopicsRequest, CreateTopicsResponse, CREATE_TOPICS_MIN_VERSION,
+        CREATE_TOPICS_MAX_VERSION
+    },
+    DeleteTopics => delete_topics::{
+        DeleteTopicsRequest, DeleteTopicsResponse, DELETE_TOPICS_MIN_VERSION,
+        DELETE_TOPICS_MAX_VERSION
+    },
+    InitProducerId => init_producer_id::{
+        InitProducerIdRequest, InitProducerIdResponse, INIT_PRODUCER_ID_MIN_VERSION,
+        INIT_PRODUCER_ID_MAX_VERSION
+    },
+    DescribeConfigs => describe_configs::{
+        DescribeConfigsRequest, DescribeConfigsResponse, DESCRIBE_CONFIGS_MIN_VERSION,
+        DESCRIBE_CONFIGS_MAX_VERSION
+    },
+    SaslAuthenticate => sasl_authenticate::{
+        SaslAuthenticateRequest, SaslAuthenticateResponse, SASL_AUTHENTICATE_MIN_VERSION,
+        SASL_AUTHENTICATE_MAX_VERSION
+    },
+    IncrementalAlterConfigs => incremental_alter_configs::{
+        IncrementalAlterConfigsRequest, IncrementalAlterConfigsResponse,
+        INCREMENTAL_ALTER_CONFIGS_MIN_VERSION, INCREMENTAL_ALTER_CONFIGS_MAX_VERSION
+    },
+    DescribeCluster => describe_cluster::{
+        DescribeClusterRequest, DescribeClusterResponse, DESCRIBE_CLUSTER_MIN_VERSION,
+        DESCRIBE_CLUSTER_MAX_VERSION
+    },
+    DescribeTopicPartitions => describe_topic_partitions::{
+        DescribeTopicPartitionsRequest, DescribeTopicPartitionsResponse,
+        DESCRIBE_TOPIC_PARTITIONS_MIN_VERSION, DESCRIBE_TOPIC_PARTITIONS_MAX_VERSION
+    },
+}
+
+impl KafkaResponse {
+    /// A response to `api_key` carrying nothing but `error_code`
+    ///
+    /// Lists are left empty and ids unset. The code goes wherever the
+    /// response has a top-level error, or a group or result standing in
+    /// for one; responses with only per-partition codes, such as Produce
+    /// and Metadata, carry none. `None` for APIs without a response type.
+    pub fn error(api_key: ApiKey, error_code: ErrorCode) -> Option<Self> {
+        let response = match api_key {
+            ApiKey::Produce => produce::ProduceResponse {
+                topics: Vec::new(),
+                throttle_time_ms: 0,
+            }
+            .into(),
+            ApiKey::Fetch => fetch::FetchResponse {
+                throttle_time_ms: 0,
+                error_code,
+                session_id: 0,
+                responses: Vec::new(),
+            }
+            .into(),
+            ApiKey::ListOffsets => list_offsets::ListOffsetsResponse {
+                throttle_time_ms: 0,
+                topics: Vec::new(),
+            }
+            .into(),
+            ApiKey::Metadata => metadata::MetadataResponse {
+                throttle_time_ms: 0,
+                brokers: Vec::new(),
+                cluster_id: None,
+                controller_id: -1,
+                topics: Vec::new(),
+                cluster_authorized_operations: i32::MIN,
+            }
+            .into(),
+            ApiKey::OffsetCommit => offset_commit::OffsetCommitResponse {
+                throttle_time_ms: 0,
+                topics: Vec::new(),
+            }
+            .into(),
+            ApiKey::OffsetFetch => offset_fetch::OffsetFetchResponse {
+                throttle_time_ms: 0,
+                groups: vec![offset_fetch::OffsetFetchResponseGroup::error(
+                    String::new(),
+                    error_code,
+                )],
+            }
+            .into(),
+            ApiKey::FindCoordinator => find_coordinator::FindCoordinatorResponse {
+                error_code,
+                ..find_coordinator::FindCoordinatorResponse::batched(Vec::new())
+            }
+            .into(),
+            ApiKey::JoinGroup => {
+                join_group::JoinGroupResponse::error(error_code, String::new()).into()
+            }
+            ApiKey::Heartbeat => heartbeat::HeartbeatResponse::new(error_code).into(),
+            ApiKey::LeaveGroup => leave_group::LeaveGroupResponse::error(error_code).into(),
+            ApiKey::SyncGroup => sync_group::SyncGroupResponse::error(error_code).into(),
+            ApiKey::SaslHandshake => sasl_handshake::SaslHandshakeResponse {
+                error_code,
+                mechanisms: Vec::new(),
+            }
+            .into(),
+            ApiKey::ApiVersions => api_versions::ApiVersionsResponse {
+                error_code,
+                api_keys: Vec::new(),
+                throttle_time_ms: 0,
+            }
+            .into(),
+            ApiKey::CreateTopics => create_topics::CreateTopicsResponse {
+                throttle_time_ms: 0,
+                topics: Vec::new(),
+            }
+            .into(),
+            ApiKey::DeleteTopics => delete_topics::DeleteTopicsResponse {
+                throttle_time_ms: 0,
+                responses: Vec::new(),
+            }
+            .into(),
+            ApiKey::InitProducerId => {
+                init_producer_id::InitProducerIdResponse::error(error_code).into()
+            }
+            ApiKey::DescribeConfigs => describe_configs::DescribeConfigsResponse {
+                throttle_time_ms: 0,
+                results: Vec::new(),
+            }
+            .into(),
+            ApiKey::SaslAuthenticate => sasl_authenticate::SaslAuthenticateResponse {
+                error_code,
+                error_message: None,
+                auth_bytes: Bytes::new(),
+                session_lifetime_ms: 0,
+            }
+            .into(),
+            ApiKey::IncrementalAlterConfigs => {
+                incremental_alter_configs::IncrementalAlterConfigsResponse {
+                    throttle_time_ms: 0,
+                    responses: Vec::new(),
+                }
+                .into()
+            }
+            ApiKey::DescribeCluster => describe_cluster::DescribeClusterResponse {
+                throttle_time_ms: 0,
+                error_code,
+                error_message: None,
+                endpoint_type: describe_cluster::ENDPOINT_TYPE_BROKERS,
+                cluster_id: String::new(),
+                controller_id: -1,
+                brokers: Vec::new(),
+                cluster_authorized_operations: i32::MIN,
+            }
+            .into(),
+            ApiKey::DescribeTopicPartitions => {
+                describe_topic_partitions::DescribeTopicPartitionsResponse {
+                    throttle_time_ms: 0,
+                    topics: Vec::new(),
+                    next_cursor: None,
+                }
+                .into()
+            }
+            _ => return None,
+        };
+        Some(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::headers::RequestHeaderV2;
+    use crate::protocol::heartbeat::{HeartbeatRequest, HeartbeatResponse};
+
+    fn header(api_key: i16, api_version: i16) -> RequestHeader {
+        RequestHeader::V2(RequestHeaderV2::new(api_key, api_version, 7, None))
+    }
+
+    #[test]
+    fn test_decodes_by_api_and_version() {
+        let request = HeartbeatRequest {
+            group_id: "group".to_string(),
+            generation_id: 3,
+            member_id: "member".to_string(),
+            group_instance_id: None,
+        };
+        let mut body = request.encode(4).unwrap().freeze();
+        let decoded =
+            KafkaRequest::decode(&header(ApiKey::Heartbeat.code(), 4), &mut body).unwrap();
+        assert_eq!(decoded, KafkaRequest::Heartbeat(request));
+        assert_eq!(decoded.api_key(), ApiKey::Heartbeat.code());
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_api_and_version_are_kept_raw() {
+        for (api_key, api_version) in [(1000, 0), (ApiKey::Heartbeat.code(), 99)] {
+            let mut body = Bytes::from_static(b"\x01\x02\x03");
+            let decoded = KafkaRequest::decode(&header(api_key, api_version), &mut body).unwrap();
+            assert_eq!(
+                decoded,
+                KafkaRequest::Unknown {
+                    api_key,
+                    raw: Bytes::from_static(b"\x01\x02\x03"),
+                }
+            );
+            assert!(body.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_version_ranges_are_in_api_key_order() {
+        let keys: Vec<_> = KafkaRequest::VERSION_RANGES
+            .iter()
+            .map(|range| range.api_key)
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(keys, sorted);
+        assert!(KafkaRequest::VERSION_RANGES.contains(&ApiVersionRange {
+            api_key: ApiKey::Fetch.code(),
+            min_version: fetch::FETCH_MIN_VERSION,
+            max_version: fetch::FETCH_MAX_VERSION,
+        }));
+    }
+
+    #[test]
+    fn test_error_responses_encode_at_every_version() {
+        for range in KafkaRequest::VERSION_RANGES {
+            let api_key = ApiKey::try_from(range.api_key).unwrap();
+            let response = KafkaResponse::error(api_key, ErrorCode::UNKNOWN_SERVER_ERROR).unwrap();
+            assert_eq!(response.api_key(), api_key);
+            for version in range.min_version..=range.max_version {
+                let encoded = response.encode(version).unwrap();
+                assert_eq!(encoded.len(), response.encoded_size(version));
+            }
+        }
+        assert_eq!(
+            KafkaResponse::error(ApiKey::ControlledShutdown, ErrorCode::UNKNOWN_SERVER_ERROR),
+            None
+        );
+    }
+
+    #[test]
+    fn test_response_encodes_like_its_message() {
+        let heartbeat = HeartbeatResponse::new(ErrorCode::REBALANCE_IN_PROGRESS);
+        let response = KafkaResponse::from(heartbeat.clone());
+        assert_eq!(response.api_key(), ApiKey::Heartbeat);
+        for version in [0, 4] {
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(encoded, heartbeat.encode(version).unwrap());
+            assert_eq!(encoded.len(), response.encoded_size(version));
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::protocol::encoding::WireFormat;
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Offset of the magic byte within a log entry (offset + size + crc)
+const MAGIC_OFFSET: usize = 8 + 4 + 4;
+
+/// Mask of the compression codec bits in the message attributes
+const COMPRESSION_CODEC_MASK: i8 = 0x07;
+
+/// A single message of a legacy (magic 0 or 1) MessageSet
+///
+/// MessageSet format, repeated until the end of the set:
+/// - offset: INT64
+/// - message_size: INT32
+/// - crc: UINT32 (CRC-32 of everything from magic to the end of the message)
+/// - magic: INT8 (0 or 1)
+/// - attributes: INT8 (low three bits are the compression codec)
+/// - timestamp: INT64 (magic 1 only)
+/// - key: NULLABLE_BYTES
+/// - value: NULLABLE_BYTES
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyMessage {
+    pub offset: i64,
+    pub magic: i8,
+    pub attributes: i8,
+    pub timestamp: Option<i64>,
+    pub key: Option<Bytes>,
+    pub value: Option<Bytes>,
+}
+
+/// Returns the magic byte of the first entry of a records payload
+///
+/// Legacy message sets and v2 record batches share the layout up to the
+/// magic byte, so this tells the two formats apart.
+pub fn records_magic(records: &[u8]) -> Option<i8> {
+    records.get(MAGIC_OFFSET).map(|&magic| magic as i8)
+}
+
+/// Decodes a legacy MessageSet, validating every message CRC
+///
+/// Compressed wrapper messages are rejected; only uncompressed legacy
+/// payloads are accepted. Keys and values are views into `records`.
+///
+/// # Examples
+/// ```
+/// use bytes::Bytes;
+/// use codecrafters_kafka::protocol::message_set::{
+///     decode_message_set, encode_message_set, records_magic, LegacyMessage,
+/// };
+///
+/// let message = LegacyMessage {
+///     offset: 0,
+///     magic: 1,
+///     attributes: 0,
+///     timestamp: Some(1_700_000_000_000),
+///     key: Some(Bytes::from_static(b"k")),
+///     value: Some(Bytes::from_static(b"hello")),
+/// };
+/// let records = encode_message_set(&[message.clone()]).unwrap().freeze();
+/// assert_eq!(records_magic(&records), Some(1));
+/// assert_eq!(decode_message_set(&records).unwrap(), vec![message]);
+///
+/// // A flipped bit fails the CRC check
+/// let mut corrupt = records.to_vec();
+/// *corrupt.last_mut().unwrap() ^= 0x01;
+/// assert!(decode_message_set(&Bytes::from(corrupt)).is_err());
+/// ```
+pub fn decode_message_set(records: &Bytes) -> ProtocolResult<Vec<LegacyMessage>> {
+    let mut buffer = records.clone();
+    let mut messages = Vec::new();
+
+    while buffer.has_remaining() {
+        let offset = WireFormat::decode_i64(&mut buffer)?;
+        let message_size = WireFormat::decode_i32(&mut buffer)?;
+        if message_size < 0 {
+            return Err(ProtocolError::invalid_length(message_size));
+        }
+        let message_size = message_size as usize;
+        if buffer.remaining() < message_size {
+            return Err(ProtocolError::insufficient_bytes(
+                message_size,
+                buffer.remaining(),
+            ));
+        }
+        let mut message = buffer.split_to(message_size);
+        messages.push(decode_message(offset, &mut message)?);
+    }
+
+    Ok(messages)
+}
+
+fn decode_message(offset: i64, message: &mut Bytes) -> ProtocolResult<LegacyMessage> {
+    if message.remaining() < 4 {
+        return Err(ProtocolError::insufficient_bytes(4, message.remaining()));
+    }
+    let crc = message.get_u32();
+    let computed = crc32fast::hash(&message[..]);
+    if crc != computed {
+        return Err(ProtocolError::InvalidFormat(format!(
+            "message at offset {} has crc {:#010x}, computed {:#010x}",
+            offset, crc, computed
+        )));
+    }
+
+    let magic = WireFormat::decode_u8(message)? as i8;
+    if magic > 1 {
+        return Err(ProtocolError::InvalidFormat(format!(
+            "unexpected magic {} in legacy message set",
+            magic
+        )));
+    }
+    let attributes = WireFormat::decode_u8(message)? as i8;
+    if attributes & COMPRESSION_CODEC_MASK != 0 {
+        return Err(ProtocolError::InvalidFormat(
+            "compressed legacy message sets are not supported".to_string(),
+        ));
+    }
+    let timestamp = if magic == 1 {
+        Some(WireFormat::decode_i64(message)?)
+    } else {
+        None
+    };
+    let key = WireFormat::decode_nullable_bytes(message)?;
+    let value = WireFormat::decode_nullable_bytes(message)?;
+    if message.has_remaining() {
+        return Err(ProtocolError::InvalidFormat(format!(
+            "{} trailing bytes in message at offset {}",
+            message.remaining(),
+            offset
+        )));
+    }
+
+    Ok(LegacyMessage {
+        offset,
+        magic,
+        attributes,
+        timestamp,
+        key,
+        value,
+    })
+}
+
+/// Encodes messages as a legacy MessageSet
+pub fn encode_message_set(messages: &[LegacyMessage]) -> ProtocolResult<BytesMut> {
+    let mut buffer = BytesMut::new();
+    for message in messages {
+        let mut body = BytesMut::new();
+        body.put_i8(message.magic);
+        body.put_i8(message.attributes);
+        if message.magic >= 1 {
+            body.put_i64(message.timestamp.unwrap_or(-1));
+        }
+        WireFormat::encode_nullable_bytes(&mut body, message.key.as_deref())?;
+        WireFormat::encode_nullable_bytes(&mut body, message.value.as_deref())?;
+
+        buffer.put_i64(message.offset);
+        buffer.put_i32(4 + body.len() as i32);
+        buffer.put_u32(crc32fast::hash(&body));
+        buffer.put_slice(&body);
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(magic: i8, value: &'static [u8]) -> LegacyMessage {
+        LegacyMessage {
+            offset: 0,
+            magic,
+            attributes: 0,
+            timestamp: (magic == 1).then_some(1_700_000_000_000),
+            key: None,
+            value: Some(Bytes::from_static(value)),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_v0_and_v1_messages() {
+        let messages = vec![message(0, b"hello"), message(1, b"world")];
+        let encoded = encode_message_set(&messages).unwrap();
+
+        assert_eq!(records_magic(&encoded), Some(0));
+        let encoded = encoded.freeze();
+        assert_eq!(decode_message_set(&encoded).unwrap(), messages);
+    }
+
+    #[test]
+    fn test_crc_mismatch_is_rejected() {
+        let mut encoded = encode_message_set(&[message(1, b"hello")]).unwrap();
+        let last = encoded.len() - 1;
+        encoded[last] ^= 0xFF;
+
+        let encoded = encoded.freeze();
+        assert!(matches!(
+            decode_message_set(&encoded),
+            Err(ProtocolError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_compressed_wrapper_is_rejected() {
+        let mut compressed = message(1, b"hello");
+        compressed.attributes = 1; // gzip
+        let encoded = encode_message_set(&[compressed]).unwrap();
+
+        let encoded = encoded.freeze();
+        assert!(decode_message_set(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_truncated_message_is_rejected() {
+        let encoded = encode_message_set(&[message(0, b"hello")])
+            .unwrap()
+            .freeze();
+        assert!(decode_message_set(&encoded.slice(..encoded.len() - 2)).is_err());
+    }
+}
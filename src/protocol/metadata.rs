@@ -0,0 +1,786 @@
+use crate::protocol::api_key::ApiKey;
+use crate::protocol::describe_topic_partitions::AUTHORIZED_OPERATIONS_OMITTED;
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::flexible;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use crate::protocol::trace::DecodeCursor;
+use bytes::{BufMut, Bytes, BytesMut};
+use uuid::Uuid;
+
+/// Lowest Metadata version we serve
+pub const METADATA_MIN_VERSION: i16 = 0;
+
+/// Highest Metadata version we serve
+pub const METADATA_MAX_VERSION: i16 = 12;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "Metadata",
+        version,
+        METADATA_MIN_VERSION..=METADATA_MAX_VERSION,
+    )
+}
+
+/// A topic named in a Metadata request
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataRequestTopic {
+    /// v10+; nil when the topic is named
+    pub topic_id: Uuid,
+    /// Nullable from v10, when the topic may be given by id instead
+    pub name: Option<String>,
+}
+
+/// Metadata request (API key 3)
+///
+/// - v0: topic names, with an empty list meaning every topic
+/// - v1+: a null topic list means every topic
+/// - v4+: `allow_auto_topic_creation`
+/// - v8+: authorized operations flags (the cluster one until v10)
+/// - v9+: flexible
+/// - v10+: topic ids
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataRequest {
+    pub topics: Option<Vec<MetadataRequestTopic>>,
+    pub allow_auto_topic_creation: bool,
+    pub include_cluster_authorized_operations: bool,
+    pub include_topic_authorized_operations: bool,
+}
+
+impl MetadataRequest {
+    /// Encodes the body for `version`
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        self.encode_body(version, version >= Self::first_flexible_version())
+    }
+
+    /// Decodes the body for `version`, reporting a body encoded for the
+    /// wrong header flexibility as such
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        flexible::decode_body(
+            ApiKey::Metadata.code(),
+            version,
+            version >= Self::first_flexible_version(),
+            buffer,
+            |buffer, flexible| Self::decode_body(buffer, version, flexible),
+        )
+    }
+
+    fn first_flexible_version() -> i16 {
+        flexible::first_flexible_version(ApiKey::Metadata.code()).unwrap()
+    }
+
+    /// Encodes the fields of `version` in the given mode
+    ///
+    /// Separate from the version so tests can produce mismatched bodies.
+    pub fn encode_body(&self, version: i16, flexible: bool) -> ProtocolResult<BytesMut> {
+        check_version(version)?;
+        let mut buffer = BytesMut::new();
+        let encode_topic = |buffer: &mut BytesMut, topic: &MetadataRequestTopic| {
+            if flexible {
+                if version >= 10 {
+                    WireFormat::encode_uuid(buffer, &topic.topic_id);
+                }
+                WireFormat::encode_compact_nullable_string(buffer, topic.name.as_deref())?;
+                WireFormat::encode_unsigned_varint(buffer, 0);
+            } else {
+                WireFormat::encode_string(buffer, topic.name.as_deref().unwrap_or_default())?;
+            }
+            Ok(())
+        };
+        // v0 has no null list: an empty one asks for every topic
+        let topics = match &self.topics {
+            None if version == 0 => Some(&[][..]),
+            topics => topics.as_deref(),
+        };
+        if flexible {
+            WireFormat::encode_compact_nullable_array(&mut buffer, topics, encode_topic)?;
+        } else {
+            WireFormat::encode_nullable_array(&mut buffer, topics, encode_topic)?;
+        }
+        if version >= 4 {
+            WireFormat::encode_bool(&mut buffer, self.allow_auto_topic_creation);
+        }
+        if (8..=10).contains(&version) {
+            WireFormat::encode_bool(&mut buffer, self.include_cluster_authorized_operations);
+        }
+        if version >= 8 {
+            WireFormat::encode_bool(&mut buffer, self.include_topic_authorized_operations);
+        }
+        if flexible {
+            WireFormat::encode_unsigned_varint(&mut buffer, 0);
+        }
+        Ok(buffer)
+    }
+
+    /// Decodes the fields of `version` in the given mode
+    pub fn decode_body(buffer: &mut Bytes, version: i16, flexible: bool) -> ProtocolResult<Self> {
+        Self::decode_from(&mut DecodeCursor::new(buffer), version, flexible)
+    }
+
+    /// Decodes the fields of `version` from a cursor, which may be tracing
+    pub fn decode_from(
+        cursor: &mut DecodeCursor<'_>,
+        version: i16,
+        flexible: bool,
+    ) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let topics = cursor.nullable_array("topics", flexible, |cursor| {
+            if flexible {
+                let topic_id = if version >= 10 {
+                    cursor.field("topic_id", WireFormat::decode_uuid)?
+                } else {
+                    Uuid::nil()
+                };
+                let name = cursor.field("name", WireFormat::decode_compact_nullable_string)?;
+                cursor.skip_tagged_fields()?;
+                Ok(MetadataRequestTopic { topic_id, name })
+            } else {
+                Ok(MetadataRequestTopic {
+                    topic_id: Uuid::nil(),
+                    name: Some(cursor.field("name", WireFormat::decode_string)?),
+                })
+            }
+        })?;
+        let mut decode_bool = |name: &str, present: bool, default: bool| {
+            if present {
+                cursor.field(name, WireFormat::decode_bool)
+            } else {
+                Ok(default)
+            }
+        };
+        let allow_auto_topic_creation =
+            decode_bool("allow_auto_topic_creation", version >= 4, true)?;
+        let include_cluster_authorized_operations = decode_bool(
+            "include_cluster_authorized_operations",
+            (8..=10).contains(&version),
+            false,
+        )?;
+        let include_topic_authorized_operations =
+            decode_bool("include_topic_authorized_operations", version >= 8, false)?;
+        if flexible {
+            cursor.skip_tagged_fields()?;
+        }
+        Ok(Self {
+            topics: match topics {
+                Some(topics) if version == 0 && topics.is_empty() => None,
+                topics => topics,
+            },
+            allow_auto_topic_creation,
+            include_cluster_authorized_operations,
+            include_topic_authorized_operations,
+        })
+    }
+}
+
+/// Metadata response (API key 3)
+///
+/// - v1+: broker racks, `controller_id` and `is_internal`
+/// - v2+: `cluster_id`
+/// - v3+: `throttle_time_ms`
+/// - v5+: per-partition `offline_replicas`
+/// - v7+: per-partition `leader_epoch`
+/// - v8+: authorized operations (the cluster ones until v10)
+/// - v9+: flexible
+/// - v10+: topic ids
+/// - v12+: nullable topic names
+///
+/// Fields a version does not carry are left out on encode and take their
+/// default on decode. Tagged fields are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataResponse {
+    pub throttle_time_ms: i32,
+    pub brokers: Vec<MetadataResponseBroker>,
+    pub cluster_id: Option<String>,
+    /// -1 when there is no active controller
+    pub controller_id: i32,
+    pub topics: Vec<MetadataResponseTopic>,
+    pub cluster_authorized_operations: i32,
+}
+
+/// A broker listed in a [`MetadataResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataResponseBroker {
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+    pub rack: Option<String>,
+}
+
+/// Per-topic result of a [`MetadataResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataResponseTopic {
+    pub error_code: ErrorCode,
+    pub name: Option<String>,
+    /// Nil for a topic that does not exist
+    pub topic_id: Uuid,
+    pub is_internal: bool,
+    pub partitions: Vec<MetadataResponsePartition>,
+    pub topic_authorized_operations: i32,
+}
+
+impl MetadataResponseTopic {
+    /// The result for a requested topic that does not exist
+    ///
+    /// A topic asked for by name is echoed with UNKNOWN_TOPIC_OR_PARTITION;
+    /// one asked for by id alone gets UNKNOWN_TOPIC_ID.
+    pub fn unknown(name: Option<String>, topic_id: Uuid) -> Self {
+        let error_code = if name.is_some() {
+            ErrorCode::UNKNOWN_TOPIC_OR_PARTITION
+        } else {
+            ErrorCode::UNKNOWN_TOPIC_ID
+        };
+        Self {
+            error_code,
+            name,
+            topic_id,
+            is_internal: false,
+            partitions: Vec::new(),
+            topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        }
+    }
+}
+
+/// Per-partition result of a [`MetadataResponseTopic`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataResponsePartition {
+    pub error_code: ErrorCode,
+    pub partition_index: i32,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub replica_nodes: Vec<i32>,
+    pub isr_nodes: Vec<i32>,
+    pub offline_replicas: Vec<i32>,
+}
+
+impl MetadataResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= MetadataRequest::first_flexible_version();
+        let throttle_time_ms = if version >= 3 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let brokers = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let broker = MetadataResponseBroker {
+                node_id: WireFormat::decode_i32(buffer)?,
+                host: WireFormat::decode_flexible_string(buffer, flexible)?,
+                port: WireFormat::decode_i32(buffer)?,
+                rack: if version >= 1 {
+                    WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+                } else {
+                    None
+                },
+            };
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(broker)
+        })?;
+        let cluster_id = if version >= 2 {
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+        } else {
+            None
+        };
+        let controller_id = if version >= 1 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            -1
+        };
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+            let name = if version >= 12 {
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+            } else {
+                Some(WireFormat::decode_flexible_string(buffer, flexible)?)
+            };
+            let topic_id = if version >= 10 {
+                WireFormat::decode_uuid(buffer)?
+            } else {
+                Uuid::nil()
+            };
+            let is_internal = version >= 1 && WireFormat::decode_bool(buffer)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let node_ids = |buffer: &mut Bytes| {
+                    WireFormat::decode_flexible_array(buffer, flexible, WireFormat::decode_i32)
+                };
+                let partition = MetadataResponsePartition {
+                    error_code: ErrorCode::from_wire(WireFormat::decode_i16(buffer)?),
+                    partition_index: WireFormat::decode_i32(buffer)?,
+                    leader_id: WireFormat::decode_i32(buffer)?,
+                    leader_epoch: if version >= 7 {
+                        WireFormat::decode_i32(buffer)?
+                    } else {
+                        -1
+                    },
+                    replica_nodes: node_ids(buffer)?,
+                    isr_nodes: node_ids(buffer)?,
+                    offline_replicas: if version >= 5 {
+                        node_ids(buffer)?
+                    } else {
+                        Vec::new()
+                    },
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(partition)
+            })?;
+            let topic_authorized_operations = if version >= 8 {
+                WireFormat::decode_i32(buffer)?
+            } else {
+                AUTHORIZED_OPERATIONS_OMITTED
+            };
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(MetadataResponseTopic {
+                error_code,
+                name,
+                topic_id,
+                is_internal,
+                partitions,
+                topic_authorized_operations,
+            })
+        })?;
+        let cluster_authorized_operations = if (8..=10).contains(&version) {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            AUTHORIZED_OPERATIONS_OMITTED
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            brokers,
+            cluster_id,
+            controller_id,
+            topics,
+            cluster_authorized_operations,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for MetadataResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= MetadataRequest::first_flexible_version();
+        if version >= 3 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.brokers, flexible, |buffer, broker| {
+            buffer.put_i32(broker.node_id);
+            WireFormat::encode_flexible_string(buffer, &broker.host, flexible)?;
+            buffer.put_i32(broker.port);
+            if version >= 1 {
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    broker.rack.as_deref(),
+                    flexible,
+                )?;
+            }
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        if version >= 2 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.cluster_id.as_deref(),
+                flexible,
+            )?;
+        }
+        if version >= 1 {
+            buffer.put_i32(self.controller_id);
+        }
+        let encode_node_id = |buffer: &mut BytesMut, node_id: &i32| {
+            buffer.put_i32(*node_id);
+            Ok(())
+        };
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            buffer.put_i16(topic.error_code.code());
+            if version >= 12 {
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    topic.name.as_deref(),
+                    flexible,
+                )?;
+            } else {
+                WireFormat::encode_flexible_string(
+                    buffer,
+                    topic.name.as_deref().unwrap_or_default(),
+                    flexible,
+                )?;
+            }
+            if version >= 10 {
+                WireFormat::encode_uuid(buffer, &topic.topic_id);
+            }
+            if version >= 1 {
+                WireFormat::encode_bool(buffer, topic.is_internal);
+            }
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i16(partition.error_code.code());
+                    buffer.put_i32(partition.partition_index);
+                    buffer.put_i32(partition.leader_id);
+                    if version >= 7 {
+                        buffer.put_i32(partition.leader_epoch);
+                    }
+                    WireFormat::encode_flexible_array(
+                        buffer,
+                        &partition.replica_nodes,
+                        flexible,
+                        encode_node_id,
+                    )?;
+                    WireFormat::encode_flexible_array(
+                        buffer,
+                        &partition.isr_nodes,
+                        flexible,
+                        encode_node_id,
+                    )?;
+                    if version >= 5 {
+                        WireFormat::encode_flexible_array(
+                            buffer,
+                            &partition.offline_replicas,
+                            flexible,
+                            encode_node_id,
+                        )?;
+                    }
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            if version >= 8 {
+                buffer.put_i32(topic.topic_authorized_operations);
+            }
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        if (8..=10).contains(&version) {
+            buffer.put_i32(self.cluster_authorized_operations);
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= MetadataRequest::first_flexible_version();
+        let tags = usize::from(flexible);
+        let node_ids_size = |node_ids: &[i32]| {
+            WireFormat::flexible_array_length_size(Some(node_ids.len()), flexible)
+                + 4 * node_ids.len()
+        };
+        let brokers: usize = self
+            .brokers
+            .iter()
+            .map(|broker| {
+                4 + WireFormat::flexible_string_size(&broker.host, flexible)
+                    + 4
+                    + if version >= 1 {
+                        WireFormat::flexible_nullable_string_size(broker.rack.as_deref(), flexible)
+                    } else {
+                        0
+                    }
+                    + tags
+            })
+            .sum();
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                let partitions: usize = topic
+                    .partitions
+                    .iter()
+                    .map(|partition| {
+                        2 + 4
+                            + 4
+                            + if version >= 7 { 4 } else { 0 }
+                            + node_ids_size(&partition.replica_nodes)
+                            + node_ids_size(&partition.isr_nodes)
+                            + if version >= 5 {
+                                node_ids_size(&partition.offline_replicas)
+                            } else {
+                                0
+                            }
+                            + tags
+                    })
+                    .sum();
+                let name = if version >= 12 {
+                    WireFormat::flexible_nullable_string_size(topic.name.as_deref(), flexible)
+                } else {
+                    WireFormat::flexible_string_size(
+                        topic.name.as_deref().unwrap_or_default(),
+                        flexible,
+                    )
+                };
+                2 + name
+                    + if version >= 10 { 16 } else { 0 }
+                    + if version >= 1 { 1 } else { 0 }
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + partitions
+                    + if version >= 8 { 4 } else { 0 }
+                    + tags
+            })
+            .sum();
+        let throttle_time = if version >= 3 { 4 } else { 0 };
+        let cluster_id = if version >= 2 {
+            WireFormat::flexible_nullable_string_size(self.cluster_id.as_deref(), flexible)
+        } else {
+            0
+        };
+        let controller_id = if version >= 1 { 4 } else { 0 };
+        let cluster_authorized_operations = if (8..=10).contains(&version) { 4 } else { 0 };
+        throttle_time
+            + WireFormat::flexible_array_length_size(Some(self.brokers.len()), flexible)
+            + brokers
+            + cluster_id
+            + controller_id
+            + WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + cluster_authorized_operations
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for MetadataResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::errors::ProtocolError;
+
+    fn request() -> MetadataRequest {
+        MetadataRequest {
+            topics: Some(vec![
+                MetadataRequestTopic {
+                    topic_id: Uuid::nil(),
+                    name: Some("orders".to_string()),
+                },
+                MetadataRequestTopic {
+                    topic_id: Uuid::nil(),
+                    name: Some("payments".to_string()),
+                },
+            ]),
+            allow_auto_topic_creation: false,
+            include_cluster_authorized_operations: false,
+            include_topic_authorized_operations: true,
+        }
+    }
+
+    fn assert_mismatch(
+        result: ProtocolResult<MetadataRequest>,
+        version: i16,
+        expected_header_flexible: bool,
+    ) {
+        match result {
+            Err(error @ ProtocolError::FlexibilityMismatch { .. }) => {
+                let message = error.to_string();
+                assert!(message.contains(&format!("v{}", version)), "{}", message);
+                let ProtocolError::FlexibilityMismatch {
+                    api_key,
+                    header_flexible,
+                    ..
+                } = error
+                else {
+                    unreachable!()
+                };
+                assert_eq!(api_key, ApiKey::Metadata.code());
+                assert_eq!(header_flexible, expected_header_flexible);
+            }
+            other => panic!("expected a flexibility mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_every_version() {
+        for version in 0..=METADATA_MAX_VERSION {
+            let mut expected = request();
+            if version < 4 {
+                expected.allow_auto_topic_creation = true;
+            }
+            if version < 8 {
+                expected.include_topic_authorized_operations = false;
+            }
+            let mut encoded = expected.encode(version).unwrap().freeze();
+            assert_eq!(
+                MetadataRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "version {}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_compact_body_behind_non_flexible_header() {
+        let before = flexible::flexibility_mismatch_count();
+        let mut body = request().encode_body(8, true).unwrap().freeze();
+        assert_mismatch(MetadataRequest::decode(&mut body, 8), 8, false);
+        assert!(flexible::flexibility_mismatch_count() > before);
+    }
+
+    #[test]
+    fn test_classic_body_behind_flexible_header() {
+        let mut body = request().encode_body(9, false).unwrap().freeze();
+        assert_mismatch(MetadataRequest::decode(&mut body, 9), 9, true);
+    }
+
+    #[test]
+    fn test_garbage_is_not_reported_as_mismatch() {
+        let mut body = Bytes::from_static(&[0xff, 0xff, 0xff, 0x00, 0x01]);
+        assert!(!matches!(
+            MetadataRequest::decode(&mut body, 9),
+            Err(ProtocolError::FlexibilityMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_error_names_nested_field() {
+        let encoded = request().encode(9).unwrap();
+        // Cut into the second topic's name
+        let mut truncated = Bytes::copy_from_slice(&encoded[..12]);
+        let error = MetadataRequest::decode_body(&mut truncated, 9, true).unwrap_err();
+        assert_eq!(error.path(), Some("topics[1].name"));
+        assert_eq!(error.offset(), Some(9));
+    }
+
+    fn response() -> MetadataResponse {
+        MetadataResponse {
+            throttle_time_ms: 7,
+            brokers: vec![MetadataResponseBroker {
+                node_id: 1,
+                host: "localhost".to_string(),
+                port: 9092,
+                rack: Some("rack-a".to_string()),
+            }],
+            cluster_id: Some("cluster".to_string()),
+            controller_id: 1,
+            topics: vec![
+                MetadataResponseTopic {
+                    error_code: ErrorCode::NONE,
+                    name: Some("orders".to_string()),
+                    topic_id: Uuid::from_u128(0x42),
+                    is_internal: false,
+                    partitions: vec![MetadataResponsePartition {
+                        error_code: ErrorCode::NONE,
+                        partition_index: 0,
+                        leader_id: 1,
+                        leader_epoch: 3,
+                        replica_nodes: vec![1],
+                        isr_nodes: vec![1],
+                        offline_replicas: vec![2],
+                    }],
+                    topic_authorized_operations: 0x0f,
+                },
+                MetadataResponseTopic::unknown(Some("missing".to_string()), Uuid::nil()),
+            ],
+            cluster_authorized_operations: 0x1f,
+        }
+    }
+
+    /// What `response()` looks like after a trip through `version`
+    fn response_for(version: i16) -> MetadataResponse {
+        let mut expected = response();
+        if version < 1 {
+            expected.brokers[0].rack = None;
+            expected.controller_id = -1;
+        }
+        if version < 2 {
+            expected.cluster_id = None;
+        }
+        if version < 3 {
+            expected.throttle_time_ms = 0;
+        }
+        for topic in &mut expected.topics {
+            if version < 8 {
+                topic.topic_authorized_operations = AUTHORIZED_OPERATIONS_OMITTED;
+            }
+            if version < 10 {
+                topic.topic_id = Uuid::nil();
+            }
+            for partition in &mut topic.partitions {
+                if version < 5 {
+                    partition.offline_replicas.clear();
+                }
+                if version < 7 {
+                    partition.leader_epoch = -1;
+                }
+            }
+        }
+        if !(8..=10).contains(&version) {
+            expected.cluster_authorized_operations = AUTHORIZED_OPERATIONS_OMITTED;
+        }
+        expected
+    }
+
+    #[test]
+    fn test_response_round_trip_every_version() {
+        for version in METADATA_MIN_VERSION..=METADATA_MAX_VERSION {
+            let response = response();
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "version {}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                MetadataResponse::decode(&mut encoded, version).unwrap(),
+                response_for(version),
+                "version {}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_v1_layout() {
+        let response = MetadataResponse {
+            throttle_time_ms: 0,
+            brokers: vec![MetadataResponseBroker {
+                node_id: 1,
+                host: "h".to_string(),
+                port: 9092,
+                rack: None,
+            }],
+            cluster_id: None,
+            controller_id: 1,
+            topics: vec![MetadataResponseTopic::unknown(
+                Some("t".to_string()),
+                Uuid::nil(),
+            )],
+            cluster_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        };
+        let expected = [
+            "00000001", // brokers
+            "00000001", "0001", "68", "00002384", "ffff",     // node, host, port, rack
+            "00000001", // controller_id
+            "00000001", // topics
+            "0003", "0001", "74", "00",       // error, name, is_internal
+            "00000000", // partitions
+        ]
+        .concat();
+        assert_eq!(hex::encode(response.encode(1).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_unknown_topic_by_id() {
+        let topic = MetadataResponseTopic::unknown(None, Uuid::from_u128(9));
+        assert_eq!(topic.error_code, ErrorCode::UNKNOWN_TOPIC_ID);
+        let named = MetadataResponseTopic::unknown(Some("t".to_string()), Uuid::nil());
+        assert_eq!(named.error_code, ErrorCode::UNKNOWN_TOPIC_OR_PARTITION);
+    }
+}
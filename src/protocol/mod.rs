@@ -1,5 +1,3 @@
-#![allow(dead_code)]
-
 //! Kafka Protocol Implementation
 //!
 //! This module provides a comprehensive implementation of the Kafka wire protocol,
@@ -9,41 +7,103 @@
 //!
 //! The protocol module is organized into several submodules:
 //! - `errors`: Protocol-specific error types and result types
+//! - `decode_limits`: Process-wide strictness settings for decoding
 //! - `encoding`: Traits and utilities for encoding/decoding protocol messages
 //! - `headers`: Request and response header implementations
+//! - `flexible`: Which versions are flexible, and spotting bodies encoded
+//!   for the wrong one
+//! - `api_key`: The APIs known by name
+//! - `error_code`: The error codes carried in responses, with names and retriability
+//! - `api_versions`: ApiVersions request and response, and version negotiation
+//! - `describe_topic_partitions`: DescribeTopicPartitions request and response
+//! - `fetch`: Fetch request and response messages
+//! - `metadata`: Metadata request messages
+//! - `message`: Requests and responses of every API as one typed value
+//! - `message_set`: Legacy (magic 0 and 1) MessageSet records
+//! - `record_batch`: v2 record batches as carried by Produce
+//! - `compression`: Codecs of compressed record batches
+//! - `produce`: Produce request and response messages
+//! - `tagged_fields`: Tag sections of flexible messages, unknown tags kept
+//! - `trace`: Field-by-field decode traces for tooling
 //!
 //! # Examples
 //!
-//! ```rust
-//! use crate::protocol::{RequestHeaderV2, ResponseHeaderV0, ProtocolEncode};
+//! ```
+//! use codecrafters_kafka::protocol::{
+//!     ProtocolDecode, ProtocolEncode, RequestHeaderV2, ResponseHeaderV0,
+//! };
 //!
 //! // Create a request header with client ID
-//! let request = RequestHeaderV2::with_client_id(1, 2, 42, "my-client");
+//! let request = RequestHeaderV2::with_client_id(18, 3, 42, "my-client");
 //!
-//! // Encode it to bytes
-//! let encoded = request.encode().unwrap();
+//! // Encode it to bytes, then decode it back from the frozen frame
+//! let mut encoded = request.encode().unwrap().freeze();
+//! assert_eq!(RequestHeaderV2::decode(&mut encoded).unwrap(), request);
+//! assert!(encoded.is_empty());
 //!
 //! // Create a response header
-//! let response = ResponseHeaderV0::new(100, 42);
-//! let response_bytes = response.encode().unwrap();
+//! let response = ResponseHeaderV0::new(42);
+//! assert_eq!(&response.encode().unwrap()[..], &[0, 0, 0, 42]);
 //! ```
 
+pub mod api_key;
+pub mod api_versions;
+pub mod compression;
+pub mod create_topics;
+pub mod decode_limits;
+pub mod delete_topics;
+pub mod describe_cluster;
+pub mod describe_configs;
+pub mod describe_topic_partitions;
 pub mod encoding;
+pub mod error_code;
 pub mod errors;
+pub mod fetch;
+pub mod find_coordinator;
+pub mod flexible;
 pub mod headers;
+pub mod heartbeat;
+pub mod incremental_alter_configs;
+pub mod init_producer_id;
+pub mod join_group;
+pub mod leave_group;
+pub mod list_offsets;
+pub mod message;
+pub mod message_set;
+pub mod metadata;
+pub mod offset_commit;
+pub mod offset_fetch;
+pub mod produce;
+pub mod record_batch;
+pub mod sasl_authenticate;
+pub mod sasl_handshake;
+pub mod sync_group;
+pub mod tagged_fields;
+pub mod trace;
 
 // Re-export commonly used types for convenience
-pub use encoding::{ProtocolDecode, ProtocolEncode, WireFormat};
+pub use api_key::ApiKey;
+pub use encoding::{
+    ProtocolDecode, ProtocolDecodeVersioned, ProtocolEncode, ProtocolEncodeVersioned, WireFormat,
+};
+pub use error_code::{ErrorCode, KafkaErrorCode};
 pub use errors::{ProtocolError, ProtocolResult};
-pub use headers::{RequestHeaderV2, ResponseHeaderV0};
+pub use headers::{
+    response_header_version, RequestHeader, RequestHeaderV0, RequestHeaderV1, RequestHeaderV2,
+    ResponseHeader, ResponseHeaderV0, ResponseHeaderV1,
+};
+pub use message::{KafkaRequest, KafkaResponse};
+pub use tagged_fields::TaggedFields;
+// UUID fields (topic ids and the like) use the uuid crate type directly
+pub use uuid::Uuid;
 
 // Backward compatibility functions for the old protocol.rs interface
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 
 /// Decodes a NULLABLE_STRING from the buffer
 ///
 /// This is a backward compatibility function that delegates to WireFormat::decode_nullable_string
-pub fn decode_nullable_string(buffer: &mut BytesMut) -> ProtocolResult<Option<String>> {
+pub fn decode_nullable_string(buffer: &mut Bytes) -> ProtocolResult<Option<String>> {
     WireFormat::decode_nullable_string(buffer)
 }
 
@@ -63,113 +123,68 @@ pub mod spec {
     pub const NULL_STRING_MARKER: i16 = -1;
 
     /// Common API keys used in Kafka protocol
+    ///
+    /// Superseded by [`ApiKey`](super::ApiKey), which can be matched
+    /// exhaustively and prints its name.
     pub mod api_keys {
-        pub const PRODUCE: i16 = 0;
-        pub const FETCH: i16 = 1;
-        pub const LIST_OFFSETS: i16 = 2;
-        pub const METADATA: i16 = 3;
-        pub const LEADER_AND_ISR: i16 = 4;
-        pub const STOP_REPLICA: i16 = 5;
-        pub const UPDATE_METADATA: i16 = 6;
-        pub const CONTROLLED_SHUTDOWN: i16 = 7;
-        pub const OFFSET_COMMIT: i16 = 8;
-        pub const OFFSET_FETCH: i16 = 9;
-        pub const FIND_COORDINATOR: i16 = 10;
-        pub const JOIN_GROUP: i16 = 11;
-        pub const HEARTBEAT: i16 = 12;
-        pub const LEAVE_GROUP: i16 = 13;
-        pub const SYNC_GROUP: i16 = 14;
-        pub const DESCRIBE_GROUPS: i16 = 15;
-        pub const LIST_GROUPS: i16 = 16;
-        pub const SASL_HANDSHAKE: i16 = 17;
-        pub const API_VERSIONS: i16 = 18;
-    }
-
-    /// Common error codes used in Kafka protocol
-    pub mod error_codes {
-        pub const NONE: i16 = 0;
-        pub const OFFSET_OUT_OF_RANGE: i16 = 1;
-        pub const CORRUPT_MESSAGE: i16 = 2;
-        pub const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
-        pub const INVALID_FETCH_SIZE: i16 = 4;
-        pub const LEADER_NOT_AVAILABLE: i16 = 5;
-        pub const NOT_LEADER_FOR_PARTITION: i16 = 6;
-        pub const REQUEST_TIMED_OUT: i16 = 7;
-        pub const BROKER_NOT_AVAILABLE: i16 = 8;
-        pub const REPLICA_NOT_AVAILABLE: i16 = 9;
-        pub const MESSAGE_TOO_LARGE: i16 = 10;
-        pub const STALE_CONTROLLER_EPOCH: i16 = 11;
-        pub const OFFSET_METADATA_TOO_LARGE: i16 = 12;
-        pub const NETWORK_EXCEPTION: i16 = 13;
-        pub const COORDINATOR_LOAD_IN_PROGRESS: i16 = 14;
-        pub const COORDINATOR_NOT_AVAILABLE: i16 = 15;
-        pub const NOT_COORDINATOR: i16 = 16;
-        pub const INVALID_TOPIC_EXCEPTION: i16 = 17;
-        pub const RECORD_LIST_TOO_LARGE: i16 = 18;
-        pub const NOT_ENOUGH_REPLICAS: i16 = 19;
-        pub const NOT_ENOUGH_REPLICAS_AFTER_APPEND: i16 = 20;
-        pub const INVALID_REQUIRED_ACKS: i16 = 21;
-        pub const ILLEGAL_GENERATION: i16 = 22;
-        pub const INCONSISTENT_GROUP_PROTOCOL: i16 = 23;
-        pub const INVALID_GROUP_ID: i16 = 24;
-        pub const UNKNOWN_MEMBER_ID: i16 = 25;
-        pub const INVALID_SESSION_TIMEOUT: i16 = 26;
-        pub const REBALANCE_IN_PROGRESS: i16 = 27;
-        pub const INVALID_COMMIT_OFFSET_SIZE: i16 = 28;
-        pub const TOPIC_AUTHORIZATION_FAILED: i16 = 29;
-        pub const GROUP_AUTHORIZATION_FAILED: i16 = 30;
-        pub const CLUSTER_AUTHORIZATION_FAILED: i16 = 31;
-        pub const INVALID_TIMESTAMP: i16 = 32;
-        pub const UNSUPPORTED_SASL_MECHANISM: i16 = 33;
-        pub const ILLEGAL_SASL_STATE: i16 = 34;
-        pub const UNSUPPORTED_VERSION: i16 = 35;
-        pub const TOPIC_ALREADY_EXISTS: i16 = 36;
-        pub const INVALID_PARTITIONS: i16 = 37;
-        pub const INVALID_REPLICATION_FACTOR: i16 = 38;
-        pub const INVALID_REPLICA_ASSIGNMENT: i16 = 39;
-        pub const INVALID_CONFIG: i16 = 40;
-        pub const NOT_CONTROLLER: i16 = 41;
-        pub const INVALID_REQUEST: i16 = 42;
-        pub const UNSUPPORTED_FOR_MESSAGE_FORMAT: i16 = 43;
-        pub const POLICY_VIOLATION: i16 = 44;
-        pub const OUT_OF_ORDER_SEQUENCE_NUMBER: i16 = 45;
-        pub const DUPLICATE_SEQUENCE_NUMBER: i16 = 46;
-        pub const INVALID_PRODUCER_EPOCH: i16 = 47;
-        pub const INVALID_TXN_STATE: i16 = 48;
-        pub const INVALID_PRODUCER_ID_MAPPING: i16 = 49;
-        pub const INVALID_TRANSACTION_TIMEOUT: i16 = 50;
-        pub const CONCURRENT_TRANSACTIONS: i16 = 51;
-        pub const TRANSACTION_COORDINATOR_FENCED: i16 = 52;
-        pub const TRANSACTIONAL_ID_AUTHORIZATION_FAILED: i16 = 53;
-        pub const SECURITY_DISABLED: i16 = 54;
-        pub const OPERATION_NOT_ATTEMPTED: i16 = 55;
-        pub const KAFKA_STORAGE_ERROR: i16 = 56;
-        pub const LOG_DIR_NOT_FOUND: i16 = 57;
-        pub const SASL_AUTHENTICATION_FAILED: i16 = 58;
-        pub const UNKNOWN_PRODUCER_ID: i16 = 59;
-        pub const REASSIGNMENT_IN_PROGRESS: i16 = 60;
-        pub const DELEGATION_TOKEN_AUTH_DISABLED: i16 = 61;
-        pub const DELEGATION_TOKEN_NOT_FOUND: i16 = 62;
-        pub const DELEGATION_TOKEN_OWNER_MISMATCH: i16 = 63;
-        pub const DELEGATION_TOKEN_REQUEST_NOT_ALLOWED: i16 = 64;
-        pub const DELEGATION_TOKEN_AUTHORIZATION_FAILED: i16 = 65;
-        pub const DELEGATION_TOKEN_EXPIRED: i16 = 66;
-        pub const INVALID_PRINCIPAL_TYPE: i16 = 67;
-        pub const NON_EMPTY_GROUP: i16 = 68;
-        pub const GROUP_ID_NOT_FOUND: i16 = 69;
-        pub const FETCH_SESSION_ID_NOT_FOUND: i16 = 70;
-        pub const INVALID_FETCH_SESSION_EPOCH: i16 = 71;
-        pub const LISTENER_NOT_FOUND: i16 = 72;
-        pub const TOPIC_DELETION_DISABLED: i16 = 73;
-        pub const FENCED_LEADER_EPOCH: i16 = 74;
-        pub const UNKNOWN_LEADER_EPOCH: i16 = 75;
-        pub const UNSUPPORTED_COMPRESSION_TYPE: i16 = 76;
-        pub const STALE_BROKER_EPOCH: i16 = 77;
-        pub const OFFSET_NOT_AVAILABLE: i16 = 78;
-        pub const MEMBER_ID_REQUIRED: i16 = 79;
-        pub const PREFERRED_LEADER_NOT_AVAILABLE: i16 = 80;
-        pub const GROUP_MAX_SIZE_REACHED: i16 = 81;
-        pub const FENCED_INSTANCE_ID: i16 = 82;
+        use crate::protocol::ApiKey;
+
+        #[deprecated(note = "use ApiKey::Produce")]
+        pub const PRODUCE: i16 = ApiKey::Produce.code();
+        #[deprecated(note = "use ApiKey::Fetch")]
+        pub const FETCH: i16 = ApiKey::Fetch.code();
+        #[deprecated(note = "use ApiKey::ListOffsets")]
+        pub const LIST_OFFSETS: i16 = ApiKey::ListOffsets.code();
+        #[deprecated(note = "use ApiKey::Metadata")]
+        pub const METADATA: i16 = ApiKey::Metadata.code();
+        #[deprecated(note = "use ApiKey::LeaderAndIsr")]
+        pub const LEADER_AND_ISR: i16 = ApiKey::LeaderAndIsr.code();
+        #[deprecated(note = "use ApiKey::StopReplica")]
+        pub const STOP_REPLICA: i16 = ApiKey::StopReplica.code();
+        #[deprecated(note = "use ApiKey::UpdateMetadata")]
+        pub const UPDATE_METADATA: i16 = ApiKey::UpdateMetadata.code();
+        #[deprecated(note = "use ApiKey::ControlledShutdown")]
+        pub const CONTROLLED_SHUTDOWN: i16 = ApiKey::ControlledShutdown.code();
+        #[deprecated(note = "use ApiKey::OffsetCommit")]
+        pub const OFFSET_COMMIT: i16 = ApiKey::OffsetCommit.code();
+        #[deprecated(note = "use ApiKey::OffsetFetch")]
+        pub const OFFSET_FETCH: i16 = ApiKey::OffsetFetch.code();
+        #[deprecated(note = "use ApiKey::FindCoordinator")]
+        pub const FIND_COORDINATOR: i16 = ApiKey::FindCoordinator.code();
+        #[deprecated(note = "use ApiKey::JoinGroup")]
+        pub const JOIN_GROUP: i16 = ApiKey::JoinGroup.code();
+        #[deprecated(note = "use ApiKey::Heartbeat")]
+        pub const HEARTBEAT: i16 = ApiKey::Heartbeat.code();
+        #[deprecated(note = "use ApiKey::LeaveGroup")]
+        pub const LEAVE_GROUP: i16 = ApiKey::LeaveGroup.code();
+        #[deprecated(note = "use ApiKey::SyncGroup")]
+        pub const SYNC_GROUP: i16 = ApiKey::SyncGroup.code();
+        #[deprecated(note = "use ApiKey::DescribeGroups")]
+        pub const DESCRIBE_GROUPS: i16 = ApiKey::DescribeGroups.code();
+        #[deprecated(note = "use ApiKey::ListGroups")]
+        pub const LIST_GROUPS: i16 = ApiKey::ListGroups.code();
+        #[deprecated(note = "use ApiKey::SaslHandshake")]
+        pub const SASL_HANDSHAKE: i16 = ApiKey::SaslHandshake.code();
+        #[deprecated(note = "use ApiKey::ApiVersions")]
+        pub const API_VERSIONS: i16 = ApiKey::ApiVersions.code();
+        #[deprecated(note = "use ApiKey::CreateTopics")]
+        pub const CREATE_TOPICS: i16 = ApiKey::CreateTopics.code();
+        #[deprecated(note = "use ApiKey::DeleteTopics")]
+        pub const DELETE_TOPICS: i16 = ApiKey::DeleteTopics.code();
+        #[deprecated(note = "use ApiKey::InitProducerId")]
+        pub const INIT_PRODUCER_ID: i16 = ApiKey::InitProducerId.code();
+        #[deprecated(note = "use ApiKey::DescribeConfigs")]
+        pub const DESCRIBE_CONFIGS: i16 = ApiKey::DescribeConfigs.code();
+        #[deprecated(note = "use ApiKey::SaslAuthenticate")]
+        pub const SASL_AUTHENTICATE: i16 = ApiKey::SaslAuthenticate.code();
+        #[deprecated(note = "use ApiKey::IncrementalAlterConfigs")]
+        pub const INCREMENTAL_ALTER_CONFIGS: i16 = ApiKey::IncrementalAlterConfigs.code();
+        #[deprecated(note = "use ApiKey::WriteTxnMarkers")]
+        pub const WRITE_TXN_MARKERS: i16 = ApiKey::WriteTxnMarkers.code();
+        #[deprecated(note = "use ApiKey::DescribeCluster")]
+        pub const DESCRIBE_CLUSTER: i16 = ApiKey::DescribeCluster.code();
+        #[deprecated(note = "use ApiKey::DescribeTopicPartitions")]
+        pub const DESCRIBE_TOPIC_PARTITIONS: i16 = ApiKey::DescribeTopicPartitions.code();
     }
 }
 
@@ -181,7 +196,7 @@ mod integration_tests {
     fn test_full_protocol_roundtrip() {
         // Test encoding and decoding a complete request/response cycle
         let request = RequestHeaderV2::with_client_id(
-            spec::api_keys::METADATA,
+            ApiKey::Metadata.code(),
             1,
             12345,
             "integration-test-client",
@@ -191,7 +206,7 @@ mod integration_tests {
         let encoded_request = request.encode().unwrap();
 
         // Decode it back
-        let mut buffer = encoded_request;
+        let mut buffer = encoded_request.freeze();
         let decoded_request = RequestHeaderV2::decode(&mut buffer).unwrap();
 
         assert_eq!(request, decoded_request);
@@ -201,7 +216,7 @@ mod integration_tests {
         let encoded_response = response.encode().unwrap();
 
         // Decode the response
-        let mut response_buffer = encoded_response;
+        let mut response_buffer = encoded_response.freeze();
         let decoded_response = ResponseHeaderV0::decode(&mut response_buffer).unwrap();
 
         assert_eq!(response, decoded_response);
@@ -220,6 +235,7 @@ mod integration_tests {
         encode_nullable_string(&mut buffer, Some("test")).unwrap();
 
         // Test decoding
+        let mut buffer = buffer.freeze();
         let result = decode_nullable_string(&mut buffer).unwrap();
         assert_eq!(result, Some("test".to_string()));
     }
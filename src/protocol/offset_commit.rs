@@ -0,0 +1,455 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest OffsetCommit version we serve
+///
+/// v0 commits to ZooKeeper and v1 carries a per-partition commit
+/// timestamp; neither is supported.
+pub const OFFSET_COMMIT_MIN_VERSION: i16 = 2;
+
+/// Highest OffsetCommit version we serve
+pub const OFFSET_COMMIT_MAX_VERSION: i16 = 8;
+
+/// First flexible OffsetCommit version
+const FIRST_FLEXIBLE_VERSION: i16 = 8;
+
+/// Generation of a commit made outside any group membership
+pub const SIMPLE_COMMIT_GENERATION: i32 = -1;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "OffsetCommit",
+        version,
+        OFFSET_COMMIT_MIN_VERSION..=OFFSET_COMMIT_MAX_VERSION,
+    )
+}
+
+/// OffsetCommit request (API key 8)
+///
+/// - v2: group, generation and member id, a retention time and
+///   per-partition offsets with metadata
+/// - v5+: `retention_time_ms` dropped
+/// - v6+: per-partition `committed_leader_epoch`
+/// - v7+: `group_instance_id`
+/// - v8+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetCommitRequest {
+    pub group_id: String,
+    /// [`SIMPLE_COMMIT_GENERATION`] for commits outside the group protocol
+    pub generation_id: i32,
+    pub member_id: String,
+    /// v7+
+    pub group_instance_id: Option<String>,
+    /// v2-v4; -1 keeps the broker's retention
+    pub retention_time_ms: i64,
+    pub topics: Vec<OffsetCommitTopic>,
+}
+
+/// Per-topic offsets of an [`OffsetCommitRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetCommitTopic {
+    pub name: String,
+    pub partitions: Vec<OffsetCommitPartition>,
+}
+
+/// Per-partition offset of an [`OffsetCommitRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetCommitPartition {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    /// v6+; -1 when unknown
+    pub committed_leader_epoch: i32,
+    pub committed_metadata: Option<String>,
+}
+
+impl OffsetCommitRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let group_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let generation_id = WireFormat::decode_i32(buffer)?;
+        let member_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let group_instance_id = if version >= 7 {
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+        } else {
+            None
+        };
+        let retention_time_ms = if version <= 4 {
+            WireFormat::decode_i64(buffer)?
+        } else {
+            -1
+        };
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let partition_index = WireFormat::decode_i32(buffer)?;
+                let committed_offset = WireFormat::decode_i64(buffer)?;
+                let committed_leader_epoch = if version >= 6 {
+                    WireFormat::decode_i32(buffer)?
+                } else {
+                    -1
+                };
+                let committed_metadata =
+                    WireFormat::decode_flexible_nullable_string(buffer, flexible)?;
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(OffsetCommitPartition {
+                    partition_index,
+                    committed_offset,
+                    committed_leader_epoch,
+                    committed_metadata,
+                })
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(OffsetCommitTopic { name, partitions })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+            retention_time_ms,
+            topics,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for OffsetCommitRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_string(buffer, &self.group_id, flexible)?;
+        buffer.put_i32(self.generation_id);
+        WireFormat::encode_flexible_string(buffer, &self.member_id, flexible)?;
+        if version >= 7 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.group_instance_id.as_deref(),
+                flexible,
+            )?;
+        }
+        if version <= 4 {
+            buffer.put_i64(self.retention_time_ms);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i32(partition.partition_index);
+                    buffer.put_i64(partition.committed_offset);
+                    if version >= 6 {
+                        buffer.put_i32(partition.committed_leader_epoch);
+                    }
+                    WireFormat::encode_flexible_nullable_string(
+                        buffer,
+                        partition.committed_metadata.as_deref(),
+                        flexible,
+                    )?;
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let group_instance_id = if version >= 7 {
+            WireFormat::flexible_nullable_string_size(self.group_instance_id.as_deref(), flexible)
+        } else {
+            0
+        };
+        let retention_time = if version <= 4 { 8 } else { 0 };
+        let leader_epoch = if version >= 6 { 4 } else { 0 };
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                let partitions: usize = topic
+                    .partitions
+                    .iter()
+                    .map(|partition| {
+                        4 + 8
+                            + leader_epoch
+                            + WireFormat::flexible_nullable_string_size(
+                                partition.committed_metadata.as_deref(),
+                                flexible,
+                            )
+                            + tags
+                    })
+                    .sum();
+                WireFormat::flexible_string_size(&topic.name, flexible)
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + partitions
+                    + tags
+            })
+            .sum();
+        WireFormat::flexible_string_size(&self.group_id, flexible)
+            + 4
+            + WireFormat::flexible_string_size(&self.member_id, flexible)
+            + group_instance_id
+            + retention_time
+            + WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for OffsetCommitRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// OffsetCommit response (API key 8)
+///
+/// - v2: an error code per partition
+/// - v3+: `throttle_time_ms`
+/// - v8+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetCommitResponse {
+    /// v3+
+    pub throttle_time_ms: i32,
+    pub topics: Vec<OffsetCommitTopicResponse>,
+}
+
+/// Per-topic results of an [`OffsetCommitResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetCommitTopicResponse {
+    pub name: String,
+    pub partitions: Vec<OffsetCommitPartitionResponse>,
+}
+
+/// Per-partition result of an [`OffsetCommitResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetCommitPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: ErrorCode,
+}
+
+impl OffsetCommitResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 3 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let partition = OffsetCommitPartitionResponse {
+                    partition_index: WireFormat::decode_i32(buffer)?,
+                    error_code: ErrorCode::from_wire(WireFormat::decode_i16(buffer)?),
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(partition)
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(OffsetCommitTopicResponse { name, partitions })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            topics,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for OffsetCommitResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 3 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i32(partition.partition_index);
+                    buffer.put_i16(partition.error_code.code());
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let throttle_time = if version >= 3 { 4 } else { 0 };
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                WireFormat::flexible_string_size(&topic.name, flexible)
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + topic.partitions.len() * (4 + 2 + tags)
+                    + tags
+            })
+            .sum();
+        throttle_time
+            + WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for OffsetCommitResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> OffsetCommitRequest {
+        OffsetCommitRequest {
+            group_id: "billing".to_string(),
+            generation_id: 2,
+            member_id: "consumer-1".to_string(),
+            group_instance_id: Some("host-a".to_string()),
+            retention_time_ms: 60_000,
+            topics: vec![OffsetCommitTopic {
+                name: "orders".to_string(),
+                partitions: vec![OffsetCommitPartition {
+                    partition_index: 1,
+                    committed_offset: 42,
+                    committed_leader_epoch: 3,
+                    committed_metadata: Some("m".to_string()),
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_v2_layout() {
+        let expected = [
+            "000762696c6c696e67",       // group_id
+            "00000002",                 // generation_id
+            "000a636f6e73756d65722d31", // member_id
+            "000000000000ea60",         // retention_time_ms
+            "00000001",                 // topics
+            "00066f7264657273",
+            "00000001", // partitions
+            "00000001",
+            "000000000000002a", // committed_offset
+            "00016d",           // committed_metadata
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(2).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in OFFSET_COMMIT_MIN_VERSION..=OFFSET_COMMIT_MAX_VERSION {
+            let request = request();
+            let mut expected = request.clone();
+            if version > 4 {
+                expected.retention_time_ms = -1;
+            }
+            if version < 6 {
+                expected.topics[0].partitions[0].committed_leader_epoch = -1;
+            }
+            if version < 7 {
+                expected.group_instance_id = None;
+            }
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                OffsetCommitRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = OffsetCommitResponse {
+            throttle_time_ms: 1,
+            topics: vec![OffsetCommitTopicResponse {
+                name: "orders".to_string(),
+                partitions: vec![OffsetCommitPartitionResponse {
+                    partition_index: 1,
+                    error_code: ErrorCode::ILLEGAL_GENERATION,
+                }],
+            }],
+        };
+        for version in OFFSET_COMMIT_MIN_VERSION..=OFFSET_COMMIT_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 3 {
+                expected.throttle_time_ms = 0;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                OffsetCommitResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_old_versions_are_rejected() {
+        assert!(request().encode(1).is_err());
+        assert!(request().encode(9).is_err());
+    }
+}
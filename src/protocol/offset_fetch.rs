@@ -0,0 +1,618 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest OffsetFetch version we serve
+///
+/// v0 reads offsets from ZooKeeper and is not supported.
+pub const OFFSET_FETCH_MIN_VERSION: i16 = 1;
+
+/// Highest OffsetFetch version we serve
+pub const OFFSET_FETCH_MAX_VERSION: i16 = 8;
+
+/// First OffsetFetch version that asks about several groups at once
+pub const OFFSET_FETCH_BATCHED_VERSION: i16 = 8;
+
+/// First flexible OffsetFetch version
+const FIRST_FLEXIBLE_VERSION: i16 = 6;
+
+/// First version where a null topic list asks for every committed offset
+const NULL_TOPICS_VERSION: i16 = 2;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "OffsetFetch",
+        version,
+        OFFSET_FETCH_MIN_VERSION..=OFFSET_FETCH_MAX_VERSION,
+    )
+}
+
+/// Rejects anything but one group below the batched version
+fn single_group<T>(groups: &[T], version: i16) -> ProtocolResult<&T> {
+    match groups {
+        [group] => Ok(group),
+        _ => Err(ProtocolError::SerializationError(format!(
+            "OffsetFetch v{} carries exactly one group, not {}",
+            version,
+            groups.len()
+        ))),
+    }
+}
+
+/// OffsetFetch request (API key 9)
+///
+/// - v1: group and the partitions to look up
+/// - v2+: a null topic list asks for every offset the group committed
+/// - v6+: flexible
+/// - v7+: `require_stable`
+/// - v8+: several groups per request
+///
+/// Below v8 the request is held as a single entry of `groups`.
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetFetchRequest {
+    pub groups: Vec<OffsetFetchRequestGroup>,
+    /// v7+
+    pub require_stable: bool,
+}
+
+/// One group's lookup in an [`OffsetFetchRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetFetchRequestGroup {
+    pub group_id: String,
+    /// `None` asks for every committed offset (v2+)
+    pub topics: Option<Vec<OffsetFetchRequestTopic>>,
+}
+
+/// Partitions of one topic to look up
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetFetchRequestTopic {
+    pub name: String,
+    pub partition_indexes: Vec<i32>,
+}
+
+fn decode_request_topics(
+    buffer: &mut Bytes,
+    flexible: bool,
+) -> ProtocolResult<Option<Vec<OffsetFetchRequestTopic>>> {
+    WireFormat::decode_flexible_nullable_array(buffer, flexible, |buffer| {
+        let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let partition_indexes =
+            WireFormat::decode_flexible_array(buffer, flexible, WireFormat::decode_i32)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(OffsetFetchRequestTopic {
+            name,
+            partition_indexes,
+        })
+    })
+}
+
+fn encode_request_topics(
+    buffer: &mut BytesMut,
+    topics: Option<&[OffsetFetchRequestTopic]>,
+    version: i16,
+) -> ProtocolResult<()> {
+    let flexible = version >= FIRST_FLEXIBLE_VERSION;
+    if topics.is_none() && version < NULL_TOPICS_VERSION {
+        return Err(ProtocolError::SerializationError(format!(
+            "OffsetFetch v{} cannot ask for every topic",
+            version
+        )));
+    }
+    WireFormat::encode_flexible_nullable_array(buffer, topics, flexible, |buffer, topic| {
+        WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+        WireFormat::encode_flexible_array(
+            buffer,
+            &topic.partition_indexes,
+            flexible,
+            |buffer, partition| {
+                buffer.put_i32(*partition);
+                Ok(())
+            },
+        )?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    })
+}
+
+fn request_topics_size(topics: Option<&[OffsetFetchRequestTopic]>, flexible: bool) -> usize {
+    let tags = usize::from(flexible);
+    let topics_size: usize = topics
+        .unwrap_or_default()
+        .iter()
+        .map(|topic| {
+            WireFormat::flexible_string_size(&topic.name, flexible)
+                + WireFormat::flexible_array_length_size(
+                    Some(topic.partition_indexes.len()),
+                    flexible,
+                )
+                + topic.partition_indexes.len() * 4
+                + tags
+        })
+        .sum();
+    WireFormat::flexible_array_length_size(topics.map(|topics| topics.len()), flexible)
+        + topics_size
+}
+
+impl OffsetFetchRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let groups = if version >= OFFSET_FETCH_BATCHED_VERSION {
+            WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let group_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+                let topics = decode_request_topics(buffer, flexible)?;
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(OffsetFetchRequestGroup { group_id, topics })
+            })?
+        } else {
+            let group_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let topics = decode_request_topics(buffer, flexible)?;
+            vec![OffsetFetchRequestGroup { group_id, topics }]
+        };
+        let require_stable = if version >= 7 {
+            WireFormat::decode_i8(buffer)? != 0
+        } else {
+            false
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            groups,
+            require_stable,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for OffsetFetchRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= OFFSET_FETCH_BATCHED_VERSION {
+            WireFormat::encode_flexible_array(buffer, &self.groups, flexible, |buffer, group| {
+                WireFormat::encode_flexible_string(buffer, &group.group_id, flexible)?;
+                encode_request_topics(buffer, group.topics.as_deref(), version)?;
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            })?;
+        } else {
+            let group = single_group(&self.groups, version)?;
+            WireFormat::encode_flexible_string(buffer, &group.group_id, flexible)?;
+            encode_request_topics(buffer, group.topics.as_deref(), version)?;
+        }
+        if version >= 7 {
+            buffer.put_i8(i8::from(self.require_stable));
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let group_size = |group: &OffsetFetchRequestGroup| {
+            WireFormat::flexible_string_size(&group.group_id, flexible)
+                + request_topics_size(group.topics.as_deref(), flexible)
+        };
+        let groups = if version >= OFFSET_FETCH_BATCHED_VERSION {
+            WireFormat::flexible_array_length_size(Some(self.groups.len()), flexible)
+                + self
+                    .groups
+                    .iter()
+                    .map(|group| group_size(group) + tags)
+                    .sum::<usize>()
+        } else {
+            self.groups.first().map(group_size).unwrap_or_default()
+        };
+        let require_stable = if version >= 7 { 1 } else { 0 };
+        groups + require_stable + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for OffsetFetchRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// OffsetFetch response (API key 9)
+///
+/// - v1: per-partition offset, metadata and error code
+/// - v2+: a top-level error code
+/// - v3+: `throttle_time_ms`
+/// - v5+: per-partition `committed_leader_epoch`
+/// - v6+: flexible
+/// - v8+: results per group, each with its own error code
+///
+/// Below v8 the response holds its one group with an empty `group_id`.
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetFetchResponse {
+    /// v3+
+    pub throttle_time_ms: i32,
+    pub groups: Vec<OffsetFetchResponseGroup>,
+}
+
+/// One group's offsets in an [`OffsetFetchResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetFetchResponseGroup {
+    /// v8+
+    pub group_id: String,
+    pub topics: Vec<OffsetFetchResponseTopic>,
+    /// v2+
+    pub error_code: ErrorCode,
+}
+
+/// Per-topic offsets of an [`OffsetFetchResponseGroup`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetFetchResponseTopic {
+    pub name: String,
+    pub partitions: Vec<OffsetFetchResponsePartition>,
+}
+
+/// Committed offset of one partition
+#[derive(Debug, Clone, PartialEq)]
+pub struct OffsetFetchResponsePartition {
+    pub partition_index: i32,
+    /// -1 when the group has not committed one
+    pub committed_offset: i64,
+    /// v5+
+    pub committed_leader_epoch: i32,
+    pub metadata: Option<String>,
+    pub error_code: ErrorCode,
+}
+
+impl OffsetFetchResponseGroup {
+    /// A group whose offsets could not be read
+    pub fn error(group_id: String, error_code: impl Into<ErrorCode>) -> Self {
+        Self {
+            group_id,
+            topics: Vec::new(),
+            error_code: error_code.into(),
+        }
+    }
+}
+
+fn decode_response_topics(
+    buffer: &mut Bytes,
+    version: i16,
+) -> ProtocolResult<Vec<OffsetFetchResponseTopic>> {
+    let flexible = version >= FIRST_FLEXIBLE_VERSION;
+    WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+        let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let partition_index = WireFormat::decode_i32(buffer)?;
+            let committed_offset = WireFormat::decode_i64(buffer)?;
+            let committed_leader_epoch = if version >= 5 {
+                WireFormat::decode_i32(buffer)?
+            } else {
+                -1
+            };
+            let metadata = WireFormat::decode_flexible_nullable_string(buffer, flexible)?;
+            let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(OffsetFetchResponsePartition {
+                partition_index,
+                committed_offset,
+                committed_leader_epoch,
+                metadata,
+                error_code,
+            })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(OffsetFetchResponseTopic { name, partitions })
+    })
+}
+
+fn encode_response_topics(
+    buffer: &mut BytesMut,
+    topics: &[OffsetFetchResponseTopic],
+    version: i16,
+) -> ProtocolResult<()> {
+    let flexible = version >= FIRST_FLEXIBLE_VERSION;
+    WireFormat::encode_flexible_array(buffer, topics, flexible, |buffer, topic| {
+        WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+        WireFormat::encode_flexible_array(
+            buffer,
+            &topic.partitions,
+            flexible,
+            |buffer, partition| {
+                buffer.put_i32(partition.partition_index);
+                buffer.put_i64(partition.committed_offset);
+                if version >= 5 {
+                    buffer.put_i32(partition.committed_leader_epoch);
+                }
+                WireFormat::encode_flexible_nullable_string(
+                    buffer,
+                    partition.metadata.as_deref(),
+                    flexible,
+                )?;
+                buffer.put_i16(partition.error_code.code());
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            },
+        )?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    })
+}
+
+fn response_topics_size(topics: &[OffsetFetchResponseTopic], version: i16) -> usize {
+    let flexible = version >= FIRST_FLEXIBLE_VERSION;
+    let tags = usize::from(flexible);
+    let leader_epoch = if version >= 5 { 4 } else { 0 };
+    let topics_size: usize = topics
+        .iter()
+        .map(|topic| {
+            let partitions: usize = topic
+                .partitions
+                .iter()
+                .map(|partition| {
+                    4 + 8
+                        + leader_epoch
+                        + WireFormat::flexible_nullable_string_size(
+                            partition.metadata.as_deref(),
+                            flexible,
+                        )
+                        + 2
+                        + tags
+                })
+                .sum();
+            WireFormat::flexible_string_size(&topic.name, flexible)
+                + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                + partitions
+                + tags
+        })
+        .sum();
+    WireFormat::flexible_array_length_size(Some(topics.len()), flexible) + topics_size
+}
+
+impl OffsetFetchResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 3 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let groups = if version >= OFFSET_FETCH_BATCHED_VERSION {
+            WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let group_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+                let topics = decode_response_topics(buffer, version)?;
+                let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(OffsetFetchResponseGroup {
+                    group_id,
+                    topics,
+                    error_code,
+                })
+            })?
+        } else {
+            let topics = decode_response_topics(buffer, version)?;
+            let error_code = if version >= 2 {
+                ErrorCode::from_wire(WireFormat::decode_i16(buffer)?)
+            } else {
+                ErrorCode::NONE
+            };
+            vec![OffsetFetchResponseGroup {
+                group_id: String::new(),
+                topics,
+                error_code,
+            }]
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            groups,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for OffsetFetchResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 3 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        if version >= OFFSET_FETCH_BATCHED_VERSION {
+            WireFormat::encode_flexible_array(buffer, &self.groups, flexible, |buffer, group| {
+                WireFormat::encode_flexible_string(buffer, &group.group_id, flexible)?;
+                encode_response_topics(buffer, &group.topics, version)?;
+                buffer.put_i16(group.error_code.code());
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            })?;
+        } else {
+            let group = single_group(&self.groups, version)?;
+            encode_response_topics(buffer, &group.topics, version)?;
+            if version >= 2 {
+                buffer.put_i16(group.error_code.code());
+            }
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let throttle_time = if version >= 3 { 4 } else { 0 };
+        let groups = if version >= OFFSET_FETCH_BATCHED_VERSION {
+            WireFormat::flexible_array_length_size(Some(self.groups.len()), flexible)
+                + self
+                    .groups
+                    .iter()
+                    .map(|group| {
+                        WireFormat::flexible_string_size(&group.group_id, flexible)
+                            + response_topics_size(&group.topics, version)
+                            + 2
+                            + tags
+                    })
+                    .sum::<usize>()
+        } else {
+            let error_code = if version >= 2 { 2 } else { 0 };
+            self.groups
+                .first()
+                .map(|group| response_topics_size(&group.topics, version) + error_code)
+                .unwrap_or_default()
+        };
+        throttle_time + groups + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for OffsetFetchResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(topics: Option<Vec<OffsetFetchRequestTopic>>) -> OffsetFetchRequest {
+        OffsetFetchRequest {
+            groups: vec![OffsetFetchRequestGroup {
+                group_id: "billing".to_string(),
+                topics,
+            }],
+            require_stable: true,
+        }
+    }
+
+    fn orders() -> Option<Vec<OffsetFetchRequestTopic>> {
+        Some(vec![OffsetFetchRequestTopic {
+            name: "orders".to_string(),
+            partition_indexes: vec![0, 1],
+        }])
+    }
+
+    fn response(group_id: &str) -> OffsetFetchResponse {
+        OffsetFetchResponse {
+            throttle_time_ms: 1,
+            groups: vec![OffsetFetchResponseGroup {
+                group_id: group_id.to_string(),
+                topics: vec![OffsetFetchResponseTopic {
+                    name: "orders".to_string(),
+                    partitions: vec![OffsetFetchResponsePartition {
+                        partition_index: 0,
+                        committed_offset: 42,
+                        committed_leader_epoch: 3,
+                        metadata: Some("m".to_string()),
+                        error_code: ErrorCode::NONE,
+                    }],
+                }],
+                error_code: ErrorCode::NONE,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_v8_layout() {
+        let expected = [
+            "02",               // groups
+            "0862696c6c696e67", // group_id
+            "00",               // topics: null
+            "00",               // group tagged fields
+            "01",               // require_stable
+            "00",               // tagged fields
+        ]
+        .concat();
+        assert_eq!(hex::encode(request(None).encode(8).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in OFFSET_FETCH_MIN_VERSION..=OFFSET_FETCH_MAX_VERSION {
+            let topic_lists = if version >= NULL_TOPICS_VERSION {
+                vec![orders(), None]
+            } else {
+                vec![orders()]
+            };
+            for topics in topic_lists {
+                let request = request(topics);
+                let mut expected = request.clone();
+                if version < 7 {
+                    expected.require_stable = false;
+                }
+                let encoded = request.encode(version).unwrap();
+                assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+                let mut encoded = encoded.freeze();
+                assert_eq!(
+                    OffsetFetchRequest::decode(&mut encoded, version).unwrap(),
+                    expected,
+                    "v{}",
+                    version
+                );
+                assert!(encoded.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        for version in OFFSET_FETCH_MIN_VERSION..=OFFSET_FETCH_MAX_VERSION {
+            let response = response("billing");
+            let mut expected = response.clone();
+            if version < 3 {
+                expected.throttle_time_ms = 0;
+            }
+            if version < 5 {
+                expected.groups[0].topics[0].partitions[0].committed_leader_epoch = -1;
+            }
+            if version < OFFSET_FETCH_BATCHED_VERSION {
+                expected.groups[0].group_id = String::new();
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                OffsetFetchResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_unbatched_versions_need_one_group() {
+        let mut batched = request(orders());
+        batched.groups.push(batched.groups[0].clone());
+        assert!(batched.encode(7).is_err());
+        assert!(batched.encode(8).is_ok());
+        assert!(request(None).encode(1).is_err());
+    }
+
+    #[test]
+    fn test_v9_is_rejected() {
+        assert!(request(orders()).encode(9).is_err());
+    }
+}
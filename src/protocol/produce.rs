@@ -0,0 +1,710 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest Produce version we serve
+pub const PRODUCE_MIN_VERSION: i16 = 0;
+
+/// Highest Produce version we serve
+pub const PRODUCE_MAX_VERSION: i16 = 11;
+
+/// First flexible Produce version
+const FIRST_FLEXIBLE_VERSION: i16 = 9;
+
+/// First Produce version whose records must be v2 record batches
+pub const FIRST_RECORD_BATCH_VERSION: i16 = 3;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "Produce",
+        version,
+        PRODUCE_MIN_VERSION..=PRODUCE_MAX_VERSION,
+    )
+}
+
+/// Produce request (API key 0)
+///
+/// - v0-v2: `records` hold a legacy message set (magic 0 for v0, magic 0
+///   or 1 for v1-v2)
+/// - v3: adds `transactional_id`; `records` must be v2 record batches
+/// - v4-v8: same layout
+/// - v9: flexible, with compact strings and arrays and tag sections
+/// - v10-v11: same layout
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProduceRequest {
+    /// v3+; always `None` before
+    pub transactional_id: Option<String>,
+    pub acks: i16,
+    pub timeout_ms: i32,
+    pub topics: Vec<ProduceTopicData>,
+}
+
+/// Per-topic data of a [`ProduceRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProduceTopicData {
+    pub name: String,
+    pub partitions: Vec<ProducePartitionData>,
+}
+
+/// Per-partition data of a [`ProduceRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProducePartitionData {
+    pub index: i32,
+    pub records: Option<Bytes>,
+}
+
+impl ProduceRequest {
+    /// Decodes the request body for the given version
+    ///
+    /// `records` are views into `buffer`, not copies.
+    ///
+    /// # Examples
+    /// ```
+    /// use bytes::Bytes;
+    /// use codecrafters_kafka::protocol::produce::{
+    ///     ProducePartitionData, ProduceRequest, ProduceTopicData,
+    /// };
+    ///
+    /// let request = ProduceRequest {
+    ///     transactional_id: None,
+    ///     acks: -1,
+    ///     timeout_ms: 1500,
+    ///     topics: vec![ProduceTopicData {
+    ///         name: "orders".to_string(),
+    ///         partitions: vec![ProducePartitionData {
+    ///             index: 0,
+    ///             records: Some(Bytes::from_static(b"opaque records")),
+    ///         }],
+    ///     }],
+    /// };
+    /// let mut frame = request.encode(2).unwrap().freeze();
+    /// assert_eq!(ProduceRequest::decode(&mut frame, 2).unwrap(), request);
+    /// assert!(ProduceRequest::decode(&mut frame, 12).is_err());
+    /// ```
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let transactional_id = if version >= 3 {
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+        } else {
+            None
+        };
+        let acks = WireFormat::decode_i16(buffer)?;
+        let timeout_ms = WireFormat::decode_i32(buffer)?;
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let partition = ProducePartitionData {
+                    index: WireFormat::decode_i32(buffer)?,
+                    records: WireFormat::decode_flexible_nullable_bytes(buffer, flexible)?,
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(partition)
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(ProduceTopicData { name, partitions })
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            transactional_id,
+            acks,
+            timeout_ms,
+            topics,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for ProduceRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 3 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.transactional_id.as_deref(),
+                flexible,
+            )?;
+        }
+        buffer.put_i16(self.acks);
+        buffer.put_i32(self.timeout_ms);
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i32(partition.index);
+                    WireFormat::encode_flexible_nullable_bytes(
+                        buffer,
+                        partition.records.as_deref(),
+                        flexible,
+                    )?;
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                let partitions: usize = topic
+                    .partitions
+                    .iter()
+                    .map(|partition| {
+                        4 + WireFormat::flexible_nullable_bytes_size(
+                            partition.records.as_deref(),
+                            flexible,
+                        ) + tags
+                    })
+                    .sum();
+                WireFormat::flexible_string_size(&topic.name, flexible)
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + partitions
+                    + tags
+            })
+            .sum();
+        let transactional_id = if version >= 3 {
+            WireFormat::flexible_nullable_string_size(self.transactional_id.as_deref(), flexible)
+        } else {
+            0
+        };
+        transactional_id
+            + 2
+            + 4
+            + WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for ProduceRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// Produce response (API key 0)
+///
+/// - v0: per-partition index, error code and base offset
+/// - v1: adds a trailing `throttle_time_ms`
+/// - v2: adds per-partition `log_append_time_ms`
+/// - v5: adds per-partition `log_start_offset`
+/// - v8: adds per-partition `record_errors` and `error_message`
+/// - v9: flexible
+///
+/// The current leader and node endpoint tags of v10+ are never written;
+/// tagged fields are skipped on decode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProduceResponse {
+    pub topics: Vec<ProduceTopicResponse>,
+    pub throttle_time_ms: i32,
+}
+
+/// Per-topic result of a [`ProduceResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProduceTopicResponse {
+    pub name: String,
+    pub partitions: Vec<ProducePartitionResponse>,
+}
+
+/// Per-partition result of a [`ProduceResponse`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProducePartitionResponse {
+    pub index: i32,
+    pub error_code: ErrorCode,
+    pub base_offset: i64,
+    /// -1 unless the topic uses LogAppendTime
+    pub log_append_time_ms: i64,
+    /// v5+; -1 when unknown
+    pub log_start_offset: i64,
+    /// v8+; the batches that caused the partition to be rejected
+    pub record_errors: Vec<ProduceRecordError>,
+    /// v8+
+    pub error_message: Option<String>,
+}
+
+/// A batch that caused a [`ProducePartitionResponse`] to fail
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProduceRecordError {
+    pub batch_index: i32,
+    pub batch_index_error_message: Option<String>,
+}
+
+impl ProducePartitionResponse {
+    /// Creates a successful partition result
+    ///
+    /// `base_offset` is the offset of the first record of the first batch;
+    /// `log_append_time_ms` is set only when the broker stamped the batches.
+    pub fn appended(index: i32, base_offset: i64, log_append_time_ms: Option<i64>) -> Self {
+        Self {
+            index,
+            error_code: ErrorCode::NONE,
+            base_offset,
+            log_append_time_ms: log_append_time_ms.unwrap_or(-1),
+            log_start_offset: -1,
+            record_errors: Vec::new(),
+            error_message: None,
+        }
+    }
+
+    /// Creates a failed partition result
+    pub fn error(index: i32, error_code: impl Into<ErrorCode>) -> Self {
+        Self {
+            index,
+            error_code: error_code.into(),
+            base_offset: -1,
+            log_append_time_ms: -1,
+            log_start_offset: -1,
+            record_errors: Vec::new(),
+            error_message: None,
+        }
+    }
+}
+
+impl ProduceResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let topics = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let name = WireFormat::decode_flexible_string(buffer, flexible)?;
+            let partitions = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                let index = WireFormat::decode_i32(buffer)?;
+                let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+                let base_offset = WireFormat::decode_i64(buffer)?;
+                let log_append_time_ms = if version >= 2 {
+                    WireFormat::decode_i64(buffer)?
+                } else {
+                    -1
+                };
+                let log_start_offset = if version >= 5 {
+                    WireFormat::decode_i64(buffer)?
+                } else {
+                    -1
+                };
+                let (record_errors, error_message) = if version >= 8 {
+                    let record_errors =
+                        WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+                            let record_error = ProduceRecordError {
+                                batch_index: WireFormat::decode_i32(buffer)?,
+                                batch_index_error_message:
+                                    WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                            };
+                            skip_tagged_fields(buffer, flexible)?;
+                            Ok(record_error)
+                        })?;
+                    let error_message =
+                        WireFormat::decode_flexible_nullable_string(buffer, flexible)?;
+                    (record_errors, error_message)
+                } else {
+                    (Vec::new(), None)
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                Ok(ProducePartitionResponse {
+                    index,
+                    error_code,
+                    base_offset,
+                    log_append_time_ms,
+                    log_start_offset,
+                    record_errors,
+                    error_message,
+                })
+            })?;
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(ProduceTopicResponse { name, partitions })
+        })?;
+        let throttle_time_ms = if version >= 1 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            topics,
+            throttle_time_ms,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for ProduceResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_array(buffer, &self.topics, flexible, |buffer, topic| {
+            WireFormat::encode_flexible_string(buffer, &topic.name, flexible)?;
+            WireFormat::encode_flexible_array(
+                buffer,
+                &topic.partitions,
+                flexible,
+                |buffer, partition| {
+                    buffer.put_i32(partition.index);
+                    buffer.put_i16(partition.error_code.code());
+                    buffer.put_i64(partition.base_offset);
+                    if version >= 2 {
+                        buffer.put_i64(partition.log_append_time_ms);
+                    }
+                    if version >= 5 {
+                        buffer.put_i64(partition.log_start_offset);
+                    }
+                    if version >= 8 {
+                        WireFormat::encode_flexible_array(
+                            buffer,
+                            &partition.record_errors,
+                            flexible,
+                            |buffer, record_error| {
+                                buffer.put_i32(record_error.batch_index);
+                                WireFormat::encode_flexible_nullable_string(
+                                    buffer,
+                                    record_error.batch_index_error_message.as_deref(),
+                                    flexible,
+                                )?;
+                                put_empty_tagged_fields(buffer, flexible);
+                                Ok(())
+                            },
+                        )?;
+                        WireFormat::encode_flexible_nullable_string(
+                            buffer,
+                            partition.error_message.as_deref(),
+                            flexible,
+                        )?;
+                    }
+                    put_empty_tagged_fields(buffer, flexible);
+                    Ok(())
+                },
+            )?;
+            put_empty_tagged_fields(buffer, flexible);
+            Ok(())
+        })?;
+        if version >= 1 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let partition_size = |partition: &ProducePartitionResponse| {
+            let errors = if version >= 8 {
+                let record_errors: usize = partition
+                    .record_errors
+                    .iter()
+                    .map(|record_error| {
+                        4 + WireFormat::flexible_nullable_string_size(
+                            record_error.batch_index_error_message.as_deref(),
+                            flexible,
+                        ) + tags
+                    })
+                    .sum();
+                WireFormat::flexible_array_length_size(
+                    Some(partition.record_errors.len()),
+                    flexible,
+                ) + record_errors
+                    + WireFormat::flexible_nullable_string_size(
+                        partition.error_message.as_deref(),
+                        flexible,
+                    )
+            } else {
+                0
+            };
+            4 + 2
+                + 8
+                + if version >= 2 { 8 } else { 0 }
+                + if version >= 5 { 8 } else { 0 }
+                + errors
+                + tags
+        };
+        let topics: usize = self
+            .topics
+            .iter()
+            .map(|topic| {
+                WireFormat::flexible_string_size(&topic.name, flexible)
+                    + WireFormat::flexible_array_length_size(Some(topic.partitions.len()), flexible)
+                    + topic.partitions.iter().map(partition_size).sum::<usize>()
+                    + tags
+            })
+            .sum();
+        let throttle_time = if version >= 1 { 4 } else { 0 };
+        WireFormat::flexible_array_length_size(Some(self.topics.len()), flexible)
+            + topics
+            + throttle_time
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for ProduceResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::message_set::{decode_message_set, records_magic};
+    use crate::protocol::record_batch::encode_test_batch;
+
+    /// Produce v0 body as sent by a legacy client: acks=1, timeout 1500ms,
+    /// one magic 0 message with a null key and value "hello" to test-0
+    const PRODUCE_V0_FIXTURE: [u8; 59] = [
+        0x00, 0x01, 0x00, 0x00, 0x05, 0xdc, 0x00, 0x00, 0x00, 0x01, 0x00, 0x04, //
+        0x74, 0x65, 0x73, 0x74, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, //
+        0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0x00, 0x00, 0x00, 0x13, 0x87, 0xa7, 0x7a, 0xb2, 0x00, 0x00, 0xff, 0xff, //
+        0xff, 0xff, 0x00, 0x00, 0x00, 0x05, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+    ];
+
+    /// Produce v2 body with one magic 1 message: key "k", value "hello",
+    /// timestamp 1700000000000
+    const PRODUCE_V2_FIXTURE: [u8; 68] = [
+        0x00, 0x01, 0x00, 0x00, 0x05, 0xdc, 0x00, 0x00, 0x00, 0x01, 0x00, 0x04, //
+        0x74, 0x65, 0x73, 0x74, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, //
+        0x00, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0x00, 0x00, 0x00, 0x1c, 0xaa, 0x1f, 0x67, 0x79, 0x01, 0x00, 0x00, 0x00, //
+        0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x00, 0x00, 0x00, 0x00, 0x01, 0x6b, 0x00, //
+        0x00, 0x00, 0x05, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+    ];
+
+    fn decode_fixture(fixture: &[u8], version: i16) -> ProduceRequest {
+        let mut buffer = Bytes::copy_from_slice(fixture);
+        let request = ProduceRequest::decode(&mut buffer, version).unwrap();
+        assert!(buffer.is_empty());
+        assert_eq!(&request.encode(version).unwrap()[..], fixture);
+        assert_eq!(request.encoded_size(version), fixture.len());
+        request
+    }
+
+    #[test]
+    fn test_v0_fixture_roundtrip() {
+        let request = decode_fixture(&PRODUCE_V0_FIXTURE, 0);
+        assert_eq!(request.acks, 1);
+        assert_eq!(request.timeout_ms, 1500);
+        assert_eq!(request.topics[0].name, "test");
+
+        let records = request.topics[0].partitions[0].records.as_ref().unwrap();
+        assert_eq!(records_magic(records), Some(0));
+        let messages = decode_message_set(records).unwrap();
+        assert_eq!(messages[0].key, None);
+        assert_eq!(messages[0].value.as_deref(), Some(&b"hello"[..]));
+    }
+
+    #[test]
+    fn test_v2_fixture_roundtrip() {
+        let request = decode_fixture(&PRODUCE_V2_FIXTURE, 2);
+
+        let records = request.topics[0].partitions[0].records.as_ref().unwrap();
+        assert_eq!(records_magic(records), Some(1));
+        let messages = decode_message_set(records).unwrap();
+        assert_eq!(messages[0].timestamp, Some(1_700_000_000_000));
+        assert_eq!(messages[0].key.as_deref(), Some(&b"k"[..]));
+    }
+
+    #[test]
+    fn test_record_values_are_views_into_the_frame() {
+        let frame = Bytes::from_static(&PRODUCE_V2_FIXTURE);
+        let request = ProduceRequest::decode(&mut frame.clone(), 2).unwrap();
+
+        // Neither the records field nor the message value inside it was
+        // copied: both point into the original frame
+        let records = request.topics[0].partitions[0].records.as_ref().unwrap();
+        assert_eq!(
+            records.as_ptr(),
+            frame[frame.len() - records.len()..].as_ptr()
+        );
+        let value = decode_message_set(records).unwrap()[0]
+            .value
+            .clone()
+            .unwrap();
+        assert_eq!(value.as_ptr(), frame[frame.len() - 5..].as_ptr());
+    }
+
+    #[test]
+    fn test_response_shape_per_version() {
+        let response = ProduceResponse {
+            topics: vec![ProduceTopicResponse {
+                name: "test".to_string(),
+                partitions: vec![ProducePartitionResponse::appended(0, 42, None)],
+            }],
+            throttle_time_ms: 0,
+        };
+
+        // array(4) + name(6) + array(4) + index(4) + error(2) + offset(8)
+        let v0 = response.encode(0).unwrap();
+        assert_eq!(v0.len(), 28);
+        let v1 = response.encode(1).unwrap();
+        assert_eq!(v1.len(), 28 + 4);
+        let v2 = response.encode(2).unwrap();
+        assert_eq!(v2.len(), 28 + 8 + 4);
+
+        for (version, encoded) in [(0, v0), (1, v1), (2, v2)] {
+            assert_eq!(encoded.len(), response.encoded_size(version));
+            let mut buffer = encoded.freeze();
+            assert_eq!(
+                ProduceResponse::decode(&mut buffer, version).unwrap(),
+                response
+            );
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_unsupported_version_is_rejected() {
+        let mut buffer = Bytes::copy_from_slice(&PRODUCE_V2_FIXTURE[..]);
+        assert!(ProduceRequest::decode(&mut buffer, 12).is_err());
+    }
+
+    /// Two topics, three partitions, one record batch each
+    fn two_topic_request() -> ProduceRequest {
+        let partition = |index| ProducePartitionData {
+            index,
+            records: Some(encode_test_batch(2, 1_700_000_000_000)),
+        };
+        ProduceRequest {
+            transactional_id: None,
+            acks: -1,
+            timeout_ms: 30_000,
+            topics: vec![
+                ProduceTopicData {
+                    name: "orders".to_string(),
+                    partitions: vec![partition(0), partition(1)],
+                },
+                ProduceTopicData {
+                    name: "payments".to_string(),
+                    partitions: vec![partition(0)],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_v9_layout() {
+        let request = ProduceRequest {
+            transactional_id: Some("tx".to_string()),
+            acks: 0,
+            timeout_ms: 1500,
+            topics: vec![ProduceTopicData {
+                name: "t".to_string(),
+                partitions: vec![ProducePartitionData {
+                    index: 0,
+                    records: Some(Bytes::from_static(b"rb")),
+                }],
+            }],
+        };
+        let encoded = request.encode(9).unwrap();
+        let expected = [
+            0x03, b't', b'x', // transactional id
+            0x00, 0x00, // acks
+            0x00, 0x00, 0x05, 0xdc, // timeout
+            0x02, 0x02, b't', // one topic
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x03, b'r', b'b', 0x00, // one partition
+            0x00, 0x00, // topic and request tags
+        ];
+        assert_eq!(&encoded[..], &expected[..]);
+        assert_eq!(encoded.len(), request.encoded_size(9));
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_record_batch_version() {
+        let mut request = two_topic_request();
+        for version in FIRST_RECORD_BATCH_VERSION..=PRODUCE_MAX_VERSION {
+            request.transactional_id = (version % 2 == 0).then(|| "tx-1".to_string());
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut buffer = encoded.freeze();
+            let decoded = ProduceRequest::decode(&mut buffer, version).unwrap();
+            assert_eq!(decoded, request, "v{}", version);
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_acks_are_preserved() {
+        for acks in [-1, 0, 1] {
+            let request = ProduceRequest {
+                acks,
+                ..two_topic_request()
+            };
+            let decoded = ProduceRequest::decode(&mut request.encode(9).unwrap().freeze(), 9);
+            assert_eq!(decoded.unwrap().acks, acks);
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let mut failed = ProducePartitionResponse::error(1, ErrorCode::CORRUPT_MESSAGE);
+        let response = |version: i16, failed: &ProducePartitionResponse| ProduceResponse {
+            topics: vec![ProduceTopicResponse {
+                name: "orders".to_string(),
+                partitions: vec![
+                    ProducePartitionResponse {
+                        log_start_offset: if version >= 5 { 3 } else { -1 },
+                        ..ProducePartitionResponse::appended(
+                            0,
+                            42,
+                            (version >= 2).then_some(1_700_000_000_000),
+                        )
+                    },
+                    failed.clone(),
+                ],
+            }],
+            throttle_time_ms: if version >= 1 { 7 } else { 0 },
+        };
+        for version in PRODUCE_MIN_VERSION..=PRODUCE_MAX_VERSION {
+            if version >= 8 {
+                failed.record_errors = vec![ProduceRecordError {
+                    batch_index: 0,
+                    batch_index_error_message: Some("bad crc".to_string()),
+                }];
+                failed.error_message = Some("corrupt batch".to_string());
+            }
+            let response = response(version, &failed);
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut buffer = encoded.freeze();
+            assert_eq!(
+                ProduceResponse::decode(&mut buffer, version).unwrap(),
+                response,
+                "v{}",
+                version
+            );
+            assert!(buffer.is_empty());
+        }
+    }
+}
@@ -0,0 +1,912 @@
+use crate::protocol::compression::CompressionType;
+use crate::protocol::encoding::{ProtocolEncode, WireFormat};
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::fetch::READ_COMMITTED;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::borrow::Cow;
+
+/// Size of the base offset and batch length fields that precede the rest
+/// of a v2 record batch header
+const LOG_OVERHEAD: usize = 8 + 4;
+
+/// Size of a complete v2 record batch header
+const BATCH_HEADER_SIZE: usize = LOG_OVERHEAD + 4 + 1 + 4 + 2 + 4 + 8 + 8 + 8 + 2 + 4 + 4;
+
+const MAGIC_OFFSET: usize = 16;
+const CRC_OFFSET: usize = 17;
+const ATTRIBUTES_OFFSET: usize = 21;
+const LAST_OFFSET_DELTA_OFFSET: usize = 23;
+const BASE_TIMESTAMP_OFFSET: usize = 27;
+const MAX_TIMESTAMP_OFFSET: usize = 35;
+const PRODUCER_ID_OFFSET: usize = 43;
+const PRODUCER_EPOCH_OFFSET: usize = 51;
+const BASE_SEQUENCE_OFFSET: usize = 53;
+const RECORDS_COUNT_OFFSET: usize = 57;
+
+/// Attribute bit set when the broker assigns record timestamps
+const LOG_APPEND_TIME_ATTRIBUTE: i16 = 0x08;
+
+/// Attribute bit set on batches written by a transactional producer
+const TRANSACTIONAL_ATTRIBUTE: i16 = 0x10;
+
+/// Attribute bit set on batches holding control records rather than data
+pub const CONTROL_ATTRIBUTE: i16 = 0x20;
+
+/// Partition leader epoch a producer writes; the broker assigns the real one
+pub const NO_PARTITION_LEADER_EPOCH: i32 = -1;
+
+/// Producer id of a batch from a non-idempotent producer
+pub const NO_PRODUCER_ID: i64 = -1;
+
+/// Producer epoch of a batch from a non-idempotent producer
+pub const NO_PRODUCER_EPOCH: i16 = -1;
+
+/// Base sequence of a batch from a non-idempotent producer
+pub const NO_SEQUENCE: i32 = -1;
+
+/// Timestamp of a record that carries none, as a magic 0 message
+pub const NO_TIMESTAMP: i64 = -1;
+
+/// Marker carried by a record of a control batch
+///
+/// The type comes from the record key, an INT16 version followed by an
+/// INT16 type; the value holds type-specific data that is not decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlRecord {
+    /// End of an aborted transaction
+    Abort,
+    /// End of a committed transaction
+    Commit,
+    /// A new KRaft leader was elected
+    LeaderChange,
+    SnapshotHeader,
+    SnapshotFooter,
+    Unknown(i16),
+}
+
+impl ControlRecord {
+    /// Parses the key of a control record
+    pub fn from_key(key: &[u8]) -> ProtocolResult<Self> {
+        if key.len() < 4 {
+            return Err(ProtocolError::insufficient_bytes(4, key.len()));
+        }
+        let control_type = i16::from_be_bytes([key[2], key[3]]);
+        Ok(match control_type {
+            0 => Self::Abort,
+            1 => Self::Commit,
+            2 => Self::LeaderChange,
+            3 => Self::SnapshotHeader,
+            4 => Self::SnapshotFooter,
+            _ => Self::Unknown(control_type),
+        })
+    }
+}
+
+/// One record of a [`RecordBatch`], with its deltas resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub offset: i64,
+    pub timestamp: i64,
+    pub key: Option<Bytes>,
+    pub value: Option<Bytes>,
+    pub headers: Vec<(String, Option<Bytes>)>,
+}
+
+impl Record {
+    /// The headers in wire order, borrowed from the record
+    ///
+    /// Keys may repeat; a `None` value is a header written with a null
+    /// value.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, Option<&Bytes>)> {
+        self.headers
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_ref()))
+    }
+}
+
+/// One v2 (magic 2) record batch, kept in wire format
+///
+/// Only the header fields the broker needs are parsed; the records
+/// themselves are left untouched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordBatch {
+    data: Bytes,
+    attributes: i16,
+    last_offset_delta: i32,
+    max_timestamp: i64,
+    record_count: i32,
+}
+
+impl RecordBatch {
+    /// The complete batch as sent by the client
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// Base offset written by the client, normally 0
+    pub fn base_offset(&self) -> i64 {
+        i64::from_be_bytes(self.data[..8].try_into().unwrap())
+    }
+
+    /// Number of offsets the batch spans
+    ///
+    /// This is `lastOffsetDelta + 1`, which differs from the record count
+    /// for compacted batches.
+    pub fn offset_count(&self) -> i64 {
+        self.last_offset_delta as i64 + 1
+    }
+
+    /// Number of records in the batch
+    pub fn record_count(&self) -> i32 {
+        self.record_count
+    }
+
+    /// Largest record timestamp in the batch
+    pub fn max_timestamp(&self) -> i64 {
+        self.max_timestamp
+    }
+
+    /// Timestamp the record timestamp deltas are relative to
+    pub fn base_timestamp(&self) -> i64 {
+        self.read_i64(BASE_TIMESTAMP_OFFSET)
+    }
+
+    /// Producer id, [`NO_PRODUCER_ID`] for a non-idempotent producer
+    pub fn producer_id(&self) -> i64 {
+        self.read_i64(PRODUCER_ID_OFFSET)
+    }
+
+    /// Producer epoch, [`NO_PRODUCER_EPOCH`] for a non-idempotent producer
+    pub fn producer_epoch(&self) -> i16 {
+        i16::from_be_bytes(
+            self.data[PRODUCER_EPOCH_OFFSET..BASE_SEQUENCE_OFFSET]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Sequence number of the first record, [`NO_SEQUENCE`] when unset
+    pub fn base_sequence(&self) -> i32 {
+        i32::from_be_bytes(
+            self.data[BASE_SEQUENCE_OFFSET..RECORDS_COUNT_OFFSET]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Sequence number of the last record, wrapping past `i32::MAX` to 0
+    /// as producers do
+    pub fn last_sequence(&self) -> i32 {
+        let last = self.base_sequence() as i64 + self.last_offset_delta as i64;
+        (last % (i32::MAX as i64 + 1)) as i32
+    }
+
+    /// Codec the records section is compressed with
+    pub fn compression(&self) -> ProtocolResult<CompressionType> {
+        CompressionType::from_attributes(self.attributes)
+    }
+
+    /// Whether the batch was written by a transactional producer
+    pub fn is_transactional(&self) -> bool {
+        self.attributes & TRANSACTIONAL_ATTRIBUTE != 0
+    }
+
+    /// Whether the batch holds control records such as transaction markers
+    pub fn is_control(&self) -> bool {
+        self.attributes & CONTROL_ATTRIBUTE != 0
+    }
+
+    /// Whether a fetch at `isolation_level` should return the batch
+    ///
+    /// Read-committed consumers need the transaction markers to drop
+    /// aborted records; everyone else discards control batches unread, so
+    /// they are left out.
+    pub fn is_fetched_at(&self, isolation_level: i8) -> bool {
+        !self.is_control() || isolation_level == READ_COMMITTED
+    }
+
+    /// The markers of a control batch
+    pub fn control_records(&self) -> ProtocolResult<Vec<ControlRecord>> {
+        if !self.is_control() {
+            return Err(ProtocolError::InvalidFormat(
+                "record batch is not a control batch".to_string(),
+            ));
+        }
+        self.records()?
+            .iter()
+            .map(|record| {
+                let key = record.key.as_deref().ok_or_else(|| {
+                    ProtocolError::InvalidFormat(format!(
+                        "control record at offset {} has no key",
+                        record.offset
+                    ))
+                })?;
+                ControlRecord::from_key(key)
+            })
+            .collect()
+    }
+
+    /// Whether the batch is marked as using broker-assigned timestamps
+    pub fn is_log_append_time(&self) -> bool {
+        self.attributes & LOG_APPEND_TIME_ATTRIBUTE != 0
+    }
+
+    /// The batch with its base offset rewritten
+    ///
+    /// The base offset is outside the CRC, so the checksum stays valid.
+    pub fn with_base_offset(&self, base_offset: i64) -> Bytes {
+        let mut data = BytesMut::with_capacity(self.data.len());
+        data.put_i64(base_offset);
+        data.put_slice(&self.data[8..]);
+        data.freeze()
+    }
+
+    /// The batch at `base_offset`, stamped with the broker's append time
+    ///
+    /// Sets the LogAppendTime attribute and writes `timestamp` as the max
+    /// timestamp, which readers then take as the timestamp of every record,
+    /// and recomputes the CRC that covers both.
+    pub fn with_log_append_time(&self, base_offset: i64, timestamp: i64) -> Bytes {
+        let mut data = BytesMut::from(&self.with_base_offset(base_offset)[..]);
+        let attributes = self.attributes | LOG_APPEND_TIME_ATTRIBUTE;
+        data[ATTRIBUTES_OFFSET..ATTRIBUTES_OFFSET + 2].copy_from_slice(&attributes.to_be_bytes());
+        data[MAX_TIMESTAMP_OFFSET..MAX_TIMESTAMP_OFFSET + 8]
+            .copy_from_slice(&timestamp.to_be_bytes());
+        let crc = crc32c::crc32c(&data[ATTRIBUTES_OFFSET..]);
+        data[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+        data.freeze()
+    }
+
+    /// Decodes the records of the batch, decompressing them if needed
+    ///
+    /// Keys, values and header values of an uncompressed batch are views
+    /// into it rather than copies.
+    pub fn records(&self) -> ProtocolResult<Vec<Record>> {
+        let base_offset = self.base_offset();
+        let base_timestamp = self.base_timestamp();
+        let mut buffer = match self.compression()? {
+            CompressionType::None => self.data.slice(BATCH_HEADER_SIZE..),
+            codec => Bytes::from(codec.decompress(&self.data[BATCH_HEADER_SIZE..])?),
+        };
+        let mut records = Vec::with_capacity(self.record_count as usize);
+        for _ in 0..self.record_count {
+            let length = WireFormat::decode_varint(&mut buffer)?;
+            if length < 0 || buffer.remaining() < length as usize {
+                return Err(ProtocolError::invalid_length(length));
+            }
+            let mut record = buffer.split_to(length as usize);
+            let _attributes = WireFormat::decode_i8(&mut record)?;
+            let timestamp_delta = WireFormat::decode_varlong(&mut record)?;
+            let offset_delta = WireFormat::decode_varint(&mut record)?;
+            let key = decode_varint_bytes(&mut record)?;
+            let value = decode_varint_bytes(&mut record)?;
+            let header_count = WireFormat::decode_varint(&mut record)?;
+            if header_count < 0 {
+                return Err(ProtocolError::invalid_length(header_count));
+            }
+            let mut headers = Vec::new();
+            for _ in 0..header_count {
+                let key =
+                    decode_varint_bytes(&mut record)?.ok_or(ProtocolError::invalid_length(-1))?;
+                let key = String::from_utf8(key.to_vec())
+                    .map_err(|error| ProtocolError::InvalidUtf8(error.to_string()))?;
+                headers.push((key, decode_varint_bytes(&mut record)?));
+            }
+            if record.has_remaining() {
+                return Err(ProtocolError::InvalidFormat(format!(
+                    "record at offset delta {} has {} trailing bytes",
+                    offset_delta,
+                    record.remaining()
+                )));
+            }
+            records.push(Record {
+                offset: base_offset + offset_delta as i64,
+                timestamp: base_timestamp + timestamp_delta,
+                key,
+                value,
+                headers,
+            });
+        }
+        if buffer.has_remaining() {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "record batch has {} bytes past its {} records",
+                buffer.remaining(),
+                self.record_count
+            )));
+        }
+        Ok(records)
+    }
+
+    fn read_i64(&self, at: usize) -> i64 {
+        i64::from_be_bytes(self.data[at..at + 8].try_into().unwrap())
+    }
+}
+
+/// Decodes a varint-length byte field, null when the length is -1
+fn decode_varint_bytes(buffer: &mut Bytes) -> ProtocolResult<Option<Bytes>> {
+    let length = WireFormat::decode_varint(buffer)?;
+    if length == -1 {
+        return Ok(None);
+    }
+    if length < 0 || buffer.remaining() < length as usize {
+        return Err(ProtocolError::invalid_length(length));
+    }
+    Ok(Some(buffer.split_to(length as usize)))
+}
+
+fn encode_varint_bytes(buffer: &mut BytesMut, value: Option<&[u8]>) {
+    match value {
+        Some(value) => {
+            WireFormat::encode_varint(buffer, value.len() as i32);
+            buffer.put_slice(value);
+        }
+        None => WireFormat::encode_varint(buffer, -1),
+    }
+}
+
+fn varint_bytes_size(value: Option<&[u8]>) -> usize {
+    value.map_or(1, |value| {
+        WireFormat::varint_size(value.len() as i32) + value.len()
+    })
+}
+
+/// Builds a v2 record batch from individual records
+///
+/// Offsets are assigned consecutively from the base offset and timestamps
+/// are stored relative to the first record's, as the Java client does.
+/// Records are encoded as they are appended and compressed as a whole when
+/// the batch is encoded; the CRC is backfilled once the whole batch has
+/// been written.
+#[derive(Debug, Clone)]
+pub struct RecordBatchBuilder {
+    base_offset: i64,
+    partition_leader_epoch: i32,
+    attributes: i16,
+    compression: CompressionType,
+    producer_id: i64,
+    producer_epoch: i16,
+    base_sequence: i32,
+    base_timestamp: i64,
+    max_timestamp: i64,
+    record_count: i32,
+    records: BytesMut,
+}
+
+impl RecordBatchBuilder {
+    /// An empty batch whose first record will get `base_offset`
+    pub fn new(base_offset: i64) -> Self {
+        Self {
+            base_offset,
+            partition_leader_epoch: NO_PARTITION_LEADER_EPOCH,
+            attributes: 0,
+            compression: CompressionType::None,
+            producer_id: NO_PRODUCER_ID,
+            producer_epoch: NO_PRODUCER_EPOCH,
+            base_sequence: NO_SEQUENCE,
+            base_timestamp: -1,
+            max_timestamp: -1,
+            record_count: 0,
+            records: BytesMut::new(),
+        }
+    }
+
+    /// Sets the partition leader epoch stamped by the broker
+    pub fn with_partition_leader_epoch(mut self, partition_leader_epoch: i32) -> Self {
+        self.partition_leader_epoch = partition_leader_epoch;
+        self
+    }
+
+    /// Marks the batch as written by an idempotent producer
+    pub fn with_producer(
+        mut self,
+        producer_id: i64,
+        producer_epoch: i16,
+        base_sequence: i32,
+    ) -> Self {
+        self.producer_id = producer_id;
+        self.producer_epoch = producer_epoch;
+        self.base_sequence = base_sequence;
+        self
+    }
+
+    /// Marks the batch as part of a transaction
+    pub fn with_transactional(mut self) -> Self {
+        self.attributes |= TRANSACTIONAL_ATTRIBUTE;
+        self
+    }
+
+    /// Marks the batch as using broker-assigned timestamps
+    pub fn with_log_append_time(mut self) -> Self {
+        self.attributes |= LOG_APPEND_TIME_ATTRIBUTE;
+        self
+    }
+
+    /// Compresses the records with `compression`
+    pub fn with_compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Number of records appended so far
+    pub fn record_count(&self) -> i32 {
+        self.record_count
+    }
+
+    /// Appends a record at the next offset
+    pub fn append(
+        &mut self,
+        timestamp: i64,
+        key: Option<Bytes>,
+        value: Option<Bytes>,
+        headers: Vec<(String, Option<Bytes>)>,
+    ) -> &mut Self {
+        if self.record_count == 0 {
+            self.base_timestamp = timestamp;
+        }
+        self.max_timestamp = self.max_timestamp.max(timestamp);
+        let timestamp_delta = timestamp - self.base_timestamp;
+        let offset_delta = self.record_count;
+
+        let length = 1
+            + WireFormat::varlong_size(timestamp_delta)
+            + WireFormat::varint_size(offset_delta)
+            + varint_bytes_size(key.as_deref())
+            + varint_bytes_size(value.as_deref())
+            + WireFormat::varint_size(headers.len() as i32)
+            + headers
+                .iter()
+                .map(|(key, value)| {
+                    varint_bytes_size(Some(key.as_bytes())) + varint_bytes_size(value.as_deref())
+                })
+                .sum::<usize>();
+        WireFormat::encode_varint(&mut self.records, length as i32);
+        self.records.put_i8(0); // attributes
+        WireFormat::encode_varlong(&mut self.records, timestamp_delta);
+        WireFormat::encode_varint(&mut self.records, offset_delta);
+        encode_varint_bytes(&mut self.records, key.as_deref());
+        encode_varint_bytes(&mut self.records, value.as_deref());
+        WireFormat::encode_varint(&mut self.records, headers.len() as i32);
+        for (key, value) in &headers {
+            encode_varint_bytes(&mut self.records, Some(key.as_bytes()));
+            encode_varint_bytes(&mut self.records, value.as_deref());
+        }
+        self.record_count += 1;
+        self
+    }
+
+    /// Encodes the batch
+    pub fn build(&self) -> ProtocolResult<Bytes> {
+        Ok(self.encode()?.freeze())
+    }
+
+    /// The records as they go on the wire, compressed if asked to be
+    fn records_section(&self) -> ProtocolResult<Cow<'_, [u8]>> {
+        match self.compression {
+            CompressionType::None => Ok(Cow::Borrowed(&self.records)),
+            codec => Ok(Cow::Owned(codec.compress(&self.records)?)),
+        }
+    }
+}
+
+impl ProtocolEncode for RecordBatchBuilder {
+    fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size());
+        self.encode_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        if self.record_count == 0 {
+            return Err(ProtocolError::SerializationError(
+                "record batch has no records".to_string(),
+            ));
+        }
+        let records = self.records_section()?;
+        let start = buffer.len();
+        buffer.put_i64(self.base_offset);
+        buffer.put_i32((BATCH_HEADER_SIZE - LOG_OVERHEAD + records.len()) as i32);
+        buffer.put_i32(self.partition_leader_epoch);
+        buffer.put_i8(2); // magic
+        buffer.put_u32(0); // crc, backfilled below
+        buffer.put_i16(self.attributes | self.compression.id());
+        buffer.put_i32(self.record_count - 1);
+        buffer.put_i64(self.base_timestamp);
+        buffer.put_i64(self.max_timestamp);
+        buffer.put_i64(self.producer_id);
+        buffer.put_i16(self.producer_epoch);
+        buffer.put_i32(self.base_sequence);
+        buffer.put_i32(self.record_count);
+        buffer.put_slice(&records);
+
+        let crc = crc32c::crc32c(&buffer[start + ATTRIBUTES_OFFSET..]);
+        buffer[start + CRC_OFFSET..start + ATTRIBUTES_OFFSET].copy_from_slice(&crc.to_be_bytes());
+        Ok(())
+    }
+
+    /// Exact, but compresses the records to find out when the batch is
+    /// compressed
+    fn encoded_size(&self) -> usize {
+        let records = self
+            .records_section()
+            .map_or(self.records.len(), |records| records.len());
+        BATCH_HEADER_SIZE + records
+    }
+}
+
+/// Splits the `records` field of a produce partition into its batches
+///
+/// Batches are delimited by their length headers. Every batch must be
+/// complete, use magic 2 and carry a valid CRC-32C; the first problem
+/// fails the whole split, so callers can reject the partition without
+/// appending any of it.
+pub fn split_record_batches(records: &Bytes) -> ProtocolResult<Vec<RecordBatch>> {
+    let mut batches = Vec::new();
+    let mut position = 0;
+
+    while position < records.len() {
+        let remaining = records.len() - position;
+        if remaining < LOG_OVERHEAD {
+            return Err(ProtocolError::insufficient_bytes(LOG_OVERHEAD, remaining));
+        }
+        let length_field = &records[position + 8..position + LOG_OVERHEAD];
+        let batch_length = i32::from_be_bytes(length_field.try_into().unwrap());
+        if batch_length < (BATCH_HEADER_SIZE - LOG_OVERHEAD) as i32 {
+            return Err(ProtocolError::invalid_length(batch_length));
+        }
+        let size = LOG_OVERHEAD + batch_length as usize;
+        if remaining < size {
+            return Err(ProtocolError::insufficient_bytes(size, remaining));
+        }
+
+        let data = records.slice(position..position + size);
+        let magic = data[MAGIC_OFFSET] as i8;
+        if magic != 2 {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "unsupported record batch magic {}",
+                magic
+            )));
+        }
+        let crc = u32::from_be_bytes(data[CRC_OFFSET..ATTRIBUTES_OFFSET].try_into().unwrap());
+        let computed = crc32c::crc32c(&data[ATTRIBUTES_OFFSET..]);
+        if crc != computed {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "record batch {} has crc {:#010x}, computed {:#010x}",
+                batches.len(),
+                crc,
+                computed
+            )));
+        }
+
+        let read_i32 = |at: usize| i32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+        let last_offset_delta = read_i32(LAST_OFFSET_DELTA_OFFSET);
+        let record_count = read_i32(RECORDS_COUNT_OFFSET);
+        if last_offset_delta < 0 || record_count < 0 {
+            return Err(ProtocolError::InvalidFormat(format!(
+                "record batch {} has last offset delta {} and {} records",
+                batches.len(),
+                last_offset_delta,
+                record_count
+            )));
+        }
+        batches.push(RecordBatch {
+            attributes: i16::from_be_bytes(
+                data[ATTRIBUTES_OFFSET..LAST_OFFSET_DELTA_OFFSET]
+                    .try_into()
+                    .unwrap(),
+            ),
+            last_offset_delta,
+            max_timestamp: i64::from_be_bytes(
+                data[MAX_TIMESTAMP_OFFSET..MAX_TIMESTAMP_OFFSET + 8]
+                    .try_into()
+                    .unwrap(),
+            ),
+            record_count,
+            data,
+        });
+        position += size;
+    }
+
+    Ok(batches)
+}
+
+/// A control batch with one LeaderChange record, laid out as a KRaft
+/// controller writes it
+#[cfg(test)]
+pub fn leader_change_test_batch() -> Bytes {
+    hex::decode(
+        [
+            "00000000000000000000004fffffffff021154eaf90020000000000000018bcfe568",
+            "000000018bcfe56800ffffffffffffffffffffffffffff000000013a0000000800",
+            "000002260000000000010200000001000200000001000000",
+        ]
+        .concat(),
+    )
+    .unwrap()
+    .into()
+}
+
+/// Encodes a batch of `record_count` empty records for tests
+#[cfg(test)]
+pub fn encode_test_batch(record_count: i32, max_timestamp: i64) -> Bytes {
+    let mut builder = RecordBatchBuilder::new(0);
+    for _ in 0..record_count {
+        builder.append(max_timestamp, None, None, Vec::new());
+    }
+    builder.build().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn concatenated(batches: &[Bytes]) -> Bytes {
+        batches.concat().into()
+    }
+
+    fn reference_builder() -> RecordBatchBuilder {
+        let mut builder = RecordBatchBuilder::new(0);
+        builder
+            .append(
+                1_700_000_000_000,
+                Some(Bytes::from_static(b"k1")),
+                Some(Bytes::from_static(b"v1")),
+                Vec::new(),
+            )
+            .append(
+                1_700_000_000_005,
+                None,
+                Some(Bytes::from_static(b"v2")),
+                vec![("h".to_string(), Some(Bytes::from_static(b"x")))],
+            )
+            .append(
+                1_700_000_000_003,
+                Some(Bytes::from_static(b"k3")),
+                None,
+                vec![("trace".to_string(), None)],
+            );
+        builder
+    }
+
+    #[test]
+    fn test_builder_matches_java_client_layout() {
+        // The Java client's layout for the same three records:
+        // no partition leader epoch, no producer, first timestamp as base
+        let expected = [
+            "0000000000000000",                 // base_offset
+            "00000059",                         // batch_length
+            "ffffffff",                         // partition_leader_epoch
+            "02",                               // magic
+            "eceafee8",                         // crc
+            "0000",                             // attributes
+            "00000002",                         // last_offset_delta
+            "0000018bcfe56800",                 // base_timestamp
+            "0000018bcfe56805",                 // max_timestamp
+            "ffffffffffffffff",                 // producer_id
+            "ffff",                             // producer_epoch
+            "ffffffff",                         // base_sequence
+            "00000003",                         // records.length
+            "14000000046b3104763100",           // records[0]
+            "18000a02010476320202680278",       // records[1]
+            "1e000604046b3301020a747261636501", // records[2]
+        ]
+        .concat();
+        let builder = reference_builder();
+        let encoded = builder.encode().unwrap();
+        assert_eq!(encoded.len(), builder.encoded_size());
+        assert_eq!(hex::encode(encoded), expected);
+    }
+
+    #[test]
+    fn test_builder_roundtrip() {
+        let encoded = reference_builder()
+            .with_producer(7, 2, 40)
+            .with_transactional()
+            .build()
+            .unwrap();
+        let batch = split_record_batches(&encoded).unwrap().remove(0);
+        assert_eq!(
+            (
+                batch.producer_id(),
+                batch.producer_epoch(),
+                batch.base_sequence()
+            ),
+            (7, 2, 40)
+        );
+        assert!(batch.is_transactional());
+        assert!(!batch.is_log_append_time());
+        assert_eq!(batch.offset_count(), 3);
+        assert_eq!(batch.max_timestamp(), 1_700_000_000_005);
+
+        let records = batch.records().unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| (record.offset, record.timestamp))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, 1_700_000_000_000),
+                (1, 1_700_000_000_005),
+                (2, 1_700_000_000_003)
+            ]
+        );
+        assert_eq!(records[0].key.as_deref(), Some(&b"k1"[..]));
+        assert_eq!(records[1].key, None);
+        assert_eq!(records[2].value, None);
+        assert_eq!(
+            records[1].headers,
+            vec![("h".to_string(), Some(Bytes::from_static(b"x")))]
+        );
+        assert_eq!(records[2].headers, vec![("trace".to_string(), None)]);
+    }
+
+    #[test]
+    fn test_record_headers() {
+        let mut builder = RecordBatchBuilder::new(0);
+        builder
+            .append(0, None, Some(Bytes::from_static(b"v")), Vec::new())
+            .append(
+                0,
+                None,
+                None,
+                vec![
+                    ("trace-id".to_string(), Some(Bytes::from_static(b"abc"))),
+                    ("trace-id".to_string(), Some(Bytes::from_static(b"def"))),
+                    ("schéma-版本".to_string(), Some(Bytes::new())),
+                    ("tombstone".to_string(), None),
+                ],
+            );
+        let encoded = builder.build().unwrap();
+        let records = split_record_batches(&encoded).unwrap()[0]
+            .records()
+            .unwrap();
+
+        assert_eq!(records[0].headers().count(), 0);
+        assert_eq!(
+            records[1].headers().collect::<Vec<_>>(),
+            vec![
+                ("trace-id", Some(&Bytes::from_static(b"abc"))),
+                ("trace-id", Some(&Bytes::from_static(b"def"))),
+                ("schéma-版本", Some(&Bytes::new())),
+                ("tombstone", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decodes_java_client_gzip_batch() {
+        // The reference records gzipped by java.util.zip.GZIPOutputStream,
+        // as the Java client's gzip codec does
+        let encoded = hex::decode(
+            [
+                "0000000000000000000000",
+                "6dffffffff02de7d1ed20001000000020000018bcfe568000000018bcfe56805",
+                "ffffffffffffffffffffffffffff00000003",
+                "1f8b08000000000000ff1361606060c936642933649060e062626429336262ca",
+                "60aa9063606361c9366664e22a294a4c4e6504009a7c372a28000000",
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let batch = split_record_batches(&encoded.into()).unwrap().remove(0);
+        assert_eq!(batch.compression().unwrap(), CompressionType::Gzip);
+
+        let reference = reference_builder().build().unwrap();
+        let reference = split_record_batches(&reference).unwrap().remove(0);
+        assert_eq!(batch.records().unwrap(), reference.records().unwrap());
+    }
+
+    #[test]
+    fn test_compressed_builder_roundtrip() {
+        let codecs = [
+            CompressionType::Gzip,
+            CompressionType::Snappy,
+            CompressionType::Lz4,
+            CompressionType::Zstd,
+        ];
+        let reference = reference_builder().build().unwrap();
+        let expected = split_record_batches(&reference).unwrap()[0]
+            .records()
+            .unwrap();
+        for codec in codecs.into_iter().filter(|codec| codec.is_available()) {
+            let builder = reference_builder().with_compression(codec);
+            let encoded = builder.encode().unwrap();
+            assert_eq!(encoded.len(), builder.encoded_size(), "{}", codec);
+            let batch = split_record_batches(&encoded.freeze()).unwrap().remove(0);
+            assert_eq!(batch.compression().unwrap(), codec);
+            assert_eq!(batch.records().unwrap(), expected, "{}", codec);
+        }
+    }
+
+    #[test]
+    fn test_decodes_commit_marker() {
+        // A transaction commit marker as the Java transaction coordinator
+        // writes it: version 0, type 1, coordinator epoch 2
+        let encoded = hex::decode(
+            [
+                "000000000000000500000042ffffffff02f01af5600030000000000000018bcfe568",
+                "000000018bcfe5680000000000000003e80003ffffffff00000001200000000800",
+                "0000010c00000000000200",
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let batch = split_record_batches(&encoded.into()).unwrap().remove(0);
+        assert!(batch.is_control());
+        assert!(batch.is_transactional());
+        assert_eq!(batch.producer_id(), 1000);
+        assert_eq!(batch.control_records().unwrap(), [ControlRecord::Commit]);
+        assert!(batch.is_fetched_at(READ_COMMITTED));
+        assert!(!batch.is_fetched_at(crate::protocol::fetch::READ_UNCOMMITTED));
+    }
+
+    #[test]
+    fn test_decodes_leader_change() {
+        let batch = split_record_batches(&leader_change_test_batch())
+            .unwrap()
+            .remove(0);
+        assert!(batch.is_control());
+        assert!(!batch.is_transactional());
+        assert_eq!(
+            batch.control_records().unwrap(),
+            [ControlRecord::LeaderChange]
+        );
+    }
+
+    #[test]
+    fn test_data_batches_are_not_control() {
+        let batch = split_record_batches(&encode_test_batch(1, 0))
+            .unwrap()
+            .remove(0);
+        assert!(!batch.is_control());
+        assert!(batch.is_fetched_at(crate::protocol::fetch::READ_UNCOMMITTED));
+        assert!(batch.control_records().is_err());
+        assert_eq!(
+            ControlRecord::from_key(&[0, 0, 0, 9]).unwrap(),
+            ControlRecord::Unknown(9)
+        );
+    }
+
+    #[test]
+    fn test_empty_builder_is_rejected() {
+        assert!(RecordBatchBuilder::new(0).build().is_err());
+    }
+
+    #[test]
+    fn test_split_concatenated_batches() {
+        let records = concatenated(&[
+            encode_test_batch(1, 100),
+            encode_test_batch(3, 200),
+            encode_test_batch(2, 300),
+        ]);
+        let batches = split_record_batches(&records).unwrap();
+        assert_eq!(
+            batches
+                .iter()
+                .map(|batch| (batch.offset_count(), batch.max_timestamp()))
+                .collect::<Vec<_>>(),
+            vec![(1, 100), (3, 200), (2, 300)]
+        );
+    }
+
+    #[test]
+    fn test_rewritten_base_offset_keeps_crc_valid() {
+        let batch = split_record_batches(&encode_test_batch(2, 0))
+            .unwrap()
+            .remove(0);
+        let rewritten = batch.with_base_offset(42);
+        let reparsed = split_record_batches(&rewritten).unwrap().remove(0);
+        assert_eq!(reparsed.base_offset(), 42);
+    }
+
+    #[test]
+    fn test_rejects_torn_or_corrupt_batches() {
+        let batch = encode_test_batch(2, 0);
+        let torn = concatenated(&[batch.clone(), batch.slice(..batch.len() - 1)]);
+        assert!(split_record_batches(&torn).is_err());
+
+        let mut corrupt = batch.to_vec();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        assert!(split_record_batches(&corrupt.into()).is_err());
+    }
+}
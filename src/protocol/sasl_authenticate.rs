@@ -0,0 +1,248 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest SaslAuthenticate version we serve
+pub const SASL_AUTHENTICATE_MIN_VERSION: i16 = 0;
+
+/// Highest SaslAuthenticate version we serve
+pub const SASL_AUTHENTICATE_MAX_VERSION: i16 = 2;
+
+/// First flexible SaslAuthenticate version
+const FIRST_FLEXIBLE_VERSION: i16 = 2;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "SaslAuthenticate",
+        version,
+        SASL_AUTHENTICATE_MIN_VERSION..=SASL_AUTHENTICATE_MAX_VERSION,
+    )
+}
+
+/// SaslAuthenticate request (API key 36)
+///
+/// - v0: the client's SASL token
+/// - v2+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaslAuthenticateRequest {
+    pub auth_bytes: Bytes,
+}
+
+impl SaslAuthenticateRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let auth_bytes = WireFormat::decode_flexible_bytes(buffer, flexible)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self { auth_bytes })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for SaslAuthenticateRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_bytes(buffer, &self.auth_bytes, flexible)?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::flexible_bytes_size(&self.auth_bytes, flexible) + usize::from(flexible)
+    }
+}
+
+impl ProtocolDecodeVersioned for SaslAuthenticateRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// SaslAuthenticate response (API key 36)
+///
+/// - v0: error code and message, and the server's SASL token
+/// - v1+: `session_lifetime_ms`
+/// - v2+: flexible
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaslAuthenticateResponse {
+    pub error_code: ErrorCode,
+    pub error_message: Option<String>,
+    pub auth_bytes: Bytes,
+    /// v1+; 0 when the session never has to re-authenticate
+    pub session_lifetime_ms: i64,
+}
+
+impl SaslAuthenticateResponse {
+    /// A response carrying only an error
+    pub fn error(error_code: impl Into<ErrorCode>, message: impl Into<String>) -> Self {
+        Self {
+            error_code: error_code.into(),
+            error_message: Some(message.into()),
+            auth_bytes: Bytes::new(),
+            session_lifetime_ms: 0,
+        }
+    }
+
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        let error_message = WireFormat::decode_flexible_nullable_string(buffer, flexible)?;
+        let auth_bytes = WireFormat::decode_flexible_bytes(buffer, flexible)?;
+        let session_lifetime_ms = if version >= 1 {
+            WireFormat::decode_i64(buffer)?
+        } else {
+            0
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            error_code,
+            error_message,
+            auth_bytes,
+            session_lifetime_ms,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for SaslAuthenticateResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        buffer.put_i16(self.error_code.code());
+        WireFormat::encode_flexible_nullable_string(
+            buffer,
+            self.error_message.as_deref(),
+            flexible,
+        )?;
+        WireFormat::encode_flexible_bytes(buffer, &self.auth_bytes, flexible)?;
+        if version >= 1 {
+            buffer.put_i64(self.session_lifetime_ms);
+        }
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let session_lifetime = if version >= 1 { 8 } else { 0 };
+        2 + WireFormat::flexible_nullable_string_size(self.error_message.as_deref(), flexible)
+            + WireFormat::flexible_bytes_size(&self.auth_bytes, flexible)
+            + session_lifetime
+            + usize::from(flexible)
+    }
+}
+
+impl ProtocolDecodeVersioned for SaslAuthenticateResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> SaslAuthenticateResponse {
+        SaslAuthenticateResponse {
+            error_code: ErrorCode::NONE,
+            error_message: None,
+            auth_bytes: Bytes::from_static(b"ok"),
+            session_lifetime_ms: 3_600_000,
+        }
+    }
+
+    #[test]
+    fn test_v2_response_layout() {
+        let expected = [
+            "0000",             // error_code
+            "00",               // error_message
+            "036f6b",           // auth_bytes
+            "000000000036ee80", // session_lifetime_ms
+            "00",               // tagged fields
+        ]
+        .concat();
+        assert_eq!(hex::encode(response().encode(2).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        let request = SaslAuthenticateRequest {
+            auth_bytes: Bytes::from_static(b"\0alice\0secret"),
+        };
+        for version in SASL_AUTHENTICATE_MIN_VERSION..=SASL_AUTHENTICATE_MAX_VERSION {
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                SaslAuthenticateRequest::decode(&mut encoded, version).unwrap(),
+                request,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = SaslAuthenticateResponse::error(
+            ErrorCode::SASL_AUTHENTICATION_FAILED,
+            "Malformed PLAIN token",
+        );
+        let response = SaslAuthenticateResponse {
+            session_lifetime_ms: 5,
+            ..response
+        };
+        for version in SASL_AUTHENTICATE_MIN_VERSION..=SASL_AUTHENTICATE_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 1 {
+                expected.session_lifetime_ms = 0;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                SaslAuthenticateResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v3_is_rejected() {
+        assert!(response().encode(3).is_err());
+    }
+}
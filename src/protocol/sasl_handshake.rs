@@ -0,0 +1,190 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest SaslHandshake version we serve
+pub const SASL_HANDSHAKE_MIN_VERSION: i16 = 0;
+
+/// Highest SaslHandshake version we serve
+pub const SASL_HANDSHAKE_MAX_VERSION: i16 = 1;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "SaslHandshake",
+        version,
+        SASL_HANDSHAKE_MIN_VERSION..=SASL_HANDSHAKE_MAX_VERSION,
+    )
+}
+
+/// SaslHandshake request (API key 17)
+///
+/// - v0: the mechanism; SASL tokens then follow as raw frames
+/// - v1: same body; tokens are carried by SaslAuthenticate instead
+///
+/// No version is flexible.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaslHandshakeRequest {
+    pub mechanism: String,
+}
+
+impl SaslHandshakeRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        Ok(Self {
+            mechanism: WireFormat::decode_string(buffer)?,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for SaslHandshakeRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        WireFormat::encode_string(buffer, &self.mechanism)
+    }
+
+    fn encoded_size(&self, _version: i16) -> usize {
+        WireFormat::string_size(&self.mechanism)
+    }
+}
+
+impl ProtocolDecodeVersioned for SaslHandshakeRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// SaslHandshake response (API key 17)
+///
+/// Every version carries the error code and the mechanisms the broker
+/// enables, which clients use to report a mismatch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaslHandshakeResponse {
+    pub error_code: ErrorCode,
+    pub mechanisms: Vec<String>,
+}
+
+impl SaslHandshakeResponse {
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        let mechanisms = WireFormat::decode_array(buffer, WireFormat::decode_string)?;
+        Ok(Self {
+            error_code,
+            mechanisms,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for SaslHandshakeResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        buffer.put_i16(self.error_code.code());
+        WireFormat::encode_array(buffer, &self.mechanisms, |buffer, mechanism| {
+            WireFormat::encode_string(buffer, mechanism)
+        })
+    }
+
+    fn encoded_size(&self, _version: i16) -> usize {
+        2 + 4
+            + self
+                .mechanisms
+                .iter()
+                .map(|mechanism| WireFormat::string_size(mechanism))
+                .sum::<usize>()
+    }
+}
+
+impl ProtocolDecodeVersioned for SaslHandshakeResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> SaslHandshakeResponse {
+        SaslHandshakeResponse {
+            error_code: ErrorCode::UNSUPPORTED_SASL_MECHANISM,
+            mechanisms: vec!["PLAIN".to_string(), "SCRAM-SHA-256".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_response_layout() {
+        let expected = [
+            "0021",                           // error_code
+            "00000002",                       // mechanisms.length
+            "0005504c41494e",                 // mechanisms[0]
+            "000d534352414d2d5348412d323536", // mechanisms[1]
+        ]
+        .concat();
+        assert_eq!(hex::encode(response().encode(1).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        let request = SaslHandshakeRequest {
+            mechanism: "PLAIN".to_string(),
+        };
+        for version in SASL_HANDSHAKE_MIN_VERSION..=SASL_HANDSHAKE_MAX_VERSION {
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                SaslHandshakeRequest::decode(&mut encoded, version).unwrap(),
+                request,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = response();
+        for version in SASL_HANDSHAKE_MIN_VERSION..=SASL_HANDSHAKE_MAX_VERSION {
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                SaslHandshakeResponse::decode(&mut encoded, version).unwrap(),
+                response,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v2_is_rejected() {
+        assert!(response().encode(2).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::protocol::encoding::{
+    self, ProtocolDecodeVersioned, ProtocolEncodeVersioned, WireFormat,
+};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::errors::ProtocolResult;
+use crate::protocol::tagged_fields::{put_empty_tagged_fields, skip_tagged_fields};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Lowest SyncGroup version we serve
+pub const SYNC_GROUP_MIN_VERSION: i16 = 0;
+
+/// Highest SyncGroup version we serve
+pub const SYNC_GROUP_MAX_VERSION: i16 = 5;
+
+/// First flexible SyncGroup version
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+fn check_version(version: i16) -> ProtocolResult<()> {
+    encoding::check_version(
+        "SyncGroup",
+        version,
+        SYNC_GROUP_MIN_VERSION..=SYNC_GROUP_MAX_VERSION,
+    )
+}
+
+/// SyncGroup request (API key 14)
+///
+/// - v0: group, generation, member id and, from the leader, the
+///   assignments
+/// - v3+: `group_instance_id`
+/// - v4+: flexible
+/// - v5+: `protocol_type` and `protocol_name`
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncGroupRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    /// v3+
+    pub group_instance_id: Option<String>,
+    /// v5+
+    pub protocol_type: Option<String>,
+    /// v5+
+    pub protocol_name: Option<String>,
+    /// Empty for every member but the leader
+    pub assignments: Vec<SyncGroupRequestAssignment>,
+}
+
+/// The assignment the leader made for one member
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncGroupRequestAssignment {
+    pub member_id: String,
+    pub assignment: Bytes,
+}
+
+impl SyncGroupRequest {
+    /// Decodes the request body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let group_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let generation_id = WireFormat::decode_i32(buffer)?;
+        let member_id = WireFormat::decode_flexible_string(buffer, flexible)?;
+        let group_instance_id = if version >= 3 {
+            WireFormat::decode_flexible_nullable_string(buffer, flexible)?
+        } else {
+            None
+        };
+        let (protocol_type, protocol_name) = if version >= 5 {
+            (
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+            )
+        } else {
+            (None, None)
+        };
+        let assignments = WireFormat::decode_flexible_array(buffer, flexible, |buffer| {
+            let assignment = SyncGroupRequestAssignment {
+                member_id: WireFormat::decode_flexible_string(buffer, flexible)?,
+                assignment: WireFormat::decode_flexible_bytes(buffer, flexible)?,
+            };
+            skip_tagged_fields(buffer, flexible)?;
+            Ok(assignment)
+        })?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+            protocol_type,
+            protocol_name,
+            assignments,
+        })
+    }
+
+    /// Encodes the request body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl ProtocolEncodeVersioned for SyncGroupRequest {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        WireFormat::encode_flexible_string(buffer, &self.group_id, flexible)?;
+        buffer.put_i32(self.generation_id);
+        WireFormat::encode_flexible_string(buffer, &self.member_id, flexible)?;
+        if version >= 3 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.group_instance_id.as_deref(),
+                flexible,
+            )?;
+        }
+        if version >= 5 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.protocol_type.as_deref(),
+                flexible,
+            )?;
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.protocol_name.as_deref(),
+                flexible,
+            )?;
+        }
+        WireFormat::encode_flexible_array(
+            buffer,
+            &self.assignments,
+            flexible,
+            |buffer, assignment| {
+                WireFormat::encode_flexible_string(buffer, &assignment.member_id, flexible)?;
+                WireFormat::encode_flexible_bytes(buffer, &assignment.assignment, flexible)?;
+                put_empty_tagged_fields(buffer, flexible);
+                Ok(())
+            },
+        )?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let tags = usize::from(flexible);
+        let group_instance_id = if version >= 3 {
+            WireFormat::flexible_nullable_string_size(self.group_instance_id.as_deref(), flexible)
+        } else {
+            0
+        };
+        let protocol = if version >= 5 {
+            WireFormat::flexible_nullable_string_size(self.protocol_type.as_deref(), flexible)
+                + WireFormat::flexible_nullable_string_size(self.protocol_name.as_deref(), flexible)
+        } else {
+            0
+        };
+        let assignments: usize = self
+            .assignments
+            .iter()
+            .map(|assignment| {
+                WireFormat::flexible_string_size(&assignment.member_id, flexible)
+                    + WireFormat::flexible_bytes_size(&assignment.assignment, flexible)
+                    + tags
+            })
+            .sum();
+        WireFormat::flexible_string_size(&self.group_id, flexible)
+            + 4
+            + WireFormat::flexible_string_size(&self.member_id, flexible)
+            + group_instance_id
+            + protocol
+            + WireFormat::flexible_array_length_size(Some(self.assignments.len()), flexible)
+            + assignments
+            + tags
+    }
+}
+
+impl ProtocolDecodeVersioned for SyncGroupRequest {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+/// SyncGroup response (API key 14)
+///
+/// - v0: error code and the member's assignment
+/// - v1+: `throttle_time_ms`
+/// - v4+: flexible
+/// - v5+: `protocol_type` and `protocol_name`
+///
+/// Tagged fields are skipped on decode and written empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncGroupResponse {
+    /// v1+
+    pub throttle_time_ms: i32,
+    pub error_code: ErrorCode,
+    /// v5+
+    pub protocol_type: Option<String>,
+    /// v5+
+    pub protocol_name: Option<String>,
+    pub assignment: Bytes,
+}
+
+impl SyncGroupResponse {
+    /// A failed sync, with an empty assignment
+    pub fn error(error_code: impl Into<ErrorCode>) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code: error_code.into(),
+            protocol_type: None,
+            protocol_name: None,
+            assignment: Bytes::new(),
+        }
+    }
+
+    /// Encodes the response body for the given version
+    pub fn encode(&self, version: i16) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::with_capacity(self.encoded_size(version));
+        self.encode_versioned(version, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Decodes the response body for the given version
+    pub fn decode(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time_ms = if version >= 1 {
+            WireFormat::decode_i32(buffer)?
+        } else {
+            0
+        };
+        let error_code = ErrorCode::from_wire(WireFormat::decode_i16(buffer)?);
+        let (protocol_type, protocol_name) = if version >= 5 {
+            (
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+                WireFormat::decode_flexible_nullable_string(buffer, flexible)?,
+            )
+        } else {
+            (None, None)
+        };
+        let assignment = WireFormat::decode_flexible_bytes(buffer, flexible)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(Self {
+            throttle_time_ms,
+            error_code,
+            protocol_type,
+            protocol_name,
+            assignment,
+        })
+    }
+}
+
+impl ProtocolEncodeVersioned for SyncGroupResponse {
+    fn encode_versioned(&self, version: i16, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        check_version(version)?;
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        if version >= 1 {
+            buffer.put_i32(self.throttle_time_ms);
+        }
+        buffer.put_i16(self.error_code.code());
+        if version >= 5 {
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.protocol_type.as_deref(),
+                flexible,
+            )?;
+            WireFormat::encode_flexible_nullable_string(
+                buffer,
+                self.protocol_name.as_deref(),
+                flexible,
+            )?;
+        }
+        WireFormat::encode_flexible_bytes(buffer, &self.assignment, flexible)?;
+        put_empty_tagged_fields(buffer, flexible);
+        Ok(())
+    }
+
+    fn encoded_size(&self, version: i16) -> usize {
+        let flexible = version >= FIRST_FLEXIBLE_VERSION;
+        let throttle_time = if version >= 1 { 4 } else { 0 };
+        let protocol = if version >= 5 {
+            WireFormat::flexible_nullable_string_size(self.protocol_type.as_deref(), flexible)
+                + WireFormat::flexible_nullable_string_size(self.protocol_name.as_deref(), flexible)
+        } else {
+            0
+        };
+        throttle_time
+            + 2
+            + protocol
+            + WireFormat::flexible_bytes_size(&self.assignment, flexible)
+            + usize::from(flexible)
+    }
+}
+
+impl ProtocolDecodeVersioned for SyncGroupResponse {
+    fn decode_versioned(buffer: &mut Bytes, version: i16) -> ProtocolResult<Self> {
+        Self::decode(buffer, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> SyncGroupRequest {
+        SyncGroupRequest {
+            group_id: "billing".to_string(),
+            generation_id: 2,
+            member_id: "consumer-1".to_string(),
+            group_instance_id: Some("host-a".to_string()),
+            protocol_type: Some("consumer".to_string()),
+            protocol_name: Some("range".to_string()),
+            assignments: vec![SyncGroupRequestAssignment {
+                member_id: "consumer-1".to_string(),
+                assignment: Bytes::from_static(b"orders-0"),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_v0_layout() {
+        let expected = [
+            "000762696c6c696e67",       // group_id
+            "00000002",                 // generation_id
+            "000a636f6e73756d65722d31", // member_id
+            "00000001",                 // assignments
+            "000a636f6e73756d65722d31",
+            "000000086f72646572732d30",
+        ]
+        .concat();
+        assert_eq!(hex::encode(request().encode(0).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_request_roundtrip_every_version() {
+        for version in SYNC_GROUP_MIN_VERSION..=SYNC_GROUP_MAX_VERSION {
+            let request = request();
+            let mut expected = request.clone();
+            if version < 3 {
+                expected.group_instance_id = None;
+            }
+            if version < 5 {
+                expected.protocol_type = None;
+                expected.protocol_name = None;
+            }
+            let encoded = request.encode(version).unwrap();
+            assert_eq!(encoded.len(), request.encoded_size(version), "v{}", version);
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                SyncGroupRequest::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip_every_version() {
+        let response = SyncGroupResponse {
+            throttle_time_ms: 1,
+            error_code: ErrorCode::NONE,
+            protocol_type: Some("consumer".to_string()),
+            protocol_name: Some("range".to_string()),
+            assignment: Bytes::from_static(b"orders-0"),
+        };
+        for version in SYNC_GROUP_MIN_VERSION..=SYNC_GROUP_MAX_VERSION {
+            let mut expected = response.clone();
+            if version < 1 {
+                expected.throttle_time_ms = 0;
+            }
+            if version < 5 {
+                expected.protocol_type = None;
+                expected.protocol_name = None;
+            }
+            let encoded = response.encode(version).unwrap();
+            assert_eq!(
+                encoded.len(),
+                response.encoded_size(version),
+                "v{}",
+                version
+            );
+            let mut encoded = encoded.freeze();
+            assert_eq!(
+                SyncGroupResponse::decode(&mut encoded, version).unwrap(),
+                expected,
+                "v{}",
+                version
+            );
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v6_is_rejected() {
+        assert!(request().encode(6).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::protocol::encoding::{ProtocolDecode, ProtocolEncode, WireFormat};
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The tag section of a flexible message
+///
+/// Holds every field by tag, including ones this broker does not know, so
+/// that re-encoding a decoded message writes the same section back.
+/// Encoded as an unsigned varint count followed by, for each field, its
+/// varint tag, varint size and raw bytes, in strictly ascending tag order.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct TaggedFields {
+    fields: BTreeMap<u32, Bytes>,
+}
+
+impl TaggedFields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Raw bytes of the field with `tag`
+    pub fn get(&self, tag: u32) -> Option<&Bytes> {
+        self.fields.get(&tag)
+    }
+
+    /// Sets a field, returning the bytes it replaced
+    pub fn insert(&mut self, tag: u32, value: impl Into<Bytes>) -> Option<Bytes> {
+        self.fields.insert(tag, value.into())
+    }
+
+    /// Fields in ascending tag order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Bytes)> {
+        self.fields.iter().map(|(tag, value)| (*tag, value))
+    }
+
+    /// Appends the tag section to `buffer`
+    pub fn encode_into(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        WireFormat::encode_unsigned_varint(buffer, self.fields.len() as u32);
+        for (tag, value) in &self.fields {
+            let size = u32::try_from(value.len()).map_err(|_| {
+                ProtocolError::SerializationError(format!(
+                    "tagged field {} of {} bytes is too long",
+                    tag,
+                    value.len()
+                ))
+            })?;
+            WireFormat::encode_unsigned_varint(buffer, *tag);
+            WireFormat::encode_unsigned_varint(buffer, size);
+            buffer.put_slice(value);
+        }
+        Ok(())
+    }
+}
+
+impl ProtocolEncode for TaggedFields {
+    fn encode(&self) -> ProtocolResult<BytesMut> {
+        let mut buffer = BytesMut::new();
+        self.encode_into(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn encode_to(&self, buffer: &mut BytesMut) -> ProtocolResult<()> {
+        self.encode_into(buffer)
+    }
+
+    fn encoded_size(&self) -> usize {
+        let fields: usize = self
+            .fields
+            .iter()
+            .map(|(&tag, value)| {
+                WireFormat::unsigned_varint_size(tag)
+                    + WireFormat::unsigned_varint_size(value.len() as u32)
+                    + value.len()
+            })
+            .sum();
+        WireFormat::unsigned_varint_size(self.fields.len() as u32) + fields
+    }
+}
+
+impl ProtocolDecode for TaggedFields {
+    /// Decodes a tag section, rejecting tags that are not strictly ascending
+    fn decode(buffer: &mut Bytes) -> ProtocolResult<Self> {
+        let count = WireFormat::decode_unsigned_varint(buffer)?;
+        let mut fields = BTreeMap::new();
+        let mut previous = None;
+        for _ in 0..count {
+            let tag = WireFormat::decode_unsigned_varint(buffer)?;
+            if let Some(previous) = previous.filter(|&previous| tag <= previous) {
+                return Err(ProtocolError::InvalidFormat(format!(
+                    "tagged field {} follows tag {}; tags must be strictly ascending",
+                    tag, previous
+                )));
+            }
+            previous = Some(tag);
+            let size = WireFormat::decode_unsigned_varint(buffer)? as usize;
+            if buffer.remaining() < size {
+                return Err(ProtocolError::insufficient_bytes(size, buffer.remaining()));
+            }
+            fields.insert(tag, buffer.split_to(size));
+        }
+        Ok(Self { fields })
+    }
+}
+
+impl fmt::Debug for TaggedFields {
+    /// The field count when empty, as in decode traces; otherwise each tag
+    /// with its bytes in hex
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.fields.is_empty() {
+            return write!(f, "0");
+        }
+        f.debug_map()
+            .entries(
+                self.fields
+                    .iter()
+                    .map(|(tag, value)| (tag, hex::encode(value))),
+            )
+            .finish()
+    }
+}
+
+/// Skips the tag section of a message, if `flexible`
+pub(crate) fn skip_tagged_fields(buffer: &mut Bytes, flexible: bool) -> ProtocolResult<()> {
+    if flexible {
+        TaggedFields::decode(buffer)?;
+    }
+    Ok(())
+}
+
+/// Writes an empty tag section, if `flexible`
+pub(crate) fn put_empty_tagged_fields(buffer: &mut BytesMut, flexible: bool) {
+    if flexible {
+        WireFormat::encode_unsigned_varint(buffer, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_fields() {
+        let mut buffer = TaggedFields::new().encode().unwrap().freeze();
+        assert_eq!(&buffer[..], &[0x00]);
+        let decoded = TaggedFields::decode(&mut buffer).unwrap();
+        assert!(decoded.is_empty());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_one_field() {
+        let mut buffer = Bytes::from_static(&[0x01, 0x03, 0x02, 0xab, 0xcd, 0x7f]);
+        let decoded = TaggedFields::decode(&mut buffer).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded.get(3).unwrap().as_ref(), &[0xab, 0xcd]);
+        // The byte after the section is left for the next field
+        assert_eq!(&buffer[..], &[0x7f]);
+        assert_eq!(
+            &decoded.encode().unwrap()[..],
+            &[0x01, 0x03, 0x02, 0xab, 0xcd]
+        );
+    }
+
+    #[test]
+    fn test_multiple_fields_round_trip() {
+        let mut fields = TaggedFields::new();
+        fields.insert(200, Bytes::from_static(b"later"));
+        fields.insert(0, Bytes::new());
+        fields.insert(7, vec![0u8; 130]);
+
+        let mut buffer = fields.encode().unwrap().freeze();
+        // Written in ascending order whatever the insertion order; tag 200
+        // and the 130-byte size take two varint bytes each
+        assert_eq!(&buffer[..3], &[0x03, 0x00, 0x00]);
+        assert_eq!(&buffer[3..6], &[0x07, 0x82, 0x01]);
+        let decoded = TaggedFields::decode(&mut buffer).unwrap();
+        assert_eq!(decoded, fields);
+        assert_eq!(
+            decoded.iter().map(|(tag, _)| tag).collect::<Vec<_>>(),
+            vec![0, 7, 200]
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_and_duplicate_tags_rejected() {
+        for section in [
+            &[0x02, 0x05, 0x00, 0x01, 0x00][..],
+            &[0x02, 0x05, 0x00, 0x05, 0x00][..],
+        ] {
+            let mut buffer = Bytes::copy_from_slice(section);
+            assert!(matches!(
+                TaggedFields::decode(&mut buffer),
+                Err(ProtocolError::InvalidFormat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_truncated_field() {
+        let mut buffer = Bytes::from_static(&[0x01, 0x00, 0x04, 0xaa]);
+        assert!(matches!(
+            TaggedFields::decode(&mut buffer),
+            Err(ProtocolError::InsufficientBytes {
+                expected: 4,
+                actual: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,470 @@
+use crate::protocol::api_key::ApiKey;
+use crate::protocol::api_versions::ApiVersionsResponse;
+use crate::protocol::encoding::WireFormat;
+use crate::protocol::errors::{ProtocolError, ProtocolResult};
+use crate::protocol::flexible;
+use crate::protocol::headers::{RequestHeaderV2, ResponseHeaderV0};
+use crate::protocol::metadata::MetadataRequest;
+use crate::protocol::tagged_fields::TaggedFields;
+use crate::protocol::ProtocolDecode;
+use bytes::{Buf, Bytes};
+use std::fmt::{Debug, Write};
+use std::ops::Range;
+
+/// Most bytes of a field shown in the annotated hex view
+const MAX_HEX_BYTES: usize = 16;
+
+/// One decoded field: where it sat in the message and what it held
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    /// Dotted path, with `[i]` for array elements, e.g. `api_keys[2].max_version`
+    pub path: String,
+    /// Byte offsets of the field, relative to the first traced byte
+    pub range: Range<usize>,
+    /// The decoded value as rendered by `Debug`
+    pub value: String,
+}
+
+/// Field-by-field record of a decode, in wire order
+///
+/// Filled in by a [`DecodeCursor`] built with [`DecodeCursor::traced`].
+/// Decoding the header and then the body into the same trace gives one
+/// continuous set of offsets.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecodeTrace {
+    entries: Vec<TraceEntry>,
+    end: usize,
+}
+
+/// First field on which two traces disagree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub path: String,
+    /// `None` when that trace ended before the field
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl DecodeTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// Offset just past the last byte consumed so far
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Renders `data`, the traced bytes, one field per line
+    ///
+    /// ```text
+    /// 0000..0002  00 00              error_code = ErrorCode(0)
+    /// ```
+    pub fn render(&self, data: &[u8]) -> String {
+        let mut output = String::new();
+        for entry in &self.entries {
+            let bytes = &data[entry.range.start.min(data.len())..entry.range.end.min(data.len())];
+            let mut hex: Vec<String> = bytes
+                .iter()
+                .take(MAX_HEX_BYTES)
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            if bytes.len() > MAX_HEX_BYTES {
+                hex.push("..".to_string());
+            }
+            let _ = writeln!(
+                output,
+                "{:04x}..{:04x}  {:<50}  {} = {}",
+                entry.range.start,
+                entry.range.end,
+                hex.join(" "),
+                entry.path,
+                entry.value
+            );
+        }
+        if self.end < data.len() {
+            let _ = writeln!(
+                output,
+                "{:04x}..{:04x}  {} undecoded bytes",
+                self.end,
+                data.len(),
+                data.len() - self.end
+            );
+        }
+        output
+    }
+
+    /// First field whose path or value differs between `self`, the
+    /// expected trace, and `actual`
+    ///
+    /// Offsets are not compared: a field that changed size shifts
+    /// everything after it, and only the field itself is of interest.
+    pub fn first_divergence(&self, actual: &DecodeTrace) -> Option<Divergence> {
+        let length = self.entries.len().max(actual.entries.len());
+        (0..length).find_map(|index| {
+            let (expected, actual) = (self.entries.get(index), actual.entries.get(index));
+            match (expected, actual) {
+                (Some(expected), Some(actual))
+                    if expected.path == actual.path && expected.value == actual.value =>
+                {
+                    None
+                }
+                _ => Some(Divergence {
+                    path: expected.or(actual).map(|entry| entry.path.clone())?,
+                    expected: expected.map(|entry| entry.value.clone()),
+                    actual: actual.map(|entry| entry.value.clone()),
+                }),
+            }
+        })
+    }
+}
+
+/// A buffer being decoded, optionally recording each field into a
+/// [`DecodeTrace`]
+///
+/// Versioned decoders take a cursor so tooling can step through a message
+/// field by field. Without a trace attached, [`field`](Self::field) is the
+/// wrapped decode plus a branch, so the broker's own decoding pays nothing
+/// for it.
+///
+/// Errors are located whether or not a trace is attached: a failing field
+/// wraps its error in [`ProtocolError::At`] with the field's offset, and
+/// each enclosing array element prefixes the path, giving paths such as
+/// `topics[2].name`.
+pub struct DecodeCursor<'a> {
+    buffer: &'a mut Bytes,
+    /// Offset of the end of `buffer`, so `end - buffer.len()` is the position
+    end: usize,
+    trace: Option<&'a mut DecodeTrace>,
+    path: Vec<String>,
+}
+
+impl<'a> DecodeCursor<'a> {
+    pub fn new(buffer: &'a mut Bytes) -> Self {
+        let end = buffer.len();
+        Self {
+            buffer,
+            end,
+            trace: None,
+            path: Vec::new(),
+        }
+    }
+
+    /// A cursor that records into `trace`, continuing its offsets
+    pub fn traced(buffer: &'a mut Bytes, trace: &'a mut DecodeTrace) -> Self {
+        let end = trace.end + buffer.len();
+        Self {
+            buffer,
+            end,
+            trace: Some(trace),
+            path: Vec::new(),
+        }
+    }
+
+    /// Offset of the next byte to decode
+    pub fn position(&self) -> usize {
+        self.end - self.buffer.len()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buffer.remaining()
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    /// Decodes one field with `decode`, recording it under `name`
+    pub fn field<T, F>(&mut self, name: &str, decode: F) -> ProtocolResult<T>
+    where
+        T: Debug,
+        F: FnOnce(&mut Bytes) -> ProtocolResult<T>,
+    {
+        let start = self.position();
+        let value = decode(self.buffer).map_err(|error| error.at(start, name))?;
+        if self.trace.is_some() {
+            let rendered = format!("{:?}", value);
+            self.record(name, start, rendered);
+        }
+        Ok(value)
+    }
+
+    /// Decodes a nullable ARRAY, or a COMPACT_ARRAY when `compact`, calling
+    /// `decode_item` for each element under `name[i]`
+    pub fn nullable_array<T, F>(
+        &mut self,
+        name: &str,
+        compact: bool,
+        mut decode_item: F,
+    ) -> ProtocolResult<Option<Vec<T>>>
+    where
+        F: FnMut(&mut Self) -> ProtocolResult<T>,
+    {
+        let length_name = format!("{}.length", name);
+        let start = self.position();
+        let count = if compact {
+            match self.field(&length_name, WireFormat::decode_unsigned_varint)? {
+                0 => None,
+                length => Some(length as usize - 1),
+            }
+        } else {
+            match self.field(&length_name, WireFormat::decode_i32)? {
+                -1 => None,
+                length if length < 0 => {
+                    return Err(ProtocolError::invalid_length(length).at(start, &length_name))
+                }
+                length => Some(length as usize),
+            }
+        };
+        let Some(count) = count else {
+            return Ok(None);
+        };
+
+        WireFormat::check_element_count(self.buffer, count)
+            .map_err(|error| error.at(start, &length_name))?;
+        let mut items = Vec::with_capacity(count);
+        for index in 0..count {
+            if self.trace.is_some() {
+                let element = match self.path.last() {
+                    Some(prefix) => format!("{}.{}[{}]", prefix, name, index),
+                    None => format!("{}[{}]", name, index),
+                };
+                self.path.push(element);
+            }
+            let item = decode_item(self);
+            if self.trace.is_some() {
+                self.path.pop();
+            }
+            items.push(item.map_err(|error| error.within(&format!("{}[{}]", name, index)))?);
+        }
+        Ok(Some(items))
+    }
+
+    /// Non-nullable counterpart of [`nullable_array`](Self::nullable_array)
+    pub fn array<T, F>(
+        &mut self,
+        name: &str,
+        compact: bool,
+        decode_item: F,
+    ) -> ProtocolResult<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> ProtocolResult<T>,
+    {
+        self.nullable_array(name, compact, decode_item)?
+            .ok_or_else(|| ProtocolError::invalid_length(-1))
+    }
+
+    /// Decodes a tag section, recorded as `_tagged_fields`
+    pub fn tagged_fields(&mut self) -> ProtocolResult<TaggedFields> {
+        self.field("_tagged_fields", TaggedFields::decode)
+    }
+
+    /// Skips a tag section, recorded as `_tagged_fields` with its tag count
+    pub fn skip_tagged_fields(&mut self) -> ProtocolResult<()> {
+        self.tagged_fields().map(drop)
+    }
+
+    fn record(&mut self, name: &str, start: usize, value: String) {
+        let end = self.position();
+        let path = match self.path.last() {
+            Some(prefix) => format!("{}.{}", prefix, name),
+            None => name.to_string(),
+        };
+        if let Some(trace) = self.trace.as_deref_mut() {
+            trace.entries.push(TraceEntry {
+                path,
+                range: start..end,
+                value,
+            });
+            trace.end = end;
+        }
+    }
+}
+
+impl Drop for DecodeCursor<'_> {
+    /// Keeps the trace's end in step with the bytes consumed, including
+    /// any not recorded as a field
+    fn drop(&mut self) {
+        let position = self.position();
+        if let Some(trace) = self.trace.as_deref_mut() {
+            trace.end = trace.end.max(position);
+        }
+    }
+}
+
+/// Traces a request frame, without its length prefix, into `trace`
+///
+/// The body is traced for APIs with a cursor-based request decoder and
+/// otherwise left undecoded. On error `trace` holds the fields decoded
+/// before it.
+pub fn trace_request(frame: &[u8], trace: &mut DecodeTrace) -> ProtocolResult<()> {
+    let mut buffer = Bytes::copy_from_slice(frame);
+    let mut cursor = DecodeCursor::traced(&mut buffer, trace);
+    // Peek at the key and version to pick the header version
+    let (api_key, version) = match frame {
+        [a, b, c, d, ..] => (i16::from_be_bytes([*a, *b]), i16::from_be_bytes([*c, *d])),
+        _ => (-1, -1),
+    };
+    let flexible = flexible::is_flexible(api_key, version).unwrap_or(true);
+    RequestHeaderV2::decode_from(&mut cursor, flexible)?;
+    if api_key == ApiKey::Metadata.code() {
+        MetadataRequest::decode_from(&mut cursor, version, flexible)?;
+    }
+    Ok(())
+}
+
+/// Traces a response frame to `api_key` at `version` into `trace`
+///
+/// Like [`trace_request`], the body is only traced where a cursor-based
+/// decoder exists.
+pub fn trace_response(
+    api_key: i16,
+    version: i16,
+    frame: &[u8],
+    trace: &mut DecodeTrace,
+) -> ProtocolResult<()> {
+    let mut buffer = Bytes::copy_from_slice(frame);
+    let mut cursor = DecodeCursor::traced(&mut buffer, trace);
+    ResponseHeaderV0::decode_from(&mut cursor)?;
+    // Flexible responses other than ApiVersions carry a header tag section
+    if api_key != ApiKey::ApiVersions.code()
+        && flexible::is_flexible(api_key, version) == Some(true)
+    {
+        cursor.skip_tagged_fields()?;
+    }
+    if api_key == ApiKey::ApiVersions.code() {
+        ApiVersionsResponse::decode_from(&mut cursor, version)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::{BufMut, BytesMut};
+
+    #[test]
+    fn test_untraced_cursor_records_nothing() {
+        let mut buffer = Bytes::from_static(&[0, 7, 0, 0, 0, 9]);
+        let mut cursor = DecodeCursor::new(&mut buffer);
+        assert_eq!(cursor.field("a", WireFormat::decode_i16).unwrap(), 7);
+        assert_eq!(cursor.position(), 2);
+        assert!(!cursor.is_tracing());
+        assert_eq!(cursor.field("b", WireFormat::decode_i32).unwrap(), 9);
+        assert_eq!(cursor.remaining(), 0);
+    }
+
+    #[test]
+    fn test_nested_paths_and_continued_offsets() {
+        let mut trace = DecodeTrace::new();
+        let mut first = Bytes::from_static(&[0, 0, 0, 1]);
+        DecodeCursor::traced(&mut first, &mut trace)
+            .field("id", WireFormat::decode_i32)
+            .unwrap();
+
+        let mut second = BytesMut::new();
+        second.put_u8(3); // compact length 2
+        second.put_i16(10);
+        second.put_i16(20);
+        let mut second = second.freeze();
+        let mut cursor = DecodeCursor::traced(&mut second, &mut trace);
+        let items = cursor
+            .array("items", true, |cursor| {
+                cursor.field("value", WireFormat::decode_i16)
+            })
+            .unwrap();
+        drop(cursor);
+        assert_eq!(items, vec![10, 20]);
+
+        let summary: Vec<(&str, Range<usize>)> = trace
+            .entries()
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.range.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("id", 0..4),
+                ("items.length", 4..5),
+                ("items[0].value", 5..7),
+                ("items[1].value", 7..9),
+            ]
+        );
+        assert_eq!(trace.end(), 9);
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let trace = |values: &[i16]| {
+            let mut buffer = BytesMut::new();
+            values.iter().for_each(|value| buffer.put_i16(*value));
+            let mut trace = DecodeTrace::new();
+            let mut buffer = buffer.freeze();
+            let mut cursor = DecodeCursor::traced(&mut buffer, &mut trace);
+            for _ in values {
+                cursor.field("v", WireFormat::decode_i16).unwrap();
+            }
+            drop(cursor);
+            trace
+        };
+        assert_eq!(trace(&[1, 2]).first_divergence(&trace(&[1, 2])), None);
+        assert_eq!(
+            trace(&[1, 2]).first_divergence(&trace(&[1, 3])),
+            Some(Divergence {
+                path: "v".to_string(),
+                expected: Some("2".to_string()),
+                actual: Some("3".to_string()),
+            })
+        );
+        assert_eq!(
+            trace(&[1]).first_divergence(&trace(&[1, 3])),
+            Some(Divergence {
+                path: "v".to_string(),
+                expected: None,
+                actual: Some("3".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_render_marks_undecoded_tail() {
+        let data = [0u8, 5, 0xaa];
+        let mut buffer = Bytes::copy_from_slice(&data[..2]);
+        let mut trace = DecodeTrace::new();
+        DecodeCursor::traced(&mut buffer, &mut trace)
+            .field("count", WireFormat::decode_i16)
+            .unwrap();
+        let rendered = trace.render(&data);
+        assert!(rendered.starts_with("0000..0002  00 05"), "{}", rendered);
+        assert!(rendered.contains("count = 5"));
+        assert!(rendered.contains("1 undecoded bytes"));
+    }
+
+    #[test]
+    fn test_trace_metadata_request_frame() {
+        let frame = hex::decode("000300010000000700016300000000").unwrap();
+        let mut trace = DecodeTrace::new();
+        trace_request(&frame, &mut trace).unwrap();
+        let paths: Vec<&str> = trace
+            .entries()
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "request_api_key",
+                "request_api_version",
+                "correlation_id",
+                "client_id",
+                "topics.length",
+            ]
+        );
+        assert_eq!(trace.entries()[4].range, 11..15);
+        assert_eq!(trace.end(), frame.len());
+    }
+}
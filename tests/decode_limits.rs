@@ -0,0 +1,63 @@
+//! Decode limits reject oversized length claims before allocating
+//!
+//! Runs in its own test binary so the counting allocator sees only this
+//! test's allocations.
+
+use bytes::Bytes;
+use codecrafters_kafka::protocol::{ProtocolError, WireFormat};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Passes through to the system allocator, remembering the largest request
+struct LargestAllocation;
+
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for LargestAllocation {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST.fetch_max(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST.fetch_max(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: LargestAllocation = LargestAllocation;
+
+#[test]
+fn two_gigabyte_string_claim_fails_without_allocating() {
+    // COMPACT_STRING length 2^31 + 1, with 16 bytes of payload behind it
+    let mut frame = vec![0x81, 0x80, 0x80, 0x80, 0x08];
+    frame.extend_from_slice(&[b'a'; 16]);
+    let mut buffer = Bytes::from(frame);
+
+    LARGEST.store(0, Ordering::Relaxed);
+    let result = WireFormat::decode_compact_string(&mut buffer);
+    let largest = LARGEST.load(Ordering::Relaxed);
+
+    match result {
+        Err(ProtocolError::LimitExceeded {
+            field,
+            limit,
+            requested,
+        }) => {
+            assert_eq!(field, "string length");
+            assert_eq!(limit, i16::MAX as usize);
+            assert_eq!(requested, 1 << 31);
+        }
+        other => panic!("expected LimitExceeded, got {:?}", other),
+    }
+    assert!(
+        largest < 4096,
+        "decoding allocated {} bytes in one allocation",
+        largest
+    );
+}
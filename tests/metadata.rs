@@ -0,0 +1,159 @@
+//! Metadata over a real TCP connection
+//!
+//! Requests are written out field by field; responses are parsed with the
+//! crate's decoder, which checks they are well formed for the version.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use codecrafters_kafka::kafka::broker::KafkaBroker;
+use codecrafters_kafka::protocol::metadata::MetadataResponse;
+use codecrafters_kafka::protocol::ErrorCode;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const METADATA: i16 = 3;
+
+/// Starts `broker` on an ephemeral port it advertises and returns its address
+async fn start(broker: KafkaBroker) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    broker.set_listen_addr(addr);
+    let broker = Arc::new(broker);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, peer_addr) = listener.accept().await.unwrap();
+            let broker = Arc::clone(&broker);
+            tokio::spawn(async move { broker.handle_connection(&mut stream, peer_addr).await });
+        }
+    });
+    addr
+}
+
+/// Frames a request with a v1 header (client id, no tag section)
+fn request_v1_header(api_key: i16, api_version: i16, correlation_id: i32, body: &[u8]) -> Vec<u8> {
+    let client_id = b"metadata-test";
+    let mut message = BytesMut::new();
+    message.put_i16(api_key);
+    message.put_i16(api_version);
+    message.put_i32(correlation_id);
+    message.put_i16(client_id.len() as i16);
+    message.put_slice(client_id);
+    message.put_slice(body);
+
+    let mut frame = (message.len() as i32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&message);
+    frame
+}
+
+/// Sends one request and returns the response after its length prefix
+async fn exchange(stream: &mut TcpStream, request: &[u8]) -> Bytes {
+    stream.write_all(request).await.unwrap();
+    let length = stream.read_i32().await.unwrap();
+    let mut response = vec![0; length as usize];
+    stream.read_exact(&mut response).await.unwrap();
+    Bytes::from(response)
+}
+
+#[tokio::test]
+async fn test_metadata_v1_lists_this_broker() {
+    let addr = start(KafkaBroker::new().with_node_id(7)).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    // A null topic list asks for every topic
+    let body = (-1i32).to_be_bytes();
+    let mut response = exchange(&mut stream, &request_v1_header(METADATA, 1, 11, &body)).await;
+    assert_eq!(response.get_i32(), 11);
+    let metadata = MetadataResponse::decode(&mut response, 1).unwrap();
+    assert!(response.is_empty());
+
+    assert_eq!(metadata.brokers.len(), 1);
+    let broker = &metadata.brokers[0];
+    assert_eq!(broker.node_id, 7);
+    assert_eq!(broker.host, "127.0.0.1");
+    assert_eq!(broker.port, i32::from(addr.port()));
+    assert_eq!(metadata.controller_id, 7);
+    assert!(metadata.topics.is_empty());
+}
+
+#[tokio::test]
+async fn test_metadata_v1_reports_unknown_topics() {
+    let addr = start(KafkaBroker::new()).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let mut body = BytesMut::new();
+    body.put_i32(2);
+    for name in ["orders", "payments"] {
+        body.put_i16(name.len() as i16);
+        body.put_slice(name.as_bytes());
+    }
+    let mut response = exchange(&mut stream, &request_v1_header(METADATA, 1, 12, &body)).await;
+    assert_eq!(response.get_i32(), 12);
+    let metadata = MetadataResponse::decode(&mut response, 1).unwrap();
+    assert!(response.is_empty());
+
+    let topics: Vec<_> = metadata
+        .topics
+        .iter()
+        .map(|topic| (topic.name.as_deref(), topic.error_code))
+        .collect();
+    assert_eq!(
+        topics,
+        [
+            (Some("orders"), ErrorCode::UNKNOWN_TOPIC_OR_PARTITION),
+            (Some("payments"), ErrorCode::UNKNOWN_TOPIC_OR_PARTITION),
+        ]
+    );
+    assert!(metadata
+        .topics
+        .iter()
+        .all(|topic| topic.partitions.is_empty()));
+}
+
+#[tokio::test]
+async fn test_metadata_v1_lists_stored_topics() {
+    let broker = KafkaBroker::new().with_node_id(7);
+    broker
+        .topics()
+        .create_topic("orders", 3, Default::default())
+        .unwrap();
+    broker
+        .topics()
+        .create_topic("__consumer_offsets", 1, Default::default())
+        .unwrap();
+    let addr = start(broker).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let body = (-1i32).to_be_bytes();
+    let mut response = exchange(&mut stream, &request_v1_header(METADATA, 1, 13, &body)).await;
+    assert_eq!(response.get_i32(), 13);
+    let metadata = MetadataResponse::decode(&mut response, 1).unwrap();
+    assert!(response.is_empty());
+
+    let topics: Vec<_> = metadata
+        .topics
+        .iter()
+        .map(|topic| {
+            (
+                topic.name.as_deref(),
+                topic.error_code,
+                topic.is_internal,
+                topic.partitions.len(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        topics,
+        [
+            (Some("__consumer_offsets"), ErrorCode::NONE, true, 1),
+            (Some("orders"), ErrorCode::NONE, false, 3),
+        ]
+    );
+    let partitions = &metadata.topics[1].partitions;
+    for (index, partition) in partitions.iter().enumerate() {
+        assert_eq!(partition.partition_index, index as i32);
+        assert_eq!(partition.leader_id, 7);
+        assert_eq!(partition.replica_nodes, [7]);
+        assert_eq!(partition.isr_nodes, [7]);
+    }
+}
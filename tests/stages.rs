@@ -0,0 +1,638 @@
+//! Byte-level checks of the CodeCrafters "Build your own Kafka" stages
+//!
+//! Each test replays what the stage tester sends over a real TCP socket to
+//! an in-process broker and checks the bytes it inspects in the reply.
+//! Requests are written out field by field rather than with the crate's own
+//! encoders, so an encoder bug cannot cancel out a matching decoder bug.
+//!
+//! The DescribeTopicPartitions and Fetch stages run against a log directory
+//! laid out like the one the tester provides: a `meta.properties`, a
+//! `__cluster_metadata-0` log holding topic and partition records and one
+//! log per partition. Stages the broker cannot pass yet are ignored, with
+//! the missing piece as the reason.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use codecrafters_kafka::kafka::broker::KafkaBroker;
+use codecrafters_kafka::kafka::storage::log_dir::load_log_dir;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+const PRODUCE: i16 = 0;
+const FETCH: i16 = 1;
+const API_VERSIONS: i16 = 18;
+const DESCRIBE_TOPIC_PARTITIONS: i16 = 75;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const UNSUPPORTED_VERSION: i16 = 35;
+const UNKNOWN_TOPIC_ID: i16 = 100;
+
+/// Starts `broker` on an ephemeral port and returns its address
+async fn start(broker: KafkaBroker) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let broker = Arc::new(broker);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, peer_addr) = listener.accept().await.unwrap();
+            let broker = Arc::clone(&broker);
+            tokio::spawn(async move { broker.handle_connection(&mut stream, peer_addr).await });
+        }
+    });
+    addr
+}
+
+/// Frames a request with a v2 header (client id and an empty tag section)
+fn request(api_key: i16, api_version: i16, correlation_id: i32, body: &[u8]) -> Vec<u8> {
+    let client_id = b"kafka-tester";
+    let mut message = BytesMut::new();
+    message.put_i16(api_key);
+    message.put_i16(api_version);
+    message.put_i32(correlation_id);
+    message.put_i16(client_id.len() as i16);
+    message.put_slice(client_id);
+    message.put_u8(0);
+    message.put_slice(body);
+
+    let mut frame = (message.len() as i32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&message);
+    frame
+}
+
+/// ApiVersions v4 body as the tester sends it
+fn api_versions_body() -> Vec<u8> {
+    let mut body = vec![10];
+    body.extend_from_slice(b"kafka-cli");
+    body.push(4);
+    body.extend_from_slice(b"0.1");
+    body.push(0);
+    body
+}
+
+/// Sends one request and returns the response after its length prefix
+async fn exchange(stream: &mut TcpStream, request: &[u8]) -> Bytes {
+    stream.write_all(request).await.unwrap();
+    receive(stream).await
+}
+
+async fn receive(stream: &mut TcpStream) -> Bytes {
+    let length = stream.read_i32().await.unwrap();
+    let mut response = vec![0; length as usize];
+    stream.read_exact(&mut response).await.unwrap();
+    Bytes::from(response)
+}
+
+fn get_unsigned_varint(buffer: &mut Bytes) -> u32 {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = buffer.get_u8();
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
+
+/// Reads a compact array length, with null read as empty
+fn get_compact_len(buffer: &mut Bytes) -> usize {
+    get_unsigned_varint(buffer).saturating_sub(1) as usize
+}
+
+fn get_compact_string(buffer: &mut Bytes) -> String {
+    let length = get_compact_len(buffer);
+    String::from_utf8(buffer.split_to(length).to_vec()).unwrap()
+}
+
+fn get_uuid(buffer: &mut Bytes) -> Uuid {
+    Uuid::from_slice(&buffer.split_to(16)).unwrap()
+}
+
+fn assert_empty_tagged_fields(buffer: &mut Bytes) {
+    assert_eq!(get_unsigned_varint(buffer), 0, "unexpected tagged fields");
+}
+
+/// Parsed ApiVersions v3/v4 response body: error code and key ranges
+fn parse_api_versions(mut body: Bytes) -> (i16, Vec<(i16, i16, i16)>) {
+    let error_code = body.get_i16();
+    let ranges = (0..get_compact_len(&mut body))
+        .map(|_| {
+            let range = (body.get_i16(), body.get_i16(), body.get_i16());
+            assert_empty_tagged_fields(&mut body);
+            range
+        })
+        .collect();
+    let _throttle_time_ms = body.get_i32();
+    assert_empty_tagged_fields(&mut body);
+    assert!(body.is_empty(), "{} trailing bytes", body.len());
+    (error_code, ranges)
+}
+
+async fn api_versions_ranges(addr: SocketAddr) -> Vec<(i16, i16, i16)> {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = request(API_VERSIONS, 4, 1, &api_versions_body());
+    let mut response = exchange(&mut stream, &request).await;
+    assert_eq!(response.get_i32(), 1);
+    let (error_code, ranges) = parse_api_versions(response);
+    assert_eq!(error_code, NONE);
+    ranges
+}
+
+#[tokio::test]
+async fn stage_bind_accepts_connections() {
+    let addr = start(KafkaBroker::new()).await;
+    TcpStream::connect(addr).await.unwrap();
+}
+
+#[tokio::test]
+async fn stage_correlation_id_is_echoed() {
+    let addr = start(KafkaBroker::new()).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    for correlation_id in [7, 1_870_644_833, -1] {
+        let request = request(API_VERSIONS, 4, correlation_id, &api_versions_body());
+        let mut response = exchange(&mut stream, &request).await;
+        // Response header v0: the correlation id and nothing else
+        assert_eq!(response.get_i32(), correlation_id);
+    }
+}
+
+#[tokio::test]
+async fn stage_unsupported_api_versions_version() {
+    let addr = start(KafkaBroker::new()).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    for version in [5, 1234, -1] {
+        let request = request(API_VERSIONS, version, 42, &api_versions_body());
+        let response = exchange(&mut stream, &request).await;
+        assert_eq!(&response[..4], &42i32.to_be_bytes());
+        assert_eq!(&response[4..6], &UNSUPPORTED_VERSION.to_be_bytes());
+    }
+}
+
+#[tokio::test]
+async fn stage_api_versions_v4() {
+    let addr = start(KafkaBroker::new()).await;
+    let ranges = api_versions_ranges(addr).await;
+    let api_versions = ranges
+        .iter()
+        .find(|(api_key, _, _)| *api_key == API_VERSIONS)
+        .expect("ApiVersions is not advertised");
+    assert!(api_versions.1 <= 4 && api_versions.2 >= 4);
+    assert!(ranges.iter().any(|(api_key, _, _)| *api_key == PRODUCE));
+}
+
+#[tokio::test]
+async fn stage_serial_requests_on_one_connection() {
+    let addr = start(KafkaBroker::new()).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    for correlation_id in 0..5 {
+        let request = request(API_VERSIONS, 4, correlation_id, &api_versions_body());
+        let mut response = exchange(&mut stream, &request).await;
+        assert_eq!(response.get_i32(), correlation_id);
+        assert_eq!(parse_api_versions(response).0, NONE);
+    }
+}
+
+#[tokio::test]
+async fn stage_concurrent_clients() {
+    let addr = start(KafkaBroker::new()).await;
+    let mut clients = Vec::new();
+    for _ in 0..3 {
+        clients.push(TcpStream::connect(addr).await.unwrap());
+    }
+
+    // Interleave requests across the connections, as the tester does
+    for round in 0..3 {
+        for (client, stream) in clients.iter_mut().enumerate() {
+            let correlation_id = (client * 100 + round) as i32;
+            let request = request(API_VERSIONS, 4, correlation_id, &api_versions_body());
+            stream.write_all(&request).await.unwrap();
+        }
+        // Read the replies back in reverse order of sending
+        for (client, stream) in clients.iter_mut().enumerate().rev() {
+            let correlation_id = (client * 100 + round) as i32;
+            let mut response = receive(stream).await;
+            assert_eq!(response.get_i32(), correlation_id);
+            assert_eq!(parse_api_versions(response).0, NONE);
+        }
+    }
+}
+
+/// A topic of the fixture log directory
+struct FixtureTopic {
+    name: &'static str,
+    id: Uuid,
+    partitions: i32,
+    /// Record values stored in partition 0
+    messages: &'static [&'static [u8]],
+}
+
+fn fixture_topics() -> Vec<FixtureTopic> {
+    vec![
+        FixtureTopic {
+            name: "bar",
+            id: Uuid::from_u128(0x71a5_9a51_1111_4000_8000_0000_0000_0091),
+            partitions: 1,
+            messages: &[],
+        },
+        FixtureTopic {
+            name: "foo",
+            id: Uuid::from_u128(0x71a5_9a51_2222_4000_8000_0000_0000_0092),
+            partitions: 2,
+            messages: &[b"Hello World!", b"Hello Earth!"],
+        },
+    ]
+}
+
+fn put_compact_bytes(buffer: &mut BytesMut, bytes: &[u8]) {
+    put_unsigned_varint(buffer, bytes.len() as u32 + 1);
+    buffer.put_slice(bytes);
+}
+
+fn put_unsigned_varint(buffer: &mut BytesMut, mut value: u32) {
+    while value >= 0x80 {
+        buffer.put_u8((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.put_u8(value as u8);
+}
+
+fn put_varint(buffer: &mut BytesMut, value: i32) {
+    put_unsigned_varint(buffer, ((value << 1) ^ (value >> 31)) as u32);
+}
+
+/// Encodes `values` as one uncompressed v2 record batch
+fn record_batch(base_offset: i64, values: &[Vec<u8>]) -> Vec<u8> {
+    let timestamp = 1_726_045_943_832i64;
+    let mut records = BytesMut::new();
+    for (offset_delta, value) in values.iter().enumerate() {
+        let mut record = BytesMut::new();
+        record.put_i8(0); // attributes
+        put_varint(&mut record, 0); // timestamp delta
+        put_varint(&mut record, offset_delta as i32);
+        put_varint(&mut record, -1); // null key
+        put_varint(&mut record, value.len() as i32);
+        record.put_slice(value);
+        put_varint(&mut record, 0); // no headers
+        put_varint(&mut records, record.len() as i32);
+        records.put_slice(&record);
+    }
+
+    let mut checksummed = BytesMut::new();
+    checksummed.put_i16(0); // attributes
+    checksummed.put_i32(values.len() as i32 - 1);
+    checksummed.put_i64(timestamp);
+    checksummed.put_i64(timestamp);
+    checksummed.put_i64(-1); // producer id
+    checksummed.put_i16(-1); // producer epoch
+    checksummed.put_i32(-1); // base sequence
+    checksummed.put_i32(values.len() as i32);
+    checksummed.put_slice(&records);
+
+    let mut batch = BytesMut::new();
+    batch.put_i64(base_offset);
+    batch.put_i32(4 + 1 + 4 + checksummed.len() as i32);
+    batch.put_i32(0); // partition leader epoch
+    batch.put_i8(2); // magic
+    batch.put_u32(crc32c::crc32c(&checksummed));
+    batch.put_slice(&checksummed);
+    batch.to_vec()
+}
+
+/// TopicRecord (type 2, version 0) value
+fn topic_record(topic: &FixtureTopic) -> Vec<u8> {
+    let mut value = BytesMut::new();
+    value.put_slice(&[1, 2, 0]); // frame version, type, version
+    put_compact_bytes(&mut value, topic.name.as_bytes());
+    value.put_slice(topic.id.as_bytes());
+    value.put_u8(0);
+    value.to_vec()
+}
+
+/// PartitionRecord (type 3, version 1) value with this broker as the only
+/// replica
+fn partition_record(topic: &FixtureTopic, partition: i32) -> Vec<u8> {
+    let mut value = BytesMut::new();
+    value.put_slice(&[1, 3, 1]); // frame version, type, version
+    value.put_i32(partition);
+    value.put_slice(topic.id.as_bytes());
+    for _ in 0..2 {
+        // replicas, then in-sync replicas
+        put_unsigned_varint(&mut value, 2);
+        value.put_i32(1);
+    }
+    put_unsigned_varint(&mut value, 1); // removing replicas
+    put_unsigned_varint(&mut value, 1); // adding replicas
+    value.put_i32(1); // leader
+    value.put_i32(0); // leader epoch
+    value.put_i32(0); // partition epoch
+    put_unsigned_varint(&mut value, 2); // directories
+    value.put_slice(Uuid::from_u128(0x10).as_bytes());
+    value.put_u8(0);
+    value.to_vec()
+}
+
+/// FeatureLevelRecord (type 12, version 0) for metadata.version
+fn feature_level_record() -> Vec<u8> {
+    let mut value = BytesMut::new();
+    value.put_slice(&[1, 12, 0]); // frame version, type, version
+    put_compact_bytes(&mut value, b"metadata.version");
+    value.put_i16(20);
+    value.put_u8(0);
+    value.to_vec()
+}
+
+/// Writes the log directory layout the later stages are run against
+fn write_fixture_log_dir(dir: &Path) {
+    std::fs::write(
+        dir.join("meta.properties"),
+        "version=1\ncluster.id=MkU3OEVBNTcwNTJENDM2Qg\nnode.id=1\ndirectory.id=AAAAAAAAAAAAAAAAAAAAEA\n",
+    )
+    .unwrap();
+
+    let mut metadata = record_batch(0, &[feature_level_record()]);
+    let mut offset = 1;
+    for topic in fixture_topics() {
+        let mut values = vec![topic_record(&topic)];
+        values.extend((0..topic.partitions).map(|partition| partition_record(&topic, partition)));
+        let count = values.len() as i64;
+        metadata.extend(record_batch(offset, &values));
+        offset += count;
+    }
+    let metadata_dir = dir.join("__cluster_metadata-0");
+    std::fs::create_dir_all(&metadata_dir).unwrap();
+    std::fs::write(metadata_dir.join("00000000000000000000.log"), metadata).unwrap();
+
+    for topic in fixture_topics() {
+        for partition in 0..topic.partitions {
+            let partition_dir = dir.join(format!("{}-{}", topic.name, partition));
+            std::fs::create_dir_all(&partition_dir).unwrap();
+            std::fs::write(
+                partition_dir.join("00000000000000000000.log"),
+                fixture_log(&topic, partition),
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Contents of a fixture partition log: one batch per message in partition 0
+fn fixture_log(topic: &FixtureTopic, partition: i32) -> Vec<u8> {
+    if partition != 0 {
+        return Vec::new();
+    }
+    topic
+        .messages
+        .iter()
+        .enumerate()
+        .flat_map(|(offset, message)| record_batch(offset as i64, &[message.to_vec()]))
+        .collect()
+}
+
+/// Starts a broker loaded from a fresh fixture log directory
+async fn start_with_fixture() -> (SocketAddr, tempfile::TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    write_fixture_log_dir(dir.path());
+    let bootstrap = load_log_dir(dir.path()).unwrap();
+    let broker = KafkaBroker::new()
+        .with_log_dir(dir.path())
+        .with_cluster_id(bootstrap.cluster_id())
+        .with_cluster_metadata(&bootstrap.cluster);
+    (start(broker).await, dir)
+}
+
+#[test]
+fn fixture_metadata_log_loads() {
+    let dir = tempfile::tempdir().unwrap();
+    write_fixture_log_dir(dir.path());
+    let bootstrap = load_log_dir(dir.path()).unwrap();
+    assert_eq!(bootstrap.cluster_id(), "MkU3OEVBNTcwNTJENDM2Qg");
+    assert_eq!(bootstrap.metadata_version(), Some(20));
+}
+
+/// DescribeTopicPartitions v0 body asking for `topics`
+fn describe_topic_partitions_body(topics: &[&str]) -> Vec<u8> {
+    let mut body = BytesMut::new();
+    put_unsigned_varint(&mut body, topics.len() as u32 + 1);
+    for topic in topics {
+        put_compact_bytes(&mut body, topic.as_bytes());
+        body.put_u8(0);
+    }
+    body.put_i32(100); // response partition limit
+    body.put_u8(0xff); // null cursor
+    body.put_u8(0);
+    body.to_vec()
+}
+
+/// A topic of a DescribeTopicPartitions response: error code, name, id and
+/// (partition index, error code, leader) of each partition
+type DescribedTopic = (i16, String, Uuid, Vec<(i32, i16, i32)>);
+
+fn parse_describe_topic_partitions(mut body: Bytes) -> Vec<DescribedTopic> {
+    let _throttle_time_ms = body.get_i32();
+    let topics = (0..get_compact_len(&mut body))
+        .map(|_| {
+            let error_code = body.get_i16();
+            let name = get_compact_string(&mut body);
+            let id = get_uuid(&mut body);
+            let _is_internal = body.get_u8();
+            let partitions = (0..get_compact_len(&mut body))
+                .map(|_| {
+                    let error_code = body.get_i16();
+                    let index = body.get_i32();
+                    let leader = body.get_i32();
+                    let _leader_epoch = body.get_i32();
+                    for _ in 0..4 {
+                        // replicas, isr, eligible leaders, last known elr
+                        let count = get_compact_len(&mut body);
+                        body.advance(4 * count);
+                    }
+                    let offline = get_compact_len(&mut body);
+                    body.advance(4 * offline);
+                    assert_empty_tagged_fields(&mut body);
+                    (index, error_code, leader)
+                })
+                .collect();
+            let _authorized_operations = body.get_i32();
+            assert_empty_tagged_fields(&mut body);
+            (error_code, name, id, partitions)
+        })
+        .collect();
+    assert_eq!(body.get_u8(), 0xff, "expected a null next cursor");
+    assert_empty_tagged_fields(&mut body);
+    assert!(body.is_empty(), "{} trailing bytes", body.len());
+    topics
+}
+
+async fn describe_topic_partitions(addr: SocketAddr, topics: &[&str]) -> Vec<DescribedTopic> {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let body = describe_topic_partitions_body(topics);
+    let request = request(DESCRIBE_TOPIC_PARTITIONS, 0, 9, &body);
+    let mut response = exchange(&mut stream, &request).await;
+    // Response header v1: correlation id and an empty tag section
+    assert_eq!(response.get_i32(), 9);
+    assert_empty_tagged_fields(&mut response);
+    parse_describe_topic_partitions(response)
+}
+
+#[tokio::test]
+async fn stage_describe_topic_partitions_is_advertised() {
+    let addr = start(KafkaBroker::new()).await;
+    let ranges = api_versions_ranges(addr).await;
+    assert!(ranges.contains(&(DESCRIBE_TOPIC_PARTITIONS, 0, 0)));
+}
+
+#[tokio::test]
+async fn stage_describe_unknown_topic() {
+    let (addr, _dir) = start_with_fixture().await;
+    let topics = describe_topic_partitions(addr, &["unknown-topic"]).await;
+    assert_eq!(
+        topics,
+        vec![(
+            UNKNOWN_TOPIC_OR_PARTITION,
+            "unknown-topic".to_string(),
+            Uuid::nil(),
+            vec![]
+        )]
+    );
+}
+
+#[tokio::test]
+async fn stage_describe_known_topics() {
+    let (addr, _dir) = start_with_fixture().await;
+    // Asked out of order; topics come back sorted by name
+    let topics = describe_topic_partitions(addr, &["foo", "bar"]).await;
+    let fixture = fixture_topics();
+    assert_eq!(
+        topics,
+        vec![
+            (NONE, "bar".to_string(), fixture[0].id, vec![(0, NONE, 1)]),
+            (
+                NONE,
+                "foo".to_string(),
+                fixture[1].id,
+                vec![(0, NONE, 1), (1, NONE, 1)]
+            ),
+        ]
+    );
+}
+
+/// Fetch v16 body reading partition 0 of each topic id from offset 0
+fn fetch_body(topic_ids: &[Uuid]) -> Vec<u8> {
+    let mut body = BytesMut::new();
+    body.put_i32(500); // max wait
+    body.put_i32(1); // min bytes
+    body.put_i32(i32::MAX); // max bytes
+    body.put_i8(0); // isolation level
+    body.put_i32(0); // session id
+    body.put_i32(0); // session epoch
+    put_unsigned_varint(&mut body, topic_ids.len() as u32 + 1);
+    for topic_id in topic_ids {
+        body.put_slice(topic_id.as_bytes());
+        put_unsigned_varint(&mut body, 2);
+        body.put_i32(0); // partition
+        body.put_i32(-1); // current leader epoch
+        body.put_i64(0); // fetch offset
+        body.put_i32(-1); // last fetched epoch
+        body.put_i64(-1); // log start offset
+        body.put_i32(1024 * 1024); // partition max bytes
+        body.put_u8(0);
+        body.put_u8(0);
+    }
+    put_unsigned_varint(&mut body, 1); // forgotten topics
+    put_compact_bytes(&mut body, b""); // rack id
+    body.put_u8(0);
+    body.to_vec()
+}
+
+/// A partition of a Fetch response: topic id, error code and records
+type FetchedPartition = (Uuid, i16, Bytes);
+
+fn parse_fetch(mut body: Bytes) -> (i16, Vec<FetchedPartition>) {
+    let _throttle_time_ms = body.get_i32();
+    let error_code = body.get_i16();
+    let _session_id = body.get_i32();
+    let mut partitions = Vec::new();
+    for _ in 0..get_compact_len(&mut body) {
+        let topic_id = get_uuid(&mut body);
+        for _ in 0..get_compact_len(&mut body) {
+            let _partition_index = body.get_i32();
+            let error_code = body.get_i16();
+            let _high_watermark = body.get_i64();
+            let _last_stable_offset = body.get_i64();
+            let _log_start_offset = body.get_i64();
+            for _ in 0..get_compact_len(&mut body) {
+                // aborted transactions: producer id, first offset, tags
+                body.advance(16);
+                assert_empty_tagged_fields(&mut body);
+            }
+            let _preferred_read_replica = body.get_i32();
+            let records_length = get_compact_len(&mut body);
+            let records = body.split_to(records_length);
+            assert_empty_tagged_fields(&mut body);
+            partitions.push((topic_id, error_code, records));
+        }
+        assert_empty_tagged_fields(&mut body);
+    }
+    assert_empty_tagged_fields(&mut body);
+    assert!(body.is_empty(), "{} trailing bytes", body.len());
+    (error_code, partitions)
+}
+
+async fn fetch(addr: SocketAddr, topic_ids: &[Uuid]) -> (i16, Vec<FetchedPartition>) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = request(FETCH, 16, 11, &fetch_body(topic_ids));
+    let mut response = exchange(&mut stream, &request).await;
+    assert_eq!(response.get_i32(), 11);
+    assert_empty_tagged_fields(&mut response);
+    parse_fetch(response)
+}
+
+#[tokio::test]
+async fn stage_fetch_is_advertised() {
+    let addr = start(KafkaBroker::new()).await;
+    let ranges = api_versions_ranges(addr).await;
+    assert!(ranges
+        .iter()
+        .any(|&(api_key, min, max)| api_key == FETCH && min <= 16 && max >= 16));
+}
+
+#[tokio::test]
+async fn stage_fetch_with_no_topics() {
+    let (addr, _dir) = start_with_fixture().await;
+    assert_eq!(fetch(addr, &[]).await, (NONE, vec![]));
+}
+
+#[tokio::test]
+async fn stage_fetch_unknown_topic() {
+    let (addr, _dir) = start_with_fixture().await;
+    let unknown = Uuid::from_u128(0xdead);
+    assert_eq!(
+        fetch(addr, &[unknown]).await,
+        (NONE, vec![(unknown, UNKNOWN_TOPIC_ID, Bytes::new())])
+    );
+}
+
+#[tokio::test]
+async fn stage_fetch_empty_topic() {
+    let (addr, _dir) = start_with_fixture().await;
+    let bar = fixture_topics()[0].id;
+    assert_eq!(
+        fetch(addr, &[bar]).await,
+        (NONE, vec![(bar, NONE, Bytes::new())])
+    );
+}
+
+#[tokio::test]
+async fn stage_fetch_with_messages() {
+    let (addr, _dir) = start_with_fixture().await;
+    let foo = &fixture_topics()[1];
+    // The record batches come back exactly as stored on disk
+    assert_eq!(
+        fetch(addr, &[foo.id]).await,
+        (NONE, vec![(foo.id, NONE, Bytes::from(fixture_log(foo, 0)))])
+    );
+}